@@ -0,0 +1,209 @@
+// Frames per second on workloads that lean on the CPU, the PPU and the APU, and the instruction
+// and bus read rates underneath them. The workloads are small programs built here, so nothing has
+// to be downloaded. Point GB_BENCH_ROM at a game to time it too.
+//
+//     cargo bench --bench emulator
+//
+// Criterion reports frames and instructions as elements per second.
+
+use std::env;
+use std::hint::black_box;
+
+use criterion::{criterion_group, criterion_main, Criterion, Throughput};
+use rustygameboy::cpu::Memory;
+use rustygameboy::emulator::Emulator;
+use rustygameboy::rom::{Rom, ValidationPolicy};
+
+// A 32 KB ROM-only cartridge that jumps to `program` at 0x150.
+fn rom(program: &[u8]) -> Rom {
+    let mut content = vec![0; 0x8000];
+    // NOP; JP 0x150
+    content[0x100..0x104].copy_from_slice(&[0x00, 0xC3, 0x50, 0x01]);
+    content[PROGRAM..PROGRAM + program.len()].copy_from_slice(program);
+    Rom::from_bytes_with_policy(content, ValidationPolicy::Lenient).unwrap()
+}
+
+// A relative jump from the end of `program` back to `target`, both counted from its start.
+fn jump_back(program: &mut Vec<u8>, opcode: u8, target: usize) {
+    let offset = target as isize - (program.len() + 2) as isize;
+    program.extend_from_slice(&[opcode, offset as i8 as u8]);
+}
+
+// Arithmetic, loads and stack traffic with the LCD off, so only the CPU and memory are busy.
+fn cpu_program() -> Vec<u8> {
+    // DI; XOR A; LDH (0x40), A
+    let mut program = vec![0xF3, 0xAF, 0xE0, 0x40];
+    let start = program.len();
+    program.extend_from_slice(&[
+        0x3C, // INC A
+        0x47, // LD B, A
+        0x80, // ADD B
+        0xCB, 0x37, // SWAP A
+        0x21, 0x00, 0xC0, // LD HL, 0xC000
+        0x77, // LD (HL), A
+        0x2A, // LD A, (HL+)
+        0xC5, // PUSH BC
+        0xC1, // POP BC
+    ]);
+    jump_back(&mut program, 0x18, start);
+    program
+}
+
+// Most of the instruction set without touching memory: every register to register load and ALU
+// operation, then every CB operation on a register.
+fn mixed_program() -> Vec<u8> {
+    // DI; XOR A; LDH (0x40), A
+    let mut program = vec![0xF3, 0xAF, 0xE0, 0x40];
+    let start = program.len();
+    // H and L are left alone as destinations, they'd point (HL) somewhere else.
+    for opcode in 0x40..=0x7F {
+        let (destination, source) = ((opcode >> 3) & 7, opcode & 7);
+        if !matches!(destination, 4..=6) && source != 6 {
+            program.push(opcode);
+        }
+    }
+    program.extend((0x80..=0xBF).filter(|opcode| opcode & 7 != 6));
+    for opcode in (0x00..=0xFF).filter(|opcode| opcode & 7 != 6) {
+        program.extend_from_slice(&[0xCB, opcode]);
+    }
+    // JP start
+    let [low, high] = ((PROGRAM + start) as u16).to_le_bytes();
+    program.extend_from_slice(&[0xC3, low, high]);
+    program
+}
+
+// Fills VRAM and OAM with patterns and turns on the background, the window and 8x16 sprites,
+// then scrolls a pixel every frame.
+fn ppu_program() -> Vec<u8> {
+    // DI; XOR A; LDH (0x40), A; LD HL, 0x8000
+    let mut program = vec![0xF3, 0xAF, 0xE0, 0x40, 0x21, 0x00, 0x80];
+    let fill_vram = program.len();
+    // LD A, L; XOR H; LD (HL+), A; LD A, H; CP 0xA0
+    program.extend_from_slice(&[0x7D, 0xAC, 0x22, 0x7C, 0xFE, 0xA0]);
+    jump_back(&mut program, 0x20, fill_vram);
+    // LD HL, 0xFE00
+    program.extend_from_slice(&[0x21, 0x00, 0xFE]);
+    let fill_oam = program.len();
+    // LD A, L; LD (HL+), A; LD A, L; CP 0xA0
+    program.extend_from_slice(&[0x7D, 0x22, 0x7D, 0xFE, 0xA0]);
+    jump_back(&mut program, 0x20, fill_oam);
+    program.extend_from_slice(&[
+        0x3E, 0x40, 0xE0, 0x4A, // LD A, 0x40; LDH (0x4A), A for WY
+        0x3E, 0x07, 0xE0, 0x4B, // LD A, 0x07; LDH (0x4B), A for WX
+        0x3E, 0x01, 0xE0, 0xFF, // LD A, 0x01; LDH (0xFF), A for the VBlank interrupt
+        0x3E, 0xF7, 0xE0, 0x40, // LD A, 0xF7; LDH (0x40), A
+    ]);
+    let frame = program.len();
+    program.extend_from_slice(&[
+        0x76, // HALT
+        0xF0, 0x43, 0x3C, 0xE0, 0x43, // LDH A, (0x43); INC A; LDH (0x43), A
+        0xAF, 0xE0, 0x0F, // XOR A; LDH (0x0F), A
+    ]);
+    jump_back(&mut program, 0x18, frame);
+    program
+}
+
+// Plays all four channels with the LCD off and sweeps the first one's frequency as fast as the
+// CPU can.
+fn apu_program() -> Vec<u8> {
+    // DI; XOR A; LDH (0x40), A
+    let mut program = vec![0xF3, 0xAF, 0xE0, 0x40];
+    let registers: &[(u8, u8)] = &[
+        (0x26, 0x80),
+        (0x24, 0x77),
+        (0x25, 0xFF),
+        (0x11, 0x80),
+        (0x12, 0xF0),
+        (0x14, 0x87),
+        (0x16, 0x40),
+        (0x17, 0xF0),
+        (0x19, 0x86),
+        (0x1A, 0x80),
+        (0x1C, 0x20),
+        (0x1E, 0x87),
+        (0x21, 0xF0),
+        (0x22, 0x22),
+        (0x23, 0x80),
+    ];
+    for (wave, address) in (0x30..0x40).enumerate() {
+        // LD A, value; LDH (address), A
+        program.extend_from_slice(&[0x3E, (wave as u8) * 0x11, 0xE0, address]);
+    }
+    for &(address, value) in registers {
+        program.extend_from_slice(&[0x3E, value, 0xE0, address]);
+    }
+    let sweep = program.len();
+    // INC A; LDH (0x13), A
+    program.extend_from_slice(&[0x3C, 0xE0, 0x13]);
+    jump_back(&mut program, 0x18, sweep);
+    program
+}
+
+fn frames(criterion: &mut Criterion) {
+    let mut workloads = vec![
+        ("cpu", rom(&cpu_program())),
+        ("ppu", rom(&ppu_program())),
+        ("apu", rom(&apu_program())),
+    ];
+    if let Some(path) = env::var_os("GB_BENCH_ROM") {
+        let path = path.to_str().expect("GB_BENCH_ROM is UTF-8");
+        workloads.push(("game", Rom::new_lenient(path).unwrap()));
+    }
+
+    let mut group = criterion.benchmark_group("frames");
+    group.throughput(Throughput::Elements(FRAMES));
+    for (name, rom) in workloads {
+        let mut emulator = Emulator::new(rom).unwrap();
+        group.bench_function(name, |bencher| {
+            bencher.iter(|| {
+                for _ in 0..FRAMES {
+                    emulator.run_frame();
+                }
+                // Like a frontend would, so the buffer doesn't just grow.
+                black_box(emulator.audio_samples());
+            })
+        });
+    }
+    group.finish();
+}
+
+fn instructions(criterion: &mut Criterion) {
+    let mut group = criterion.benchmark_group("dispatch");
+    group.throughput(Throughput::Elements(INSTRUCTIONS));
+    for (name, program) in [("step", cpu_program()), ("mixed", mixed_program())] {
+        let mut emulator = Emulator::new(rom(&program)).unwrap();
+        group.bench_function(name, |bencher| {
+            bencher.iter(|| {
+                for _ in 0..INSTRUCTIONS {
+                    black_box(emulator.step());
+                }
+            })
+        });
+    }
+    group.finish();
+}
+
+fn bus_reads(criterion: &mut Criterion) {
+    let mut emulator = Emulator::new(rom(&cpu_program())).unwrap();
+    let addresses: Vec<u16> = (0..=0xFFFF).step_by(7).collect();
+    let mut group = criterion.benchmark_group("bus");
+    group.throughput(Throughput::Elements(addresses.len() as u64));
+    group.bench_function("read", |bencher| {
+        bencher.iter(|| {
+            let bus = emulator.bus_mut();
+            addresses
+                .iter()
+                .fold(0u8, |sum, &address| sum.wrapping_add(bus.read(address)))
+        })
+    });
+    group.finish();
+}
+
+criterion_group!(benches, frames, instructions, bus_reads);
+criterion_main!(benches);
+
+const PROGRAM: usize = 0x150;
+
+const FRAMES: u64 = 10;
+
+const INSTRUCTIONS: u64 = 10_000;
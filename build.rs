@@ -0,0 +1,22 @@
+// Regenerates include/rustygameboy.h from src/capi.rs when building with the capi feature.
+fn main() {
+    #[cfg(feature = "capi")]
+    {
+        println!("cargo:rerun-if-changed=src/capi.rs");
+        let config = cbindgen::Config {
+            language: cbindgen::Language::C,
+            include_guard: Some("RUSTYGAMEBOY_H".to_string()),
+            header: Some(
+                "// Generated by cbindgen from src/capi.rs, see the comments there.".to_string(),
+            ),
+            usize_is_size_t: true,
+            ..Default::default()
+        };
+        cbindgen::Builder::new()
+            .with_config(config)
+            .with_src("src/capi.rs")
+            .generate()
+            .expect("src/capi.rs can be parsed")
+            .write_to_file("include/rustygameboy.h");
+    }
+}
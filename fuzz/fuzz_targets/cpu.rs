@@ -0,0 +1,22 @@
+// Runs whatever loads as a ROM for a few frames. Random code writes everywhere, switches banks and
+// hits the illegal opcodes, so this finds the indexing and overflow panics a game never would.
+//
+//     cargo +nightly fuzz run cpu
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use rustygameboy::emulator::{Emulator, RunLimit, CYCLES_PER_FRAME};
+use rustygameboy::rom::{Rom, ValidationPolicy};
+
+fuzz_target!(|data: &[u8]| {
+    let Ok(rom) = Rom::from_bytes_with_policy(data.to_vec(), ValidationPolicy::Lenient) else {
+        return;
+    };
+    let Ok(mut emulator) = Emulator::new(rom) else {
+        return;
+    };
+    emulator.run_for(RunLimit::Cycles(CYCLES_PER_FRAME as u64 * FRAMES));
+});
+
+const FRAMES: u64 = 4;
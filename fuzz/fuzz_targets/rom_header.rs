@@ -0,0 +1,25 @@
+// Any bytes at all have to load as a ROM or fail with an error, never panic, and the header of
+// whatever loads leniently has to be readable.
+//
+//     cargo +nightly fuzz run rom_header
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use rustygameboy::rom::{Rom, ValidationPolicy};
+
+fuzz_target!(|data: &[u8]| {
+    let _ = Rom::from_bytes(data.to_vec());
+    let Ok(rom) = Rom::from_bytes_with_policy(data.to_vec(), ValidationPolicy::Lenient) else {
+        return;
+    };
+    let _ = rom.header_info();
+    let _ = rom.cartridge_features();
+    let _ = rom.get_rom_size();
+    let _ = rom.get_ram_size();
+    let _ = rom.global_checksum();
+    let _ = rom.is_mbc1_multicart();
+    for warning in rom.warnings() {
+        let _ = warning.to_string();
+    }
+});
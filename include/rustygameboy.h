@@ -0,0 +1,54 @@
+// Generated by cbindgen from src/capi.rs, see the comments there.
+
+#ifndef RUSTYGAMEBOY_H
+#define RUSTYGAMEBOY_H
+
+#include <stdarg.h>
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+#include <stdlib.h>
+
+#define GB_OK 0
+
+#define GB_ERROR -1
+
+#define GB_SCREEN_WIDTH 160
+
+#define GB_SCREEN_HEIGHT 144
+
+#define GB_BUTTON_A 1
+
+#define GB_BUTTON_B 2
+
+#define GB_BUTTON_SELECT 4
+
+#define GB_BUTTON_START 8
+
+#define GB_BUTTON_RIGHT 16
+
+#define GB_BUTTON_LEFT 32
+
+#define GB_BUTTON_UP 64
+
+#define GB_BUTTON_DOWN 128
+
+typedef struct GbEmulator GbEmulator;
+
+struct GbEmulator *gb_create(void);
+
+void gb_destroy(struct GbEmulator *gb);
+
+int gb_load_rom(struct GbEmulator *gb, const uint8_t *data, size_t size);
+
+const char *gb_last_error(const struct GbEmulator *gb);
+
+void gb_run_frame(struct GbEmulator *gb);
+
+const uint8_t *gb_get_framebuffer(const struct GbEmulator *gb);
+
+void gb_set_input(struct GbEmulator *gb, uint8_t buttons);
+
+size_t gb_read_audio(struct GbEmulator *gb, float *buffer, size_t capacity);
+
+#endif  /* RUSTYGAMEBOY_H */
@@ -0,0 +1,53 @@
+use std::fmt;
+use std::io::{Error, Result};
+use std::str::FromStr;
+
+// How closely the emulator follows the hardware where that costs speed. Normal draws the screen a
+// line at a time, which is all almost every game needs. High runs the PPU's pixel FIFO a dot at a
+// time, for demos and test ROMs that change registers in the middle of a line.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Accuracy {
+    #[default]
+    Normal,
+    High,
+}
+
+impl fmt::Display for Accuracy {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            Accuracy::Normal => "normal",
+            Accuracy::High => "high",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+impl FromStr for Accuracy {
+    type Err = Error;
+
+    fn from_str(name: &str) -> Result<Accuracy> {
+        match name.to_ascii_lowercase().as_str() {
+            "normal" => Ok(Accuracy::Normal),
+            "high" => Ok(Accuracy::High),
+            _ => Err(Error::other(format!(
+                "{} is not an accuracy, expected normal or high.",
+                name
+            ))),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rstest::rstest;
+
+    use super::*;
+
+    #[rstest]
+    #[case("normal", Some(Accuracy::Normal))]
+    #[case("HIGH", Some(Accuracy::High))]
+    #[case("cycle", None)]
+    fn test_from_str(#[case] name: &str, #[case] expected: Option<Accuracy>) {
+        assert_eq!(name.parse::<Accuracy>().ok(), expected);
+    }
+}
@@ -0,0 +1,723 @@
+mod envelope;
+mod length_counter;
+mod noise;
+mod resampler;
+mod square;
+mod wave;
+
+use std::collections::VecDeque;
+use std::io::{Error, Result};
+use std::str::FromStr;
+use std::time::Duration;
+
+use crate::savestate::{Snapshot, StateReader, StateWriter};
+use noise::Noise;
+use resampler::Resampler;
+use square::Square;
+use wave::Wave;
+
+// The four sound channels, in the order NR51 and NR52 list them.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Channel {
+    Square1,
+    Square2,
+    Wave,
+    Noise,
+}
+
+impl Channel {
+    pub const ALL: [Channel; 4] = [
+        Channel::Square1,
+        Channel::Square2,
+        Channel::Wave,
+        Channel::Noise,
+    ];
+}
+
+impl FromStr for Channel {
+    type Err = Error;
+
+    // Takes the channel's number, 1-4, or its name.
+    fn from_str(name: &str) -> Result<Channel> {
+        match name.to_ascii_lowercase().as_str() {
+            "1" | "square1" => Ok(Channel::Square1),
+            "2" | "square2" => Ok(Channel::Square2),
+            "3" | "wave" => Ok(Channel::Wave),
+            "4" | "noise" => Ok(Channel::Noise),
+            _ => Err(Error::other(format!(
+                "{} is not a sound channel, use 1-4 or square1, square2, wave or noise.",
+                name
+            ))),
+        }
+    }
+}
+
+pub struct Apu {
+    powered: bool,
+    square1: Square,
+    square2: Square,
+    wave: Wave,
+    noise: Noise,
+    nr50: u8,
+    nr51: u8,
+    frame_sequencer_step: u8,
+    sample_rate: u32,
+    resampler: Resampler,
+    // High-pass filter state removing the DC offset of the DACs, like the capacitors on the board.
+    capacitors: (f32, f32),
+    // Interleaved left/right samples waiting for the host to pull them, at most `latency` worth.
+    samples: VecDeque<f32>,
+    latency: Duration,
+    // Mixer settings for listening to channels on their own. Games can't see them.
+    channels_enabled: [bool; 4],
+    channel_gains: [f32; 4],
+    capture: Option<Capture>,
+}
+
+// A copy of the output for recording, made at its own rate so changing the speed or the host's
+// rate doesn't change the recording.
+struct Capture {
+    // How much the capacitors keep per sample at the capture's rate.
+    charge: f32,
+    tracks: Vec<Track>,
+}
+
+struct Track {
+    // The channel on its own, or everything that's heard.
+    channel: Option<Channel>,
+    resampler: Resampler,
+    capacitors: (f32, f32),
+    samples: Vec<f32>,
+}
+
+impl Default for Apu {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Apu {
+    pub fn new() -> Apu {
+        Apu {
+            powered: true,
+            square1: Square::new(true),
+            square2: Square::new(false),
+            wave: Wave::new(),
+            noise: Noise::new(),
+            nr50: 0x77,
+            nr51: 0xF3,
+            frame_sequencer_step: 0,
+            sample_rate: DEFAULT_SAMPLE_RATE,
+            resampler: Resampler::new(DEFAULT_SAMPLE_RATE),
+            capacitors: (0.0, 0.0),
+            samples: VecDeque::new(),
+            latency: DEFAULT_LATENCY,
+            channels_enabled: [true; 4],
+            channel_gains: [1.0; 4],
+            capture: None,
+        }
+    }
+
+    pub fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    pub fn set_sample_rate(&mut self, sample_rate: u32) {
+        self.sample_rate = sample_rate;
+        self.resampler = Resampler::new(sample_rate);
+        self.samples.clear();
+    }
+
+    // Like `set_sample_rate` without dropping the samples waiting or starting the resampler over,
+    // for the small changes dynamic rate control makes while playing.
+    pub fn adjust_sample_rate(&mut self, sample_rate: u32) {
+        self.sample_rate = sample_rate;
+        self.resampler.set_sample_rate(sample_rate);
+    }
+
+    pub fn latency(&self) -> Duration {
+        self.latency
+    }
+
+    // How much audio waits for the host before the oldest is dropped. A host that pulls every
+    // frame only needs a little over a frame, more rides out hiccups at the cost of lag.
+    pub fn set_latency(&mut self, latency: Duration) {
+        self.latency = latency;
+        self.trim_samples();
+    }
+
+    pub fn channel_enabled(&self, channel: Channel) -> bool {
+        self.channels_enabled[channel as usize]
+    }
+
+    // Leaves a channel out of the mix. It keeps running, so the game can't tell.
+    pub fn set_channel_enabled(&mut self, channel: Channel, enabled: bool) {
+        self.channels_enabled[channel as usize] = enabled;
+    }
+
+    pub fn channel_gain(&self, channel: Channel) -> f32 {
+        self.channel_gains[channel as usize]
+    }
+
+    // Scales a channel's output, 1.0 being the hardware's level. Values over 1.0 can clip.
+    pub fn set_channel_gain(&mut self, channel: Channel, gain: f32) {
+        self.channel_gains[channel as usize] = gain.max(0.0);
+    }
+
+    // Starts keeping a copy of the output at `sample_rate`, and with `stems` a copy of each channel
+    // on its own, even the muted ones.
+    pub fn start_capture(&mut self, sample_rate: u32, stems: bool) {
+        let channels = [
+            Channel::Square1,
+            Channel::Square2,
+            Channel::Wave,
+            Channel::Noise,
+        ];
+        let stems = channels.into_iter().filter(|_| stems).map(Some);
+        let tracks = std::iter::once(None)
+            .chain(stems)
+            .map(|channel| Track {
+                channel,
+                resampler: Resampler::new(sample_rate),
+                capacitors: (0.0, 0.0),
+                samples: Vec::new(),
+            })
+            .collect();
+        self.capture = Some(Capture {
+            charge: CAPACITOR_CHARGE.powf(CPU_CLOCK as f32 / sample_rate as f32),
+            tracks,
+        });
+    }
+
+    // Whether the capture has stems, or None when there's no capture.
+    pub fn capture_stems(&self) -> Option<bool> {
+        self.capture
+            .as_ref()
+            .map(|capture| capture.tracks.len() > 1)
+    }
+
+    // Throws away what wasn't taken yet.
+    pub fn stop_capture(&mut self) {
+        self.capture = None;
+    }
+
+    // The interleaved stereo samples captured since the last call: the output, followed by the
+    // square, wave and noise channels when capturing stems. Empty when not capturing.
+    pub fn take_captured(&mut self) -> Vec<Vec<f32>> {
+        self.capture.as_mut().map_or_else(Vec::new, |capture| {
+            capture
+                .tracks
+                .iter_mut()
+                .map(|track| std::mem::take(&mut track.samples))
+                .collect()
+        })
+    }
+
+    // The number of f32 values (two per stereo frame) ready to be pulled.
+    pub fn samples_available(&self) -> usize {
+        self.samples.len()
+    }
+
+    // Moves as many interleaved stereo samples as are available into `buffer` and returns how many
+    // were written. The rest of `buffer` is left untouched.
+    pub fn fill_buffer(&mut self, buffer: &mut [f32]) -> usize {
+        let count = buffer.len().min(self.samples.len()) & !1;
+        for (destination, sample) in buffer.iter_mut().zip(self.samples.drain(..count)) {
+            *destination = sample;
+        }
+        count
+    }
+
+    pub fn read(&self, address: u16) -> u8 {
+        let value = match address {
+            0xFF10..=0xFF14 => self.square1.read(address - 0xFF10),
+            0xFF15..=0xFF19 => self.square2.read(address - 0xFF15),
+            0xFF1A..=0xFF1E => self.wave.read(address - 0xFF1A),
+            0xFF1F..=0xFF23 => self.noise.read(address - 0xFF1F),
+            0xFF24 => self.nr50,
+            0xFF25 => self.nr51,
+            0xFF26 => {
+                let status = [
+                    self.square1.enabled(),
+                    self.square2.enabled(),
+                    self.wave.enabled(),
+                    self.noise.enabled(),
+                ]
+                .iter()
+                .enumerate()
+                .fold(0, |acc, (i, &enabled)| acc | ((enabled as u8) << i));
+                if self.powered {
+                    0x80 | status
+                } else {
+                    status
+                }
+            }
+            0xFF30..=0xFF3F => return self.wave.read_ram((address - 0xFF30) as usize),
+            _ => 0xFF,
+        };
+
+        match address {
+            0xFF10..=0xFF2F => value | READ_MASKS[(address - 0xFF10) as usize],
+            _ => value,
+        }
+    }
+
+    pub fn write(&mut self, address: u16, value: u8) {
+        match address {
+            0xFF26 => self.write_power(value & 0x80 != 0),
+            0xFF30..=0xFF3F => self.wave.write_ram((address - 0xFF30) as usize, value),
+            // Every other register is read-only while the APU is off.
+            _ if !self.powered => {}
+            0xFF10..=0xFF14 => self.square1.write(address - 0xFF10, value),
+            0xFF15..=0xFF19 => self.square2.write(address - 0xFF15, value),
+            0xFF1A..=0xFF1E => self.wave.write(address - 0xFF1A, value),
+            0xFF1F..=0xFF23 => self.noise.write(address - 0xFF1F, value),
+            0xFF24 => self.nr50 = value,
+            0xFF25 => self.nr51 = value,
+            _ => {}
+        }
+    }
+
+    // Runs the channels from one change in the output to the next, so the resampler sees every
+    // change at the cycle it happened. Register writes between ticks show up at the start.
+    pub fn tick(&mut self, cycles: u32) {
+        let mut remaining = cycles;
+        self.update_levels();
+        while remaining > 0 {
+            let step = if self.powered {
+                self.cycles_until_step().clamp(1, remaining)
+            } else {
+                remaining
+            };
+            if self.powered {
+                self.square1.tick(step);
+                self.square2.tick(step);
+                self.wave.tick(step);
+                self.noise.tick(step);
+            }
+            self.resampler.advance(step);
+            if let Some(capture) = self.capture.as_mut() {
+                for track in &mut capture.tracks {
+                    track.resampler.advance(step);
+                }
+            }
+            self.update_levels();
+            remaining -= step;
+        }
+
+        // Samples are produced even while powered off so the host stream doesn't stall.
+        let charge = CAPACITOR_CHARGE.powf(CPU_CLOCK as f32 / self.sample_rate as f32);
+        let (samples, capacitors) = (&mut self.samples, &mut self.capacitors);
+        self.resampler.drain(|left, right| {
+            let (left, right) = high_pass(capacitors, charge, left, right);
+            samples.push_back(left);
+            samples.push_back(right);
+        });
+        self.trim_samples();
+        if let Some(capture) = self.capture.as_mut() {
+            for track in &mut capture.tracks {
+                let (samples, capacitors) = (&mut track.samples, &mut track.capacitors);
+                track.resampler.drain(|left, right| {
+                    let (left, right) = high_pass(capacitors, capture.charge, left, right);
+                    samples.extend([left, right]);
+                });
+            }
+        }
+    }
+
+    fn update_levels(&mut self) {
+        let outputs = self.channel_outputs();
+        let heard = self.heard_channels();
+        self.resampler.set_level(sum(&outputs, heard));
+        if let Some(capture) = self.capture.as_mut() {
+            for track in &mut capture.tracks {
+                let channels = track.channel.map_or(heard, |channel| 1 << channel as u8);
+                track.resampler.set_level(sum(&outputs, channels));
+            }
+        }
+    }
+
+    // A bit for each channel that isn't muted, see `set_channel_enabled`.
+    fn heard_channels(&self) -> u8 {
+        (0..4)
+            .filter(|&i| self.channels_enabled[i])
+            .fold(0, |channels, i| channels | 1 << i)
+    }
+
+    // The output can only change when a channel's timer runs out, or when a register changes. A
+    // silent channel never changes.
+    fn cycles_until_step(&self) -> u32 {
+        [
+            (
+                self.square1.enabled(),
+                self.square1.dac_enabled(),
+                self.square1.timer(),
+            ),
+            (
+                self.square2.enabled(),
+                self.square2.dac_enabled(),
+                self.square2.timer(),
+            ),
+            (
+                self.wave.enabled(),
+                self.wave.dac_enabled(),
+                self.wave.timer(),
+            ),
+            (
+                self.noise.enabled(),
+                self.noise.dac_enabled(),
+                self.noise.timer(),
+            ),
+        ]
+        .into_iter()
+        .filter(|&(enabled, dac_enabled, _)| enabled && dac_enabled)
+        .map(|(_, _, timer)| timer)
+        .min()
+        .unwrap_or(u32::MAX)
+    }
+
+    fn write_power(&mut self, powered: bool) {
+        if self.powered && !powered {
+            // Powering off clears every register except wave RAM.
+            let mut wave = Wave::new();
+            for index in 0..16 {
+                wave.write_ram(index, self.wave.read_ram(index));
+            }
+            self.square1 = Square::new(true);
+            self.square2 = Square::new(false);
+            self.wave = wave;
+            self.noise = Noise::new();
+            self.nr50 = 0;
+            self.nr51 = 0;
+        } else if !self.powered && powered {
+            self.frame_sequencer_step = 0;
+        }
+
+        self.powered = powered;
+    }
+
+    // Called on the falling edge of DIV bit 4 (bit 5 in double speed mode), every 8192 T-cycles.
+    pub fn clock_frame_sequencer(&mut self) {
+        if !self.powered {
+            return;
+        }
+
+        // Length counters run at 256 Hz, sweep at 128 Hz and envelopes at 64 Hz.
+        if self.frame_sequencer_step.is_multiple_of(2) {
+            self.square1.clock_length();
+            self.square2.clock_length();
+            self.wave.clock_length();
+            self.noise.clock_length();
+        }
+        if self.frame_sequencer_step == 2 || self.frame_sequencer_step == 6 {
+            self.square1.clock_sweep();
+        }
+        if self.frame_sequencer_step == 7 {
+            self.square1.clock_envelope();
+            self.square2.clock_envelope();
+            self.noise.clock_envelope();
+        }
+
+        self.frame_sequencer_step = (self.frame_sequencer_step + 1) % 8;
+    }
+
+    // Drop the oldest audio when nobody is pulling so memory and lag stay bounded.
+    fn trim_samples(&mut self) {
+        let frames = (self.sample_rate as f64 * self.latency.as_secs_f64()).ceil() as usize;
+        let excess = self.samples.len().saturating_sub(frames * 2);
+        self.samples.drain(..excess);
+    }
+
+    #[cfg(test)]
+    fn mix(&self) -> (f32, f32) {
+        sum(&self.channel_outputs(), self.heard_channels())
+    }
+
+    // What each channel adds to the left and right outputs.
+    fn channel_outputs(&self) -> [(f32, f32); 4] {
+        let channels = [
+            (self.square1.output(), self.square1.dac_enabled()),
+            (self.square2.output(), self.square2.dac_enabled()),
+            (self.wave.output(), self.wave.dac_enabled()),
+            (self.noise.output(), self.noise.dac_enabled()),
+        ];
+        let left_volume = (((self.nr50 >> 4) & 0x07) + 1) as f32 / 8.0 / 4.0;
+        let right_volume = ((self.nr50 & 0x07) + 1) as f32 / 8.0 / 4.0;
+
+        let mut outputs = [(0.0, 0.0); 4];
+        for (i, &(output, dac_enabled)) in channels.iter().enumerate() {
+            if !self.powered || !dac_enabled {
+                continue;
+            }
+
+            // Each DAC maps 0-15 onto -1.0-1.0.
+            let analog = (output as f32 / 7.5 - 1.0) * self.channel_gains[i];
+            if self.nr51 & (0x10 << i) != 0 {
+                outputs[i].0 = analog * left_volume;
+            }
+            if self.nr51 & (0x01 << i) != 0 {
+                outputs[i].1 = analog * right_volume;
+            }
+        }
+        outputs
+    }
+}
+
+impl Snapshot for Apu {
+    fn component(&self) -> &'static str {
+        "APU"
+    }
+
+    fn version(&self) -> u8 {
+        1
+    }
+
+    // The host sample rate and queued samples aren't part of the emulated state.
+    fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_bool(self.powered);
+        self.square1.save_state(writer);
+        self.square2.save_state(writer);
+        self.wave.save_state(writer);
+        self.noise.save_state(writer);
+        writer.write_u8(self.nr50);
+        writer.write_u8(self.nr51);
+        writer.write_u8(self.frame_sequencer_step);
+        writer.write_f32(self.capacitors.0);
+        writer.write_f32(self.capacitors.1);
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader) -> Result<()> {
+        self.powered = reader.read_bool()?;
+        self.square1.load_state(reader)?;
+        self.square2.load_state(reader)?;
+        self.wave.load_state(reader)?;
+        self.noise.load_state(reader)?;
+        self.nr50 = reader.read_u8()?;
+        self.nr51 = reader.read_u8()?;
+        self.frame_sequencer_step = reader.read_u8()?;
+        self.capacitors.0 = reader.read_f32()?;
+        self.capacitors.1 = reader.read_f32()?;
+        Ok(())
+    }
+}
+
+// Mixes the channels with a bit set in `channels`.
+fn sum(outputs: &[(f32, f32); 4], channels: u8) -> (f32, f32) {
+    outputs
+        .iter()
+        .enumerate()
+        .filter(|(i, _)| channels & 1 << i != 0)
+        .fold((0.0, 0.0), |(left, right), (_, output)| {
+            (left + output.0, right + output.1)
+        })
+}
+
+// `charge` is how much the capacitors keep per sample.
+fn high_pass(capacitors: &mut (f32, f32), charge: f32, left: f32, right: f32) -> (f32, f32) {
+    let left_out = left - capacitors.0;
+    let right_out = right - capacitors.1;
+    *capacitors = (left - left_out * charge, right - right_out * charge);
+    (left_out, right_out)
+}
+
+pub const CPU_CLOCK: u64 = 4_194_304;
+
+pub const DEFAULT_SAMPLE_RATE: u32 = 48_000;
+
+pub const DEFAULT_LATENCY: Duration = Duration::from_secs(1);
+
+// How much charge the DAC capacitors keep per T-cycle.
+const CAPACITOR_CHARGE: f32 = 0.999958;
+
+// Bits that always read back as 1 for 0xFF10-0xFF2F.
+const READ_MASKS: [u8; 0x20] = [
+    0x80, 0x3F, 0x00, 0xFF, 0xBF, 0xFF, 0x3F, 0x00, 0xFF, 0xBF, 0x7F, 0xFF, 0x9F, 0xFF, 0xBF, 0xFF,
+    0xFF, 0x00, 0x00, 0xBF, 0x00, 0x00, 0x70, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF,
+];
+
+#[cfg(test)]
+mod tests {
+    use rstest::rstest;
+
+    use super::*;
+
+    fn apu_with_square_playing() -> Apu {
+        let mut apu = Apu::new();
+        apu.write(0xFF12, 0xF0);
+        apu.write(0xFF14, 0x80);
+        apu
+    }
+
+    #[rstest]
+    #[case(0xFF10, 0x80)]
+    #[case(0xFF11, 0x3F)]
+    #[case(0xFF13, 0xFF)]
+    #[case(0xFF14, 0xBF)]
+    #[case(0xFF15, 0xFF)]
+    #[case(0xFF1A, 0x7F)]
+    #[case(0xFF1C, 0x9F)]
+    #[case(0xFF20, 0xFF)]
+    #[case(0xFF27, 0xFF)]
+    fn test_read_masks(#[case] address: u16, #[case] expected: u8) {
+        let apu = Apu::new();
+        assert_eq!(apu.read(address), expected);
+    }
+
+    #[test]
+    fn test_channel_status_in_nr52() {
+        let apu = apu_with_square_playing();
+        assert_eq!(apu.read(0xFF26), 0xF1);
+    }
+
+    #[test]
+    fn test_power_off_clears_registers_but_not_wave_ram() {
+        // Arrange
+        let mut apu = apu_with_square_playing();
+        apu.write(0xFF30, 0x12);
+
+        // Act
+        apu.write(0xFF26, 0x00);
+        apu.write(0xFF24, 0x77);
+
+        // Assert
+        assert_eq!(apu.read(0xFF26), 0x70);
+        assert_eq!(apu.read(0xFF12), 0x00);
+        assert_eq!(apu.read(0xFF24), 0x00);
+        assert_eq!(apu.read(0xFF30), 0x12);
+    }
+
+    #[test]
+    fn test_length_counter_stops_channel() {
+        let mut apu = Apu::new();
+        apu.write(0xFF11, 0x3F);
+        apu.write(0xFF12, 0xF0);
+        apu.write(0xFF14, 0xC0);
+
+        apu.clock_frame_sequencer();
+
+        assert_eq!(apu.read(0xFF26) & 0x01, 0);
+    }
+
+    #[test]
+    fn test_sample_production_rate() {
+        let mut apu = Apu::new();
+
+        apu.tick(CPU_CLOCK as u32 / 64);
+
+        assert_eq!(apu.samples_available(), 2 * 750);
+    }
+
+    #[test]
+    fn test_fill_buffer_drains_whole_frames() {
+        let mut apu = Apu::new();
+        apu.tick(CPU_CLOCK as u32 / 64);
+        let mut buffer = [1.0; 33];
+
+        let written = apu.fill_buffer(&mut buffer);
+
+        assert_eq!(written, 32);
+        assert_eq!(apu.samples_available(), 1500 - 32);
+        assert_eq!(buffer[32], 1.0);
+    }
+
+    #[test]
+    fn test_silent_when_dacs_are_off() {
+        let mut apu = Apu::new();
+        apu.tick(CPU_CLOCK as u32 / 64);
+        let mut buffer = [1.0; 96];
+
+        apu.fill_buffer(&mut buffer);
+
+        assert!(buffer.iter().all(|&sample| sample == 0.0));
+    }
+
+    #[test]
+    fn test_panning() {
+        let mut apu = apu_with_square_playing();
+        apu.write(0xFF25, 0x10);
+
+        let (left, right) = apu.mix();
+
+        assert!(left != 0.0);
+        assert_eq!(right, 0.0);
+    }
+
+    #[rstest]
+    #[case("1", Some(Channel::Square1))]
+    #[case("Wave", Some(Channel::Wave))]
+    #[case("4", Some(Channel::Noise))]
+    #[case("5", None)]
+    fn test_parse_channel(#[case] name: &str, #[case] expected: Option<Channel>) {
+        assert_eq!(name.parse().ok(), expected);
+    }
+
+    #[rstest]
+    #[case(false, 1.0, 0.0)]
+    #[case(true, 0.5, 0.5)]
+    #[case(true, 1.0, 1.0)]
+    fn test_channel_mixer(#[case] enabled: bool, #[case] gain: f32, #[case] expected: f32) {
+        // Arrange
+        let mut apu = apu_with_square_playing();
+        apu.write(0xFF25, 0x11);
+        let (full, _) = apu.mix();
+
+        // Act
+        apu.set_channel_enabled(Channel::Square1, enabled);
+        apu.set_channel_gain(Channel::Square1, gain);
+
+        // Assert
+        let (left, _) = apu.mix();
+        assert_eq!(left, full * expected);
+        assert_eq!(apu.read(0xFF26), 0xF1);
+    }
+
+    #[test]
+    fn test_high_pass_removes_dc_offset() {
+        let mut capacitors = (0.0, 0.0);
+        let charge = CAPACITOR_CHARGE.powf(CPU_CLOCK as f32 / 48_000.0);
+
+        let (first, _) = high_pass(&mut capacitors, charge, -0.25, -0.25);
+        let mut last = first;
+        for _ in 0..48_000 {
+            last = high_pass(&mut capacitors, charge, -0.25, -0.25).0;
+        }
+
+        assert_eq!(first, -0.25);
+        assert!(last.abs() < 0.001);
+    }
+
+    #[test]
+    fn test_square_wave_changes_between_syncs() {
+        let mut apu = apu_with_square_playing();
+
+        apu.tick(CPU_CLOCK as u32 / 64);
+
+        let mut buffer = [0.0; 1500];
+        apu.fill_buffer(&mut buffer);
+        let left = buffer.iter().step_by(2);
+        let (low, high) = left.fold((f32::MAX, f32::MIN), |(low, high), &sample| {
+            (low.min(sample), high.max(sample))
+        });
+        assert!(high - low > 0.1);
+    }
+
+    #[test]
+    fn test_latency_bounds_buffer() {
+        let mut apu = Apu::new();
+        apu.set_latency(Duration::from_millis(10));
+
+        apu.tick(CPU_CLOCK as u32 / 10);
+
+        assert_eq!(apu.samples_available(), 2 * 480);
+    }
+
+    #[test]
+    fn test_sample_buffer_is_bounded() {
+        let mut apu = Apu::new();
+        apu.set_sample_rate(1000);
+
+        apu.tick(CPU_CLOCK as u32 * 3);
+
+        assert_eq!(apu.samples_available(), 2000);
+    }
+}
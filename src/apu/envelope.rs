@@ -0,0 +1,124 @@
+use std::io::Result;
+
+use crate::savestate::{StateReader, StateWriter};
+
+// Volume envelope shared by the square and noise channels (NRx2).
+#[derive(Default)]
+pub struct Envelope {
+    initial_volume: u8,
+    increase: bool,
+    period: u8,
+    volume: u8,
+    timer: u8,
+}
+
+impl Envelope {
+    pub fn write(&mut self, value: u8) {
+        self.initial_volume = value >> 4;
+        self.increase = value & 0x08 != 0;
+        self.period = value & 0x07;
+    }
+
+    pub fn read(&self) -> u8 {
+        (self.initial_volume << 4) | if self.increase { 0x08 } else { 0 } | self.period
+    }
+
+    // The channel's DAC is powered by the upper 5 bits of NRx2.
+    pub fn dac_enabled(&self) -> bool {
+        self.read() & 0xF8 != 0
+    }
+
+    pub fn volume(&self) -> u8 {
+        self.volume
+    }
+
+    pub fn trigger(&mut self) {
+        self.volume = self.initial_volume;
+        self.timer = self.period;
+    }
+
+    pub fn clock(&mut self) {
+        if self.period == 0 {
+            return;
+        }
+
+        self.timer = self.timer.saturating_sub(1);
+        if self.timer == 0 {
+            self.timer = self.period;
+            if self.increase && self.volume < 15 {
+                self.volume += 1;
+            } else if !self.increase && self.volume > 0 {
+                self.volume -= 1;
+            }
+        }
+    }
+
+    pub fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_u8(self.initial_volume);
+        writer.write_bool(self.increase);
+        writer.write_u8(self.period);
+        writer.write_u8(self.volume);
+        writer.write_u8(self.timer);
+    }
+
+    pub fn load_state(&mut self, reader: &mut StateReader) -> Result<()> {
+        self.initial_volume = reader.read_u8()?;
+        self.increase = reader.read_bool()?;
+        self.period = reader.read_u8()?;
+        self.volume = reader.read_u8()?;
+        self.timer = reader.read_u8()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_decrease() {
+        let mut envelope = Envelope::default();
+        envelope.write(0xF2);
+        envelope.trigger();
+
+        envelope.clock();
+        assert_eq!(envelope.volume(), 15);
+        envelope.clock();
+
+        assert_eq!(envelope.volume(), 14);
+    }
+
+    #[test]
+    fn test_increase_stops_at_15() {
+        let mut envelope = Envelope::default();
+        envelope.write(0xE9);
+        envelope.trigger();
+
+        for _ in 0..5 {
+            envelope.clock();
+        }
+
+        assert_eq!(envelope.volume(), 15);
+    }
+
+    #[test]
+    fn test_period_zero_disables() {
+        let mut envelope = Envelope::default();
+        envelope.write(0x80);
+        envelope.trigger();
+
+        envelope.clock();
+
+        assert_eq!(envelope.volume(), 8);
+    }
+
+    #[test]
+    fn test_dac_enabled() {
+        let mut envelope = Envelope::default();
+
+        envelope.write(0x08);
+        assert!(envelope.dac_enabled());
+        envelope.write(0x07);
+        assert!(!envelope.dac_enabled());
+    }
+}
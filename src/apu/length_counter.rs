@@ -0,0 +1,96 @@
+use std::io::Result;
+
+use crate::savestate::{StateReader, StateWriter};
+
+// Silences a channel after a programmable number of 256 Hz frame sequencer clocks.
+pub struct LengthCounter {
+    max: u16,
+    counter: u16,
+    enabled: bool,
+}
+
+impl LengthCounter {
+    pub fn new(max: u16) -> LengthCounter {
+        LengthCounter {
+            max,
+            counter: 0,
+            enabled: false,
+        }
+    }
+
+    pub fn load(&mut self, value: u8) {
+        self.counter = self.max - value as u16 % self.max;
+    }
+
+    pub fn enabled(&self) -> bool {
+        self.enabled
+    }
+
+    pub fn set_enabled(&mut self, enabled: bool) {
+        self.enabled = enabled;
+    }
+
+    pub fn trigger(&mut self) {
+        if self.counter == 0 {
+            self.counter = self.max;
+        }
+    }
+
+    // Returns true when the counter just expired and the channel must be disabled.
+    pub fn clock(&mut self) -> bool {
+        if !self.enabled || self.counter == 0 {
+            return false;
+        }
+
+        self.counter -= 1;
+        self.counter == 0
+    }
+
+    pub fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_u16(self.counter);
+        writer.write_bool(self.enabled);
+    }
+
+    pub fn load_state(&mut self, reader: &mut StateReader) -> Result<()> {
+        self.counter = reader.read_u16()?;
+        self.enabled = reader.read_bool()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_expires() {
+        let mut length = LengthCounter::new(64);
+        length.load(62);
+        length.set_enabled(true);
+
+        assert!(!length.clock());
+        assert!(length.clock());
+        assert!(!length.clock());
+    }
+
+    #[test]
+    fn test_disabled_does_not_count() {
+        let mut length = LengthCounter::new(64);
+        length.load(63);
+
+        assert!(!length.clock());
+    }
+
+    #[test]
+    fn test_trigger_reloads_expired_counter() {
+        let mut length = LengthCounter::new(256);
+        length.set_enabled(true);
+
+        length.trigger();
+
+        for _ in 0..255 {
+            assert!(!length.clock());
+        }
+        assert!(length.clock());
+    }
+}
@@ -0,0 +1,222 @@
+use std::io::Result;
+
+use super::envelope::Envelope;
+use super::length_counter::LengthCounter;
+use crate::savestate::{StateReader, StateWriter};
+
+// Channel 4, which outputs pseudo-random noise from a linear feedback shift register.
+pub struct Noise {
+    enabled: bool,
+    clock_shift: u8,
+    short_mode: bool,
+    divisor_code: u8,
+    timer: u32,
+    lfsr: u16,
+    length: LengthCounter,
+    envelope: Envelope,
+}
+
+impl Default for Noise {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Noise {
+    pub fn new() -> Noise {
+        Noise {
+            enabled: false,
+            clock_shift: 0,
+            short_mode: false,
+            divisor_code: 0,
+            timer: 0,
+            lfsr: 0x7FFF,
+            length: LengthCounter::new(64),
+            envelope: Envelope::default(),
+        }
+    }
+
+    pub fn enabled(&self) -> bool {
+        self.enabled
+    }
+
+    pub fn dac_enabled(&self) -> bool {
+        self.envelope.dac_enabled()
+    }
+
+    // `register` is 1-4 for NR41-NR44.
+    pub fn read(&self, register: u16) -> u8 {
+        match register {
+            2 => self.envelope.read(),
+            3 => {
+                (self.clock_shift << 4) | if self.short_mode { 0x08 } else { 0 } | self.divisor_code
+            }
+            4 if self.length.enabled() => 0x40,
+            _ => 0,
+        }
+    }
+
+    pub fn write(&mut self, register: u16, value: u8) {
+        match register {
+            1 => self.length.load(value & 0x3F),
+            2 => {
+                self.envelope.write(value);
+                if !self.dac_enabled() {
+                    self.enabled = false;
+                }
+            }
+            3 => {
+                self.clock_shift = value >> 4;
+                self.short_mode = value & 0x08 != 0;
+                self.divisor_code = value & 0x07;
+            }
+            4 => {
+                self.length.set_enabled(value & 0x40 != 0);
+                if value & 0x80 != 0 {
+                    self.trigger();
+                }
+            }
+            _ => {}
+        }
+    }
+
+    // Cycles until the next step.
+    pub fn timer(&self) -> u32 {
+        self.timer
+    }
+
+    pub fn tick(&mut self, cycles: u32) {
+        let mut cycles = cycles;
+        while cycles > 0 {
+            if self.timer > cycles {
+                self.timer -= cycles;
+                break;
+            }
+
+            cycles -= self.timer;
+            self.timer = self.period();
+            self.step_lfsr();
+        }
+    }
+
+    pub fn clock_length(&mut self) {
+        if self.length.clock() {
+            self.enabled = false;
+        }
+    }
+
+    pub fn clock_envelope(&mut self) {
+        self.envelope.clock();
+    }
+
+    pub fn output(&self) -> u8 {
+        if !self.enabled || self.lfsr & 0x01 != 0 {
+            return 0;
+        }
+
+        self.envelope.volume()
+    }
+
+    fn period(&self) -> u32 {
+        let divisor = match self.divisor_code {
+            0 => 8,
+            code => code as u32 * 16,
+        };
+        divisor << self.clock_shift
+    }
+
+    fn step_lfsr(&mut self) {
+        let bit = (self.lfsr ^ (self.lfsr >> 1)) & 0x01;
+        self.lfsr = (self.lfsr >> 1) | (bit << 14);
+        if self.short_mode {
+            self.lfsr = (self.lfsr & !0x40) | (bit << 6);
+        }
+    }
+
+    fn trigger(&mut self) {
+        self.enabled = self.dac_enabled();
+        self.length.trigger();
+        self.timer = self.period();
+        self.lfsr = 0x7FFF;
+        self.envelope.trigger();
+    }
+
+    pub fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_bool(self.enabled);
+        writer.write_u8(self.clock_shift);
+        writer.write_bool(self.short_mode);
+        writer.write_u8(self.divisor_code);
+        writer.write_u32(self.timer);
+        writer.write_u16(self.lfsr);
+        self.length.save_state(writer);
+        self.envelope.save_state(writer);
+    }
+
+    pub fn load_state(&mut self, reader: &mut StateReader) -> Result<()> {
+        self.enabled = reader.read_bool()?;
+        self.clock_shift = reader.read_u8()?;
+        self.short_mode = reader.read_bool()?;
+        self.divisor_code = reader.read_u8()?;
+        self.timer = reader.read_u32()?;
+        self.lfsr = reader.read_u16()?;
+        self.length.load_state(reader)?;
+        self.envelope.load_state(reader)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rstest::rstest;
+
+    use super::*;
+
+    #[rstest]
+    #[case(0x00, 8)]
+    #[case(0x01, 16)]
+    #[case(0x07, 112)]
+    #[case(0x21, 64)]
+    fn test_period(#[case] nr43: u8, #[case] expected: u32) {
+        let mut noise = Noise::new();
+
+        noise.write(3, nr43);
+
+        assert_eq!(noise.period(), expected);
+    }
+
+    #[test]
+    fn test_lfsr_sequence() {
+        let mut noise = Noise::new();
+        noise.lfsr = 0x0001;
+
+        noise.step_lfsr();
+
+        assert_eq!(noise.lfsr, 0x4000);
+    }
+
+    #[test]
+    fn test_short_mode_feeds_bit_6() {
+        let mut noise = Noise::new();
+        noise.write(3, 0x08);
+        noise.lfsr = 0x0001;
+
+        noise.step_lfsr();
+
+        assert_eq!(noise.lfsr, 0x4040);
+    }
+
+    #[test]
+    fn test_output_after_trigger() {
+        let mut noise = Noise::new();
+        noise.write(2, 0xA0);
+        noise.write(4, 0x80);
+
+        // The LFSR starts with all bits set, so the first output is silent.
+        assert_eq!(noise.output(), 0);
+        noise.tick(8);
+
+        assert_eq!(noise.output(), 0);
+        noise.lfsr = 0x7FFE;
+        assert_eq!(noise.output(), 10);
+    }
+}
@@ -0,0 +1,159 @@
+use std::f64::consts::PI;
+
+use super::CPU_CLOCK;
+
+// Turns the APU's output, a level that only changes at exact cycles, into samples at any rate. Each
+// change adds a band-limited step, a windowed sinc, to the samples around it instead of showing up
+// in the next sample as a hard edge, which would alias into the audible range. The output runs
+// half the kernel's width behind the input, a third of a millisecond at 48 kHz.
+pub struct Resampler {
+    // The time since `deltas[0]` in samples, with FRACTION_BITS of fraction.
+    time: u64,
+    // How far one cycle moves `time`. CPU_CLOCK is a power of 2, so this is exact for every rate.
+    step: u64,
+    level: (f32, f32),
+    // The changes spread over the samples they affect, waiting to be summed into levels.
+    deltas: Vec<(f32, f32)>,
+    sum: (f32, f32),
+    kernel: Vec<[f32; TAPS]>,
+}
+
+impl Resampler {
+    pub fn new(sample_rate: u32) -> Resampler {
+        Resampler {
+            time: 0,
+            step: ((sample_rate as u64) << FRACTION_BITS) / CPU_CLOCK,
+            level: (0.0, 0.0),
+            deltas: vec![(0.0, 0.0); TAPS],
+            sum: (0.0, 0.0),
+            kernel: kernel(),
+        }
+    }
+
+    // Changes the rate from the current time on, keeping what's already been resampled.
+    pub fn set_sample_rate(&mut self, sample_rate: u32) {
+        self.step = ((sample_rate as u64) << FRACTION_BITS) / CPU_CLOCK;
+    }
+
+    pub fn advance(&mut self, cycles: u32) {
+        self.time += cycles as u64 * self.step;
+        let needed = (self.time >> FRACTION_BITS) as usize + TAPS;
+        if self.deltas.len() < needed {
+            self.deltas.resize(needed, (0.0, 0.0));
+        }
+    }
+
+    // Moves the output to `level` from the current time on.
+    pub fn set_level(&mut self, level: (f32, f32)) {
+        let delta = (level.0 - self.level.0, level.1 - self.level.1);
+        if delta == (0.0, 0.0) {
+            return;
+        }
+        self.level = level;
+
+        let start = (self.time >> FRACTION_BITS) as usize;
+        let phase = (self.time >> (FRACTION_BITS - PHASE_BITS)) as usize & (PHASES - 1);
+        for (slot, weight) in self.deltas[start..start + TAPS]
+            .iter_mut()
+            .zip(self.kernel[phase])
+        {
+            slot.0 += delta.0 * weight;
+            slot.1 += delta.1 * weight;
+        }
+    }
+
+    // Passes every sample no later change can affect to `output`.
+    pub fn drain(&mut self, mut output: impl FnMut(f32, f32)) {
+        let finished = (self.time >> FRACTION_BITS) as usize;
+        for &(left, right) in &self.deltas[..finished] {
+            self.sum = (self.sum.0 + left, self.sum.1 + right);
+            output(self.sum.0, self.sum.1);
+        }
+        self.deltas.drain(..finished);
+        self.time -= (finished as u64) << FRACTION_BITS;
+    }
+}
+
+// A Blackman-windowed sinc for each phase, the fraction of a sample between the change and the
+// sample before it. Each phase sums to 1 so a step always settles on exactly the new level.
+fn kernel() -> Vec<[f32; TAPS]> {
+    (0..PHASES)
+        .map(|phase| {
+            let offset = phase as f64 / PHASES as f64;
+            let mut taps = [0.0; TAPS];
+            for (tap, weight) in taps.iter_mut().enumerate() {
+                let x = tap as f64 - (TAPS / 2) as f64 + 1.0 - offset;
+                let sinc = if x == 0.0 {
+                    1.0
+                } else {
+                    (PI * x * CUTOFF).sin() / (PI * x * CUTOFF)
+                };
+                let t = (x + (TAPS / 2) as f64) / TAPS as f64;
+                let window = 0.42 - 0.5 * (2.0 * PI * t).cos() + 0.08 * (4.0 * PI * t).cos();
+                *weight = sinc * window;
+            }
+            let total: f64 = taps.iter().sum();
+            taps.map(|weight| (weight / total) as f32)
+        })
+        .collect()
+}
+
+// Wide enough to keep aliasing well below what 16-bit output can resolve.
+const TAPS: usize = 16;
+
+const PHASE_BITS: u32 = 6;
+
+const PHASES: usize = 1 << PHASE_BITS;
+
+const FRACTION_BITS: u32 = 32;
+
+// The band limit as a fraction of half the sample rate, leaving room for the window's roll-off.
+const CUTOFF: f64 = 0.9;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn collect(resampler: &mut Resampler) -> Vec<f32> {
+        let mut samples = Vec::new();
+        resampler.drain(|left, _| samples.push(left));
+        samples
+    }
+
+    #[test]
+    fn test_rate() {
+        let mut resampler = Resampler::new(44_100);
+
+        resampler.advance(CPU_CLOCK as u32);
+
+        assert_eq!(collect(&mut resampler).len(), 44_100);
+    }
+
+    #[test]
+    fn test_step_settles_on_level() {
+        // Arrange
+        let mut resampler = Resampler::new(48_000);
+        resampler.advance(1000);
+
+        // Act
+        resampler.set_level((0.5, -0.5));
+        resampler.advance(10_000);
+
+        // Assert
+        let samples = collect(&mut resampler);
+        // Nothing changes before the step reaches the output.
+        assert!(samples[..8].iter().all(|&sample| sample == 0.0));
+        assert!((samples.last().unwrap() - 0.5).abs() < 1e-6);
+        // The edge rings a little instead of jumping.
+        let overshoot = samples.iter().cloned().fold(0.0, f32::max);
+        assert!(overshoot > 0.5 && overshoot < 0.6);
+    }
+
+    #[test]
+    fn test_kernel_phases_are_normalized() {
+        for phase in kernel() {
+            let total: f32 = phase.iter().sum();
+            assert!((total - 1.0).abs() < 1e-6);
+        }
+    }
+}
@@ -0,0 +1,330 @@
+use std::io::Result;
+
+use super::envelope::Envelope;
+use super::length_counter::LengthCounter;
+use crate::savestate::{StateReader, StateWriter};
+
+// Channels 1 and 2. Only channel 1 has the frequency sweep unit.
+pub struct Square {
+    has_sweep: bool,
+    enabled: bool,
+    duty: u8,
+    duty_step: u8,
+    frequency: u16,
+    timer: u32,
+    length: LengthCounter,
+    envelope: Envelope,
+    sweep_period: u8,
+    sweep_negate: bool,
+    sweep_shift: u8,
+    sweep_timer: u8,
+    sweep_enabled: bool,
+    shadow_frequency: u16,
+}
+
+impl Square {
+    pub fn new(has_sweep: bool) -> Square {
+        Square {
+            has_sweep,
+            enabled: false,
+            duty: 0,
+            duty_step: 0,
+            frequency: 0,
+            timer: 0,
+            length: LengthCounter::new(64),
+            envelope: Envelope::default(),
+            sweep_period: 0,
+            sweep_negate: false,
+            sweep_shift: 0,
+            sweep_timer: 0,
+            sweep_enabled: false,
+            shadow_frequency: 0,
+        }
+    }
+
+    pub fn enabled(&self) -> bool {
+        self.enabled
+    }
+
+    pub fn dac_enabled(&self) -> bool {
+        self.envelope.dac_enabled()
+    }
+
+    // `register` is 0-4 for NRx0-NRx4.
+    pub fn read(&self, register: u16) -> u8 {
+        match register {
+            0 => {
+                (self.sweep_period << 4)
+                    | if self.sweep_negate { 0x08 } else { 0 }
+                    | self.sweep_shift
+            }
+            1 => self.duty << 6,
+            2 => self.envelope.read(),
+            4 if self.length.enabled() => 0x40,
+            _ => 0,
+        }
+    }
+
+    pub fn write(&mut self, register: u16, value: u8) {
+        match register {
+            0 => {
+                if self.has_sweep {
+                    self.sweep_period = (value >> 4) & 0x07;
+                    self.sweep_negate = value & 0x08 != 0;
+                    self.sweep_shift = value & 0x07;
+                }
+            }
+            1 => {
+                self.duty = value >> 6;
+                self.length.load(value & 0x3F);
+            }
+            2 => {
+                self.envelope.write(value);
+                if !self.dac_enabled() {
+                    self.enabled = false;
+                }
+            }
+            3 => self.frequency = (self.frequency & 0x700) | value as u16,
+            _ => {
+                self.frequency = (self.frequency & 0x0FF) | ((value as u16 & 0x07) << 8);
+                self.length.set_enabled(value & 0x40 != 0);
+                if value & 0x80 != 0 {
+                    self.trigger();
+                }
+            }
+        }
+    }
+
+    // Cycles until the next step.
+    pub fn timer(&self) -> u32 {
+        self.timer
+    }
+
+    pub fn tick(&mut self, cycles: u32) {
+        let mut cycles = cycles;
+        while cycles > 0 {
+            if self.timer > cycles {
+                self.timer -= cycles;
+                break;
+            }
+
+            cycles -= self.timer;
+            self.timer = self.period();
+            self.duty_step = (self.duty_step + 1) % 8;
+        }
+    }
+
+    pub fn clock_length(&mut self) {
+        if self.length.clock() {
+            self.enabled = false;
+        }
+    }
+
+    pub fn clock_envelope(&mut self) {
+        self.envelope.clock();
+    }
+
+    pub fn clock_sweep(&mut self) {
+        self.sweep_timer = self.sweep_timer.saturating_sub(1);
+        if self.sweep_timer > 0 {
+            return;
+        }
+
+        self.sweep_timer = self.sweep_reload();
+        if !self.sweep_enabled || self.sweep_period == 0 {
+            return;
+        }
+
+        let frequency = self.next_sweep_frequency();
+        if frequency <= 2047 && self.sweep_shift != 0 {
+            self.frequency = frequency;
+            self.shadow_frequency = frequency;
+            // The new frequency is checked for overflow a second time without being stored.
+            self.next_sweep_frequency();
+        }
+    }
+
+    // The digital output, 0-15.
+    pub fn output(&self) -> u8 {
+        if !self.enabled {
+            return 0;
+        }
+
+        let high = (DUTY_PATTERNS[self.duty as usize] >> (7 - self.duty_step)) & 0x01;
+        high * self.envelope.volume()
+    }
+
+    fn period(&self) -> u32 {
+        (2048 - self.frequency as u32) * 4
+    }
+
+    fn sweep_reload(&self) -> u8 {
+        // A period of 0 is treated as 8.
+        if self.sweep_period == 0 {
+            8
+        } else {
+            self.sweep_period
+        }
+    }
+
+    fn next_sweep_frequency(&mut self) -> u16 {
+        let delta = self.shadow_frequency >> self.sweep_shift;
+        let frequency = if self.sweep_negate {
+            self.shadow_frequency.wrapping_sub(delta)
+        } else {
+            self.shadow_frequency + delta
+        };
+
+        if frequency > 2047 {
+            self.enabled = false;
+        }
+        frequency
+    }
+
+    fn trigger(&mut self) {
+        self.enabled = self.dac_enabled();
+        self.length.trigger();
+        self.timer = self.period();
+        self.envelope.trigger();
+
+        if self.has_sweep {
+            self.shadow_frequency = self.frequency;
+            self.sweep_timer = self.sweep_reload();
+            self.sweep_enabled = self.sweep_period != 0 || self.sweep_shift != 0;
+            if self.sweep_shift != 0 {
+                self.next_sweep_frequency();
+            }
+        }
+    }
+
+    pub fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_bool(self.enabled);
+        writer.write_u8(self.duty);
+        writer.write_u8(self.duty_step);
+        writer.write_u16(self.frequency);
+        writer.write_u32(self.timer);
+        self.length.save_state(writer);
+        self.envelope.save_state(writer);
+        writer.write_u8(self.sweep_period);
+        writer.write_bool(self.sweep_negate);
+        writer.write_u8(self.sweep_shift);
+        writer.write_u8(self.sweep_timer);
+        writer.write_bool(self.sweep_enabled);
+        writer.write_u16(self.shadow_frequency);
+    }
+
+    pub fn load_state(&mut self, reader: &mut StateReader) -> Result<()> {
+        self.enabled = reader.read_bool()?;
+        self.duty = reader.read_u8()?;
+        self.duty_step = reader.read_u8()?;
+        self.frequency = reader.read_u16()?;
+        self.timer = reader.read_u32()?;
+        self.length.load_state(reader)?;
+        self.envelope.load_state(reader)?;
+        self.sweep_period = reader.read_u8()?;
+        self.sweep_negate = reader.read_bool()?;
+        self.sweep_shift = reader.read_u8()?;
+        self.sweep_timer = reader.read_u8()?;
+        self.sweep_enabled = reader.read_bool()?;
+        self.shadow_frequency = reader.read_u16()?;
+        Ok(())
+    }
+}
+
+const DUTY_PATTERNS: [u8; 4] = [0b0000_0001, 0b1000_0001, 0b1000_0111, 0b0111_1110];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn triggered_square(duty: u8, frequency: u16) -> Square {
+        let mut square = Square::new(true);
+        square.write(1, duty << 6);
+        square.write(2, 0xF0);
+        square.write(3, frequency as u8);
+        square.write(4, 0x80 | (frequency >> 8) as u8);
+        square
+    }
+
+    #[test]
+    fn test_duty_cycle_waveform() {
+        // Arrange
+        let mut square = triggered_square(2, 2047);
+        let mut waveform = Vec::new();
+
+        // Act
+        for _ in 0..8 {
+            square.tick(4);
+            waveform.push(square.output());
+        }
+
+        // Assert
+        assert_eq!(waveform, vec![0, 0, 0, 0, 15, 15, 15, 15]);
+    }
+
+    #[test]
+    fn test_trigger_without_dac_stays_disabled() {
+        let mut square = Square::new(false);
+        square.write(2, 0x00);
+
+        square.write(4, 0x80);
+
+        assert!(!square.enabled());
+    }
+
+    #[test]
+    fn test_turning_dac_off_disables_channel() {
+        let mut square = triggered_square(0, 0);
+
+        square.write(2, 0x00);
+
+        assert!(!square.enabled());
+    }
+
+    #[test]
+    fn test_length_disables_channel() {
+        let mut square = triggered_square(0, 0);
+        square.write(1, 63);
+        square.write(4, 0xC0);
+
+        square.clock_length();
+
+        assert!(!square.enabled());
+    }
+
+    #[test]
+    fn test_sweep_increases_frequency() {
+        let mut square = Square::new(true);
+        square.write(0, 0x11);
+        square.write(2, 0xF0);
+        square.write(3, 0x00);
+        square.write(4, 0x81);
+
+        square.clock_sweep();
+
+        assert_eq!(square.frequency, 0x180);
+        assert!(square.enabled());
+    }
+
+    #[test]
+    fn test_sweep_overflow_disables_channel() {
+        let mut square = Square::new(true);
+        square.write(0, 0x11);
+        square.write(2, 0xF0);
+        square.write(3, 0xFF);
+        square.write(4, 0x85);
+
+        square.clock_sweep();
+
+        assert!(!square.enabled());
+    }
+
+    #[test]
+    fn test_sweep_ignored_on_channel_two() {
+        let mut square = Square::new(false);
+
+        square.write(0, 0x7F);
+
+        assert_eq!(square.read(0), 0);
+    }
+}
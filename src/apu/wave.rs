@@ -0,0 +1,219 @@
+use std::io::Result;
+
+use super::length_counter::LengthCounter;
+use crate::savestate::{StateReader, StateWriter};
+
+// Channel 3, which plays back 32 4-bit samples from wave RAM.
+pub struct Wave {
+    enabled: bool,
+    dac_enabled: bool,
+    volume_code: u8,
+    frequency: u16,
+    timer: u32,
+    position: u8,
+    sample_buffer: u8,
+    length: LengthCounter,
+    ram: [u8; 16],
+}
+
+impl Default for Wave {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Wave {
+    pub fn new() -> Wave {
+        Wave {
+            enabled: false,
+            dac_enabled: false,
+            volume_code: 0,
+            frequency: 0,
+            timer: 0,
+            position: 0,
+            sample_buffer: 0,
+            length: LengthCounter::new(256),
+            ram: [0; 16],
+        }
+    }
+
+    pub fn enabled(&self) -> bool {
+        self.enabled
+    }
+
+    pub fn dac_enabled(&self) -> bool {
+        self.dac_enabled
+    }
+
+    // `register` is 0-4 for NR30-NR34.
+    pub fn read(&self, register: u16) -> u8 {
+        match register {
+            0 if self.dac_enabled => 0x80,
+            2 => self.volume_code << 5,
+            4 if self.length.enabled() => 0x40,
+            _ => 0,
+        }
+    }
+
+    pub fn write(&mut self, register: u16, value: u8) {
+        match register {
+            0 => {
+                self.dac_enabled = value & 0x80 != 0;
+                if !self.dac_enabled {
+                    self.enabled = false;
+                }
+            }
+            1 => self.length.load(value),
+            2 => self.volume_code = (value >> 5) & 0x03,
+            3 => self.frequency = (self.frequency & 0x700) | value as u16,
+            _ => {
+                self.frequency = (self.frequency & 0x0FF) | ((value as u16 & 0x07) << 8);
+                self.length.set_enabled(value & 0x40 != 0);
+                if value & 0x80 != 0 {
+                    self.trigger();
+                }
+            }
+        }
+    }
+
+    pub fn read_ram(&self, index: usize) -> u8 {
+        self.ram[index]
+    }
+
+    pub fn write_ram(&mut self, index: usize, value: u8) {
+        self.ram[index] = value;
+    }
+
+    // Cycles until the next step.
+    pub fn timer(&self) -> u32 {
+        self.timer
+    }
+
+    pub fn tick(&mut self, cycles: u32) {
+        let mut cycles = cycles;
+        while cycles > 0 {
+            if self.timer > cycles {
+                self.timer -= cycles;
+                break;
+            }
+
+            cycles -= self.timer;
+            self.timer = self.period();
+            self.position = (self.position + 1) % 32;
+            let byte = self.ram[self.position as usize / 2];
+            self.sample_buffer = if self.position.is_multiple_of(2) {
+                byte >> 4
+            } else {
+                byte & 0x0F
+            };
+        }
+    }
+
+    pub fn clock_length(&mut self) {
+        if self.length.clock() {
+            self.enabled = false;
+        }
+    }
+
+    pub fn output(&self) -> u8 {
+        if !self.enabled {
+            return 0;
+        }
+
+        match self.volume_code {
+            0 => 0,
+            code => self.sample_buffer >> (code - 1),
+        }
+    }
+
+    fn period(&self) -> u32 {
+        (2048 - self.frequency as u32) * 2
+    }
+
+    fn trigger(&mut self) {
+        self.enabled = self.dac_enabled;
+        self.length.trigger();
+        self.timer = self.period();
+        self.position = 0;
+    }
+
+    pub fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_bool(self.enabled);
+        writer.write_bool(self.dac_enabled);
+        writer.write_u8(self.volume_code);
+        writer.write_u16(self.frequency);
+        writer.write_u32(self.timer);
+        writer.write_u8(self.position);
+        writer.write_u8(self.sample_buffer);
+        self.length.save_state(writer);
+        writer.write_bytes(&self.ram);
+    }
+
+    pub fn load_state(&mut self, reader: &mut StateReader) -> Result<()> {
+        self.enabled = reader.read_bool()?;
+        self.dac_enabled = reader.read_bool()?;
+        self.volume_code = reader.read_u8()?;
+        self.frequency = reader.read_u16()?;
+        self.timer = reader.read_u32()?;
+        self.position = reader.read_u8()?;
+        self.sample_buffer = reader.read_u8()?;
+        self.length.load_state(reader)?;
+        reader.read_bytes_into(&mut self.ram)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rstest::rstest;
+
+    use super::*;
+
+    fn triggered_wave(volume_code: u8) -> Wave {
+        let mut wave = Wave::new();
+        for index in 0..16 {
+            wave.write_ram(index, ((index as u8 * 2) << 4) | (index as u8 * 2 + 1));
+        }
+        wave.write(0, 0x80);
+        wave.write(2, volume_code << 5);
+        wave.write(3, 0xFF);
+        wave.write(4, 0x87);
+        wave
+    }
+
+    #[test]
+    fn test_plays_samples_in_order() {
+        let mut wave = triggered_wave(1);
+        let mut samples = Vec::new();
+
+        for _ in 0..4 {
+            wave.tick(2);
+            samples.push(wave.output());
+        }
+
+        assert_eq!(samples, vec![1, 2, 3, 4]);
+    }
+
+    #[rstest]
+    #[case(0, 0)]
+    #[case(1, 15)]
+    #[case(2, 7)]
+    #[case(3, 3)]
+    fn test_volume_shift(#[case] volume_code: u8, #[case] expected: u8) {
+        let mut wave = triggered_wave(volume_code);
+        wave.write_ram(0, 0x0F);
+
+        wave.tick(2);
+
+        assert_eq!(wave.output(), expected);
+    }
+
+    #[test]
+    fn test_dac_off_disables_channel() {
+        let mut wave = triggered_wave(1);
+
+        wave.write(0, 0x00);
+
+        assert!(!wave.enabled());
+    }
+}
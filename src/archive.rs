@@ -0,0 +1,325 @@
+use std::fs;
+use std::io::{Error, Read, Result};
+use std::path::Path;
+
+use flate2::read::{DeflateDecoder, GzDecoder};
+
+use crate::hash::crc32;
+use crate::rom::MAX_ROM_SIZE;
+
+// Reads a ROM that may be compressed. A .gz file holds the ROM itself, and a .zip file the first
+// .gb or .gbc file in it, or the one named after a # like games.zip#tetris.gb. Anything else is
+// read as is.
+pub fn read(path: &str) -> Result<Vec<u8>> {
+    let (path, entry) = match path.rsplit_once('#') {
+        Some((archive, entry)) if has_extension(archive, "zip") => (archive, Some(entry)),
+        _ => (path, None),
+    };
+    let content = fs::read(path)?;
+    if has_extension(path, "zip") {
+        unzip(&content, entry).map_err(|error| Error::other(format!("{}: {}", path, error)))
+    } else if has_extension(path, "gz") {
+        decompress(GzDecoder::new(content.as_slice()))
+    } else {
+        Ok(content)
+    }
+}
+
+// The contents of the ZIP file entry called `name`, which may leave out the directories it's in, or
+// the first ROM.
+pub fn unzip(zip: &[u8], name: Option<&str>) -> Result<Vec<u8>> {
+    let entries = central_directory(zip)?;
+    let entry = entries
+        .iter()
+        .find(|entry| match name {
+            Some(name) => entry.name == name || entry.name.rsplit('/').next() == Some(name),
+            None => ROM_EXTENSIONS
+                .iter()
+                .any(|extension| has_extension(&entry.name, extension)),
+        })
+        .ok_or_else(|| match name {
+            Some(name) => Error::other(format!("There's no {} in the archive.", name)),
+            None => Error::other("There's no .gb or .gbc file in the archive."),
+        })?;
+    entry.extract(zip)
+}
+
+struct Entry {
+    name: String,
+    method: u16,
+    crc: u32,
+    compressed_size: usize,
+    size: usize,
+    header_offset: usize,
+}
+
+impl Entry {
+    fn extract(&self, zip: &[u8]) -> Result<Vec<u8>> {
+        let header = slice(zip, self.header_offset, LOCAL_HEADER_SIZE)?;
+        if le32(header, 0) != LOCAL_HEADER_SIGNATURE {
+            return Err(truncated());
+        }
+        let start = self.header_offset
+            + LOCAL_HEADER_SIZE
+            + le16(header, 26) as usize
+            + le16(header, 28) as usize;
+        let data = slice(zip, start, self.compressed_size)?;
+        if self.size > MAX_ROM_SIZE {
+            return Err(too_large());
+        }
+        let content = match self.method {
+            STORED => data.to_vec(),
+            DEFLATED => decompress(DeflateDecoder::new(data))?,
+            method => {
+                return Err(Error::other(format!(
+                "{} is compressed with method {}, only stored and deflated files are supported.",
+                self.name, method
+            )))
+            }
+        };
+        if content.len() != self.size || crc32(&content) != self.crc {
+            return Err(Error::other(format!("{} is corrupt.", self.name)));
+        }
+        Ok(content)
+    }
+}
+
+// The files listed at the end of the archive, skipping past a comment if there is one.
+fn central_directory(zip: &[u8]) -> Result<Vec<Entry>> {
+    let end = (0..=zip.len().saturating_sub(END_RECORD_SIZE))
+        .rev()
+        .find(|&offset| zip[offset..].starts_with(&END_SIGNATURE.to_le_bytes()))
+        .ok_or_else(|| Error::other("It isn't a ZIP file."))?;
+    let record = slice(zip, end, END_RECORD_SIZE)?;
+    let count = le16(record, 10) as usize;
+    let mut offset = le32(record, 16) as usize;
+    let mut entries = Vec::with_capacity(count);
+    for _ in 0..count {
+        let header = slice(zip, offset, CENTRAL_HEADER_SIZE)?;
+        if le32(header, 0) != CENTRAL_HEADER_SIGNATURE {
+            return Err(truncated());
+        }
+        let name_length = le16(header, 28) as usize;
+        let name = slice(zip, offset + CENTRAL_HEADER_SIZE, name_length)?;
+        entries.push(Entry {
+            name: String::from_utf8_lossy(name).into_owned(),
+            method: le16(header, 10),
+            crc: le32(header, 16),
+            compressed_size: le32(header, 20) as usize,
+            size: le32(header, 24) as usize,
+            header_offset: le32(header, 42) as usize,
+        });
+        offset += CENTRAL_HEADER_SIZE
+            + name_length
+            + le16(header, 30) as usize
+            + le16(header, 32) as usize;
+    }
+    Ok(entries)
+}
+
+// Stops at a byte past the largest ROM, so a ZIP bomb can't use up the memory.
+fn decompress(decoder: impl Read) -> Result<Vec<u8>> {
+    let mut content = Vec::new();
+    decoder
+        .take(MAX_ROM_SIZE as u64 + 1)
+        .read_to_end(&mut content)?;
+    if content.len() > MAX_ROM_SIZE {
+        return Err(too_large());
+    }
+    Ok(content)
+}
+
+fn has_extension(path: &str, extension: &str) -> bool {
+    Path::new(path)
+        .extension()
+        .is_some_and(|actual| actual.eq_ignore_ascii_case(extension))
+}
+
+fn slice(data: &[u8], start: usize, length: usize) -> Result<&[u8]> {
+    start
+        .checked_add(length)
+        .and_then(|end| data.get(start..end))
+        .ok_or_else(truncated)
+}
+
+fn truncated() -> Error {
+    Error::other("The ZIP file is truncated.")
+}
+
+fn too_large() -> Error {
+    Error::other("The file is larger than any ROM.")
+}
+
+fn le16(data: &[u8], offset: usize) -> u16 {
+    u16::from_le_bytes([data[offset], data[offset + 1]])
+}
+
+fn le32(data: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes(data[offset..offset + 4].try_into().unwrap())
+}
+
+const ROM_EXTENSIONS: [&str; 2] = ["gb", "gbc"];
+
+const END_SIGNATURE: u32 = 0x0605_4B50;
+const END_RECORD_SIZE: usize = 22;
+const CENTRAL_HEADER_SIGNATURE: u32 = 0x0201_4B50;
+const CENTRAL_HEADER_SIZE: usize = 46;
+const LOCAL_HEADER_SIGNATURE: u32 = 0x0403_4B50;
+const LOCAL_HEADER_SIZE: usize = 30;
+
+const STORED: u16 = 0;
+const DEFLATED: u16 = 8;
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use flate2::write::{DeflateEncoder, GzEncoder};
+    use flate2::Compression;
+    use rstest::rstest;
+    use tempfile::tempdir;
+
+    use super::*;
+
+    // A ZIP file with `files`, deflating the ones marked true.
+    fn zip(files: &[(&str, &[u8], bool)]) -> Vec<u8> {
+        let (mut zip, mut directory) = (Vec::new(), Vec::new());
+        for &(name, content, deflate) in files {
+            let data = if deflate {
+                let mut encoder = DeflateEncoder::new(Vec::new(), Compression::default());
+                encoder.write_all(content).unwrap();
+                encoder.finish().unwrap()
+            } else {
+                content.to_vec()
+            };
+            let method = if deflate { DEFLATED } else { STORED };
+            let sizes = [crc32(content), data.len() as u32, content.len() as u32];
+            let offset = zip.len() as u32;
+
+            zip.extend(LOCAL_HEADER_SIGNATURE.to_le_bytes());
+            zip.extend([20, 0, 0, 0]);
+            zip.extend(method.to_le_bytes());
+            zip.extend([0; 4]);
+            sizes.iter().for_each(|size| zip.extend(size.to_le_bytes()));
+            zip.extend((name.len() as u16).to_le_bytes());
+            zip.extend([0; 2]);
+            zip.extend(name.as_bytes());
+            zip.extend(&data);
+
+            directory.extend(CENTRAL_HEADER_SIGNATURE.to_le_bytes());
+            directory.extend([20, 0, 20, 0, 0, 0]);
+            directory.extend(method.to_le_bytes());
+            directory.extend([0; 4]);
+            sizes
+                .iter()
+                .for_each(|size| directory.extend(size.to_le_bytes()));
+            directory.extend((name.len() as u16).to_le_bytes());
+            directory.extend([0; 12]);
+            directory.extend(offset.to_le_bytes());
+            directory.extend(name.as_bytes());
+        }
+        let directory_offset = zip.len() as u32;
+        zip.extend(&directory);
+        zip.extend(END_SIGNATURE.to_le_bytes());
+        zip.extend([0; 4]);
+        zip.extend((files.len() as u16).to_le_bytes());
+        zip.extend((files.len() as u16).to_le_bytes());
+        zip.extend((directory.len() as u32).to_le_bytes());
+        zip.extend(directory_offset.to_le_bytes());
+        // A comment, which the end record has to be found behind.
+        zip.extend(7u16.to_le_bytes());
+        zip.extend(b"comment");
+        zip
+    }
+
+    #[rstest]
+    #[case(None, Some(b"first".as_slice()))]
+    #[case(Some("roms/second.gbc"), Some(b"second".as_slice()))]
+    #[case(Some("second.gbc"), Some(b"second".as_slice()))]
+    #[case(Some("third.gb"), None)]
+    fn test_unzip(#[case] name: Option<&str>, #[case] expected: Option<&[u8]>) {
+        let zip = zip(&[
+            ("readme.txt", b"not a rom", false),
+            ("first.GB", b"first", false),
+            ("roms/second.gbc", b"second", true),
+        ]);
+
+        let content = unzip(&zip, name);
+
+        assert_eq!(content.ok().as_deref(), expected);
+    }
+
+    #[test]
+    fn test_unzip_without_rom() {
+        let zip = zip(&[("readme.txt", b"not a rom", true)]);
+
+        assert!(unzip(&zip, None).is_err());
+        assert!(unzip(b"not a zip", None).is_err());
+    }
+
+    #[test]
+    fn test_unzip_corrupt() {
+        let mut zip = zip(&[("game.gb", b"game", false)]);
+        zip[LOCAL_HEADER_SIZE + "game.gb".len()] ^= 0xFF;
+
+        assert!(unzip(&zip, None).is_err());
+    }
+
+    #[rstest]
+    // Too short for an end record after the signature.
+    #[case(END_SIGNATURE.to_le_bytes().to_vec())]
+    #[case([END_SIGNATURE.to_le_bytes().as_slice(), &[0; 8]].concat())]
+    fn test_unzip_truncated_end_record(#[case] zip: Vec<u8>) {
+        assert!(unzip(&zip, None).is_err());
+    }
+
+    #[test]
+    fn test_unzip_too_large() {
+        // Arrange
+        let content = vec![0; MAX_ROM_SIZE + 1];
+        let mut zip = zip(&[("game.gb", &content, true)]);
+        // Claims to be small, so only the decompressed length gives it away.
+        let size_offset = zip.len() - END_RECORD_SIZE - 7 - CENTRAL_HEADER_SIZE - 7 + 24;
+        zip[size_offset..size_offset + 4].copy_from_slice(&1u32.to_le_bytes());
+
+        // Act
+        let result = unzip(&zip, None);
+
+        // Assert
+        assert_eq!(
+            result.unwrap_err().to_string(),
+            "The file is larger than any ROM."
+        );
+    }
+
+    #[test]
+    fn test_read() {
+        // Arrange
+        let directory = tempdir().unwrap();
+        let mut gz = GzEncoder::new(Vec::new(), Compression::default());
+        gz.write_all(b"gzipped").unwrap();
+        fs::write(directory.path().join("game.gb.gz"), gz.finish().unwrap()).unwrap();
+        fs::write(
+            directory.path().join("games.zip"),
+            zip(&[("a.gb", b"a", false), ("b.gb", b"b", true)]),
+        )
+        .unwrap();
+        fs::write(directory.path().join("game.gb"), b"plain").unwrap();
+        let path = |name: &str| directory.path().join(name).display().to_string();
+
+        // Act
+        let contents = ["game.gb.gz", "games.zip", "games.zip#b.gb", "game.gb"]
+            .map(|name| read(&path(name)).unwrap());
+
+        // Assert
+        assert_eq!(
+            contents,
+            [
+                b"gzipped".to_vec(),
+                b"a".to_vec(),
+                b"b".to_vec(),
+                b"plain".to_vec()
+            ]
+        );
+    }
+}
@@ -0,0 +1,124 @@
+use std::fs;
+use std::io::{ErrorKind, Result};
+use std::path::{Path, PathBuf};
+
+use crate::emulator::Emulator;
+
+// Saves live next to the ROM with the same name, e.g. game.gb and game.sav.
+pub fn save_path(rom_path: &Path) -> PathBuf {
+    rom_path.with_extension("sav")
+}
+
+// The path to give `load` and `save` for the save to go in `save_dir`, when there is one, instead of
+// next to the ROM. It's named after the ROM either way.
+pub fn rom_path_in(rom_path: &Path, save_dir: Option<&Path>) -> PathBuf {
+    match save_dir {
+        Some(directory) => directory.join(rom_path.file_name().unwrap_or_default()),
+        None => rom_path.to_path_buf(),
+    }
+}
+
+// Restores the cartridge RAM from the save next to the ROM. A missing save is not an error.
+pub fn load(emulator: &mut Emulator, rom_path: &Path) -> Result<()> {
+    if !emulator.has_battery() {
+        return Ok(());
+    }
+
+    match fs::read(save_path(rom_path)) {
+        Ok(data) => emulator.load_save_data(&data),
+        Err(error) if error.kind() == ErrorKind::NotFound => {}
+        Err(error) => return Err(error),
+    }
+
+    Ok(())
+}
+
+// Writes to a temporary file first so a crash mid-write can't corrupt the existing save.
+pub fn save(emulator: &Emulator, rom_path: &Path) -> Result<()> {
+    if !emulator.has_battery() {
+        return Ok(());
+    }
+
+    let path = save_path(rom_path);
+    let temporary = path.with_extension("sav.tmp");
+    fs::write(&temporary, emulator.save_data())?;
+    fs::rename(temporary, path)
+}
+
+#[cfg(test)]
+mod tests {
+    use tempfile::{tempdir, TempDir};
+
+    use super::*;
+    use crate::cpu::Memory;
+    use crate::rom::Rom;
+
+    fn emulator(cartridge_type: u8) -> Emulator {
+        let mut content = vec![0; 0x8000];
+        content[0x147] = cartridge_type;
+        content[0x149] = 0x02;
+        Emulator::new(Rom::from_content(content)).unwrap()
+    }
+
+    // The directory goes away when it's dropped.
+    fn rom_path() -> (TempDir, PathBuf) {
+        let directory = tempdir().unwrap();
+        let path = directory.path().join("game.gb");
+        (directory, path)
+    }
+
+    #[test]
+    fn test_rom_path_in() {
+        let rom_path = Path::new("roms/game.gb");
+
+        assert_eq!(
+            save_path(&rom_path_in(rom_path, Some(Path::new("saves")))),
+            Path::new("saves/game.sav")
+        );
+        assert_eq!(rom_path_in(rom_path, None), rom_path);
+    }
+
+    #[test]
+    fn test_save_path() {
+        assert_eq!(
+            save_path(Path::new("roms/game.gb")),
+            PathBuf::from("roms/game.sav")
+        );
+    }
+
+    #[test]
+    fn test_save_and_load() {
+        // Arrange
+        let (_directory, path) = rom_path();
+        let mut emulator = emulator(0x03);
+        emulator.bus_mut().write(0x0000, 0x0A);
+        emulator.bus_mut().write(0xA123, 0x42);
+
+        // Act
+        save(&emulator, &path).unwrap();
+        let mut loaded = self::emulator(0x03);
+        load(&mut loaded, &path).unwrap();
+        loaded.bus_mut().write(0x0000, 0x0A);
+
+        // Assert
+        assert_eq!(loaded.bus_mut().read(0xA123), 0x42);
+    }
+
+    #[test]
+    fn test_load_without_save() {
+        let (_directory, path) = rom_path();
+        let mut emulator = emulator(0x03);
+
+        assert!(load(&mut emulator, &path).is_ok());
+    }
+
+    #[test]
+    fn test_no_save_without_battery() {
+        let (_directory, path) = rom_path();
+        let emulator = emulator(0x02);
+
+        save(&emulator, &path).unwrap();
+
+        assert!(!save_path(&path).exists());
+    }
+}
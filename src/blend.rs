@@ -0,0 +1,94 @@
+use std::collections::VecDeque;
+
+// Averages the last few frames the way the original LCD's slow pixels did. Games that flicker
+// objects on and off every other frame for transparency, or to show more than 10 on a line, rely
+// on that to look solid.
+pub struct FrameBlend {
+    frames: usize,
+    history: VecDeque<Vec<u8>>,
+    output: Vec<u8>,
+}
+
+impl FrameBlend {
+    pub fn new(frames: usize) -> FrameBlend {
+        FrameBlend {
+            frames: frames.max(1),
+            history: VecDeque::new(),
+            output: Vec::new(),
+        }
+    }
+
+    // How many frames are averaged.
+    pub fn frames(&self) -> usize {
+        self.frames
+    }
+
+    // Adds a finished RGBA frame, dropping the oldest once there are enough.
+    pub fn push(&mut self, frame: &[u8]) {
+        let mut oldest = if self.history.len() == self.frames {
+            self.history.pop_front().unwrap_or_default()
+        } else {
+            Vec::new()
+        };
+        oldest.clear();
+        oldest.extend_from_slice(frame);
+        self.history.push_back(oldest);
+
+        let count = self.history.len() as u32;
+        self.output.resize(frame.len(), 0xFF);
+        for (index, byte) in self.output.iter_mut().enumerate() {
+            let sum: u32 = self.history.iter().map(|frame| frame[index] as u32).sum();
+            *byte = ((sum + count / 2) / count) as u8;
+        }
+    }
+
+    // Forgets the frames so far, after a jump like loading a state.
+    pub fn clear(&mut self) {
+        self.history.clear();
+    }
+
+    // The average of the frames pushed so far, or nothing before the first.
+    pub fn output(&self) -> Option<&[u8]> {
+        (!self.history.is_empty()).then_some(self.output.as_slice())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rstest::rstest;
+
+    use super::*;
+
+    #[rstest]
+    #[case(1, &[200], 200)]
+    #[case(2, &[0, 255], 128)]
+    #[case(2, &[90, 0, 255], 128)]
+    #[case(3, &[0, 30, 60], 30)]
+    fn test_push(#[case] frames: usize, #[case] pushed: &[u8], #[case] expected: u8) {
+        // Arrange
+        let mut blend = FrameBlend::new(frames);
+
+        // Act
+        for &value in pushed {
+            blend.push(&[value, value, value, 0xFF]);
+        }
+
+        // Assert
+        assert_eq!(
+            blend.output(),
+            Some(&[expected, expected, expected, 0xFF][..])
+        );
+    }
+
+    #[test]
+    fn test_clear() {
+        let mut blend = FrameBlend::new(2);
+        blend.push(&[0; 4]);
+
+        blend.clear();
+
+        assert_eq!(blend.output(), None);
+        blend.push(&[100; 4]);
+        assert_eq!(blend.output(), Some(&[100; 4][..]));
+    }
+}
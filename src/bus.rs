@@ -0,0 +1,2078 @@
+use std::fmt;
+use std::io::{Error, Result};
+
+use crate::apu::Apu;
+use crate::camera::CameraSource;
+use crate::cdl::{self, CodeDataLog};
+use crate::cheats::Cheats;
+use crate::colorization;
+use crate::cpu::Memory;
+use crate::infrared::{Infrared, IrDevice};
+use crate::interrupts::Interrupts;
+use crate::joypad::Joypad;
+use crate::mbc::{self, Accelerometer, Mbc, RumbleCallback};
+use crate::model::EmulatorModel;
+use crate::ppu::{Mode, OamCorruption, Ppu};
+use crate::rom::Rom;
+use crate::savestate::{Snapshot, StateReader, StateWriter};
+use crate::scheduler::{Event, Scheduler};
+use crate::serial::{Serial, SerialCallback, SerialDevice};
+use crate::sgb::Sgb;
+use crate::timer::Timer;
+
+pub struct Bus {
+    mbc: Box<dyn Mbc>,
+    ppu: Ppu,
+    apu: Apu,
+    timer: Timer,
+    joypad: Joypad,
+    serial: Serial,
+    infrared: Infrared,
+    // Only with the SGB model and a cartridge that says it supports it.
+    sgb: Option<Sgb>,
+    wram: Vec<u8>,
+    io: Vec<u8>,
+    hram: Vec<u8>,
+    interrupts: Interrupts,
+    dma: Option<Dma>,
+    model: EmulatorModel,
+    cgb: bool,
+    // SVBK: the work RAM bank mapped at 0xD000-0xDFFF, always 1 outside CGB mode.
+    wram_bank: u8,
+    // KEY1: the CPU runs twice as fast as the PPU and APU in double speed mode.
+    double_speed: bool,
+    speed_switch_armed: bool,
+    hdma: Hdma,
+    // Mapped over the start of the cartridge until a write to 0xFF50.
+    boot_rom: Option<Vec<u8>>,
+    scheduler: Scheduler,
+    // The PPU, APU and timer only catch up to the scheduler's time when one of their events is due
+    // or their registers are accessed. These are the times they caught up to.
+    ppu_synced: u64,
+    apu_synced: u64,
+    timer_synced: u64,
+    rtc_synced: u64,
+    // The last byte on the cartridge and work RAM bus, which is what reads nothing answers return.
+    open_bus: u8,
+    memory_strictness: MemoryStrictness,
+    violations: Vec<MemoryViolation>,
+    watchpoints: Vec<Watchpoint>,
+    watch_hits: Vec<WatchHit>,
+    // Where the instruction being executed started, for reporting watchpoint hits.
+    instruction_pc: u16,
+    // When the serial device gets polled next, never without one.
+    next_serial_poll: u64,
+    cheats: Cheats,
+    // The PPU frame the GameShark codes were last applied in.
+    cheat_frame: u64,
+    code_data_log: Option<CodeDataLog>,
+}
+
+// How the bus treats accesses that work on hardware but are usually mistakes.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MemoryStrictness {
+    // Behave like the hardware and nothing else.
+    Permissive,
+    // Also record every such access for `Bus::take_memory_violations`.
+    Report,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ViolationKind {
+    EchoRam,
+    // 0xFEA0-0xFEFF, which Nintendo prohibits using.
+    Unusable,
+    // Cartridge RAM that is disabled, missing or out of range.
+    UnmappedCartridgeRam,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct MemoryViolation {
+    pub kind: ViolationKind,
+    pub address: u16,
+    pub write: bool,
+}
+
+impl fmt::Display for MemoryViolation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let access = if self.write { "Write to" } else { "Read from" };
+        let region = match self.kind {
+            ViolationKind::EchoRam => "echo RAM",
+            ViolationKind::Unusable => "the unusable region",
+            ViolationKind::UnmappedCartridgeRam => "unmapped cartridge RAM",
+        };
+        write!(f, "{} {} at {:#06X}.", access, region, self.address)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AccessKind {
+    Read,
+    Write,
+    Execute,
+}
+
+// An inclusive address range and the kinds of access to it worth stopping for. Fetching an
+// instruction counts as executing it, not as reading its bytes.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Watchpoint {
+    pub start: u16,
+    pub end: u16,
+    pub read: bool,
+    pub write: bool,
+    pub execute: bool,
+}
+
+impl Watchpoint {
+    fn matches(&self, address: u16, kind: AccessKind) -> bool {
+        let watched = match kind {
+            AccessKind::Read => self.read,
+            AccessKind::Write => self.write,
+            AccessKind::Execute => self.execute,
+        };
+        watched && (self.start..=self.end).contains(&address)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct WatchHit {
+    pub kind: AccessKind,
+    pub address: u16,
+    // The byte read or written, or the opcode executed.
+    pub value: u8,
+    // The instruction that made the access.
+    pub pc: u16,
+}
+
+impl fmt::Display for WatchHit {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.kind {
+            AccessKind::Read => write!(f, "Read {:#04X} from", self.value)?,
+            AccessKind::Write => write!(f, "Wrote {:#04X} to", self.value)?,
+            AccessKind::Execute => write!(f, "Executed")?,
+        }
+        write!(f, " {:#06X} at PC {:#06X}.", self.address, self.pc)
+    }
+}
+
+// OAM DMA copies one byte per machine cycle from `source` into OAM.
+struct Dma {
+    source: u16,
+    index: u16,
+}
+
+// CGB VRAM DMA. General purpose DMA copies everything at once, HBlank DMA copies one 16-byte
+// block each time a visible line enters HBlank. The CPU stops while a block copies, for the same
+// time at either speed, so twice the cycles in double speed mode.
+struct Hdma {
+    source: u16,
+    destination: u16,
+    // The number of blocks left minus one, like the low 7 bits of HDMA5 read back.
+    remaining: u8,
+    active: bool,
+    // CPU cycles still to wait for blocks that were copied, and the ones waited since the CPU last
+    // asked.
+    stall: u32,
+    stalled: u32,
+}
+
+impl Bus {
+    pub fn new(rom: Rom) -> Result<Bus> {
+        let model = EmulatorModel::for_rom(&rom)?;
+        Bus::with_model(rom, model)
+    }
+
+    pub fn with_model(rom: Rom, model: EmulatorModel) -> Result<Bus> {
+        let cgb = model.cgb_mode(&rom)?;
+        let palettes = colorization::for_rom(&rom);
+        let sgb = (model == EmulatorModel::Sgb && rom.sgb_support()?).then(Sgb::new);
+        let mut bus = Bus {
+            mbc: mbc::new(rom)?,
+            ppu: Ppu::for_model(model, cgb),
+            apu: Apu::new(),
+            timer: Timer::new(),
+            joypad: Joypad::new(),
+            serial: Serial::new(cgb),
+            infrared: Infrared::new(),
+            sgb,
+            wram: initial_wram(
+                model,
+                if cgb {
+                    WRAM_BANK_SIZE * 8
+                } else {
+                    WRAM_BANK_SIZE * 2
+                },
+            ),
+            io: vec![0; IO_SIZE],
+            hram: vec![0; HRAM_SIZE],
+            interrupts: Interrupts::new(),
+            dma: None,
+            model,
+            cgb,
+            wram_bank: 1,
+            double_speed: false,
+            speed_switch_armed: false,
+            hdma: Hdma {
+                source: 0,
+                destination: 0,
+                remaining: 0x7F,
+                active: false,
+                stall: 0,
+                stalled: 0,
+            },
+            boot_rom: None,
+            scheduler: Scheduler::new(),
+            ppu_synced: 0,
+            apu_synced: 0,
+            timer_synced: 0,
+            rtc_synced: 0,
+            open_bus: 0xFF,
+            memory_strictness: MemoryStrictness::Permissive,
+            violations: Vec::new(),
+            watchpoints: Vec::new(),
+            watch_hits: Vec::new(),
+            instruction_pc: 0,
+            next_serial_poll: u64::MAX,
+            cheats: Cheats::new(),
+            cheat_frame: 0,
+            code_data_log: None,
+        };
+        bus.apply_post_boot_state();
+        if !model.is_cgb() {
+            bus.copy_boot_logo();
+        } else if !cgb {
+            bus.ppu.set_compatibility_palettes(palettes);
+        }
+        bus.schedule_ppu();
+        bus.schedule_timer();
+        bus.schedule_frame_sequencer();
+        Ok(bus)
+    }
+
+    // Sets the I/O registers to the values the boot ROM leaves behind, for starting without one.
+    fn apply_post_boot_state(&mut self) {
+        for &(address, value) in POST_BOOT_REGISTERS {
+            self.write(address, value);
+        }
+    }
+
+    // The monochrome boot ROMs leave the logo they scrolled in behind in VRAM: each bit of the
+    // cartridge's logo doubled in both directions into tiles 1-24, a (R) in tile 25 and a map
+    // showing all of them. Some games fade it out instead of clearing it.
+    fn copy_boot_logo(&mut self) {
+        for index in 0..LOGO_SIZE as u16 {
+            let byte = self.mbc.read_rom(LOGO_ADDRESS + index);
+            for (half, nibble) in [byte >> 4, byte & 0x0F].into_iter().enumerate() {
+                let row = double_bits(nibble);
+                let offset = 0x10 + index * 8 + half as u16 * 4;
+                self.ppu.write_vram_dma(offset, row);
+                self.ppu.write_vram_dma(offset + 2, row);
+            }
+        }
+        for (row, &value) in REGISTERED_TILE.iter().enumerate() {
+            self.ppu.write_vram_dma(0x190 + row as u16 * 2, value);
+        }
+        for tile in 1..=12u16 {
+            self.ppu.write_vram_dma(0x1903 + tile, tile as u8);
+            self.ppu.write_vram_dma(0x1923 + tile, tile as u8 + 12);
+        }
+        self.ppu.write_vram_dma(0x1910, 0x19);
+    }
+
+    // Maps a DMG (256 bytes) or CGB (2304 bytes) boot ROM over the cartridge and turns off the
+    // hardware it initializes, so it runs like after power-on.
+    pub fn map_boot_rom(&mut self, boot_rom: Vec<u8>) -> Result<()> {
+        if boot_rom.len() != DMG_BOOT_ROM_SIZE && boot_rom.len() != CGB_BOOT_ROM_SIZE {
+            return Err(Error::other(format!(
+                "The boot ROM must be {} or {} bytes but was {}.",
+                DMG_BOOT_ROM_SIZE,
+                CGB_BOOT_ROM_SIZE,
+                boot_rom.len()
+            )));
+        }
+
+        self.boot_rom = Some(boot_rom);
+        self.write(0xFF40, 0x00);
+        self.write(0xFF26, 0x00);
+        self.write(0xFF04, 0x00);
+        self.write(0xFF0F, 0x00);
+        Ok(())
+    }
+
+    pub fn boot_rom_mapped(&self) -> bool {
+        self.boot_rom.is_some()
+    }
+
+    // The CGB boot ROM leaves a hole at 0x0100-0x01FF for the cartridge header.
+    fn boot_rom_byte(&self, address: u16) -> Option<u8> {
+        let boot_rom = self.boot_rom.as_ref()?;
+        match address {
+            0x0100..=0x01FF => None,
+            _ => boot_rom.get(address as usize).copied(),
+        }
+    }
+
+    pub fn model(&self) -> EmulatorModel {
+        self.model
+    }
+
+    // Whether the cartridge runs with the CGB features, see `EmulatorModel::cgb_mode`.
+    pub fn cgb(&self) -> bool {
+        self.cgb
+    }
+
+    pub fn double_speed(&self) -> bool {
+        self.double_speed
+    }
+
+    // All of WRAM, with the CGB's switchable banks in order.
+    pub fn wram(&self) -> &[u8] {
+        &self.wram
+    }
+
+    pub fn hram(&self) -> &[u8] {
+        &self.hram
+    }
+
+    pub fn mbc(&self) -> &dyn Mbc {
+        self.mbc.as_ref()
+    }
+
+    pub fn mbc_mut(&mut self) -> &mut dyn Mbc {
+        self.mbc.as_mut()
+    }
+
+    // Only up to date after `sync`, which `Emulator` calls after each frame or step.
+    pub fn ppu(&self) -> &Ppu {
+        &self.ppu
+    }
+
+    pub fn ppu_mut(&mut self) -> &mut Ppu {
+        &mut self.ppu
+    }
+
+    pub fn apu(&self) -> &Apu {
+        &self.apu
+    }
+
+    pub fn apu_mut(&mut self) -> &mut Apu {
+        &mut self.apu
+    }
+
+    pub fn joypad(&self) -> &Joypad {
+        &self.joypad
+    }
+
+    pub fn joypad_mut(&mut self) -> &mut Joypad {
+        &mut self.joypad
+    }
+
+    pub fn sgb(&self) -> Option<&Sgb> {
+        self.sgb.as_ref()
+    }
+
+    // Brings every lazily updated component up to the current time.
+    pub fn sync(&mut self) {
+        self.sync_ppu();
+        self.sync_apu();
+        self.sync_timer();
+        self.sync_rtc();
+    }
+
+    // The PPU and APU run at half the CPU's rate in double speed mode, so they catch up in whole
+    // cycles of their own clock.
+    fn video_cycles_since(&self, synced: u64) -> (u32, u64) {
+        let elapsed = self.scheduler.now() - synced;
+        if self.double_speed {
+            ((elapsed / 2) as u32, elapsed & !1)
+        } else {
+            (elapsed as u32, elapsed)
+        }
+    }
+
+    fn sync_ppu(&mut self) {
+        let (cycles, elapsed) = self.video_cycles_since(self.ppu_synced);
+        self.ppu_synced += elapsed;
+        self.ppu.tick(cycles);
+        self.interrupts.request(self.ppu.take_interrupts());
+        if self.ppu.take_hblank_started() && self.hdma.active {
+            self.copy_hdma_block();
+        }
+        if self.ppu.frames() != self.cheat_frame {
+            self.cheat_frame = self.ppu.frames();
+            self.apply_ram_cheats();
+            if let (Some(sgb), Some(shades)) = (&mut self.sgb, self.ppu.shades()) {
+                sgb.end_frame(&shades);
+            }
+        }
+    }
+
+    fn sync_apu(&mut self) {
+        let (cycles, elapsed) = self.video_cycles_since(self.apu_synced);
+        self.apu_synced += elapsed;
+        self.apu.tick(cycles);
+    }
+
+    // The cartridge clock has its own crystal, so it counts at the normal speed's rate.
+    fn sync_rtc(&mut self) {
+        let (cycles, elapsed) = self.video_cycles_since(self.rtc_synced);
+        self.rtc_synced += elapsed;
+        self.mbc.tick_rtc(cycles as u64);
+    }
+
+    // The timer follows the CPU clock and steps a machine cycle at a time.
+    fn sync_timer(&mut self) {
+        let elapsed = (self.scheduler.now() - self.timer_synced) & !3;
+        self.timer_synced += elapsed;
+        self.timer.tick(elapsed as u32);
+        self.interrupts.request(self.timer.take_interrupts());
+    }
+
+    fn schedule_ppu(&mut self) {
+        match self.ppu.cycles_until_mode_change() {
+            Some(dots) => {
+                let speed = if self.double_speed { 2 } else { 1 };
+                let due = self.ppu_synced + dots as u64 * speed;
+                self.scheduler.schedule_at(Event::PpuModeChange, due);
+            }
+            None => self.scheduler.cancel(Event::PpuModeChange),
+        }
+    }
+
+    fn schedule_timer(&mut self) {
+        match self.timer.cycles_until_interrupt() {
+            Some(cycles) => {
+                let due = self.timer_synced + cycles;
+                self.scheduler.schedule_at(Event::TimerInterrupt, due);
+            }
+            None => self.scheduler.cancel(Event::TimerInterrupt),
+        }
+    }
+
+    fn schedule_frame_sequencer(&mut self) {
+        let cycles = self.timer.cycles_until_div_apu_clock(self.double_speed);
+        let due = self.timer_synced + cycles;
+        self.scheduler.schedule_at(Event::ApuFrameSequencer, due);
+    }
+
+    fn handle_event(&mut self, event: Event) {
+        match event {
+            Event::PpuModeChange => {
+                self.sync_ppu();
+                self.schedule_ppu();
+            }
+            Event::TimerInterrupt => {
+                self.sync_timer();
+                self.schedule_timer();
+            }
+            Event::SerialTransfer => {
+                self.serial.complete_transfer();
+                self.interrupts.request(self.serial.take_interrupts());
+            }
+            Event::ApuFrameSequencer => {
+                self.sync_timer();
+                self.sync_apu();
+                self.apu.clock_frame_sequencer();
+                self.schedule_frame_sequencer();
+            }
+        }
+    }
+
+    fn read_ppu(&mut self, address: u16) -> u8 {
+        self.sync_ppu();
+        self.ppu.read(address)
+    }
+
+    // Writes can turn the LCD on or off, which moves the next mode change.
+    fn write_ppu(&mut self, address: u16, value: u8) {
+        self.sync_ppu();
+        self.ppu.write(address, value);
+        self.schedule_ppu();
+    }
+
+    fn read_apu(&mut self, address: u16) -> u8 {
+        self.sync_apu();
+        self.apu.read(address)
+    }
+
+    fn write_apu(&mut self, address: u16, value: u8) {
+        self.sync_apu();
+        self.apu.write(address, value);
+    }
+
+    fn read_timer(&mut self, address: u16) -> u8 {
+        self.sync_timer();
+        self.timer.read(address)
+    }
+
+    fn write_timer(&mut self, address: u16, value: u8) {
+        self.sync_timer();
+        // Resetting DIV while the frame sequencer's bit is set is a falling edge too.
+        if address == 0xFF04 && self.timer.div_apu_signal(self.double_speed) {
+            self.sync_apu();
+            self.apu.clock_frame_sequencer();
+        }
+        self.timer.write(address, value);
+        self.interrupts.request(self.timer.take_interrupts());
+        self.schedule_timer();
+        self.schedule_frame_sequencer();
+    }
+
+    fn write_serial(&mut self, address: u16, value: u8) {
+        self.serial.write(address, value);
+        match self.serial.transfer_cycles() {
+            Some(cycles) if address == 0xFF02 => {
+                self.scheduler.schedule(Event::SerialTransfer, cycles)
+            }
+            Some(_) => {}
+            None => self.scheduler.cancel(Event::SerialTransfer),
+        }
+    }
+
+    fn step_dma(&mut self) {
+        let Some(dma) = self.dma.as_mut() else {
+            return;
+        };
+
+        let source = dma.source + dma.index;
+        let index = dma.index as usize;
+        dma.index += 1;
+        if dma.index as usize == OAM_SIZE {
+            self.dma = None;
+        }
+
+        // Sources at 0xE000 and above read from work RAM.
+        let source = if source >= 0xE000 {
+            source - 0x2000
+        } else {
+            source
+        };
+        let value = self.read(source);
+        self.sync_ppu();
+        self.ppu.write_oam_dma(index, value);
+    }
+
+    pub fn memory_strictness(&self) -> MemoryStrictness {
+        self.memory_strictness
+    }
+
+    pub fn set_memory_strictness(&mut self, strictness: MemoryStrictness) {
+        self.memory_strictness = strictness;
+    }
+
+    // Returns and clears the accesses recorded since the last call.
+    pub fn take_memory_violations(&mut self) -> Vec<MemoryViolation> {
+        std::mem::take(&mut self.violations)
+    }
+
+    pub fn add_watchpoint(&mut self, watchpoint: Watchpoint) {
+        self.watchpoints.push(watchpoint);
+    }
+
+    // Returns whether an identical watchpoint was there to remove.
+    pub fn remove_watchpoint(&mut self, watchpoint: &Watchpoint) -> bool {
+        let count = self.watchpoints.len();
+        self.watchpoints.retain(|w| w != watchpoint);
+        self.watchpoints.len() != count
+    }
+
+    pub fn watchpoints(&self) -> &[Watchpoint] {
+        &self.watchpoints
+    }
+
+    // Returns and clears the watched accesses since the last call, oldest first.
+    pub fn take_watch_hits(&mut self) -> Vec<WatchHit> {
+        std::mem::take(&mut self.watch_hits)
+    }
+
+    // Plugs a device into the link port. It isn't part of save states.
+    pub fn set_serial_device(&mut self, device: Option<Box<dyn SerialDevice>>) {
+        self.serial.set_device(device);
+        self.reset_serial_poll();
+    }
+
+    pub fn take_serial_device(&mut self) -> Option<Box<dyn SerialDevice>> {
+        let device = self.serial.take_device();
+        self.reset_serial_poll();
+        device
+    }
+
+    // See `Serial::set_callback`.
+    pub fn set_serial_callback(&mut self, callback: Option<SerialCallback>) {
+        self.serial.set_callback(callback);
+    }
+
+    pub fn take_serial_callback(&mut self) -> Option<SerialCallback> {
+        self.serial.take_callback()
+    }
+
+    // Points the CGB's infrared port at a device. It isn't part of save states.
+    pub fn set_ir_device(&mut self, device: Option<Box<dyn IrDevice>>) {
+        self.infrared.set_device(device);
+    }
+
+    pub fn take_ir_device(&mut self) -> Option<Box<dyn IrDevice>> {
+        self.infrared.take_device()
+    }
+
+    // Cheats aren't part of save states either.
+    pub fn cheats(&self) -> &Cheats {
+        &self.cheats
+    }
+
+    pub fn cheats_mut(&mut self) -> &mut Cheats {
+        &mut self.cheats
+    }
+
+    // Logs how the game uses each ROM byte from now on. It isn't part of save states.
+    pub fn set_code_data_log(&mut self, log: Option<CodeDataLog>) {
+        self.code_data_log = log;
+    }
+
+    pub fn code_data_log(&self) -> Option<&CodeDataLog> {
+        self.code_data_log.as_ref()
+    }
+
+    pub fn take_code_data_log(&mut self) -> Option<CodeDataLog> {
+        self.code_data_log.take()
+    }
+
+    // GameShark codes write their bytes as a frame starts, the way the real one does from the
+    // VBlank interrupt. The writes skip watchpoints and open bus, nothing on the bus saw them.
+    fn apply_ram_cheats(&mut self) {
+        for (bank, address, value) in self.cheats.ram_writes() {
+            match address {
+                0xA000..=0xBFFF => self.mbc.write_ram(address, value),
+                0xD000..=0xDFFF if self.cgb => {
+                    let bank = (bank & 0x07).max(1) as usize;
+                    self.wram[bank * WRAM_BANK_SIZE + (address & 0x0FFF) as usize] = value;
+                }
+                _ => {
+                    let offset = self.wram_offset(address);
+                    self.wram[offset] = value;
+                }
+            }
+        }
+    }
+
+    fn reset_serial_poll(&mut self) {
+        self.next_serial_poll = if self.serial.has_device() {
+            self.scheduler.now()
+        } else {
+            u64::MAX
+        };
+    }
+
+    fn poll_serial(&mut self) {
+        self.serial.poll_device();
+        self.interrupts.request(self.serial.take_interrupts());
+        self.next_serial_poll = self.scheduler.now() + SERIAL_POLL_CYCLES;
+    }
+
+    fn read_byte(&mut self, address: u16) -> u8 {
+        if let Some(value) = self.boot_rom_byte(address) {
+            return value;
+        }
+
+        let value = match address {
+            0x0000..=0x7FFF => {
+                let value = self.mbc.read_rom(address);
+                if self.cheats.is_empty() {
+                    value
+                } else {
+                    self.cheats.patch_rom(address, value)
+                }
+            }
+            0x8000..=0x9FFF => self.read_ppu(address),
+            0xA000..=0xBFFF => self.read_cartridge_ram(address),
+            0xC000..=0xDFFF => self.wram[self.wram_offset(address)],
+            // Echo RAM at 0xE000-0xFDFF mirrors 0xC000-0xDDFF.
+            0xE000..=0xFDFF => {
+                self.record_violation(ViolationKind::EchoRam, address, false);
+                self.wram[self.wram_offset(address)]
+            }
+            0xFE00..=0xFE9F => self.read_ppu(address),
+            0xFEA0..=0xFEFF => self.read_unusable(address),
+            0xFF00 => match &self.sgb {
+                Some(sgb) => sgb.read_joypad(self.joypad.read()),
+                None => self.joypad.read(),
+            },
+            0xFF01 | 0xFF02 => self.serial.read(address),
+            0xFF04..=0xFF07 => self.read_timer(address),
+            0xFF0F => self.interrupts.read(address),
+            0xFF10..=0xFF3F => self.read_apu(address),
+            0xFF40..=0xFF45 | 0xFF47..=0xFF4B => self.read_ppu(address),
+            0xFF50 => 0xFF,
+            0xFF4D if self.cgb => {
+                0x7E | ((self.double_speed as u8) << 7) | self.speed_switch_armed as u8
+            }
+            0xFF4F | 0xFF68..=0xFF6C if self.cgb => self.read_ppu(address),
+            0xFF51..=0xFF54 if self.cgb => 0xFF,
+            0xFF55 if self.cgb => self.read_hdma(),
+            0xFF56 if self.cgb => self.infrared.read(self.scheduler.now()),
+            0xFF70 if self.cgb => 0xF8 | self.wram_bank,
+            0xFF01..=0xFF7F => self.io[(address - 0xFF00) as usize],
+            0xFF80..=0xFFFE => self.hram[(address - 0xFF80) as usize],
+            _ => self.interrupts.read(address),
+        };
+        if is_external(address) {
+            self.open_bus = value;
+        }
+        value
+    }
+
+    // Reads of the boot ROM aren't the game's.
+    fn log_rom_access(&mut self, address: u16, value: u8, code: bool) {
+        if address >= 0x8000 || self.boot_rom_byte(address).is_some() {
+            return;
+        }
+        let offset = self.mbc.rom_offset(address);
+        if let Some(log) = &mut self.code_data_log {
+            if code {
+                log.mark(offset, cdl::CODE);
+            } else {
+                log.mark_data(offset, value);
+            }
+        }
+    }
+
+    fn check_watchpoints(&mut self, kind: AccessKind, address: u16, value: u8) {
+        if self.watch_hits.len() < MAX_WATCH_HITS
+            && self.watchpoints.iter().any(|w| w.matches(address, kind))
+        {
+            self.watch_hits.push(WatchHit {
+                kind,
+                address,
+                value,
+                pc: self.instruction_pc,
+            });
+        }
+    }
+
+    fn record_violation(&mut self, kind: ViolationKind, address: u16, write: bool) {
+        // Nobody may be collecting these, so stop at a limit rather than growing forever.
+        if self.memory_strictness == MemoryStrictness::Report
+            && self.violations.len() < MAX_VIOLATIONS
+        {
+            self.violations.push(MemoryViolation {
+                kind,
+                address,
+                write,
+            });
+        }
+    }
+
+    fn read_cartridge_ram(&mut self, address: u16) -> u8 {
+        // The Pocket Camera is polled here until its capture finishes.
+        self.sync_rtc();
+        if self.mbc.ram_readable(address) {
+            return self.mbc.read_ram(address);
+        }
+
+        self.record_violation(ViolationKind::UnmappedCartridgeRam, address, false);
+        self.open_bus
+    }
+
+    fn write_cartridge_ram(&mut self, address: u16, value: u8) {
+        self.sync_rtc();
+        if !self.mbc.ram_readable(address) {
+            self.record_violation(ViolationKind::UnmappedCartridgeRam, address, true);
+        }
+        self.mbc.write_ram(address, value);
+    }
+
+    fn tick_machine_cycle(&mut self) {
+        self.scheduler.advance(4);
+        while let Some(event) = self.scheduler.pop_due() {
+            self.handle_event(event);
+        }
+        self.step_dma();
+        if self.scheduler.now() >= self.next_serial_poll {
+            self.poll_serial();
+        }
+    }
+
+    // See `Ppu::corrupt_oam`.
+    fn corrupt_oam(&mut self, address: u16, corruption: OamCorruption) {
+        if (0xFE00..=0xFEFF).contains(&address) {
+            self.sync_ppu();
+            self.ppu.corrupt_oam(corruption);
+        }
+    }
+
+    // DMG returns 0 and CGB repeats the high nibble of the address, except while OAM is blocked,
+    // when both read 0xFF.
+    fn read_unusable(&mut self, address: u16) -> u8 {
+        self.record_violation(ViolationKind::Unusable, address, false);
+        self.sync_ppu();
+        if matches!(self.ppu.mode(), Mode::OamScan | Mode::Drawing) {
+            return 0xFF;
+        }
+
+        if self.cgb {
+            let nibble = (address as u8) & 0xF0;
+            nibble | (nibble >> 4)
+        } else {
+            0x00
+        }
+    }
+
+    // Both the C000 and the echo E000 ranges.
+    fn wram_offset(&self, address: u16) -> usize {
+        let offset = (address & 0x1FFF) as usize;
+        if offset < WRAM_BANK_SIZE {
+            offset
+        } else {
+            self.wram_bank as usize * WRAM_BANK_SIZE + offset - WRAM_BANK_SIZE
+        }
+    }
+
+    fn read_hdma(&self) -> u8 {
+        if self.hdma.active {
+            self.hdma.remaining
+        } else {
+            0x80 | self.hdma.remaining
+        }
+    }
+
+    fn write_hdma(&mut self, value: u8) {
+        // Writing with bit 7 clear during HBlank DMA stops it instead of starting a new transfer.
+        if self.hdma.active && value & 0x80 == 0 {
+            self.hdma.active = false;
+            return;
+        }
+
+        self.hdma.remaining = value & 0x7F;
+        if value & 0x80 != 0 {
+            self.hdma.active = true;
+            // With the LCD off there's no HBlank to wait for, so the first block goes right away.
+            if !self.ppu.lcd_enabled() {
+                self.copy_hdma_block();
+            }
+        } else {
+            for _ in 0..=self.hdma.remaining {
+                self.copy_hdma_block();
+            }
+        }
+    }
+
+    fn copy_hdma_block(&mut self) {
+        self.sync_ppu();
+        for _ in 0..HDMA_BLOCK_SIZE {
+            let value = self.read(self.hdma.source);
+            self.ppu.write_vram_dma(self.hdma.destination, value);
+            if let Some(log) = &mut self.code_data_log {
+                log.vram_written(0x8000 | self.hdma.destination, value);
+            }
+            self.hdma.source = self.hdma.source.wrapping_add(1);
+            self.hdma.destination = (self.hdma.destination + 1) & 0x1FFF;
+        }
+        let speed = if self.double_speed { 2 } else { 1 };
+        self.hdma.stall += HDMA_BLOCK_CYCLES * speed;
+
+        if self.hdma.remaining == 0 {
+            self.hdma.active = false;
+            self.hdma.remaining = 0x7F;
+        } else {
+            self.hdma.remaining -= 1;
+        }
+    }
+
+    pub fn set_rumble_callback(&mut self, callback: RumbleCallback) {
+        self.mbc.set_rumble_callback(callback);
+    }
+
+    // Tilts MBC7 cartridges. It isn't part of save states.
+    pub fn set_accelerometer(&mut self, accelerometer: Option<Box<dyn Accelerometer>>) {
+        self.mbc.set_accelerometer(accelerometer);
+    }
+
+    pub fn set_camera_source(&mut self, source: Option<Box<dyn CameraSource>>) {
+        self.mbc.set_camera_source(source);
+    }
+}
+
+impl Snapshot for Bus {
+    fn component(&self) -> &'static str {
+        "bus"
+    }
+
+    fn version(&self) -> u8 {
+        1
+    }
+
+    fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_u8(self.model.to_u8());
+        writer.write_bytes(&self.wram);
+        writer.write_bytes(&self.io);
+        writer.write_bytes(&self.hram);
+        writer.write_bool(self.dma.is_some());
+        if let Some(dma) = &self.dma {
+            writer.write_u16(dma.source);
+            writer.write_u16(dma.index);
+        }
+        writer.write_snapshot(&self.interrupts);
+        writer.write_snapshot(&self.timer);
+        writer.write_snapshot(&self.joypad);
+        writer.write_snapshot(&self.ppu);
+        writer.write_snapshot(&self.apu);
+        writer.write_snapshot(self.mbc.as_ref());
+        writer.write_u8(self.wram_bank);
+        writer.write_bool(self.double_speed);
+        writer.write_bool(self.speed_switch_armed);
+        // An empty array means the boot ROM is unmapped or was never used.
+        writer.write_bytes(self.boot_rom.as_deref().unwrap_or_default());
+        writer.write_u16(self.hdma.source);
+        writer.write_u16(self.hdma.destination);
+        writer.write_u8(self.hdma.remaining);
+        writer.write_bool(self.hdma.active);
+        writer.write_u32(self.hdma.stall);
+        writer.write_snapshot(&self.serial);
+        writer.write_snapshot(&self.infrared);
+        if let Some(sgb) = &self.sgb {
+            writer.write_snapshot(sgb);
+        }
+        writer.write_snapshot(&self.scheduler);
+        writer.write_u64(self.ppu_synced);
+        writer.write_u64(self.apu_synced);
+        writer.write_u64(self.timer_synced);
+        writer.write_u64(self.rtc_synced);
+        writer.write_u8(self.open_bus);
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader) -> Result<()> {
+        let model = EmulatorModel::from_u8(reader.read_u8()?)?;
+        if model != self.model {
+            return Err(Error::other(format!(
+                "The save state was made with the {} model, not {}.",
+                model, self.model
+            )));
+        }
+        reader.read_bytes_into(&mut self.wram)?;
+        reader.read_bytes_into(&mut self.io)?;
+        reader.read_bytes_into(&mut self.hram)?;
+        self.dma = if reader.read_bool()? {
+            Some(Dma {
+                source: reader.read_u16()?,
+                index: reader.read_u16()?,
+            })
+        } else {
+            None
+        };
+        reader.read_snapshot(&mut self.interrupts)?;
+        reader.read_snapshot(&mut self.timer)?;
+        reader.read_snapshot(&mut self.joypad)?;
+        reader.read_snapshot(&mut self.ppu)?;
+        reader.read_snapshot(&mut self.apu)?;
+        reader.read_snapshot(self.mbc.as_mut())?;
+        self.wram_bank = reader.read_u8()?.clamp(1, 7);
+        self.double_speed = reader.read_bool()?;
+        self.speed_switch_armed = reader.read_bool()?;
+        let boot_rom = reader.read_bytes()?;
+        self.boot_rom = (!boot_rom.is_empty()).then(|| boot_rom.to_vec());
+        self.hdma.source = reader.read_u16()?;
+        self.hdma.destination = reader.read_u16()?;
+        self.hdma.remaining = reader.read_u8()?;
+        self.hdma.active = reader.read_bool()?;
+        self.hdma.stall = reader.read_u32()? & !3;
+        reader.read_snapshot(&mut self.serial)?;
+        reader.read_snapshot(&mut self.infrared)?;
+        if let Some(sgb) = &mut self.sgb {
+            reader.read_snapshot(sgb)?;
+        }
+        reader.read_snapshot(&mut self.scheduler)?;
+        self.ppu_synced = reader.read_u64()?;
+        self.apu_synced = reader.read_u64()?;
+        self.timer_synced = reader.read_u64()?;
+        self.rtc_synced = reader.read_u64()?;
+        self.open_bus = reader.read_u8()?;
+        // The time just jumped, so the next poll would be off.
+        self.reset_serial_poll();
+        Ok(())
+    }
+}
+
+impl Memory for Bus {
+    fn read(&mut self, address: u16) -> u8 {
+        self.corrupt_oam(address, OamCorruption::Read);
+        let value = self.read_byte(address);
+        if !self.watchpoints.is_empty() {
+            self.check_watchpoints(AccessKind::Read, address, value);
+        }
+        if self.code_data_log.is_some() {
+            self.log_rom_access(address, value, false);
+        }
+        value
+    }
+
+    fn fetch(&mut self, address: u16) -> u8 {
+        let value = self.read_byte(address);
+        if self.code_data_log.is_some() {
+            self.log_rom_access(address, value, true);
+        }
+        value
+    }
+
+    fn instruction_start(&mut self, pc: u16) {
+        self.instruction_pc = pc;
+        if !self.watchpoints.is_empty() {
+            let opcode = self.peek(pc);
+            self.check_watchpoints(AccessKind::Execute, pc, opcode);
+        }
+    }
+
+    // Reads without touching open bus or reporting violations. Registers don't have read side
+    // effects, so this is otherwise the same as a CPU read.
+    fn peek(&mut self, address: u16) -> u8 {
+        let open_bus = self.open_bus;
+        let violations = self.violations.len();
+        let value = self.read_byte(address);
+        self.open_bus = open_bus;
+        self.violations.truncate(violations);
+        value
+    }
+
+    fn write(&mut self, address: u16, value: u8) {
+        if !self.watchpoints.is_empty() {
+            self.check_watchpoints(AccessKind::Write, address, value);
+        }
+        if is_external(address) {
+            self.open_bus = value;
+        }
+        self.corrupt_oam(address, OamCorruption::Write);
+
+        match address {
+            0x0000..=0x7FFF => {
+                // Latching the clock needs it up to date.
+                self.sync_rtc();
+                self.mbc.write_rom(address, value);
+            }
+            0x8000..=0x9FFF => {
+                if let Some(log) = &mut self.code_data_log {
+                    log.vram_written(address, value);
+                }
+                self.write_ppu(address, value);
+            }
+            0xA000..=0xBFFF => self.write_cartridge_ram(address, value),
+            0xC000..=0xFDFF => {
+                if address >= 0xE000 {
+                    self.record_violation(ViolationKind::EchoRam, address, true);
+                }
+                let offset = self.wram_offset(address);
+                self.wram[offset] = value;
+            }
+            0xFE00..=0xFE9F => self.write_ppu(address, value),
+            0xFEA0..=0xFEFF => self.record_violation(ViolationKind::Unusable, address, true),
+            0xFF00 => {
+                self.joypad.write(value);
+                if let Some(sgb) = &mut self.sgb {
+                    sgb.write_joypad(value);
+                }
+            }
+            0xFF01 | 0xFF02 => self.write_serial(address, value),
+            0xFF04..=0xFF07 => self.write_timer(address, value),
+            0xFF0F => self.interrupts.write(address, value),
+            0xFF10..=0xFF3F => self.write_apu(address, value),
+            0xFF40..=0xFF45 | 0xFF47..=0xFF4B => self.write_ppu(address, value),
+            0xFF4D if self.cgb => self.speed_switch_armed = value & 0x01 != 0,
+            // Unmapping the boot ROM can't be undone until the next power cycle.
+            0xFF50 => {
+                if value != 0 {
+                    self.boot_rom = None;
+                }
+            }
+            0xFF4F | 0xFF68..=0xFF6C if self.cgb => self.write_ppu(address, value),
+            0xFF51 if self.cgb => {
+                self.hdma.source = (self.hdma.source & 0x00FF) | ((value as u16) << 8)
+            }
+            0xFF52 if self.cgb => {
+                self.hdma.source = (self.hdma.source & 0xFF00) | (value & 0xF0) as u16
+            }
+            0xFF53 if self.cgb => {
+                self.hdma.destination =
+                    (self.hdma.destination & 0x00FF) | (((value & 0x1F) as u16) << 8)
+            }
+            0xFF54 if self.cgb => {
+                self.hdma.destination = (self.hdma.destination & 0x1F00) | (value & 0xF0) as u16
+            }
+            0xFF55 if self.cgb => self.write_hdma(value),
+            0xFF56 if self.cgb => self.infrared.write(value, self.scheduler.now()),
+            0xFF70 if self.cgb => self.wram_bank = (value & 0x07).max(1),
+            0xFF46 => {
+                self.io[0x46] = value;
+                self.dma = Some(Dma {
+                    source: (value as u16) << 8,
+                    index: 0,
+                });
+            }
+            0xFF01..=0xFF7F => self.io[(address - 0xFF00) as usize] = value,
+            0xFF80..=0xFFFE => self.hram[(address - 0xFF80) as usize] = value,
+            _ => self.interrupts.write(address, value),
+        }
+    }
+
+    // The CPU ticks whole machine cycles, which is the finest step events are checked at. Any wait
+    // for VRAM DMA comes first, so the access the cycle belongs to happens after it.
+    fn tick(&mut self, cycles: u32) {
+        while self.hdma.stall > 0 {
+            self.hdma.stall -= 4;
+            self.hdma.stalled += 4;
+            self.tick_machine_cycle();
+        }
+        for _ in 0..cycles / 4 {
+            self.tick_machine_cycle();
+        }
+        self.interrupts.request(self.joypad.take_interrupts());
+    }
+
+    fn take_stalled_cycles(&mut self) -> u32 {
+        std::mem::take(&mut self.hdma.stalled)
+    }
+
+    fn address_incremented(&mut self, address: u16) {
+        self.corrupt_oam(address, OamCorruption::Write);
+    }
+
+    fn read_incrementing(&mut self, address: u16) -> u8 {
+        self.corrupt_oam(address, OamCorruption::ReadIncrementing);
+        self.read(address)
+    }
+
+    fn pending_interrupts(&mut self) -> u8 {
+        self.interrupts.pending()
+    }
+
+    fn acknowledge_interrupt(&mut self, mask: u8) {
+        self.interrupts.acknowledge(mask);
+    }
+
+    // STOP resets DIV whether or not it switches speed.
+    fn switch_speed(&mut self) -> bool {
+        self.write_timer(0xFF04, 0);
+        if !self.speed_switch_armed {
+            return false;
+        }
+
+        // Everything has to catch up at the old speed before the PPU and APU clocks change.
+        self.sync();
+        self.speed_switch_armed = false;
+        self.double_speed = !self.double_speed;
+        self.schedule_ppu();
+        self.schedule_frame_sequencer();
+        true
+    }
+}
+
+const WRAM_BANK_SIZE: usize = 0x1000;
+
+const HDMA_BLOCK_SIZE: u16 = 0x10;
+
+// How long a block takes in normal speed.
+const HDMA_BLOCK_CYCLES: u32 = 32;
+
+const DMG_BOOT_ROM_SIZE: usize = 0x100;
+
+const CGB_BOOT_ROM_SIZE: usize = 0x900;
+
+// The sound trigger bits are left out so the cartridge doesn't start with a beep.
+const POST_BOOT_REGISTERS: &[(u16, u8)] = &[
+    (0xFF00, 0xCF),
+    (0xFF05, 0x00),
+    (0xFF06, 0x00),
+    (0xFF07, 0xF8),
+    (0xFF26, 0xF1),
+    (0xFF10, 0x80),
+    (0xFF11, 0xBF),
+    (0xFF12, 0xF3),
+    (0xFF13, 0xFF),
+    (0xFF14, 0x3F),
+    (0xFF16, 0x3F),
+    (0xFF17, 0x00),
+    (0xFF18, 0xFF),
+    (0xFF19, 0x3F),
+    (0xFF1A, 0x7F),
+    (0xFF1B, 0xFF),
+    (0xFF1C, 0x9F),
+    (0xFF1D, 0xFF),
+    (0xFF1E, 0x3F),
+    (0xFF20, 0xFF),
+    (0xFF21, 0x00),
+    (0xFF22, 0x00),
+    (0xFF23, 0x3F),
+    (0xFF24, 0x77),
+    (0xFF25, 0xF3),
+    (0xFF40, 0x91),
+    (0xFF41, 0x85),
+    (0xFF42, 0x00),
+    (0xFF43, 0x00),
+    (0xFF45, 0x00),
+    (0xFF47, 0xFC),
+    (0xFF48, 0xFF),
+    (0xFF49, 0xFF),
+    (0xFF4A, 0x00),
+    (0xFF4B, 0x00),
+    (0xFF0F, 0xE1),
+    (0xFFFF, 0x00),
+];
+
+const OAM_SIZE: usize = 0xA0;
+
+const MAX_VIOLATIONS: usize = 1024;
+
+const MAX_WATCH_HITS: usize = 1024;
+
+// About a scanline, often enough that the other end of a link cable barely waits.
+const SERIAL_POLL_CYCLES: u64 = 456;
+
+const LOGO_ADDRESS: u16 = 0x104;
+
+const LOGO_SIZE: usize = 48;
+
+const REGISTERED_TILE: [u8; 8] = [0x3C, 0x42, 0xB9, 0xA5, 0xB9, 0xA5, 0x42, 0x3C];
+
+// Work RAM powers up with whatever the cells settle on, which differs between units. These are
+// stand-ins that stay the same between runs: noise on the monochrome models, and on CGB the runs
+// of 0x00 and 0xFF its RAM tends to start with.
+fn initial_wram(model: EmulatorModel, size: usize) -> Vec<u8> {
+    if model.is_cgb() {
+        return (0..size)
+            .map(|i| if (i / 8) % 2 == 0 { 0x00 } else { 0xFF })
+            .collect();
+    }
+
+    let mut state: u32 = 0x2545_F491;
+    (0..size)
+        .map(|_| {
+            state ^= state << 13;
+            state ^= state >> 17;
+            state ^= state << 5;
+            state as u8
+        })
+        .collect()
+}
+
+// Turns the 4 bits of a logo row into 8 pixels, each bit twice.
+fn double_bits(nibble: u8) -> u8 {
+    (0..4).fold(0, |row, bit| {
+        let set = (nibble >> bit) & 0x01;
+        row | (set << (bit * 2)) | (set << (bit * 2 + 1))
+    })
+}
+
+// The cartridge and work RAM share the external bus. VRAM has its own and everything from 0xFE00
+// up is inside the CPU.
+fn is_external(address: u16) -> bool {
+    !(0x8000..=0x9FFF).contains(&address) && address < 0xFE00
+}
+
+const IO_SIZE: usize = 0x80;
+
+const HRAM_SIZE: usize = 0x7F;
+
+#[cfg(test)]
+mod tests {
+    use rstest::rstest;
+
+    use super::*;
+    use crate::accuracy::Accuracy;
+    use crate::cheats::Cheat;
+    use crate::cpu::Cpu;
+    use crate::infrared::IrLoopback;
+    use crate::joypad::Button;
+    use crate::ppu::Mode;
+
+    fn cgb_bus() -> Bus {
+        let mut content = vec![0; 0x8000];
+        content[0x143] = 0xC0;
+        Bus::new(Rom::from_content(content)).unwrap()
+    }
+
+    fn bus_with_rom(size: usize, ram_size_byte: u8) -> Bus {
+        let mut content: Vec<u8> = (0..size).map(|i| (i % 0xFF) as u8).collect();
+        content[0x147] = 0x08;
+        content[0x149] = ram_size_byte;
+        Bus::new(Rom::from_content(content)).unwrap()
+    }
+
+    #[rstest]
+    #[case(0x0000)]
+    #[case(0x3FFF)]
+    #[case(0x4000)]
+    #[case(0x7FFF)]
+    fn test_read_rom(#[case] address: u16) {
+        // Arrange
+        let mut bus = bus_with_rom(0x8000, 0x00);
+
+        // Act + Assert
+        assert_eq!(bus.read(address), (address as usize % 0xFF) as u8);
+    }
+
+    #[test]
+    fn test_write_rom_is_ignored() {
+        let mut bus = bus_with_rom(0x8000, 0x00);
+        let before = bus.read(0x2000);
+
+        bus.write(0x2000, before.wrapping_add(1));
+
+        assert_eq!(bus.read(0x2000), before);
+    }
+
+    #[test]
+    fn test_read_past_end_of_rom() {
+        let mut bus = bus_with_rom(0x4000, 0x00);
+        assert_eq!(bus.read(0x4000), 0xFF);
+    }
+
+    #[rstest]
+    #[case(0xC000)]
+    #[case(0xDFFF)]
+    #[case(0xFF01)]
+    #[case(0xFF7F)]
+    #[case(0xFF80)]
+    #[case(0xFFFE)]
+    #[case(0xFFFF)]
+    fn test_read_write_ram_regions(#[case] address: u16) {
+        // Arrange
+        let mut bus = bus_with_rom(0x8000, 0x00);
+
+        // Act
+        bus.write(address, 0x5A);
+
+        // Assert
+        assert_eq!(bus.read(address), 0x5A);
+    }
+
+    #[test]
+    fn test_external_ram() {
+        let mut bus = bus_with_rom(0x8000, 0x02);
+
+        bus.write(0xA000, 0x12);
+        bus.write(0xBFFF, 0x34);
+
+        assert_eq!(bus.read(0xA000), 0x12);
+        assert_eq!(bus.read(0xBFFF), 0x34);
+    }
+
+    #[test]
+    fn test_missing_external_ram_reads_open_bus() {
+        let mut bus = bus_with_rom(0x8000, 0x00);
+
+        let rom_byte = bus.read(0x0123);
+
+        assert_eq!(bus.read(0xA000), rom_byte);
+        bus.write(0xA000, 0x12);
+        assert_eq!(bus.read(0xA000), 0x12);
+    }
+
+    #[test]
+    fn test_open_bus_keeps_last_operand() {
+        // Arrange
+        let mut content = vec![0; 0x8000];
+        // LD A, (0xA000)
+        content[0x100..0x103].copy_from_slice(&[0xFA, 0x00, 0xA0]);
+        let mut bus = Bus::new(Rom::from_content(content)).unwrap();
+        let mut cpu = Cpu::new();
+
+        // Act
+        cpu.step(&mut bus);
+
+        // Assert
+        assert_eq!(cpu.registers.a, 0xA0);
+    }
+
+    #[rstest]
+    #[case(Accuracy::Normal, false)]
+    #[case(Accuracy::High, true)]
+    fn test_inc_corrupts_oam_during_scan(#[case] accuracy: Accuracy, #[case] corrupted: bool) {
+        // Arrange
+        let mut content = vec![0; 0x8000];
+        // LD HL, 0xFE10; INC HL
+        content[0x100..0x104].copy_from_slice(&[0x21, 0x10, 0xFE, 0x23]);
+        let mut bus = Bus::new(Rom::from_content(content)).unwrap();
+        bus.ppu_mut().set_accuracy(accuracy);
+        for index in 0..0xA0 {
+            bus.ppu_mut().write_oam_dma(index, index as u8);
+        }
+        let before = bus.ppu().oam().to_vec();
+        bus.write(0xFF40, 0x00);
+        bus.write(0xFF40, 0x80);
+        let mut cpu = Cpu::new();
+
+        // Act
+        cpu.step(&mut bus);
+        cpu.step(&mut bus);
+
+        // Assert
+        assert_eq!(bus.ppu().oam() != before, corrupted);
+    }
+
+    #[test]
+    fn test_echo_ram_mirrors_wram() {
+        let mut bus = bus_with_rom(0x8000, 0x00);
+
+        bus.write(0xC123, 0x77);
+        bus.write(0xFDFF, 0x88);
+
+        assert_eq!(bus.read(0xE123), 0x77);
+        assert_eq!(bus.read(0xDDFF), 0x88);
+    }
+
+    #[rstest]
+    #[case(false, 0xFEA0, 0x00)]
+    #[case(false, 0xFEFF, 0x00)]
+    #[case(true, 0xFEA0, 0xAA)]
+    #[case(true, 0xFEB7, 0xBB)]
+    #[case(true, 0xFEFF, 0xFF)]
+    fn test_unusable_region(#[case] cgb: bool, #[case] address: u16, #[case] expected: u8) {
+        let mut bus = if cgb {
+            cgb_bus()
+        } else {
+            bus_with_rom(0x8000, 0x00)
+        };
+        // With the LCD off OAM is never blocked.
+        bus.write(0xFF40, 0x00);
+
+        bus.write(address, 0x12);
+
+        assert_eq!(bus.read(address), expected);
+    }
+
+    #[test]
+    fn test_unusable_region_while_oam_blocked() {
+        let mut bus = cgb_bus();
+        while bus.ppu().mode() != Mode::OamScan {
+            bus.tick(4);
+            bus.sync();
+        }
+
+        assert_eq!(bus.read(0xFEA0), 0xFF);
+    }
+
+    #[test]
+    fn test_memory_violations_reported() {
+        // Arrange
+        let mut bus = bus_with_rom(0x8000, 0x00);
+        bus.read(0xE000);
+        bus.set_memory_strictness(MemoryStrictness::Report);
+
+        // Act
+        bus.write(0xE123, 0x01);
+        bus.read(0xFEA0);
+        bus.read(0xA000);
+        bus.read(0xC000);
+
+        // Assert
+        let violation = |kind, address, write| MemoryViolation {
+            kind,
+            address,
+            write,
+        };
+        assert_eq!(
+            bus.take_memory_violations(),
+            vec![
+                violation(ViolationKind::EchoRam, 0xE123, true),
+                violation(ViolationKind::Unusable, 0xFEA0, false),
+                violation(ViolationKind::UnmappedCartridgeRam, 0xA000, false),
+            ]
+        );
+        assert!(bus.take_memory_violations().is_empty());
+    }
+
+    #[test]
+    fn test_monochrome_models_keep_boot_logo() {
+        // Arrange
+        let mut content = vec![0; 0x8000];
+        content[0x104] = 0xCE;
+
+        // Act
+        let bus = Bus::new(Rom::from_content(content)).unwrap();
+
+        // Assert
+        assert_eq!(bus.ppu().read(0x8010), 0xF0);
+        assert_eq!(bus.ppu().read(0x8012), 0xF0);
+        assert_eq!(bus.ppu().read(0x8014), 0xFC);
+        assert_eq!(bus.ppu().read(0x9904), 0x01);
+        assert_eq!(bus.ppu().read(0x9910), 0x19);
+        assert_eq!(cgb_bus().ppu().read(0x9904), 0x00);
+    }
+
+    #[test]
+    fn test_cgb_runs_dmg_cartridge_in_compatibility_mode() {
+        let bus = Bus::with_model(Rom::from_content(vec![0; 0x8000]), EmulatorModel::Cgb).unwrap();
+
+        assert!(!bus.cgb());
+        assert_eq!(bus.model(), EmulatorModel::Cgb);
+    }
+
+    #[test]
+    fn test_watchpoints() {
+        // Arrange
+        let mut content = vec![0; 0x8000];
+        // LD A,(0xC000); LD (0xC001),A; NOP
+        content[0x0100..0x0107].copy_from_slice(&[0xFA, 0x00, 0xC0, 0xEA, 0x01, 0xC0, 0x00]);
+        let mut bus = Bus::new(Rom::from_content(content)).unwrap();
+        bus.add_watchpoint(Watchpoint {
+            start: 0xC000,
+            end: 0xC0FF,
+            read: true,
+            write: true,
+            execute: false,
+        });
+        bus.add_watchpoint(Watchpoint {
+            start: 0x0106,
+            end: 0x0106,
+            read: true,
+            write: false,
+            execute: true,
+        });
+        bus.write(0xC000, 0x42);
+        bus.take_watch_hits();
+        let mut cpu = Cpu::new();
+
+        // Act
+        for _ in 0..3 {
+            cpu.step(&mut bus);
+        }
+
+        // Assert
+        assert_eq!(
+            bus.take_watch_hits(),
+            [
+                WatchHit {
+                    kind: AccessKind::Read,
+                    address: 0xC000,
+                    value: 0x42,
+                    pc: 0x0100
+                },
+                WatchHit {
+                    kind: AccessKind::Write,
+                    address: 0xC001,
+                    value: 0x42,
+                    pc: 0x0103
+                },
+                WatchHit {
+                    kind: AccessKind::Execute,
+                    address: 0x0106,
+                    value: 0x00,
+                    pc: 0x0106
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_remove_watchpoint() {
+        let mut bus = bus_with_rom(0x8000, 0x00);
+        let watchpoint = Watchpoint {
+            start: 0xC000,
+            end: 0xC000,
+            read: false,
+            write: true,
+            execute: false,
+        };
+        bus.add_watchpoint(watchpoint);
+
+        assert!(bus.remove_watchpoint(&watchpoint));
+        bus.write(0xC000, 0x01);
+
+        assert!(bus.take_watch_hits().is_empty());
+        assert!(!bus.remove_watchpoint(&watchpoint));
+    }
+
+    #[test]
+    fn test_cpu_executes_through_bus() {
+        // Arrange
+        let mut content = vec![0; 0x8000];
+        // LD A, 0x42; LD (0xC000), A
+        content[0x100..0x105].copy_from_slice(&[0x3E, 0x42, 0xEA, 0x00, 0xC0]);
+        let mut bus = Bus::new(Rom::from_content(content)).unwrap();
+        let mut cpu = Cpu::new();
+
+        // Act
+        cpu.step(&mut bus);
+        cpu.step(&mut bus);
+
+        // Assert
+        assert_eq!(bus.read(0xC000), 0x42);
+    }
+
+    #[test]
+    fn test_ppu_registers_are_routed() {
+        let mut bus = bus_with_rom(0x8000, 0x00);
+
+        bus.write(0xFF42, 0x12);
+        bus.write(0xFF44, 0x34);
+
+        assert_eq!(bus.read(0xFF42), 0x12);
+        assert_eq!(bus.read(0xFF44), 0x00);
+    }
+
+    #[test]
+    fn test_apu_registers_are_routed() {
+        let mut bus = bus_with_rom(0x8000, 0x00);
+
+        bus.write(0xFF24, 0x35);
+        bus.write(0xFF3F, 0x9A);
+
+        assert_eq!(bus.read(0xFF24), 0x35);
+        assert_eq!(bus.read(0xFF3F), 0x9A);
+        assert_eq!(bus.read(0xFF26), 0xF0);
+    }
+
+    #[test]
+    fn test_vblank_interrupt_is_requested() {
+        let mut bus = bus_with_rom(0x8000, 0x00);
+
+        bus.tick(456 * 144);
+
+        assert_eq!(bus.read(0xFF0F), 0xE1);
+    }
+
+    #[test]
+    fn test_game_genie_patches_rom_reads() {
+        // Arrange
+        let mut bus = bus_with_rom(0x8000, 0x00);
+        let original = bus.read(0x0AF2);
+
+        // Act
+        bus.cheats_mut().add(Cheat::new("3EA-F2F").unwrap());
+
+        // Assert
+        assert_eq!(bus.read(0x0AF2), 0x3E);
+        assert_eq!(bus.peek(0x0AF2), 0x3E);
+        bus.cheats_mut().set_active(false);
+        assert_eq!(bus.read(0x0AF2), original);
+    }
+
+    #[test]
+    fn test_gameshark_writes_ram_each_frame() {
+        // Arrange
+        let mut bus = cgb_bus();
+        bus.cheats_mut().add(Cheat::new("01FF34C1").unwrap());
+        bus.cheats_mut().add(Cheat::new("0363FFD0").unwrap());
+        bus.write(0xC134, 0x00);
+        bus.write(0xD0FF, 0x00);
+
+        // Act
+        bus.tick(456 * 144);
+
+        // Assert
+        assert_eq!(bus.read(0xC134), 0xFF);
+        // Bank 3, not the one that's mapped.
+        assert_eq!(bus.read(0xD0FF), 0x00);
+        bus.write(0xFF70, 0x03);
+        assert_eq!(bus.read(0xD0FF), 0x63);
+    }
+
+    #[test]
+    fn test_timer_interrupt_is_requested() {
+        let mut bus = bus_with_rom(0x8000, 0x00);
+        bus.write(0xFF05, 0xFF);
+        bus.write(0xFF07, 0x05);
+
+        bus.tick(32);
+
+        assert_eq!(bus.read(0xFF0F) & 0x04, 0x04);
+    }
+
+    #[test]
+    fn test_serial_transfer_completes() {
+        // Arrange
+        let mut bus = bus_with_rom(0x8000, 0x00);
+        bus.write(0xFF0F, 0x00);
+        bus.write(0xFF01, 0x42);
+        bus.write(0xFF02, 0x81);
+
+        // Act
+        bus.tick(4092);
+        let early = bus.read(0xFF0F) & 0x08;
+        bus.tick(4);
+
+        // Assert
+        assert_eq!(early, 0);
+        assert_eq!(bus.read(0xFF0F) & 0x08, 0x08);
+        assert_eq!(bus.read(0xFF01), 0xFF);
+        assert_eq!(bus.read(0xFF02), 0x7F);
+    }
+
+    // Clocks a transfer with 0x29 the first time it's polled while the Game Boy waits.
+    struct ExternalClock;
+
+    impl SerialDevice for ExternalClock {
+        fn transfer(&mut self, _byte: u8) -> u8 {
+            0xFF
+        }
+
+        fn clock(&mut self, byte: Option<u8>) -> Option<u8> {
+            byte.map(|_| 0x29)
+        }
+    }
+
+    #[test]
+    fn test_serial_device_clocks_transfer() {
+        // Arrange
+        let mut bus = bus_with_rom(0x8000, 0x00);
+        bus.set_serial_device(Some(Box::new(ExternalClock)));
+        bus.write(0xFF0F, 0x00);
+        bus.write(0xFF01, 0x42);
+        bus.write(0xFF02, 0x80);
+
+        // Act
+        bus.tick(SERIAL_POLL_CYCLES as u32);
+
+        // Assert
+        assert_eq!(bus.read(0xFF0F) & 0x08, 0x08);
+        assert_eq!(bus.read(0xFF01), 0x29);
+        assert_eq!(bus.read(0xFF02), 0x7E);
+    }
+
+    #[test]
+    fn test_frame_sequencer_follows_div() {
+        let mut bus = bus_with_rom(0x8000, 0x00);
+        bus.write(0xFF11, 0x3F);
+        bus.write(0xFF12, 0xF0);
+        bus.write(0xFF14, 0xC0);
+
+        bus.tick(8192);
+
+        assert_eq!(bus.read(0xFF26) & 0x01, 0);
+    }
+
+    #[test]
+    fn test_div_reset_clocks_frame_sequencer() {
+        // Arrange
+        let mut bus = bus_with_rom(0x8000, 0x00);
+        bus.write(0xFF04, 0x00);
+        bus.tick(4096);
+        bus.write(0xFF11, 0x3F);
+        bus.write(0xFF12, 0xF0);
+        bus.write(0xFF14, 0xC0);
+
+        // Act
+        bus.write(0xFF04, 0x00);
+
+        // Assert
+        assert_eq!(bus.read(0xFF26) & 0x01, 0);
+    }
+
+    #[test]
+    fn test_ppu_catches_up_on_register_read() {
+        let mut bus = bus_with_rom(0x8000, 0x00);
+        bus.write(0xFF40, 0x00);
+        bus.write(0xFF40, 0x80);
+
+        bus.tick(80);
+
+        assert_eq!(bus.read(0xFF41) & 0x03, 3);
+        assert_eq!(bus.ppu().mode(), Mode::Drawing);
+    }
+
+    #[test]
+    fn test_joypad_is_routed() {
+        let mut bus = bus_with_rom(0x8000, 0x00);
+        bus.write(0xFF00, 0x10);
+
+        bus.joypad_mut().set_button(Button::Start, true);
+        bus.tick(4);
+
+        assert_eq!(bus.read(0xFF00), 0xD7);
+        assert_eq!(bus.read(0xFF0F) & 0x10, 0x10);
+    }
+
+    #[test]
+    fn test_code_data_log() {
+        // Arrange
+        let mut content = vec![0; 0x8000];
+        // LD A,(0x0200); LD (0x8000),A; LD A,(0x0201); LD (0xC000),A
+        content[0x0100..0x010C].copy_from_slice(&[
+            0xFA, 0x00, 0x02, 0xEA, 0x00, 0x80, 0xFA, 0x01, 0x02, 0xEA, 0x00, 0xC0,
+        ]);
+        content[0x0200..0x0202].copy_from_slice(&[0x3C, 0x7E]);
+        let mut bus = Bus::new(Rom::from_content(content)).unwrap();
+        bus.set_code_data_log(Some(CodeDataLog::new(0x8000)));
+        let mut cpu = Cpu::new();
+
+        // Act
+        for _ in 0..4 {
+            cpu.step(&mut bus);
+        }
+
+        // Assert
+        let log = bus.take_code_data_log().unwrap();
+        assert_eq!(log.flags()[0x0100..0x010C], [cdl::CODE; 12]);
+        assert_eq!(
+            log.flags()[0x0200..0x0202],
+            [cdl::DATA | cdl::GRAPHICS, cdl::DATA]
+        );
+        assert_eq!(log.count(cdl::CODE | cdl::DATA), 14);
+    }
+
+    #[test]
+    fn test_oam_dma() {
+        // Arrange
+        let mut bus = bus_with_rom(0x8000, 0x00);
+        for i in 0..0xA0 {
+            bus.write(0xC100 + i, i as u8);
+        }
+        bus.write(0xFF40, 0x00);
+
+        // Act
+        bus.write(0xFF46, 0xC1);
+        bus.tick(4 * 0xA0);
+
+        // Assert
+        assert_eq!(bus.read(0xFE00), 0x00);
+        assert_eq!(bus.read(0xFE9F), 0x9F);
+        assert_eq!(bus.read(0xFF46), 0xC1);
+    }
+
+    #[test]
+    fn test_cgb_wram_banking() {
+        // Arrange
+        let mut bus = cgb_bus();
+        for bank in 1..8 {
+            bus.write(0xFF70, bank);
+            bus.write(0xD000, bank);
+        }
+
+        // Act
+        bus.write(0xFF70, 0x00);
+
+        // Assert
+        assert_eq!(bus.read(0xFF70), 0xF9);
+        assert_eq!(bus.read(0xD000), 1);
+        bus.write(0xFF70, 0x05);
+        assert_eq!(bus.read(0xD000), 5);
+        assert_eq!(bus.read(0xF000), 5);
+    }
+
+    #[test]
+    fn test_speed_switch() {
+        // Arrange
+        let mut content = vec![0; 0x8000];
+        content[0x143] = 0x80;
+        // LD A, 0x01; LDH (0x4D), A; STOP
+        content[0x100..0x106].copy_from_slice(&[0x3E, 0x01, 0xE0, 0x4D, 0x10, 0x00]);
+        let mut bus = Bus::new(Rom::from_content(content)).unwrap();
+        let mut cpu = Cpu::new_cgb();
+
+        // Act
+        let cycles: Vec<u32> = (0..3).map(|_| cpu.step(&mut bus)).collect();
+
+        // Assert
+        assert!(!cpu.stopped());
+        assert!(bus.double_speed());
+        assert_eq!(bus.read(0xFF4D), 0xFE);
+        assert_eq!(cycles[2], 8 + 2050 * 4);
+        // DIV went back to 0 at the STOP and counts 64 cycles a step in double speed too.
+        assert_eq!(bus.read(0xFF04), (2050 * 4 / 256) as u8);
+    }
+
+    #[test]
+    fn test_double_speed_slows_ppu_relative_to_cpu() {
+        let mut bus = cgb_bus();
+        bus.write(0xFF4D, 0x01);
+        bus.switch_speed();
+
+        bus.tick(456);
+
+        assert_eq!(bus.read(0xFF44), 0);
+        bus.tick(456);
+        assert_eq!(bus.read(0xFF44), 1);
+    }
+
+    #[test]
+    fn test_general_purpose_dma() {
+        // Arrange
+        let mut bus = cgb_bus();
+        for i in 0..0x20 {
+            bus.write(0xC000 + i, i as u8);
+        }
+        bus.write(0xFF40, 0x00);
+        bus.write(0xFF51, 0xC0);
+        bus.write(0xFF52, 0x00);
+        bus.write(0xFF53, 0x01);
+        bus.write(0xFF54, 0x00);
+
+        // Act
+        bus.write(0xFF55, 0x01);
+
+        // Assert
+        assert_eq!(bus.read(0x8100), 0x00);
+        assert_eq!(bus.read(0x811F), 0x1F);
+        assert_eq!(bus.read(0xFF55), 0xFF);
+    }
+
+    #[test]
+    fn test_hblank_dma() {
+        // Arrange
+        let mut bus = cgb_bus();
+        for i in 0..0x20 {
+            bus.write(0xC000 + i, 0x80 + i as u8);
+        }
+        bus.write(0xFF51, 0xC0);
+        bus.write(0xFF52, 0x00);
+        bus.write(0xFF53, 0x00);
+        bus.write(0xFF54, 0x00);
+
+        // Act + Assert
+        bus.write(0xFF55, 0x81);
+        assert_eq!(bus.read(0xFF55), 0x01);
+
+        bus.tick(456);
+        assert_eq!(bus.read(0xFF55), 0x00);
+
+        bus.tick(456);
+        assert_eq!(bus.read(0xFF55), 0xFF);
+        bus.write(0xFF40, 0x00);
+        assert_eq!(bus.read(0x8000), 0x80);
+        assert_eq!(bus.read(0x801F), 0x9F);
+    }
+
+    #[rstest]
+    #[case(false, 4 + 2 * 32)]
+    #[case(true, 4 + 2 * 64)]
+    fn test_general_purpose_dma_stalls_cpu(#[case] double_speed: bool, #[case] expected: u32) {
+        // Arrange
+        let mut content = vec![0; 0x8000];
+        content[0x143] = 0xC0;
+        // LD A, 0x01; LDH (0x55), A; NOP
+        content[0x100..0x105].copy_from_slice(&[0x3E, 0x01, 0xE0, 0x55, 0x00]);
+        let mut bus = Bus::new(Rom::from_content(content)).unwrap();
+        bus.double_speed = double_speed;
+        let mut cpu = Cpu::new();
+        cpu.step(&mut bus);
+        cpu.step(&mut bus);
+
+        // Act
+        let cycles = cpu.step(&mut bus);
+
+        // Assert
+        assert_eq!(cycles, expected);
+        assert_eq!(bus.read(0xFF55), 0xFF);
+    }
+
+    #[test]
+    fn test_hblank_dma_with_lcd_off() {
+        // Arrange
+        let mut bus = cgb_bus();
+        for i in 0..0x20 {
+            bus.write(0xC000 + i, 0x80 + i as u8);
+        }
+        bus.write(0xFF40, 0x00);
+        bus.write(0xFF51, 0xC0);
+        bus.write(0xFF52, 0x00);
+        bus.write(0xFF53, 0x00);
+        bus.write(0xFF54, 0x00);
+
+        // Act
+        bus.write(0xFF55, 0x81);
+
+        // Assert
+        assert_eq!(bus.read(0xFF55), 0x00);
+        assert_eq!(bus.read(0x800F), 0x8F);
+        assert_eq!(bus.read(0x8010), 0x00);
+    }
+
+    #[test]
+    fn test_infrared_only_on_cgb() {
+        let mut cgb = cgb_bus();
+        let mut dmg = bus_with_rom(0x8000, 0x00);
+        cgb.set_ir_device(Some(Box::new(IrLoopback::default())));
+
+        cgb.write(0xFF56, 0xC1);
+        dmg.write(0xFF56, 0xC1);
+
+        assert_eq!(cgb.read(0xFF56), 0xFD);
+        assert_eq!(dmg.read(0xFF56), 0xC1);
+    }
+
+    #[rstest]
+    #[case(EmulatorModel::Sgb, 0xFE)]
+    #[case(EmulatorModel::Dmg, 0xFF)]
+    fn test_sgb_multiplayer(#[case] model: EmulatorModel, #[case] expected: u8) {
+        // Arrange
+        let mut content = vec![0; 0x8000];
+        content[0x146] = 0x03;
+        let mut bus = Bus::with_model(Rom::from_content(content), model).unwrap();
+        // MLT_REQ for two players, a bit at a time with a 0 to end the packet.
+        let packet = [0x89, 0x01];
+        bus.write(0xFF00, 0x00);
+        bus.write(0xFF00, 0x30);
+        for index in 0..129 {
+            let bit = packet
+                .get(index / 8)
+                .map_or(0, |byte| (byte >> (index % 8)) & 1);
+            bus.write(0xFF00, if bit != 0 { 0x10 } else { 0x20 });
+            bus.write(0xFF00, 0x30);
+        }
+
+        // Act
+        bus.write(0xFF00, 0x10);
+        bus.write(0xFF00, 0x30);
+
+        // Assert
+        assert_eq!(bus.sgb().is_some(), model == EmulatorModel::Sgb);
+        assert_eq!(bus.read(0xFF00), expected);
+    }
+
+    #[test]
+    fn test_cancel_hblank_dma() {
+        let mut bus = cgb_bus();
+        bus.write(0xFF55, 0x83);
+        bus.tick(456);
+
+        bus.write(0xFF55, 0x00);
+
+        assert_eq!(bus.read(0xFF55), 0x82);
+    }
+
+    #[test]
+    fn test_boot_rom_is_unmapped_by_ff50() {
+        // Arrange
+        let mut bus = bus_with_rom(0x8000, 0x00);
+        bus.map_boot_rom(vec![0xAA; DMG_BOOT_ROM_SIZE]).unwrap();
+        let before = (bus.read(0x0000), bus.read(0x00FF), bus.read(0x0100));
+
+        // Act
+        bus.write(0xFF50, 0x01);
+
+        // Assert
+        assert_eq!(before, (0xAA, 0xAA, (0x100 % 0xFF) as u8));
+        assert_eq!(bus.read(0x0000), 0x00);
+        assert!(!bus.boot_rom_mapped());
+    }
+
+    #[test]
+    fn test_cgb_boot_rom_skips_cartridge_header() {
+        let mut bus = bus_with_rom(0x8000, 0x00);
+
+        bus.map_boot_rom(vec![0xAA; CGB_BOOT_ROM_SIZE]).unwrap();
+
+        assert_eq!(bus.read(0x00FF), 0xAA);
+        assert_eq!(bus.read(0x0147), 0x08);
+        assert_eq!(bus.read(0x08FF), 0xAA);
+        assert_eq!(bus.read(0x0900), (0x900 % 0xFF) as u8);
+    }
+
+    #[test]
+    fn test_boot_rom_with_wrong_size() {
+        let mut bus = bus_with_rom(0x8000, 0x00);
+
+        assert!(bus.map_boot_rom(vec![0; 0x200]).is_err());
+        assert!(!bus.boot_rom_mapped());
+    }
+
+    #[rstest]
+    #[case(0xFF07, 0xF8)]
+    #[case(0xFF0F, 0xE1)]
+    #[case(0xFF24, 0x77)]
+    #[case(0xFF26, 0xF0)]
+    #[case(0xFF40, 0x91)]
+    #[case(0xFF47, 0xFC)]
+    fn test_post_boot_registers(#[case] address: u16, #[case] expected: u8) {
+        let mut bus = bus_with_rom(0x8000, 0x00);
+
+        assert_eq!(bus.read(address), expected);
+    }
+}
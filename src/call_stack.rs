@@ -0,0 +1,102 @@
+use std::fmt;
+
+use crate::cpu::Registers;
+use crate::emulator::Emulator;
+
+// A routine that's been called and hasn't returned yet.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct CallFrame {
+    // Where the routine starts.
+    pub target: u16,
+    // The ROM bank the routine is in, 0 outside 0x4000-0x7FFF.
+    pub bank: usize,
+    // The CALL or RST, or the instruction an interrupt came in before.
+    pub caller: u16,
+    pub return_address: u16,
+    // Where the return address is on the stack. The frame is gone once SP is above it.
+    pub sp: u16,
+    pub interrupt: bool,
+}
+
+impl fmt::Display for CallFrame {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.interrupt {
+            write!(f, "{:04X} interrupt at {:04X}", self.target, self.caller)
+        } else {
+            write!(f, "{:04X} from {:04X}", self.target, self.caller)
+        }
+    }
+}
+
+// The calls the game is in, worked out from each instruction as it runs. A call is an instruction
+// that pushes the address after it and jumps, and an interrupt is a step that pushes PC and lands
+// on a vector. Frames leave when SP moves above their return address, by RET or otherwise, so
+// routines that drop their return address by hand don't linger. It starts empty when tracking
+// starts partway through a game.
+#[derive(Clone, Debug, Default)]
+pub struct CallStack {
+    // Innermost last.
+    frames: Vec<CallFrame>,
+}
+
+impl CallStack {
+    pub fn new() -> CallStack {
+        CallStack::default()
+    }
+
+    pub fn frames(&self) -> &[CallFrame] {
+        &self.frames
+    }
+
+    pub fn depth(&self) -> usize {
+        self.frames.len()
+    }
+
+    // Catches up with the step that just ran, which started at `before` with `opcode` at PC.
+    // Returns the frame it entered, if any.
+    pub fn update(
+        &mut self,
+        emulator: &mut Emulator,
+        before: &Registers,
+        opcode: u8,
+    ) -> Option<CallFrame> {
+        let after = emulator.cpu().registers;
+        self.frames.retain(|frame| frame.sp >= after.sp);
+        if after.sp != before.sp.wrapping_sub(2) {
+            return None;
+        }
+
+        let pushed = u16::from_le_bytes([
+            emulator.peek(after.sp),
+            emulator.peek(after.sp.wrapping_add(1)),
+        ]);
+        let call_length = match opcode {
+            0xC4 | 0xCC | 0xCD | 0xD4 | 0xDC => Some(3),
+            _ if opcode & 0xC7 == 0xC7 => Some(1),
+            _ => None,
+        };
+        let call = call_length.is_some_and(|length| pushed == before.pc.wrapping_add(length));
+        let interrupt = !call && pushed == before.pc && INTERRUPT_VECTORS.contains(&after.pc);
+        if !call && !interrupt {
+            return None;
+        }
+        let bank = match after.pc {
+            0x4000..=0x7FFF => emulator.bus().mbc().rom_offset(after.pc) / ROM_BANK_SIZE,
+            _ => 0,
+        };
+        let frame = CallFrame {
+            target: after.pc,
+            bank,
+            caller: before.pc,
+            return_address: pushed,
+            sp: after.sp,
+            interrupt,
+        };
+        self.frames.push(frame);
+        Some(frame)
+    }
+}
+
+const INTERRUPT_VECTORS: [u16; 5] = [0x40, 0x48, 0x50, 0x58, 0x60];
+
+const ROM_BANK_SIZE: usize = 0x4000;
@@ -0,0 +1,220 @@
+#[cfg(feature = "png")]
+use std::fs::File;
+use std::io::{Error, Result};
+#[cfg(feature = "webcam")]
+use std::io::{ErrorKind, Read};
+#[cfg(feature = "png")]
+use std::path::Path;
+#[cfg(feature = "webcam")]
+use std::process::{Child, Command, Stdio};
+#[cfg(feature = "webcam")]
+use std::sync::{Arc, Mutex};
+#[cfg(feature = "webcam")]
+use std::thread;
+
+#[cfg(feature = "webcam")]
+use crate::video::FFMPEG;
+
+// What the Pocket Camera's sensor is pointed at. Each capture asks for a picture of
+// CAMERA_WIDTH x CAMERA_HEIGHT brightnesses, a byte each from 0 for black to 255 for white.
+pub trait CameraSource {
+    fn capture(&mut self) -> Vec<u8>;
+}
+
+// Bars from white to black over a gradient, the same every time. It's what the sensor sees
+// without anything else to look at.
+pub struct TestPattern;
+
+impl CameraSource for TestPattern {
+    fn capture(&mut self) -> Vec<u8> {
+        let mut image = Vec::with_capacity(CAMERA_WIDTH * CAMERA_HEIGHT);
+        for y in 0..CAMERA_HEIGHT {
+            for x in 0..CAMERA_WIDTH {
+                let brightness = if y < CAMERA_HEIGHT / 2 {
+                    255 - x / 16 * 255 / 7
+                } else {
+                    (x + y) * 255 / (CAMERA_WIDTH + CAMERA_HEIGHT - 2)
+                };
+                image.push(brightness as u8);
+            }
+        }
+        image
+    }
+}
+
+// A still picture, stretched to the sensor's size.
+pub struct CameraImage {
+    image: Vec<u8>,
+}
+
+impl CameraImage {
+    // Brightnesses of `width` by `height` pixels, a byte each.
+    pub fn new(pixels: &[u8], width: usize, height: usize) -> Result<CameraImage> {
+        if width == 0 || height == 0 || pixels.len() != width * height {
+            return Err(Error::other(format!(
+                "{} bytes is not a {}x{} image.",
+                pixels.len(),
+                width,
+                height
+            )));
+        }
+        let mut image = Vec::with_capacity(CAMERA_WIDTH * CAMERA_HEIGHT);
+        for y in 0..CAMERA_HEIGHT {
+            for x in 0..CAMERA_WIDTH {
+                let (source_x, source_y) = (x * width / CAMERA_WIDTH, y * height / CAMERA_HEIGHT);
+                image.push(pixels[source_y * width + source_x]);
+            }
+        }
+        Ok(CameraImage { image })
+    }
+
+    // Any PNG, in color or not.
+    #[cfg(feature = "png")]
+    pub fn load(path: &Path) -> Result<CameraImage> {
+        let load = || -> Result<CameraImage> {
+            let mut decoder = png::Decoder::new(File::open(path)?);
+            decoder.set_transformations(png::Transformations::normalize_to_color8());
+            let mut reader = decoder.read_info().map_err(Error::other)?;
+            let mut pixels = vec![0; reader.output_buffer_size()];
+            let info = reader.next_frame(&mut pixels).map_err(Error::other)?;
+            let channels = info.color_type.samples();
+            let brightness = |pixel: &[u8]| match pixel {
+                [gray] | [gray, _] => *gray,
+                [red, green, blue, ..] => {
+                    ((*red as u32 * 299 + *green as u32 * 587 + *blue as u32 * 114) / 1000) as u8
+                }
+                _ => 0,
+            };
+            let gray: Vec<u8> = pixels[..info.buffer_size()]
+                .chunks_exact(channels)
+                .map(brightness)
+                .collect();
+            CameraImage::new(&gray, info.width as usize, info.height as usize)
+        };
+        load().map_err(|error| Error::other(format!("{}: {}", path.display(), error)))
+    }
+}
+
+impl CameraSource for CameraImage {
+    fn capture(&mut self) -> Vec<u8> {
+        self.image.clone()
+    }
+}
+
+// A webcam read through ffmpeg, which has to be on the PATH. Frames are read as they come, and a
+// capture gets the latest one.
+#[cfg(feature = "webcam")]
+pub struct Webcam {
+    ffmpeg: Child,
+    frame: Arc<Mutex<Vec<u8>>>,
+}
+
+#[cfg(feature = "webcam")]
+impl Webcam {
+    // The platform's default camera, or `device` in ffmpeg's terms: a path like /dev/video1 on
+    // Linux, an index on macOS and a name on Windows.
+    pub fn open(device: Option<&str>) -> Result<Webcam> {
+        let (format, default) = if cfg!(target_os = "linux") {
+            ("v4l2", "/dev/video0")
+        } else if cfg!(target_os = "macos") {
+            ("avfoundation", "0")
+        } else {
+            ("dshow", "video=Integrated Camera")
+        };
+        let mut ffmpeg = Command::new(FFMPEG)
+            .args([
+                "-loglevel",
+                "error",
+                "-f",
+                format,
+                "-i",
+                device.unwrap_or(default),
+            ])
+            .args(["-vf", &format!("scale={}:{}", CAMERA_WIDTH, CAMERA_HEIGHT)])
+            .args(["-pix_fmt", "gray", "-f", "rawvideo", "-"])
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .spawn()
+            .map_err(|error| {
+                if error.kind() == ErrorKind::NotFound {
+                    Error::new(
+                        error.kind(),
+                        format!("A webcam needs {}, which isn't on the PATH.", FFMPEG),
+                    )
+                } else {
+                    Error::new(error.kind(), format!("Could not run {}: {}", FFMPEG, error))
+                }
+            })?;
+
+        let mut output = ffmpeg.stdout.take().expect("stdout is piped");
+        let frame = Arc::new(Mutex::new(vec![0x80; CAMERA_WIDTH * CAMERA_HEIGHT]));
+        let latest = frame.clone();
+        thread::spawn(move || {
+            let mut buffer = vec![0; CAMERA_WIDTH * CAMERA_HEIGHT];
+            while output.read_exact(&mut buffer).is_ok() {
+                latest
+                    .lock()
+                    .unwrap_or_else(|poisoned| poisoned.into_inner())
+                    .copy_from_slice(&buffer);
+            }
+        });
+        Ok(Webcam { ffmpeg, frame })
+    }
+}
+
+#[cfg(feature = "webcam")]
+impl CameraSource for Webcam {
+    fn capture(&mut self) -> Vec<u8> {
+        self.frame
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .clone()
+    }
+}
+
+#[cfg(feature = "webcam")]
+impl Drop for Webcam {
+    fn drop(&mut self) {
+        let _ = self.ffmpeg.kill();
+        let _ = self.ffmpeg.wait();
+    }
+}
+
+pub const CAMERA_WIDTH: usize = 128;
+
+pub const CAMERA_HEIGHT: usize = 112;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pattern_size() {
+        let image = TestPattern.capture();
+
+        assert_eq!(image.len(), CAMERA_WIDTH * CAMERA_HEIGHT);
+        assert_eq!(image[0], 255);
+        assert_eq!(image[CAMERA_WIDTH - 1], 0);
+    }
+
+    #[test]
+    fn test_image_is_stretched() {
+        // Arrange
+        // Left half black, right half white.
+        let pixels = [0x00, 0xFF, 0x00, 0xFF];
+
+        // Act
+        let image = CameraImage::new(&pixels, 2, 2).unwrap().capture();
+
+        // Assert
+        assert_eq!(image.len(), CAMERA_WIDTH * CAMERA_HEIGHT);
+        assert_eq!(image[CAMERA_WIDTH / 2 - 1], 0x00);
+        assert_eq!(image[CAMERA_WIDTH / 2], 0xFF);
+        assert_eq!(image[CAMERA_WIDTH * CAMERA_HEIGHT - 1], 0xFF);
+    }
+
+    #[test]
+    fn test_image_size_mismatch() {
+        assert!(CameraImage::new(&[0; 3], 2, 2).is_err());
+    }
+}
@@ -0,0 +1,209 @@
+// A C API for embedding the emulator in C, C++ or anything with a C FFI, like Python's ctypes.
+// Build with `cargo build --release --features capi` and link against the cdylib, with
+// include/rustygameboy.h for the declarations. The header is regenerated on every capi build.
+//
+// Functions taking a `GbEmulator` pointer expect one from `gb_create` that hasn't been passed to
+// `gb_destroy`. Handles aren't thread safe, use each from one thread at a time.
+#![allow(clippy::missing_safety_doc)]
+
+use std::ffi::{c_char, c_int, CString};
+use std::ptr;
+use std::slice;
+
+use crate::emulator::Emulator;
+use crate::joypad::Button;
+use crate::rom::{Rom, ValidationPolicy};
+
+// An emulator handle, opaque to C.
+pub struct GbEmulator {
+    emulator: Option<Emulator>,
+    last_error: Option<CString>,
+}
+
+impl GbEmulator {
+    fn fail(&mut self, error: impl ToString) -> c_int {
+        // The message can't contain a NUL, but be safe rather than panic across the FFI.
+        let message = error.to_string().replace('\0', " ");
+        self.last_error = CString::new(message).ok();
+        GB_ERROR
+    }
+}
+
+// A handle without a game. Load one with `gb_load_rom`.
+#[no_mangle]
+pub extern "C" fn gb_create() -> *mut GbEmulator {
+    Box::into_raw(Box::new(GbEmulator {
+        emulator: None,
+        last_error: None,
+    }))
+}
+
+// Null is ignored.
+#[no_mangle]
+pub unsafe extern "C" fn gb_destroy(gb: *mut GbEmulator) {
+    if !gb.is_null() {
+        drop(Box::from_raw(gb));
+    }
+}
+
+// Copies the `size` bytes at `data` and starts the ROM with the model it was made for, replacing
+// any game already running. Returns GB_OK or GB_ERROR, see `gb_last_error`.
+#[no_mangle]
+pub unsafe extern "C" fn gb_load_rom(gb: *mut GbEmulator, data: *const u8, size: usize) -> c_int {
+    let gb = &mut *gb;
+    if data.is_null() {
+        return gb.fail("The ROM data is null.");
+    }
+    let content = slice::from_raw_parts(data, size).to_vec();
+    let emulator = Rom::from_bytes_with_policy(content, ValidationPolicy::Lenient)
+        .map_err(std::io::Error::from)
+        .and_then(Emulator::new);
+    match emulator {
+        Ok(emulator) => {
+            gb.emulator = Some(emulator);
+            GB_OK
+        }
+        Err(error) => gb.fail(error),
+    }
+}
+
+// Why the last call that returned GB_ERROR failed, or null. The string belongs to the handle and
+// lasts until the next error or `gb_destroy`.
+#[no_mangle]
+pub unsafe extern "C" fn gb_last_error(gb: *const GbEmulator) -> *const c_char {
+    (*gb)
+        .last_error
+        .as_ref()
+        .map_or(ptr::null(), |error| error.as_ptr())
+}
+
+// Runs until the next frame is finished. Does nothing without a game.
+#[no_mangle]
+pub unsafe extern "C" fn gb_run_frame(gb: *mut GbEmulator) {
+    if let Some(emulator) = (*gb).emulator.as_mut() {
+        emulator.run_frame();
+    }
+}
+
+// GB_SCREEN_WIDTH x GB_SCREEN_HEIGHT pixels, 4 bytes (RGBA) each, or null without a game. The
+// pointer stays valid until the next call that changes the handle.
+#[no_mangle]
+pub unsafe extern "C" fn gb_get_framebuffer(gb: *const GbEmulator) -> *const u8 {
+    (*gb)
+        .emulator
+        .as_ref()
+        .map_or(ptr::null(), |emulator| emulator.framebuffer().as_ptr())
+}
+
+// Sets which buttons are held, as GB_BUTTON_* bits ORed together.
+#[no_mangle]
+pub unsafe extern "C" fn gb_set_input(gb: *mut GbEmulator, buttons: u8) {
+    if let Some(emulator) = (*gb).emulator.as_mut() {
+        for (bit, button) in BUTTONS {
+            emulator.set_button(button, buttons & bit != 0);
+        }
+    }
+}
+
+// Moves up to `capacity` interleaved stereo samples at 48 kHz into `buffer` and returns how many
+// it moved. Call it until it returns 0 after each frame.
+#[no_mangle]
+pub unsafe extern "C" fn gb_read_audio(
+    gb: *mut GbEmulator,
+    buffer: *mut f32,
+    capacity: usize,
+) -> usize {
+    match (*gb).emulator.as_mut() {
+        Some(emulator) if !buffer.is_null() => {
+            emulator.fill_audio_buffer(slice::from_raw_parts_mut(buffer, capacity))
+        }
+        _ => 0,
+    }
+}
+
+pub const GB_OK: c_int = 0;
+
+pub const GB_ERROR: c_int = -1;
+
+pub const GB_SCREEN_WIDTH: usize = 160;
+
+pub const GB_SCREEN_HEIGHT: usize = 144;
+
+pub const GB_BUTTON_A: u8 = 0x01;
+
+pub const GB_BUTTON_B: u8 = 0x02;
+
+pub const GB_BUTTON_SELECT: u8 = 0x04;
+
+pub const GB_BUTTON_START: u8 = 0x08;
+
+pub const GB_BUTTON_RIGHT: u8 = 0x10;
+
+pub const GB_BUTTON_LEFT: u8 = 0x20;
+
+pub const GB_BUTTON_UP: u8 = 0x40;
+
+pub const GB_BUTTON_DOWN: u8 = 0x80;
+
+const BUTTONS: [(u8, Button); 8] = [
+    (GB_BUTTON_A, Button::A),
+    (GB_BUTTON_B, Button::B),
+    (GB_BUTTON_SELECT, Button::Select),
+    (GB_BUTTON_START, Button::Start),
+    (GB_BUTTON_RIGHT, Button::Right),
+    (GB_BUTTON_LEFT, Button::Left),
+    (GB_BUTTON_UP, Button::Up),
+    (GB_BUTTON_DOWN, Button::Down),
+];
+
+#[cfg(test)]
+mod tests {
+    use std::ffi::CStr;
+
+    use super::*;
+    use crate::ppu::{SCREEN_HEIGHT, SCREEN_WIDTH};
+
+    #[test]
+    fn test_screen_size_matches_ppu() {
+        assert_eq!(
+            (GB_SCREEN_WIDTH, GB_SCREEN_HEIGHT),
+            (SCREEN_WIDTH, SCREEN_HEIGHT)
+        );
+    }
+
+    #[test]
+    fn test_run_game() {
+        unsafe {
+            // Arrange
+            let gb = gb_create();
+            let rom = vec![0; 0x8000];
+
+            // Act
+            let result = gb_load_rom(gb, rom.as_ptr(), rom.len());
+            gb_set_input(gb, GB_BUTTON_A | GB_BUTTON_START);
+            gb_run_frame(gb);
+
+            // Assert
+            assert_eq!(result, GB_OK);
+            assert!(!gb_get_framebuffer(gb).is_null());
+            let mut samples = [0.0; 256];
+            assert!(gb_read_audio(gb, samples.as_mut_ptr(), samples.len()) > 0);
+            gb_destroy(gb);
+        }
+    }
+
+    #[test]
+    fn test_load_error() {
+        unsafe {
+            let gb = gb_create();
+
+            let result = gb_load_rom(gb, [0u8; 16].as_ptr(), 16);
+
+            assert_eq!(result, GB_ERROR);
+            let error = CStr::from_ptr(gb_last_error(gb)).to_str().unwrap();
+            assert_eq!(error, "The ROM is too short to contain a header.");
+            assert!(gb_get_framebuffer(gb).is_null());
+            gb_destroy(gb);
+        }
+    }
+}
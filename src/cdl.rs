@@ -0,0 +1,121 @@
+use std::fs;
+use std::io::{ErrorKind, Result};
+use std::path::Path;
+
+// A code/data log: a byte for each ROM byte saying how the game used it, in the .cdl layout other
+// emulators' loggers write, bit 0 for code and bit 1 for data. Bytes the game never touched stay 0.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CodeDataLog {
+    flags: Vec<u8>,
+    // The last ROM byte read as data and its value, for spotting copies to tile data.
+    last_read: Option<(usize, u8)>,
+}
+
+impl CodeDataLog {
+    pub fn new(rom_size: usize) -> CodeDataLog {
+        CodeDataLog {
+            flags: vec![0; rom_size],
+            last_read: None,
+        }
+    }
+
+    // Carries on from the log at `path` so sessions add up, or starts over when there's none or
+    // it's for a ROM of another size.
+    pub fn load(path: &Path, rom_size: usize) -> Result<CodeDataLog> {
+        match fs::read(path) {
+            Ok(flags) if flags.len() == rom_size => Ok(CodeDataLog {
+                flags,
+                last_read: None,
+            }),
+            Ok(_) => Ok(CodeDataLog::new(rom_size)),
+            Err(error) if error.kind() == ErrorKind::NotFound => Ok(CodeDataLog::new(rom_size)),
+            Err(error) => Err(error),
+        }
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        fs::write(path, &self.flags)
+    }
+
+    pub fn flags(&self) -> &[u8] {
+        &self.flags
+    }
+
+    // Offsets past the end of the ROM are ignored.
+    pub fn mark(&mut self, offset: usize, flag: u8) {
+        if let Some(flags) = self.flags.get_mut(offset) {
+            *flags |= flag;
+        }
+    }
+
+    pub fn mark_data(&mut self, offset: usize, value: u8) {
+        self.mark(offset, DATA);
+        self.last_read = Some((offset, value));
+    }
+
+    // Games copy tiles by reading them from ROM and writing the same value to VRAM right after,
+    // which is all the CPU can do. Compressed graphics don't get marked.
+    pub fn vram_written(&mut self, address: u16, value: u8) {
+        if let Some((offset, read)) = self.last_read.take() {
+            if read == value && (0x8000..=0x97FF).contains(&address) {
+                self.mark(offset, GRAPHICS);
+            }
+        }
+    }
+
+    // How many ROM bytes have `flag`.
+    pub fn count(&self, flag: u8) -> usize {
+        self.flags
+            .iter()
+            .filter(|&&flags| flags & flag != 0)
+            .count()
+    }
+}
+
+pub const CODE: u8 = 0x01;
+pub const DATA: u8 = 0x02;
+pub const GRAPHICS: u8 = 0x04;
+
+#[cfg(test)]
+mod tests {
+    use tempfile::tempdir;
+
+    use super::*;
+
+    #[test]
+    fn test_vram_written() {
+        // Arrange
+        let mut log = CodeDataLog::new(0x10);
+        log.mark_data(1, 0xAA);
+        log.vram_written(0x8000, 0xAA);
+        log.mark_data(2, 0xBB);
+        log.vram_written(0x8001, 0xCC);
+        log.mark_data(3, 0xDD);
+        log.vram_written(0xC000, 0xDD);
+
+        // Act
+        let flags = &log.flags()[1..4];
+
+        // Assert
+        assert_eq!(flags, [DATA | GRAPHICS, DATA, DATA]);
+        assert_eq!(log.count(GRAPHICS), 1);
+    }
+
+    #[test]
+    fn test_load_adds_up() {
+        // Arrange
+        let directory = tempdir().unwrap();
+        let path = directory.path().join("game.cdl");
+        let mut log = CodeDataLog::new(4);
+        log.mark(0, CODE);
+        log.save(&path).unwrap();
+
+        // Act
+        let loaded = CodeDataLog::load(&path, 4).unwrap();
+        let other_size = CodeDataLog::load(&path, 8).unwrap();
+
+        // Assert
+        assert_eq!(loaded.flags(), [CODE, 0, 0, 0]);
+        assert_eq!(other_size.count(CODE), 0);
+    }
+}
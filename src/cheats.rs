@@ -0,0 +1,342 @@
+use std::fmt;
+use std::fs;
+use std::io::{Error, Result};
+use std::path::Path;
+use std::str::FromStr;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Code {
+    // Game Genie: changes what reads from ROM return. With `compare` the byte is only replaced
+    // when the ROM holds that value, which keeps other banks mapped at the same address intact.
+    GameGenie {
+        address: u16,
+        value: u8,
+        compare: Option<u8>,
+    },
+    // GameShark: writes a byte to RAM once a frame. The bank picks the CGB work RAM bank for
+    // 0xD000-0xDFFF, cartridge RAM is written in whichever bank is mapped.
+    GameShark {
+        bank: u8,
+        address: u16,
+        value: u8,
+    },
+}
+
+impl FromStr for Code {
+    type Err = Error;
+
+    // Game Genie codes look like ABC-DEF or ABC-DEF-GHI, GameShark codes like 01FF34C1.
+    fn from_str(code: &str) -> Result<Code> {
+        let invalid = || Error::other(format!("{} is not a Game Genie or GameShark code.", code));
+        let digits = code
+            .chars()
+            .filter(|&c| c != '-')
+            .map(|c| c.to_digit(16).map(|digit| digit as u8))
+            .collect::<Option<Vec<u8>>>()
+            .ok_or_else(invalid)?;
+        let byte = |index: usize| digits[index] << 4 | digits[index + 1];
+
+        match digits.len() {
+            6 | 9 => {
+                // The top digit of the address comes last and is inverted.
+                let address = ((digits[5] ^ 0xF) as u16) << 12
+                    | (digits[2] as u16) << 8
+                    | (digits[3] as u16) << 4
+                    | digits[4] as u16;
+                if address >= 0x8000 {
+                    return Err(Error::other(format!(
+                        "{} patches {:#06X}, Game Genie codes can only patch ROM.",
+                        code, address
+                    )));
+                }
+                // H is a check digit nothing needs.
+                let compare = (digits.len() == 9)
+                    .then(|| (digits[6] << 4 | digits[8]).rotate_right(2) ^ 0xBA);
+                Ok(Code::GameGenie {
+                    address,
+                    value: byte(0),
+                    compare,
+                })
+            }
+            8 if !code.contains('-') => {
+                let address = (byte(6) as u16) << 8 | byte(4) as u16;
+                if !(0xA000..=0xDFFF).contains(&address) {
+                    return Err(Error::other(format!(
+                        "{} writes {:#06X}, GameShark codes can only write cartridge or work RAM.",
+                        code, address
+                    )));
+                }
+                Ok(Code::GameShark {
+                    bank: byte(0),
+                    address,
+                    value: byte(2),
+                })
+            }
+            _ => Err(invalid()),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Cheat {
+    // The code as it was written.
+    pub text: String,
+    pub code: Code,
+    pub name: String,
+    pub enabled: bool,
+}
+
+impl Cheat {
+    pub fn new(text: &str) -> Result<Cheat> {
+        Ok(Cheat {
+            text: text.to_string(),
+            code: text.parse()?,
+            name: String::new(),
+            enabled: true,
+        })
+    }
+}
+
+impl fmt::Display for Cheat {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let state = if self.enabled { "on" } else { "off" };
+        write!(f, "{} ({})", self.text, state)?;
+        if !self.name.is_empty() {
+            write!(f, " {}", self.name)?;
+        }
+        Ok(())
+    }
+}
+
+// The codes the bus applies, see `Bus::cheats_mut`. Turning them all off keeps which ones are
+// enabled for when they're turned back on.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Cheats {
+    cheats: Vec<Cheat>,
+    active: bool,
+}
+
+impl Default for Cheats {
+    fn default() -> Self {
+        Cheats::new()
+    }
+}
+
+impl Extend<Cheat> for Cheats {
+    fn extend<T: IntoIterator<Item = Cheat>>(&mut self, cheats: T) {
+        self.cheats.extend(cheats);
+    }
+}
+
+impl Cheats {
+    pub fn new() -> Cheats {
+        Cheats {
+            cheats: Vec::new(),
+            active: true,
+        }
+    }
+
+    // A cheat file has a code per line, optionally followed by a name. A leading - adds the code
+    // disabled, and lines starting with # are comments.
+    //
+    //   # Super Mario Land
+    //   00A-17B-C49 Start with 10 lives
+    //   -01FF34C1 Invincible
+    pub fn parse(text: &str) -> Result<Cheats> {
+        let mut cheats = Cheats::new();
+        for (number, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let (enabled, line) = match line.strip_prefix('-') {
+                Some(line) => (false, line.trim_start()),
+                None => (true, line),
+            };
+            let (text, name) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+            let mut cheat = Cheat::new(text)
+                .map_err(|error| Error::other(format!("Line {}: {}", number + 1, error)))?;
+            cheat.name = name.trim().to_string();
+            cheat.enabled = enabled;
+            cheats.add(cheat);
+        }
+        Ok(cheats)
+    }
+
+    pub fn load(path: &Path) -> Result<Cheats> {
+        let text = fs::read_to_string(path).map_err(|error| {
+            Error::new(
+                error.kind(),
+                format!("Could not read {}: {}", path.display(), error),
+            )
+        })?;
+        Cheats::parse(&text)
+    }
+
+    pub fn add(&mut self, cheat: Cheat) {
+        self.cheats.push(cheat);
+    }
+
+    pub fn remove(&mut self, index: usize) -> Option<Cheat> {
+        (index < self.cheats.len()).then(|| self.cheats.remove(index))
+    }
+
+    pub fn clear(&mut self) {
+        self.cheats.clear();
+    }
+
+    pub fn cheats(&self) -> &[Cheat] {
+        &self.cheats
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.cheats.is_empty()
+    }
+
+    // Returns false if there's no cheat at `index`.
+    pub fn set_enabled(&mut self, index: usize, enabled: bool) -> bool {
+        match self.cheats.get_mut(index) {
+            Some(cheat) => {
+                cheat.enabled = enabled;
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn active(&self) -> bool {
+        self.active
+    }
+
+    pub fn set_active(&mut self, active: bool) {
+        self.active = active;
+    }
+
+    fn codes(&self) -> impl Iterator<Item = Code> + '_ {
+        self.cheats
+            .iter()
+            .filter(|cheat| self.active && cheat.enabled)
+            .map(|cheat| cheat.code)
+    }
+
+    // What a read of `value` from ROM at `address` returns with the Game Genie codes applied.
+    pub fn patch_rom(&self, address: u16, value: u8) -> u8 {
+        for code in self.codes() {
+            if let Code::GameGenie {
+                address: patched,
+                value: replacement,
+                compare,
+            } = code
+            {
+                if patched == address && compare.is_none_or(|compare| compare == value) {
+                    return replacement;
+                }
+            }
+        }
+        value
+    }
+
+    // The GameShark writes to make this frame as (bank, address, value).
+    pub fn ram_writes(&self) -> impl Iterator<Item = (u8, u16, u8)> + '_ {
+        self.codes().filter_map(|code| match code {
+            Code::GameShark {
+                bank,
+                address,
+                value,
+            } => Some((bank, address, value)),
+            Code::GameGenie { .. } => None,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rstest::rstest;
+
+    use super::*;
+
+    #[rstest]
+    #[case(
+        "00A-17B-C49",
+        Code::GameGenie { address: 0x4A17, value: 0x00, compare: Some(0xC8) }
+    )]
+    #[case("3EA-F2F", Code::GameGenie { address: 0x0AF2, value: 0x3E, compare: None })]
+    #[case("3eaf2f", Code::GameGenie { address: 0x0AF2, value: 0x3E, compare: None })]
+    #[case("01FF34C1", Code::GameShark { bank: 0x01, address: 0xC134, value: 0xFF })]
+    #[case("9263FFD0", Code::GameShark { bank: 0x92, address: 0xD0FF, value: 0x63 })]
+    fn test_parse_code(#[case] text: &str, #[case] expected: Code) {
+        assert_eq!(text.parse::<Code>().unwrap(), expected);
+    }
+
+    #[rstest]
+    #[case("")]
+    #[case("00A-17B-C4")]
+    #[case("0GA-17B-C49")]
+    #[case("01FF-34C1")]
+    // Patches 0x8A17, which is VRAM.
+    #[case("00A-177-C49")]
+    // Writes 0xFF34, which isn't RAM.
+    #[case("01FF34FF")]
+    fn test_parse_invalid_code(#[case] text: &str) {
+        assert!(text.parse::<Code>().is_err());
+    }
+
+    #[test]
+    fn test_patch_rom() {
+        let mut cheats = Cheats::new();
+        cheats.add(Cheat::new("00A-17B-C49").unwrap());
+        cheats.add(Cheat::new("3EA-F2F").unwrap());
+
+        assert_eq!(cheats.patch_rom(0x4A17, 0xC8), 0x00);
+        // Another bank is mapped.
+        assert_eq!(cheats.patch_rom(0x4A17, 0x12), 0x12);
+        assert_eq!(cheats.patch_rom(0x0AF2, 0x12), 0x3E);
+        assert_eq!(cheats.patch_rom(0x0AF3, 0x12), 0x12);
+    }
+
+    #[test]
+    fn test_disabled_cheats_do_nothing() {
+        let mut cheats = Cheats::new();
+        cheats.add(Cheat::new("3EA-F2F").unwrap());
+        cheats.add(Cheat::new("01FF34C1").unwrap());
+
+        cheats.set_active(false);
+
+        assert_eq!(cheats.patch_rom(0x0AF2, 0x12), 0x12);
+        assert_eq!(cheats.ram_writes().count(), 0);
+        cheats.set_active(true);
+        assert!(cheats.set_enabled(0, false));
+        assert_eq!(cheats.patch_rom(0x0AF2, 0x12), 0x12);
+        assert_eq!(
+            cheats.ram_writes().collect::<Vec<_>>(),
+            [(0x01, 0xC134, 0xFF)]
+        );
+        assert!(!cheats.set_enabled(2, false));
+    }
+
+    #[test]
+    fn test_parse_file() {
+        // Arrange
+        let text = "# Comments and blank lines are skipped\n\n00A-17B-C49 Start with 10 lives\n-01FF34C1   Invincible\n3EA-F2F\n";
+
+        // Act
+        let cheats = Cheats::parse(text).unwrap();
+
+        // Assert
+        let cheats = cheats.cheats();
+        assert_eq!(cheats.len(), 3);
+        assert_eq!(cheats[0].name, "Start with 10 lives");
+        assert!(cheats[0].enabled);
+        assert_eq!(cheats[1].text, "01FF34C1");
+        assert_eq!(cheats[1].name, "Invincible");
+        assert!(!cheats[1].enabled);
+        assert_eq!(cheats[2].name, "");
+    }
+
+    #[test]
+    fn test_parse_file_reports_line() {
+        let error = Cheats::parse("3EA-F2F\nnonsense\n").unwrap_err();
+
+        assert!(error.to_string().starts_with("Line 2:"));
+    }
+}
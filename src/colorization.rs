@@ -0,0 +1,249 @@
+use std::io::{Error, Result};
+use std::ops::Range;
+use std::str::FromStr;
+
+use crate::rom::{Licensee, Rom};
+
+// The RGB555 colors BGP, OBP0 and OBP1's shades map to when a CGB runs a DMG game.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct CompatibilityPalettes {
+    pub bg: [u16; 4],
+    pub obj0: [u16; 4],
+    pub obj1: [u16; 4],
+}
+
+impl Default for CompatibilityPalettes {
+    // What the boot ROM picks for games it has no palettes of its own for.
+    fn default() -> Self {
+        PaletteCombo::RightA.palettes()
+    }
+}
+
+impl CompatibilityPalettes {
+    const fn uniform(colors: [u16; 4]) -> CompatibilityPalettes {
+        CompatibilityPalettes {
+            bg: colors,
+            obj0: colors,
+            obj1: colors,
+        }
+    }
+}
+
+// The palettes the CGB boot ROM gives a DMG game. Nintendo's own games are looked up by the sum
+// of their title's bytes, and the fourth letter of the title tells apart games whose sums clash.
+// Everything else gets the default.
+pub fn for_rom(rom: &Rom) -> CompatibilityPalettes {
+    let nintendo = match rom.licensee() {
+        Ok(Licensee::Old(code)) => code == NINTENDO,
+        Ok(Licensee::New(code)) => code == "01",
+        Err(_) => false,
+    };
+    let title = match rom.content().get(TITLE) {
+        Some(title) if nintendo => title,
+        _ => return CompatibilityPalettes::default(),
+    };
+
+    let checksum = title.iter().fold(0u8, |sum, &byte| sum.wrapping_add(byte));
+    TITLES
+        .iter()
+        .find(|(sum, fourth_letter, _)| {
+            *sum == checksum && fourth_letter.is_none_or(|letter| letter == title[3])
+        })
+        .map_or_else(CompatibilityPalettes::default, |(_, _, combo)| {
+            combo.palettes()
+        })
+}
+
+// The directions and buttons that can be held while the CGB boot ROM shows the logo to pick a
+// DMG game's colors.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PaletteCombo {
+    Up,
+    UpA,
+    UpB,
+    Left,
+    LeftA,
+    LeftB,
+    Down,
+    DownA,
+    DownB,
+    Right,
+    RightA,
+    RightB,
+}
+
+impl PaletteCombo {
+    pub fn palettes(self) -> CompatibilityPalettes {
+        match self {
+            // Brown
+            PaletteCombo::Up => CompatibilityPalettes::uniform(BROWN),
+            // Red
+            PaletteCombo::UpA => CompatibilityPalettes {
+                bg: RED,
+                obj0: GREEN,
+                obj1: BLUE,
+            },
+            // Dark brown
+            PaletteCombo::UpB => CompatibilityPalettes::uniform([
+                rgb(0xFFE6C5),
+                rgb(0xCE9C84),
+                rgb(0x846B29),
+                rgb(0x5A3108),
+            ]),
+            // Blue
+            PaletteCombo::Left => CompatibilityPalettes {
+                bg: BLUE,
+                obj0: RED,
+                obj1: RED,
+            },
+            // Dark blue
+            PaletteCombo::LeftA => CompatibilityPalettes {
+                bg: [rgb(0xFFFFFF), rgb(0x8C8CDE), rgb(0x52528C), rgb(0x000000)],
+                obj0: RED,
+                obj1: BROWN,
+            },
+            // Gray
+            PaletteCombo::LeftB => CompatibilityPalettes::uniform([
+                rgb(0xFFFFFF),
+                rgb(0xA5A5A5),
+                rgb(0x525252),
+                rgb(0x000000),
+            ]),
+            // Pale yellow
+            PaletteCombo::Down => CompatibilityPalettes::uniform([
+                rgb(0xFFFFA5),
+                rgb(0xFF9494),
+                rgb(0x9494FF),
+                rgb(0x000000),
+            ]),
+            // Orange
+            PaletteCombo::DownA => CompatibilityPalettes::uniform([
+                rgb(0xFFFFFF),
+                rgb(0xFFFF00),
+                rgb(0xFF0000),
+                rgb(0x000000),
+            ]),
+            // Yellow
+            PaletteCombo::DownB => CompatibilityPalettes {
+                bg: [rgb(0xFFFFFF), rgb(0xFFFF00), rgb(0x7B4A00), rgb(0x000000)],
+                obj0: BLUE,
+                obj1: GREEN,
+            },
+            // Green
+            PaletteCombo::Right => CompatibilityPalettes::uniform([
+                rgb(0xFFFFFF),
+                rgb(0x52FF00),
+                rgb(0xFF4200),
+                rgb(0x000000),
+            ]),
+            // Dark green
+            PaletteCombo::RightA => CompatibilityPalettes {
+                bg: [rgb(0xFFFFFF), rgb(0x7BFF31), rgb(0x0063C5), rgb(0x000000)],
+                obj0: RED,
+                obj1: RED,
+            },
+            // Inverted
+            PaletteCombo::RightB => CompatibilityPalettes::uniform([
+                rgb(0x000000),
+                rgb(0x008484),
+                rgb(0xFFDE00),
+                rgb(0xFFFFFF),
+            ]),
+        }
+    }
+}
+
+impl FromStr for PaletteCombo {
+    type Err = Error;
+
+    fn from_str(name: &str) -> Result<PaletteCombo> {
+        match name.to_ascii_lowercase().replace(' ', "").as_str() {
+            "up" => Ok(PaletteCombo::Up),
+            "up+a" => Ok(PaletteCombo::UpA),
+            "up+b" => Ok(PaletteCombo::UpB),
+            "left" => Ok(PaletteCombo::Left),
+            "left+a" => Ok(PaletteCombo::LeftA),
+            "left+b" => Ok(PaletteCombo::LeftB),
+            "down" => Ok(PaletteCombo::Down),
+            "down+a" => Ok(PaletteCombo::DownA),
+            "down+b" => Ok(PaletteCombo::DownB),
+            "right" => Ok(PaletteCombo::Right),
+            "right+a" => Ok(PaletteCombo::RightA),
+            "right+b" => Ok(PaletteCombo::RightB),
+            _ => Err(Error::other(format!(
+                "{} is not a palette combination, expected a direction optionally followed by +a or +b, like up+a.",
+                name
+            ))),
+        }
+    }
+}
+
+// Converts 0xRRGGBB to RGB555, dropping the low 3 bits of each channel.
+const fn rgb(color: u32) -> u16 {
+    let red = (color >> 19) & 0x1F;
+    let green = (color >> 11) & 0x1F;
+    let blue = (color >> 3) & 0x1F;
+    (red | green << 5 | blue << 10) as u16
+}
+
+const TITLE: Range<usize> = 0x0134..0x0144;
+
+const NINTENDO: u8 = 0x01;
+
+const RED: [u16; 4] = [rgb(0xFFFFFF), rgb(0xFF8484), rgb(0x943A3A), rgb(0x000000)];
+
+const GREEN: [u16; 4] = [rgb(0xFFFFFF), rgb(0x7BFF31), rgb(0x008400), rgb(0x000000)];
+
+const BLUE: [u16; 4] = [rgb(0xFFFFFF), rgb(0x63A5FF), rgb(0x0000FF), rgb(0x000000)];
+
+const BROWN: [u16; 4] = [rgb(0xFFFFFF), rgb(0xFFAD63), rgb(0x843100), rgb(0x000000)];
+
+// Title sums, the fourth letter for sums more than one game has, and the palettes the boot ROM
+// gives them. Only the games whose palettes match one of the combinations are here so far.
+const TITLES: [(u8, Option<u8>, PaletteCombo); 2] = [
+    // POKEMON RED
+    (0x14, None, PaletteCombo::UpA),
+    // POKEMON BLUE
+    (0x61, Some(b'E'), PaletteCombo::Left),
+];
+
+#[cfg(test)]
+mod tests {
+    use rstest::rstest;
+
+    use super::*;
+
+    fn rom(title: &str, licensee: u8) -> Rom {
+        let mut content = vec![0; 0x8000];
+        content[0x0134..0x0134 + title.len()].copy_from_slice(title.as_bytes());
+        content[0x014B] = licensee;
+        Rom::from_content(content)
+    }
+
+    #[rstest]
+    #[case("POKEMON RED", NINTENDO, PaletteCombo::UpA)]
+    #[case("POKEMON BLUE", NINTENDO, PaletteCombo::Left)]
+    // The same sum as POKEMON BLUE with another fourth letter.
+    #[case("POKMEON BLUE", NINTENDO, PaletteCombo::RightA)]
+    #[case("POKEMON RED", 0x08, PaletteCombo::RightA)]
+    fn test_for_rom(#[case] title: &str, #[case] licensee: u8, #[case] expected: PaletteCombo) {
+        assert_eq!(for_rom(&rom(title, licensee)), expected.palettes());
+    }
+
+    #[test]
+    fn test_default_matches_boot_rom() {
+        let palettes = CompatibilityPalettes::default();
+
+        assert_eq!(palettes.bg, [0x7FFF, 0x1BEF, 0x6180, 0x0000]);
+        assert_eq!(palettes.obj0, [0x7FFF, 0x421F, 0x1CF2, 0x0000]);
+    }
+
+    #[rstest]
+    #[case("up", Some(PaletteCombo::Up))]
+    #[case("Left+B", Some(PaletteCombo::LeftB))]
+    #[case("down + a", Some(PaletteCombo::DownA))]
+    #[case("a+b", None)]
+    fn test_parse_combo(#[case] name: &str, #[case] expected: Option<PaletteCombo>) {
+        assert_eq!(name.parse::<PaletteCombo>().ok(), expected);
+    }
+}
@@ -0,0 +1,558 @@
+use std::collections::HashMap;
+use std::env;
+use std::fmt::Display;
+use std::fs;
+use std::io::{Error, ErrorKind, Result};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use serde::{Deserialize, Deserializer};
+
+use crate::colorization::PaletteCombo;
+use crate::display::{Scaling, WINDOW_SCALES};
+use crate::filter::Filter;
+use crate::input::{Target, DEFAULT_DEADZONE};
+use crate::joypad::Button;
+use crate::pacing::SyncMode;
+use crate::rom::Rom;
+
+// The settings file, read from config.toml in the user's config directory. Everything in it is
+// optional and the command line wins over it:
+//
+//     [keys]
+//     a = "X"
+//     b = ["Z", "Left Ctrl"]
+//
+//     [controllers.default]
+//     a = "a"
+//     turbo_a = "y"
+//     deadzone = 0.2
+//
+//     [turbo]
+//     a = 3
+//
+//     [video]
+//     scale = 4
+//     palette = "green"
+//
+//     [paths]
+//     save_dir = "/home/me/saves"
+//
+//     [games."POKEMON RED".video]
+//     frame_blend = 2
+//
+// Games are matched by the title in the header, or by the header's global checksum like "0x91E6"
+// when two games share a title.
+#[derive(Debug, Default, Deserialize, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    // SDL key names for each button or turbo button, replacing its default keys.
+    keys: HashMap<String, KeyNames>,
+    controllers: Controllers,
+    // The frames each turbo button stays pressed and released for.
+    turbo: HashMap<String, u32>,
+    #[serde(flatten)]
+    settings: Settings,
+    games: HashMap<String, Settings>,
+}
+
+impl Config {
+    // Reads the config at `path`, or the default one when there's no path. The default not
+    // existing is fine, it's only an error when `path` doesn't.
+    pub fn load(path: Option<&Path>) -> Result<Config> {
+        let (path, required) = match path {
+            Some(path) => (path.to_path_buf(), true),
+            None => match default_path() {
+                Some(path) => (path, false),
+                None => return Ok(Config::default()),
+            },
+        };
+        let text = match fs::read_to_string(&path) {
+            Ok(text) => text,
+            Err(error) if error.kind() == ErrorKind::NotFound && !required => {
+                return Ok(Config::default())
+            }
+            Err(error) => {
+                return Err(Error::new(
+                    error.kind(),
+                    format!("Could not read {}: {}", path.display(), error),
+                ))
+            }
+        };
+        Config::parse(&text).map_err(|error| Error::other(format!("{}: {}", path.display(), error)))
+    }
+
+    pub fn parse(text: &str) -> Result<Config> {
+        let config: Config = toml::from_str(text).map_err(Error::other)?;
+        config.key_bindings()?;
+        config.turbo_periods()?;
+        for profile in config.controllers.profiles() {
+            profile.validate()?;
+        }
+        config.settings.validate()?;
+        for settings in config.games.values() {
+            settings.validate()?;
+        }
+        Ok(config)
+    }
+
+    // The keys each remapped button is on.
+    pub fn key_bindings(&self) -> Result<Vec<(Target, Vec<String>)>> {
+        bindings(&self.keys)
+    }
+
+    pub fn turbo_periods(&self) -> Result<Vec<(Button, u32)>> {
+        self.turbo
+            .iter()
+            .map(|(button, &frames)| {
+                check(&format!("turbo.{}", button), Some(frames), TURBO_PERIODS)?;
+                Ok((button.parse()?, frames))
+            })
+            .collect()
+    }
+
+    pub fn controllers(&self) -> &Controllers {
+        &self.controllers
+    }
+
+    // The settings for `rom`: everything outside [games], then its title's overrides, then its
+    // checksum's.
+    pub fn settings_for(&self, rom: &Rom) -> Settings {
+        let mut settings = self.settings.clone();
+        let title = rom.title().ok();
+        let checksum = rom
+            .stored_global_checksum()
+            .ok()
+            .map(|checksum| format!("0x{:04X}", checksum));
+        for key in [title, checksum].into_iter().flatten() {
+            let game = self
+                .games
+                .iter()
+                .find(|(name, _)| name.eq_ignore_ascii_case(&key));
+            if let Some((_, game)) = game {
+                settings.merge(game);
+            }
+        }
+        settings
+    }
+}
+
+// What can be set for every game and overridden for one.
+#[derive(Clone, Debug, Default, Deserialize, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct Settings {
+    pub video: Video,
+    pub audio: Audio,
+    pub paths: Paths,
+}
+
+#[derive(Clone, Debug, Default, Deserialize, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct Video {
+    pub scale: Option<u32>,
+    #[serde(deserialize_with = "parse")]
+    pub scaling: Option<Scaling>,
+    #[serde(deserialize_with = "parse")]
+    pub filter: Option<Filter>,
+    // A preset's name or a palette file.
+    pub palette: Option<String>,
+    #[serde(deserialize_with = "parse")]
+    pub cgb_palette: Option<PaletteCombo>,
+    pub frame_blend: Option<u8>,
+    #[serde(deserialize_with = "parse")]
+    pub sync: Option<SyncMode>,
+}
+
+#[derive(Clone, Debug, Default, Deserialize, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct Audio {
+    pub sample_rate: Option<u32>,
+    // In milliseconds.
+    pub latency: Option<u64>,
+    pub turbo_audio: Option<bool>,
+}
+
+#[derive(Clone, Debug, Default, Deserialize, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct Paths {
+    pub boot_rom: Option<PathBuf>,
+    // Where battery saves go instead of next to the ROM.
+    pub save_dir: Option<PathBuf>,
+}
+
+impl Settings {
+    // Takes whatever `other` sets.
+    fn merge(&mut self, other: &Settings) {
+        fn take<T: Clone>(value: &mut Option<T>, other: &Option<T>) {
+            if other.is_some() {
+                value.clone_from(other);
+            }
+        }
+        let (video, other_video) = (&mut self.video, &other.video);
+        take(&mut video.scale, &other_video.scale);
+        take(&mut video.scaling, &other_video.scaling);
+        take(&mut video.filter, &other_video.filter);
+        take(&mut video.palette, &other_video.palette);
+        take(&mut video.cgb_palette, &other_video.cgb_palette);
+        take(&mut video.frame_blend, &other_video.frame_blend);
+        take(&mut video.sync, &other_video.sync);
+        let (audio, other_audio) = (&mut self.audio, &other.audio);
+        take(&mut audio.sample_rate, &other_audio.sample_rate);
+        take(&mut audio.latency, &other_audio.latency);
+        take(&mut audio.turbo_audio, &other_audio.turbo_audio);
+        take(&mut self.paths.boot_rom, &other.paths.boot_rom);
+        take(&mut self.paths.save_dir, &other.paths.save_dir);
+    }
+
+    // The same limits the command line has.
+    fn validate(&self) -> Result<()> {
+        check("video.scale", self.video.scale, WINDOW_SCALES)?;
+        check("video.frame_blend", self.video.frame_blend, FRAME_BLEND)?;
+        check("audio.sample_rate", self.audio.sample_rate, SAMPLE_RATES)?;
+        check("audio.latency", self.audio.latency, AUDIO_LATENCY)
+    }
+}
+
+// A profile for each controller, by the name SDL gives it, and "default" for the rest.
+#[derive(Clone, Debug, Default, Deserialize, PartialEq)]
+#[serde(transparent)]
+pub struct Controllers(HashMap<String, ControllerProfile>);
+
+impl Controllers {
+    pub fn profile(&self, name: &str) -> ControllerProfile {
+        [name, "default"]
+            .iter()
+            .find_map(|name| {
+                self.0
+                    .iter()
+                    .find(|(profile, _)| profile.eq_ignore_ascii_case(name))
+            })
+            .map(|(_, profile)| profile.clone())
+            .unwrap_or_default()
+    }
+
+    pub fn profiles(&self) -> impl Iterator<Item = &ControllerProfile> {
+        self.0.values()
+    }
+}
+
+#[derive(Clone, Debug, Default, Deserialize, PartialEq)]
+#[serde(default)]
+pub struct ControllerProfile {
+    // How far (0 to 1) the left stick has to move to press a direction.
+    deadzone: Option<f32>,
+    // SDL controller button names like "a" or "dpup" for each button, replacing its defaults.
+    #[serde(flatten)]
+    buttons: HashMap<String, KeyNames>,
+}
+
+impl ControllerProfile {
+    pub fn deadzone(&self) -> f32 {
+        self.deadzone.unwrap_or(DEFAULT_DEADZONE)
+    }
+
+    pub fn bindings(&self) -> Result<Vec<(Target, Vec<String>)>> {
+        bindings(&self.buttons)
+    }
+
+    fn validate(&self) -> Result<()> {
+        self.bindings()?;
+        match self.deadzone {
+            Some(deadzone) if !(0.0..=1.0).contains(&deadzone) => Err(Error::other(format!(
+                "A controller's deadzone is {} but has to be from 0 to 1.",
+                deadzone
+            ))),
+            _ => Ok(()),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+#[serde(untagged)]
+enum KeyNames {
+    One(String),
+    Many(Vec<String>),
+}
+
+fn bindings(names: &HashMap<String, KeyNames>) -> Result<Vec<(Target, Vec<String>)>> {
+    names
+        .iter()
+        .map(|(button, names)| {
+            let names = match names {
+                KeyNames::One(name) => vec![name.clone()],
+                KeyNames::Many(names) => names.clone(),
+            };
+            Ok((button.parse()?, names))
+        })
+        .collect()
+}
+
+// The ROMs played last, newest first, kept one to a line in recent.txt next to the config file.
+pub struct RecentRoms {
+    path: PathBuf,
+    roms: Vec<PathBuf>,
+}
+
+impl RecentRoms {
+    // The list next to `config_path`, or the default config file. Nothing's remembered where
+    // there's no config directory.
+    pub fn load(config_path: Option<&Path>) -> Result<Option<RecentRoms>> {
+        let Some(path) = config_path
+            .map(Path::to_path_buf)
+            .or_else(default_path)
+            .map(|path| path.with_file_name(RECENT_FILE))
+        else {
+            return Ok(None);
+        };
+        let roms = match fs::read_to_string(&path) {
+            Ok(text) => text
+                .lines()
+                .filter(|line| !line.trim().is_empty())
+                .map(PathBuf::from)
+                .collect(),
+            Err(error) if error.kind() == ErrorKind::NotFound => Vec::new(),
+            Err(error) => return Err(error),
+        };
+        Ok(Some(RecentRoms { path, roms }))
+    }
+
+    pub fn roms(&self) -> &[PathBuf] {
+        &self.roms
+    }
+
+    // Moves `rom` to the front and writes the list out.
+    pub fn add(&mut self, rom: &Path) -> Result<()> {
+        let rom = fs::canonicalize(rom).unwrap_or_else(|_| rom.to_path_buf());
+        self.roms.retain(|recent| *recent != rom);
+        self.roms.insert(0, rom);
+        self.roms.truncate(MAX_RECENT_ROMS);
+
+        if let Some(directory) = self.path.parent() {
+            fs::create_dir_all(directory)?;
+        }
+        let text: String = self
+            .roms
+            .iter()
+            .map(|rom| format!("{}\n", rom.display()))
+            .collect();
+        fs::write(&self.path, text)
+    }
+}
+
+// $XDG_CONFIG_HOME/rustygameboy/config.toml, falling back to ~/.config, or
+// %APPDATA%\rustygameboy\config.toml on Windows.
+pub fn default_path() -> Option<PathBuf> {
+    let directory = if cfg!(windows) {
+        env::var_os("APPDATA").map(PathBuf::from)
+    } else {
+        env::var_os("XDG_CONFIG_HOME")
+            .filter(|directory| !directory.is_empty())
+            .map(PathBuf::from)
+            .or_else(|| env::var_os("HOME").map(|home| Path::new(&home).join(".config")))
+    }?;
+    Some(directory.join("rustygameboy").join("config.toml"))
+}
+
+// Reads a string with the type's FromStr, so names in the config match the command line's.
+fn parse<'de, D, T>(deserializer: D) -> std::result::Result<Option<T>, D::Error>
+where
+    D: Deserializer<'de>,
+    T: FromStr,
+    T::Err: Display,
+{
+    let Some(text) = Option::<String>::deserialize(deserializer)? else {
+        return Ok(None);
+    };
+    text.parse().map(Some).map_err(serde::de::Error::custom)
+}
+
+fn check<T: PartialOrd + Display>(
+    name: &str,
+    value: Option<T>,
+    range: std::ops::RangeInclusive<T>,
+) -> Result<()> {
+    match value {
+        Some(value) if !range.contains(&value) => Err(Error::other(format!(
+            "{} is {} but has to be from {} to {}.",
+            name,
+            value,
+            range.start(),
+            range.end()
+        ))),
+        _ => Ok(()),
+    }
+}
+
+const FRAME_BLEND: std::ops::RangeInclusive<u8> = 1..=4;
+
+const SAMPLE_RATES: std::ops::RangeInclusive<u32> = 8_000..=192_000;
+
+const AUDIO_LATENCY: std::ops::RangeInclusive<u64> = 20..=1000;
+
+const TURBO_PERIODS: std::ops::RangeInclusive<u32> = 1..=30;
+
+const RECENT_FILE: &str = "recent.txt";
+
+const MAX_RECENT_ROMS: usize = 9;
+
+#[cfg(test)]
+mod tests {
+    use rstest::rstest;
+    use tempfile::tempdir;
+
+    use super::*;
+
+    fn rom(title: &str, global_checksum: u16) -> Rom {
+        let mut content = vec![0; 0x8000];
+        content[0x0134..0x0134 + title.len()].copy_from_slice(title.as_bytes());
+        content[0x014E..0x0150].copy_from_slice(&global_checksum.to_be_bytes());
+        Rom::from_content(content)
+    }
+
+    #[test]
+    fn test_parse() {
+        let text = r#"
+            [keys]
+            a = "X"
+            select = ["Backspace", "Right Shift"]
+            turbo_b = "S"
+
+            [turbo]
+            b = 4
+
+            [video]
+            scale = 4
+            scaling = "fit"
+            filter = "dot-matrix"
+            sync = "vsync"
+
+            [audio]
+            latency = 60
+
+            [paths]
+            save_dir = "saves"
+        "#;
+
+        let config = Config::parse(text).unwrap();
+
+        let mut keys = config.key_bindings().unwrap();
+        keys.sort_by_key(|(target, _)| target.button() as u8);
+        assert_eq!(
+            keys,
+            [
+                (Target::Button(Button::A), vec!["X".to_string()]),
+                (Target::Turbo(Button::B), vec!["S".to_string()]),
+                (
+                    Target::Button(Button::Select),
+                    vec!["Backspace".to_string(), "Right Shift".to_string()]
+                ),
+            ]
+        );
+        assert_eq!(config.turbo_periods().unwrap(), [(Button::B, 4)]);
+        let settings = &config.settings;
+        assert_eq!(settings.video.scale, Some(4));
+        assert_eq!(settings.video.scaling, Some(Scaling::Fit));
+        assert_eq!(settings.video.filter, Some(Filter::DotMatrix));
+        assert_eq!(settings.video.sync, Some(SyncMode::Vsync));
+        assert_eq!(settings.audio.latency, Some(60));
+        assert_eq!(settings.paths.save_dir, Some(PathBuf::from("saves")));
+    }
+
+    #[rstest]
+    #[case("[video]\nscale = 9\n", "video.scale is 9 but has to be from 2 to 6")]
+    #[case("[video]\nfilter = \"crt\"\n", "crt is not a filter")]
+    #[case("[keys]\nturbo = \"T\"\n", "turbo is not a Game Boy button")]
+    #[case("[sound]\n", "unknown field")]
+    #[case("[turbo]\na = 0\n", "turbo.a is 0 but has to be from 1 to 30")]
+    fn test_parse_errors(#[case] text: &str, #[case] message: &str) {
+        let error = Config::parse(text).unwrap_err();
+
+        assert!(error.to_string().contains(message), "{}", error);
+    }
+
+    #[test]
+    fn test_controller_profiles() {
+        // Arrange
+        let text = r#"
+            [controllers.default]
+            deadzone = 0.5
+
+            [controllers."8BitDo SN30 Pro"]
+            a = "a"
+            b = ["b", "y"]
+        "#;
+        let config = Config::parse(text).unwrap();
+
+        // Act
+        let sn30 = config.controllers().profile("8bitdo sn30 pro");
+        let other = config.controllers().profile("Xbox Controller");
+
+        // Assert
+        assert_eq!(sn30.deadzone(), DEFAULT_DEADZONE);
+        assert_eq!(sn30.bindings().unwrap().len(), 2);
+        assert_eq!(other.deadzone(), 0.5);
+        assert!(other.bindings().unwrap().is_empty());
+        assert!(Config::parse("[controllers.default]\ndeadzone = 2.0\n").is_err());
+    }
+
+    #[test]
+    fn test_settings_for() {
+        // Arrange
+        let text = r#"
+            [video]
+            scale = 4
+            frame_blend = 1
+
+            [games."POKEMON RED".video]
+            frame_blend = 2
+            palette = "green"
+
+            [games."0x91E6".video]
+            palette = "pocket"
+        "#;
+        let config = Config::parse(text).unwrap();
+
+        // Act
+        let red = config.settings_for(&rom("POKEMON RED", 0x91E6));
+        let other = config.settings_for(&rom("TETRIS", 0x1234));
+
+        // Assert
+        assert_eq!(red.video.scale, Some(4));
+        assert_eq!(red.video.frame_blend, Some(2));
+        // The checksum is more specific than the title.
+        assert_eq!(red.video.palette.as_deref(), Some("pocket"));
+        assert_eq!(other.video.frame_blend, Some(1));
+        assert_eq!(other.video.palette, None);
+    }
+
+    #[test]
+    fn test_recent_roms() {
+        // Arrange
+        let directory = tempdir().unwrap();
+        // Saving makes the directory the config goes in.
+        let config_path = directory.path().join("config").join("config.toml");
+        let rom_path = |name| directory.path().join(name);
+        let mut recent = RecentRoms::load(Some(&config_path)).unwrap().unwrap();
+
+        // Act
+        for rom in ["a.gb", "b.gb", "a.gb"] {
+            recent.add(&rom_path(rom)).unwrap();
+        }
+        let reloaded = RecentRoms::load(Some(&config_path)).unwrap().unwrap();
+
+        // Assert
+        assert_eq!(reloaded.roms(), [rom_path("a.gb"), rom_path("b.gb")]);
+    }
+
+    #[test]
+    fn test_load() {
+        let directory = tempdir().unwrap();
+        let path = directory.path().join("config.toml");
+        fs::write(&path, "[video]\nscale = 2\n").unwrap();
+
+        let config = Config::load(Some(&path));
+
+        assert_eq!(config.unwrap().settings.video.scale, Some(2));
+        assert!(Config::load(Some(Path::new("missing.toml"))).is_err());
+    }
+}
@@ -0,0 +1,1848 @@
+use std::io::Result;
+use std::marker::PhantomData;
+
+use crate::interrupts;
+use crate::model::EmulatorModel;
+use crate::savestate::{Snapshot, StateReader, StateWriter};
+use crate::trace::Tracer;
+
+pub trait Memory {
+    fn read(&mut self, address: u16) -> u8;
+
+    fn write(&mut self, address: u16, value: u8);
+
+    // A read for debugging tools that shouldn't leave a trace on the hardware state.
+    fn peek(&mut self, address: u16) -> u8 {
+        self.read(address)
+    }
+
+    // Reads an opcode or operand, as opposed to data.
+    fn fetch(&mut self, address: u16) -> u8 {
+        self.read(address)
+    }
+
+    // Called with PC before each instruction is fetched.
+    fn instruction_start(&mut self, _pc: u16) {}
+
+    // Called once for every machine cycle the CPU spends, before the access it belongs to.
+    fn tick(&mut self, _cycles: u32) {}
+
+    // Cycles the last tick also spent with the CPU stopped, like while CGB VRAM DMA copies.
+    fn take_stalled_cycles(&mut self) -> u32 {
+        0
+    }
+
+    // Called when an idle cycle increments or decrements a register pair holding `address`, like
+    // INC rr does. The DMG corrupts OAM when that's in 0xFE00-0xFEFF during mode 2.
+    fn address_incremented(&mut self, _address: u16) {}
+
+    // A read that increments or decrements the register pair holding `address` at the same time,
+    // like LD A,(HL+) and POP do.
+    fn read_incrementing(&mut self, address: u16) -> u8 {
+        self.read(address)
+    }
+
+    // Interrupts that are both requested in IF and enabled in IE.
+    fn pending_interrupts(&mut self) -> u8 {
+        self.read(0xFFFF) & self.read(0xFF0F) & 0x1F
+    }
+
+    fn acknowledge_interrupt(&mut self, mask: u8) {
+        let flag = self.read(0xFF0F);
+        self.write(0xFF0F, flag & !mask);
+    }
+
+    // Called by STOP. Returns true if a CGB speed switch was armed and carried out instead.
+    fn switch_speed(&mut self) -> bool {
+        false
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Registers {
+    pub a: u8,
+    pub f: u8,
+    pub b: u8,
+    pub c: u8,
+    pub d: u8,
+    pub e: u8,
+    pub h: u8,
+    pub l: u8,
+    pub sp: u16,
+    pub pc: u16,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Flag {
+    Zero = 0x80,
+    Subtract = 0x40,
+    HalfCarry = 0x20,
+    Carry = 0x10,
+}
+
+impl Registers {
+    pub fn af(&self) -> u16 {
+        u16::from_be_bytes([self.a, self.f])
+    }
+
+    pub fn set_af(&mut self, value: u16) {
+        let [a, f] = value.to_be_bytes();
+        self.a = a;
+        // The low nibble of F is hard-wired to 0.
+        self.f = f & 0xF0;
+    }
+
+    pub fn bc(&self) -> u16 {
+        u16::from_be_bytes([self.b, self.c])
+    }
+
+    pub fn set_bc(&mut self, value: u16) {
+        [self.b, self.c] = value.to_be_bytes();
+    }
+
+    pub fn de(&self) -> u16 {
+        u16::from_be_bytes([self.d, self.e])
+    }
+
+    pub fn set_de(&mut self, value: u16) {
+        [self.d, self.e] = value.to_be_bytes();
+    }
+
+    pub fn hl(&self) -> u16 {
+        u16::from_be_bytes([self.h, self.l])
+    }
+
+    pub fn set_hl(&mut self, value: u16) {
+        [self.h, self.l] = value.to_be_bytes();
+    }
+
+    pub fn flag(&self, flag: Flag) -> bool {
+        self.f & flag as u8 != 0
+    }
+
+    pub fn set_flag(&mut self, flag: Flag, set: bool) {
+        if set {
+            self.f |= flag as u8;
+        } else {
+            self.f &= !(flag as u8);
+        }
+    }
+
+    fn set_flags(&mut self, zero: bool, subtract: bool, half_carry: bool, carry: bool) {
+        self.set_flag(Flag::Zero, zero);
+        self.set_flag(Flag::Subtract, subtract);
+        self.set_flag(Flag::HalfCarry, half_carry);
+        self.set_flag(Flag::Carry, carry);
+    }
+
+    pub fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_u8(self.a);
+        writer.write_u8(self.f);
+        writer.write_u8(self.b);
+        writer.write_u8(self.c);
+        writer.write_u8(self.d);
+        writer.write_u8(self.e);
+        writer.write_u8(self.h);
+        writer.write_u8(self.l);
+        writer.write_u16(self.sp);
+        writer.write_u16(self.pc);
+    }
+
+    pub fn load_state(&mut self, reader: &mut StateReader) -> Result<()> {
+        self.a = reader.read_u8()?;
+        self.f = reader.read_u8()?;
+        self.b = reader.read_u8()?;
+        self.c = reader.read_u8()?;
+        self.d = reader.read_u8()?;
+        self.e = reader.read_u8()?;
+        self.h = reader.read_u8()?;
+        self.l = reader.read_u8()?;
+        self.sp = reader.read_u16()?;
+        self.pc = reader.read_u16()?;
+        Ok(())
+    }
+}
+
+pub struct Cpu {
+    pub registers: Registers,
+    ime: bool,
+    // EI only enables interrupts after the instruction that follows it.
+    ime_scheduled: bool,
+    halted: bool,
+    // HALT with IME off and an interrupt already pending fails to increment PC on the next fetch.
+    halt_bug: bool,
+    stopped: bool,
+    locked: bool,
+    step_cycles: u32,
+    tracer: Option<Tracer>,
+}
+
+impl Default for Cpu {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Cpu {
+    // Starts with the register values the DMG boot ROM leaves behind so cartridges can run directly.
+    pub fn new() -> Cpu {
+        Cpu {
+            registers: Registers {
+                a: 0x01,
+                f: 0xB0,
+                b: 0x00,
+                c: 0x13,
+                d: 0x00,
+                e: 0xD8,
+                h: 0x01,
+                l: 0x4D,
+                sp: 0xFFFE,
+                pc: 0x0100,
+            },
+            ime: false,
+            ime_scheduled: false,
+            halted: false,
+            halt_bug: false,
+            stopped: false,
+            locked: false,
+            step_cycles: 0,
+            tracer: None,
+        }
+    }
+
+    // Everything cleared and PC at 0x0000, where the boot ROM starts.
+    pub fn power_on() -> Cpu {
+        let mut cpu = Cpu::new();
+        cpu.registers = Registers::default();
+        cpu
+    }
+
+    // The register values the CGB boot ROM leaves behind for CGB cartridges.
+    pub fn new_cgb() -> Cpu {
+        let mut cpu = Cpu::new();
+        cpu.registers = Registers {
+            a: 0x11,
+            f: 0x80,
+            b: 0x00,
+            c: 0x00,
+            d: 0xFF,
+            e: 0x56,
+            h: 0x00,
+            l: 0x0D,
+            sp: 0xFFFE,
+            pc: 0x0100,
+        };
+        cpu
+    }
+
+    // The register values each model's boot ROM leaves behind. The CGB boot ROM leaves different
+    // ones depending on whether the cartridge runs in CGB mode.
+    pub fn for_model(model: EmulatorModel, cgb_mode: bool) -> Cpu {
+        let mut cpu = match model {
+            EmulatorModel::Cgb if cgb_mode => return Cpu::new_cgb(),
+            EmulatorModel::Cgb => Cpu::new_cgb(),
+            _ => Cpu::new(),
+        };
+        let registers = &mut cpu.registers;
+        match model {
+            EmulatorModel::Dmg => {}
+            EmulatorModel::Mgb => registers.a = 0xFF,
+            EmulatorModel::Sgb => {
+                registers.f = 0x00;
+                registers.c = 0x14;
+                registers.e = 0x00;
+                registers.h = 0xC0;
+                registers.l = 0x60;
+            }
+            EmulatorModel::Cgb => {
+                registers.d = 0x00;
+                registers.e = 0x08;
+                registers.l = 0x7C;
+            }
+        }
+        cpu
+    }
+
+    pub fn ime(&self) -> bool {
+        self.ime
+    }
+
+    // For tests that start the CPU in a given state.
+    pub fn set_ime(&mut self, ime: bool) {
+        self.ime = ime;
+        self.ime_scheduled = false;
+    }
+
+    pub fn halted(&self) -> bool {
+        self.halted
+    }
+
+    pub fn stopped(&self) -> bool {
+        self.stopped
+    }
+
+    pub fn set_tracer(&mut self, tracer: Option<Tracer>) {
+        self.tracer = tracer;
+    }
+
+    // Removes the tracer so it can be finished, see `Tracer::finish`.
+    pub fn take_tracer(&mut self) -> Option<Tracer> {
+        self.tracer.take()
+    }
+
+    // Executes a single instruction and returns the number of T-cycles it took.
+    pub fn step<M: Memory>(&mut self, mem: &mut M) -> u32 {
+        self.step_cycles = 0;
+        self.step_instruction(mem);
+        if let Some(tracer) = &mut self.tracer {
+            tracer.add_cycles(self.step_cycles);
+        }
+        self.step_cycles
+    }
+
+    fn step_instruction<M: Memory>(&mut self, mem: &mut M) {
+        if self.stopped || self.locked {
+            self.idle(mem);
+            return;
+        }
+
+        if self.halted {
+            if mem.pending_interrupts() == 0 {
+                self.idle(mem);
+                return;
+            }
+
+            // Leaving HALT takes an extra machine cycle.
+            self.halted = false;
+            self.idle(mem);
+        }
+
+        if self.ime && mem.pending_interrupts() != 0 {
+            self.dispatch_interrupt(mem);
+            return;
+        }
+
+        if self.ime_scheduled {
+            self.ime_scheduled = false;
+            self.ime = true;
+        }
+
+        mem.instruction_start(self.registers.pc);
+        if let Some(tracer) = &mut self.tracer {
+            let pc = self.registers.pc;
+            let memory = [0, 1, 2, 3].map(|offset| mem.peek(pc.wrapping_add(offset)));
+            tracer.trace(self.registers, memory);
+        }
+
+        let opcode = self.fetch(mem);
+        if self.halt_bug {
+            self.halt_bug = false;
+            self.registers.pc = self.registers.pc.wrapping_sub(1);
+        }
+        Dispatch::<M>::OPCODES[opcode as usize >> 4][opcode as usize & 0x0F](self, mem);
+    }
+
+    // Takes 5 machine cycles: two internal delays, pushing PC and jumping to the vector.
+    fn dispatch_interrupt<M: Memory>(&mut self, mem: &mut M) {
+        self.ime = false;
+        self.idle(mem);
+        self.idle_incrementing(mem, self.registers.sp);
+
+        let [high, low] = self.registers.pc.to_be_bytes();
+        self.registers.sp = self.registers.sp.wrapping_sub(1);
+        self.write(mem, self.registers.sp, high);
+
+        // The interrupt is picked after the high byte is pushed, which may have overwritten IE.
+        // If nothing is pending anymore the CPU jumps to 0x0000 instead.
+        let pending = mem.pending_interrupts();
+        self.registers.sp = self.registers.sp.wrapping_sub(1);
+        self.write(mem, self.registers.sp, low);
+
+        let mask = pending & pending.wrapping_neg();
+        self.registers.pc = if mask == 0 {
+            0x0000
+        } else {
+            mem.acknowledge_interrupt(mask);
+            interrupts::vector(mask)
+        };
+        self.idle(mem);
+    }
+
+    fn halt<M: Memory>(&mut self, mem: &mut M) {
+        if !self.ime && mem.pending_interrupts() != 0 {
+            self.halt_bug = true;
+        } else {
+            self.halted = true;
+        }
+    }
+
+    fn idle<M: Memory>(&mut self, mem: &mut M) {
+        mem.tick(4);
+        self.step_cycles += 4 + mem.take_stalled_cycles();
+    }
+
+    fn read<M: Memory>(&mut self, mem: &mut M, address: u16) -> u8 {
+        self.idle(mem);
+        mem.read(address)
+    }
+
+    fn idle_incrementing<M: Memory>(&mut self, mem: &mut M, address: u16) {
+        self.idle(mem);
+        mem.address_incremented(address);
+    }
+
+    fn read_incrementing<M: Memory>(&mut self, mem: &mut M, address: u16) -> u8 {
+        self.idle(mem);
+        mem.read_incrementing(address)
+    }
+
+    fn write<M: Memory>(&mut self, mem: &mut M, address: u16, value: u8) {
+        self.idle(mem);
+        mem.write(address, value);
+    }
+
+    fn fetch<M: Memory>(&mut self, mem: &mut M) -> u8 {
+        self.idle(mem);
+        let value = mem.fetch(self.registers.pc);
+        self.registers.pc = self.registers.pc.wrapping_add(1);
+        value
+    }
+
+    fn fetch16<M: Memory>(&mut self, mem: &mut M) -> u16 {
+        let low = self.fetch(mem);
+        let high = self.fetch(mem);
+        u16::from_le_bytes([low, high])
+    }
+
+    fn push<M: Memory>(&mut self, mem: &mut M, value: u16) {
+        let [high, low] = value.to_be_bytes();
+        self.idle_incrementing(mem, self.registers.sp);
+        self.registers.sp = self.registers.sp.wrapping_sub(1);
+        self.write(mem, self.registers.sp, high);
+        self.registers.sp = self.registers.sp.wrapping_sub(1);
+        self.write(mem, self.registers.sp, low);
+    }
+
+    fn pop<M: Memory>(&mut self, mem: &mut M) -> u16 {
+        let low = self.read_incrementing(mem, self.registers.sp);
+        self.registers.sp = self.registers.sp.wrapping_add(1);
+        let high = self.read_incrementing(mem, self.registers.sp);
+        self.registers.sp = self.registers.sp.wrapping_add(1);
+        u16::from_le_bytes([low, high])
+    }
+
+    // Operand order used by the opcode encoding: B, C, D, E, H, L, (HL), A.
+    fn read_r8<M: Memory>(&mut self, mem: &mut M, index: u8) -> u8 {
+        match index {
+            0 => self.registers.b,
+            1 => self.registers.c,
+            2 => self.registers.d,
+            3 => self.registers.e,
+            4 => self.registers.h,
+            5 => self.registers.l,
+            6 => self.read(mem, self.registers.hl()),
+            _ => self.registers.a,
+        }
+    }
+
+    fn write_r8<M: Memory>(&mut self, mem: &mut M, index: u8, value: u8) {
+        match index {
+            0 => self.registers.b = value,
+            1 => self.registers.c = value,
+            2 => self.registers.d = value,
+            3 => self.registers.e = value,
+            4 => self.registers.h = value,
+            5 => self.registers.l = value,
+            6 => self.write(mem, self.registers.hl(), value),
+            _ => self.registers.a = value,
+        }
+    }
+
+    // Operand order used by 16-bit loads and arithmetic: BC, DE, HL, SP.
+    fn read_r16(&self, index: u8) -> u16 {
+        match index {
+            0 => self.registers.bc(),
+            1 => self.registers.de(),
+            2 => self.registers.hl(),
+            _ => self.registers.sp,
+        }
+    }
+
+    fn write_r16(&mut self, index: u8, value: u16) {
+        match index {
+            0 => self.registers.set_bc(value),
+            1 => self.registers.set_de(value),
+            2 => self.registers.set_hl(value),
+            _ => self.registers.sp = value,
+        }
+    }
+
+    // Conditions in encoding order: NZ, Z, NC, C.
+    fn condition(&self, index: u8) -> bool {
+        match index {
+            0 => !self.registers.flag(Flag::Zero),
+            1 => self.registers.flag(Flag::Zero),
+            2 => !self.registers.flag(Flag::Carry),
+            _ => self.registers.flag(Flag::Carry),
+        }
+    }
+
+    // Always inlined into its handler in `Dispatch`, where the opcode is a constant and only its
+    // arm of the match is left.
+    #[inline(always)]
+    fn execute<M: Memory>(&mut self, mem: &mut M, opcode: u8) {
+        let y = (opcode >> 3) & 0x07;
+        let z = opcode & 0x07;
+        let p = (opcode >> 4) & 0x03;
+
+        match opcode {
+            0x00 => {}
+            0x01 | 0x11 | 0x21 | 0x31 => {
+                let value = self.fetch16(mem);
+                self.write_r16(p, value);
+            }
+            0x02 => self.write(mem, self.registers.bc(), self.registers.a),
+            0x12 => self.write(mem, self.registers.de(), self.registers.a),
+            0x22 => {
+                let hl = self.registers.hl();
+                self.write(mem, hl, self.registers.a);
+                self.registers.set_hl(hl.wrapping_add(1));
+            }
+            0x32 => {
+                let hl = self.registers.hl();
+                self.write(mem, hl, self.registers.a);
+                self.registers.set_hl(hl.wrapping_sub(1));
+            }
+            0x0A => self.registers.a = self.read(mem, self.registers.bc()),
+            0x1A => self.registers.a = self.read(mem, self.registers.de()),
+            0x2A => {
+                let hl = self.registers.hl();
+                self.registers.a = self.read_incrementing(mem, hl);
+                self.registers.set_hl(hl.wrapping_add(1));
+            }
+            0x3A => {
+                let hl = self.registers.hl();
+                self.registers.a = self.read_incrementing(mem, hl);
+                self.registers.set_hl(hl.wrapping_sub(1));
+            }
+            0x03 | 0x13 | 0x23 | 0x33 => {
+                let value = self.read_r16(p);
+                self.write_r16(p, value.wrapping_add(1));
+                self.idle_incrementing(mem, value);
+            }
+            0x0B | 0x1B | 0x2B | 0x3B => {
+                let value = self.read_r16(p);
+                self.write_r16(p, value.wrapping_sub(1));
+                self.idle_incrementing(mem, value);
+            }
+            0x04 | 0x0C | 0x14 | 0x1C | 0x24 | 0x2C | 0x34 | 0x3C => {
+                let value = self.read_r8(mem, y);
+                let result = value.wrapping_add(1);
+                self.registers.set_flag(Flag::Zero, result == 0);
+                self.registers.set_flag(Flag::Subtract, false);
+                self.registers
+                    .set_flag(Flag::HalfCarry, value & 0x0F == 0x0F);
+                self.write_r8(mem, y, result);
+            }
+            0x05 | 0x0D | 0x15 | 0x1D | 0x25 | 0x2D | 0x35 | 0x3D => {
+                let value = self.read_r8(mem, y);
+                let result = value.wrapping_sub(1);
+                self.registers.set_flag(Flag::Zero, result == 0);
+                self.registers.set_flag(Flag::Subtract, true);
+                self.registers
+                    .set_flag(Flag::HalfCarry, value & 0x0F == 0x00);
+                self.write_r8(mem, y, result);
+            }
+            0x06 | 0x0E | 0x16 | 0x1E | 0x26 | 0x2E | 0x36 | 0x3E => {
+                let value = self.fetch(mem);
+                self.write_r8(mem, y, value);
+            }
+            0x07 => {
+                let result = self.rlc(self.registers.a);
+                self.registers.a = result;
+                self.registers.set_flag(Flag::Zero, false);
+            }
+            0x0F => {
+                let result = self.rrc(self.registers.a);
+                self.registers.a = result;
+                self.registers.set_flag(Flag::Zero, false);
+            }
+            0x17 => {
+                let result = self.rl(self.registers.a);
+                self.registers.a = result;
+                self.registers.set_flag(Flag::Zero, false);
+            }
+            0x1F => {
+                let result = self.rr(self.registers.a);
+                self.registers.a = result;
+                self.registers.set_flag(Flag::Zero, false);
+            }
+            0x08 => {
+                let address = self.fetch16(mem);
+                let [high, low] = self.registers.sp.to_be_bytes();
+                self.write(mem, address, low);
+                self.write(mem, address.wrapping_add(1), high);
+            }
+            0x09 | 0x19 | 0x29 | 0x39 => {
+                let hl = self.registers.hl();
+                let value = self.read_r16(p);
+                let result = hl.wrapping_add(value);
+                self.registers.set_flag(Flag::Subtract, false);
+                self.registers
+                    .set_flag(Flag::HalfCarry, (hl & 0x0FFF) + (value & 0x0FFF) > 0x0FFF);
+                self.registers
+                    .set_flag(Flag::Carry, hl as u32 + value as u32 > 0xFFFF);
+                self.registers.set_hl(result);
+                self.idle(mem);
+            }
+            0x10 => {
+                self.fetch(mem);
+                if mem.switch_speed() {
+                    // The CPU sits out the switch while the rest of the hardware keeps going.
+                    for _ in 0..SPEED_SWITCH_CYCLES / 4 {
+                        self.idle(mem);
+                    }
+                } else {
+                    self.stopped = true;
+                }
+            }
+            0x18 => {
+                let offset = self.fetch(mem) as i8;
+                self.jump_relative(mem, offset);
+            }
+            0x20 | 0x28 | 0x30 | 0x38 => {
+                let offset = self.fetch(mem) as i8;
+                if self.condition(y - 4) {
+                    self.jump_relative(mem, offset);
+                }
+            }
+            0x27 => self.daa(),
+            0x2F => {
+                self.registers.a = !self.registers.a;
+                self.registers.set_flag(Flag::Subtract, true);
+                self.registers.set_flag(Flag::HalfCarry, true);
+            }
+            0x37 => {
+                self.registers.set_flag(Flag::Subtract, false);
+                self.registers.set_flag(Flag::HalfCarry, false);
+                self.registers.set_flag(Flag::Carry, true);
+            }
+            0x3F => {
+                let carry = self.registers.flag(Flag::Carry);
+                self.registers.set_flag(Flag::Subtract, false);
+                self.registers.set_flag(Flag::HalfCarry, false);
+                self.registers.set_flag(Flag::Carry, !carry);
+            }
+            0x76 => self.halt(mem),
+            0x40..=0x7F => {
+                let value = self.read_r8(mem, z);
+                self.write_r8(mem, y, value);
+            }
+            0x80..=0xBF => {
+                let value = self.read_r8(mem, z);
+                self.alu(y, value);
+            }
+            0xC6 | 0xCE | 0xD6 | 0xDE | 0xE6 | 0xEE | 0xF6 | 0xFE => {
+                let value = self.fetch(mem);
+                self.alu(y, value);
+            }
+            0xC0 | 0xC8 | 0xD0 | 0xD8 => {
+                self.idle(mem);
+                if self.condition(y) {
+                    self.registers.pc = self.pop(mem);
+                    self.idle(mem);
+                }
+            }
+            0xC9 => {
+                self.registers.pc = self.pop(mem);
+                self.idle(mem);
+            }
+            0xD9 => {
+                self.registers.pc = self.pop(mem);
+                self.idle(mem);
+                self.ime = true;
+            }
+            0xC1 | 0xD1 | 0xE1 => {
+                let value = self.pop(mem);
+                self.write_r16(p, value);
+            }
+            0xF1 => {
+                let value = self.pop(mem);
+                self.registers.set_af(value);
+            }
+            0xC5 | 0xD5 | 0xE5 => {
+                let value = self.read_r16(p);
+                self.push(mem, value);
+            }
+            0xF5 => self.push(mem, self.registers.af()),
+            0xC2 | 0xCA | 0xD2 | 0xDA => {
+                let address = self.fetch16(mem);
+                if self.condition(y) {
+                    self.registers.pc = address;
+                    self.idle(mem);
+                }
+            }
+            0xC3 => {
+                self.registers.pc = self.fetch16(mem);
+                self.idle(mem);
+            }
+            0xE9 => self.registers.pc = self.registers.hl(),
+            0xC4 | 0xCC | 0xD4 | 0xDC => {
+                let address = self.fetch16(mem);
+                if self.condition(y) {
+                    self.call(mem, address);
+                }
+            }
+            0xCD => {
+                let address = self.fetch16(mem);
+                self.call(mem, address);
+            }
+            0xC7 | 0xCF | 0xD7 | 0xDF | 0xE7 | 0xEF | 0xF7 | 0xFF => {
+                self.call(mem, (y as u16) * 8);
+            }
+            0xCB => {
+                let opcode = self.fetch(mem);
+                Dispatch::<M>::CB_OPCODES[opcode as usize >> 4][opcode as usize & 0x0F](self, mem);
+            }
+            0xE0 => {
+                let offset = self.fetch(mem);
+                self.write(mem, 0xFF00 | offset as u16, self.registers.a);
+            }
+            0xF0 => {
+                let offset = self.fetch(mem);
+                self.registers.a = self.read(mem, 0xFF00 | offset as u16);
+            }
+            0xE2 => self.write(mem, 0xFF00 | self.registers.c as u16, self.registers.a),
+            0xF2 => self.registers.a = self.read(mem, 0xFF00 | self.registers.c as u16),
+            0xEA => {
+                let address = self.fetch16(mem);
+                self.write(mem, address, self.registers.a);
+            }
+            0xFA => {
+                let address = self.fetch16(mem);
+                self.registers.a = self.read(mem, address);
+            }
+            0xE8 => {
+                let offset = self.fetch(mem);
+                self.registers.sp = self.add_sp_offset(offset);
+                self.idle(mem);
+                self.idle(mem);
+            }
+            0xF8 => {
+                let offset = self.fetch(mem);
+                let result = self.add_sp_offset(offset);
+                self.registers.set_hl(result);
+                self.idle(mem);
+            }
+            0xF9 => {
+                self.registers.sp = self.registers.hl();
+                self.idle(mem);
+            }
+            0xF3 => {
+                self.ime = false;
+                self.ime_scheduled = false;
+            }
+            0xFB => self.ime_scheduled = true,
+            // The remaining opcodes (0xD3, 0xDB, 0xDD, 0xE3, 0xE4, 0xEB, 0xEC, 0xED, 0xF4, 0xFC and
+            // 0xFD) don't exist and lock up the CPU until it is reset.
+            _ => self.locked = true,
+        }
+    }
+
+    #[inline(always)]
+    fn execute_cb<M: Memory>(&mut self, mem: &mut M, opcode: u8) {
+        let y = (opcode >> 3) & 0x07;
+        let z = opcode & 0x07;
+
+        match opcode {
+            0x00..=0x3F => {
+                let value = self.read_r8(mem, z);
+                let result = match y {
+                    0 => self.rlc(value),
+                    1 => self.rrc(value),
+                    2 => self.rl(value),
+                    3 => self.rr(value),
+                    4 => self.sla(value),
+                    5 => self.sra(value),
+                    6 => self.swap(value),
+                    _ => self.srl(value),
+                };
+                self.registers.set_flag(Flag::Zero, result == 0);
+                self.write_r8(mem, z, result);
+            }
+            0x40..=0x7F => {
+                let value = self.read_r8(mem, z);
+                self.registers.set_flag(Flag::Zero, value & (1 << y) == 0);
+                self.registers.set_flag(Flag::Subtract, false);
+                self.registers.set_flag(Flag::HalfCarry, true);
+            }
+            0x80..=0xBF => {
+                let value = self.read_r8(mem, z);
+                self.write_r8(mem, z, value & !(1 << y));
+            }
+            _ => {
+                let value = self.read_r8(mem, z);
+                self.write_r8(mem, z, value | (1 << y));
+            }
+        }
+    }
+
+    fn jump_relative<M: Memory>(&mut self, mem: &mut M, offset: i8) {
+        self.registers.pc = self.registers.pc.wrapping_add(offset as u16);
+        self.idle(mem);
+    }
+
+    fn call<M: Memory>(&mut self, mem: &mut M, address: u16) {
+        self.push(mem, self.registers.pc);
+        self.registers.pc = address;
+    }
+
+    fn alu(&mut self, operation: u8, value: u8) {
+        let a = self.registers.a;
+        let carry = self.registers.flag(Flag::Carry) as u8;
+
+        match operation {
+            // ADD
+            0 => {
+                let result = a.wrapping_add(value);
+                self.registers.set_flags(
+                    result == 0,
+                    false,
+                    (a & 0x0F) + (value & 0x0F) > 0x0F,
+                    a as u16 + value as u16 > 0xFF,
+                );
+                self.registers.a = result;
+            }
+            // ADC
+            1 => {
+                let result = a.wrapping_add(value).wrapping_add(carry);
+                self.registers.set_flags(
+                    result == 0,
+                    false,
+                    (a & 0x0F) + (value & 0x0F) + carry > 0x0F,
+                    a as u16 + value as u16 + carry as u16 > 0xFF,
+                );
+                self.registers.a = result;
+            }
+            // SUB
+            2 => {
+                let result = a.wrapping_sub(value);
+                self.registers
+                    .set_flags(result == 0, true, a & 0x0F < value & 0x0F, a < value);
+                self.registers.a = result;
+            }
+            // SBC
+            3 => {
+                let result = a.wrapping_sub(value).wrapping_sub(carry);
+                self.registers.set_flags(
+                    result == 0,
+                    true,
+                    a & 0x0F < (value & 0x0F) + carry,
+                    (a as u16) < value as u16 + carry as u16,
+                );
+                self.registers.a = result;
+            }
+            // AND
+            4 => {
+                let result = a & value;
+                self.registers.set_flags(result == 0, false, true, false);
+                self.registers.a = result;
+            }
+            // XOR
+            5 => {
+                let result = a ^ value;
+                self.registers.set_flags(result == 0, false, false, false);
+                self.registers.a = result;
+            }
+            // OR
+            6 => {
+                let result = a | value;
+                self.registers.set_flags(result == 0, false, false, false);
+                self.registers.a = result;
+            }
+            // CP
+            _ => {
+                self.registers
+                    .set_flags(a == value, true, a & 0x0F < value & 0x0F, a < value);
+            }
+        }
+    }
+
+    fn add_sp_offset(&mut self, offset: u8) -> u16 {
+        let sp = self.registers.sp;
+        // The flags come from the unsigned addition of the offset to the low byte of SP.
+        self.registers.set_flags(
+            false,
+            false,
+            (sp & 0x000F) + (offset as u16 & 0x000F) > 0x000F,
+            (sp & 0x00FF) + offset as u16 > 0x00FF,
+        );
+        sp.wrapping_add(offset as i8 as u16)
+    }
+
+    fn daa(&mut self) {
+        let mut a = self.registers.a;
+        let mut carry = self.registers.flag(Flag::Carry);
+        let half_carry = self.registers.flag(Flag::HalfCarry);
+
+        if self.registers.flag(Flag::Subtract) {
+            if carry {
+                a = a.wrapping_sub(0x60);
+            }
+            if half_carry {
+                a = a.wrapping_sub(0x06);
+            }
+        } else {
+            if carry || a > 0x99 {
+                a = a.wrapping_add(0x60);
+                carry = true;
+            }
+            if half_carry || a & 0x0F > 0x09 {
+                a = a.wrapping_add(0x06);
+            }
+        }
+
+        self.registers.a = a;
+        self.registers.set_flag(Flag::Zero, a == 0);
+        self.registers.set_flag(Flag::HalfCarry, false);
+        self.registers.set_flag(Flag::Carry, carry);
+    }
+
+    // The rotate and shift helpers set N, H and C; Z is left to the caller since RLCA and friends
+    // always clear it.
+    fn rlc(&mut self, value: u8) -> u8 {
+        self.set_shift_flags(value & 0x80 != 0);
+        value.rotate_left(1)
+    }
+
+    fn rrc(&mut self, value: u8) -> u8 {
+        self.set_shift_flags(value & 0x01 != 0);
+        value.rotate_right(1)
+    }
+
+    fn rl(&mut self, value: u8) -> u8 {
+        let carry = self.registers.flag(Flag::Carry) as u8;
+        self.set_shift_flags(value & 0x80 != 0);
+        (value << 1) | carry
+    }
+
+    fn rr(&mut self, value: u8) -> u8 {
+        let carry = self.registers.flag(Flag::Carry) as u8;
+        self.set_shift_flags(value & 0x01 != 0);
+        (value >> 1) | (carry << 7)
+    }
+
+    fn sla(&mut self, value: u8) -> u8 {
+        self.set_shift_flags(value & 0x80 != 0);
+        value << 1
+    }
+
+    fn sra(&mut self, value: u8) -> u8 {
+        self.set_shift_flags(value & 0x01 != 0);
+        (value >> 1) | (value & 0x80)
+    }
+
+    fn swap(&mut self, value: u8) -> u8 {
+        self.set_shift_flags(false);
+        value.rotate_left(4)
+    }
+
+    fn srl(&mut self, value: u8) -> u8 {
+        self.set_shift_flags(value & 0x01 != 0);
+        value >> 1
+    }
+
+    fn set_shift_flags(&mut self, carry: bool) {
+        self.registers.set_flag(Flag::Subtract, false);
+        self.registers.set_flag(Flag::HalfCarry, false);
+        self.registers.set_flag(Flag::Carry, carry);
+    }
+}
+
+impl Snapshot for Cpu {
+    fn component(&self) -> &'static str {
+        "CPU"
+    }
+
+    fn version(&self) -> u8 {
+        1
+    }
+
+    fn save_state(&self, writer: &mut StateWriter) {
+        self.registers.save_state(writer);
+        writer.write_bool(self.ime);
+        writer.write_bool(self.ime_scheduled);
+        writer.write_bool(self.halted);
+        writer.write_bool(self.halt_bug);
+        writer.write_bool(self.stopped);
+        writer.write_bool(self.locked);
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader) -> Result<()> {
+        self.registers.load_state(reader)?;
+        self.ime = reader.read_bool()?;
+        self.ime_scheduled = reader.read_bool()?;
+        self.halted = reader.read_bool()?;
+        self.halt_bug = reader.read_bool()?;
+        self.stopped = reader.read_bool()?;
+        self.locked = reader.read_bool()?;
+        Ok(())
+    }
+}
+
+// A handler for every opcode, indexed by its high and low nibbles. Each one is `Cpu::execute`
+// compiled for its opcode alone, so the registers and conditions its bits pick are decoded ahead of
+// time and running an instruction is a single indirect call.
+struct Dispatch<M>(PhantomData<M>);
+
+type Handler<M> = fn(&mut Cpu, &mut M);
+
+fn execute_opcode<M: Memory, const OPCODE: u8>(cpu: &mut Cpu, mem: &mut M) {
+    cpu.execute(mem, OPCODE);
+}
+
+fn execute_cb_opcode<M: Memory, const OPCODE: u8>(cpu: &mut Cpu, mem: &mut M) {
+    cpu.execute_cb(mem, OPCODE);
+}
+
+macro_rules! handlers {
+    ($handler:ident) => {
+        [
+            handlers!(@row $handler 0x0),
+            handlers!(@row $handler 0x1),
+            handlers!(@row $handler 0x2),
+            handlers!(@row $handler 0x3),
+            handlers!(@row $handler 0x4),
+            handlers!(@row $handler 0x5),
+            handlers!(@row $handler 0x6),
+            handlers!(@row $handler 0x7),
+            handlers!(@row $handler 0x8),
+            handlers!(@row $handler 0x9),
+            handlers!(@row $handler 0xA),
+            handlers!(@row $handler 0xB),
+            handlers!(@row $handler 0xC),
+            handlers!(@row $handler 0xD),
+            handlers!(@row $handler 0xE),
+            handlers!(@row $handler 0xF),
+        ]
+    };
+    (@row $handler:ident $high:literal) => {
+        [
+            $handler::<M, { $high << 4 }>,
+            $handler::<M, { $high << 4 | 0x1 }>,
+            $handler::<M, { $high << 4 | 0x2 }>,
+            $handler::<M, { $high << 4 | 0x3 }>,
+            $handler::<M, { $high << 4 | 0x4 }>,
+            $handler::<M, { $high << 4 | 0x5 }>,
+            $handler::<M, { $high << 4 | 0x6 }>,
+            $handler::<M, { $high << 4 | 0x7 }>,
+            $handler::<M, { $high << 4 | 0x8 }>,
+            $handler::<M, { $high << 4 | 0x9 }>,
+            $handler::<M, { $high << 4 | 0xA }>,
+            $handler::<M, { $high << 4 | 0xB }>,
+            $handler::<M, { $high << 4 | 0xC }>,
+            $handler::<M, { $high << 4 | 0xD }>,
+            $handler::<M, { $high << 4 | 0xE }>,
+            $handler::<M, { $high << 4 | 0xF }>,
+        ]
+    };
+}
+
+impl<M: Memory> Dispatch<M> {
+    const OPCODES: [[Handler<M>; 16]; 16] = handlers!(execute_opcode);
+
+    const CB_OPCODES: [[Handler<M>; 16]; 16] = handlers!(execute_cb_opcode);
+}
+
+const SPEED_SWITCH_CYCLES: u32 = 2050 * 4;
+
+#[cfg(test)]
+mod tests {
+    use rstest::rstest;
+
+    use super::*;
+
+    // A, F, B, C, D, E, H, L
+    #[rstest]
+    #[case(EmulatorModel::Dmg, false, [0x01, 0xB0, 0x00, 0x13, 0x00, 0xD8, 0x01, 0x4D])]
+    #[case(EmulatorModel::Mgb, false, [0xFF, 0xB0, 0x00, 0x13, 0x00, 0xD8, 0x01, 0x4D])]
+    #[case(EmulatorModel::Sgb, false, [0x01, 0x00, 0x00, 0x14, 0x00, 0x00, 0xC0, 0x60])]
+    #[case(EmulatorModel::Cgb, true, [0x11, 0x80, 0x00, 0x00, 0xFF, 0x56, 0x00, 0x0D])]
+    #[case(EmulatorModel::Cgb, false, [0x11, 0x80, 0x00, 0x00, 0x00, 0x08, 0x00, 0x7C])]
+    fn test_for_model(
+        #[case] model: EmulatorModel,
+        #[case] cgb_mode: bool,
+        #[case] expected: [u8; 8],
+    ) {
+        let registers = Cpu::for_model(model, cgb_mode).registers;
+
+        assert_eq!(
+            [
+                registers.a,
+                registers.f,
+                registers.b,
+                registers.c,
+                registers.d,
+                registers.e,
+                registers.h,
+                registers.l
+            ],
+            expected
+        );
+        assert_eq!(registers.sp, 0xFFFE);
+        assert_eq!(registers.pc, 0x0100);
+    }
+
+    struct TestMemory {
+        data: Vec<u8>,
+        ticks: u32,
+    }
+
+    impl TestMemory {
+        fn with_program(program: &[u8]) -> TestMemory {
+            let mut data = vec![0; 0x10000];
+            data[0x0100..0x0100 + program.len()].copy_from_slice(program);
+            TestMemory { data, ticks: 0 }
+        }
+    }
+
+    impl Memory for TestMemory {
+        fn read(&mut self, address: u16) -> u8 {
+            self.data[address as usize]
+        }
+
+        fn write(&mut self, address: u16, value: u8) {
+            self.data[address as usize] = value;
+        }
+
+        fn tick(&mut self, cycles: u32) {
+            self.ticks += cycles;
+        }
+    }
+
+    fn cpu_with_flags(f: u8) -> Cpu {
+        let mut cpu = Cpu::new();
+        cpu.registers.f = f;
+        cpu
+    }
+
+    #[test]
+    fn test_register_pairs() {
+        // Arrange
+        let mut registers = Registers::default();
+
+        // Act
+        registers.set_af(0x12FF);
+        registers.set_bc(0x3456);
+        registers.set_de(0x789A);
+        registers.set_hl(0xBCDE);
+
+        // Assert
+        assert_eq!(registers.af(), 0x12F0);
+        assert_eq!((registers.b, registers.c), (0x34, 0x56));
+        assert_eq!(registers.de(), 0x789A);
+        assert_eq!(registers.hl(), 0xBCDE);
+    }
+
+    #[rstest]
+    #[case(&[0x00], 4)]
+    #[case(&[0x01, 0x34, 0x12], 12)]
+    #[case(&[0x03], 8)]
+    #[case(&[0x06, 0x42], 8)]
+    #[case(&[0x08, 0x00, 0xC0], 20)]
+    #[case(&[0x09], 8)]
+    #[case(&[0x18, 0x05], 12)]
+    #[case(&[0x20, 0x05], 8)]
+    #[case(&[0x28, 0x05], 12)]
+    #[case(&[0x34], 12)]
+    #[case(&[0x36, 0x42], 12)]
+    #[case(&[0x46], 8)]
+    #[case(&[0x86], 8)]
+    #[case(&[0xC0], 8)]
+    #[case(&[0xC1], 12)]
+    #[case(&[0xC2, 0x00, 0x02], 12)]
+    #[case(&[0xC3, 0x00, 0x02], 16)]
+    #[case(&[0xC4, 0x00, 0x02], 12)]
+    #[case(&[0xC5], 16)]
+    #[case(&[0xC8], 20)]
+    #[case(&[0xC9], 16)]
+    #[case(&[0xCC, 0x00, 0x02], 24)]
+    #[case(&[0xCD, 0x00, 0x02], 24)]
+    #[case(&[0xCF], 16)]
+    #[case(&[0xCB, 0x11], 8)]
+    #[case(&[0xCB, 0x46], 12)]
+    #[case(&[0xCB, 0x86], 16)]
+    #[case(&[0xE0, 0x80], 12)]
+    #[case(&[0xE2], 8)]
+    #[case(&[0xE8, 0x01], 16)]
+    #[case(&[0xE9], 4)]
+    #[case(&[0xEA, 0x00, 0xC0], 16)]
+    #[case(&[0xF8, 0x01], 12)]
+    #[case(&[0xF9], 8)]
+    fn test_step_cycles(#[case] program: &[u8], #[case] expected_cycles: u32) {
+        // Arrange
+        let mut mem = TestMemory::with_program(program);
+        let mut cpu = cpu_with_flags(0x80);
+
+        // Act
+        let cycles = cpu.step(&mut mem);
+
+        // Assert
+        assert_eq!(cycles, expected_cycles);
+        assert_eq!(mem.ticks, expected_cycles);
+    }
+
+    #[test]
+    fn test_ld_r8_r8() {
+        let mut mem = TestMemory::with_program(&[0x06, 0x42, 0x48, 0x71]);
+        let mut cpu = Cpu::new();
+        cpu.registers.set_hl(0xC000);
+
+        cpu.step(&mut mem);
+        cpu.step(&mut mem);
+        cpu.step(&mut mem);
+
+        assert_eq!(cpu.registers.b, 0x42);
+        assert_eq!(cpu.registers.c, 0x42);
+        assert_eq!(mem.data[0xC000], 0x42);
+        assert_eq!(cpu.registers.pc, 0x0104);
+    }
+
+    #[test]
+    fn test_ld_hl_increment_and_decrement() {
+        let mut mem = TestMemory::with_program(&[0x22, 0x3A]);
+        let mut cpu = Cpu::new();
+        cpu.registers.a = 0x99;
+        cpu.registers.set_hl(0xC000);
+
+        cpu.step(&mut mem);
+        assert_eq!(mem.data[0xC000], 0x99);
+        assert_eq!(cpu.registers.hl(), 0xC001);
+
+        cpu.step(&mut mem);
+        assert_eq!(cpu.registers.hl(), 0xC000);
+    }
+
+    #[test]
+    fn test_ld_a16_sp() {
+        let mut mem = TestMemory::with_program(&[0x08, 0x00, 0xC0]);
+        let mut cpu = Cpu::new();
+        cpu.registers.sp = 0xABCD;
+
+        cpu.step(&mut mem);
+
+        assert_eq!(mem.data[0xC000], 0xCD);
+        assert_eq!(mem.data[0xC001], 0xAB);
+    }
+
+    #[rstest]
+    // ADD
+    #[case(0x80, 0x3A, 0xC6, 0x00, 0x00, 0xB0)]
+    #[case(0x80, 0x0F, 0x01, 0x00, 0x10, 0x20)]
+    #[case(0x80, 0xFF, 0x01, 0x00, 0x00, 0xB0)]
+    // ADC
+    #[case(0x88, 0xE1, 0x0F, 0x10, 0xF1, 0x20)]
+    #[case(0x88, 0xE1, 0x3B, 0x10, 0x1D, 0x10)]
+    #[case(0x88, 0xE1, 0x1E, 0x10, 0x00, 0xB0)]
+    // SUB
+    #[case(0x90, 0x3E, 0x3E, 0x00, 0x00, 0xC0)]
+    #[case(0x90, 0x3E, 0x0F, 0x00, 0x2F, 0x60)]
+    #[case(0x90, 0x3E, 0x40, 0x00, 0xFE, 0x50)]
+    // SBC
+    #[case(0x98, 0x3B, 0x2A, 0x10, 0x10, 0x40)]
+    #[case(0x98, 0x3B, 0x3A, 0x10, 0x00, 0xC0)]
+    #[case(0x98, 0x3B, 0x4F, 0x10, 0xEB, 0x70)]
+    // AND
+    #[case(0xA0, 0x5A, 0x3F, 0x00, 0x1A, 0x20)]
+    #[case(0xA0, 0x5A, 0x00, 0x00, 0x00, 0xA0)]
+    // XOR
+    #[case(0xA8, 0xFF, 0xFF, 0x00, 0x00, 0x80)]
+    #[case(0xA8, 0xFF, 0x0F, 0x00, 0xF0, 0x00)]
+    // OR
+    #[case(0xB0, 0x5A, 0x03, 0x00, 0x5B, 0x00)]
+    #[case(0xB0, 0x00, 0x00, 0x00, 0x00, 0x80)]
+    // CP
+    #[case(0xB8, 0x3C, 0x2F, 0x00, 0x3C, 0x60)]
+    #[case(0xB8, 0x3C, 0x3C, 0x00, 0x3C, 0xC0)]
+    #[case(0xB8, 0x3C, 0x40, 0x00, 0x3C, 0x50)]
+    fn test_alu(
+        #[case] opcode: u8,
+        #[case] a: u8,
+        #[case] b: u8,
+        #[case] f: u8,
+        #[case] expected_a: u8,
+        #[case] expected_f: u8,
+    ) {
+        // Arrange
+        let mut mem = TestMemory::with_program(&[opcode]);
+        let mut cpu = cpu_with_flags(f);
+        cpu.registers.a = a;
+        cpu.registers.b = b;
+
+        // Act
+        cpu.step(&mut mem);
+
+        // Assert
+        assert_eq!(cpu.registers.a, expected_a);
+        assert_eq!(cpu.registers.f, expected_f);
+    }
+
+    #[rstest]
+    #[case(0x04, 0xFF, 0x10, 0x00, 0xB0)]
+    #[case(0x04, 0x0F, 0x00, 0x10, 0x20)]
+    #[case(0x04, 0x41, 0x10, 0x42, 0x10)]
+    #[case(0x05, 0x01, 0x00, 0x00, 0xC0)]
+    #[case(0x05, 0x00, 0x00, 0xFF, 0x60)]
+    #[case(0x05, 0x10, 0x10, 0x0F, 0x70)]
+    fn test_inc_dec_r8(
+        #[case] opcode: u8,
+        #[case] b: u8,
+        #[case] f: u8,
+        #[case] expected_b: u8,
+        #[case] expected_f: u8,
+    ) {
+        let mut mem = TestMemory::with_program(&[opcode]);
+        let mut cpu = cpu_with_flags(f);
+        cpu.registers.b = b;
+
+        cpu.step(&mut mem);
+
+        assert_eq!(cpu.registers.b, expected_b);
+        assert_eq!(cpu.registers.f, expected_f);
+    }
+
+    #[test]
+    fn test_inc_dec_r16_leave_flags() {
+        let mut mem = TestMemory::with_program(&[0x03, 0x1B]);
+        let mut cpu = cpu_with_flags(0xF0);
+        cpu.registers.set_bc(0xFFFF);
+        cpu.registers.set_de(0x0000);
+
+        cpu.step(&mut mem);
+        cpu.step(&mut mem);
+
+        assert_eq!(cpu.registers.bc(), 0x0000);
+        assert_eq!(cpu.registers.de(), 0xFFFF);
+        assert_eq!(cpu.registers.f, 0xF0);
+    }
+
+    #[rstest]
+    #[case(0x8A23, 0x0605, 0x00, 0x9028, 0x20)]
+    #[case(0x8A23, 0x8A23, 0x80, 0x1446, 0xB0)]
+    #[case(0x0001, 0x0001, 0x40, 0x0002, 0x00)]
+    fn test_add_hl(
+        #[case] hl: u16,
+        #[case] bc: u16,
+        #[case] f: u8,
+        #[case] expected_hl: u16,
+        #[case] expected_f: u8,
+    ) {
+        let mut mem = TestMemory::with_program(&[0x09]);
+        let mut cpu = cpu_with_flags(f);
+        cpu.registers.set_hl(hl);
+        cpu.registers.set_bc(bc);
+
+        cpu.step(&mut mem);
+
+        assert_eq!(cpu.registers.hl(), expected_hl);
+        assert_eq!(cpu.registers.f, expected_f);
+    }
+
+    #[rstest]
+    #[case(0xE8, 0xFFF8, 0x02, 0xFFFA, 0x00)]
+    #[case(0xE8, 0x000F, 0x01, 0x0010, 0x20)]
+    #[case(0xE8, 0x00FF, 0x01, 0x0100, 0x30)]
+    #[case(0xE8, 0x0000, 0xFF, 0xFFFF, 0x00)]
+    #[case(0xE8, 0x0001, 0xFF, 0x0000, 0x30)]
+    #[case(0xF8, 0xFFF8, 0x02, 0xFFFA, 0x00)]
+    #[case(0xF8, 0x0001, 0xFF, 0x0000, 0x30)]
+    fn test_sp_offset(
+        #[case] opcode: u8,
+        #[case] sp: u16,
+        #[case] offset: u8,
+        #[case] expected: u16,
+        #[case] expected_f: u8,
+    ) {
+        let mut mem = TestMemory::with_program(&[opcode, offset]);
+        let mut cpu = cpu_with_flags(0xC0);
+        cpu.registers.sp = sp;
+
+        cpu.step(&mut mem);
+
+        let actual = if opcode == 0xE8 {
+            cpu.registers.sp
+        } else {
+            cpu.registers.hl()
+        };
+        assert_eq!(actual, expected);
+        assert_eq!(cpu.registers.f, expected_f);
+    }
+
+    #[rstest]
+    // After addition.
+    #[case(0x45, 0x00, 0x45, 0x00)]
+    #[case(0x0A, 0x00, 0x10, 0x00)]
+    #[case(0x9A, 0x00, 0x00, 0x90)]
+    #[case(0x00, 0x10, 0x60, 0x10)]
+    #[case(0x03, 0x20, 0x09, 0x00)]
+    #[case(0xA0, 0x00, 0x00, 0x90)]
+    // After subtraction.
+    #[case(0x45, 0x40, 0x45, 0x40)]
+    #[case(0x0F, 0x60, 0x09, 0x40)]
+    #[case(0xA0, 0x50, 0x40, 0x50)]
+    #[case(0x00, 0x40, 0x00, 0xC0)]
+    fn test_daa(#[case] a: u8, #[case] f: u8, #[case] expected_a: u8, #[case] expected_f: u8) {
+        let mut mem = TestMemory::with_program(&[0x27]);
+        let mut cpu = cpu_with_flags(f);
+        cpu.registers.a = a;
+
+        cpu.step(&mut mem);
+
+        assert_eq!(cpu.registers.a, expected_a);
+        assert_eq!(cpu.registers.f, expected_f);
+    }
+
+    // Runs `program` with B as the operand and returns A and F.
+    fn run_with_b(program: &[u8], a: u8, b: u8, f: u8) -> (u8, u8) {
+        let mut mem = TestMemory::with_program(program);
+        let mut cpu = cpu_with_flags(f);
+        cpu.registers.a = a;
+        cpu.registers.b = b;
+        for _ in program {
+            cpu.step(&mut mem);
+        }
+        (cpu.registers.a, cpu.registers.f)
+    }
+
+    fn flags(zero: bool, subtract: bool, half_carry: bool, carry: bool) -> u8 {
+        ((zero as u8) << 7)
+            | ((subtract as u8) << 6)
+            | ((half_carry as u8) << 5)
+            | ((carry as u8) << 4)
+    }
+
+    // A reference model for the flags that works on the full-width sum instead of nibbles: bit 4
+    // of a ^ b ^ result is the carry or borrow into it.
+    fn add_model(a: u8, b: u8, carry: bool) -> (u8, u8) {
+        let sum = a as u16 + b as u16 + carry as u16;
+        let result = sum as u8;
+        let half_carry = (a ^ b ^ result) & 0x10 != 0;
+        (result, flags(result == 0, false, half_carry, sum > 0xFF))
+    }
+
+    fn sub_model(a: u8, b: u8, carry: bool) -> (u8, u8) {
+        let difference = a as i16 - b as i16 - carry as i16;
+        let result = difference as u8;
+        let half_carry = (a ^ b ^ result) & 0x10 != 0;
+        (result, flags(result == 0, true, half_carry, difference < 0))
+    }
+
+    fn bcd(value: u8) -> u8 {
+        ((value / 10) << 4) | (value % 10)
+    }
+
+    // Every A, B and carry that can go in. The other flags are set to show they're ignored.
+    fn alu_inputs() -> impl Iterator<Item = (u8, u8, bool, u8)> {
+        (0..=0xFFFF_u32).flat_map(|ab| {
+            let (a, b) = ((ab >> 8) as u8, ab as u8);
+            [false, true].map(|carry| (a, b, carry, flags(true, true, true, carry)))
+        })
+    }
+
+    // Every pair of BCD numbers, with and without carry.
+    fn bcd_inputs() -> impl Iterator<Item = (u8, u8, bool)> {
+        (0..100_u8)
+            .flat_map(|x| (0..100_u8).flat_map(move |y| [false, true].map(|carry| (x, y, carry))))
+    }
+
+    #[test]
+    fn test_add_flags() {
+        for (a, b, _, f) in alu_inputs() {
+            assert_eq!(run_with_b(&[0x80], a, b, f), add_model(a, b, false));
+        }
+    }
+
+    #[test]
+    fn test_adc_flags() {
+        for (a, b, carry, f) in alu_inputs() {
+            assert_eq!(run_with_b(&[0x88], a, b, f), add_model(a, b, carry));
+        }
+    }
+
+    #[test]
+    fn test_sub_flags() {
+        for (a, b, _, f) in alu_inputs() {
+            assert_eq!(run_with_b(&[0x90], a, b, f), sub_model(a, b, false));
+        }
+    }
+
+    #[test]
+    fn test_sbc_flags() {
+        for (a, b, carry, f) in alu_inputs() {
+            assert_eq!(run_with_b(&[0x98], a, b, f), sub_model(a, b, carry));
+        }
+    }
+
+    #[test]
+    fn test_cp_flags() {
+        for (a, b, _, f) in alu_inputs() {
+            let (_, expected_f) = sub_model(a, b, false);
+            assert_eq!(run_with_b(&[0xB8], a, b, f), (a, expected_f));
+        }
+    }
+
+    // After adding or subtracting two BCD numbers, DAA has to leave their decimal sum or
+    // difference with carry set when it wrapped past 99 or below 0.
+    #[test]
+    fn test_daa_after_add() {
+        for (x, y, carry) in bcd_inputs() {
+            let opcode = if carry { 0x88 } else { 0x80 };
+            let f = flags(false, false, false, carry);
+            let sum = x + y + carry as u8;
+            let decimal = sum % 100;
+
+            let (a, f) = run_with_b(&[opcode, 0x27], bcd(x), bcd(y), f);
+
+            assert_eq!(a, bcd(decimal));
+            assert_eq!(f, flags(decimal == 0, false, false, sum >= 100));
+        }
+    }
+
+    #[test]
+    fn test_daa_after_sub() {
+        for (x, y, carry) in bcd_inputs() {
+            let opcode = if carry { 0x98 } else { 0x90 };
+            let f = flags(false, false, false, carry);
+            let difference = x as i16 - y as i16 - carry as i16;
+            let decimal = difference.rem_euclid(100) as u8;
+
+            let (a, f) = run_with_b(&[opcode, 0x27], bcd(x), bcd(y), f);
+
+            assert_eq!(a, bcd(decimal));
+            assert_eq!(f, flags(decimal == 0, true, false, difference < 0));
+        }
+    }
+
+    #[rstest]
+    #[case(0x2F, 0x35, 0x00, 0xCA, 0x60)]
+    #[case(0x37, 0x35, 0xE0, 0x35, 0x90)]
+    #[case(0x3F, 0x35, 0x70, 0x35, 0x00)]
+    #[case(0x3F, 0x35, 0x00, 0x35, 0x10)]
+    #[case(0x07, 0x85, 0x80, 0x0B, 0x10)]
+    #[case(0x0F, 0x01, 0x80, 0x80, 0x10)]
+    #[case(0x17, 0x95, 0x10, 0x2B, 0x10)]
+    #[case(0x1F, 0x81, 0x00, 0x40, 0x10)]
+    fn test_accumulator_ops(
+        #[case] opcode: u8,
+        #[case] a: u8,
+        #[case] f: u8,
+        #[case] expected_a: u8,
+        #[case] expected_f: u8,
+    ) {
+        let mut mem = TestMemory::with_program(&[opcode]);
+        let mut cpu = cpu_with_flags(f);
+        cpu.registers.a = a;
+
+        cpu.step(&mut mem);
+
+        assert_eq!(cpu.registers.a, expected_a);
+        assert_eq!(cpu.registers.f, expected_f);
+    }
+
+    #[rstest]
+    #[case(0x00, 0x85, 0x00, 0x0B, 0x10)]
+    #[case(0x00, 0x00, 0x00, 0x00, 0x80)]
+    #[case(0x08, 0x01, 0x00, 0x80, 0x10)]
+    #[case(0x10, 0x80, 0x00, 0x00, 0x90)]
+    #[case(0x18, 0x01, 0x10, 0x80, 0x10)]
+    #[case(0x20, 0x80, 0x00, 0x00, 0x90)]
+    #[case(0x28, 0x8A, 0x00, 0xC5, 0x00)]
+    #[case(0x30, 0xF0, 0x10, 0x0F, 0x00)]
+    #[case(0x30, 0x00, 0x10, 0x00, 0x80)]
+    #[case(0x38, 0xFF, 0x00, 0x7F, 0x10)]
+    fn test_cb_shifts(
+        #[case] opcode: u8,
+        #[case] b: u8,
+        #[case] f: u8,
+        #[case] expected_b: u8,
+        #[case] expected_f: u8,
+    ) {
+        let mut mem = TestMemory::with_program(&[0xCB, opcode]);
+        let mut cpu = cpu_with_flags(f);
+        cpu.registers.b = b;
+
+        cpu.step(&mut mem);
+
+        assert_eq!(cpu.registers.b, expected_b);
+        assert_eq!(cpu.registers.f, expected_f);
+    }
+
+    #[test]
+    fn test_cb_bit_res_set() {
+        let mut mem = TestMemory::with_program(&[0xCB, 0x7E, 0xCB, 0xFE, 0xCB, 0x7E, 0xCB, 0x86]);
+        let mut cpu = cpu_with_flags(0x10);
+        cpu.registers.set_hl(0xC000);
+        mem.data[0xC000] = 0x01;
+
+        cpu.step(&mut mem);
+        assert_eq!(cpu.registers.f, 0xB0);
+
+        cpu.step(&mut mem);
+        assert_eq!(mem.data[0xC000], 0x81);
+
+        cpu.step(&mut mem);
+        assert_eq!(cpu.registers.f, 0x30);
+
+        cpu.step(&mut mem);
+        assert_eq!(mem.data[0xC000], 0x80);
+    }
+
+    #[test]
+    fn test_push_pop() {
+        let mut mem = TestMemory::with_program(&[0xC5, 0xF1]);
+        let mut cpu = Cpu::new();
+        cpu.registers.set_bc(0x12FF);
+        cpu.registers.sp = 0xD000;
+
+        cpu.step(&mut mem);
+        assert_eq!(cpu.registers.sp, 0xCFFE);
+        assert_eq!(mem.data[0xCFFF], 0x12);
+        assert_eq!(mem.data[0xCFFE], 0xFF);
+
+        cpu.step(&mut mem);
+        assert_eq!(cpu.registers.af(), 0x12F0);
+        assert_eq!(cpu.registers.sp, 0xD000);
+    }
+
+    #[test]
+    fn test_call_and_ret() {
+        let mut mem = TestMemory::with_program(&[0xCD, 0x00, 0x02]);
+        mem.data[0x0200] = 0xC9;
+        let mut cpu = Cpu::new();
+
+        cpu.step(&mut mem);
+        assert_eq!(cpu.registers.pc, 0x0200);
+        assert_eq!(cpu.registers.sp, 0xFFFC);
+
+        cpu.step(&mut mem);
+        assert_eq!(cpu.registers.pc, 0x0103);
+        assert_eq!(cpu.registers.sp, 0xFFFE);
+    }
+
+    #[rstest]
+    #[case(0xC7, 0x0000)]
+    #[case(0xCF, 0x0008)]
+    #[case(0xD7, 0x0010)]
+    #[case(0xDF, 0x0018)]
+    #[case(0xE7, 0x0020)]
+    #[case(0xEF, 0x0028)]
+    #[case(0xF7, 0x0030)]
+    #[case(0xFF, 0x0038)]
+    fn test_rst(#[case] opcode: u8, #[case] expected_pc: u16) {
+        let mut mem = TestMemory::with_program(&[opcode]);
+        let mut cpu = Cpu::new();
+
+        cpu.step(&mut mem);
+
+        assert_eq!(cpu.registers.pc, expected_pc);
+        assert_eq!(mem.data[0xFFFC], 0x01);
+        assert_eq!(mem.data[0xFFFD], 0x01);
+    }
+
+    #[test]
+    fn test_jr_backwards() {
+        let mut mem = TestMemory::with_program(&[0x18, 0xFE]);
+        let mut cpu = Cpu::new();
+
+        cpu.step(&mut mem);
+
+        assert_eq!(cpu.registers.pc, 0x0100);
+    }
+
+    #[test]
+    fn test_ldh() {
+        let mut mem = TestMemory::with_program(&[0xE0, 0x80, 0xF2]);
+        let mut cpu = Cpu::new();
+        cpu.registers.a = 0x5A;
+        cpu.registers.c = 0x81;
+        mem.data[0xFF81] = 0xA5;
+
+        cpu.step(&mut mem);
+        assert_eq!(mem.data[0xFF80], 0x5A);
+
+        cpu.step(&mut mem);
+        assert_eq!(cpu.registers.a, 0xA5);
+    }
+
+    #[test]
+    fn test_di_ei_reti() {
+        let mut mem = TestMemory::with_program(&[0xFB, 0x00, 0xF3, 0xD9]);
+        let mut cpu = Cpu::new();
+        cpu.registers.sp = 0xD000;
+        mem.data[0xD000] = 0x34;
+        mem.data[0xD001] = 0x12;
+
+        cpu.step(&mut mem);
+        assert!(!cpu.ime());
+
+        cpu.step(&mut mem);
+        assert!(cpu.ime());
+
+        cpu.step(&mut mem);
+        assert!(!cpu.ime());
+
+        cpu.step(&mut mem);
+        assert!(cpu.ime());
+        assert_eq!(cpu.registers.pc, 0x1234);
+    }
+
+    #[test]
+    fn test_ei_di_cancels_enable() {
+        let mut mem = TestMemory::with_program(&[0xFB, 0xF3, 0x00]);
+        let mut cpu = Cpu::new();
+
+        cpu.step(&mut mem);
+        cpu.step(&mut mem);
+        cpu.step(&mut mem);
+
+        assert!(!cpu.ime());
+    }
+
+    #[test]
+    fn test_halt() {
+        let mut mem = TestMemory::with_program(&[0x76, 0x00]);
+        let mut cpu = Cpu::new();
+
+        cpu.step(&mut mem);
+        let cycles = cpu.step(&mut mem);
+
+        assert!(cpu.halted());
+        assert_eq!(cycles, 4);
+        assert_eq!(cpu.registers.pc, 0x0101);
+    }
+
+    #[rstest]
+    #[case(0x01, 0x0040)]
+    #[case(0x04, 0x0050)]
+    #[case(0x18, 0x0058)]
+    fn test_interrupt_dispatch(#[case] flag: u8, #[case] expected_pc: u16) {
+        // Arrange
+        let mut mem = TestMemory::with_program(&[0xFB, 0x00, 0x00]);
+        let mut cpu = Cpu::new();
+        cpu.registers.sp = 0xD000;
+        mem.data[0xFFFF] = 0x1F;
+        mem.data[0xFF0F] = flag;
+
+        // Act
+        cpu.step(&mut mem);
+        cpu.step(&mut mem);
+        let cycles = cpu.step(&mut mem);
+
+        // Assert
+        assert_eq!(cycles, 20);
+        assert_eq!(cpu.registers.pc, expected_pc);
+        assert_eq!(cpu.registers.sp, 0xCFFE);
+        assert_eq!(mem.data[0xCFFE], 0x02);
+        assert_eq!(mem.data[0xCFFF], 0x01);
+        assert_eq!(mem.data[0xFF0F], flag & (flag - 1));
+        assert!(!cpu.ime());
+    }
+
+    #[test]
+    fn test_disabled_interrupt_is_not_dispatched() {
+        let mut mem = TestMemory::with_program(&[0xFB, 0x00, 0x00]);
+        let mut cpu = Cpu::new();
+        mem.data[0xFFFF] = 0x01;
+        mem.data[0xFF0F] = 0x04;
+
+        cpu.step(&mut mem);
+        cpu.step(&mut mem);
+        cpu.step(&mut mem);
+
+        assert_eq!(cpu.registers.pc, 0x0103);
+    }
+
+    #[test]
+    fn test_ie_overwritten_during_dispatch_jumps_to_zero() {
+        let mut mem = TestMemory::with_program(&[0xFB, 0x00, 0x00]);
+        let mut cpu = Cpu::new();
+        cpu.registers.sp = 0x0000;
+        // Pushing the high byte of PC (0x01) into IE disables the requested STAT interrupt.
+        mem.data[0xFFFF] = 0x02;
+        mem.data[0xFF0F] = 0x02;
+
+        cpu.step(&mut mem);
+        cpu.step(&mut mem);
+        cpu.step(&mut mem);
+
+        assert_eq!(cpu.registers.pc, 0x0000);
+        assert_eq!(mem.data[0xFF0F], 0x02);
+    }
+
+    #[test]
+    fn test_halt_wakes_on_interrupt_without_ime() {
+        let mut mem = TestMemory::with_program(&[0x76, 0x3C]);
+        let mut cpu = Cpu::new();
+        let a = cpu.registers.a;
+        mem.data[0xFFFF] = 0x04;
+
+        cpu.step(&mut mem);
+        cpu.step(&mut mem);
+        assert!(cpu.halted());
+
+        mem.data[0xFF0F] = 0x04;
+        cpu.step(&mut mem);
+
+        assert!(!cpu.halted());
+        assert_eq!(cpu.registers.a, a.wrapping_add(1));
+        assert_eq!(mem.data[0xFF0F], 0x04);
+    }
+
+    #[test]
+    fn test_halt_wakes_and_dispatches_with_ime() {
+        let mut mem = TestMemory::with_program(&[0xFB, 0x76, 0x00]);
+        let mut cpu = Cpu::new();
+        cpu.registers.sp = 0xD000;
+        mem.data[0xFFFF] = 0x01;
+
+        cpu.step(&mut mem);
+        cpu.step(&mut mem);
+        mem.data[0xFF0F] = 0x01;
+        let cycles = cpu.step(&mut mem);
+
+        assert_eq!(cycles, 24);
+        assert_eq!(cpu.registers.pc, 0x0040);
+        assert_eq!(mem.data[0xCFFE], 0x02);
+    }
+
+    #[test]
+    fn test_halt_bug_repeats_next_byte() {
+        // HALT; INC A with an interrupt pending and IME off runs INC A twice.
+        let mut mem = TestMemory::with_program(&[0x76, 0x3C, 0x00]);
+        let mut cpu = Cpu::new();
+        let a = cpu.registers.a;
+        mem.data[0xFFFF] = 0x01;
+        mem.data[0xFF0F] = 0x01;
+
+        cpu.step(&mut mem);
+        cpu.step(&mut mem);
+        cpu.step(&mut mem);
+
+        assert!(!cpu.halted());
+        assert_eq!(cpu.registers.a, a.wrapping_add(2));
+        assert_eq!(cpu.registers.pc, 0x0102);
+    }
+
+    #[test]
+    fn test_illegal_opcode_locks_cpu() {
+        let mut mem = TestMemory::with_program(&[0xD3, 0x3C]);
+        let mut cpu = Cpu::new();
+        let a = cpu.registers.a;
+
+        cpu.step(&mut mem);
+        cpu.step(&mut mem);
+
+        assert_eq!(cpu.registers.a, a);
+        assert_eq!(cpu.registers.pc, 0x0101);
+    }
+}
@@ -0,0 +1,191 @@
+use std::fs;
+use std::io::{Error, Result};
+use std::path::Path;
+
+use crate::rom::Rom;
+
+// A ROM database in the Logiqx XML format No-Intro's DAT files use. No database ships with the
+// emulator, since No-Intro's can't be redistributed, so it has to be downloaded from them.
+pub struct Database {
+    entries: Vec<Entry>,
+}
+
+// A known good dump.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Entry {
+    // The canonical name, like "Tetris (World) (Rev 1)".
+    pub name: String,
+    pub size: Option<usize>,
+    pub crc32: Option<u32>,
+    pub sha1: Option<[u8; 20]>,
+    // The dump was checked against more than one cartridge.
+    pub verified: bool,
+}
+
+impl Database {
+    pub fn load(path: &Path) -> Result<Database> {
+        Database::parse(&fs::read_to_string(path)?)
+            .map_err(|error| Error::other(format!("{}: {}", path.display(), error)))
+    }
+
+    pub fn parse(xml: &str) -> Result<Database> {
+        let mut entries = Vec::new();
+        let mut game = None;
+        for tag in tags(xml) {
+            let (name, attributes) = tag.split_once(char::is_whitespace).unwrap_or((tag, ""));
+            match name {
+                "game" | "machine" => game = attribute(attributes, "name"),
+                "/game" | "/machine" => game = None,
+                "rom" => {
+                    let Some(game) = &game else {
+                        continue;
+                    };
+                    let value = |name| attribute(attributes, name);
+                    entries.push(Entry {
+                        name: game.clone(),
+                        size: value("size").and_then(|size| size.parse().ok()),
+                        crc32: value("crc").and_then(|crc| u32::from_str_radix(&crc, 16).ok()),
+                        sha1: value("sha1").and_then(|sha1| parse_sha1(&sha1)),
+                        verified: value("status").as_deref() == Some("verified"),
+                    });
+                }
+                _ => {}
+            }
+        }
+        if entries.is_empty() {
+            return Err(Error::other(
+                "There are no games in it, expected a Logiqx XML DAT like No-Intro's.",
+            ));
+        }
+        Ok(Database { entries })
+    }
+
+    pub fn entries(&self) -> &[Entry] {
+        &self.entries
+    }
+
+    // The entry with the ROM's SHA-1, or its CRC-32 and size for entries without one.
+    pub fn lookup(&self, rom: &Rom) -> Option<&Entry> {
+        let (crc32, sha1, size) = (rom.crc32(), rom.sha1(), rom.content().len());
+        self.entries.iter().find(|entry| match entry.sha1 {
+            Some(entry_sha1) => entry_sha1 == sha1,
+            None => entry.crc32 == Some(crc32) && entry.size.is_none_or(|entry| entry == size),
+        })
+    }
+}
+
+// What's between each < and >, skipping comments and declarations.
+fn tags(xml: &str) -> impl Iterator<Item = &str> {
+    xml.split('<')
+        .skip(1)
+        .filter_map(|tag| {
+            tag.split_once('>')
+                .map(|(tag, _)| tag.trim_end_matches('/'))
+        })
+        .filter(|tag| !tag.starts_with(['!', '?']))
+}
+
+fn attribute(attributes: &str, name: &str) -> Option<String> {
+    let mut rest = attributes;
+    while let Some((key, value)) = rest.split_once('=') {
+        let value = value.trim_start();
+        let quote = value
+            .chars()
+            .next()
+            .filter(|&quote| quote == '"' || quote == '\'')?;
+        let (value, after) = value[1..].split_once(quote)?;
+        if key.trim() == name {
+            return Some(unescape(value));
+        }
+        rest = after;
+    }
+    None
+}
+
+fn unescape(value: &str) -> String {
+    value
+        .replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&apos;", "'")
+        .replace("&amp;", "&")
+}
+
+fn parse_sha1(hex: &str) -> Option<[u8; 20]> {
+    if hex.len() != 40 || !hex.is_ascii() {
+        return None;
+    }
+    let mut sha1 = [0; 20];
+    for (index, byte) in sha1.iter_mut().enumerate() {
+        *byte = u8::from_str_radix(&hex[index * 2..index * 2 + 2], 16).ok()?;
+    }
+    Some(sha1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hash::hex;
+    use crate::rom::ValidationPolicy;
+
+    fn rom(content: Vec<u8>) -> Rom {
+        Rom::from_bytes_with_policy(content, ValidationPolicy::Lenient).unwrap()
+    }
+
+    fn dat(first: &Rom, second: &Rom) -> String {
+        format!(
+            r#"<?xml version="1.0"?>
+<!DOCTYPE datafile>
+<datafile>
+    <header><name>Nintendo - Game Boy</name></header>
+    <game name="First &amp; Best (World)">
+        <description>First &amp; Best (World)</description>
+        <rom name="first.gb" size="{}" crc="{:08x}" sha1="{}" status="verified"/>
+    </game>
+    <game name='Second (Japan)'>
+        <rom name="second.gb" size="{}" crc="{:08X}"/>
+    </game>
+</datafile>"#,
+            first.content().len(),
+            first.crc32(),
+            hex(&first.sha1()),
+            second.content().len(),
+            second.crc32(),
+        )
+    }
+
+    #[test]
+    fn test_lookup() {
+        // Arrange
+        let first = rom(vec![0; 0x8000]);
+        let mut content = vec![0; 0x8000];
+        content[0x150] = 1;
+        let second = rom(content);
+        let mut content = vec![0; 0x8000];
+        content[0x150] = 2;
+        let unknown = rom(content);
+
+        // Act
+        let database = Database::parse(&dat(&first, &second)).unwrap();
+
+        // Assert
+        assert_eq!(database.entries().len(), 2);
+        let found = database.lookup(&first).unwrap();
+        assert_eq!(
+            (found.name.as_str(), found.verified),
+            ("First & Best (World)", true)
+        );
+        let found = database.lookup(&second).unwrap();
+        assert_eq!(
+            (found.name.as_str(), found.verified),
+            ("Second (Japan)", false)
+        );
+        assert!(database.lookup(&unknown).is_none());
+    }
+
+    #[test]
+    fn test_parse_without_games() {
+        assert!(Database::parse("<datafile></datafile>").is_err());
+        assert!(Database::parse("clrmamepro ( name \"Nintendo - Game Boy\" )").is_err());
+    }
+}
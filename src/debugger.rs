@@ -0,0 +1,852 @@
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs;
+use std::io::{Error, Result};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use crate::bus::{WatchHit, Watchpoint};
+use crate::call_stack::{CallFrame, CallStack};
+use crate::disasm::{self, Instruction};
+use crate::emulator::Emulator;
+use crate::symbols::Symbols;
+
+pub mod expression;
+
+use expression::Expression;
+
+// What a debugger frontend can ask for. Addresses and values are hexadecimal, with or without a
+// 0x or $ prefix, and addresses can be labels when there are symbols.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Command {
+    Step,
+    StepOver,
+    StepOut,
+    Continue,
+    Break(u16, Breakpoint),
+    Delete(u16),
+    Watch(Watchpoint),
+    Unwatch(Watchpoint),
+    // Moves the memory view.
+    Memory(u16),
+    Write(u16, u8),
+    // Saves a region to a file, as a hex dump when it ends in .txt.
+    Dump(Region, PathBuf),
+    Quit,
+}
+
+impl Command {
+    pub fn parse_with(line: &str, symbols: Option<&Symbols>) -> Result<Command> {
+        let address = |value: &str| parse_address(value, symbols);
+        let words: Vec<&str> = line.split_whitespace().collect();
+        let command = match words.as_slice() {
+            ["s" | "step"] => Command::Step,
+            ["n" | "next"] => Command::StepOver,
+            ["o" | "out"] => Command::StepOut,
+            ["c" | "continue"] => Command::Continue,
+            ["b" | "break", at, options @ ..] => {
+                Command::Break(address(at)?, parse_breakpoint(options, false, symbols)?)
+            }
+            ["tb" | "tbreak", at, options @ ..] => {
+                Command::Break(address(at)?, parse_breakpoint(options, true, symbols)?)
+            }
+            ["d" | "delete", at] => Command::Delete(address(at)?),
+            ["watch", range, access @ ..] => {
+                Command::Watch(parse_watchpoint(range, access, symbols)?)
+            }
+            ["unwatch", range, access @ ..] => {
+                Command::Unwatch(parse_watchpoint(range, access, symbols)?)
+            }
+            ["m" | "memory", at] => Command::Memory(address(at)?),
+            ["w" | "write", at, value] => {
+                let value = u8::try_from(parse_number(value)?)
+                    .map_err(|_| Error::other(format!("{} doesn't fit in a byte.", value)))?;
+                Command::Write(address(at)?, value)
+            }
+            ["dump", region, path] => Command::Dump(region.parse()?, PathBuf::from(path)),
+            ["q" | "quit"] => Command::Quit,
+            _ => {
+                return Err(Error::other(format!(
+                    "Unknown command \"{}\". Try s, n, o, c, b <addr> [after <n>] [if <cond>], tb <addr>, d <addr>, watch <range> [rwx], unwatch <range> [rwx], m <addr>, w <addr> <value>, dump <region> <file> or q.",
+                    line.trim()
+                )))
+            }
+        };
+        Ok(command)
+    }
+}
+
+impl FromStr for Command {
+    type Err = Error;
+
+    fn from_str(line: &str) -> Result<Command> {
+        Command::parse_with(line, None)
+    }
+}
+
+// When a breakpoint stops a run. The default stops every time.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Breakpoint {
+    // Only counts as a hit when this is true.
+    pub condition: Option<Expression>,
+    // How many hits to let through before stopping.
+    pub ignore: u32,
+    // Deleted once it stops a run.
+    pub temporary: bool,
+    pub hits: u32,
+}
+
+impl fmt::Display for Breakpoint {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut parts = Vec::new();
+        if self.temporary {
+            parts.push("once".to_string());
+        }
+        if self.ignore > 0 {
+            parts.push(format!("after {} hits", self.ignore));
+        }
+        if let Some(condition) = &self.condition {
+            parts.push(format!("if {}", condition));
+        }
+        write!(f, "{}", parts.join(" "))
+    }
+}
+
+// Memory to dump. Unlike the CPU's address space, the banked regions include every bank.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Region {
+    Wram,
+    Hram,
+    CartridgeRam,
+    Vram(usize),
+    Oam,
+    // Inclusive, read the way the CPU sees it.
+    Addresses(u16, u16),
+}
+
+impl Region {
+    // Where the first byte is in the CPU's address space. The banked regions go past the end of
+    // their window, so their hex dumps count from 0 instead.
+    pub fn start(&self) -> usize {
+        match self {
+            Region::Wram | Region::CartridgeRam => 0,
+            Region::Hram => 0xFF80,
+            Region::Vram(_) => 0x8000,
+            Region::Oam => 0xFE00,
+            Region::Addresses(start, _) => *start as usize,
+        }
+    }
+}
+
+impl FromStr for Region {
+    type Err = Error;
+
+    fn from_str(value: &str) -> Result<Region> {
+        let region = match value.to_ascii_lowercase().as_str() {
+            "wram" => Region::Wram,
+            "hram" => Region::Hram,
+            "sram" | "cartridge-ram" => Region::CartridgeRam,
+            "vram" => Region::Vram(0),
+            "oam" => Region::Oam,
+            name => match name.strip_prefix("vram").map(str::parse) {
+                Some(Ok(bank)) => Region::Vram(bank),
+                _ if name.contains("..") => {
+                    let (start, end) = parse_range(value, None)?;
+                    Region::Addresses(start, end)
+                }
+                _ => {
+                    return Err(Error::other(format!(
+                        "{} is not a region, expected wram, hram, sram, vram, vram1, oam or a range like C000..CFFF.",
+                        value
+                    )))
+                }
+            },
+        };
+        Ok(region)
+    }
+}
+
+impl fmt::Display for Region {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Region::Wram => write!(f, "WRAM"),
+            Region::Hram => write!(f, "HRAM"),
+            Region::CartridgeRam => write!(f, "cartridge RAM"),
+            Region::Vram(bank) => write!(f, "VRAM bank {}", bank),
+            Region::Oam => write!(f, "OAM"),
+            Region::Addresses(start, end) => write!(f, "{:04X}..{:04X}", start, end),
+        }
+    }
+}
+
+// Why a run handed control back to the frontend.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Stop {
+    Breakpoint(u16),
+    // Stops after the instruction that made the access.
+    Watchpoint(WatchHit),
+    // A step over finished.
+    Stepped,
+    // The routine a step out started in returned.
+    SteppedOut,
+}
+
+impl fmt::Display for Stop {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Stop::Breakpoint(address) => write!(f, "Breakpoint at {:04X}", address),
+            Stop::Watchpoint(hit) => write!(f, "Watchpoint: {}", hit),
+            Stop::Stepped => write!(f, "Stepped over"),
+            Stop::SteppedOut => write!(f, "Stepped out"),
+        }
+    }
+}
+
+// Breakpoints and stepping on top of an emulator. Frontends call `run` a slice at a time so they
+// stay responsive while the game runs.
+#[derive(Default)]
+pub struct Debugger {
+    breakpoints: BTreeMap<u16, Breakpoint>,
+    call_stack: CallStack,
+    // How shallow the call stack has to get to finish a step over or out, and the stop to report.
+    finish: Option<(usize, Stop)>,
+}
+
+impl Debugger {
+    pub fn new() -> Debugger {
+        Debugger::default()
+    }
+
+    pub fn breakpoints(&self) -> impl Iterator<Item = (u16, &Breakpoint)> + '_ {
+        self.breakpoints
+            .iter()
+            .map(|(&address, breakpoint)| (address, breakpoint))
+    }
+
+    pub fn has_breakpoint(&self, address: u16) -> bool {
+        self.breakpoints.contains_key(&address)
+    }
+
+    // Stops every time PC gets to `address`.
+    pub fn add_breakpoint(&mut self, address: u16) {
+        self.set_breakpoint(address, Breakpoint::default());
+    }
+
+    // Replaces any breakpoint already at `address`, hit count and all.
+    pub fn set_breakpoint(&mut self, address: u16, breakpoint: Breakpoint) {
+        self.breakpoints.insert(address, breakpoint);
+    }
+
+    // Returns whether there was one.
+    pub fn remove_breakpoint(&mut self, address: u16) -> bool {
+        self.breakpoints.remove(&address).is_some()
+    }
+
+    // Innermost last.
+    pub fn call_stack(&self) -> &[CallFrame] {
+        self.call_stack.frames()
+    }
+
+    // Watchpoint hits while stepping aren't worth stopping for, since stepping stops anyway.
+    pub fn step(&mut self, emulator: &mut Emulator) {
+        self.finish = None;
+        self.step_tracked(emulator);
+        emulator.take_watch_hits();
+    }
+
+    // Runs a CALL or RST until it returns and single-steps anything else. Returns true when the
+    // call still has to finish through `run`.
+    pub fn step_over(&mut self, emulator: &mut Emulator) -> bool {
+        let depth = self.call_stack.depth();
+        self.step(emulator);
+        // A conditional call that isn't taken, or anything but a call, is done already.
+        if self.call_stack.depth() <= depth {
+            return false;
+        }
+        self.finish = Some((depth, Stop::Stepped));
+        true
+    }
+
+    // Runs until the current routine returns. Returns false when there's no call to return from.
+    pub fn step_out(&mut self, emulator: &mut Emulator) -> bool {
+        let Some(depth) = self.call_stack.depth().checked_sub(1) else {
+            return false;
+        };
+        self.finish = Some((depth, Stop::SteppedOut));
+        // Stepping first, in case the routine is about to return.
+        self.step_tracked(emulator);
+        emulator.take_watch_hits();
+        true
+    }
+
+    // Runs for up to `cycles` T-cycles and stops early at a breakpoint, a watchpoint or when a step
+    // over or out is done. The instruction at the current PC always runs so a run can leave a breakpoint.
+    pub fn run(&mut self, emulator: &mut Emulator, cycles: u32) -> Option<Stop> {
+        // Anything already there came from the frontend poking memory, not from the game.
+        emulator.take_watch_hits();
+        // A step out whose first step already returned.
+        if let Some((depth, stop)) = self.finish {
+            if self.call_stack.depth() <= depth {
+                self.finish = None;
+                return Some(stop);
+            }
+        }
+        let mut elapsed = 0;
+        while elapsed < cycles {
+            elapsed += self.step_tracked(emulator);
+
+            if let Some(&hit) = emulator.take_watch_hits().first() {
+                self.finish = None;
+                return Some(Stop::Watchpoint(hit));
+            }
+            if let Some((depth, stop)) = self.finish {
+                if self.call_stack.depth() <= depth {
+                    self.finish = None;
+                    return Some(stop);
+                }
+            }
+            let pc = emulator.cpu().registers.pc;
+            if self.breakpoint_hit(emulator, pc) {
+                self.finish = None;
+                return Some(Stop::Breakpoint(pc));
+            }
+        }
+        None
+    }
+
+    // Steps and keeps the call stack up to date.
+    fn step_tracked(&mut self, emulator: &mut Emulator) -> u32 {
+        let registers = emulator.cpu().registers;
+        let opcode = emulator.peek(registers.pc);
+        let cycles = emulator.step();
+        self.call_stack.update(emulator, &registers, opcode);
+        cycles
+    }
+
+    // Counts a hit at `pc` if there's a breakpoint whose condition holds, and says whether it's
+    // time to stop.
+    fn breakpoint_hit(&mut self, emulator: &mut Emulator, pc: u16) -> bool {
+        let Some(breakpoint) = self.breakpoints.get_mut(&pc) else {
+            return false;
+        };
+        if !breakpoint
+            .condition
+            .as_ref()
+            .is_none_or(|condition| condition.is_true(emulator))
+        {
+            return false;
+        }
+        breakpoint.hits += 1;
+        if breakpoint.hits <= breakpoint.ignore {
+            return false;
+        }
+        if breakpoint.temporary {
+            self.breakpoints.remove(&pc);
+        }
+        true
+    }
+}
+
+// Decodes the instruction at PC without side effects.
+pub fn current_instruction(emulator: &mut Emulator) -> Instruction {
+    let pc = emulator.cpu().registers.pc;
+    let bytes = [0, 1, 2].map(|offset| emulator.peek(pc.wrapping_add(offset)));
+    disasm::decode(&bytes, pc)
+}
+
+// A copy of `region` as it is now, without side effects.
+pub fn dump(emulator: &mut Emulator, region: Region) -> Result<Vec<u8>> {
+    if let Region::Addresses(start, end) = region {
+        return Ok((start..=end)
+            .map(|address| emulator.peek(address))
+            .collect());
+    }
+    let bus = emulator.bus();
+    let bytes = match region {
+        Region::Wram => bus.wram(),
+        Region::Hram => bus.hram(),
+        Region::CartridgeRam => bus.mbc().ram(),
+        Region::Vram(bank) => bus.ppu().vram_bank(bank).unwrap_or_default(),
+        Region::Oam => bus.ppu().oam(),
+        Region::Addresses(..) => unreachable!(),
+    };
+    if bytes.is_empty() {
+        return Err(Error::other(format!("There is no {}.", region)));
+    }
+    Ok(bytes.to_vec())
+}
+
+// Writes `region` to `path`, as a hex dump if it's a .txt file and as it is otherwise.
+pub fn dump_to_file(emulator: &mut Emulator, region: Region, path: &Path) -> Result<()> {
+    let bytes = dump(emulator, region)?;
+    if path.extension().is_some_and(|extension| extension == "txt") {
+        fs::write(path, hexdump(&bytes, region.start()))
+    } else {
+        fs::write(path, bytes)
+    }
+}
+
+// 16 bytes a line, each line starting with the address of its first byte and ending with the bytes
+// that are printable ASCII.
+pub fn hexdump(bytes: &[u8], start: usize) -> String {
+    let mut dump = String::new();
+    for (index, line) in bytes.chunks(16).enumerate() {
+        let hex: Vec<String> = line.iter().map(|byte| format!("{:02X}", byte)).collect();
+        let text: String = line
+            .iter()
+            .map(|&byte| match byte {
+                0x20..=0x7E => byte as char,
+                _ => '.',
+            })
+            .collect();
+        dump += &format!(
+            "{:04X}  {:<47}  {}\n",
+            start + index * 16,
+            hex.join(" "),
+            text
+        );
+    }
+    dump
+}
+
+// `after <n>` to let n hits through, then `if <condition>` to the end of the line.
+fn parse_breakpoint(
+    options: &[&str],
+    temporary: bool,
+    symbols: Option<&Symbols>,
+) -> Result<Breakpoint> {
+    let mut breakpoint = Breakpoint {
+        temporary,
+        ..Breakpoint::default()
+    };
+    let mut options = options;
+    if let ["after", count, rest @ ..] = options {
+        breakpoint.ignore = count
+            .parse()
+            .map_err(|_| Error::other(format!("{} is not a number of hits.", count)))?;
+        options = rest;
+    }
+    match options {
+        [] => {}
+        ["if", condition @ ..] => {
+            breakpoint.condition = Some(Expression::parse_with(&condition.join(" "), symbols)?)
+        }
+        _ => {
+            return Err(Error::other(
+                "Break with after <hits> and if <condition>, like \"b 0150 after 2 if A == 3F\".",
+            ))
+        }
+    }
+    Ok(breakpoint)
+}
+
+// `start` or `start..end` (inclusive), then any of r, w and x. Without them, reads and writes.
+fn parse_watchpoint(range: &str, access: &[&str], symbols: Option<&Symbols>) -> Result<Watchpoint> {
+    let (start, end) = parse_range(range, symbols)?;
+
+    let access = match access {
+        [] => "rw",
+        [access] if access.chars().all(|c| "rwx".contains(c)) => access,
+        _ => {
+            return Err(Error::other(
+                "Watch with any of r, w and x, like \"watch C000..C0FF w\".",
+            ))
+        }
+    };
+    Ok(Watchpoint {
+        start,
+        end,
+        read: access.contains('r'),
+        write: access.contains('w'),
+        execute: access.contains('x'),
+    })
+}
+
+// `start` or `start..end`, inclusive.
+fn parse_range(range: &str, symbols: Option<&Symbols>) -> Result<(u16, u16)> {
+    let (start, end) = match range.split_once("..") {
+        Some((start, end)) => (parse_address(start, symbols)?, parse_address(end, symbols)?),
+        None => {
+            let address = parse_address(range, symbols)?;
+            (address, address)
+        }
+    };
+    if start > end {
+        return Err(Error::other(format!("{} is an empty range.", range)));
+    }
+    Ok((start, end))
+}
+
+// A label by that name first, so a label that happens to look like a number still works.
+fn parse_address(value: &str, symbols: Option<&Symbols>) -> Result<u16> {
+    match symbols.and_then(|symbols| symbols.get(value)) {
+        Some((_, address)) => Ok(address),
+        None => parse_number(value),
+    }
+}
+
+fn parse_number(value: &str) -> Result<u16> {
+    let digits = value
+        .strip_prefix("0x")
+        .or_else(|| value.strip_prefix('$'))
+        .unwrap_or(value);
+    u16::from_str_radix(digits, 16)
+        .map_err(|_| Error::other(format!("{} is not a hexadecimal number.", value)))
+}
+
+#[cfg(test)]
+mod tests {
+    use rstest::rstest;
+
+    use super::*;
+    use crate::rom::Rom;
+
+    // Runs `program` from 0x0100 with a subroutine at 0x0200 that does two NOPs and returns.
+    fn emulator(program: &[u8]) -> Emulator {
+        let mut content = vec![0; 0x8000];
+        content[0x0100..0x0100 + program.len()].copy_from_slice(program);
+        content[0x0200..0x0203].copy_from_slice(&[0x00, 0x00, 0xC9]);
+        Emulator::new(Rom::from_content(content)).unwrap()
+    }
+
+    #[rstest]
+    #[case("s", Command::Step)]
+    #[case("next", Command::StepOver)]
+    #[case("o", Command::StepOut)]
+    #[case(" c ", Command::Continue)]
+    #[case("b 0150", Command::Break(0x0150, Breakpoint::default()))]
+    #[case("break $C000", Command::Break(0xC000, Breakpoint::default()))]
+    #[case("tb 0150", Command::Break(0x0150, Breakpoint { temporary: true, ..Breakpoint::default() }))]
+    #[case("b 0150 after 3", Command::Break(0x0150, Breakpoint { ignore: 3, ..Breakpoint::default() }))]
+    #[case(
+        "b 0150 after 1 if A == 3F",
+        Command::Break(0x0150, Breakpoint { condition: Some("A == 3F".parse().unwrap()), ignore: 1, ..Breakpoint::default() })
+    )]
+    #[case("d 0x150", Command::Delete(0x0150))]
+    #[case("m ff80", Command::Memory(0xFF80))]
+    #[case("w C000 3F", Command::Write(0xC000, 0x3F))]
+    #[case("watch C000", Command::Watch(watchpoint(0xC000, 0xC000, "rw")))]
+    #[case("watch C000..C0FF w", Command::Watch(watchpoint(0xC000, 0xC0FF, "w")))]
+    #[case("unwatch 0150 x", Command::Unwatch(watchpoint(0x0150, 0x0150, "x")))]
+    #[case(
+        "dump wram wram.bin",
+        Command::Dump(Region::Wram, PathBuf::from("wram.bin"))
+    )]
+    #[case(
+        "dump VRAM1 tiles.txt",
+        Command::Dump(Region::Vram(1), PathBuf::from("tiles.txt"))
+    )]
+    #[case(
+        "dump C000..C0FF out.bin",
+        Command::Dump(Region::Addresses(0xC000, 0xC0FF), PathBuf::from("out.bin"))
+    )]
+    #[case("q", Command::Quit)]
+    fn test_parse_command(#[case] line: &str, #[case] expected: Command) {
+        assert_eq!(line.parse::<Command>().unwrap(), expected);
+    }
+
+    #[rstest]
+    #[case("")]
+    #[case("jump 0150")]
+    #[case("b")]
+    #[case("b zz")]
+    #[case("b 0150 if")]
+    #[case("b 0150 after x")]
+    #[case("b 0150 when A == 1")]
+    #[case("w C000 100")]
+    #[case("watch C0FF..C000")]
+    #[case("watch C000 q")]
+    #[case("dump wram")]
+    #[case("dump rom rom.bin")]
+    fn test_parse_invalid_command(#[case] line: &str) {
+        assert!(line.parse::<Command>().is_err());
+    }
+
+    #[rstest]
+    #[case("b UpdateSprites", Command::Break(0x2843, Breakpoint::default()))]
+    #[case("b Add", Command::Break(0x4000, Breakpoint::default()))]
+    #[case("b ADD", Command::Break(0x0ADD, Breakpoint::default()))]
+    #[case("m wOAM", Command::Memory(0xC100))]
+    #[case("watch wOAM..C19F w", Command::Watch(watchpoint(0xC100, 0xC19F, "w")))]
+    fn test_parse_command_with_symbols(#[case] line: &str, #[case] expected: Command) {
+        let symbols = Symbols::parse("00:2843 UpdateSprites\n01:4000 Add\n00:C100 wOAM\n").unwrap();
+
+        assert_eq!(Command::parse_with(line, Some(&symbols)).unwrap(), expected);
+    }
+
+    fn watchpoint(start: u16, end: u16, access: &str) -> Watchpoint {
+        Watchpoint {
+            start,
+            end,
+            read: access.contains('r'),
+            write: access.contains('w'),
+            execute: access.contains('x'),
+        }
+    }
+
+    #[test]
+    fn test_run_stops_at_watchpoint() {
+        // Arrange
+        // NOP; LD (0xC000),A; JR -2
+        let mut emulator = emulator(&[0x00, 0xEA, 0x00, 0xC0, 0x18, 0xFE]);
+        emulator.add_watchpoint(watchpoint(0xC000, 0xC000, "w"));
+        let mut debugger = Debugger::new();
+
+        // Act
+        let stop = debugger.run(&mut emulator, 1000);
+
+        // Assert
+        assert!(matches!(
+            stop,
+            Some(Stop::Watchpoint(hit)) if hit.address == 0xC000 && hit.pc == 0x0101
+        ));
+        assert_eq!(emulator.cpu().registers.pc, 0x0104);
+    }
+
+    #[test]
+    fn test_run_stops_at_breakpoint() {
+        // Arrange
+        let mut emulator = emulator(&[0x00, 0x00, 0x00, 0x18, 0xFB]);
+        let mut debugger = Debugger::new();
+        debugger.add_breakpoint(0x0102);
+
+        // Act
+        let stop = debugger.run(&mut emulator, 1000);
+
+        // Assert
+        assert_eq!(stop, Some(Stop::Breakpoint(0x0102)));
+        assert_eq!(emulator.cpu().registers.pc, 0x0102);
+    }
+
+    #[test]
+    fn test_run_leaves_current_breakpoint() {
+        let mut emulator = emulator(&[0x00, 0x18, 0xFD]);
+        let mut debugger = Debugger::new();
+        debugger.add_breakpoint(0x0100);
+
+        let stop = debugger.run(&mut emulator, 1000);
+
+        // Around the loop and back to the start.
+        assert_eq!(stop, Some(Stop::Breakpoint(0x0100)));
+    }
+
+    #[test]
+    fn test_conditional_breakpoint() {
+        // Arrange
+        // INC A; JR -3
+        let mut emulator = emulator(&[0x3C, 0x18, 0xFD]);
+        emulator.cpu_mut().registers.a = 0;
+        let mut debugger = Debugger::new();
+        debugger.set_breakpoint(
+            0x0100,
+            Breakpoint {
+                condition: Some("A == 3".parse().unwrap()),
+                ..Breakpoint::default()
+            },
+        );
+
+        // Act
+        let stop = debugger.run(&mut emulator, 1000);
+
+        // Assert
+        assert_eq!(stop, Some(Stop::Breakpoint(0x0100)));
+        assert_eq!(emulator.cpu().registers.a, 3);
+    }
+
+    #[test]
+    fn test_breakpoint_hit_count() {
+        // Arrange
+        let mut emulator = emulator(&[0x3C, 0x18, 0xFD]);
+        emulator.cpu_mut().registers.a = 0;
+        let mut debugger = Debugger::new();
+        debugger.set_breakpoint(
+            0x0101,
+            Breakpoint {
+                ignore: 4,
+                ..Breakpoint::default()
+            },
+        );
+
+        // Act
+        let stop = debugger.run(&mut emulator, 1000);
+
+        // Assert
+        assert_eq!(stop, Some(Stop::Breakpoint(0x0101)));
+        assert_eq!(emulator.cpu().registers.a, 5);
+        assert_eq!(debugger.breakpoints().next().unwrap().1.hits, 5);
+    }
+
+    #[test]
+    fn test_temporary_breakpoint() {
+        let mut emulator = emulator(&[0x3C, 0x18, 0xFD]);
+        let mut debugger = Debugger::new();
+        debugger.set_breakpoint(
+            0x0101,
+            Breakpoint {
+                temporary: true,
+                ..Breakpoint::default()
+            },
+        );
+
+        let first = debugger.run(&mut emulator, 1000);
+        let second = debugger.run(&mut emulator, 1000);
+
+        assert_eq!(first, Some(Stop::Breakpoint(0x0101)));
+        assert_eq!(second, None);
+        assert!(!debugger.has_breakpoint(0x0101));
+    }
+
+    #[test]
+    fn test_run_gives_up_after_cycles() {
+        let mut emulator = emulator(&[0x18, 0xFE]);
+        let mut debugger = Debugger::new();
+
+        assert_eq!(debugger.run(&mut emulator, 100), None);
+    }
+
+    #[test]
+    fn test_step_over_call() {
+        // Arrange
+        let mut emulator = emulator(&[0xCD, 0x00, 0x02, 0x00]);
+        let mut debugger = Debugger::new();
+
+        // Act
+        let running = debugger.step_over(&mut emulator);
+        let stop = debugger.run(&mut emulator, 1000);
+
+        // Assert
+        assert!(running);
+        assert_eq!(stop, Some(Stop::Stepped));
+        assert_eq!(emulator.cpu().registers.pc, 0x0103);
+    }
+
+    #[test]
+    fn test_dump() {
+        // Arrange
+        let mut emulator = emulator(&[]);
+        emulator.poke(0xC001, 0x41);
+        emulator.poke(0xFF80, 0x7F);
+
+        // Act
+        let wram = dump(&mut emulator, Region::Wram).unwrap();
+        let hram = dump(&mut emulator, Region::Hram).unwrap();
+        let range = dump(&mut emulator, Region::Addresses(0xC000, 0xC002)).unwrap();
+
+        // Assert
+        assert_eq!((wram.len(), wram[1]), (0x2000, 0x41));
+        assert_eq!((hram.len(), hram[0]), (0x7F, 0x7F));
+        assert_eq!(range, wram[..3]);
+        // The DMG has one VRAM bank and the test cartridge no RAM.
+        assert_eq!(dump(&mut emulator, Region::Vram(0)).unwrap().len(), 0x2000);
+        assert!(dump(&mut emulator, Region::Vram(1)).is_err());
+        assert!(dump(&mut emulator, Region::CartridgeRam).is_err());
+    }
+
+    #[test]
+    fn test_hexdump() {
+        let bytes: Vec<u8> = (0x3C..0x4F).collect();
+
+        let dump = hexdump(&bytes, 0xFF80);
+
+        assert_eq!(
+            dump,
+            "FF80  3C 3D 3E 3F 40 41 42 43 44 45 46 47 48 49 4A 4B  <=>?@ABCDEFGHIJK\n\
+             FF90  4C 4D 4E                                         LMN\n"
+        );
+    }
+
+    #[test]
+    fn test_step_over_untaken_call() {
+        // CALL NZ,0200 with Z set.
+        let mut emulator = emulator(&[0xC4, 0x00, 0x02]);
+        emulator.cpu_mut().registers.f = 0x80;
+        let mut debugger = Debugger::new();
+
+        assert!(!debugger.step_over(&mut emulator));
+        assert_eq!(emulator.cpu().registers.pc, 0x0103);
+    }
+
+    #[test]
+    fn test_step_out() {
+        // Arrange
+        let mut emulator = emulator(&[0xCD, 0x00, 0x02, 0x00]);
+        let mut debugger = Debugger::new();
+        debugger.step(&mut emulator);
+        let frames = debugger.call_stack().to_vec();
+
+        // Act
+        let running = debugger.step_out(&mut emulator);
+        let stop = debugger.run(&mut emulator, 1000);
+
+        // Assert
+        assert_eq!(frames.len(), 1);
+        assert_eq!(
+            (frames[0].target, frames[0].caller, frames[0].return_address),
+            (0x0200, 0x0100, 0x0103)
+        );
+        assert!(running);
+        assert_eq!(stop, Some(Stop::SteppedOut));
+        assert_eq!(emulator.cpu().registers.pc, 0x0103);
+        assert!(debugger.call_stack().is_empty());
+    }
+
+    #[test]
+    fn test_step_out_without_call() {
+        let mut emulator = emulator(&[0x00]);
+        let mut debugger = Debugger::new();
+
+        assert!(!debugger.step_out(&mut emulator));
+        assert_eq!(emulator.cpu().registers.pc, 0x0100);
+    }
+
+    #[test]
+    fn test_call_stack_drops_popped_return_address() {
+        // CALL 0105; NOP; NOP; POP HL; JP HL
+        let mut emulator = emulator(&[0xCD, 0x05, 0x01, 0x00, 0x00, 0xE1, 0xE9]);
+        let mut debugger = Debugger::new();
+
+        debugger.step(&mut emulator);
+        let depth = debugger.call_stack().len();
+        debugger.step(&mut emulator);
+
+        assert_eq!(depth, 1);
+        assert!(debugger.call_stack().is_empty());
+    }
+
+    #[test]
+    fn test_call_stack_tracks_interrupts() {
+        // Arrange
+        // EI; NOP; NOP with VBlank requested.
+        let mut emulator = emulator(&[0xFB, 0x00, 0x00]);
+        emulator.poke(0xFFFF, 0x01);
+        emulator.poke(0xFF0F, 0x01);
+        let mut debugger = Debugger::new();
+
+        // Act
+        for _ in 0..3 {
+            debugger.step(&mut emulator);
+        }
+
+        // Assert
+        assert_eq!(
+            debugger.call_stack(),
+            [CallFrame {
+                target: 0x0040,
+                bank: 0,
+                caller: 0x0102,
+                return_address: 0x0102,
+                sp: emulator.cpu().registers.sp,
+                interrupt: true,
+            }]
+        );
+    }
+
+    #[test]
+    fn test_step_over_other_instruction_steps() {
+        let mut emulator = emulator(&[0x00]);
+        let mut debugger = Debugger::new();
+
+        assert!(!debugger.step_over(&mut emulator));
+        assert_eq!(emulator.cpu().registers.pc, 0x0101);
+    }
+}
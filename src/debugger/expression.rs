@@ -0,0 +1,421 @@
+use std::fmt;
+use std::io::{Error, Result};
+use std::str::FromStr;
+
+use crate::emulator::Emulator;
+use crate::symbols::Symbols;
+
+// A condition like `A == 3F && [C000] != 0`, evaluated against the emulator whenever a breakpoint is
+// reached. Numbers are hexadecimal like everywhere else in the debugger, so a lone `a` is the
+// register and ten is `0xA`. [address] reads a byte, and comparisons give 1 or 0. With symbols, a
+// label stands for its address, so `[wPlayerX] > 80` works.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Expression {
+    source: String,
+    node: Node,
+}
+
+impl Expression {
+    // Register names win over labels, and labels over numbers.
+    pub fn parse_with(source: &str, symbols: Option<&Symbols>) -> Result<Expression> {
+        let tokens = tokenize(source)?;
+        let mut parser = Parser {
+            tokens: &tokens,
+            position: 0,
+            symbols,
+        };
+        let node = parser.expression(0)?;
+        if let Some(token) = parser.peek() {
+            return Err(Error::other(format!("Unexpected {} in {}.", token, source)));
+        }
+        Ok(Expression {
+            source: source.trim().to_string(),
+            node,
+        })
+    }
+
+    pub fn evaluate(&self, emulator: &mut Emulator) -> u32 {
+        self.node.evaluate(emulator)
+    }
+
+    pub fn is_true(&self, emulator: &mut Emulator) -> bool {
+        self.evaluate(emulator) != 0
+    }
+}
+
+impl FromStr for Expression {
+    type Err = Error;
+
+    fn from_str(source: &str) -> Result<Expression> {
+        Expression::parse_with(source, None)
+    }
+}
+
+impl fmt::Display for Expression {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.source)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum Node {
+    Number(u32),
+    Register(Register),
+    Memory(Box<Node>),
+    Not(Box<Node>),
+    Negate(Box<Node>),
+    Binary(Operator, Box<Node>, Box<Node>),
+}
+
+impl Node {
+    fn evaluate(&self, emulator: &mut Emulator) -> u32 {
+        match self {
+            Node::Number(value) => *value,
+            Node::Register(register) => register.read(emulator),
+            Node::Memory(address) => {
+                let address = address.evaluate(emulator) as u16;
+                emulator.peek(address) as u32
+            }
+            Node::Not(value) => (value.evaluate(emulator) == 0) as u32,
+            Node::Negate(value) => value.evaluate(emulator).wrapping_neg(),
+            // Both sides of && and || run either way, which is fine without side effects.
+            Node::Binary(operator, left, right) => {
+                operator.apply(left.evaluate(emulator), right.evaluate(emulator))
+            }
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Register {
+    A,
+    F,
+    B,
+    C,
+    D,
+    E,
+    H,
+    L,
+    Af,
+    Bc,
+    De,
+    Hl,
+    Sp,
+    Pc,
+}
+
+impl Register {
+    fn from_name(name: &str) -> Option<Register> {
+        let register = match name.to_ascii_lowercase().as_str() {
+            "a" => Register::A,
+            "f" => Register::F,
+            "b" => Register::B,
+            "c" => Register::C,
+            "d" => Register::D,
+            "e" => Register::E,
+            "h" => Register::H,
+            "l" => Register::L,
+            "af" => Register::Af,
+            "bc" => Register::Bc,
+            "de" => Register::De,
+            "hl" => Register::Hl,
+            "sp" => Register::Sp,
+            "pc" => Register::Pc,
+            _ => return None,
+        };
+        Some(register)
+    }
+
+    fn read(self, emulator: &Emulator) -> u32 {
+        let registers = &emulator.cpu().registers;
+        let value = match self {
+            Register::A => registers.a as u16,
+            Register::F => registers.f as u16,
+            Register::B => registers.b as u16,
+            Register::C => registers.c as u16,
+            Register::D => registers.d as u16,
+            Register::E => registers.e as u16,
+            Register::H => registers.h as u16,
+            Register::L => registers.l as u16,
+            Register::Af => registers.af(),
+            Register::Bc => registers.bc(),
+            Register::De => registers.de(),
+            Register::Hl => registers.hl(),
+            Register::Sp => registers.sp,
+            Register::Pc => registers.pc,
+        };
+        value as u32
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Operator {
+    Or,
+    And,
+    Equal,
+    NotEqual,
+    Less,
+    LessOrEqual,
+    Greater,
+    GreaterOrEqual,
+    BitOr,
+    BitXor,
+    BitAnd,
+    Add,
+    Subtract,
+}
+
+impl Operator {
+    fn from_symbol(symbol: &str) -> Option<Operator> {
+        let operator = match symbol {
+            "||" => Operator::Or,
+            "&&" => Operator::And,
+            "==" => Operator::Equal,
+            "!=" => Operator::NotEqual,
+            "<" => Operator::Less,
+            "<=" => Operator::LessOrEqual,
+            ">" => Operator::Greater,
+            ">=" => Operator::GreaterOrEqual,
+            "|" => Operator::BitOr,
+            "^" => Operator::BitXor,
+            "&" => Operator::BitAnd,
+            "+" => Operator::Add,
+            "-" => Operator::Subtract,
+            _ => return None,
+        };
+        Some(operator)
+    }
+
+    // Higher binds tighter, the same order as C.
+    fn precedence(self) -> u8 {
+        match self {
+            Operator::Or => 1,
+            Operator::And => 2,
+            Operator::Equal | Operator::NotEqual => 3,
+            Operator::Less
+            | Operator::LessOrEqual
+            | Operator::Greater
+            | Operator::GreaterOrEqual => 4,
+            Operator::BitOr => 5,
+            Operator::BitXor => 6,
+            Operator::BitAnd => 7,
+            Operator::Add | Operator::Subtract => 8,
+        }
+    }
+
+    fn apply(self, left: u32, right: u32) -> u32 {
+        match self {
+            Operator::Or => (left != 0 || right != 0) as u32,
+            Operator::And => (left != 0 && right != 0) as u32,
+            Operator::Equal => (left == right) as u32,
+            Operator::NotEqual => (left != right) as u32,
+            Operator::Less => (left < right) as u32,
+            Operator::LessOrEqual => (left <= right) as u32,
+            Operator::Greater => (left > right) as u32,
+            Operator::GreaterOrEqual => (left >= right) as u32,
+            Operator::BitOr => left | right,
+            Operator::BitXor => left ^ right,
+            Operator::BitAnd => left & right,
+            Operator::Add => left.wrapping_add(right),
+            Operator::Subtract => left.wrapping_sub(right),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum Token {
+    Word(String),
+    Symbol(&'static str),
+}
+
+impl fmt::Display for Token {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Token::Word(word) => write!(f, "\"{}\"", word),
+            Token::Symbol(symbol) => write!(f, "\"{}\"", symbol),
+        }
+    }
+}
+
+fn tokenize(source: &str) -> Result<Vec<Token>> {
+    let mut tokens = Vec::new();
+    let mut rest = source.trim_start();
+    while !rest.is_empty() {
+        if let Some(&symbol) = SYMBOLS.iter().find(|&&symbol| rest.starts_with(symbol)) {
+            tokens.push(Token::Symbol(symbol));
+            rest = &rest[symbol.len()..];
+        } else {
+            let length = rest
+                .find(|c: char| !(c.is_ascii_alphanumeric() || "$_.".contains(c)))
+                .unwrap_or(rest.len());
+            if length == 0 {
+                return Err(Error::other(format!(
+                    "{} can't be in a condition.",
+                    rest.chars().next().unwrap()
+                )));
+            }
+            tokens.push(Token::Word(rest[..length].to_string()));
+            rest = &rest[length..];
+        }
+        rest = rest.trim_start();
+    }
+    Ok(tokens)
+}
+
+// Precedence climbing over the tokens.
+struct Parser<'a> {
+    tokens: &'a [Token],
+    position: usize,
+    symbols: Option<&'a Symbols>,
+}
+
+impl Parser<'_> {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.position)
+    }
+
+    fn next(&mut self) -> Result<&Token> {
+        let token = self
+            .tokens
+            .get(self.position)
+            .ok_or_else(|| Error::other("The condition ends too soon."))?;
+        self.position += 1;
+        Ok(token)
+    }
+
+    fn expect(&mut self, symbol: &str) -> Result<()> {
+        match self.next()? {
+            Token::Symbol(found) if *found == symbol => Ok(()),
+            token => Err(Error::other(format!(
+                "Expected \"{}\" but found {}.",
+                symbol, token
+            ))),
+        }
+    }
+
+    // Everything that binds at least as tightly as `precedence`.
+    fn expression(&mut self, precedence: u8) -> Result<Node> {
+        let mut left = self.operand()?;
+        while let Some(Token::Symbol(symbol)) = self.peek() {
+            let Some(operator) = Operator::from_symbol(symbol) else {
+                break;
+            };
+            if operator.precedence() <= precedence {
+                break;
+            }
+            self.position += 1;
+            let right = self.expression(operator.precedence())?;
+            left = Node::Binary(operator, Box::new(left), Box::new(right));
+        }
+        Ok(left)
+    }
+
+    fn operand(&mut self) -> Result<Node> {
+        let node = match self.next()?.clone() {
+            Token::Symbol("(") => {
+                let node = self.expression(0)?;
+                self.expect(")")?;
+                node
+            }
+            Token::Symbol("[") => {
+                let address = self.expression(0)?;
+                self.expect("]")?;
+                Node::Memory(Box::new(address))
+            }
+            Token::Symbol("!") => Node::Not(Box::new(self.operand()?)),
+            Token::Symbol("-") => Node::Negate(Box::new(self.operand()?)),
+            Token::Word(word) => {
+                let label = self.symbols.and_then(|symbols| symbols.get(&word));
+                match (Register::from_name(&word), label) {
+                    (Some(register), _) => Node::Register(register),
+                    (None, Some((_, address))) => Node::Number(address as u32),
+                    (None, None) => Node::Number(parse_number(&word)?),
+                }
+            }
+            token => return Err(Error::other(format!("Unexpected {}.", token))),
+        };
+        Ok(node)
+    }
+}
+
+fn parse_number(value: &str) -> Result<u32> {
+    let digits = value
+        .strip_prefix("0x")
+        .or_else(|| value.strip_prefix('$'))
+        .unwrap_or(value);
+    u32::from_str_radix(digits, 16).map_err(|_| {
+        Error::other(format!(
+            "{} is not a register or a hexadecimal number.",
+            value
+        ))
+    })
+}
+
+// Longer symbols first so == isn't read as two =.
+const SYMBOLS: [&str; 18] = [
+    "||", "&&", "==", "!=", "<=", ">=", "<", ">", "|", "^", "&", "+", "-", "!", "(", ")", "[", "]",
+];
+
+#[cfg(test)]
+mod tests {
+    use rstest::rstest;
+
+    use super::*;
+    use crate::rom::Rom;
+
+    #[rstest]
+    #[case("A == 3F", 1)]
+    #[case("a == 0x3F && [C000] != 0", 1)]
+    #[case("A == 3F && [C000] == 0", 0)]
+    #[case("HL", 0xC001)]
+    #[case("[HL - 1]", 0x12)]
+    #[case("1 + 2 == 3 || 0", 1)]
+    #[case("!(pc >= $150)", 1)]
+    #[case("A & F0 | 1", 0x31)]
+    #[case("-1 == FFFFFFFF", 1)]
+    fn test_evaluate(#[case] source: &str, #[case] expected: u32) {
+        // Arrange
+        let mut emulator = Emulator::new(Rom::from_content(vec![0; 0x8000])).unwrap();
+        emulator.cpu_mut().registers.a = 0x3F;
+        emulator.cpu_mut().registers.h = 0xC0;
+        emulator.cpu_mut().registers.l = 0x01;
+        emulator.poke(0xC000, 0x12);
+
+        // Act
+        let value = source
+            .parse::<Expression>()
+            .unwrap()
+            .evaluate(&mut emulator);
+
+        // Assert
+        assert_eq!(value, expected);
+    }
+
+    #[test]
+    fn test_evaluate_labels() {
+        // Arrange
+        let mut emulator = Emulator::new(Rom::from_content(vec![0; 0x8000])).unwrap();
+        emulator.cpu_mut().registers.a = 0x01;
+        emulator.poke(0xC000, 0x12);
+        let symbols = Symbols::parse("00:C000 wPlayer.x\n00:0150 a\n").unwrap();
+
+        // Act
+        let expression = Expression::parse_with("[wPlayer.x] == 12 && a == 1", Some(&symbols));
+
+        // Assert
+        assert!(expression.unwrap().is_true(&mut emulator));
+        assert!("[wPlayer.x]".parse::<Expression>().is_err());
+    }
+
+    #[rstest]
+    #[case("")]
+    #[case("A ==")]
+    #[case("(A == 1")]
+    #[case("[C000")]
+    #[case("A = 1")]
+    #[case("zz")]
+    #[case("A 1")]
+    fn test_parse_invalid(#[case] source: &str) {
+        assert!(source.parse::<Expression>().is_err());
+    }
+}
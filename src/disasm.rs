@@ -0,0 +1,443 @@
+use std::fmt;
+use std::ops::Range;
+
+// A decoded SM83 instruction, printed in RGBDS syntax (`ld a, [hl+]`, `jr nz, $0150`).
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Instruction {
+    pub mnemonic: &'static str,
+    pub operands: Vec<Operand>,
+    // In bytes, including the 0xCB prefix.
+    pub length: u8,
+    // T-cycles. Conditional jumps, calls and returns take `taken_cycles` instead when the
+    // condition holds.
+    pub cycles: u8,
+    pub taken_cycles: Option<u8>,
+}
+
+// One line of a listing: where an instruction sits, its bytes and what they decode to.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Line {
+    pub address: u16,
+    pub bytes: Vec<u8>,
+    pub instruction: Instruction,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Operand {
+    Register(&'static str),
+    // A register holding an address, like [hl] or [c].
+    Indirect(&'static str),
+    Condition(&'static str),
+    Byte(u8),
+    Word(u16),
+    // A memory address read or written, like [$C000].
+    Address(u16),
+    // Where a jump, call or RST goes. Relative jumps are already resolved against PC.
+    Target(u16),
+    // The signed immediate of `add sp, e8`.
+    Offset(i8),
+    // The `sp + e8` of `ld hl, sp + e8`.
+    StackOffset(i8),
+    Bit(u8),
+}
+
+impl fmt::Display for Operand {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Operand::Register(name) | Operand::Condition(name) => write!(f, "{}", name),
+            Operand::Indirect(name) => write!(f, "[{}]", name),
+            Operand::Byte(value) => write!(f, "${:02X}", value),
+            Operand::Word(value) | Operand::Target(value) => write!(f, "${:04X}", value),
+            Operand::Address(address) => write!(f, "[${:04X}]", address),
+            Operand::Offset(offset) => write!(f, "{}", offset),
+            Operand::StackOffset(offset) if offset < 0 => write!(f, "sp - {}", -(offset as i16)),
+            Operand::StackOffset(offset) => write!(f, "sp + {}", offset),
+            Operand::Bit(bit) => write!(f, "{}", bit),
+        }
+    }
+}
+
+impl Instruction {
+    // Names the jump targets and memory addresses `label` knows, like `call UpdateSprites` or
+    // `ld a, [wPlayerX]`.
+    pub fn format_with<'a>(&self, label: impl Fn(u16) -> Option<&'a str>) -> String {
+        let mut text = self.mnemonic.to_string();
+        for (index, operand) in self.operands.iter().enumerate() {
+            text += if index == 0 { " " } else { ", " };
+            let labeled = match *operand {
+                Operand::Target(address) => label(address).map(str::to_string),
+                Operand::Address(address) => label(address).map(|name| format!("[{}]", name)),
+                _ => None,
+            };
+            text += &labeled.unwrap_or_else(|| operand.to_string());
+        }
+        text
+    }
+}
+
+impl fmt::Display for Instruction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.format_with(|_| None))
+    }
+}
+
+impl Line {
+    // See `Instruction::format_with`.
+    pub fn format_with<'a>(&self, label: impl Fn(u16) -> Option<&'a str>) -> String {
+        let bytes: Vec<String> = self.bytes.iter().map(|b| format!("{:02X}", b)).collect();
+        format!(
+            "{:04X}  {:<10}{}",
+            self.address,
+            bytes.join(" "),
+            self.instruction.format_with(label)
+        )
+    }
+}
+
+impl fmt::Display for Line {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.format_with(|_| None))
+    }
+}
+
+// Decodes instructions back to back over `range`, reading memory through `read` so it works on ROM
+// files as well as on a running emulator. The last instruction may run past the end of the range.
+pub fn disassemble(mut read: impl FnMut(u16) -> u8, range: Range<u16>) -> Vec<Line> {
+    let mut lines = Vec::new();
+    let mut address = range.start;
+    while address >= range.start && address < range.end {
+        let bytes = [0, 1, 2].map(|offset| read(address.wrapping_add(offset)));
+        let instruction = decode(&bytes, address);
+        let length = instruction.length;
+        lines.push(Line {
+            address,
+            bytes: bytes[..length as usize].to_vec(),
+            instruction,
+        });
+        address = address.wrapping_add(length as u16);
+    }
+    lines
+}
+
+// Decodes the instruction at the start of `bytes`, which was read from `pc`. Missing bytes past
+// the end of the slice read as 0x00. Opcodes the CPU doesn't have decode as `db`.
+pub fn decode(bytes: &[u8], pc: u16) -> Instruction {
+    let byte = |index: usize| bytes.get(index).copied().unwrap_or(0);
+    let opcode = byte(0);
+    let n8 = byte(1);
+    let n16 = u16::from_le_bytes([byte(1), byte(2)]);
+    let relative = pc.wrapping_add(2).wrapping_add(n8 as i8 as u16);
+
+    let x = opcode >> 6;
+    let y = (opcode >> 3) & 0x07;
+    let z = opcode & 0x07;
+    let p = (y >> 1) as usize;
+    let q = y & 0x01;
+    let r = |index: u8| R8[index as usize];
+
+    use Operand::*;
+    let (mnemonic, operands, length): (&'static str, Vec<Operand>, u8) = match (x, z) {
+        (0, 0) => match y {
+            0 => ("nop", vec![], 1),
+            1 => ("ld", vec![Address(n16), Register("sp")], 3),
+            2 => ("stop", vec![], 2),
+            3 => ("jr", vec![Target(relative)], 2),
+            _ => (
+                "jr",
+                vec![Condition(CONDITIONS[y as usize - 4]), Target(relative)],
+                2,
+            ),
+        },
+        (0, 1) if q == 0 => ("ld", vec![Register(R16[p]), Word(n16)], 3),
+        (0, 1) => ("add", vec![Register("hl"), Register(R16[p])], 1),
+        (0, 2) if q == 0 => ("ld", vec![Indirect(R16_MEMORY[p]), Register("a")], 1),
+        (0, 2) => ("ld", vec![Register("a"), Indirect(R16_MEMORY[p])], 1),
+        (0, 3) if q == 0 => ("inc", vec![Register(R16[p])], 1),
+        (0, 3) => ("dec", vec![Register(R16[p])], 1),
+        (0, 4) => ("inc", vec![r(y)], 1),
+        (0, 5) => ("dec", vec![r(y)], 1),
+        (0, 6) => ("ld", vec![r(y), Byte(n8)], 2),
+        (0, _) => (ACCUMULATOR_OPS[y as usize], vec![], 1),
+        (1, 6) if y == 6 => ("halt", vec![], 1),
+        (1, _) => ("ld", vec![r(y), r(z)], 1),
+        (2, _) => alu(y, r(z), 1),
+        (3, 0) => match y {
+            0..=3 => ("ret", vec![Condition(CONDITIONS[y as usize])], 1),
+            4 => ("ldh", vec![Address(0xFF00 | n8 as u16), Register("a")], 2),
+            5 => ("add", vec![Register("sp"), Offset(n8 as i8)], 2),
+            6 => ("ldh", vec![Register("a"), Address(0xFF00 | n8 as u16)], 2),
+            _ => ("ld", vec![Register("hl"), StackOffset(n8 as i8)], 2),
+        },
+        (3, 1) if q == 0 => ("pop", vec![Register(R16_STACK[p])], 1),
+        (3, 1) => match p {
+            0 => ("ret", vec![], 1),
+            1 => ("reti", vec![], 1),
+            2 => ("jp", vec![Register("hl")], 1),
+            _ => ("ld", vec![Register("sp"), Register("hl")], 1),
+        },
+        (3, 2) => match y {
+            0..=3 => (
+                "jp",
+                vec![Condition(CONDITIONS[y as usize]), Target(n16)],
+                3,
+            ),
+            4 => ("ldh", vec![Indirect("c"), Register("a")], 1),
+            5 => ("ld", vec![Address(n16), Register("a")], 3),
+            6 => ("ldh", vec![Register("a"), Indirect("c")], 1),
+            _ => ("ld", vec![Register("a"), Address(n16)], 3),
+        },
+        (3, 3) => match y {
+            0 => ("jp", vec![Target(n16)], 3),
+            1 => return decode_cb(n8),
+            6 => ("di", vec![], 1),
+            7 => ("ei", vec![], 1),
+            _ => ("db", vec![Byte(opcode)], 1),
+        },
+        (3, 4) if y < 4 => (
+            "call",
+            vec![Condition(CONDITIONS[y as usize]), Target(n16)],
+            3,
+        ),
+        (3, 5) if q == 0 => ("push", vec![Register(R16_STACK[p])], 1),
+        (3, 5) if p == 0 => ("call", vec![Target(n16)], 3),
+        (3, 6) => alu(y, Byte(n8), 2),
+        (3, 7) => ("rst", vec![Target(y as u16 * 8)], 1),
+        _ => ("db", vec![Byte(opcode)], 1),
+    };
+
+    let (cycles, taken_cycles) = timing(opcode);
+    Instruction {
+        mnemonic,
+        operands,
+        length,
+        cycles,
+        taken_cycles,
+    }
+}
+
+fn decode_cb(opcode: u8) -> Instruction {
+    let y = (opcode >> 3) & 0x07;
+    let target = R8[(opcode & 0x07) as usize];
+    let (mnemonic, operands) = match opcode >> 6 {
+        0 => (ROTATIONS[y as usize], vec![target]),
+        1 => ("bit", vec![Operand::Bit(y), target]),
+        2 => ("res", vec![Operand::Bit(y), target]),
+        _ => ("set", vec![Operand::Bit(y), target]),
+    };
+    // Only BIT leaves [hl] alone, so it skips the write back.
+    let cycles = match (opcode & 0x07, opcode >> 6) {
+        (6, 1) => 12,
+        (6, _) => 16,
+        _ => 8,
+    };
+    Instruction {
+        mnemonic,
+        operands,
+        length: 2,
+        cycles,
+        taken_cycles: None,
+    }
+}
+
+// Cycles of the unprefixed opcodes, and for conditional ones how long they take when taken.
+fn timing(opcode: u8) -> (u8, Option<u8>) {
+    let cycles = match opcode {
+        0x20 | 0x28 | 0x30 | 0x38 => return (8, Some(12)),
+        0xC0 | 0xC8 | 0xD0 | 0xD8 => return (8, Some(20)),
+        0xC2 | 0xCA | 0xD2 | 0xDA => return (12, Some(16)),
+        0xC4 | 0xCC | 0xD4 | 0xDC => return (12, Some(24)),
+        0x08 => 20,
+        0x34..=0x36 => 12,
+        0x01 | 0x11 | 0x21 | 0x31 | 0x18 => 12,
+        0x02 | 0x12 | 0x22 | 0x32 | 0x0A | 0x1A | 0x2A | 0x3A => 8,
+        0x03 | 0x13 | 0x23 | 0x33 | 0x0B | 0x1B | 0x2B | 0x3B => 8,
+        0x09 | 0x19 | 0x29 | 0x39 => 8,
+        0x06 | 0x0E | 0x16 | 0x1E | 0x26 | 0x2E | 0x3E => 8,
+        0x76 => 4,
+        0x40..=0x7F if opcode & 0x07 == 6 || (opcode >> 3) & 0x07 == 6 => 8,
+        0x80..=0xBF if opcode & 0x07 == 6 => 8,
+        0xC1 | 0xD1 | 0xE1 | 0xF1 => 12,
+        0xC5 | 0xD5 | 0xE5 | 0xF5 => 16,
+        0xC3 | 0xC9 | 0xD9 | 0xEA | 0xFA | 0xE8 => 16,
+        0xC7 | 0xCF | 0xD7 | 0xDF | 0xE7 | 0xEF | 0xF7 | 0xFF => 16,
+        0xCD => 24,
+        0xE0 | 0xF0 | 0xF8 => 12,
+        0xE2 | 0xF2 | 0xF9 => 8,
+        0xC6 | 0xCE | 0xD6 | 0xDE | 0xE6 | 0xEE | 0xF6 | 0xFE => 8,
+        _ => 4,
+    };
+    (cycles, None)
+}
+
+// ADD, ADC and SBC name the accumulator, the others leave it implied.
+fn alu(y: u8, source: Operand, length: u8) -> (&'static str, Vec<Operand>, u8) {
+    let mnemonic = ALU_OPS[y as usize];
+    let operands = match y {
+        0 | 1 | 3 => vec![Operand::Register("a"), source],
+        _ => vec![source],
+    };
+    (mnemonic, operands, length)
+}
+
+const R8: [Operand; 8] = [
+    Operand::Register("b"),
+    Operand::Register("c"),
+    Operand::Register("d"),
+    Operand::Register("e"),
+    Operand::Register("h"),
+    Operand::Register("l"),
+    Operand::Indirect("hl"),
+    Operand::Register("a"),
+];
+
+const R16: [&str; 4] = ["bc", "de", "hl", "sp"];
+
+const R16_MEMORY: [&str; 4] = ["bc", "de", "hl+", "hl-"];
+
+const R16_STACK: [&str; 4] = ["bc", "de", "hl", "af"];
+
+const CONDITIONS: [&str; 4] = ["nz", "z", "nc", "c"];
+
+const ALU_OPS: [&str; 8] = ["add", "adc", "sub", "sbc", "and", "xor", "or", "cp"];
+
+const ACCUMULATOR_OPS: [&str; 8] = ["rlca", "rrca", "rla", "rra", "daa", "cpl", "scf", "ccf"];
+
+const ROTATIONS: [&str; 8] = ["rlc", "rrc", "rl", "rr", "sla", "sra", "swap", "srl"];
+
+#[cfg(test)]
+mod tests {
+    use rstest::rstest;
+
+    use super::*;
+    use crate::cpu::{Cpu, Memory};
+
+    struct Ram(Vec<u8>);
+
+    impl Memory for Ram {
+        fn read(&mut self, address: u16) -> u8 {
+            self.0[address as usize]
+        }
+
+        fn write(&mut self, address: u16, value: u8) {
+            self.0[address as usize] = value;
+        }
+    }
+
+    // Runs the instruction on the CPU with the given flags and returns how long it took.
+    fn run(bytes: [u8; 3], f: u8) -> u32 {
+        let mut ram = Ram(vec![0; 0x10000]);
+        ram.0[0x0100..0x0103].copy_from_slice(&bytes);
+        let mut cpu = Cpu::new();
+        cpu.registers.f = f;
+        cpu.step(&mut ram)
+    }
+
+    #[rstest]
+    #[case(&[0x00], "nop", 1)]
+    #[case(&[0x08, 0x00, 0xC0], "ld [$C000], sp", 3)]
+    #[case(&[0x10, 0x00], "stop", 2)]
+    #[case(&[0x18, 0xFE], "jr $0200", 2)]
+    #[case(&[0x20, 0x05], "jr nz, $0207", 2)]
+    #[case(&[0x21, 0x34, 0x12], "ld hl, $1234", 3)]
+    #[case(&[0x22], "ld [hl+], a", 1)]
+    #[case(&[0x3A], "ld a, [hl-]", 1)]
+    #[case(&[0x36, 0x42], "ld [hl], $42", 2)]
+    #[case(&[0x27], "daa", 1)]
+    #[case(&[0x76], "halt", 1)]
+    #[case(&[0x78], "ld a, b", 1)]
+    #[case(&[0x8E], "adc a, [hl]", 1)]
+    #[case(&[0xAF], "xor a", 1)]
+    #[case(&[0xC3, 0x50, 0x01], "jp $0150", 3)]
+    #[case(&[0xCD, 0x43, 0x28], "call $2843", 3)]
+    #[case(&[0xE0, 0x44], "ldh [$FF44], a", 2)]
+    #[case(&[0xE2], "ldh [c], a", 1)]
+    #[case(&[0xE8, 0xFE], "add sp, -2", 2)]
+    #[case(&[0xF1], "pop af", 1)]
+    #[case(&[0xF8, 0x05], "ld hl, sp + 5", 2)]
+    #[case(&[0xFA, 0x00, 0xD0], "ld a, [$D000]", 3)]
+    #[case(&[0xFE, 0x90], "cp $90", 2)]
+    #[case(&[0xFF], "rst $0038", 1)]
+    #[case(&[0xCB, 0x37], "swap a", 2)]
+    #[case(&[0xCB, 0x7E], "bit 7, [hl]", 2)]
+    #[case(&[0xCB, 0xC1], "set 0, c", 2)]
+    #[case(&[0xD3], "db $D3", 1)]
+    fn test_decode(#[case] bytes: &[u8], #[case] expected: &str, #[case] length: u8) {
+        let instruction = decode(bytes, 0x0200);
+
+        assert_eq!(instruction.to_string(), expected);
+        assert_eq!(instruction.length, length);
+    }
+
+    #[test]
+    fn test_cycles_match_cpu() {
+        for opcode in 0..=0xFFu8 {
+            let bytes = [opcode, 0x00, 0xC0];
+            let instruction = decode(&bytes, 0x0100);
+            // STOP, HALT and the opcodes that lock up the CPU don't finish in a step.
+            if instruction.mnemonic == "db" || opcode == 0x10 || opcode == 0x76 {
+                continue;
+            }
+
+            // Z and C clear, so NZ and NC are taken and Z and C aren't.
+            let cycles = run(bytes, 0x00) as u8;
+            let expected = match (instruction.taken_cycles, (opcode >> 3) & 0x01) {
+                (Some(taken), 0) => taken,
+                _ => instruction.cycles,
+            };
+            assert_eq!(cycles, expected, "opcode {:02X}", opcode);
+        }
+
+        for opcode in 0..=0xFFu8 {
+            let instruction = decode(&[0xCB, opcode], 0x0100);
+            assert_eq!(
+                run([0xCB, opcode, 0x00], 0x00) as u8,
+                instruction.cycles,
+                "opcode CB {:02X}",
+                opcode
+            );
+        }
+    }
+
+    #[test]
+    fn test_disassemble_range() {
+        // Arrange
+        let rom = [0x00, 0xC3, 0x50, 0x01, 0xCB, 0x37];
+
+        // Act
+        let lines = disassemble(
+            |address| rom.get(address as usize).copied().unwrap_or(0),
+            0..5,
+        );
+
+        // Assert
+        let lines: Vec<String> = lines.iter().map(|line| line.to_string()).collect();
+        assert_eq!(
+            lines,
+            [
+                "0000  00        nop",
+                "0001  C3 50 01  jp $0150",
+                "0004  CB 37     swap a",
+            ]
+        );
+    }
+
+    #[rstest]
+    #[case(&[0xCD, 0x43, 0x28], "call UpdateSprites")]
+    #[case(&[0xFA, 0x00, 0xC0], "ld a, [wPlayerX]")]
+    #[case(&[0xC3, 0x50, 0x01], "jp $0150")]
+    #[case(&[0x21, 0x00, 0xC0], "ld hl, $C000")]
+    fn test_format_with_labels(#[case] bytes: &[u8], #[case] expected: &str) {
+        let label = |address| match address {
+            0x2843 => Some("UpdateSprites"),
+            0xC000 => Some("wPlayerX"),
+            _ => None,
+        };
+
+        assert_eq!(decode(bytes, 0x0100).format_with(label), expected);
+    }
+
+    #[test]
+    fn test_decode_past_end_of_bytes() {
+        assert_eq!(decode(&[0xC3], 0x0000).to_string(), "jp $0000");
+    }
+}
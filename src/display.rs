@@ -0,0 +1,126 @@
+use std::io::{Error, Result};
+use std::str::FromStr;
+
+// How the screen fills a window of any size. Pixels are always scaled with nearest neighbor.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Scaling {
+    // The largest whole multiple of the screen that fits, so every pixel is the same size.
+    Integer,
+    // As large as fits with the screen's shape, with bars on the sides or top and bottom.
+    Fit,
+    // The whole window, whatever its shape.
+    Stretch,
+}
+
+impl Scaling {
+    pub const ALL: [Scaling; 3] = [Scaling::Integer, Scaling::Fit, Scaling::Stretch];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Scaling::Integer => "integer",
+            Scaling::Fit => "fit",
+            Scaling::Stretch => "stretch",
+        }
+    }
+
+    pub fn next(self) -> Scaling {
+        let index = Scaling::ALL.iter().position(|&scaling| scaling == self);
+        Scaling::ALL[(index.unwrap_or(0) + 1) % Scaling::ALL.len()]
+    }
+
+    // Where a picture `screen` pixels in size goes in a window `width` by `height` pixels, as x, y,
+    // width and height.
+    pub fn layout(self, width: u32, height: u32, screen: (u32, u32)) -> (i32, i32, u32, u32) {
+        let (screen_width, screen_height) = screen;
+        let (scaled_width, scaled_height) = match self {
+            // A window smaller than the screen gets it shrunk to fit instead.
+            Scaling::Integer if width >= screen_width && height >= screen_height => {
+                let scale = (width / screen_width).min(height / screen_height);
+                (screen_width * scale, screen_height * scale)
+            }
+            Scaling::Integer | Scaling::Fit => {
+                if width * screen_height > height * screen_width {
+                    (height * screen_width / screen_height, height)
+                } else {
+                    (width, width * screen_height / screen_width)
+                }
+            }
+            Scaling::Stretch => (width, height),
+        };
+        (
+            ((width - scaled_width) / 2) as i32,
+            ((height - scaled_height) / 2) as i32,
+            scaled_width,
+            scaled_height,
+        )
+    }
+}
+
+impl FromStr for Scaling {
+    type Err = Error;
+
+    fn from_str(name: &str) -> Result<Scaling> {
+        Scaling::ALL
+            .into_iter()
+            .find(|scaling| scaling.name().eq_ignore_ascii_case(name))
+            .ok_or_else(|| {
+                Error::other(format!(
+                    "{} is not a scaling mode, expected integer, fit or stretch.",
+                    name
+                ))
+            })
+    }
+}
+
+// The window sizes, as multiples of the screen, that stepping through sizes goes through.
+pub const WINDOW_SCALES: std::ops::RangeInclusive<u32> = 2..=6;
+
+#[cfg(test)]
+mod tests {
+    use rstest::rstest;
+
+    use super::*;
+    use crate::ppu::{SCREEN_HEIGHT, SCREEN_WIDTH};
+
+    #[rstest]
+    #[case(Scaling::Integer, 480, 432, (0, 0, 480, 432))]
+    // 3x with room to spare, centered.
+    #[case(Scaling::Integer, 600, 500, (60, 34, 480, 432))]
+    #[case(Scaling::Integer, 100, 100, (0, 5, 100, 90))]
+    #[case(Scaling::Fit, 600, 500, (22, 0, 555, 500))]
+    #[case(Scaling::Fit, 800, 400, (178, 0, 444, 400))]
+    #[case(Scaling::Fit, 320, 600, (0, 156, 320, 288))]
+    #[case(Scaling::Stretch, 800, 400, (0, 0, 800, 400))]
+    fn test_layout(
+        #[case] scaling: Scaling,
+        #[case] width: u32,
+        #[case] height: u32,
+        #[case] expected: (i32, i32, u32, u32),
+    ) {
+        let screen = (SCREEN_WIDTH as u32, SCREEN_HEIGHT as u32);
+
+        assert_eq!(scaling.layout(width, height, screen), expected);
+    }
+
+    #[test]
+    fn test_layout_sgb_border() {
+        assert_eq!(
+            Scaling::Integer.layout(600, 500, (256, 224)),
+            (44, 26, 512, 448)
+        );
+    }
+
+    #[test]
+    fn test_next() {
+        assert_eq!(Scaling::Integer.next(), Scaling::Fit);
+        assert_eq!(Scaling::Stretch.next(), Scaling::Integer);
+    }
+
+    #[rstest]
+    #[case("Integer", Some(Scaling::Integer))]
+    #[case("stretch", Some(Scaling::Stretch))]
+    #[case("crop", None)]
+    fn test_parse(#[case] name: &str, #[case] expected: Option<Scaling>) {
+        assert_eq!(name.parse::<Scaling>().ok(), expected);
+    }
+}
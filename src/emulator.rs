@@ -0,0 +1,1121 @@
+use std::io::{Error, Result};
+use std::path::Path;
+use std::time::Duration;
+
+use crate::accuracy::Accuracy;
+use crate::apu::{Channel, DEFAULT_SAMPLE_RATE};
+use crate::bus::{Bus, MemoryStrictness, MemoryViolation, WatchHit, Watchpoint};
+use crate::camera::CameraSource;
+use crate::cdl::CodeDataLog;
+use crate::cheats::Cheats;
+use crate::colorization::CompatibilityPalettes;
+use crate::cpu::{Cpu, Memory};
+use crate::infrared::IrDevice;
+use crate::joypad::Button;
+use crate::mbc::{Accelerometer, RtcClock};
+use crate::model::EmulatorModel;
+use crate::movie::MovieSession;
+use crate::pacing;
+use crate::palette::Palette;
+use crate::ppu::Layer;
+use crate::profiler::Profiler;
+use crate::rom::Rom;
+use crate::savestate::{self, StateReader, StateWriter};
+#[cfg(feature = "png")]
+use crate::screenshot::{self, ScreenshotOptions};
+#[cfg(feature = "lua")]
+use crate::script::Script;
+use crate::serial::{SerialCallback, SerialDevice};
+use crate::sgb::Sgb;
+use crate::trace::Tracer;
+use crate::video::VideoRecording;
+use crate::wav::AudioRecording;
+
+// How long `Emulator::run_for` runs.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RunLimit {
+    Frames(u32),
+    Cycles(u64),
+}
+
+// What `Emulator::run_frame` finished.
+#[derive(Clone, Copy, Debug)]
+pub struct Frame<'a> {
+    // The screen, see `Emulator::framebuffer`.
+    pub pixels: &'a [u8],
+    // The T-cycles it took.
+    pub cycles: u32,
+}
+
+pub type FrameCallback = Box<dyn FnMut(&[u8])>;
+
+pub type AudioBufferCallback = Box<dyn FnMut(&[f32])>;
+
+// Ties the CPU to the bus and drives both a frame at a time for frontends.
+pub struct Emulator {
+    cpu: Cpu,
+    bus: Bus,
+    has_battery: bool,
+    // The host's audio sample rate, which the APU divides by the speed.
+    sample_rate: u32,
+    speed: f64,
+    movie: Option<MovieSession>,
+    audio_recording: Option<AudioRecording>,
+    video_recording: Option<VideoRecording>,
+    // Drops the sound instead of recording it, see `suspend_recordings`.
+    recordings_suspended: bool,
+    recording_errors: Vec<Error>,
+    profiler: Option<Profiler>,
+    frame_callback: Option<FrameCallback>,
+    audio_callback: Option<AudioBufferCallback>,
+    #[cfg(feature = "lua")]
+    script: Option<Script>,
+    #[cfg(feature = "lua")]
+    script_error: Option<Error>,
+}
+
+impl Emulator {
+    // Picks the model the cartridge was made for, see `EmulatorModel::for_rom`.
+    pub fn new(rom: Rom) -> Result<Emulator> {
+        let model = EmulatorModel::for_rom(&rom)?;
+        Emulator::with_model(rom, model)
+    }
+
+    pub fn with_model(rom: Rom, model: EmulatorModel) -> Result<Emulator> {
+        let has_battery = rom.has_battery()?;
+        let bus = Bus::with_model(rom, model)?;
+        let cpu = Cpu::for_model(model, bus.cgb());
+        Ok(Emulator {
+            cpu,
+            bus,
+            has_battery,
+            sample_rate: DEFAULT_SAMPLE_RATE,
+            speed: 1.0,
+            movie: None,
+            audio_recording: None,
+            video_recording: None,
+            recordings_suspended: false,
+            recording_errors: Vec::new(),
+            profiler: None,
+            frame_callback: None,
+            audio_callback: None,
+            #[cfg(feature = "lua")]
+            script: None,
+            #[cfg(feature = "lua")]
+            script_error: None,
+        })
+    }
+
+    // Runs the given boot ROM before the cartridge instead of starting with post-boot values.
+    pub fn with_boot_rom(rom: Rom, boot_rom: Vec<u8>) -> Result<Emulator> {
+        let mut emulator = Emulator::new(rom)?;
+        emulator.map_boot_rom(boot_rom)?;
+        Ok(emulator)
+    }
+
+    // Goes back to power-on and runs the boot ROM, which only makes sense before the first step.
+    pub fn map_boot_rom(&mut self, boot_rom: Vec<u8>) -> Result<()> {
+        self.bus.map_boot_rom(boot_rom)?;
+        self.cpu = Cpu::power_on();
+        Ok(())
+    }
+
+    // Puts another cartridge in and powers on with the model made for it. What isn't about the
+    // game stays: the sound and display settings, speed, memory strictness, recordings, tracer,
+    // link port device and script. The movie, cheats and watchpoints were for the old game and
+    // go with it, and its battery has to be saved before this.
+    pub fn swap_rom(&mut self, rom: Rom) -> Result<()> {
+        let model = EmulatorModel::for_rom(&rom)?;
+        let mut swapped = Emulator::with_model(rom, model)?;
+        self.write_recordings();
+
+        let (apu, swapped_apu) = (self.bus.apu(), swapped.bus.apu_mut());
+        swapped_apu.set_latency(apu.latency());
+        for channel in Channel::ALL {
+            swapped_apu.set_channel_enabled(channel, apu.channel_enabled(channel));
+            swapped_apu.set_channel_gain(channel, apu.channel_gain(channel));
+        }
+        swapped.sample_rate = self.sample_rate;
+        swapped.speed = self.speed;
+        swapped.apply_sample_rate();
+        swapped.set_dmg_palette(*self.dmg_palette());
+        swapped.set_frame_blend(self.frame_blend());
+        for layer in Layer::ALL {
+            swapped.set_layer_enabled(layer, self.layer_enabled(layer));
+        }
+        swapped.set_memory_strictness(self.bus.memory_strictness());
+        swapped.set_accuracy(self.accuracy());
+        swapped.set_tracer(self.take_tracer());
+        swapped.set_serial_device(self.take_serial_device());
+        swapped.set_ir_device(self.take_ir_device());
+        swapped.on_vblank(self.frame_callback.take());
+        swapped.on_audio_buffer(self.audio_callback.take());
+        swapped.on_serial_byte(self.bus.take_serial_callback());
+        swapped.audio_recording = self.audio_recording.take();
+        swapped.video_recording = self.video_recording.take();
+        swapped.recording_errors = std::mem::take(&mut self.recording_errors);
+        swapped.update_capture(swapped.recording_sample_rate());
+        #[cfg(feature = "lua")]
+        {
+            swapped.script = self.script.take();
+            swapped.script_error = self.script_error.take();
+        }
+        *self = swapped;
+        Ok(())
+    }
+
+    pub fn model(&self) -> EmulatorModel {
+        self.bus.model()
+    }
+
+    pub fn cpu(&self) -> &Cpu {
+        &self.cpu
+    }
+
+    pub fn cpu_mut(&mut self) -> &mut Cpu {
+        &mut self.cpu
+    }
+
+    pub fn bus(&self) -> &Bus {
+        &self.bus
+    }
+
+    pub fn bus_mut(&mut self) -> &mut Bus {
+        &mut self.bus
+    }
+
+    // Reads memory without the side effects a CPU read has, for debuggers and other tools.
+    pub fn peek(&mut self, address: u16) -> u8 {
+        self.bus.peek(address)
+    }
+
+    // Writes memory the way the CPU would.
+    pub fn poke(&mut self, address: u16, value: u8) {
+        self.bus.write(address, value);
+    }
+
+    // Executes a single instruction and returns the number of T-cycles it took.
+    pub fn step(&mut self) -> u32 {
+        let cycles = self.step_cpu();
+        self.bus.sync();
+        cycles
+    }
+
+    fn step_cpu(&mut self) -> u32 {
+        let Some(mut profiler) = self.profiler.take() else {
+            return self.cpu.step(&mut self.bus);
+        };
+        let before = self.cpu.registers;
+        let opcode = self.bus.peek(before.pc);
+        let cycles = self.cpu.step(&mut self.bus);
+        profiler.record(self, &before, opcode, cycles);
+        self.profiler = Some(profiler);
+        cycles
+    }
+
+    // Runs until the PPU finishes a frame and returns it. With the LCD off no frame ever finishes,
+    // so this gives up after the cycles a frame would have taken.
+    pub fn run_frame(&mut self) -> Frame<'_> {
+        if let Some(mut movie) = self.movie.take() {
+            movie.advance(self);
+            self.movie = Some(movie);
+        }
+        let frame = self.bus.ppu().frames();
+        let mut cycles = 0;
+        while self.bus.ppu().frames() == frame && cycles < self.cycles_per_frame() {
+            cycles += self.step_cpu();
+        }
+        self.bus.sync();
+        self.write_recordings();
+        #[cfg(feature = "lua")]
+        self.run_script();
+        self.notify_observers();
+        Frame {
+            pixels: self.framebuffer(),
+            cycles,
+        }
+    }
+
+    // Called at the end of every `run_frame` with the screen, see `framebuffer`. That's also when
+    // the LCD is off and no frame really finished, so a frontend always has something to show.
+    pub fn on_vblank(&mut self, callback: Option<FrameCallback>) {
+        self.frame_callback = callback;
+    }
+
+    // Called at the end of every `run_frame` with the interleaved stereo samples made during it.
+    // They're taken from the APU, so `fill_audio_buffer` finds none while this is set.
+    pub fn on_audio_buffer(&mut self, callback: Option<AudioBufferCallback>) {
+        self.audio_callback = callback;
+    }
+
+    // See `Serial::set_callback`.
+    pub fn on_serial_byte(&mut self, callback: Option<SerialCallback>) {
+        self.bus.set_serial_callback(callback);
+    }
+
+    // What `run_frame` does at its end, for frontends that build their frames out of `run_for`.
+    pub fn notify_observers(&mut self) {
+        if let Some(callback) = self.frame_callback.as_mut() {
+            callback(
+                self.bus
+                    .sgb()
+                    .map_or(self.bus.ppu().framebuffer(), Sgb::screen),
+            );
+        }
+        if self.audio_callback.is_some() {
+            let samples = self.audio_samples();
+            if let Some(callback) = self.audio_callback.as_mut() {
+                callback(&samples);
+            }
+        }
+    }
+
+    // Records the audio to `path` at the current sample rate, whatever the speed, and with `stems`
+    // each channel to a file of its own, see `AudioRecording`. Replaces any audio recording going
+    // on.
+    pub fn start_audio_recording(&mut self, path: &Path, stems: bool) -> Result<()> {
+        self.stop_audio_recording()?;
+        let sample_rate = self.recording_sample_rate();
+        self.audio_recording = Some(AudioRecording::create(path, sample_rate, stems)?);
+        self.update_capture(sample_rate);
+        Ok(())
+    }
+
+    pub fn stop_audio_recording(&mut self) -> Result<()> {
+        self.write_recordings();
+        let result = match self.audio_recording.take() {
+            Some(recording) => recording.finish(),
+            None => Ok(()),
+        };
+        self.update_capture(self.recording_sample_rate());
+        result
+    }
+
+    pub fn recording_audio(&self) -> bool {
+        self.audio_recording.is_some()
+    }
+
+    // Records the screen and sound to `path`, see `VideoRecording`. Replaces any video recording
+    // going on.
+    pub fn start_video_recording(&mut self, path: &Path) -> Result<()> {
+        self.stop_video_recording()?;
+        let sample_rate = self.recording_sample_rate();
+        self.video_recording = Some(VideoRecording::start(path, sample_rate)?);
+        self.update_capture(sample_rate);
+        Ok(())
+    }
+
+    pub fn stop_video_recording(&mut self) -> Result<()> {
+        self.write_recordings();
+        let result = match self.video_recording.take() {
+            Some(recording) => recording.finish(),
+            None => Ok(()),
+        };
+        self.update_capture(self.recording_sample_rate());
+        result
+    }
+
+    pub fn recording_video(&self) -> bool {
+        self.video_recording.is_some()
+    }
+
+    // Why recordings stopped since the last call. A recording that fails is stopped rather than
+    // failing again every frame.
+    pub fn take_recording_errors(&mut self) -> Vec<Error> {
+        std::mem::take(&mut self.recording_errors)
+    }
+
+    // Leaves out what's run from the recordings until resumed, like frames run again after
+    // loading a state that were already recorded the first time round.
+    pub fn suspend_recordings(&mut self, suspended: bool) {
+        self.recordings_suspended = suspended;
+    }
+
+    // Recordings going on at the same time share the APU's capture, so they share its rate.
+    fn recording_sample_rate(&self) -> u32 {
+        self.audio_recording
+            .as_ref()
+            .map(AudioRecording::sample_rate)
+            .or(self
+                .video_recording
+                .as_ref()
+                .map(VideoRecording::sample_rate))
+            .unwrap_or(self.sample_rate)
+    }
+
+    // Makes the capture match the recordings, restarting it only when the stems change.
+    fn update_capture(&mut self, sample_rate: u32) {
+        let stems = self
+            .audio_recording
+            .as_ref()
+            .is_some_and(AudioRecording::stems);
+        let recording = self.audio_recording.is_some() || self.video_recording.is_some();
+        let apu = self.bus.apu_mut();
+        if !recording {
+            apu.stop_capture();
+        } else if apu.capture_stems() != Some(stems) {
+            apu.start_capture(sample_rate, stems);
+        }
+    }
+
+    // See `take_recording_errors`.
+    fn write_recordings(&mut self) {
+        if self.audio_recording.is_none() && self.video_recording.is_none() {
+            return;
+        }
+        let tracks = self.bus.apu_mut().take_captured();
+        if self.recordings_suspended {
+            return;
+        }
+        if let Some(recording) = self.audio_recording.as_mut() {
+            if let Err(error) = recording.write(&tracks) {
+                self.audio_recording = None;
+                self.recording_errors.push(Error::new(
+                    error.kind(),
+                    format!("Stopped recording audio: {}", error),
+                ));
+            }
+        }
+        if let Some(recording) = self.video_recording.as_mut() {
+            let frame = self
+                .bus
+                .sgb()
+                .map_or(self.bus.ppu().framebuffer(), Sgb::screen);
+            if let Err(error) = recording.write(frame, &tracks[0]) {
+                self.video_recording = None;
+                self.recording_errors.push(Error::new(
+                    error.kind(),
+                    format!("Stopped recording video: {}", error),
+                ));
+            }
+        }
+        self.update_capture(self.recording_sample_rate());
+    }
+
+    // A movie that records or replaces the buttons at the start of every `run_frame`. Loading a
+    // state leaves it where it was, see `MovieSession::seek`.
+    pub fn set_movie(&mut self, movie: Option<MovieSession>) {
+        self.movie = movie;
+    }
+
+    pub fn movie(&self) -> Option<&MovieSession> {
+        self.movie.as_ref()
+    }
+
+    pub fn movie_mut(&mut self) -> Option<&mut MovieSession> {
+        self.movie.as_mut()
+    }
+
+    pub fn take_movie(&mut self) -> Option<MovieSession> {
+        self.movie.take()
+    }
+
+    // A script whose hooks run after every `run_frame`. It isn't part of save states.
+    #[cfg(feature = "lua")]
+    pub fn set_script(&mut self, script: Option<Script>) {
+        self.script = script;
+    }
+
+    #[cfg(feature = "lua")]
+    pub fn take_script(&mut self) -> Option<Script> {
+        self.script.take()
+    }
+
+    // Why the script stopped, once. A script that fails is stopped rather than failing again
+    // every frame.
+    #[cfg(feature = "lua")]
+    pub fn take_script_error(&mut self) -> Option<Error> {
+        self.script_error.take()
+    }
+
+    #[cfg(feature = "lua")]
+    fn run_script(&mut self) {
+        if let Some(mut script) = self.script.take() {
+            match script.frame(self) {
+                Ok(()) => self.script = Some(script),
+                Err(error) => {
+                    self.script_error = Some(Error::new(
+                        error.kind(),
+                        format!("Stopped the script: {}", error),
+                    ))
+                }
+            }
+        }
+    }
+
+    // Runs without a frontend and returns the T-cycles it took. Frames are counted like
+    // `run_frame` does, so frames with the LCD off count too.
+    pub fn run_for(&mut self, limit: RunLimit) -> u64 {
+        match limit {
+            RunLimit::Frames(frames) => (0..frames).map(|_| self.run_frame().cycles as u64).sum(),
+            RunLimit::Cycles(cycles) => {
+                let mut elapsed = 0;
+                while elapsed < cycles {
+                    elapsed += self.step_cpu() as u64;
+                }
+                self.bus.sync();
+                self.write_recordings();
+                elapsed
+            }
+        }
+    }
+
+    // Double speed mode fits twice as many CPU cycles into a frame.
+    fn cycles_per_frame(&self) -> u32 {
+        if self.bus.double_speed() {
+            CYCLES_PER_FRAME * 2
+        } else {
+            CYCLES_PER_FRAME
+        }
+    }
+
+    // The last frame as a PNG.
+    #[cfg(feature = "png")]
+    pub fn screenshot(&self, options: ScreenshotOptions) -> Result<Vec<u8>> {
+        screenshot::encode(self.bus.ppu(), self.framebuffer(), options)
+    }
+
+    // 160x144 pixels, 4 bytes (RGBA) per pixel. An SGB game's are the SGB's colors.
+    pub fn framebuffer(&self) -> &[u8] {
+        self.bus
+            .sgb()
+            .map_or(self.bus.ppu().framebuffer(), Sgb::screen)
+    }
+
+    // An SGB game's 256x224 picture, the screen inside its border, or None for other games.
+    pub fn sgb_frame(&self) -> Option<Vec<u8>> {
+        self.bus.sgb().map(Sgb::bordered_screen)
+    }
+
+    // Picks other colors for a DMG game on a CGB, like holding a button combination during the
+    // boot ROM's logo does. Only does something in compatibility mode.
+    pub fn set_compatibility_palettes(&mut self, palettes: CompatibilityPalettes) {
+        self.bus.ppu_mut().set_compatibility_palettes(palettes);
+    }
+
+    // See `Ppu::set_dmg_palette`.
+    pub fn dmg_palette(&self) -> &Palette {
+        self.bus.ppu().dmg_palette()
+    }
+
+    pub fn set_dmg_palette(&mut self, palette: Palette) {
+        self.bus.ppu_mut().set_dmg_palette(palette);
+    }
+
+    // See `Ppu::set_frame_blend`.
+    pub fn frame_blend(&self) -> usize {
+        self.bus.ppu().frame_blend()
+    }
+
+    pub fn set_frame_blend(&mut self, frames: usize) {
+        self.bus.ppu_mut().set_frame_blend(frames);
+    }
+
+    // See `Ppu::set_layer_enabled`.
+    pub fn layer_enabled(&self, layer: Layer) -> bool {
+        self.bus.ppu().layer_enabled(layer)
+    }
+
+    pub fn set_layer_enabled(&mut self, layer: Layer, enabled: bool) {
+        self.bus.ppu_mut().set_layer_enabled(layer, enabled);
+    }
+
+    // See `Ppu::set_accuracy`.
+    pub fn accuracy(&self) -> Accuracy {
+        self.bus.ppu().accuracy()
+    }
+
+    pub fn set_accuracy(&mut self, accuracy: Accuracy) {
+        self.bus.ppu_mut().set_accuracy(accuracy);
+    }
+
+    pub fn set_button(&mut self, button: Button, pressed: bool) {
+        self.bus.joypad_mut().set_button(button, pressed);
+    }
+
+    pub fn press(&mut self, button: Button) {
+        self.set_button(button, true);
+    }
+
+    pub fn release(&mut self, button: Button) {
+        self.set_button(button, false);
+    }
+
+    // See `Joypad::buttons`.
+    pub fn buttons(&self) -> u8 {
+        self.bus.joypad().buttons()
+    }
+
+    pub fn set_buttons(&mut self, buttons: u8) {
+        self.bus.joypad_mut().set_buttons(buttons);
+    }
+
+    pub fn set_sample_rate(&mut self, sample_rate: u32) {
+        self.sample_rate = sample_rate;
+        self.apply_sample_rate();
+    }
+
+    // See `Apu::set_latency`.
+    pub fn set_audio_latency(&mut self, latency: Duration) {
+        self.bus.apu_mut().set_latency(latency);
+    }
+
+    pub fn speed(&self) -> f64 {
+        self.speed
+    }
+
+    // Tells the emulator the host runs it `speed` times faster than the hardware, see
+    // `pacing::FramePacer`. Audio keeps up by making fewer samples per emulated second, which
+    // raises the pitch when faster and lowers it in slow motion.
+    pub fn set_speed(&mut self, speed: f64) -> Result<()> {
+        self.speed = pacing::check_speed(speed)?;
+        self.apply_sample_rate();
+        Ok(())
+    }
+
+    // Scales the rate samples are made at by `ratio` without interrupting the sound, see
+    // `pacing::rate_control`. Setting the sample rate or speed undoes it.
+    pub fn adjust_sample_rate(&mut self, ratio: f64) {
+        let sample_rate = (self.sample_rate as f64 * ratio / self.speed).round() as u32;
+        self.bus.apu_mut().adjust_sample_rate(sample_rate.max(1));
+    }
+
+    fn apply_sample_rate(&mut self) {
+        let sample_rate = (self.sample_rate as f64 / self.speed).round() as u32;
+        self.bus.apu_mut().set_sample_rate(sample_rate.max(1));
+    }
+
+    pub fn cheats(&self) -> &Cheats {
+        self.bus.cheats()
+    }
+
+    pub fn cheats_mut(&mut self) -> &mut Cheats {
+        self.bus.cheats_mut()
+    }
+
+    pub fn set_memory_strictness(&mut self, strictness: MemoryStrictness) {
+        self.bus.set_memory_strictness(strictness);
+    }
+
+    // See `Bus::take_memory_violations`.
+    pub fn take_memory_violations(&mut self) -> Vec<MemoryViolation> {
+        self.bus.take_memory_violations()
+    }
+
+    pub fn add_watchpoint(&mut self, watchpoint: Watchpoint) {
+        self.bus.add_watchpoint(watchpoint);
+    }
+
+    pub fn remove_watchpoint(&mut self, watchpoint: &Watchpoint) -> bool {
+        self.bus.remove_watchpoint(watchpoint)
+    }
+
+    // See `Bus::take_watch_hits`.
+    pub fn take_watch_hits(&mut self) -> Vec<WatchHit> {
+        self.bus.take_watch_hits()
+    }
+
+    pub fn set_serial_device(&mut self, device: Option<Box<dyn SerialDevice>>) {
+        self.bus.set_serial_device(device);
+    }
+
+    pub fn take_serial_device(&mut self) -> Option<Box<dyn SerialDevice>> {
+        self.bus.take_serial_device()
+    }
+
+    pub fn set_accelerometer(&mut self, accelerometer: Option<Box<dyn Accelerometer>>) {
+        self.bus.set_accelerometer(accelerometer);
+    }
+
+    pub fn set_camera_source(&mut self, source: Option<Box<dyn CameraSource>>) {
+        self.bus.set_camera_source(source);
+    }
+
+    pub fn set_ir_device(&mut self, device: Option<Box<dyn IrDevice>>) {
+        self.bus.set_ir_device(device);
+    }
+
+    pub fn take_ir_device(&mut self) -> Option<Box<dyn IrDevice>> {
+        self.bus.take_ir_device()
+    }
+
+    pub fn set_tracer(&mut self, tracer: Option<Tracer>) {
+        self.cpu.set_tracer(tracer);
+    }
+
+    pub fn take_tracer(&mut self) -> Option<Tracer> {
+        self.cpu.take_tracer()
+    }
+
+    // See `Bus::set_code_data_log`.
+    pub fn set_code_data_log(&mut self, log: Option<CodeDataLog>) {
+        self.bus.set_code_data_log(log);
+    }
+
+    pub fn take_code_data_log(&mut self) -> Option<CodeDataLog> {
+        self.bus.take_code_data_log()
+    }
+
+    // Attributes every instruction's cycles to the routine it's in from now on, see `Profiler`.
+    pub fn set_profiler(&mut self, profiler: Option<Profiler>) {
+        self.profiler = profiler;
+    }
+
+    pub fn take_profiler(&mut self) -> Option<Profiler> {
+        self.profiler.take()
+    }
+
+    pub fn has_battery(&self) -> bool {
+        self.has_battery
+    }
+
+    // The battery-backed cartridge state, see `Mbc::save_data`.
+    pub fn save_data(&self) -> Vec<u8> {
+        self.bus.mbc().save_data()
+    }
+
+    pub fn load_save_data(&mut self, data: &[u8]) {
+        self.bus.mbc_mut().load_save_data(data);
+    }
+
+    // Makes a run depend only on the ROM, the state it starts from and the buttons pressed, by
+    // having the cartridge clock count emulated time instead of the host's. Everything else
+    // already is, work RAM included, which starts with the same pattern every time. Save states
+    // keep the setting.
+    pub fn set_deterministic(&mut self, deterministic: bool) {
+        let clock = if deterministic {
+            RtcClock::Emulated
+        } else {
+            RtcClock::Host
+        };
+        self.bus.mbc_mut().set_rtc_clock(clock);
+    }
+
+    // A 64-bit FNV-1a hash of the save state, for checking that two runs ended up in the same
+    // place. The state includes the audio filter, so compare runs at the same sample rate.
+    pub fn state_hash(&self) -> u64 {
+        self.save_state()
+            .iter()
+            .fold(FNV_OFFSET_BASIS, |hash, &byte| {
+                (hash ^ byte as u64).wrapping_mul(FNV_PRIME)
+            })
+    }
+
+    pub fn save_state(&self) -> Vec<u8> {
+        let mut writer = StateWriter::new();
+        savestate::write_header(&mut writer);
+        writer.write_bytes(&self.cartridge_checksums());
+        writer.write_snapshot(&self.cpu);
+        writer.write_snapshot(&self.bus);
+        writer.into_bytes()
+    }
+
+    // Leaves the emulator untouched if the state can't be loaded.
+    pub fn load_state(&mut self, state: &[u8]) -> Result<()> {
+        let backup = self.save_state();
+        let result = self.load_state_unchecked(state);
+        if result.is_err() {
+            self.load_state_unchecked(&backup)
+                .expect("restoring the state taken before loading failed");
+        }
+        result
+    }
+
+    fn load_state_unchecked(&mut self, state: &[u8]) -> Result<()> {
+        let mut reader = StateReader::new(state);
+        savestate::read_header(&mut reader)?;
+        if reader.read_bytes()? != self.cartridge_checksums() {
+            return Err(Error::other(
+                "The save state was made with a different ROM.",
+            ));
+        }
+
+        reader.read_snapshot(&mut self.cpu)?;
+        reader.read_snapshot(&mut self.bus)?;
+        if !reader.is_empty() {
+            return Err(Error::other("The save state has unexpected trailing data."));
+        }
+
+        Ok(())
+    }
+
+    // The header and global checksums identify the ROM a state belongs to.
+    fn cartridge_checksums(&self) -> [u8; 3] {
+        let mbc = self.bus.mbc();
+        [
+            mbc.read_rom(0x014D),
+            mbc.read_rom(0x014E),
+            mbc.read_rom(0x014F),
+        ]
+    }
+
+    // Moves interleaved stereo samples into `buffer`, see `Apu::fill_buffer`.
+    pub fn fill_audio_buffer(&mut self, buffer: &mut [f32]) -> usize {
+        self.bus.apu_mut().fill_buffer(buffer)
+    }
+
+    // All the interleaved stereo samples made since they were last taken, for embedders that
+    // don't keep a buffer of their own.
+    pub fn audio_samples(&mut self) -> Vec<f32> {
+        let mut samples = vec![0.0; self.bus.apu().samples_available() & !1];
+        self.fill_audio_buffer(&mut samples);
+        samples
+    }
+}
+
+pub const CYCLES_PER_FRAME: u32 = 70224;
+
+const FNV_OFFSET_BASIS: u64 = 0xCBF2_9CE4_8422_2325;
+
+const FNV_PRIME: u64 = 0x0000_0100_0000_01B3;
+
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    use tempfile::tempdir;
+
+    use super::*;
+    use crate::apu::CPU_CLOCK;
+    use crate::cheats::Cheat;
+    use crate::ppu::{SCREEN_HEIGHT, SCREEN_WIDTH};
+
+    fn emulator(program: &[u8]) -> Emulator {
+        let mut content = vec![0; 0x8000];
+        content[0x100..0x100 + program.len()].copy_from_slice(program);
+        Emulator::new(Rom::from_content(content)).unwrap()
+    }
+
+    #[test]
+    fn test_run_frame_stops_at_frame_boundary() {
+        // JR -2
+        let mut emulator = emulator(&[0x18, 0xFE]);
+
+        emulator.run_frame();
+        let frame = emulator.run_frame();
+
+        assert_eq!(frame.pixels.len(), SCREEN_WIDTH * SCREEN_HEIGHT * 4);
+        assert!(frame.cycles.abs_diff(CYCLES_PER_FRAME) < 12);
+        assert_eq!(emulator.bus().ppu().frames(), 2);
+    }
+
+    #[test]
+    fn test_run_frame_with_lcd_off() {
+        // LD A, 0; LDH (0x40), A; JR -2
+        let mut emulator = emulator(&[0x3E, 0x00, 0xE0, 0x40, 0x18, 0xFE]);
+
+        let cycles = emulator.run_frame().cycles;
+
+        assert_eq!(emulator.bus().ppu().frames(), 0);
+        assert!(cycles >= CYCLES_PER_FRAME);
+    }
+
+    #[test]
+    fn test_swap_rom() {
+        // Arrange
+        let mut emulator = emulator(&[0x18, 0xFE]);
+        emulator.set_speed(2.0).unwrap();
+        emulator.set_frame_blend(2);
+        emulator.set_accuracy(Accuracy::High);
+        emulator.cheats_mut().add(Cheat::new("01FF34C1").unwrap());
+        emulator.run_frame();
+        let mut content = vec![0; 0x8000];
+        // JR -2 behind a NOP, and the CGB flag.
+        content[0x100..0x103].copy_from_slice(&[0x00, 0x18, 0xFE]);
+        content[0x143] = 0x80;
+
+        // Act
+        emulator.swap_rom(Rom::from_content(content)).unwrap();
+
+        // Assert
+        assert_eq!(emulator.model(), EmulatorModel::Cgb);
+        assert_eq!(emulator.peek(0x100), 0x00);
+        assert_eq!(emulator.bus().ppu().frames(), 0);
+        assert_eq!((emulator.speed(), emulator.frame_blend()), (2.0, 2));
+        assert_eq!(emulator.accuracy(), Accuracy::High);
+        assert!(emulator.cheats().is_empty());
+    }
+
+    #[test]
+    fn test_run_for_frames() {
+        let mut emulator = emulator(&[0x18, 0xFE]);
+
+        emulator.run_for(RunLimit::Frames(3));
+
+        assert_eq!(emulator.bus().ppu().frames(), 3);
+    }
+
+    #[test]
+    fn test_run_for_cycles() {
+        let mut emulator = emulator(&[0x18, 0xFE]);
+
+        let cycles = emulator.run_for(RunLimit::Cycles(1000));
+
+        // JR takes 12 cycles, so the last one can overshoot.
+        assert!((1000..1012).contains(&cycles));
+    }
+
+    #[test]
+    fn test_set_speed_scales_audio() {
+        let mut emulator = emulator(&[0x18, 0xFE]);
+        emulator.set_sample_rate(48000);
+
+        emulator.set_speed(2.0).unwrap();
+
+        assert_eq!(emulator.bus_mut().apu_mut().sample_rate(), 24000);
+        assert!(emulator.set_speed(0.0).is_err());
+        assert_eq!(emulator.speed(), 2.0);
+    }
+
+    #[test]
+    fn test_adjust_sample_rate_keeps_samples() {
+        // Arrange
+        let mut emulator = emulator(&[0x18, 0xFE]);
+        emulator.set_sample_rate(48000);
+        emulator.run_for(RunLimit::Cycles(CYCLES_PER_FRAME as u64 / 2));
+        let waiting = emulator.bus_mut().apu_mut().samples_available();
+
+        // Act
+        emulator.adjust_sample_rate(1.005);
+
+        // Assert
+        assert_eq!(emulator.bus_mut().apu_mut().sample_rate(), 48240);
+        assert_eq!(emulator.bus_mut().apu_mut().samples_available(), waiting);
+        assert!(waiting > 0);
+    }
+
+    #[test]
+    fn test_audio_samples_drains_the_apu() {
+        let mut emulator = emulator(&[0x18, 0xFE]);
+        emulator.set_sample_rate(48000);
+        emulator.run_frame();
+
+        let samples = emulator.audio_samples();
+
+        assert_eq!(samples.len() % 2, 0);
+        assert!(!samples.is_empty());
+        assert!(emulator.audio_samples().is_empty());
+    }
+
+    #[test]
+    fn test_observers() {
+        // Arrange
+        // LD A, 0x42; LDH (0x01), A; LD A, 0x81; LDH (0x02), A; JR -2
+        let mut emulator = emulator(&[0x3E, 0x42, 0xE0, 0x01, 0x3E, 0x81, 0xE0, 0x02, 0x18, 0xFE]);
+        let seen = Rc::new(RefCell::new((0, 0, Vec::new())));
+        let (frames, audio, serial) = (seen.clone(), seen.clone(), seen.clone());
+        emulator.on_vblank(Some(Box::new(move |frame| {
+            assert_eq!(frame.len(), 160 * 144 * 4);
+            frames.borrow_mut().0 += 1;
+        })));
+        emulator.on_audio_buffer(Some(Box::new(move |samples| {
+            audio.borrow_mut().1 += samples.len();
+        })));
+        emulator.on_serial_byte(Some(Box::new(move |byte| serial.borrow_mut().2.push(byte))));
+
+        // Act
+        emulator.run_frame();
+        emulator.run_frame();
+
+        // Assert
+        let (frames, samples, bytes) = &*seen.borrow();
+        assert_eq!(*frames, 2);
+        assert!(*samples > 0);
+        assert_eq!(*bytes, [0x42]);
+        assert!(emulator.audio_samples().is_empty());
+    }
+
+    #[test]
+    fn test_audio_recording_ignores_speed() {
+        // Arrange
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("out.wav");
+        let mut emulator = emulator(&[0x18, 0xFE]);
+        emulator.set_sample_rate(48000);
+        emulator.set_speed(2.0).unwrap();
+
+        // Act
+        emulator.start_audio_recording(&path, true).unwrap();
+        let cycles = emulator.run_for(RunLimit::Cycles(CPU_CLOCK / 8));
+        emulator.stop_audio_recording().unwrap();
+
+        // Assert
+        assert!(!emulator.recording_audio());
+        // An eighth of a second of 16-bit stereo at 48 kHz, less what the resampler holds back.
+        let size = std::fs::metadata(&path).unwrap().len();
+        let expected = 44 + cycles * 48000 / CPU_CLOCK * 4;
+        assert!((expected - 200..=expected).contains(&size));
+        assert!(dir.path().join("out-noise.wav").exists());
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_audio_recording_error() {
+        // Arrange
+        let mut emulator = emulator(&[0x18, 0xFE]);
+        // Every write fails with the disk full.
+        emulator
+            .start_audio_recording(Path::new("/dev/full"), false)
+            .unwrap();
+
+        // Act
+        emulator.run_for(RunLimit::Cycles(CPU_CLOCK));
+
+        // Assert
+        let errors = emulator.take_recording_errors();
+        assert_eq!(errors.len(), 1);
+        assert!(errors[0].to_string().starts_with("Stopped recording audio"));
+        assert!(!emulator.recording_audio());
+        assert!(emulator.take_recording_errors().is_empty());
+    }
+
+    #[test]
+    fn test_suspended_recordings_leave_out_sound() {
+        // Arrange
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("out.wav");
+        let mut emulator = emulator(&[0x18, 0xFE]);
+        emulator.start_audio_recording(&path, false).unwrap();
+
+        // Act
+        emulator.suspend_recordings(true);
+        emulator.run_for(RunLimit::Cycles(CPU_CLOCK / 8));
+        emulator.suspend_recordings(false);
+        emulator.stop_audio_recording().unwrap();
+
+        // Assert
+        // Only the WAV header.
+        assert_eq!(std::fs::metadata(&path).unwrap().len(), 44);
+    }
+
+    #[test]
+    fn test_video_recording_needs_a_video_file() {
+        let mut emulator = emulator(&[0x18, 0xFE]);
+
+        let result = emulator.start_video_recording(Path::new("out.wav"));
+
+        assert!(result.is_err());
+        assert!(!emulator.recording_video());
+        assert_eq!(emulator.bus_mut().apu_mut().capture_stems(), None);
+    }
+
+    #[test]
+    fn test_save_and_load_state() {
+        // Arrange
+        // INC A; LD (0xC000), A; JR -6
+        let mut emulator = emulator(&[0x3C, 0xEA, 0x00, 0xC0, 0x18, 0xFA]);
+        emulator.run_frame();
+        let state = emulator.save_state();
+        let a = emulator.cpu().registers.a;
+        let frames = emulator.bus().ppu().frames();
+
+        // Act
+        emulator.run_frame();
+        emulator.load_state(&state).unwrap();
+
+        // Assert
+        assert_eq!(emulator.save_state(), state);
+        assert_eq!(emulator.cpu().registers.a, a);
+        assert_eq!(emulator.bus_mut().read(0xC000), a);
+        assert_eq!(emulator.bus().ppu().frames(), frames);
+    }
+
+    #[test]
+    fn test_load_state_from_different_rom() {
+        let mut content = vec![0; 0x8000];
+        content[0x14E] = 0x12;
+        let other = Emulator::new(Rom::from_content(content)).unwrap();
+        let mut emulator = emulator(&[]);
+
+        assert!(emulator.load_state(&other.save_state()).is_err());
+    }
+
+    #[test]
+    fn test_failed_load_keeps_state() {
+        let mut emulator = emulator(&[0x3C, 0x18, 0xFD]);
+        emulator.run_frame();
+        let before = emulator.save_state();
+
+        let result = emulator.load_state(&before[..before.len() - 1]);
+
+        assert!(result.is_err());
+        assert_eq!(emulator.save_state(), before);
+    }
+
+    #[test]
+    fn test_cgb_cartridge_starts_in_cgb_mode() {
+        let mut content = vec![0; 0x8000];
+        content[0x143] = 0x80;
+
+        let emulator = Emulator::new(Rom::from_content(content)).unwrap();
+
+        assert!(emulator.bus().cgb());
+        assert_eq!(emulator.cpu().registers.a, 0x11);
+    }
+
+    #[test]
+    fn test_cgb_runs_dmg_cartridge_in_compatibility_mode() {
+        let emulator =
+            Emulator::with_model(Rom::from_content(vec![0; 0x8000]), EmulatorModel::Cgb).unwrap();
+
+        assert!(!emulator.bus().cgb());
+        assert_eq!(emulator.cpu().registers.a, 0x11);
+        assert_eq!(emulator.cpu().registers.e, 0x08);
+    }
+
+    #[test]
+    fn test_load_state_from_different_model() {
+        let dmg = emulator(&[]);
+        let mut mgb =
+            Emulator::with_model(Rom::from_content(vec![0; 0x8000]), EmulatorModel::Mgb).unwrap();
+
+        assert!(mgb.load_state(&dmg.save_state()).is_err());
+    }
+
+    #[test]
+    fn test_set_button_reaches_joypad() {
+        let mut emulator = emulator(&[]);
+        emulator.bus_mut().write(0xFF00, 0x10);
+
+        emulator.set_button(Button::A, true);
+
+        assert_eq!(emulator.bus_mut().read(0xFF00), 0xDE);
+    }
+
+    #[test]
+    fn test_press_and_release() {
+        let mut emulator = emulator(&[]);
+        emulator.bus_mut().write(0xFF00, 0x20);
+
+        emulator.press(Button::Down);
+        let pressed = emulator.bus_mut().read(0xFF00);
+        emulator.release(Button::Down);
+
+        assert_eq!(pressed, 0xE7);
+        assert_eq!(emulator.bus_mut().read(0xFF00), 0xEF);
+    }
+
+    #[test]
+    fn test_boot_rom_hands_over_to_cartridge() {
+        // Arrange
+        // LD A, 1; LDH (0x50), A, padded with NOPs up to 0x0100.
+        let mut boot_rom = vec![0; 0x100];
+        boot_rom[0xFC..].copy_from_slice(&[0x3E, 0x01, 0xE0, 0x50]);
+        boot_rom[0] = 0xC3;
+        boot_rom[1] = 0xFC;
+        let mut emulator =
+            Emulator::with_boot_rom(Rom::from_content(vec![0; 0x8000]), boot_rom).unwrap();
+        let pc = emulator.cpu().registers.pc;
+
+        // Act
+        for _ in 0..3 {
+            emulator.step();
+        }
+
+        // Assert
+        assert_eq!(pc, 0x0000);
+        assert_eq!(emulator.cpu().registers.pc, 0x0100);
+        assert!(!emulator.bus().boot_rom_mapped());
+    }
+}
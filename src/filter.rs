@@ -0,0 +1,77 @@
+use std::io::{Error, Result};
+use std::str::FromStr;
+
+// Post-processing that makes the screen look more like the real one. The window draws them with
+// shaders, splitting each Game Boy pixel into 3 columns and 3 rows for the gaps between the LCD's
+// cells whatever size it's drawn at.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Filter {
+    None,
+    // A color LCD's red, green and blue columns, with a dark line between rows.
+    Lcd,
+    // The DMG's dot matrix: light gaps between the cells, and cells that are slow to change, which
+    // leaves a trail behind anything moving.
+    DotMatrix,
+    // Every third line darkened, like a CRT's scanlines.
+    Scanlines,
+}
+
+impl Filter {
+    pub const ALL: [Filter; 4] = [
+        Filter::None,
+        Filter::Lcd,
+        Filter::DotMatrix,
+        Filter::Scanlines,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Filter::None => "none",
+            Filter::Lcd => "lcd",
+            Filter::DotMatrix => "dot-matrix",
+            Filter::Scanlines => "scanlines",
+        }
+    }
+
+    pub fn next(self) -> Filter {
+        let index = Filter::ALL.iter().position(|&filter| filter == self);
+        Filter::ALL[(index.unwrap_or(0) + 1) % Filter::ALL.len()]
+    }
+}
+
+impl FromStr for Filter {
+    type Err = Error;
+
+    fn from_str(name: &str) -> Result<Filter> {
+        Filter::ALL
+            .into_iter()
+            .find(|filter| filter.name().eq_ignore_ascii_case(name))
+            .ok_or_else(|| {
+                Error::other(format!(
+                    "{} is not a filter, expected none, lcd, dot-matrix or scanlines.",
+                    name
+                ))
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rstest::rstest;
+
+    use super::*;
+
+    #[rstest]
+    #[case("LCD", Some(Filter::Lcd))]
+    #[case("dot-matrix", Some(Filter::DotMatrix))]
+    #[case("crt", None)]
+    fn test_parse(#[case] name: &str, #[case] expected: Option<Filter>) {
+        assert_eq!(name.parse::<Filter>().ok(), expected);
+    }
+
+    #[test]
+    fn test_next_wraps_around() {
+        assert_eq!(Filter::None.next(), Filter::Lcd);
+        assert_eq!(Filter::Scanlines.next(), Filter::None);
+    }
+}
@@ -0,0 +1,980 @@
+use std::cell::Cell;
+use std::collections::HashMap;
+use std::fs;
+use std::io::{Error, Result};
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use rustygameboy::battery;
+use rustygameboy::cheats::{Cheat, Cheats};
+use rustygameboy::config::{ControllerProfile, Controllers, RecentRoms};
+use rustygameboy::display::{Scaling, WINDOW_SCALES};
+use rustygameboy::emulator::Emulator;
+use rustygameboy::filter::Filter;
+use rustygameboy::input::{self, InputMap, Target};
+use rustygameboy::joypad::Button;
+use rustygameboy::mbc::Tilt;
+use rustygameboy::netplay::Netplay;
+use rustygameboy::pacing::{self, FramePacer, RefreshPacer, SyncMode, FRAME_DURATION};
+use rustygameboy::palette::{Palette, PalettePreset};
+use rustygameboy::paths;
+use rustygameboy::ppu::{Layer, SCREEN_HEIGHT, SCREEN_WIDTH};
+use rustygameboy::rewind::Rewind;
+use rustygameboy::rom::{Rom, ValidationPolicy};
+use rustygameboy::savestate;
+#[cfg(feature = "png")]
+use rustygameboy::screenshot::ScreenshotOptions;
+use rustygameboy::sgb::{BORDER_HEIGHT, BORDER_WIDTH};
+use sdl2::audio::{AudioQueue, AudioSpecDesired};
+use sdl2::controller::{self, Axis};
+use sdl2::event::{Event, WindowEvent};
+use sdl2::keyboard::{Keycode, Mod, Scancode};
+use sdl2::pixels::PixelFormatEnum;
+use sdl2::render::Canvas;
+use sdl2::video::Window;
+use sdl2::VideoSubsystem;
+
+use self::renderer::Renderer;
+
+mod renderer;
+
+// What the command line can change about the window.
+pub struct Options {
+    // The window starts this many times the screen's size.
+    pub scale: u32,
+    pub scaling: Scaling,
+    pub filter: Filter,
+    // Shows SGB games inside their border, which the filters don't apply to.
+    pub sgb_border: bool,
+    // 0 turns rewinding off.
+    pub rewind_seconds: u32,
+    pub speed: f64,
+    pub sync: SyncMode,
+    // Play audio during turbo, dropping what doesn't fit so the pitch stays right.
+    pub turbo_audio: bool,
+    // What to ask the sound card for, it may pick another rate.
+    pub sample_rate: u32,
+    // How much sound may wait in the queue.
+    pub audio_latency: Duration,
+    // Reloaded with F7, followed by the codes from the command line.
+    pub cheat_file: Option<PathBuf>,
+    pub cheats: Vec<Cheat>,
+    // Record each channel next to the mix when F10 starts recording audio.
+    pub audio_stems: bool,
+    // SDL key names replacing a button's default keys.
+    pub keys: Vec<(Target, Vec<String>)>,
+    pub turbo_periods: Vec<(Button, u32)>,
+    pub controllers: Controllers,
+    // Where battery saves go instead of next to the ROM, for ROMs dropped on the window.
+    pub save_dir: Option<PathBuf>,
+    // Loads dropped ROMs without the Nintendo logo too.
+    pub lenient: bool,
+    // The config file from the command line, which the recent ROMs are kept next to.
+    pub config: Option<PathBuf>,
+}
+
+// Everything that can hold a button. Controllers are told apart by their SDL instance ids.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+enum Input {
+    Key(Keycode),
+    Pad(u32, controller::Button),
+    // A direction of a controller's left stick.
+    Stick(u32, Button),
+}
+
+// Opens a window and runs the emulator at the Game Boy's ~59.7 Hz until it is closed.
+// F5 saves a state to the selected slot (0-9) and F8 loads it back. F12 saves a screenshot next
+// to the ROM. Holding R plays backwards, holding Tab runs as fast as possible, and - and = step
+// through slower and faster speeds. C turns the cheats off and on, and F7 reloads the cheat file
+// after editing it. F9 makes a movie read-only or read-write. F10 starts and stops recording the
+// audio to a WAV file next to the ROM, and F11 the screen and audio to a video. P switches to the
+// next DMG palette. F2 switches between scaling modes, F3 steps through window sizes and F4
+// switches between filters. Ctrl+1, 2 and 3 hide and show the background, window and sprites. F6
+// opens a window showing VRAM as it changes, and Shift+F6 saves it and OAM next to the ROM.
+// Controllers can be plugged in and out while playing, and dropping a ROM
+// on the window switches to it, which is where `rom_path` ends up pointing. With `netplay` the
+// frames come from the session instead, and loading states, rewinding, cheats and dropping ROMs
+// are off since the other player's game wouldn't follow.
+pub fn run(
+    emulator: &mut Emulator,
+    rom_path: &mut PathBuf,
+    options: &Options,
+    netplay: Option<&mut Netplay>,
+) -> Result<()> {
+    let result = run_window(emulator, rom_path, options, netplay);
+    // The audio queue closes with the window.
+    emulator.on_audio_buffer(None);
+    result
+}
+
+fn run_window(
+    emulator: &mut Emulator,
+    rom_path: &mut PathBuf,
+    options: &Options,
+    mut netplay: Option<&mut Netplay>,
+) -> Result<()> {
+    let sdl = sdl2::init().map_err(Error::other)?;
+    let video = sdl.video().map_err(Error::other)?;
+    let audio = sdl.audio().map_err(Error::other)?;
+    let game_controllers = sdl.game_controller().map_err(Error::other)?;
+
+    let (screen_width, screen_height) = screen_size(emulator, options);
+    Renderer::request_context(&video);
+    let mut window = video
+        .window(
+            "RustyGameBoy",
+            screen_width * options.scale,
+            screen_height * options.scale,
+        )
+        .position_centered()
+        .resizable()
+        .opengl()
+        .build()
+        .map_err(Error::other)?;
+    let mut renderer = Renderer::new(
+        &video,
+        &window,
+        options.filter,
+        options.sync == SyncMode::Vsync,
+    )?;
+    let mut scaling = options.scaling;
+    let mut scale = options.scale;
+
+    let desired = AudioSpecDesired {
+        freq: Some(options.sample_rate as i32),
+        channels: Some(2),
+        samples: Some(1024),
+    };
+    let queue: Rc<AudioQueue<f32>> =
+        Rc::new(audio.open_queue(None, &desired).map_err(Error::other)?);
+    let sample_rate = queue.spec().freq as u32;
+    emulator.set_sample_rate(sample_rate);
+    emulator.set_audio_latency(options.audio_latency);
+    // Stereo f32 samples.
+    let max_queued_bytes = (sample_rate as f64 * options.audio_latency.as_secs_f64()) as u32 * 8;
+    queue.resume();
+    let muted = Rc::new(Cell::new(false));
+    // Whether the last frame queued any audio, which there's only something to wait for after.
+    let queued = Rc::new(Cell::new(false));
+    let (queue_muted, frame_queued, frame_queue) = (muted.clone(), queued.clone(), queue.clone());
+    emulator.on_audio_buffer(Some(Box::new(move |samples| {
+        // Drop samples instead of letting latency build up when the queue backs up.
+        if !queue_muted.get() && frame_queue.size() < max_queued_bytes {
+            match frame_queue.queue_audio(samples) {
+                Ok(()) => frame_queued.set(true),
+                Err(error) => eprintln!("Could not play audio: {}", error),
+            }
+        }
+    })));
+
+    let mut inputs = InputMap::new();
+    for (keycode, target) in key_bindings(&options.keys)? {
+        inputs.bind(Input::Key(keycode), target);
+    }
+    for &(button, frames) in &options.turbo_periods {
+        inputs.set_turbo_period(button, frames);
+    }
+    // Checked now so a mistake shows up before a controller with it is plugged in.
+    for profile in options.controllers.profiles() {
+        pad_bindings(profile)?;
+    }
+    // Open controllers with their left stick's deadzone. SDL reports the ones already plugged in
+    // as added at the start.
+    let mut controllers = HashMap::new();
+    let mut events = sdl.event_pump().map_err(Error::other)?;
+    let mut pacer = FramePacer::new(options.speed)?;
+    let mut refresh_pacer = match options.sync {
+        SyncMode::Vsync => Some(RefreshPacer::new(refresh_rate(&window), options.speed)?),
+        _ => None,
+    };
+    emulator.set_speed(options.speed * refresh_pacer.as_ref().map_or(1.0, RefreshPacer::skew))?;
+    let mut slot = 0;
+    // Rewinding doesn't tell a movie which frame it went back to, so it's off during one.
+    let rewind_seconds = if emulator.movie().is_some() {
+        0
+    } else {
+        options.rewind_seconds
+    };
+    let mut rewind = Rewind::new(rewind_seconds, REWIND_INTERVAL);
+    let mut rewinding = false;
+    // The movie frame each slot was saved at this session, so loading it can seek the movie.
+    let mut movie_frames = HashMap::new();
+    // The presets, and the palette from the command line if it came from a file.
+    let mut palettes = PalettePreset::ALL
+        .map(|preset| (preset.name(), preset.palette()))
+        .to_vec();
+    if !palettes
+        .iter()
+        .any(|(_, palette)| palette == emulator.dmg_palette())
+    {
+        palettes.push(("file", *emulator.dmg_palette()));
+    }
+    let mut vram_window: Option<Canvas<Window>> = None;
+    let tilt = Tilt::default();
+    // Tilting isn't sent to the other player.
+    if netplay.is_none() {
+        emulator.set_accelerometer(Some(Box::new(tilt.clone())));
+    }
+    loop {
+        for event in events.poll_iter() {
+            if netplay.is_some() && desyncs(&event) {
+                continue;
+            }
+            match event {
+                Event::Quit { .. }
+                | Event::KeyDown {
+                    keycode: Some(Keycode::Escape),
+                    ..
+                } => return Ok(()),
+                // With the VRAM window open, closing the main window doesn't quit on its own.
+                Event::Window {
+                    window_id,
+                    win_event: WindowEvent::Close,
+                    ..
+                } => {
+                    if vram_window
+                        .as_ref()
+                        .is_some_and(|vram| vram.window().id() == window_id)
+                    {
+                        vram_window = None;
+                    } else {
+                        return Ok(());
+                    }
+                }
+                #[cfg(feature = "png")]
+                Event::KeyDown {
+                    keycode: Some(Keycode::F6),
+                    keymod,
+                    repeat: false,
+                    ..
+                } if keymod.intersects(Mod::LSHIFTMOD | Mod::RSHIFTMOD) => {
+                    if let Err(error) = save_vram(emulator, rom_path) {
+                        eprintln!("Could not save VRAM: {}", error);
+                    }
+                }
+                Event::KeyDown {
+                    keycode: Some(Keycode::F6),
+                    repeat: false,
+                    ..
+                } => {
+                    vram_window = match vram_window {
+                        Some(_) => None,
+                        None => Some(open_vram_window(&video, emulator)?),
+                    };
+                }
+                Event::DropFile { filename, .. } => {
+                    let dropped = PathBuf::from(filename);
+                    match swap_rom(emulator, rom_path, &dropped, options) {
+                        Ok(()) => {
+                            // Neither the history nor the slots' frames belong to this game.
+                            rewind.clear();
+                            movie_frames.clear();
+                            emulator.set_accelerometer(Some(Box::new(tilt.clone())));
+                            eprintln!("Loaded {}", dropped.display());
+                        }
+                        Err(error) => {
+                            eprintln!("Could not load {}: {}", dropped.display(), error)
+                        }
+                    }
+                }
+                Event::KeyDown {
+                    keycode: Some(Keycode::F5),
+                    repeat: false,
+                    ..
+                } => {
+                    let path = savestate::state_path(rom_path, slot);
+                    match fs::write(&path, emulator.save_state()) {
+                        Ok(()) => {
+                            if let Some(movie) = emulator.movie() {
+                                movie_frames.insert(slot, movie.frame());
+                            }
+                        }
+                        Err(error) => {
+                            eprintln!("Could not save state to {}: {}", path.display(), error)
+                        }
+                    }
+                }
+                Event::KeyDown {
+                    keycode: Some(Keycode::F8),
+                    repeat: false,
+                    ..
+                } => {
+                    let path = savestate::state_path(rom_path, slot);
+                    let result = fs::read(&path).and_then(|state| emulator.load_state(&state));
+                    match result {
+                        // The history leads up to a different moment now.
+                        Ok(()) => {
+                            rewind.clear();
+                            seek_movie(emulator, movie_frames.get(&slot).copied());
+                        }
+                        Err(error) => {
+                            eprintln!("Could not load state from {}: {}", path.display(), error)
+                        }
+                    }
+                }
+                Event::KeyDown {
+                    keycode: Some(Keycode::F9),
+                    repeat: false,
+                    ..
+                } => {
+                    if let Some(movie) = emulator.movie_mut() {
+                        let read_only = !movie.read_only();
+                        movie.set_read_only(read_only);
+                        eprintln!(
+                            "Movie {} at frame {}",
+                            if read_only { "read-only" } else { "read-write" },
+                            movie.frame()
+                        );
+                    }
+                }
+                Event::KeyDown {
+                    keycode: Some(Keycode::F2),
+                    repeat: false,
+                    ..
+                } => {
+                    scaling = scaling.next();
+                    eprintln!("Scaling: {}", scaling.name());
+                }
+                Event::KeyDown {
+                    keycode: Some(Keycode::F3),
+                    repeat: false,
+                    ..
+                } => {
+                    scale = if scale < *WINDOW_SCALES.end() {
+                        scale + 1
+                    } else {
+                        *WINDOW_SCALES.start()
+                    };
+                    let (width, height) = screen_size(emulator, options);
+                    window
+                        .set_size(width * scale, height * scale)
+                        .map_err(Error::other)?;
+                    eprintln!("Window: {}x", scale);
+                }
+                Event::KeyDown {
+                    keycode: Some(Keycode::F4),
+                    repeat: false,
+                    ..
+                } => {
+                    renderer.set_filter(renderer.filter().next());
+                    eprintln!("Filter: {}", renderer.filter().name());
+                }
+                Event::KeyDown {
+                    keycode: Some(Keycode::P),
+                    repeat: false,
+                    ..
+                } => {
+                    let (name, palette) = next_palette(&palettes, emulator.dmg_palette());
+                    emulator.set_dmg_palette(palette);
+                    eprintln!("Palette: {}", name);
+                }
+                Event::KeyDown {
+                    keycode: Some(Keycode::F10),
+                    repeat: false,
+                    ..
+                } => toggle_audio_recording(emulator, rom_path, options.audio_stems),
+                Event::KeyDown {
+                    keycode: Some(Keycode::F11),
+                    repeat: false,
+                    ..
+                } => toggle_video_recording(emulator, rom_path),
+                Event::KeyDown {
+                    keycode: Some(Keycode::R),
+                    ..
+                } => rewinding = true,
+                Event::KeyUp {
+                    keycode: Some(Keycode::R),
+                    ..
+                } => rewinding = false,
+                Event::KeyDown {
+                    keycode: Some(Keycode::Tab),
+                    ..
+                } => pacer.set_turbo(true),
+                Event::KeyUp {
+                    keycode: Some(Keycode::Tab),
+                    ..
+                } => pacer.set_turbo(false),
+                Event::KeyDown {
+                    keycode: Some(keycode @ (Keycode::Minus | Keycode::Equals)),
+                    repeat: false,
+                    ..
+                } => {
+                    let speed = step_speed(pacer.speed(), keycode == Keycode::Equals);
+                    pacer.set_speed(speed)?;
+                    let mut skew = 1.0;
+                    if let Some(refresh_pacer) = &mut refresh_pacer {
+                        refresh_pacer.set_speed(speed)?;
+                        skew = refresh_pacer.skew();
+                    }
+                    emulator.set_speed(speed * skew)?;
+                    eprintln!("Speed: {}x", speed);
+                }
+                Event::KeyDown {
+                    keycode: Some(Keycode::C),
+                    repeat: false,
+                    ..
+                } => {
+                    let active = !emulator.cheats().active();
+                    emulator.cheats_mut().set_active(active);
+                    eprintln!("Cheats {}", if active { "on" } else { "off" });
+                }
+                Event::KeyDown {
+                    keycode: Some(Keycode::F7),
+                    repeat: false,
+                    ..
+                } => {
+                    if let Some(path) = &options.cheat_file {
+                        match Cheats::load(path) {
+                            Ok(mut cheats) => {
+                                cheats.extend(options.cheats.iter().cloned());
+                                cheats.set_active(emulator.cheats().active());
+                                eprintln!("Loaded {} cheats", cheats.cheats().len());
+                                *emulator.cheats_mut() = cheats;
+                            }
+                            Err(error) => eprintln!("{}", error),
+                        }
+                    }
+                }
+                #[cfg(feature = "png")]
+                Event::KeyDown {
+                    keycode: Some(Keycode::F12),
+                    repeat: false,
+                    ..
+                } => {
+                    let path = paths::next_free_path(rom_path, "png");
+                    let result = emulator
+                        .screenshot(ScreenshotOptions::default())
+                        .and_then(|png| fs::write(&path, png));
+                    match result {
+                        Ok(()) => eprintln!("Saved a screenshot to {}", path.display()),
+                        Err(error) => {
+                            eprintln!(
+                                "Could not save a screenshot to {}: {}",
+                                path.display(),
+                                error
+                            )
+                        }
+                    }
+                }
+                Event::KeyDown {
+                    keycode: Some(keycode @ (Keycode::Num1 | Keycode::Num2 | Keycode::Num3)),
+                    keymod,
+                    repeat: false,
+                    ..
+                } if keymod.intersects(Mod::LCTRLMOD | Mod::RCTRLMOD) => {
+                    let layer = layer_key(keycode);
+                    let enabled = !emulator.layer_enabled(layer);
+                    emulator.set_layer_enabled(layer, enabled);
+                    eprintln!(
+                        "{} {}",
+                        layer.name(),
+                        if enabled { "shown" } else { "hidden" }
+                    );
+                }
+                Event::KeyDown {
+                    keycode: Some(keycode),
+                    repeat: false,
+                    ..
+                } => {
+                    if let Some(button) = inputs.press(Input::Key(keycode)) {
+                        emulator.set_button(button, true);
+                    } else if let Some(selected) = state_slot(keycode) {
+                        slot = selected;
+                    }
+                }
+                Event::KeyUp {
+                    keycode: Some(keycode),
+                    ..
+                } => {
+                    if let Some(button) = inputs.release(Input::Key(keycode)) {
+                        emulator.set_button(button, false);
+                    }
+                }
+                Event::ControllerDeviceAdded { which, .. } => match game_controllers.open(which) {
+                    Ok(pad) => {
+                        let id = pad.instance_id();
+                        let profile = options.controllers.profile(&pad.name());
+                        for (pad_button, target) in pad_bindings(&profile)? {
+                            inputs.bind(Input::Pad(id, pad_button), target);
+                        }
+                        for direction in DIRECTIONS {
+                            inputs.bind(Input::Stick(id, direction), Target::Button(direction));
+                        }
+                        eprintln!("Controller connected: {}", pad.name());
+                        controllers.insert(id, (pad, profile.deadzone()));
+                    }
+                    Err(error) => eprintln!("Could not open controller {}: {}", which, error),
+                },
+                Event::ControllerDeviceRemoved { which, .. } => {
+                    if let Some((pad, _)) = controllers.remove(&which) {
+                        eprintln!("Controller disconnected: {}", pad.name());
+                    }
+                    let released = inputs.unbind(|input| {
+                        matches!(*input, Input::Pad(id, _) | Input::Stick(id, _) if id == which)
+                    });
+                    for button in released {
+                        emulator.set_button(button, false);
+                    }
+                }
+                Event::ControllerButtonDown { which, button, .. } => {
+                    if let Some(button) = inputs.press(Input::Pad(which, button)) {
+                        emulator.set_button(button, true);
+                    }
+                }
+                Event::ControllerButtonUp { which, button, .. } => {
+                    if let Some(button) = inputs.release(Input::Pad(which, button)) {
+                        emulator.set_button(button, false);
+                    }
+                }
+                Event::ControllerAxisMotion {
+                    which,
+                    axis: Axis::LeftX | Axis::LeftY,
+                    ..
+                } => {
+                    if let Some((pad, deadzone)) = controllers.get(&which) {
+                        let (x, y) = (pad.axis(Axis::LeftX), pad.axis(Axis::LeftY));
+                        for (direction, held) in input::stick_directions(x, y, *deadzone) {
+                            if let Some((button, pressed)) =
+                                inputs.set(Input::Stick(which, direction), held)
+                            {
+                                emulator.set_button(button, pressed);
+                            }
+                        }
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        for (button, pressed) in inputs.next_frame() {
+            emulator.set_button(button, pressed);
+        }
+        // MBC7 cartridges tilt with IJKL and every controller's right stick.
+        let keys = events.keyboard_state();
+        let key_axis = |negative, positive| {
+            keys.is_scancode_pressed(positive) as i32 as f32
+                - keys.is_scancode_pressed(negative) as i32 as f32
+        };
+        let (mut x, mut y) = (
+            key_axis(Scancode::J, Scancode::L),
+            key_axis(Scancode::I, Scancode::K),
+        );
+        for (pad, _) in controllers.values() {
+            x += pad.axis(Axis::RightX) as f32 / i16::MAX as f32;
+            y += pad.axis(Axis::RightY) as f32 / i16::MAX as f32;
+        }
+        tilt.set(x, y);
+
+        muted.set(pacer.turbo() && !options.turbo_audio);
+        let started = Instant::now();
+        let frames = match &mut refresh_pacer {
+            Some(refresh_pacer) if !pacer.turbo() => refresh_pacer.refresh(),
+            _ => 1,
+        };
+        let mut frame = 0;
+        while frame < frames || turbo_has_time(refresh_pacer.as_ref(), pacer.turbo(), started) {
+            // Rewinding goes back a state per frame and stays on the oldest one when it runs out.
+            if rewinding {
+                rewind.rewind(emulator);
+            } else if let Some(netplay) = netplay.as_deref_mut() {
+                netplay.advance(emulator)?;
+            } else {
+                emulator.run_frame();
+                rewind.capture(emulator);
+            }
+            for violation in emulator.take_memory_violations() {
+                eprintln!("{}", violation);
+            }
+            for error in emulator.take_recording_errors() {
+                eprintln!("{}", error);
+            }
+            #[cfg(feature = "lua")]
+            if let Some(error) = emulator.take_script_error() {
+                eprintln!("{}", error);
+            }
+            frame += 1;
+        }
+
+        let border = options.sgb_border.then(|| emulator.sgb_frame()).flatten();
+        let (shown, shown_width) = match &border {
+            Some(frame) => (frame.as_slice(), BORDER_WIDTH),
+            None => (emulator.framebuffer(), SCREEN_WIDTH),
+        };
+        let (width, height) = window.drawable_size();
+        let layout = scaling.layout(width, height, screen_size(emulator, options));
+        renderer.present(&window, shown, shown_width, layout)?;
+        if let Some(vram) = &mut vram_window {
+            draw_vram(vram, emulator)?;
+        }
+
+        match options.sync {
+            SyncMode::Timer => thread::sleep(pacer.frame_done(Instant::now())),
+            // Frames without sound, like while rewinding, fall back to the clock.
+            SyncMode::Audio if !queued.take() => thread::sleep(pacer.frame_done(Instant::now())),
+            SyncMode::Audio if !pacer.turbo() => {
+                let frame_bytes = (sample_rate as f64 * FRAME_DURATION.as_secs_f64()
+                    / emulator.speed()) as u32
+                    * 8;
+                wait_for_audio(&queue, max_queued_bytes.saturating_sub(frame_bytes));
+            }
+            // Presenting waited for the refresh.
+            SyncMode::Vsync => {
+                let fill = queue.size() as f64 / max_queued_bytes as f64;
+                emulator.adjust_sample_rate(pacing::rate_control(fill));
+            }
+            SyncMode::Audio | SyncMode::None => {}
+        }
+    }
+}
+
+// The display's refresh rate, or 60 Hz if SDL doesn't know it.
+fn refresh_rate(window: &Window) -> f64 {
+    match window.display_mode() {
+        Ok(mode) if mode.refresh_rate > 0 => mode.refresh_rate as f64,
+        _ => 60.0,
+    }
+}
+
+// In vsync mode turbo runs frames until most of a refresh has gone by, leaving time to draw.
+fn turbo_has_time(refresh_pacer: Option<&RefreshPacer>, turbo: bool, started: Instant) -> bool {
+    match refresh_pacer {
+        Some(refresh_pacer) if turbo => {
+            started.elapsed().as_secs_f64() < TURBO_REFRESH_SHARE / refresh_pacer.refresh_rate()
+        }
+        _ => false,
+    }
+}
+
+// Blocks until no more than `bytes` of audio are queued. A sound card that stops playing only
+// holds the frame up for AUDIO_WAIT_LIMIT.
+fn wait_for_audio(queue: &AudioQueue<f32>, bytes: u32) {
+    let start = Instant::now();
+    while queue.size() > bytes && start.elapsed() < AUDIO_WAIT_LIMIT {
+        thread::sleep(AUDIO_POLL_INTERVAL);
+    }
+}
+
+// Opens an empty window until a ROM is dropped on it or 1-9 picks one of the `recent` ROMs, and
+// returns None if it's closed first.
+pub fn pick_rom(recent: &[PathBuf], scale: u32) -> Result<Option<PathBuf>> {
+    let sdl = sdl2::init().map_err(Error::other)?;
+    let video = sdl.video().map_err(Error::other)?;
+    let window = video
+        .window(
+            "RustyGameBoy",
+            SCREEN_WIDTH as u32 * scale,
+            SCREEN_HEIGHT as u32 * scale,
+        )
+        .position_centered()
+        .resizable()
+        .build()
+        .map_err(Error::other)?;
+    let mut canvas = window.into_canvas().build().map_err(Error::other)?;
+    let mut events = sdl.event_pump().map_err(Error::other)?;
+
+    eprintln!("Drop a ROM on the window to play it");
+    for (index, rom) in recent.iter().enumerate() {
+        eprintln!("{}: {}", index + 1, rom.display());
+    }
+    loop {
+        for event in events.poll_iter() {
+            match event {
+                Event::Quit { .. }
+                | Event::KeyDown {
+                    keycode: Some(Keycode::Escape),
+                    ..
+                } => return Ok(None),
+                Event::DropFile { filename, .. } => return Ok(Some(PathBuf::from(filename))),
+                Event::KeyDown {
+                    keycode: Some(keycode),
+                    ..
+                } => {
+                    let picked = state_slot(keycode)
+                        .and_then(|slot| (slot as usize).checked_sub(1))
+                        .and_then(|index| recent.get(index));
+                    if let Some(rom) = picked {
+                        return Ok(Some(rom.clone()));
+                    }
+                }
+                _ => {}
+            }
+        }
+        canvas.clear();
+        canvas.present();
+        thread::sleep(PICK_POLL_INTERVAL);
+    }
+}
+
+// Saves the battery of the game being played, then switches to the ROM at `dropped` with its own
+// battery. A movie is tied to its cartridge, so one that's playing or recording keeps it in.
+fn swap_rom(
+    emulator: &mut Emulator,
+    rom_path: &mut PathBuf,
+    dropped: &Path,
+    options: &Options,
+) -> Result<()> {
+    if emulator.movie().is_some() {
+        return Err(Error::other("a movie is playing or recording"));
+    }
+    let policy = if options.lenient {
+        ValidationPolicy::Lenient
+    } else {
+        ValidationPolicy::Strict
+    };
+    let rom = Rom::with_policy(&dropped.to_string_lossy(), policy)?;
+    let save_dir = options.save_dir.as_deref();
+    battery::save(emulator, &battery::rom_path_in(rom_path, save_dir))?;
+    emulator.swap_rom(rom)?;
+    battery::load(emulator, &battery::rom_path_in(dropped, save_dir))?;
+    dropped.clone_into(rom_path);
+    if let Some(mut recent) = RecentRoms::load(options.config.as_deref())? {
+        recent.add(dropped)?;
+    }
+    Ok(())
+}
+
+// The size of the picture in the window, bigger with an SGB game's border around the screen.
+fn screen_size(emulator: &Emulator, options: &Options) -> (u32, u32) {
+    if options.sgb_border && emulator.bus().sgb().is_some() {
+        (BORDER_WIDTH as u32, BORDER_HEIGHT as u32)
+    } else {
+        (SCREEN_WIDTH as u32, SCREEN_HEIGHT as u32)
+    }
+}
+
+// Keeps a movie in step with a state that was just loaded. A state saved before the movie started
+// has no frame to go back to, so the movie carries on and won't match what's on screen.
+fn seek_movie(emulator: &mut Emulator, frame: Option<usize>) {
+    let Some(movie) = emulator.movie_mut() else {
+        return;
+    };
+    match frame {
+        Some(frame) => movie.seek(frame),
+        None => eprintln!("The state wasn't saved during this movie, so the movie is out of sync"),
+    }
+}
+
+fn toggle_audio_recording(emulator: &mut Emulator, rom_path: &Path, stems: bool) {
+    if emulator.recording_audio() {
+        match emulator.stop_audio_recording() {
+            Ok(()) => eprintln!("Stopped recording audio"),
+            Err(error) => eprintln!("Could not finish the audio recording: {}", error),
+        }
+        return;
+    }
+    let path = paths::next_free_path(rom_path, "wav");
+    match emulator.start_audio_recording(&path, stems) {
+        Ok(()) => eprintln!("Recording audio to {}", path.display()),
+        Err(error) => eprintln!("{}", error),
+    }
+}
+
+// The palette after `current`, going back to the first after the last.
+fn next_palette(
+    palettes: &[(&'static str, Palette)],
+    current: &Palette,
+) -> (&'static str, Palette) {
+    let index = palettes
+        .iter()
+        .position(|(_, palette)| palette == current)
+        .map_or(0, |index| (index + 1) % palettes.len());
+    palettes[index]
+}
+
+// Stopping waits for ffmpeg to finish encoding.
+fn toggle_video_recording(emulator: &mut Emulator, rom_path: &Path) {
+    if emulator.recording_video() {
+        match emulator.stop_video_recording() {
+            Ok(()) => eprintln!("Stopped recording video"),
+            Err(error) => eprintln!("Could not finish the video recording: {}", error),
+        }
+        return;
+    }
+    let path = paths::next_free_path(rom_path, "mkv");
+    match emulator.start_video_recording(&path) {
+        Ok(()) => eprintln!("Recording video to {}", path.display()),
+        Err(error) => eprintln!("{}", error),
+    }
+}
+
+fn open_vram_window(video: &VideoSubsystem, emulator: &Emulator) -> Result<Canvas<Window>> {
+    let overview = emulator.bus().ppu().vram_overview();
+    video
+        .window(
+            "RustyGameBoy VRAM",
+            (overview.width * VRAM_WINDOW_SCALE) as u32,
+            (overview.height * VRAM_WINDOW_SCALE) as u32,
+        )
+        .resizable()
+        .build()
+        .map_err(Error::other)?
+        .into_canvas()
+        .build()
+        .map_err(Error::other)
+}
+
+// Decodes VRAM again, so the window keeps up with the game.
+fn draw_vram(canvas: &mut Canvas<Window>, emulator: &Emulator) -> Result<()> {
+    let overview = emulator.bus().ppu().vram_overview();
+    let texture_creator = canvas.texture_creator();
+    let mut texture = texture_creator
+        .create_texture_streaming(
+            PixelFormatEnum::RGBA32,
+            overview.width as u32,
+            overview.height as u32,
+        )
+        .map_err(Error::other)?;
+    texture
+        .update(None, &overview.pixels, overview.width * 4)
+        .map_err(Error::other)?;
+    canvas.clear();
+    canvas.copy(&texture, None, None).map_err(Error::other)?;
+    canvas.present();
+    Ok(())
+}
+
+// Saves the tile sheet, both tile maps and the palettes as PNGs and OAM as text, named after the
+// ROM.
+#[cfg(feature = "png")]
+fn save_vram(emulator: &Emulator, rom_path: &Path) -> Result<()> {
+    let ppu = emulator.bus().ppu();
+    let stem = rom_path.file_stem().unwrap_or_default().to_string_lossy();
+    let path = |name: &str| rom_path.with_file_name(format!("{}-{}", stem, name));
+    for (name, image) in [
+        ("tiles.png", ppu.tile_sheet()),
+        ("map0.png", ppu.tile_map(0)),
+        ("map1.png", ppu.tile_map(1)),
+        ("palettes.png", ppu.palette_swatches()),
+    ] {
+        fs::write(path(name), image.to_png()?)?;
+    }
+    let oam: String = ppu
+        .objects()
+        .iter()
+        .map(|object| format!("{}\n", object))
+        .collect();
+    fs::write(path("oam.txt"), oam)?;
+    eprintln!("Saved VRAM to {}", path("*").display());
+    Ok(())
+}
+
+// The next of SPEEDS up or down from `speed`, staying at the ends.
+fn step_speed(speed: f64, faster: bool) -> f64 {
+    let next = if faster {
+        SPEEDS.iter().find(|&&step| step > speed)
+    } else {
+        SPEEDS.iter().rev().find(|&&step| step < speed)
+    };
+    next.copied().unwrap_or(speed)
+}
+
+// The default keys, with the buttons in `remapped` moved to the keys named there. Hotkeys come
+// first, so a button on one of them never gets pressed.
+fn key_bindings(remapped: &[(Target, Vec<String>)]) -> Result<Vec<(Keycode, Target)>> {
+    bindings(&DEFAULT_KEYS, remapped, |name| {
+        Keycode::from_name(name).ok_or_else(|| {
+            Error::other(format!(
+                "{} is not a key, expected an SDL key name like Return or Left Shift.",
+                name
+            ))
+        })
+    })
+}
+
+// The same for a controller's buttons.
+fn pad_bindings(profile: &ControllerProfile) -> Result<Vec<(controller::Button, Target)>> {
+    bindings(&DEFAULT_PAD_BUTTONS, &profile.bindings()?, |name| {
+        controller::Button::from_string(name).ok_or_else(|| {
+            Error::other(format!(
+                "{} is not a controller button, expected an SDL name like a, back or dpup.",
+                name
+            ))
+        })
+    })
+}
+
+fn bindings<T: Copy>(
+    defaults: &[(T, Target)],
+    remapped: &[(Target, Vec<String>)],
+    parse: impl Fn(&str) -> Result<T>,
+) -> Result<Vec<(T, Target)>> {
+    let mut bindings: Vec<(T, Target)> = defaults
+        .iter()
+        .copied()
+        .filter(|(_, target)| remapped.iter().all(|(remapped, _)| remapped != target))
+        .collect();
+    for (target, names) in remapped {
+        for name in names {
+            bindings.push((parse(name)?, *target));
+        }
+    }
+    Ok(bindings)
+}
+
+// Loading a state, rewinding, cheats and switching ROMs, which netplay can't tell the other player.
+fn desyncs(event: &Event) -> bool {
+    matches!(
+        event,
+        Event::KeyDown {
+            keycode: Some(Keycode::F7 | Keycode::F8 | Keycode::R | Keycode::C),
+            ..
+        } | Event::DropFile { .. }
+    )
+}
+
+// The layer Ctrl and 1, 2 or 3 toggles, in Layer::ALL's order.
+fn layer_key(keycode: Keycode) -> Layer {
+    Layer::ALL[(keycode.into_i32() - Keycode::Num1.into_i32()) as usize]
+}
+
+fn state_slot(keycode: Keycode) -> Option<u8> {
+    let slot = keycode.into_i32() - Keycode::Num0.into_i32();
+    (0..=9).contains(&slot).then_some(slot as u8)
+}
+
+const VRAM_WINDOW_SCALE: usize = 2;
+
+const PICK_POLL_INTERVAL: Duration = Duration::from_millis(16);
+
+const AUDIO_POLL_INTERVAL: Duration = Duration::from_millis(1);
+
+const AUDIO_WAIT_LIMIT: Duration = Duration::from_millis(100);
+
+const TURBO_REFRESH_SHARE: f64 = 0.75;
+
+const DEFAULT_KEYS: [(Keycode, Target); 11] = [
+    (Keycode::Right, Target::Button(Button::Right)),
+    (Keycode::Left, Target::Button(Button::Left)),
+    (Keycode::Up, Target::Button(Button::Up)),
+    (Keycode::Down, Target::Button(Button::Down)),
+    (Keycode::Z, Target::Button(Button::A)),
+    (Keycode::X, Target::Button(Button::B)),
+    (Keycode::Backspace, Target::Button(Button::Select)),
+    (Keycode::RShift, Target::Button(Button::Select)),
+    (Keycode::Return, Target::Button(Button::Start)),
+    (Keycode::A, Target::Turbo(Button::A)),
+    (Keycode::S, Target::Turbo(Button::B)),
+];
+
+// By position rather than label: the Game Boy's A is the right face button, which SDL calls b.
+const DEFAULT_PAD_BUTTONS: [(controller::Button, Target); 10] = [
+    (controller::Button::DPadRight, Target::Button(Button::Right)),
+    (controller::Button::DPadLeft, Target::Button(Button::Left)),
+    (controller::Button::DPadUp, Target::Button(Button::Up)),
+    (controller::Button::DPadDown, Target::Button(Button::Down)),
+    (controller::Button::B, Target::Button(Button::A)),
+    (controller::Button::A, Target::Button(Button::B)),
+    (controller::Button::Back, Target::Button(Button::Select)),
+    (controller::Button::Start, Target::Button(Button::Start)),
+    (controller::Button::Y, Target::Turbo(Button::A)),
+    (controller::Button::X, Target::Turbo(Button::B)),
+];
+
+const DIRECTIONS: [Button; 4] = [Button::Right, Button::Left, Button::Up, Button::Down];
+
+const SPEEDS: [f64; 6] = [0.25, 0.5, 1.0, 2.0, 4.0, 8.0];
+
+// Frames between rewind states, which makes rewinding this many times faster than playing.
+const REWIND_INTERVAL: u32 = 2;
@@ -0,0 +1,299 @@
+use std::io::{Error, Result};
+
+use glow::HasContext;
+use rustygameboy::filter::Filter;
+use rustygameboy::ppu::{SCREEN_HEIGHT, SCREEN_WIDTH};
+use sdl2::video::{GLContext, GLProfile, SwapInterval, Window};
+use sdl2::VideoSubsystem;
+
+// Draws the main window with OpenGL, running the filters as fragment shaders on the GPU. The
+// picture is uploaded as is every frame and the shaders scale it to the window.
+pub struct Renderer {
+    gl: glow::Context,
+    // The GL objects belong to it, so it lives as long as they do.
+    context: GLContext,
+    // Core profiles draw nothing without one bound, even with no vertex buffers.
+    vertex_array: glow::VertexArray,
+    // The shader for each filter, in the order of Filter::ALL.
+    programs: Vec<glow::Program>,
+    // Copies a frame into `cells`, which the blending mixes with what's there.
+    blend_program: glow::Program,
+    picture: glow::Texture,
+    picture_size: (usize, usize),
+    // What the dot matrix's cells show, which only moves part of the way to each new frame.
+    cells: glow::Texture,
+    cells_framebuffer: glow::Framebuffer,
+    // Whether `cells` holds a frame to move from.
+    cells_filled: bool,
+    filter: Filter,
+}
+
+impl Renderer {
+    // Asks for the context the shaders are written for. Has to happen before the window is built.
+    pub fn request_context(video: &VideoSubsystem) {
+        let attributes = video.gl_attr();
+        attributes.set_context_profile(GLProfile::Core);
+        attributes.set_context_version(3, 3);
+        // macOS only gives out core contexts that leave out everything deprecated.
+        attributes.set_context_flags().forward_compatible().set();
+    }
+
+    // `window` has to be built with `opengl()`. With `vsync` presenting waits for the display.
+    pub fn new(
+        video: &VideoSubsystem,
+        window: &Window,
+        filter: Filter,
+        vsync: bool,
+    ) -> Result<Renderer> {
+        let context = window.gl_create_context().map_err(Error::other)?;
+        window.gl_make_current(&context).map_err(Error::other)?;
+        let interval = if vsync {
+            SwapInterval::VSync
+        } else {
+            SwapInterval::Immediate
+        };
+        video
+            .gl_set_swap_interval(interval)
+            .map_err(|error| Error::other(format!("Could not set the swap interval: {}", error)))?;
+        let gl = unsafe {
+            glow::Context::from_loader_function(|name| video.gl_get_proc_address(name) as *const _)
+        };
+
+        unsafe {
+            let vertex_array = gl.create_vertex_array().map_err(Error::other)?;
+            let programs = Filter::ALL
+                .iter()
+                .map(|&filter| link(&gl, fragment_shader(filter)))
+                .collect::<Result<_>>()?;
+            let blend_program = link(&gl, COPY_SHADER)?;
+            gl.use_program(Some(blend_program));
+            gl.uniform_1_i32(
+                gl.get_uniform_location(blend_program, "offscreen").as_ref(),
+                1,
+            );
+
+            let picture = create_texture(&gl)?;
+            let cells = create_texture(&gl)?;
+            gl.tex_image_2d(
+                glow::TEXTURE_2D,
+                0,
+                glow::RGBA8 as i32,
+                SCREEN_WIDTH as i32,
+                SCREEN_HEIGHT as i32,
+                0,
+                glow::RGBA,
+                glow::UNSIGNED_BYTE,
+                glow::PixelUnpackData::Slice(None),
+            );
+            let cells_framebuffer = gl.create_framebuffer().map_err(Error::other)?;
+            gl.bind_framebuffer(glow::FRAMEBUFFER, Some(cells_framebuffer));
+            gl.framebuffer_texture_2d(
+                glow::FRAMEBUFFER,
+                glow::COLOR_ATTACHMENT0,
+                glow::TEXTURE_2D,
+                Some(cells),
+                0,
+            );
+            if gl.check_framebuffer_status(glow::FRAMEBUFFER) != glow::FRAMEBUFFER_COMPLETE {
+                return Err(Error::other("The GPU can't draw into a texture."));
+            }
+            gl.bind_framebuffer(glow::FRAMEBUFFER, None);
+            gl.clear_color(0.0, 0.0, 0.0, 1.0);
+
+            Ok(Renderer {
+                gl,
+                context,
+                vertex_array,
+                programs,
+                blend_program,
+                picture,
+                picture_size: (0, 0),
+                cells,
+                cells_framebuffer,
+                cells_filled: false,
+                filter,
+            })
+        }
+    }
+
+    pub fn filter(&self) -> Filter {
+        self.filter
+    }
+
+    // The dot matrix starts over from the next frame.
+    pub fn set_filter(&mut self, filter: Filter) {
+        self.filter = filter;
+        self.cells_filled = false;
+    }
+
+    // Draws the RGBA `frame`, `width` pixels wide, at `layout` (x, y, width and height from the
+    // window's top left, see `Scaling::layout`) on black and shows it. The filter only applies to
+    // the 160x144 screen, not an SGB border around it.
+    pub fn present(
+        &mut self,
+        window: &Window,
+        frame: &[u8],
+        width: usize,
+        layout: (i32, i32, u32, u32),
+    ) -> Result<()> {
+        // The VRAM window's renderer makes its own context current when it draws.
+        window
+            .gl_make_current(&self.context)
+            .map_err(Error::other)?;
+        let size = (width, frame.len() / 4 / width);
+        let filter = if size == (SCREEN_WIDTH, SCREEN_HEIGHT) {
+            self.filter
+        } else {
+            Filter::None
+        };
+
+        unsafe {
+            let gl = &self.gl;
+            gl.bind_vertex_array(Some(self.vertex_array));
+            gl.bind_texture(glow::TEXTURE_2D, Some(self.picture));
+            upload(gl, frame, size, self.picture_size);
+            self.picture_size = size;
+            if filter == Filter::DotMatrix {
+                self.blend_cells();
+            }
+
+            let gl = &self.gl;
+            gl.bind_framebuffer(glow::FRAMEBUFFER, None);
+            gl.clear(glow::COLOR_BUFFER_BIT);
+            let (x, y, width, height) = layout;
+            let (_, window_height) = window.drawable_size();
+            // GL counts up from the bottom.
+            gl.viewport(
+                x,
+                window_height as i32 - y - height as i32,
+                width as i32,
+                height as i32,
+            );
+            let index = Filter::ALL.iter().position(|&shown| shown == filter);
+            gl.use_program(Some(self.programs[index.unwrap_or(0)]));
+            let source = if filter == Filter::DotMatrix {
+                self.cells
+            } else {
+                self.picture
+            };
+            gl.bind_texture(glow::TEXTURE_2D, Some(source));
+            gl.draw_arrays(glow::TRIANGLE_STRIP, 0, 4);
+        }
+        window.gl_swap_window();
+        Ok(())
+    }
+
+    // Moves each cell part of the way to the picture's color, or all the way the first time, by
+    // blending the picture over what the cells showed.
+    unsafe fn blend_cells(&mut self) {
+        let gl = &self.gl;
+        gl.bind_framebuffer(glow::FRAMEBUFFER, Some(self.cells_framebuffer));
+        gl.viewport(0, 0, SCREEN_WIDTH as i32, SCREEN_HEIGHT as i32);
+        if self.cells_filled {
+            gl.enable(glow::BLEND);
+            gl.blend_color(0.0, 0.0, 0.0, 1.0 - DOT_MATRIX_PERSISTENCE);
+            gl.blend_func(glow::CONSTANT_ALPHA, glow::ONE_MINUS_CONSTANT_ALPHA);
+        }
+        gl.use_program(Some(self.blend_program));
+        gl.draw_arrays(glow::TRIANGLE_STRIP, 0, 4);
+        gl.disable(glow::BLEND);
+        self.cells_filled = true;
+    }
+}
+
+fn fragment_shader(filter: Filter) -> &'static str {
+    match filter {
+        Filter::None => COPY_SHADER,
+        Filter::Lcd => include_str!("shaders/lcd.frag"),
+        Filter::DotMatrix => include_str!("shaders/dot_matrix.frag"),
+        Filter::Scanlines => include_str!("shaders/scanlines.frag"),
+    }
+}
+
+// Builds a program from the shared vertex shader and `fragment`.
+unsafe fn link(gl: &glow::Context, fragment: &str) -> Result<glow::Program> {
+    let program = gl.create_program().map_err(Error::other)?;
+    let mut shaders = Vec::new();
+    for (kind, source) in [
+        (glow::VERTEX_SHADER, VERTEX_SHADER),
+        (glow::FRAGMENT_SHADER, fragment),
+    ] {
+        let shader = gl.create_shader(kind).map_err(Error::other)?;
+        gl.shader_source(shader, source);
+        gl.compile_shader(shader);
+        if !gl.get_shader_compile_status(shader) {
+            return Err(Error::other(format!(
+                "Could not compile a shader: {}",
+                gl.get_shader_info_log(shader)
+            )));
+        }
+        gl.attach_shader(program, shader);
+        shaders.push(shader);
+    }
+    gl.link_program(program);
+    if !gl.get_program_link_status(program) {
+        return Err(Error::other(format!(
+            "Could not link the shaders: {}",
+            gl.get_program_info_log(program)
+        )));
+    }
+    for shader in shaders {
+        gl.detach_shader(program, shader);
+        gl.delete_shader(shader);
+    }
+    Ok(program)
+}
+
+// A texture with sharp pixels, left bound.
+unsafe fn create_texture(gl: &glow::Context) -> Result<glow::Texture> {
+    let texture = gl.create_texture().map_err(Error::other)?;
+    gl.bind_texture(glow::TEXTURE_2D, Some(texture));
+    for (parameter, value) in [
+        (glow::TEXTURE_MIN_FILTER, glow::NEAREST),
+        (glow::TEXTURE_MAG_FILTER, glow::NEAREST),
+        (glow::TEXTURE_WRAP_S, glow::CLAMP_TO_EDGE),
+        (glow::TEXTURE_WRAP_T, glow::CLAMP_TO_EDGE),
+    ] {
+        gl.tex_parameter_i32(glow::TEXTURE_2D, parameter, value as i32);
+    }
+    Ok(texture)
+}
+
+// Replaces the bound texture's pixels, making room first if the size changed, like when an SGB
+// border is turned on.
+unsafe fn upload(gl: &glow::Context, frame: &[u8], size: (usize, usize), old_size: (usize, usize)) {
+    let (width, height) = (size.0 as i32, size.1 as i32);
+    let pixels = glow::PixelUnpackData::Slice(Some(frame));
+    if size == old_size {
+        gl.tex_sub_image_2d(
+            glow::TEXTURE_2D,
+            0,
+            0,
+            0,
+            width,
+            height,
+            glow::RGBA,
+            glow::UNSIGNED_BYTE,
+            pixels,
+        );
+    } else {
+        gl.tex_image_2d(
+            glow::TEXTURE_2D,
+            0,
+            glow::RGBA8 as i32,
+            width,
+            height,
+            0,
+            glow::RGBA,
+            glow::UNSIGNED_BYTE,
+            pixels,
+        );
+    }
+}
+
+const VERTEX_SHADER: &str = include_str!("shaders/screen.vert");
+
+const COPY_SHADER: &str = include_str!("shaders/copy.frag");
+
+// How much of last frame's color a dot matrix cell keeps.
+const DOT_MATRIX_PERSISTENCE: f32 = 0.45;
@@ -0,0 +1,20 @@
+#version 330 core
+
+// The DMG's dot matrix, with light gaps right of and below each cell. `picture` is what the cells
+// show, which trails behind the game's frames.
+uniform sampler2D picture;
+
+in vec2 uv;
+out vec4 color;
+
+// How far toward white the gaps are.
+const float GAP_LIGHTNESS = 0.4;
+
+void main() {
+    vec3 pixel = texture(picture, uv).rgb;
+    vec2 cell = fract(uv * vec2(textureSize(picture, 0)));
+    if (max(cell.x, cell.y) >= 2.0 / 3.0) {
+        pixel += (1.0 - pixel) * GAP_LIGHTNESS;
+    }
+    color = vec4(pixel, 1.0);
+}
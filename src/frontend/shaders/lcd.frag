@@ -0,0 +1,25 @@
+#version 330 core
+
+// A color LCD's red, green and blue columns, with a dark line between rows. Each Game Boy pixel is
+// split into 3 columns and 3 rows whatever size it's drawn at.
+uniform sampler2D picture;
+
+in vec2 uv;
+out vec4 color;
+
+// How much of the other two colors an LCD column lets through.
+const float BLEED = 0.25;
+
+const float GAP_DARKNESS = 0.6;
+
+void main() {
+    vec3 pixel = texture(picture, uv).rgb;
+    vec2 cell = fract(uv * vec2(textureSize(picture, 0)));
+    int column = min(int(cell.x * 3.0), 2);
+    vec3 shaded = pixel * BLEED;
+    shaded[column] = pixel[column];
+    if (cell.y >= 2.0 / 3.0) {
+        shaded *= GAP_DARKNESS;
+    }
+    color = vec4(shaded, 1.0);
+}
@@ -0,0 +1,18 @@
+#version 330 core
+
+// The bottom third of every row of Game Boy pixels darkened, like a CRT's scanlines.
+uniform sampler2D picture;
+
+in vec2 uv;
+out vec4 color;
+
+const float GAP_DARKNESS = 0.6;
+
+void main() {
+    vec3 pixel = texture(picture, uv).rgb;
+    float row = fract(uv.y * float(textureSize(picture, 0).y));
+    if (row >= 2.0 / 3.0) {
+        pixel *= GAP_DARKNESS;
+    }
+    color = vec4(pixel, 1.0);
+}
@@ -0,0 +1,14 @@
+#version 330 core
+
+// A rectangle over the whole viewport, drawn as a 4 vertex triangle strip without any buffers.
+// Textures hold the picture's top row first, which goes at the top of the window but at the bottom
+// of another texture.
+uniform bool offscreen;
+
+out vec2 uv;
+
+void main() {
+    vec2 corner = vec2(gl_VertexID & 1, gl_VertexID >> 1);
+    uv = offscreen ? corner : vec2(corner.x, 1.0 - corner.y);
+    gl_Position = vec4(corner * 2.0 - 1.0, 0.0, 1.0);
+}
@@ -0,0 +1,470 @@
+use std::io::{BufReader, ErrorKind, Read, Result, Write};
+use std::net::TcpStream;
+
+use crate::bus::{AccessKind, Watchpoint};
+use crate::debugger::{Debugger, Stop};
+use crate::emulator::{Emulator, CYCLES_PER_FRAME};
+
+// A GDB remote serial protocol stub. GDB has no SM83 target, so the registers are sent as six
+// little-endian 16-bit values: AF, BC, DE, HL, SP and PC, numbered 0 to 5.
+pub struct GdbStub {
+    debugger: Debugger,
+}
+
+// What the stub does with a packet after replying, or instead of it.
+#[derive(Debug, PartialEq, Eq)]
+enum Response {
+    Reply(String),
+    Step,
+    Continue,
+    // Replied to and closing the connection.
+    Detach(String),
+}
+
+impl Default for GdbStub {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl GdbStub {
+    pub fn new() -> GdbStub {
+        GdbStub {
+            debugger: Debugger::new(),
+        }
+    }
+
+    // Serves a connected debugger until it detaches, kills the target or disconnects. The emulator
+    // stays stopped until GDB asks it to step or continue.
+    pub fn serve(&mut self, emulator: &mut Emulator, stream: TcpStream) -> Result<()> {
+        let mut reader = BufReader::new(stream.try_clone()?);
+        let mut writer = stream;
+        while let Some(packet) = read_packet(&mut reader, &mut writer)? {
+            match self.respond(emulator, &packet) {
+                Response::Reply(reply) => write_packet(&mut writer, &reply)?,
+                Response::Step => {
+                    self.debugger.step(emulator);
+                    write_packet(&mut writer, SIGTRAP)?;
+                }
+                Response::Continue => {
+                    let reply = self.resume(emulator, &mut writer)?;
+                    write_packet(&mut writer, &reply)?;
+                }
+                Response::Detach(reply) => {
+                    write_packet(&mut writer, &reply)?;
+                    return Ok(());
+                }
+            }
+        }
+        Ok(())
+    }
+
+    // Runs a frame at a time until a breakpoint or watchpoint, checking between frames whether GDB
+    // sent Ctrl-C. Returns the stop reply.
+    fn resume(&mut self, emulator: &mut Emulator, stream: &mut TcpStream) -> Result<String> {
+        stream.set_nonblocking(true)?;
+        let result = loop {
+            if let Some(stop) = self.debugger.run(emulator, CYCLES_PER_FRAME) {
+                break Ok(stop_reply(stop));
+            }
+            let mut byte = [0];
+            match stream.read(&mut byte) {
+                Ok(0) => break Err(ErrorKind::UnexpectedEof.into()),
+                Ok(_) if byte[0] == INTERRUPT => break Ok(SIGINT.to_string()),
+                Ok(_) => {}
+                Err(error) if error.kind() == ErrorKind::WouldBlock => {}
+                Err(error) => break Err(error),
+            }
+        };
+        stream.set_nonblocking(false)?;
+        result
+    }
+
+    fn respond(&mut self, emulator: &mut Emulator, packet: &str) -> Response {
+        let reply = |text: &str| Response::Reply(text.to_string());
+        let (command, args) = packet.split_at(packet.len().min(1));
+        match command {
+            "?" => reply(SIGTRAP),
+            "g" => Response::Reply(registers(emulator).iter().map(|&r| hex16(r)).collect()),
+            "G" => match parse_words(args) {
+                Some(values) if values.len() == REGISTER_COUNT => {
+                    for (index, value) in values.into_iter().enumerate() {
+                        set_register(emulator, index, value);
+                    }
+                    reply("OK")
+                }
+                _ => reply(ERROR),
+            },
+            "p" => match usize::from_str_radix(args, 16) {
+                Ok(index) if index < REGISTER_COUNT => {
+                    Response::Reply(hex16(registers(emulator)[index]))
+                }
+                _ => reply(ERROR),
+            },
+            "P" => match args.split_once('=').and_then(|(index, value)| {
+                let index = usize::from_str_radix(index, 16).ok()?;
+                let value = parse_words(value)?;
+                (index < REGISTER_COUNT && value.len() == 1).then(|| (index, value[0]))
+            }) {
+                Some((index, value)) => {
+                    set_register(emulator, index, value);
+                    reply("OK")
+                }
+                None => reply(ERROR),
+            },
+            "m" => match parse_address_length(args) {
+                Some((address, length)) => Response::Reply(
+                    (0..length)
+                        .map(|offset| {
+                            format!("{:02x}", emulator.peek(address.wrapping_add(offset)))
+                        })
+                        .collect(),
+                ),
+                None => reply(ERROR),
+            },
+            "M" => match args
+                .split_once(':')
+                .and_then(|(range, data)| Some((parse_address_length(range)?, parse_bytes(data)?)))
+            {
+                Some(((address, length), data)) if data.len() == length as usize => {
+                    for (offset, value) in data.into_iter().enumerate() {
+                        emulator.poke(address.wrapping_add(offset as u16), value);
+                    }
+                    reply("OK")
+                }
+                _ => reply(ERROR),
+            },
+            "Z" | "z" => match parse_point(args) {
+                Some(Point::Breakpoint(address)) if command == "Z" => {
+                    self.debugger.add_breakpoint(address);
+                    reply("OK")
+                }
+                Some(Point::Breakpoint(address)) => {
+                    self.debugger.remove_breakpoint(address);
+                    reply("OK")
+                }
+                Some(Point::Watchpoint(watchpoint)) if command == "Z" => {
+                    emulator.add_watchpoint(watchpoint);
+                    reply("OK")
+                }
+                Some(Point::Watchpoint(watchpoint)) => {
+                    emulator.remove_watchpoint(&watchpoint);
+                    reply("OK")
+                }
+                None => reply(""),
+            },
+            "s" | "c" => {
+                if let Ok(address) = u16::from_str_radix(args, 16) {
+                    emulator.cpu_mut().registers.pc = address;
+                }
+                if command == "s" {
+                    Response::Step
+                } else {
+                    Response::Continue
+                }
+            }
+            "H" => reply("OK"),
+            "q" if args.starts_with("Supported") => reply("PacketSize=4000"),
+            "q" if args == "Attached" => reply("1"),
+            "D" => Response::Detach("OK".to_string()),
+            "k" => Response::Detach(String::new()),
+            _ => reply(""),
+        }
+    }
+}
+
+fn registers(emulator: &Emulator) -> [u16; REGISTER_COUNT] {
+    let r = &emulator.cpu().registers;
+    [r.af(), r.bc(), r.de(), r.hl(), r.sp, r.pc]
+}
+
+fn set_register(emulator: &mut Emulator, index: usize, value: u16) {
+    let r = &mut emulator.cpu_mut().registers;
+    match index {
+        0 => r.set_af(value),
+        1 => r.set_bc(value),
+        2 => r.set_de(value),
+        3 => r.set_hl(value),
+        4 => r.sp = value,
+        _ => r.pc = value,
+    }
+}
+
+// Reads the next `$data#checksum` packet, acknowledging it. Returns None once the connection
+// closes. Bad checksums are NAKed so GDB resends.
+fn read_packet(reader: &mut impl Read, writer: &mut impl Write) -> Result<Option<String>> {
+    let mut byte = [0];
+    loop {
+        // Skip acknowledgements and interrupts sent while stopped.
+        loop {
+            if reader.read(&mut byte)? == 0 {
+                return Ok(None);
+            }
+            if byte[0] == b'$' {
+                break;
+            }
+        }
+
+        let mut data = Vec::new();
+        loop {
+            if reader.read(&mut byte)? == 0 {
+                return Ok(None);
+            }
+            if byte[0] == b'#' {
+                break;
+            }
+            data.push(byte[0]);
+        }
+        let mut checksum = [0; 2];
+        reader.read_exact(&mut checksum)?;
+
+        let expected = std::str::from_utf8(&checksum)
+            .ok()
+            .and_then(|text| u8::from_str_radix(text, 16).ok());
+        if expected == Some(packet_checksum(&data)) {
+            writer.write_all(b"+")?;
+            return Ok(Some(String::from_utf8_lossy(&data).into_owned()));
+        }
+        writer.write_all(b"-")?;
+    }
+}
+
+fn write_packet(writer: &mut impl Write, data: &str) -> Result<()> {
+    write!(writer, "${}#{:02x}", data, packet_checksum(data.as_bytes()))?;
+    writer.flush()
+}
+
+fn packet_checksum(data: &[u8]) -> u8 {
+    data.iter().fold(0, |sum, &byte| sum.wrapping_add(byte))
+}
+
+fn hex16(value: u16) -> String {
+    let [low, high] = value.to_le_bytes();
+    format!("{:02x}{:02x}", low, high)
+}
+
+fn parse_bytes(hex: &str) -> Option<Vec<u8>> {
+    if !hex.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|index| u8::from_str_radix(hex.get(index..index + 2)?, 16).ok())
+        .collect()
+}
+
+// Little-endian 16-bit values, the way registers are sent.
+fn parse_words(hex: &str) -> Option<Vec<u16>> {
+    let bytes = parse_bytes(hex)?;
+    if !bytes.len().is_multiple_of(2) {
+        return None;
+    }
+    Some(
+        bytes
+            .chunks(2)
+            .map(|pair| u16::from_le_bytes([pair[0], pair[1]]))
+            .collect(),
+    )
+}
+
+fn parse_address_length(args: &str) -> Option<(u16, u16)> {
+    let (address, length) = args.split_once(',')?;
+    Some((
+        u16::from_str_radix(address, 16).ok()?,
+        u16::from_str_radix(length, 16).ok()?,
+    ))
+}
+
+enum Point {
+    Breakpoint(u16),
+    Watchpoint(Watchpoint),
+}
+
+// `type,address,kind`. Software (0) and hardware (1) breakpoints are the same thing here. For the
+// write (2), read (3) and access (4) watchpoints, kind is the length in bytes.
+fn parse_point(args: &str) -> Option<Point> {
+    let mut parts = args.split(',');
+    let kind = parts.next()?;
+    let address = u16::from_str_radix(parts.next()?, 16).ok()?;
+    let length = u16::from_str_radix(parts.next().unwrap_or("1"), 16).ok()?;
+    let watchpoint = |read, write| {
+        Some(Point::Watchpoint(Watchpoint {
+            start: address,
+            end: address.wrapping_add(length.max(1) - 1),
+            read,
+            write,
+            execute: false,
+        }))
+    };
+    match kind {
+        "0" | "1" => Some(Point::Breakpoint(address)),
+        "2" => watchpoint(false, true),
+        "3" => watchpoint(true, false),
+        "4" => watchpoint(true, true),
+        _ => None,
+    }
+}
+
+fn stop_reply(stop: Stop) -> String {
+    match stop {
+        Stop::Watchpoint(hit) => {
+            let kind = match hit.kind {
+                AccessKind::Write => "watch",
+                AccessKind::Read => "rwatch",
+                AccessKind::Execute => return SIGTRAP.to_string(),
+            };
+            format!("T05{}:{:x};", kind, hit.address)
+        }
+        _ => SIGTRAP.to_string(),
+    }
+}
+
+const REGISTER_COUNT: usize = 6;
+
+const SIGINT: &str = "S02";
+
+const SIGTRAP: &str = "S05";
+
+const ERROR: &str = "E01";
+
+const INTERRUPT: u8 = 0x03;
+
+#[cfg(test)]
+mod tests {
+    use rstest::rstest;
+
+    use super::*;
+    use crate::rom::Rom;
+
+    fn emulator() -> Emulator {
+        let mut content = vec![0; 0x8000];
+        content[0x0100..0x0103].copy_from_slice(&[0x00, 0x18, 0xFD]);
+        Emulator::new(Rom::from_content(content)).unwrap()
+    }
+
+    fn reply(text: &str) -> Response {
+        Response::Reply(text.to_string())
+    }
+
+    #[test]
+    fn test_read_registers() {
+        let mut stub = GdbStub::new();
+
+        assert_eq!(
+            stub.respond(&mut emulator(), "g"),
+            reply("b0011300d8004d01feff0001")
+        );
+    }
+
+    #[test]
+    fn test_write_registers() {
+        // Arrange
+        let mut stub = GdbStub::new();
+        let mut emulator = emulator();
+
+        // Act
+        let all = stub.respond(&mut emulator, "G00123456789abcdef0011223");
+        let pc = stub.respond(&mut emulator, "P5=5001");
+
+        // Assert
+        assert_eq!(all, reply("OK"));
+        assert_eq!(pc, reply("OK"));
+        let r = &emulator.cpu().registers;
+        assert_eq!((r.a, r.f), (0x12, 0x00));
+        assert_eq!(r.bc(), 0x5634);
+        assert_eq!(r.sp, 0x01F0);
+        assert_eq!(r.pc, 0x0150);
+        assert_eq!(stub.respond(&mut emulator, "p5"), reply("5001"));
+    }
+
+    #[test]
+    fn test_memory() {
+        let mut stub = GdbStub::new();
+        let mut emulator = emulator();
+
+        assert_eq!(stub.respond(&mut emulator, "MC000,2:abcd"), reply("OK"));
+
+        assert_eq!(stub.respond(&mut emulator, "mC000,2"), reply("abcd"));
+        assert_eq!(stub.respond(&mut emulator, "m0100,3"), reply("0018fd"));
+    }
+
+    #[rstest]
+    #[case("p9")]
+    #[case("G00")]
+    #[case("mzz,1")]
+    #[case("MC000,2:ab")]
+    fn test_malformed_packets(#[case] packet: &str) {
+        assert_eq!(
+            GdbStub::new().respond(&mut emulator(), packet),
+            reply(ERROR)
+        );
+    }
+
+    #[test]
+    fn test_breakpoints() {
+        let mut stub = GdbStub::new();
+        let mut emulator = emulator();
+
+        assert_eq!(stub.respond(&mut emulator, "Z0,101,1"), reply("OK"));
+        assert_eq!(stub.respond(&mut emulator, "c"), Response::Continue);
+        assert!(stub.debugger.run(&mut emulator, 1000).is_some());
+        assert_eq!(emulator.cpu().registers.pc, 0x0101);
+
+        assert_eq!(stub.respond(&mut emulator, "z0,101,1"), reply("OK"));
+        assert!(!stub.debugger.has_breakpoint(0x0101));
+        assert_eq!(stub.respond(&mut emulator, "Z9,101,1"), reply(""));
+    }
+
+    #[test]
+    fn test_watchpoints() {
+        // Arrange
+        let mut stub = GdbStub::new();
+        let mut emulator = emulator();
+        // LD (0xC001),A
+        emulator.cpu_mut().registers.pc = 0xC000;
+        stub.respond(&mut emulator, "MC000,4:ea01c000");
+
+        // Act
+        let added = stub.respond(&mut emulator, "Z2,c001,2");
+        let stop = stub.debugger.run(&mut emulator, 1000).unwrap();
+
+        // Assert
+        assert_eq!(added, reply("OK"));
+        assert_eq!(stop_reply(stop), "T05watch:c001;");
+        assert_eq!(stub.respond(&mut emulator, "z2,c001,2"), reply("OK"));
+        assert!(emulator.bus().watchpoints().is_empty());
+    }
+
+    #[test]
+    fn test_continue_at_address() {
+        let mut stub = GdbStub::new();
+        let mut emulator = emulator();
+
+        assert_eq!(stub.respond(&mut emulator, "s0102"), Response::Step);
+        assert_eq!(emulator.cpu().registers.pc, 0x0102);
+    }
+
+    #[test]
+    fn test_read_packet() {
+        // Arrange
+        let mut input: &[u8] = b"+$m0,1#xx$m0,1#fa";
+        let mut output = Vec::new();
+
+        // Act
+        let packet = read_packet(&mut input, &mut output).unwrap();
+
+        // Assert
+        assert_eq!(packet.as_deref(), Some("m0,1"));
+        assert_eq!(output, b"-+");
+        assert_eq!(read_packet(&mut input, &mut output).unwrap(), None);
+    }
+
+    #[test]
+    fn test_write_packet() {
+        let mut output = Vec::new();
+
+        write_packet(&mut output, "OK").unwrap();
+
+        assert_eq!(output, b"$OK#9a");
+    }
+}
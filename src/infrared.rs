@@ -0,0 +1,222 @@
+use std::fs;
+use std::io::{Error, Result};
+use std::path::Path;
+
+use crate::savestate::{Snapshot, StateReader, StateWriter};
+
+// Whatever the CGB's infrared port points at. Times are in CPU cycles since power on, so they run
+// twice as fast in double speed mode like the CPU does.
+pub trait IrDevice {
+    // Called when the game turns its LED on or off.
+    fn emit(&mut self, _on: bool, _now: u64) {}
+
+    // Whether light reaches the sensor.
+    fn receiving(&mut self, now: u64) -> bool;
+}
+
+// Nothing in front of the port, the same as no device at all.
+pub struct IrDark;
+
+impl IrDevice for IrDark {
+    fn receiving(&mut self, _now: u64) -> bool {
+        false
+    }
+}
+
+// A mirror in front of the port: the sensor sees the game's own LED, which is how games check the
+// port works.
+#[derive(Default)]
+pub struct IrLoopback {
+    led: bool,
+}
+
+impl IrDevice for IrLoopback {
+    fn emit(&mut self, on: bool, _now: u64) {
+        self.led = on;
+    }
+
+    fn receiving(&mut self, _now: u64) -> bool {
+        self.led
+    }
+}
+
+// Plays back a recording of pulses, like the other side of a Mystery Gift. The recording starts
+// the first time the game looks at the sensor and stays dark once it's over.
+pub struct IrScript {
+    // Alternating cycles of light and dark.
+    durations: Vec<u64>,
+    start: Option<u64>,
+}
+
+impl IrScript {
+    pub fn new(durations: Vec<u64>) -> IrScript {
+        IrScript {
+            durations,
+            start: None,
+        }
+    }
+
+    // A text file of durations in CPU cycles separated by whitespace, starting with light. Anything
+    // after a # is a comment.
+    pub fn load(path: &Path) -> Result<IrScript> {
+        let text = fs::read_to_string(path)?;
+        IrScript::parse(&text)
+            .map_err(|error| Error::other(format!("{}: {}", path.display(), error)))
+    }
+
+    pub fn parse(text: &str) -> Result<IrScript> {
+        let mut durations = Vec::new();
+        for line in text.lines() {
+            let line = line.split('#').next().unwrap_or_default();
+            for word in line.split_whitespace() {
+                let duration = word.parse().map_err(|_| {
+                    Error::other(format!(
+                        "{} is not a duration, expected a number of cycles.",
+                        word
+                    ))
+                })?;
+                durations.push(duration);
+            }
+        }
+        Ok(IrScript::new(durations))
+    }
+}
+
+impl IrDevice for IrScript {
+    fn receiving(&mut self, now: u64) -> bool {
+        let mut elapsed = now - *self.start.get_or_insert(now);
+        for (index, &duration) in self.durations.iter().enumerate() {
+            if elapsed < duration {
+                return index % 2 == 0;
+            }
+            elapsed -= duration;
+        }
+        false
+    }
+}
+
+// RP (0xFF56) on the CGB: the LED in bit 0, reading enabled with both of bits 6 and 7, and bit 1
+// low while light is received. Without a device the port sees nothing.
+pub struct Infrared {
+    rp: u8,
+    device: Option<Box<dyn IrDevice>>,
+}
+
+impl Default for Infrared {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Infrared {
+    pub fn new() -> Infrared {
+        Infrared {
+            rp: 0,
+            device: None,
+        }
+    }
+
+    pub fn set_device(&mut self, device: Option<Box<dyn IrDevice>>) {
+        self.device = device;
+    }
+
+    pub fn take_device(&mut self) -> Option<Box<dyn IrDevice>> {
+        self.device.take()
+    }
+
+    pub fn read(&mut self, now: u64) -> u8 {
+        let reading = self.rp & READ_ENABLE == READ_ENABLE;
+        let receiving = reading
+            && self
+                .device
+                .as_mut()
+                .is_some_and(|device| device.receiving(now));
+        let signal = if receiving { 0 } else { NO_SIGNAL };
+        0x3C | self.rp | signal
+    }
+
+    pub fn write(&mut self, value: u8, now: u64) {
+        let led = value & LED_ON != 0;
+        if led != (self.rp & LED_ON != 0) {
+            if let Some(device) = &mut self.device {
+                device.emit(led, now);
+            }
+        }
+        self.rp = value & (READ_ENABLE | LED_ON);
+    }
+}
+
+impl Snapshot for Infrared {
+    fn component(&self) -> &'static str {
+        "infrared port"
+    }
+
+    fn version(&self) -> u8 {
+        1
+    }
+
+    fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_u8(self.rp);
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader) -> Result<()> {
+        self.rp = reader.read_u8()? & (READ_ENABLE | LED_ON);
+        Ok(())
+    }
+}
+
+const READ_ENABLE: u8 = 0xC0;
+
+const NO_SIGNAL: u8 = 0x02;
+
+const LED_ON: u8 = 0x01;
+
+#[cfg(test)]
+mod tests {
+    use rstest::rstest;
+
+    use super::*;
+
+    #[rstest]
+    #[case(0x00, 0x3E)]
+    #[case(0x01, 0x3F)]
+    #[case(0xC0, 0xFE)]
+    #[case(0xC1, 0xFD)]
+    fn test_loopback(#[case] value: u8, #[case] expected: u8) {
+        let mut infrared = Infrared::new();
+        infrared.set_device(Some(Box::new(IrLoopback::default())));
+
+        infrared.write(value, 0);
+
+        assert_eq!(infrared.read(0), expected);
+    }
+
+    #[test]
+    fn test_no_device_is_dark() {
+        let mut infrared = Infrared::new();
+
+        infrared.write(0xC1, 0);
+
+        assert_eq!(infrared.read(0), 0xFF);
+    }
+
+    #[test]
+    fn test_script() {
+        // Arrange
+        let mut script = IrScript::parse("# Light, then dark, then light\n100 50\n25").unwrap();
+
+        // Act
+        let received: Vec<bool> = [1000, 1099, 1100, 1149, 1150, 1175]
+            .iter()
+            .map(|&now| script.receiving(now))
+            .collect();
+
+        // Assert
+        assert_eq!(received, [true, true, false, false, true, false]);
+    }
+
+    #[test]
+    fn test_parse_invalid_script() {
+        assert!(IrScript::parse("100 long").is_err());
+    }
+}
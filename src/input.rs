@@ -0,0 +1,296 @@
+use std::collections::{HashMap, HashSet};
+use std::hash::Hash;
+use std::io::{Error, Result};
+use std::str::FromStr;
+
+use crate::joypad::Button;
+
+// What an input does: hold a button, or press and release it over and over while held.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Target {
+    Button(Button),
+    Turbo(Button),
+}
+
+impl Target {
+    pub fn button(self) -> Button {
+        match self {
+            Target::Button(button) | Target::Turbo(button) => button,
+        }
+    }
+}
+
+impl FromStr for Target {
+    type Err = Error;
+
+    // A button's name, or turbo_ followed by one like turbo_a.
+    fn from_str(name: &str) -> Result<Target> {
+        let lowercase = name.to_ascii_lowercase();
+        match lowercase.strip_prefix("turbo_") {
+            Some(button) => button.parse().map(Target::Turbo),
+            None => lowercase.parse().map(Target::Button),
+        }
+        .map_err(|_| Error::other(format!("{} is not a Game Boy button.", name)))
+    }
+}
+
+// Maps whatever the frontend reads, like keys and controller buttons, to the Game Boy's buttons.
+// A button stays down while anything bound to it is held, so letting go of a key doesn't release
+// a button a controller still holds.
+pub struct InputMap<I> {
+    bindings: HashMap<I, Target>,
+    held: HashSet<I>,
+    // The frames each turbo button stays pressed, then released, for.
+    turbo_periods: HashMap<Button, u32>,
+    // Frames since each turbo button started being held.
+    turbo_frames: HashMap<Button, u32>,
+}
+
+impl<I: Copy + Eq + Hash> Default for InputMap<I> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<I: Copy + Eq + Hash> InputMap<I> {
+    pub fn new() -> InputMap<I> {
+        InputMap {
+            bindings: HashMap::new(),
+            held: HashSet::new(),
+            turbo_periods: HashMap::new(),
+            turbo_frames: HashMap::new(),
+        }
+    }
+
+    pub fn bind(&mut self, input: I, target: Target) {
+        self.bindings.insert(input, target);
+    }
+
+    // How many frames a turbo `button` stays pressed and then released for, DEFAULT_TURBO_PERIOD
+    // unless set.
+    pub fn set_turbo_period(&mut self, button: Button, frames: u32) {
+        self.turbo_periods.insert(button, frames.max(1));
+    }
+
+    // Removes the inputs `unbind` picks, like a controller's when it's unplugged, and returns the
+    // buttons nothing holds anymore.
+    pub fn unbind(&mut self, unbind: impl Fn(&I) -> bool) -> Vec<Button> {
+        let held: Vec<I> = self.held.iter().copied().filter(&unbind).collect();
+        let released = held
+            .into_iter()
+            .filter_map(|input| self.release(input))
+            .collect();
+        self.bindings.retain(|input, _| !unbind(input));
+        released
+    }
+
+    // The button to press, if `input` is bound and its button wasn't down already.
+    pub fn press(&mut self, input: I) -> Option<Button> {
+        let target = *self.bindings.get(&input)?;
+        let button = target.button();
+        let was_pressed = self.pressed(button);
+        if let Target::Turbo(button) = target {
+            if !self.held_target(target) {
+                self.turbo_frames.insert(button, 0);
+            }
+        }
+        self.held.insert(input);
+        (!was_pressed && self.pressed(button)).then_some(button)
+    }
+
+    // The button to release, if `input` was the last thing holding it down.
+    pub fn release(&mut self, input: I) -> Option<Button> {
+        if !self.held.remove(&input) {
+            return None;
+        }
+        let button = self.bindings[&input].button();
+        (!self.pressed(button)).then_some(button)
+    }
+
+    // Presses or releases `input`.
+    pub fn set(&mut self, input: I, held: bool) -> Option<(Button, bool)> {
+        if held {
+            self.press(input).map(|button| (button, true))
+        } else {
+            self.release(input).map(|button| (button, false))
+        }
+    }
+
+    // Moves the turbo buttons on by a frame and returns the ones that changed.
+    pub fn next_frame(&mut self) -> Vec<(Button, bool)> {
+        let held: Vec<Button> = self
+            .turbo_frames
+            .keys()
+            .copied()
+            .filter(|&button| self.held_target(Target::Turbo(button)))
+            .collect();
+        let mut changes = Vec::new();
+        for button in held {
+            let was_pressed = self.pressed(button);
+            *self.turbo_frames.entry(button).or_default() += 1;
+            let pressed = self.pressed(button);
+            if pressed != was_pressed {
+                changes.push((button, pressed));
+            }
+        }
+        changes
+    }
+
+    fn pressed(&self, button: Button) -> bool {
+        self.held_target(Target::Button(button))
+            || self.held_target(Target::Turbo(button)) && self.turbo_on(button)
+    }
+
+    fn held_target(&self, target: Target) -> bool {
+        self.held
+            .iter()
+            .any(|input| self.bindings.get(input) == Some(&target))
+    }
+
+    fn turbo_on(&self, button: Button) -> bool {
+        let period = self
+            .turbo_periods
+            .get(&button)
+            .copied()
+            .unwrap_or(DEFAULT_TURBO_PERIOD);
+        let frames = self.turbo_frames.get(&button).copied().unwrap_or(0);
+        (frames / period) % 2 == 0
+    }
+}
+
+// Which directions an analog stick at `x` and `y` (down and right are positive) holds, with
+// `deadzone` (0 to 1) of its travel ignored so a stick at rest doesn't drift.
+pub fn stick_directions(x: i16, y: i16, deadzone: f32) -> [(Button, bool); 4] {
+    let threshold = (deadzone.clamp(0.0, 1.0) * i16::MAX as f32) as i32;
+    let (x, y) = (x as i32, y as i32);
+    [
+        (Button::Right, x > threshold),
+        (Button::Left, x < -threshold),
+        (Button::Up, y < -threshold),
+        (Button::Down, y > threshold),
+    ]
+}
+
+pub const DEFAULT_DEADZONE: f32 = 0.3;
+
+// Pressed for 2 frames and released for 2, 15 presses a second. Most games only see a press that
+// was released for at least a frame.
+pub const DEFAULT_TURBO_PERIOD: u32 = 2;
+
+#[cfg(test)]
+mod tests {
+    use rstest::rstest;
+
+    use super::*;
+
+    #[test]
+    fn test_held_by_two_inputs() {
+        // Arrange
+        let mut inputs = InputMap::new();
+        inputs.bind('z', Target::Button(Button::A));
+        inputs.bind('k', Target::Button(Button::A));
+
+        // Act
+        let presses = [inputs.press('z'), inputs.press('k')];
+        let releases = [inputs.release('z'), inputs.release('k')];
+
+        // Assert
+        assert_eq!(presses, [Some(Button::A), None]);
+        assert_eq!(releases, [None, Some(Button::A)]);
+    }
+
+    #[test]
+    fn test_unbound_input() {
+        let mut inputs = InputMap::new();
+        inputs.bind('z', Target::Button(Button::A));
+
+        assert_eq!(inputs.press('q'), None);
+        assert_eq!(inputs.release('q'), None);
+        assert_eq!(inputs.release('z'), None);
+    }
+
+    #[test]
+    fn test_unbind_releases() {
+        // Arrange
+        let mut inputs = InputMap::new();
+        inputs.bind((0, 'a'), Target::Button(Button::A));
+        inputs.bind((1, 'a'), Target::Button(Button::B));
+        inputs.bind((1, 'b'), Target::Button(Button::A));
+        inputs.press((0, 'a'));
+        inputs.press((1, 'a'));
+        inputs.press((1, 'b'));
+
+        // Act
+        let released = inputs.unbind(|&(controller, _)| controller == 1);
+
+        // Assert
+        // A is still held by the first controller.
+        assert_eq!(released, [Button::B]);
+        assert_eq!(inputs.press((1, 'a')), None);
+    }
+
+    #[test]
+    fn test_turbo() {
+        // Arrange
+        let mut inputs = InputMap::new();
+        inputs.bind('t', Target::Turbo(Button::A));
+        inputs.set_turbo_period(Button::A, 2);
+
+        // Act
+        let pressed = inputs.press('t');
+        let frames: Vec<_> = (0..5).map(|_| inputs.next_frame()).collect();
+        let released = inputs.release('t');
+
+        // Assert
+        assert_eq!(pressed, Some(Button::A));
+        assert_eq!(
+            frames,
+            [
+                vec![],
+                vec![(Button::A, false)],
+                vec![],
+                vec![(Button::A, true)],
+                vec![],
+            ]
+        );
+        assert_eq!(released, Some(Button::A));
+        assert!(inputs.next_frame().is_empty());
+    }
+
+    #[test]
+    fn test_turbo_under_held_button() {
+        let mut inputs = InputMap::new();
+        inputs.bind('t', Target::Turbo(Button::A));
+        inputs.bind('z', Target::Button(Button::A));
+        inputs.set_turbo_period(Button::A, 1);
+        inputs.press('z');
+        inputs.press('t');
+
+        // Holding A normally keeps it down whatever the turbo does.
+        assert!(inputs.next_frame().is_empty());
+        assert_eq!(inputs.release('z'), Some(Button::A));
+        assert_eq!(inputs.next_frame(), [(Button::A, true)]);
+    }
+
+    #[rstest]
+    #[case(0, 0, [false, false, false, false])]
+    // Inside the deadzone.
+    #[case(9000, -9000, [false, false, false, false])]
+    #[case(20000, 0, [true, false, false, false])]
+    #[case(-20000, -20000, [false, true, true, false])]
+    #[case(0, i16::MAX, [false, false, false, true])]
+    fn test_stick_directions(#[case] x: i16, #[case] y: i16, #[case] expected: [bool; 4]) {
+        let directions = stick_directions(x, y, DEFAULT_DEADZONE);
+
+        assert_eq!(directions.map(|(_, held)| held), expected);
+    }
+
+    #[rstest]
+    #[case("Start", Some(Target::Button(Button::Start)))]
+    #[case("turbo_a", Some(Target::Turbo(Button::A)))]
+    #[case("TURBO_B", Some(Target::Turbo(Button::B)))]
+    #[case("turbo_c", None)]
+    fn test_parse_target(#[case] name: &str, #[case] expected: Option<Target>) {
+        assert_eq!(name.parse::<Target>().ok(), expected);
+    }
+}
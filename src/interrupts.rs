@@ -0,0 +1,139 @@
+use std::io::Result;
+
+use crate::savestate::{Snapshot, StateReader, StateWriter};
+
+// Holds the IF (0xFF0F) and IE (0xFFFF) registers. Components request interrupts by setting bits in
+// IF; the CPU services the lowest pending bit first.
+pub struct Interrupts {
+    flag: u8,
+    enable: u8,
+}
+
+impl Default for Interrupts {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Interrupts {
+    pub fn new() -> Interrupts {
+        Interrupts { flag: 0, enable: 0 }
+    }
+
+    pub fn request(&mut self, mask: u8) {
+        self.flag |= mask & INTERRUPT_MASK;
+    }
+
+    // Interrupts that are both requested and enabled.
+    pub fn pending(&self) -> u8 {
+        self.flag & self.enable & INTERRUPT_MASK
+    }
+
+    pub fn acknowledge(&mut self, mask: u8) {
+        self.flag &= !mask;
+    }
+
+    pub fn read(&self, address: u16) -> u8 {
+        match address {
+            0xFF0F => self.flag | !INTERRUPT_MASK,
+            0xFFFF => self.enable,
+            _ => 0xFF,
+        }
+    }
+
+    pub fn write(&mut self, address: u16, value: u8) {
+        match address {
+            0xFF0F => self.flag = value & INTERRUPT_MASK,
+            // The upper three bits of IE are plain storage.
+            0xFFFF => self.enable = value,
+            _ => {}
+        }
+    }
+}
+
+impl Snapshot for Interrupts {
+    fn component(&self) -> &'static str {
+        "interrupt controller"
+    }
+
+    fn version(&self) -> u8 {
+        1
+    }
+
+    fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_u8(self.flag);
+        writer.write_u8(self.enable);
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader) -> Result<()> {
+        self.flag = reader.read_u8()?;
+        self.enable = reader.read_u8()?;
+        Ok(())
+    }
+}
+
+// The address the CPU jumps to when servicing the interrupt with the given bit.
+pub fn vector(mask: u8) -> u16 {
+    0x40 + 8 * mask.trailing_zeros() as u16
+}
+
+pub const VBLANK_INTERRUPT: u8 = 0x01;
+
+pub const STAT_INTERRUPT: u8 = 0x02;
+
+pub const TIMER_INTERRUPT: u8 = 0x04;
+
+pub const SERIAL_INTERRUPT: u8 = 0x08;
+
+pub const JOYPAD_INTERRUPT: u8 = 0x10;
+
+const INTERRUPT_MASK: u8 = 0x1F;
+
+#[cfg(test)]
+mod tests {
+    use rstest::rstest;
+
+    use super::*;
+
+    #[test]
+    fn test_pending_requires_enable() {
+        let mut interrupts = Interrupts::new();
+
+        interrupts.request(TIMER_INTERRUPT | VBLANK_INTERRUPT);
+        interrupts.write(0xFFFF, TIMER_INTERRUPT);
+
+        assert_eq!(interrupts.pending(), TIMER_INTERRUPT);
+    }
+
+    #[test]
+    fn test_acknowledge_clears_flag() {
+        let mut interrupts = Interrupts::new();
+        interrupts.request(STAT_INTERRUPT | JOYPAD_INTERRUPT);
+
+        interrupts.acknowledge(STAT_INTERRUPT);
+
+        assert_eq!(interrupts.read(0xFF0F), 0xE0 | JOYPAD_INTERRUPT);
+    }
+
+    #[test]
+    fn test_register_masks() {
+        let mut interrupts = Interrupts::new();
+
+        interrupts.write(0xFF0F, 0xFF);
+        interrupts.write(0xFFFF, 0xFF);
+
+        assert_eq!(interrupts.read(0xFF0F), 0xFF);
+        assert_eq!(interrupts.read(0xFFFF), 0xFF);
+        assert_eq!(interrupts.pending(), 0x1F);
+    }
+
+    #[rstest]
+    #[case(VBLANK_INTERRUPT, 0x40)]
+    #[case(STAT_INTERRUPT, 0x48)]
+    #[case(TIMER_INTERRUPT, 0x50)]
+    #[case(SERIAL_INTERRUPT, 0x58)]
+    #[case(JOYPAD_INTERRUPT, 0x60)]
+    fn test_vector(#[case] mask: u8, #[case] expected: u16) {
+        assert_eq!(vector(mask), expected);
+    }
+}
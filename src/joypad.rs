@@ -0,0 +1,265 @@
+use std::io::{Error, Result};
+use std::str::FromStr;
+
+use crate::interrupts::JOYPAD_INTERRUPT;
+use crate::savestate::{Snapshot, StateReader, StateWriter};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Button {
+    Right,
+    Left,
+    Up,
+    Down,
+    A,
+    B,
+    Select,
+    Start,
+}
+
+impl Button {
+    // Direction buttons sit in the low nibble and action buttons in the high nibble.
+    fn mask(self) -> u8 {
+        1 << self as u8
+    }
+}
+
+impl FromStr for Button {
+    type Err = Error;
+
+    fn from_str(name: &str) -> Result<Button> {
+        match name.to_ascii_lowercase().as_str() {
+            "right" => Ok(Button::Right),
+            "left" => Ok(Button::Left),
+            "up" => Ok(Button::Up),
+            "down" => Ok(Button::Down),
+            "a" => Ok(Button::A),
+            "b" => Ok(Button::B),
+            "select" => Ok(Button::Select),
+            "start" => Ok(Button::Start),
+            _ => Err(Error::other(format!("{} is not a Game Boy button.", name))),
+        }
+    }
+}
+
+pub struct Joypad {
+    // Bits 4 and 5 of P1; a 0 selects the directions (bit 4) or the action buttons (bit 5).
+    select: u8,
+    pressed: u8,
+    interrupts: u8,
+}
+
+impl Default for Joypad {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Joypad {
+    pub fn new() -> Joypad {
+        Joypad {
+            select: SELECT_MASK,
+            pressed: 0,
+            interrupts: 0,
+        }
+    }
+
+    pub fn set_button(&mut self, button: Button, pressed: bool) {
+        let before = self.lines();
+        if pressed {
+            self.pressed |= button.mask();
+        } else {
+            self.pressed &= !button.mask();
+        }
+        self.request_on_falling_edge(before);
+    }
+
+    pub fn pressed(&self, button: Button) -> bool {
+        self.pressed & button.mask() != 0
+    }
+
+    // Every button at once, with bit `Button as u8` set for each one held.
+    pub fn buttons(&self) -> u8 {
+        self.pressed
+    }
+
+    pub fn set_buttons(&mut self, buttons: u8) {
+        let before = self.lines();
+        self.pressed = buttons;
+        self.request_on_falling_edge(before);
+    }
+
+    // Returns and clears the joypad interrupt requested since the last call.
+    pub fn take_interrupts(&mut self) -> u8 {
+        std::mem::take(&mut self.interrupts)
+    }
+
+    pub fn read(&self) -> u8 {
+        0xC0 | self.select | self.lines()
+    }
+
+    pub fn write(&mut self, value: u8) {
+        let before = self.lines();
+        self.select = value & SELECT_MASK;
+        self.request_on_falling_edge(before);
+    }
+
+    // The low nibble of P1, where a pressed button in a selected group pulls its line low.
+    fn lines(&self) -> u8 {
+        let mut pulled = 0;
+        if self.select & SELECT_DIRECTIONS == 0 {
+            pulled |= self.pressed & 0x0F;
+        }
+        if self.select & SELECT_ACTIONS == 0 {
+            pulled |= self.pressed >> 4;
+        }
+        !pulled & 0x0F
+    }
+
+    fn request_on_falling_edge(&mut self, before: u8) {
+        if before & !self.lines() != 0 {
+            self.interrupts |= JOYPAD_INTERRUPT;
+        }
+    }
+}
+
+impl Snapshot for Joypad {
+    fn component(&self) -> &'static str {
+        "joypad"
+    }
+
+    fn version(&self) -> u8 {
+        1
+    }
+
+    fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_u8(self.select);
+        writer.write_u8(self.pressed);
+        writer.write_u8(self.interrupts);
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader) -> Result<()> {
+        self.select = reader.read_u8()?;
+        self.pressed = reader.read_u8()?;
+        self.interrupts = reader.read_u8()?;
+        Ok(())
+    }
+}
+
+const SELECT_DIRECTIONS: u8 = 0x10;
+
+const SELECT_ACTIONS: u8 = 0x20;
+
+const SELECT_MASK: u8 = SELECT_DIRECTIONS | SELECT_ACTIONS;
+
+#[cfg(test)]
+mod tests {
+    use rstest::rstest;
+
+    use super::*;
+
+    #[rstest]
+    #[case("a", Some(Button::A))]
+    #[case("Start", Some(Button::Start))]
+    #[case("UP", Some(Button::Up))]
+    #[case("turbo", None)]
+    fn test_parse_button(#[case] name: &str, #[case] expected: Option<Button>) {
+        assert_eq!(name.parse::<Button>().ok(), expected);
+    }
+
+    #[test]
+    fn test_nothing_selected_reads_high() {
+        let mut joypad = Joypad::new();
+        joypad.set_button(Button::A, true);
+        joypad.set_button(Button::Up, true);
+
+        assert_eq!(joypad.read(), 0xFF);
+    }
+
+    #[rstest]
+    #[case(Button::Right, 0x20, 0xEE)]
+    #[case(Button::Left, 0x20, 0xED)]
+    #[case(Button::Up, 0x20, 0xEB)]
+    #[case(Button::Down, 0x20, 0xE7)]
+    #[case(Button::A, 0x10, 0xDE)]
+    #[case(Button::B, 0x10, 0xDD)]
+    #[case(Button::Select, 0x10, 0xDB)]
+    #[case(Button::Start, 0x10, 0xD7)]
+    #[case(Button::Start, 0x20, 0xEF)]
+    fn test_button_matrix(#[case] button: Button, #[case] select: u8, #[case] expected: u8) {
+        // Arrange
+        let mut joypad = Joypad::new();
+        joypad.write(select);
+
+        // Act
+        joypad.set_button(button, true);
+
+        // Assert
+        assert_eq!(joypad.read(), expected);
+    }
+
+    #[test]
+    fn test_both_groups_selected() {
+        let mut joypad = Joypad::new();
+        joypad.write(0x00);
+
+        joypad.set_button(Button::Right, true);
+        joypad.set_button(Button::B, true);
+
+        assert_eq!(joypad.read(), 0xCC);
+    }
+
+    #[test]
+    fn test_release() {
+        let mut joypad = Joypad::new();
+        joypad.write(0x10);
+        joypad.set_button(Button::A, true);
+
+        joypad.set_button(Button::A, false);
+
+        assert_eq!(joypad.read(), 0xDF);
+        assert!(!joypad.pressed(Button::A));
+    }
+
+    #[test]
+    fn test_press_requests_interrupt() {
+        let mut joypad = Joypad::new();
+        joypad.write(0x10);
+
+        joypad.set_button(Button::Start, true);
+
+        assert_eq!(joypad.take_interrupts(), JOYPAD_INTERRUPT);
+        assert_eq!(joypad.take_interrupts(), 0);
+    }
+
+    #[test]
+    fn test_unselected_press_does_not_request_interrupt() {
+        let mut joypad = Joypad::new();
+        joypad.write(0x20);
+
+        joypad.set_button(Button::Start, true);
+
+        assert_eq!(joypad.take_interrupts(), 0);
+    }
+
+    #[test]
+    fn test_selecting_held_button_requests_interrupt() {
+        let mut joypad = Joypad::new();
+        joypad.set_button(Button::Down, true);
+
+        joypad.write(0x20);
+
+        assert_eq!(joypad.take_interrupts(), JOYPAD_INTERRUPT);
+    }
+
+    #[test]
+    fn test_set_buttons() {
+        let mut joypad = Joypad::new();
+        joypad.write(0x10);
+
+        joypad.set_buttons(Button::A.mask() | Button::Start.mask());
+
+        assert_eq!(joypad.read(), 0xD6);
+        assert_eq!(joypad.buttons(), 0x90);
+        assert_eq!(joypad.take_interrupts(), JOYPAD_INTERRUPT);
+    }
+}
@@ -0,0 +1,56 @@
+pub mod accuracy;
+pub mod apu;
+pub mod archive;
+pub mod battery;
+pub mod blend;
+pub mod bus;
+pub mod call_stack;
+pub mod camera;
+#[cfg(feature = "capi")]
+pub mod capi;
+pub mod cdl;
+pub mod cheats;
+pub mod colorization;
+pub mod config;
+pub mod cpu;
+pub mod dat;
+pub mod debugger;
+pub mod disasm;
+pub mod display;
+pub mod emulator;
+pub mod filter;
+pub mod gdb;
+pub mod hash;
+pub mod infrared;
+pub mod input;
+pub mod interrupts;
+pub mod joypad;
+#[cfg(feature = "libretro")]
+pub mod libretro;
+pub mod mbc;
+pub mod model;
+pub mod movie;
+pub mod netplay;
+pub mod pacing;
+pub mod palette;
+pub mod patch;
+pub mod paths;
+pub mod peripherals;
+pub mod ppu;
+pub mod profiler;
+pub mod rewind;
+pub mod rom;
+pub mod savestate;
+pub mod scheduler;
+pub mod screenshot;
+#[cfg(feature = "lua")]
+pub mod script;
+pub mod serial;
+pub mod sgb;
+pub mod symbols;
+pub mod timer;
+pub mod trace;
+pub mod video;
+#[cfg(feature = "wasm")]
+pub mod wasm;
+pub mod wav;
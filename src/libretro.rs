@@ -0,0 +1,553 @@
+// A libretro core, so frontends like RetroArch can run the emulator. Build it with
+// `cargo build --release --features libretro` and load librustygameboy.so (or .dll or .dylib) as
+// the core. The frontend calls these functions from one thread, which is why the core lives in a
+// thread local.
+// The unsafe functions take pointers from the frontend, which libretro.h says how to pass.
+#![allow(clippy::missing_safety_doc)]
+
+use std::cell::RefCell;
+use std::ffi::{c_char, c_uint, c_void, CString};
+use std::ptr;
+use std::slice;
+
+use crate::apu::{CPU_CLOCK, DEFAULT_SAMPLE_RATE};
+use crate::cheats::Cheat;
+use crate::emulator::{AudioBufferCallback, Emulator, FrameCallback, CYCLES_PER_FRAME};
+use crate::joypad::Button;
+use crate::ppu::{SCREEN_HEIGHT, SCREEN_WIDTH};
+use crate::rom::{Rom, ValidationPolicy};
+use crate::savestate::{StateReader, StateWriter};
+
+#[repr(C)]
+pub struct SystemInfo {
+    library_name: *const c_char,
+    library_version: *const c_char,
+    valid_extensions: *const c_char,
+    need_fullpath: bool,
+    block_extract: bool,
+}
+
+#[repr(C)]
+pub struct GameGeometry {
+    base_width: c_uint,
+    base_height: c_uint,
+    max_width: c_uint,
+    max_height: c_uint,
+    aspect_ratio: f32,
+}
+
+#[repr(C)]
+pub struct SystemTiming {
+    fps: f64,
+    sample_rate: f64,
+}
+
+#[repr(C)]
+pub struct SystemAvInfo {
+    geometry: GameGeometry,
+    timing: SystemTiming,
+}
+
+#[repr(C)]
+pub struct GameInfo {
+    path: *const c_char,
+    data: *const c_void,
+    size: usize,
+    meta: *const c_char,
+}
+
+// How the frontend's log is handed over, in its own struct like libretro.h does.
+#[repr(C)]
+pub struct LogCallback {
+    log: Option<LogPrintf>,
+}
+
+pub type EnvironmentCallback = extern "C" fn(command: c_uint, data: *mut c_void) -> bool;
+pub type VideoRefreshCallback =
+    extern "C" fn(data: *const c_void, width: c_uint, height: c_uint, pitch: usize);
+pub type AudioSampleCallback = extern "C" fn(left: i16, right: i16);
+pub type AudioSampleBatchCallback = extern "C" fn(data: *const i16, frames: usize) -> usize;
+pub type InputPollCallback = extern "C" fn();
+pub type InputStateCallback =
+    extern "C" fn(port: c_uint, device: c_uint, index: c_uint, id: c_uint) -> i16;
+pub type LogPrintf = unsafe extern "C" fn(level: c_uint, format: *const c_char, ...);
+
+#[derive(Default)]
+struct Core {
+    environment: Option<EnvironmentCallback>,
+    video_refresh: Option<VideoRefreshCallback>,
+    audio_sample_batch: Option<AudioSampleBatchCallback>,
+    input_poll: Option<InputPollCallback>,
+    input_state: Option<InputStateCallback>,
+    log: Option<LogPrintf>,
+    // Kept to power cycle on reset.
+    rom: Vec<u8>,
+    emulator: Option<Emulator>,
+    // What retro_serialize_size reports, which mustn't change while the game is loaded.
+    state_size: usize,
+}
+
+impl Core {
+    fn run(&mut self) {
+        let Some(emulator) = self.emulator.as_mut() else {
+            return;
+        };
+
+        if let (Some(poll), Some(state)) = (self.input_poll, self.input_state) {
+            poll();
+            for (id, button) in BUTTONS {
+                let pressed = state(0, DEVICE_JOYPAD, 0, id) != 0;
+                emulator.set_button(button, pressed);
+            }
+        }
+
+        emulator.run_frame();
+    }
+
+    // Hands every frame and its audio straight to the frontend's callbacks as the emulator makes
+    // them. Called again whenever the callbacks or the emulator change.
+    fn observe(&mut self) {
+        let Some(emulator) = self.emulator.as_mut() else {
+            return;
+        };
+
+        emulator.on_vblank(self.video_refresh.map(|video_refresh| -> FrameCallback {
+            // The frame as XRGB8888, which every frontend supports.
+            let mut video = Vec::with_capacity(SCREEN_WIDTH * SCREEN_HEIGHT);
+            Box::new(move |frame| {
+                video.clear();
+                video.extend(
+                    frame
+                        .chunks_exact(4)
+                        .map(|pixel| u32::from_be_bytes([0, pixel[0], pixel[1], pixel[2]])),
+                );
+                video_refresh(
+                    video.as_ptr() as *const c_void,
+                    SCREEN_WIDTH as c_uint,
+                    SCREEN_HEIGHT as c_uint,
+                    SCREEN_WIDTH * 4,
+                );
+            })
+        }));
+
+        emulator.on_audio_buffer(self.audio_sample_batch.map(
+            |audio_sample_batch| -> AudioBufferCallback {
+                let mut audio: Vec<i16> = Vec::new();
+                Box::new(move |samples| {
+                    audio.clear();
+                    audio.extend(
+                        samples
+                            .iter()
+                            .map(|&sample| (sample.clamp(-1.0, 1.0) * i16::MAX as f32) as i16),
+                    );
+                    audio_sample_batch(audio.as_ptr(), audio.len() / 2);
+                })
+            },
+        ));
+    }
+
+    fn load(&mut self, rom: Vec<u8>) -> bool {
+        let emulator = Rom::from_bytes_with_policy(rom.clone(), ValidationPolicy::Lenient)
+            .and_then(|rom| Ok(Emulator::new(rom)?));
+        match emulator {
+            Ok(emulator) => {
+                self.rom = rom;
+                // The PPU's FIFOs and a half received SGB command change the state's length, the
+                // rest is set by the game.
+                self.state_size = frame_state(&emulator.save_state()).len() + STATE_SLACK;
+                self.emulator = Some(emulator);
+                self.observe();
+                true
+            }
+            Err(error) => {
+                log(
+                    self.log,
+                    LOG_ERROR,
+                    &format!("Could not load the ROM: {}", error),
+                );
+                false
+            }
+        }
+    }
+}
+
+thread_local! {
+    static CORE: RefCell<Core> = RefCell::new(Core::default());
+}
+
+fn with_core<T>(f: impl FnOnce(&mut Core) -> T) -> T {
+    CORE.with(|core| f(&mut core.borrow_mut()))
+}
+
+// The state after its length, so the zeros padding it to `state_size` can be told apart from it.
+fn frame_state(state: &[u8]) -> Vec<u8> {
+    let mut writer = StateWriter::new();
+    writer.write_bytes(state);
+    writer.into_bytes()
+}
+
+// Goes to the frontend's log, which is where RetroArch and the like show it, or to stderr when
+// the frontend has none.
+fn log(log: Option<LogPrintf>, level: c_uint, message: &str) {
+    let Some(log) = log else {
+        eprintln!("{}", message);
+        return;
+    };
+    // The message is passed as an argument so a % in it isn't read as a format.
+    let message = CString::new(message.replace('\0', "")).expect("NULs are removed");
+    unsafe { log(level, c"%s\n".as_ptr(), message.as_ptr()) };
+}
+
+#[no_mangle]
+pub extern "C" fn retro_api_version() -> c_uint {
+    API_VERSION
+}
+
+#[no_mangle]
+pub extern "C" fn retro_set_environment(callback: EnvironmentCallback) {
+    let mut log = LogCallback { log: None };
+    let has_log = callback(
+        ENVIRONMENT_GET_LOG_INTERFACE,
+        &mut log as *mut LogCallback as *mut c_void,
+    );
+    with_core(|core| {
+        core.environment = Some(callback);
+        core.log = log.log.filter(|_| has_log);
+    });
+}
+
+#[no_mangle]
+pub extern "C" fn retro_set_video_refresh(callback: VideoRefreshCallback) {
+    with_core(|core| {
+        core.video_refresh = Some(callback);
+        core.observe();
+    });
+}
+
+// Audio goes out a frame at a time through the batch callback instead.
+#[no_mangle]
+pub extern "C" fn retro_set_audio_sample(_callback: AudioSampleCallback) {}
+
+#[no_mangle]
+pub extern "C" fn retro_set_audio_sample_batch(callback: AudioSampleBatchCallback) {
+    with_core(|core| {
+        core.audio_sample_batch = Some(callback);
+        core.observe();
+    });
+}
+
+#[no_mangle]
+pub extern "C" fn retro_set_input_poll(callback: InputPollCallback) {
+    with_core(|core| core.input_poll = Some(callback));
+}
+
+#[no_mangle]
+pub extern "C" fn retro_set_input_state(callback: InputStateCallback) {
+    with_core(|core| core.input_state = Some(callback));
+}
+
+#[no_mangle]
+pub extern "C" fn retro_init() {}
+
+#[no_mangle]
+pub extern "C" fn retro_deinit() {
+    with_core(|core| core.emulator = None);
+}
+
+// `info` must point to a `retro_system_info` the frontend owns.
+#[no_mangle]
+pub unsafe extern "C" fn retro_get_system_info(info: *mut SystemInfo) {
+    *info = SystemInfo {
+        library_name: c"RustyGameBoy".as_ptr(),
+        library_version: concat!(env!("CARGO_PKG_VERSION"), "\0").as_ptr() as *const c_char,
+        valid_extensions: c"gb|gbc".as_ptr(),
+        need_fullpath: false,
+        block_extract: false,
+    };
+}
+
+// `info` must point to a `retro_system_av_info` the frontend owns.
+#[no_mangle]
+pub unsafe extern "C" fn retro_get_system_av_info(info: *mut SystemAvInfo) {
+    *info = SystemAvInfo {
+        geometry: GameGeometry {
+            base_width: SCREEN_WIDTH as c_uint,
+            base_height: SCREEN_HEIGHT as c_uint,
+            max_width: SCREEN_WIDTH as c_uint,
+            max_height: SCREEN_HEIGHT as c_uint,
+            aspect_ratio: SCREEN_WIDTH as f32 / SCREEN_HEIGHT as f32,
+        },
+        timing: SystemTiming {
+            fps: CPU_CLOCK as f64 / CYCLES_PER_FRAME as f64,
+            sample_rate: DEFAULT_SAMPLE_RATE as f64,
+        },
+    };
+}
+
+#[no_mangle]
+pub extern "C" fn retro_set_controller_port_device(_port: c_uint, _device: c_uint) {}
+
+// Power cycles, keeping the cartridge RAM like the real thing would.
+#[no_mangle]
+pub extern "C" fn retro_reset() {
+    with_core(|core| {
+        let save = core.emulator.as_ref().map(Emulator::save_data);
+        if core.load(core.rom.clone()) {
+            if let (Some(emulator), Some(save)) = (core.emulator.as_mut(), save) {
+                emulator.load_save_data(&save);
+            }
+        }
+    });
+}
+
+#[no_mangle]
+pub extern "C" fn retro_run() {
+    with_core(Core::run);
+}
+
+#[no_mangle]
+pub extern "C" fn retro_serialize_size() -> usize {
+    with_core(|core| core.emulator.as_ref().map_or(0, |_| core.state_size))
+}
+
+// `data` must point to `size` writable bytes.
+#[no_mangle]
+pub unsafe extern "C" fn retro_serialize(data: *mut c_void, size: usize) -> bool {
+    let Some(state) = with_core(|core| core.emulator.as_ref().map(Emulator::save_state)) else {
+        return false;
+    };
+    let state = frame_state(&state);
+    if state.len() > size {
+        return false;
+    }
+    ptr::copy_nonoverlapping(state.as_ptr(), data as *mut u8, state.len());
+    ptr::write_bytes((data as *mut u8).add(state.len()), 0, size - state.len());
+    true
+}
+
+// `data` must point to `size` readable bytes.
+#[no_mangle]
+pub unsafe extern "C" fn retro_unserialize(data: *const c_void, size: usize) -> bool {
+    let Ok(state) = StateReader::new(slice::from_raw_parts(data as *const u8, size)).read_bytes()
+    else {
+        return false;
+    };
+    with_core(|core| match core.emulator.as_mut() {
+        Some(emulator) => emulator.load_state(state).is_ok(),
+        None => false,
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn retro_cheat_reset() {
+    with_core(|core| {
+        if let Some(emulator) = core.emulator.as_mut() {
+            emulator.cheats_mut().clear();
+        }
+    });
+}
+
+// `code` must be a NUL-terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn retro_cheat_set(_index: c_uint, enabled: bool, code: *const c_char) {
+    if code.is_null() {
+        return;
+    }
+    let code = std::ffi::CStr::from_ptr(code).to_string_lossy();
+    with_core(|core| {
+        let Some(emulator) = core.emulator.as_mut() else {
+            return;
+        };
+        // Frontends join the codes of a multi-part cheat with +.
+        for text in code.split('+').map(str::trim) {
+            match Cheat::new(text) {
+                Ok(mut cheat) => {
+                    cheat.enabled = enabled;
+                    emulator.cheats_mut().add(cheat);
+                }
+                Err(error) => log(core.log, LOG_WARN, &error.to_string()),
+            }
+        }
+    });
+}
+
+// `info` must be null or point to a `retro_game_info` with `size` bytes at `data`.
+#[no_mangle]
+pub unsafe extern "C" fn retro_load_game(info: *const GameInfo) -> bool {
+    if info.is_null() || (*info).data.is_null() {
+        return false;
+    }
+    let rom = slice::from_raw_parts((*info).data as *const u8, (*info).size).to_vec();
+    with_core(|core| {
+        let mut format = PIXEL_FORMAT_XRGB8888;
+        let supported = core.environment.is_some_and(|environment| {
+            environment(
+                ENVIRONMENT_SET_PIXEL_FORMAT,
+                &mut format as *mut c_uint as *mut c_void,
+            )
+        });
+        if !supported {
+            log(
+                core.log,
+                LOG_ERROR,
+                "The frontend doesn't support XRGB8888.",
+            );
+            return false;
+        }
+        core.load(rom)
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn retro_load_game_special(
+    _game_type: c_uint,
+    _info: *const GameInfo,
+    _count: usize,
+) -> bool {
+    false
+}
+
+#[no_mangle]
+pub extern "C" fn retro_unload_game() {
+    with_core(|core| core.emulator = None);
+}
+
+#[no_mangle]
+pub extern "C" fn retro_get_region() -> c_uint {
+    REGION_NTSC
+}
+
+// Handing out cartridge RAM lets the frontend keep the save file.
+#[no_mangle]
+pub extern "C" fn retro_get_memory_data(id: c_uint) -> *mut c_void {
+    with_core(|core| match (id, core.emulator.as_mut()) {
+        (MEMORY_SAVE_RAM, Some(emulator)) if emulator.has_battery() => {
+            emulator.bus_mut().mbc_mut().ram_mut().as_mut_ptr() as *mut c_void
+        }
+        _ => ptr::null_mut(),
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn retro_get_memory_size(id: c_uint) -> usize {
+    with_core(|core| match (id, core.emulator.as_ref()) {
+        (MEMORY_SAVE_RAM, Some(emulator)) if emulator.has_battery() => {
+            emulator.bus().mbc().ram().len()
+        }
+        _ => 0,
+    })
+}
+
+const API_VERSION: c_uint = 1;
+
+const ENVIRONMENT_SET_PIXEL_FORMAT: c_uint = 10;
+
+const ENVIRONMENT_GET_LOG_INTERFACE: c_uint = 27;
+
+const PIXEL_FORMAT_XRGB8888: c_uint = 1;
+
+const LOG_WARN: c_uint = 2;
+
+const LOG_ERROR: c_uint = 3;
+
+const DEVICE_JOYPAD: c_uint = 1;
+
+const REGION_NTSC: c_uint = 0;
+
+const MEMORY_SAVE_RAM: c_uint = 0;
+
+// More than the FIFOs' pixels and objects and the longest SGB command can add to the state.
+const STATE_SLACK: usize = 0x400;
+
+// RETRO_DEVICE_ID_JOYPAD_* for each button.
+const BUTTONS: [(c_uint, Button); 8] = [
+    (0, Button::B),
+    (2, Button::Select),
+    (3, Button::Start),
+    (4, Button::Up),
+    (5, Button::Down),
+    (6, Button::Left),
+    (7, Button::Right),
+    (8, Button::A),
+];
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use super::*;
+
+    static FRAMES: AtomicUsize = AtomicUsize::new(0);
+    static AUDIO_FRAMES: AtomicUsize = AtomicUsize::new(0);
+
+    extern "C" fn environment(command: c_uint, _data: *mut c_void) -> bool {
+        command == ENVIRONMENT_SET_PIXEL_FORMAT
+    }
+
+    extern "C" fn video_refresh(data: *const c_void, width: c_uint, height: c_uint, pitch: usize) {
+        assert!(!data.is_null());
+        assert_eq!((width, height, pitch), (160, 144, 640));
+        FRAMES.fetch_add(1, Ordering::SeqCst);
+    }
+
+    extern "C" fn audio_sample_batch(_data: *const i16, frames: usize) -> usize {
+        AUDIO_FRAMES.fetch_add(frames, Ordering::SeqCst);
+        frames
+    }
+
+    extern "C" fn unused_log() {}
+
+    extern "C" fn log_environment(command: c_uint, data: *mut c_void) -> bool {
+        if command != ENVIRONMENT_GET_LOG_INTERFACE {
+            return false;
+        }
+        // Stable Rust can't define a variadic function, so this one is only kept, never called.
+        let log = unsafe { std::mem::transmute::<extern "C" fn(), LogPrintf>(unused_log) };
+        unsafe { (*(data as *mut LogCallback)).log = Some(log) };
+        true
+    }
+
+    #[test]
+    fn test_log_interface() {
+        retro_set_environment(log_environment);
+        assert!(with_core(|core| core.log.is_some()));
+
+        retro_set_environment(environment);
+        assert!(with_core(|core| core.log.is_none()));
+    }
+
+    #[test]
+    fn test_run_game() {
+        // Arrange
+        retro_set_environment(environment);
+        retro_set_video_refresh(video_refresh);
+        retro_set_audio_sample_batch(audio_sample_batch);
+        retro_init();
+        let rom = vec![0; 0x8000];
+        let info = GameInfo {
+            path: ptr::null(),
+            data: rom.as_ptr() as *const c_void,
+            size: rom.len(),
+            meta: ptr::null(),
+        };
+
+        // Act
+        let loaded = unsafe { retro_load_game(&info) };
+        retro_run();
+        retro_run();
+
+        // Assert
+        assert!(loaded);
+        assert_eq!(FRAMES.load(Ordering::SeqCst), 2);
+        assert!(AUDIO_FRAMES.load(Ordering::SeqCst) > 0);
+        let mut state = vec![0; retro_serialize_size()];
+        assert!(unsafe { retro_serialize(state.as_mut_ptr() as *mut c_void, state.len()) });
+        assert!(unsafe { retro_unserialize(state.as_ptr() as *const c_void, state.len()) });
+        retro_run();
+        assert_eq!(retro_serialize_size(), state.len());
+        assert!(unsafe { retro_serialize(state.as_mut_ptr() as *mut c_void, state.len()) });
+        assert!(unsafe { retro_unserialize(state.as_ptr() as *const c_void, state.len()) });
+        retro_unload_game();
+        retro_deinit();
+    }
+}
@@ -1,17 +1,1693 @@
-pub mod rom;
+use std::ops::Range;
+use std::path::PathBuf;
+use std::process::ExitCode;
+use std::{fs, io};
 
-use std::{env, io};
+use clap::parser::ValueSource;
+use clap::{Args, CommandFactory, FromArgMatches, Parser, Subcommand};
+use rustygameboy::accuracy::Accuracy;
+use rustygameboy::apu::Channel;
+use rustygameboy::cheats::{Cheat, Cheats};
+use rustygameboy::colorization::PaletteCombo;
+use rustygameboy::config::{Config, Controllers, Settings};
+use rustygameboy::debugger::Region;
+use rustygameboy::disasm;
+use rustygameboy::display::Scaling;
+use rustygameboy::emulator::{RunLimit, CYCLES_PER_FRAME};
+use rustygameboy::filter::Filter;
+use rustygameboy::input::Target;
+use rustygameboy::joypad::Button;
+use rustygameboy::model::EmulatorModel;
+use rustygameboy::movie::{Movie, MovieSession};
+use rustygameboy::pacing::SyncMode;
+use rustygameboy::ppu::Layer;
+use rustygameboy::rom;
+use rustygameboy::screenshot::ScreenshotColors;
+use rustygameboy::symbols::Symbols;
+use rustygameboy::trace::TraceFormat;
 
-fn main() -> io::Result<()> {
-    let args: Vec<String> = env::args().collect();
-    if args.len() != 2 {
-        return Err(io::Error::new(
-            io::ErrorKind::Other,
-            "Expected only the path to the ROM.",
+#[cfg(feature = "sdl")]
+mod frontend;
+mod rom_info;
+mod scan;
+#[cfg(feature = "debugger")]
+mod tui;
+
+#[derive(Parser)]
+#[command(
+    version,
+    about = "A Game Boy and Game Boy Color emulator.",
+    args_conflicts_with_subcommands = true,
+    subcommand_negates_reqs = true
+)]
+struct Cli {
+    #[command(subcommand)]
+    command: Option<Command>,
+    // Running a ROM is the default so `rusty_gameboy game.gb` keeps working.
+    #[command(flatten)]
+    run: RunArgs,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    #[command(about = "Run a ROM (the default when no command is given).")]
+    Run(Box<RunArgs>),
+    #[command(about = "Print what the cartridge header says about a ROM.")]
+    RomInfo {
+        #[arg(help = "Path to the ROM.")]
+        rom: String,
+        #[arg(long, value_enum, default_value_t = rom_info::Format::Text)]
+        format: rom_info::Format,
+        #[arg(
+            long,
+            value_name = "PATH",
+            help = "Look the ROM up in a No-Intro DAT file (Logiqx XML) for its name and whether the dump is verified."
+        )]
+        dat: Option<PathBuf>,
+    },
+    #[command(about = "Disassemble part of a ROM.")]
+    Disasm {
+        #[arg(help = "Path to the ROM.")]
+        rom: String,
+        #[arg(
+            long,
+            value_parser = parse_range,
+            help = "Addresses to disassemble, like 0x150..0x200. The end is exclusive and at most 0x8000."
+        )]
+        range: Range<u16>,
+        #[arg(long, default_value_t = 1, help = "ROM bank mapped at 0x4000-0x7FFF.")]
+        bank: usize,
+        #[arg(
+            long,
+            value_name = "PATH",
+            help = "Show labels from this RGBDS or wla-dx .sym file. Defaults to the .sym file next to the ROM."
+        )]
+        sym: Option<PathBuf>,
+    },
+    #[command(
+        about = "Check every .gb and .gbc file under a directory and list their titles, controllers, hashes and problems. Fails if any of them can't be loaded."
+    )]
+    Scan {
+        #[arg(help = "Directory to search, including its subdirectories.")]
+        dir: PathBuf,
+        #[arg(long, value_enum, default_value_t = scan::Format::Table)]
+        format: scan::Format,
+    },
+}
+
+#[derive(Args)]
+struct RunArgs {
+    #[arg(help = "Path to the ROM. Without one, the window waits for a ROM to be dropped on it.")]
+    rom: Option<String>,
+    #[arg(long, help = "Load and validate the ROM without opening a window.")]
+    headless: bool,
+    #[arg(
+        long,
+        value_name = "PATH",
+        help = "Read settings from this file instead of config.toml in the config directory, like ~/.config/rustygameboy. Options given here win over it."
+    )]
+    config: Option<String>,
+    #[arg(
+        long,
+        value_name = "DIR",
+        help = "Keep battery saves in this directory instead of next to the ROM."
+    )]
+    save_dir: Option<String>,
+    #[arg(
+        long,
+        value_name = "PATH",
+        help = "Run this boot ROM before the cartridge."
+    )]
+    boot_rom: Option<String>,
+    #[arg(
+        long,
+        value_name = "PATH",
+        help = "Apply an IPS or BPS patch to the ROM before loading it. The file stays unchanged."
+    )]
+    patch: Option<String>,
+    #[arg(long, help = "Fail if the global checksum doesn't match.")]
+    verify_checksum: bool,
+    #[arg(
+        long,
+        help = "Load ROMs without the Nintendo logo too, which homebrew often leaves out, only warning about it."
+    )]
+    lenient: bool,
+    #[arg(
+        long,
+        help = "Report accesses to echo RAM, 0xFEA0-0xFEFF and unmapped cartridge RAM."
+    )]
+    strict_memory: bool,
+    #[arg(
+        long,
+        value_name = "MODEL",
+        help = "Hardware to emulate: dmg, mgb, sgb or cgb. Defaults to what the cartridge supports."
+    )]
+    model: Option<EmulatorModel>,
+    #[arg(
+        long,
+        value_name = "LEVEL",
+        default_value = "normal",
+        help = "How closely to follow the hardware: normal, or high to draw with the PPU's pixel FIFO a dot at a time. High is slower but shows mid-line register changes."
+    )]
+    accuracy: Accuracy,
+    #[arg(
+        long,
+        value_name = "PALETTE",
+        help = "Colors for DMG games: gray, green, pocket, high-contrast or a JASC .pal file with 4 or 12 colors. P cycles through them while playing."
+    )]
+    palette: Option<String>,
+    #[arg(
+        long,
+        value_name = "COMBO",
+        help = "Colors for a DMG game on a CGB, picked like the boot ROM's button combinations: up, left, down or right, optionally followed by +a or +b."
+    )]
+    cgb_palette: Option<PaletteCombo>,
+    #[arg(
+        long,
+        value_name = "FRAMES",
+        default_value_t = 1,
+        value_parser = clap::value_parser!(u8).range(1..=4),
+        help = "Average the screen over this many frames like the original LCD's ghosting, for games that flicker sprites for transparency. 2 suits most of them."
+    )]
+    frame_blend: u8,
+    #[arg(
+        long,
+        value_name = "PATH",
+        help = "Log every executed instruction to this file, or to stdout for -."
+    )]
+    trace: Option<String>,
+    #[arg(
+        long,
+        value_name = "FORMAT",
+        default_value = "full",
+        help = "doctor for the bare register lines other emulators log, full to add cycles and disassembly."
+    )]
+    trace_format: TraceFormat,
+    #[arg(
+        long,
+        value_name = "PATH",
+        help = "Log which ROM bytes run as code, get read as data or get copied to tile data, and save it to this .cdl file when the emulator closes. An existing log for the ROM is added to."
+    )]
+    cdl: Option<PathBuf>,
+    #[arg(
+        long,
+        value_name = "PATH",
+        help = "Count the cycles spent in each routine and write the busiest first to this file, or to stdout for -, when the emulator closes."
+    )]
+    profile: Option<String>,
+    #[arg(
+        long,
+        value_name = "PATH",
+        help = "Show labels in the trace, profile and debugger from this RGBDS or wla-dx .sym file, which breakpoints can also use. Defaults to the .sym file next to the ROM."
+    )]
+    sym: Option<PathBuf>,
+    #[arg(
+        long,
+        value_name = "PATH",
+        help = "Write the bytes the game sends over the link port to this file, or to stdout for -."
+    )]
+    serial_out: Option<String>,
+    #[arg(
+        long,
+        value_name = "URL",
+        value_parser = parse_link,
+        conflicts_with = "serial_out",
+        help = "Link to another instance: tcp://:PORT waits for it to connect, tcp://HOST:PORT connects to one that's waiting."
+    )]
+    link: Option<LinkAddress>,
+    #[arg(
+        long,
+        value_name = "URL",
+        value_parser = parse_netplay,
+        conflicts_with_all = ["serial_out", "link", "printer", "ir", "camera", "boot_rom", "record", "play", "script", "frames", "cycles", "headless", "debug", "gdb"],
+        help = "Play over a link cable online, guessing the other player's buttons and correcting course when they arrive: udp://:PORT waits for the other player, udp://HOST:PORT joins one. Both need the same ROM."
+    )]
+    netplay: Option<LinkAddress>,
+    #[arg(
+        long,
+        value_name = "DIR",
+        conflicts_with_all = ["serial_out", "link"],
+        help = "Plug in a Game Boy Printer that saves what it prints to this directory as PNGs."
+    )]
+    printer: Option<String>,
+    #[arg(
+        long,
+        value_name = "DEVICE",
+        help = "What the CGB's infrared port sees: dark, loopback for its own LED, or a file of light and dark durations in CPU cycles to play back."
+    )]
+    ir: Option<String>,
+    #[arg(
+        long,
+        value_name = "SOURCE",
+        help = "What the Pocket Camera sees: pattern, webcam (or webcam:DEVICE) with the webcam feature, or a PNG image."
+    )]
+    camera: Option<String>,
+    #[arg(
+        long,
+        value_name = "N",
+        group = "limit",
+        conflicts_with_all = ["headless", "debug", "gdb"],
+        help = "Run this many frames without a window or audio, then exit."
+    )]
+    frames: Option<u32>,
+    #[arg(
+        long,
+        value_name = "N",
+        group = "limit",
+        conflicts_with_all = ["headless", "debug", "gdb"],
+        help = "Run this many T-cycles without a window or audio, then exit."
+    )]
+    cycles: Option<u64>,
+    #[arg(
+        long,
+        value_name = "PATH",
+        requires = "limit",
+        help = "Save the last frame of a --frames or --cycles run as a PNG."
+    )]
+    screenshot: Option<String>,
+    #[arg(
+        long,
+        value_name = "N",
+        default_value_t = 1,
+        value_parser = clap::value_parser!(u32).range(1..=8),
+        help = "Scale the screenshot up by this much."
+    )]
+    screenshot_scale: u32,
+    #[arg(
+        long,
+        value_name = "COLORS",
+        default_value = "screen",
+        help = "screen for the colors shown, shades for a DMG game's shade indices with the DMG colors as the palette."
+    )]
+    screenshot_colors: ScreenshotColors,
+    #[arg(
+        long,
+        requires = "limit",
+        conflicts_with_all = ["serial_out", "link", "printer"],
+        help = "Echo the link port to stdout, stop once the game sends Passed or Failed and exit with 0 or 1. Exits with 2 if it sent neither."
+    )]
+    exit_code_from_serial: bool,
+    #[arg(
+        long,
+        value_name = "N",
+        default_value_t = 3,
+        value_parser = clap::value_parser!(u32).range(2..=6),
+        help = "Open the window this many times the screen's size. F3 steps through 2 to 6 while playing."
+    )]
+    scale: u32,
+    #[arg(
+        long,
+        value_name = "MODE",
+        default_value = "integer",
+        help = "integer for whole multiples of the screen, fit for as large as fits with bars or stretch to fill the window. F2 switches while playing."
+    )]
+    scaling: Scaling,
+    #[arg(
+        long,
+        value_name = "FILTER",
+        default_value = "none",
+        help = "lcd for a color LCD's subpixels, dot-matrix for the DMG's grid and slow pixels or scanlines. F4 switches while playing."
+    )]
+    filter: Filter,
+    #[arg(
+        long,
+        help = "Show Super Game Boy games inside their border at 256x224, like on a TV."
+    )]
+    sgb_border: bool,
+    #[arg(
+        long,
+        value_name = "SECONDS",
+        default_value_t = 10,
+        help = "How far holding R can rewind. 0 turns rewinding off."
+    )]
+    rewind_seconds: u32,
+    #[arg(
+        long,
+        default_value_t = 1.0,
+        value_parser = parse_speed,
+        help = "Run this many times faster than the hardware, like 2 or 0.5. - and = change it while playing."
+    )]
+    speed: f64,
+    #[arg(
+        long,
+        value_name = "MODE",
+        default_value = "timer",
+        help = "What to pace frames by: timer sleeps between them, audio waits for the sound card so the sound never crackles, vsync waits for the display so it never tears and nudges the sound to match, and none runs as fast as possible."
+    )]
+    sync: SyncMode,
+    #[arg(
+        long,
+        help = "Keep the sound on while Tab fast-forwards, skipping what doesn't fit instead of muting."
+    )]
+    turbo_audio: bool,
+    #[arg(
+        long,
+        value_name = "HZ",
+        default_value_t = rustygameboy::apu::DEFAULT_SAMPLE_RATE,
+        value_parser = clap::value_parser!(u32).range(8_000..=192_000),
+        help = "Ask the sound card for this rate, like 44100 or 48000. The sound is resampled to whatever it gives."
+    )]
+    sample_rate: u32,
+    #[arg(
+        long,
+        value_name = "MS",
+        default_value_t = 100,
+        value_parser = clap::value_parser!(u64).range(20..=1000),
+        help = "How much sound can queue up before the oldest is dropped. Lower lags less, higher rides out stutters."
+    )]
+    audio_latency: u64,
+    #[arg(
+        long,
+        value_name = "CHANNELS",
+        value_delimiter = ',',
+        help = "Leave sound channels out of the mix, like 3,4 or wave,noise. 1 and 2 are the squares."
+    )]
+    mute_channels: Vec<Channel>,
+    #[arg(
+        long,
+        value_name = "LAYERS",
+        value_delimiter = ',',
+        help = "Don't draw these layers, like bg,window or sprites, to debug rendering or rip graphics."
+    )]
+    hide_layers: Vec<Layer>,
+    #[arg(
+        long,
+        value_name = "PATH",
+        help = "Record the sound to a WAV file until the emulator closes. F10 starts and stops a recording next to the ROM."
+    )]
+    record_audio: Option<String>,
+    #[arg(
+        long,
+        help = "Record each sound channel to its own WAV file too, named after the recording with -square1, -square2, -wave and -noise."
+    )]
+    audio_stems: bool,
+    #[arg(
+        long,
+        value_name = "PATH",
+        help = "Record the screen and sound losslessly to an .mkv or .mp4 file with ffmpeg until the emulator closes. F11 starts and stops a recording next to the ROM."
+    )]
+    record_video: Option<String>,
+    #[arg(
+        long,
+        value_name = "CODE",
+        value_parser = parse_cheat,
+        help = "Apply a Game Genie (ABC-DEF-GHI) or GameShark (01FF34C1) code. Can be given more than once."
+    )]
+    cheat: Vec<Cheat>,
+    #[arg(
+        long,
+        value_name = "PATH",
+        help = "Load codes from a file, one per line followed by an optional name. A line starting with - adds its code turned off."
+    )]
+    cheats: Option<String>,
+    #[arg(
+        long,
+        value_name = "PATH",
+        conflicts_with_all = ["debug", "gdb"],
+        help = "Run a Lua script with hooks into every frame and memory writes, see src/script.rs for its API."
+    )]
+    script: Option<String>,
+    #[arg(
+        long,
+        value_name = "PATH",
+        conflicts_with = "play",
+        help = "Record the buttons held every frame into a movie, starting from power-on."
+    )]
+    record: Option<String>,
+    #[arg(
+        long,
+        value_name = "PATH",
+        help = "Replay a movie from the state it was recorded from. F9 toggles read-only while playing."
+    )]
+    play: Option<String>,
+    #[arg(
+        long,
+        requires = "play",
+        help = "Let the movie change: it records on past its end and loading a state records over what came after it."
+    )]
+    read_write: bool,
+    #[arg(long, help = "Open the terminal debugger instead of a window.")]
+    debug: bool,
+    #[arg(
+        long,
+        value_name = "PORT",
+        help = "Wait for GDB to connect on this port and let it drive the emulator instead of a window."
+    )]
+    gdb: Option<u16>,
+    #[arg(
+        long,
+        value_name = "[REGION=]PATH",
+        value_parser = parse_dump,
+        help = "Save memory to a file when the emulator closes, like wram.bin or C000..C0FF=table.txt. The region is wram, hram, sram, vram, vram1, oam or an address range, named after the file when left out. A .txt file gets a hex dump. Can be given more than once."
+    )]
+    dump_on_exit: Vec<(Region, PathBuf)>,
+    // The keys from the config file, which has no command line option.
+    #[arg(skip)]
+    keys: Vec<(Target, Vec<String>)>,
+    #[arg(skip)]
+    turbo_periods: Vec<(Button, u32)>,
+    #[arg(skip)]
+    controllers: Controllers,
+}
+
+impl RunArgs {
+    fn rom_path(&self) -> &str {
+        self.rom.as_deref().expect("run_rom picks a ROM first")
+    }
+
+    fn limit(&self) -> Option<RunLimit> {
+        match (self.frames, self.cycles) {
+            (Some(frames), _) => Some(RunLimit::Frames(frames)),
+            (_, Some(cycles)) => Some(RunLimit::Cycles(cycles)),
+            _ => None,
+        }
+    }
+}
+
+fn main() -> io::Result<ExitCode> {
+    let matches = Cli::command().get_matches();
+    let cli = Cli::from_arg_matches(&matches).unwrap_or_else(|error| error.exit());
+    let run_matches = matches.subcommand_matches("run").unwrap_or(&matches);
+    let from_command_line =
+        |id: &str| run_matches.value_source(id) == Some(ValueSource::CommandLine);
+    match cli.command {
+        Some(Command::Run(args)) => {
+            let config = Config::load(args.config.as_deref().map(std::path::Path::new))?;
+            run_rom(*args, &config, from_command_line)
+        }
+        Some(Command::RomInfo { rom, format, dat }) => {
+            rom_info::print(&rom, format, dat.as_deref()).map(|()| ExitCode::SUCCESS)
+        }
+        Some(Command::Disasm {
+            rom,
+            range,
+            bank,
+            sym,
+        }) => {
+            let symbols = load_symbols(&rom, sym.as_deref())?;
+            print_disassembly(&rom, range, bank, symbols.as_ref()).map(|()| ExitCode::SUCCESS)
+        }
+        Some(Command::Scan { dir, format }) => Ok(if scan::print(&dir, format)? {
+            ExitCode::SUCCESS
+        } else {
+            ExitCode::FAILURE
+        }),
+        None => {
+            let config = Config::load(cli.run.config.as_deref().map(std::path::Path::new))?;
+            run_rom(cli.run, &config, from_command_line)
+        }
+    }
+}
+
+fn run_rom(
+    mut args: RunArgs,
+    config: &Config,
+    from_command_line: impl Fn(&str) -> bool,
+) -> io::Result<ExitCode> {
+    if args.rom.is_none() {
+        match pick_rom(&args)? {
+            Some(path) => args.rom = Some(path.display().to_string()),
+            None => return Ok(ExitCode::SUCCESS),
+        }
+    }
+    let rom = load_rom(&args)?;
+    apply_settings(&mut args, &config.settings_for(&rom), from_command_line);
+    args.keys = config.key_bindings()?;
+    args.turbo_periods = config.turbo_periods()?;
+    args.controllers = config.controllers().clone();
+    let boot_rom = args.boot_rom.as_ref().map(fs::read).transpose()?;
+    for warning in rom.warnings() {
+        eprintln!("Warning: {}", warning);
+    }
+    if args.verify_checksum {
+        rom.verify_global_checksum()?;
+    }
+    if args.headless {
+        return Ok(ExitCode::SUCCESS);
+    }
+    if let Some(limit) = args.limit() {
+        return run_scripted(rom, boot_rom, &args, limit);
+    }
+    let result = if args.debug {
+        debug(rom, boot_rom, &args)
+    } else if let Some(port) = args.gdb {
+        play(rom, boot_rom, &args, |emulator, _| {
+            serve_gdb(emulator, port)
+        })
+    } else {
+        run(rom, boot_rom, &args)
+    };
+    result.map(|()| ExitCode::SUCCESS)
+}
+
+// Waits for a ROM to be dropped on the window or picked from the recent ones, or None if the window
+// was closed instead. Only the window can ask for one.
+fn pick_rom(args: &RunArgs) -> io::Result<Option<PathBuf>> {
+    if args.headless || args.limit().is_some() || args.debug || args.gdb.is_some() {
+        return Err(io::Error::other("Give the path to a ROM to run."));
+    }
+    pick_in_window(args)
+}
+
+#[cfg(feature = "sdl")]
+fn pick_in_window(args: &RunArgs) -> io::Result<Option<PathBuf>> {
+    use rustygameboy::config::RecentRoms;
+
+    let recent = RecentRoms::load(args.config.as_deref().map(std::path::Path::new))?;
+    frontend::pick_rom(
+        recent.as_ref().map_or(&[][..], RecentRoms::roms),
+        args.scale,
+    )
+}
+
+#[cfg(not(feature = "sdl"))]
+fn pick_in_window(_args: &RunArgs) -> io::Result<Option<PathBuf>> {
+    Err(io::Error::other(
+        "Give the path to a ROM to run, built without the sdl feature there's no window to drop one on.",
+    ))
+}
+
+// Fills in what wasn't given on the command line from the config file.
+fn apply_settings(
+    args: &mut RunArgs,
+    settings: &Settings,
+    from_command_line: impl Fn(&str) -> bool,
+) {
+    let configured = |id: &str| !from_command_line(id);
+    let (video, audio, paths) = (&settings.video, &settings.audio, &settings.paths);
+    if let Some(scale) = video.scale.filter(|_| configured("scale")) {
+        args.scale = scale;
+    }
+    if let Some(scaling) = video.scaling.filter(|_| configured("scaling")) {
+        args.scaling = scaling;
+    }
+    if let Some(filter) = video.filter.filter(|_| configured("filter")) {
+        args.filter = filter;
+    }
+    if video.palette.is_some() && configured("palette") {
+        args.palette.clone_from(&video.palette);
+    }
+    if video.cgb_palette.is_some() && configured("cgb_palette") {
+        args.cgb_palette = video.cgb_palette;
+    }
+    if let Some(frames) = video.frame_blend.filter(|_| configured("frame_blend")) {
+        args.frame_blend = frames;
+    }
+    if let Some(sync) = video.sync.filter(|_| configured("sync")) {
+        args.sync = sync;
+    }
+    if let Some(rate) = audio.sample_rate.filter(|_| configured("sample_rate")) {
+        args.sample_rate = rate;
+    }
+    if let Some(latency) = audio.latency.filter(|_| configured("audio_latency")) {
+        args.audio_latency = latency;
+    }
+    if let Some(turbo_audio) = audio.turbo_audio.filter(|_| configured("turbo_audio")) {
+        args.turbo_audio = turbo_audio;
+    }
+    if paths.boot_rom.is_some() && configured("boot_rom") {
+        args.boot_rom = paths
+            .boot_rom
+            .as_ref()
+            .map(|path| path.display().to_string());
+    }
+    if paths.save_dir.is_some() && configured("save_dir") {
+        args.save_dir = paths
+            .save_dir
+            .as_ref()
+            .map(|path| path.display().to_string());
+    }
+}
+
+// Patches apply before validation, since fixing the header is sometimes what they're for. Real
+// hardware only checks the logo, so the rest of the header only ever gives warnings.
+fn load_rom(args: &RunArgs) -> io::Result<rom::Rom> {
+    let mut content = rustygameboy::archive::read(args.rom_path())?;
+    if let Some(patch) = &args.patch {
+        content = rustygameboy::patch::apply(&content, &fs::read(patch)?)
+            .map_err(|error| io::Error::other(format!("Could not apply {}: {}", patch, error)))?;
+    }
+    let policy = if args.lenient {
+        rom::ValidationPolicy::Lenient
+    } else {
+        rom::ValidationPolicy::Strict
+    };
+    Ok(rom::Rom::from_bytes_with_policy(content, policy)?)
+}
+
+// Runs without a window or audio for CI. The run ends early once the game reports a result over
+// the link port if the exit code comes from it.
+fn run_scripted(
+    rom: rom::Rom,
+    boot_rom: Option<Vec<u8>>,
+    args: &RunArgs,
+    limit: RunLimit,
+) -> io::Result<ExitCode> {
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    let output = Rc::new(RefCell::new(Vec::new()));
+    let mut code = ExitCode::SUCCESS;
+    play(rom, boot_rom, args, |emulator, _| {
+        if args.exit_code_from_serial {
+            emulator.set_serial_device(Some(Box::new(SerialEcho(output.clone()))));
+        }
+
+        let (mut frames, mut cycles) = (0, 0);
+        loop {
+            let step = match limit {
+                RunLimit::Frames(limit) if frames < limit => RunLimit::Frames(1),
+                RunLimit::Cycles(limit) if cycles < limit => {
+                    RunLimit::Cycles((limit - cycles).min(CYCLES_PER_FRAME as u64))
+                }
+                _ => break,
+            };
+            cycles += emulator.run_for(step);
+            frames += 1;
+            if args.exit_code_from_serial && serial_result(&output.borrow()).is_some() {
+                break;
+            }
+        }
+
+        if let Some(path) = &args.screenshot {
+            save_screenshot(emulator, path, args)?;
+        }
+        // Runs of the same movie end with the same hash, which makes for an easy regression check.
+        if emulator.movie().is_some() {
+            eprintln!("State hash: {:016x}", emulator.state_hash());
+        }
+        if args.exit_code_from_serial {
+            code = serial_result(&output.borrow()).unwrap_or(ExitCode::from(2));
+        }
+        Ok(())
+    })?;
+    Ok(code)
+}
+
+// Blargg's test ROMs and many homebrew tests end their output with one of these.
+fn serial_result(output: &[u8]) -> Option<ExitCode> {
+    let contains = |word: &[u8]| output.windows(word.len()).any(|window| window == word);
+    if contains(b"Passed") {
+        Some(ExitCode::SUCCESS)
+    } else if contains(b"Failed") {
+        Some(ExitCode::FAILURE)
+    } else {
+        None
+    }
+}
+
+// Collects what the game sends over the link port and echoes it to stdout.
+struct SerialEcho(std::rc::Rc<std::cell::RefCell<Vec<u8>>>);
+
+impl rustygameboy::serial::SerialDevice for SerialEcho {
+    fn transfer(&mut self, byte: u8) -> u8 {
+        use std::io::Write;
+
+        let mut stdout = io::stdout();
+        let _ = stdout.write_all(&[byte]).and_then(|()| stdout.flush());
+        self.0.borrow_mut().push(byte);
+        0xFF
+    }
+}
+
+#[cfg(feature = "png")]
+fn save_screenshot(
+    emulator: &rustygameboy::emulator::Emulator,
+    path: &str,
+    args: &RunArgs,
+) -> io::Result<()> {
+    use rustygameboy::screenshot::ScreenshotOptions;
+
+    let png = emulator.screenshot(ScreenshotOptions {
+        scale: args.screenshot_scale,
+        colors: args.screenshot_colors,
+    })?;
+    fs::write(path, png)
+}
+
+#[cfg(not(feature = "png"))]
+fn save_screenshot(
+    _emulator: &rustygameboy::emulator::Emulator,
+    _path: &str,
+    _args: &RunArgs,
+) -> io::Result<()> {
+    Err(io::Error::other(
+        "Built without the png feature, rebuild with --features png.",
+    ))
+}
+
+fn print_disassembly(
+    path: &str,
+    range: Range<u16>,
+    bank: usize,
+    symbols: Option<&Symbols>,
+) -> io::Result<()> {
+    let rom = rom::Rom::new_lenient(path)?;
+    let content = rom.content();
+    let read = |address: u16| {
+        let offset = match address {
+            0x0000..=0x3FFF => address as usize,
+            _ => bank * 0x4000 + (address as usize & 0x3FFF),
+        };
+        content.get(offset).copied().unwrap_or(0xFF)
+    };
+    let label = |address| symbols.and_then(|symbols| symbols.label(Some(bank), address));
+    for line in disasm::disassemble(read, range) {
+        if let Some(name) = label(line.address) {
+            println!("{}:", name);
+        }
+        println!("{}", line.format_with(label));
+    }
+    Ok(())
+}
+
+// The .sym file given, or the one next to the ROM if there is one.
+fn load_symbols(rom_path: &str, sym: Option<&std::path::Path>) -> io::Result<Option<Symbols>> {
+    match sym {
+        Some(sym) => Symbols::load(sym).map(Some),
+        None => Symbols::for_rom(std::path::Path::new(rom_path)),
+    }
+}
+
+// Parses `start..end` with hexadecimal (0x) or decimal addresses inside the ROM area.
+fn parse_range(value: &str) -> Result<Range<u16>, String> {
+    let (start, end) = value
+        .split_once("..")
+        .ok_or_else(|| format!("{} is not a range like 0x150..0x200", value))?;
+    let parse = |number: &str| -> Result<u32, String> {
+        let number = number.trim();
+        match number.strip_prefix("0x") {
+            Some(hex) => u32::from_str_radix(hex, 16),
+            None => number.parse(),
+        }
+        .map_err(|_| format!("{} is not an address", number))
+    };
+    let (start, end) = (parse(start)?, parse(end)?);
+    if start > end || end > 0x8000 {
+        return Err(format!(
+            "{} must be an increasing range within 0x0000..0x8000",
+            value
         ));
     }
+    Ok(start as u16..end as u16)
+}
+
+fn play(
+    rom: rom::Rom,
+    boot_rom: Option<Vec<u8>>,
+    args: &RunArgs,
+    frontend: impl FnOnce(&mut rustygameboy::emulator::Emulator, &mut PathBuf) -> io::Result<()>,
+) -> io::Result<()> {
+    use std::path::Path;
+    use std::rc::Rc;
+
+    use rustygameboy::bus::MemoryStrictness;
+    use rustygameboy::cdl::CodeDataLog;
+    use rustygameboy::profiler::{Profiler, Routine};
+    use rustygameboy::{battery, emulator::Emulator};
+
+    let mut path = PathBuf::from(args.rom_path());
+    let symbols = load_symbols(args.rom_path(), args.sym.as_deref())?.map(Rc::new);
+    let code_data_log = args
+        .cdl
+        .as_ref()
+        .map(|cdl| CodeDataLog::load(cdl, rom.content().len()))
+        .transpose()?;
+    let mut emulator = match args.model {
+        Some(model) => Emulator::with_model(rom, model)?,
+        None => Emulator::new(rom)?,
+    };
+    if let Some(boot_rom) = boot_rom {
+        emulator.map_boot_rom(boot_rom)?;
+    }
+    if args.strict_memory {
+        emulator.set_memory_strictness(MemoryStrictness::Report);
+    }
+    emulator.set_accuracy(args.accuracy);
+    if let Some(combo) = args.cgb_palette {
+        emulator.set_compatibility_palettes(combo.palettes());
+    }
+    if let Some(palette) = &args.palette {
+        emulator.set_dmg_palette(load_palette(palette)?);
+    }
+    emulator.set_frame_blend(args.frame_blend as usize);
+    for &layer in &args.hide_layers {
+        emulator.set_layer_enabled(layer, false);
+    }
+    for &channel in &args.mute_channels {
+        emulator
+            .bus_mut()
+            .apu_mut()
+            .set_channel_enabled(channel, false);
+    }
+    if let Some(trace) = &args.trace {
+        let mut tracer = tracer(trace, args.trace_format)?;
+        tracer.set_symbols(symbols.clone());
+        emulator.set_tracer(Some(tracer));
+    }
+    emulator.set_code_data_log(code_data_log);
+    if args.profile.is_some() {
+        emulator.set_profiler(Some(Profiler::new()));
+    }
+    if let Some(serial_out) = &args.serial_out {
+        emulator.set_serial_device(Some(serial_output(serial_out)?));
+    }
+    if let Some(link) = &args.link {
+        emulator.set_serial_device(Some(Box::new(connect_link(link)?)));
+    }
+    if let Some(directory) = &args.printer {
+        emulator.set_serial_device(Some(printer(directory)?));
+    }
+    if let Some(device) = &args.ir {
+        emulator.set_ir_device(Some(ir_device(device)?));
+    }
+    if let Some(source) = &args.camera {
+        emulator.set_camera_source(Some(camera_source(source)?));
+    }
+    *emulator.cheats_mut() = load_cheats(args.cheats.as_deref().map(Path::new), &args.cheat)?;
+    let save_dir = args.save_dir.as_deref().map(Path::new);
+    battery::load(&mut emulator, &battery::rom_path_in(&path, save_dir))?;
+    if let Some(script) = &args.script {
+        load_script(&mut emulator, script)?;
+    }
+    let movie = start_movie(&mut emulator, args)?;
+    emulator.set_movie(movie);
+    if let Some(record_audio) = &args.record_audio {
+        emulator.set_sample_rate(args.sample_rate);
+        emulator.start_audio_recording(Path::new(record_audio), args.audio_stems)?;
+    }
+    if let Some(record_video) = &args.record_video {
+        emulator.set_sample_rate(args.sample_rate);
+        emulator.start_video_recording(Path::new(record_video))?;
+    }
+    // The frontend may swap in another ROM, whose battery is saved at the end instead.
+    let result = frontend(&mut emulator, &mut path);
+    for (region, dump_path) in &args.dump_on_exit {
+        rustygameboy::debugger::dump_to_file(&mut emulator, *region, dump_path)?;
+    }
+    emulator.stop_audio_recording()?;
+    emulator.stop_video_recording()?;
+    for error in emulator.take_recording_errors() {
+        eprintln!("{}", error);
+    }
+    #[cfg(feature = "lua")]
+    if let Some(error) = emulator.take_script_error() {
+        eprintln!("{}", error);
+    }
+    // A movie plays from its own cartridge RAM, which shouldn't replace the player's save.
+    if args.play.is_none() {
+        battery::save(&emulator, &battery::rom_path_in(&path, save_dir))?;
+    }
+    if let Some(movie) = emulator.take_movie() {
+        save_movie(movie, args)?;
+    }
+    if let Some(tracer) = emulator.take_tracer() {
+        tracer.finish()?;
+    }
+    if let (Some(cdl), Some(log)) = (&args.cdl, emulator.take_code_data_log()) {
+        log.save(cdl)?;
+    }
+    if let (Some(profile), Some(profiler)) = (&args.profile, emulator.take_profiler()) {
+        let report = profiler.report(|routine| match (routine, &symbols) {
+            (Routine::At { bank, address }, Some(symbols)) => {
+                symbols.label(Some(bank), address).map(str::to_string)
+            }
+            _ => None,
+        });
+        if profile == "-" {
+            print!("{}", report);
+        } else {
+            fs::write(profile, report)?;
+        }
+    }
+    if let Some(device) = emulator.take_serial_device() {
+        device.finish()?;
+    }
+    result
+}
+
+fn tracer(path: &str, format: TraceFormat) -> io::Result<rustygameboy::trace::Tracer> {
+    use std::io::BufWriter;
+
+    let out: Box<dyn io::Write> = if path == "-" {
+        Box::new(BufWriter::new(io::stdout()))
+    } else {
+        Box::new(BufWriter::new(fs::File::create(path)?))
+    };
+    Ok(rustygameboy::trace::Tracer::new(out, format))
+}
+
+fn serial_output(path: &str) -> io::Result<Box<dyn rustygameboy::serial::SerialDevice>> {
+    use rustygameboy::serial::SerialOutput;
+
+    if path == "-" {
+        Ok(Box::new(SerialOutput::new(io::stdout())))
+    } else {
+        Ok(Box::new(SerialOutput::new(fs::File::create(path)?)))
+    }
+}
+
+// `region=path`, or just a path named after the region like wram.bin.
+fn parse_dump(value: &str) -> Result<(Region, PathBuf), String> {
+    let (region, path) = match value.split_once('=') {
+        Some((region, path)) => (region, path),
+        None => {
+            let name = std::path::Path::new(value)
+                .file_name()
+                .and_then(|name| name.to_str())
+                .unwrap_or(value);
+            (name.split('.').next().unwrap_or(name), value)
+        }
+    };
+    let region = region
+        .parse()
+        .map_err(|error: io::Error| error.to_string())?;
+    Ok((region, PathBuf::from(path)))
+}
+
+fn parse_cheat(value: &str) -> Result<Cheat, String> {
+    Cheat::new(value).map_err(|error| error.to_string())
+}
+
+// The codes in the cheat file followed by the ones from the command line.
+fn load_cheats(file: Option<&std::path::Path>, codes: &[Cheat]) -> io::Result<Cheats> {
+    let mut cheats = match file {
+        Some(file) => Cheats::load(file)?,
+        None => Cheats::new(),
+    };
+    cheats.extend(codes.iter().cloned());
+    Ok(cheats)
+}
+
+fn start_movie(
+    emulator: &mut rustygameboy::emulator::Emulator,
+    args: &RunArgs,
+) -> io::Result<Option<MovieSession>> {
+    if args.record.is_some() {
+        return Ok(Some(MovieSession::record(emulator)));
+    }
+    match &args.play {
+        Some(path) => {
+            let movie = Movie::load(std::path::Path::new(path))?;
+            MovieSession::play(movie, emulator, !args.read_write).map(Some)
+        }
+        None => Ok(None),
+    }
+}
+
+// Read-write movies are saved back over the file they were played from.
+fn save_movie(session: MovieSession, args: &RunArgs) -> io::Result<()> {
+    let path = match (&args.record, &args.play) {
+        (Some(path), _) => path,
+        (_, Some(path)) if session.modified() => path,
+        _ => return Ok(()),
+    };
+    let movie = session.into_movie();
+    movie.save(std::path::Path::new(path))?;
+    eprintln!(
+        "Saved {} frames and {} rerecords to {}",
+        movie.len(),
+        movie.rerecords(),
+        path
+    );
+    Ok(())
+}
 
-    let r = rom::Rom::new(&args[1])?;
+#[cfg(feature = "lua")]
+fn load_script(emulator: &mut rustygameboy::emulator::Emulator, path: &str) -> io::Result<()> {
+    use rustygameboy::script::Script;
 
+    let script = Script::load(emulator, std::path::Path::new(path))?;
+    emulator.set_script(Some(script));
     Ok(())
 }
+
+#[cfg(not(feature = "lua"))]
+fn load_script(_emulator: &mut rustygameboy::emulator::Emulator, _path: &str) -> io::Result<()> {
+    Err(io::Error::other(
+        "Built without the lua feature, rebuild with --features lua.",
+    ))
+}
+
+// A preset's name, or else a palette file.
+fn load_palette(value: &str) -> io::Result<rustygameboy::palette::Palette> {
+    use rustygameboy::palette::{Palette, PalettePreset};
+
+    match value.parse::<PalettePreset>() {
+        Ok(preset) => Ok(preset.palette()),
+        Err(_) => Palette::load(std::path::Path::new(value)),
+    }
+}
+
+fn parse_speed(value: &str) -> Result<f64, String> {
+    let speed = value
+        .parse()
+        .map_err(|_| format!("{} is not a number", value))?;
+    rustygameboy::pacing::check_speed(speed).map_err(|error| error.to_string())
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum LinkAddress {
+    Listen(u16),
+    Connect(String),
+}
+
+fn parse_link(value: &str) -> Result<LinkAddress, String> {
+    parse_address(value, "tcp")
+}
+
+fn parse_netplay(value: &str) -> Result<LinkAddress, String> {
+    parse_address(value, "udp")
+}
+
+fn parse_address(value: &str, scheme: &str) -> Result<LinkAddress, String> {
+    let address = value
+        .strip_prefix(scheme)
+        .and_then(|address| address.strip_prefix("://"))
+        .ok_or_else(|| format!("{} is not a {}:// URL", value, scheme))?;
+    let (host, port) = address
+        .rsplit_once(':')
+        .ok_or_else(|| format!("{} has no port", value))?;
+    let port: u16 = port
+        .parse()
+        .map_err(|_| format!("{} is not a port", port))?;
+    if host.is_empty() {
+        Ok(LinkAddress::Listen(port))
+    } else {
+        Ok(LinkAddress::Connect(address.to_string()))
+    }
+}
+
+fn connect_link(address: &LinkAddress) -> io::Result<rustygameboy::peripherals::link::TcpLink> {
+    use rustygameboy::peripherals::link::TcpLink;
+
+    match address {
+        LinkAddress::Listen(port) => {
+            eprintln!("Waiting for the other player on port {}", port);
+            TcpLink::listen(("0.0.0.0", *port))
+        }
+        LinkAddress::Connect(address) => TcpLink::connect(address.as_str()),
+    }
+}
+
+#[cfg(feature = "sdl")]
+fn start_netplay(
+    address: &LinkAddress,
+    emulator: &mut rustygameboy::emulator::Emulator,
+    content: Vec<u8>,
+) -> io::Result<rustygameboy::netplay::Netplay> {
+    use rustygameboy::netplay::Netplay;
+
+    // The other player's Game Boy runs the same ROM.
+    let rom = rom::Rom::from_bytes_with_policy(content, rom::ValidationPolicy::Lenient)?;
+    match address {
+        LinkAddress::Listen(port) => {
+            eprintln!("Waiting for the other player on port {}", port);
+            Netplay::host(*port, emulator, rom)
+        }
+        LinkAddress::Connect(address) => Netplay::join(address.as_str(), emulator, rom),
+    }
+}
+
+#[cfg(feature = "png")]
+fn printer(directory: &str) -> io::Result<Box<dyn rustygameboy::serial::SerialDevice>> {
+    Ok(Box::new(rustygameboy::peripherals::printer::Printer::new(
+        directory,
+    )))
+}
+
+#[cfg(not(feature = "png"))]
+fn printer(_directory: &str) -> io::Result<Box<dyn rustygameboy::serial::SerialDevice>> {
+    Err(io::Error::other(
+        "Built without the png feature, rebuild with --features png.",
+    ))
+}
+
+fn ir_device(name: &str) -> io::Result<Box<dyn rustygameboy::infrared::IrDevice>> {
+    use std::path::Path;
+
+    use rustygameboy::infrared::{IrDark, IrLoopback, IrScript};
+
+    Ok(match name {
+        "dark" => Box::new(IrDark),
+        "loopback" => Box::new(IrLoopback::default()),
+        path => Box::new(IrScript::load(Path::new(path))?),
+    })
+}
+
+fn camera_source(name: &str) -> io::Result<Box<dyn rustygameboy::camera::CameraSource>> {
+    use rustygameboy::camera::TestPattern;
+
+    match name {
+        "pattern" => Ok(Box::new(TestPattern)),
+        #[cfg(feature = "webcam")]
+        webcam if webcam == "webcam" || webcam.starts_with("webcam:") => Ok(Box::new(
+            rustygameboy::camera::Webcam::open(webcam.strip_prefix("webcam:"))?,
+        )),
+        #[cfg(not(feature = "webcam"))]
+        webcam if webcam == "webcam" || webcam.starts_with("webcam:") => Err(io::Error::other(
+            "Built without the webcam feature, rebuild with --features webcam.",
+        )),
+        #[cfg(feature = "png")]
+        path => Ok(Box::new(rustygameboy::camera::CameraImage::load(
+            std::path::Path::new(path),
+        )?)),
+        #[cfg(not(feature = "png"))]
+        _ => Err(io::Error::other(
+            "Built without the png feature, rebuild with --features png.",
+        )),
+    }
+}
+
+fn serve_gdb(emulator: &mut rustygameboy::emulator::Emulator, port: u16) -> io::Result<()> {
+    use std::net::TcpListener;
+
+    use rustygameboy::gdb::GdbStub;
+
+    let listener = TcpListener::bind(("127.0.0.1", port))?;
+    eprintln!("Waiting for GDB on port {}", port);
+    let (stream, address) = listener.accept()?;
+    eprintln!("GDB connected from {}", address);
+    GdbStub::new().serve(emulator, stream)
+}
+
+#[cfg(feature = "sdl")]
+fn run(rom: rom::Rom, boot_rom: Option<Vec<u8>>, args: &RunArgs) -> io::Result<()> {
+    use rustygameboy::config::RecentRoms;
+
+    let options = frontend::Options {
+        scale: args.scale,
+        scaling: args.scaling,
+        filter: args.filter,
+        sgb_border: args.sgb_border,
+        rewind_seconds: args.rewind_seconds,
+        speed: args.speed,
+        sync: args.sync,
+        turbo_audio: args.turbo_audio,
+        sample_rate: args.sample_rate,
+        audio_latency: std::time::Duration::from_millis(args.audio_latency),
+        cheat_file: args.cheats.as_ref().map(PathBuf::from),
+        cheats: args.cheat.clone(),
+        audio_stems: args.audio_stems,
+        keys: args.keys.clone(),
+        turbo_periods: args.turbo_periods.clone(),
+        controllers: args.controllers.clone(),
+        save_dir: args.save_dir.as_ref().map(PathBuf::from),
+        lenient: args.lenient,
+        config: args.config.as_ref().map(PathBuf::from),
+    };
+    // Only ROMs played in the window count as recent, not scripted runs.
+    if let Some(mut recent) = RecentRoms::load(options.config.as_deref())? {
+        if let Err(error) = recent.add(std::path::Path::new(args.rom_path())) {
+            eprintln!("Could not remember the ROM: {}", error);
+        }
+    }
+    let netplay_rom = args.netplay.as_ref().map(|_| rom.content().to_vec());
+    play(rom, boot_rom, args, |emulator, path| {
+        let mut netplay = match (&args.netplay, netplay_rom) {
+            (Some(address), Some(content)) => Some(start_netplay(address, emulator, content)?),
+            _ => None,
+        };
+        frontend::run(emulator, path, &options, netplay.as_mut())
+    })
+}
+
+#[cfg(not(feature = "sdl"))]
+fn run(_rom: rom::Rom, _boot_rom: Option<Vec<u8>>, _args: &RunArgs) -> io::Result<()> {
+    Err(io::Error::other(
+        "Built without the sdl feature, rebuild with --features sdl or pass --headless.",
+    ))
+}
+
+#[cfg(feature = "debugger")]
+fn debug(rom: rom::Rom, boot_rom: Option<Vec<u8>>, args: &RunArgs) -> io::Result<()> {
+    let symbols = load_symbols(args.rom_path(), args.sym.as_deref())?;
+    play(rom, boot_rom, args, |emulator, _| {
+        tui::run(emulator, symbols.map(std::rc::Rc::new))
+    })
+}
+
+#[cfg(not(feature = "debugger"))]
+fn debug(_rom: rom::Rom, _boot_rom: Option<Vec<u8>>, _args: &RunArgs) -> io::Result<()> {
+    Err(io::Error::other(
+        "Built without the debugger feature, rebuild with --features debugger.",
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use clap::CommandFactory;
+    use rstest::rstest;
+    use tempfile::tempdir;
+
+    use super::*;
+
+    #[test]
+    fn test_cli() {
+        Cli::command().debug_assert();
+    }
+
+    #[test]
+    fn test_rom_path_without_command_runs_it() {
+        let cli = Cli::try_parse_from(["rusty_gameboy", "game.gb", "--headless"]).unwrap();
+
+        assert!(cli.command.is_none());
+        assert_eq!(cli.run.rom.as_deref(), Some("game.gb"));
+        assert!(cli.run.headless);
+    }
+
+    #[test]
+    fn test_rom_info_command() {
+        let cli = Cli::try_parse_from(["rusty_gameboy", "rom-info", "game.gb", "--format", "json"])
+            .unwrap();
+
+        assert!(matches!(
+            cli.command,
+            Some(Command::RomInfo { rom, format: rom_info::Format::Json, dat: None }) if rom == "game.gb"
+        ));
+    }
+
+    #[test]
+    fn test_sync() {
+        let cli = Cli::try_parse_from(["rusty_gameboy", "game.gb", "--sync", "audio"]).unwrap();
+
+        assert_eq!(cli.run.sync, SyncMode::Audio);
+        assert_eq!(
+            Cli::try_parse_from(["rusty_gameboy", "game.gb"])
+                .unwrap()
+                .run
+                .sync,
+            SyncMode::Timer
+        );
+        assert!(Cli::try_parse_from(["rusty_gameboy", "game.gb", "--sync", "gsync"]).is_err());
+    }
+
+    #[test]
+    fn test_model() {
+        let cli = Cli::try_parse_from(["rusty_gameboy", "game.gb", "--model", "sgb"]).unwrap();
+
+        assert_eq!(cli.run.model, Some(EmulatorModel::Sgb));
+        assert!(Cli::try_parse_from(["rusty_gameboy", "game.gb", "--model", "gba"]).is_err());
+    }
+
+    #[test]
+    fn test_trace() {
+        let cli = Cli::try_parse_from([
+            "rusty_gameboy",
+            "game.gb",
+            "--trace",
+            "-",
+            "--trace-format",
+            "doctor",
+        ])
+        .unwrap();
+
+        assert_eq!(cli.run.trace.as_deref(), Some("-"));
+        assert_eq!(cli.run.trace_format, TraceFormat::Doctor);
+    }
+
+    #[test]
+    fn test_disasm_command() {
+        let cli = Cli::try_parse_from([
+            "rusty_gameboy",
+            "disasm",
+            "game.gb",
+            "--range",
+            "0x150..0x200",
+        ])
+        .unwrap();
+
+        assert!(matches!(
+            cli.command,
+            Some(Command::Disasm { rom, range, bank: 1, sym: None }) if rom == "game.gb" && range == (0x150..0x200)
+        ));
+    }
+
+    #[test]
+    fn test_scan_command() {
+        let cli =
+            Cli::try_parse_from(["rusty_gameboy", "scan", "roms", "--format", "csv"]).unwrap();
+
+        assert!(matches!(
+            cli.command,
+            Some(Command::Scan { dir, format: scan::Format::Csv }) if dir == std::path::Path::new("roms")
+        ));
+    }
+
+    #[rstest]
+    #[case("0x150..0x200", Some(0x150..0x200))]
+    #[case("256..0x8000", Some(0x100..0x8000))]
+    #[case("0x200..0x150", None)]
+    #[case("0x0..0x8001", None)]
+    #[case("0x150", None)]
+    #[case("zz..0x200", None)]
+    fn test_parse_range(#[case] value: &str, #[case] expected: Option<Range<u16>>) {
+        assert_eq!(parse_range(value).ok(), expected);
+    }
+
+    #[test]
+    fn test_gdb_port() {
+        let cli = Cli::try_parse_from(["rusty_gameboy", "game.gb", "--gdb", "2345"]).unwrap();
+
+        assert_eq!(cli.run.gdb, Some(2345));
+    }
+
+    #[rstest]
+    #[case("tcp://:5000", Some(LinkAddress::Listen(5000)))]
+    #[case("tcp://192.168.1.2:5000", Some(LinkAddress::Connect("192.168.1.2:5000".to_string())))]
+    #[case("tcp://localhost", None)]
+    #[case("tcp://:port", None)]
+    #[case("localhost:5000", None)]
+    fn test_parse_link(#[case] value: &str, #[case] expected: Option<LinkAddress>) {
+        assert_eq!(parse_link(value).ok(), expected);
+    }
+
+    #[rstest]
+    #[case("udp://:5000", Some(LinkAddress::Listen(5000)))]
+    #[case("udp://example.com:5000", Some(LinkAddress::Connect("example.com:5000".to_string())))]
+    #[case("tcp://:5000", None)]
+    #[case("udp:5000", None)]
+    fn test_parse_netplay(#[case] value: &str, #[case] expected: Option<LinkAddress>) {
+        assert_eq!(parse_netplay(value).ok(), expected);
+    }
+
+    #[test]
+    fn test_netplay_conflicts_with_movies() {
+        assert!(Cli::try_parse_from([
+            "rusty_gameboy",
+            "game.gb",
+            "--netplay",
+            "udp://:5000",
+            "--record",
+            "game.movie"
+        ])
+        .is_err());
+    }
+
+    #[test]
+    fn test_link_conflicts_with_serial_out() {
+        assert!(Cli::try_parse_from([
+            "rusty_gameboy",
+            "game.gb",
+            "--link",
+            "tcp://:5000",
+            "--serial-out",
+            "-"
+        ])
+        .is_err());
+    }
+
+    #[test]
+    fn test_scripted_run() {
+        let cli = Cli::try_parse_from([
+            "rusty_gameboy",
+            "run",
+            "game.gb",
+            "--frames",
+            "600",
+            "--screenshot",
+            "out.png",
+            "--exit-code-from-serial",
+        ])
+        .unwrap();
+
+        let Some(Command::Run(args)) = cli.command else {
+            panic!("expected the run command");
+        };
+        assert_eq!(args.limit(), Some(RunLimit::Frames(600)));
+        assert_eq!(args.screenshot.as_deref(), Some("out.png"));
+        assert!(args.exit_code_from_serial);
+    }
+
+    #[rstest]
+    #[case(&["--screenshot", "out.png"])]
+    #[case(&["--exit-code-from-serial"])]
+    #[case(&["--frames", "1", "--cycles", "1"])]
+    #[case(&["--frames", "1", "--headless"])]
+    fn test_invalid_scripted_run(#[case] options: &[&str]) {
+        let arguments = ["rusty_gameboy", "game.gb"].iter().chain(options);
+
+        assert!(Cli::try_parse_from(arguments).is_err());
+    }
+
+    #[rstest]
+    #[case(b"cpu_instrs\n\nPassed all tests\n", Some(ExitCode::SUCCESS))]
+    #[case(b"01:ok 02:01\nFailed 1 tests\n", Some(ExitCode::FAILURE))]
+    #[case(b"01:ok 02:", None)]
+    fn test_serial_result(#[case] output: &[u8], #[case] expected: Option<ExitCode>) {
+        assert_eq!(serial_result(output), expected);
+    }
+
+    #[rstest]
+    #[case("2", Some(2.0))]
+    #[case("0.5", Some(0.5))]
+    #[case("0", None)]
+    #[case("fast", None)]
+    fn test_parse_speed(#[case] value: &str, #[case] expected: Option<f64>) {
+        assert_eq!(parse_speed(value).ok(), expected);
+    }
+
+    #[test]
+    fn test_cheats() {
+        let cli = Cli::try_parse_from([
+            "rusty_gameboy",
+            "game.gb",
+            "--cheat",
+            "3EA-F2F",
+            "--cheat",
+            "01FF34C1",
+        ])
+        .unwrap();
+
+        let cheats = load_cheats(None, &cli.run.cheat).unwrap();
+
+        assert_eq!(cheats.cheats().len(), 2);
+        assert!(Cli::try_parse_from(["rusty_gameboy", "game.gb", "--cheat", "3EA"]).is_err());
+    }
+
+    #[test]
+    fn test_load_patched_rom() {
+        // Arrange
+        let directory = tempdir().unwrap();
+        let rom_path = directory.path().join("game.gb");
+        let patch_path = directory.path().join("hack.ips");
+        let mut content = vec![0; 0x8000];
+        content[0x134] = b'A';
+        fs::write(&rom_path, content).unwrap();
+        // Changes the title to B.
+        fs::write(&patch_path, b"PATCH\x00\x01\x34\x00\x01BEOF").unwrap();
+        let cli = Cli::try_parse_from([
+            "rusty_gameboy".as_ref(),
+            rom_path.as_os_str(),
+            "--patch".as_ref(),
+            patch_path.as_os_str(),
+            "--lenient".as_ref(),
+        ])
+        .unwrap();
+
+        // Act
+        let rom = load_rom(&cli.run);
+
+        // Assert
+        assert_eq!(rom.unwrap().title().unwrap(), "B");
+    }
+
+    #[test]
+    fn test_lenient() {
+        // Arrange
+        let directory = tempdir().unwrap();
+        let rom_path = directory.path().join("game.gb");
+        // No logo.
+        fs::write(&rom_path, vec![0; 0x8000]).unwrap();
+        let load = |options: &[&str]| {
+            let mut arguments = vec!["rusty_gameboy", rom_path.to_str().unwrap(), "--headless"];
+            arguments.extend(options);
+            load_rom(&Cli::try_parse_from(arguments).unwrap().run)
+        };
+
+        // Act
+        let strict = load(&[]);
+        let lenient = load(&["--lenient"]);
+
+        // Assert
+        assert!(strict.is_err());
+        assert!(lenient
+            .unwrap()
+            .warnings()
+            .contains(&rom::RomWarning::LogoMismatch));
+    }
+
+    #[test]
+    fn test_record_and_play_movie() {
+        // Arrange
+        let directory = tempdir().unwrap();
+        let rom_path = directory.path().join("game.gb");
+        let movie_path = directory.path().join("game.movie");
+        fs::write(&rom_path, vec![0; 0x8000]).unwrap();
+        let run = |options: [&str; 2]| {
+            let cli = Cli::try_parse_from([
+                "rusty_gameboy".as_ref(),
+                rom_path.as_os_str(),
+                "--frames".as_ref(),
+                "30".as_ref(),
+                "--lenient".as_ref(),
+                options[0].as_ref(),
+                options[1].as_ref(),
+            ])
+            .unwrap();
+            run_rom(cli.run, &Config::default(), |_| true).unwrap();
+        };
+        let movie_path_str = movie_path.to_str().unwrap();
+
+        // Act
+        run(["--record", movie_path_str]);
+        let recorded = fs::read(&movie_path).unwrap();
+        run(["--play", movie_path_str]);
+
+        // Assert
+        let movie = Movie::load(&movie_path);
+        let replayed = fs::read(&movie_path).unwrap();
+        assert_eq!(movie.unwrap().len(), 30);
+        assert_eq!(replayed, recorded);
+        assert!(Cli::try_parse_from(["rusty_gameboy", "game.gb", "--read-write"]).is_err());
+    }
+
+    #[test]
+    fn test_record_audio() {
+        // Arrange
+        let directory = tempdir().unwrap();
+        let rom_path = directory.path().join("game.gb");
+        let wav_path = directory.path().join("game.wav");
+        fs::write(&rom_path, vec![0; 0x8000]).unwrap();
+        let cli = Cli::try_parse_from([
+            "rusty_gameboy".as_ref(),
+            rom_path.as_os_str(),
+            "--frames".as_ref(),
+            "10".as_ref(),
+            "--record-audio".as_ref(),
+            wav_path.as_os_str(),
+            "--audio-stems".as_ref(),
+            "--lenient".as_ref(),
+        ])
+        .unwrap();
+
+        // Act
+        run_rom(cli.run, &Config::default(), |_| true).unwrap();
+
+        // Assert
+        let wav = fs::read(&wav_path).unwrap();
+        let stem = fs::read(directory.path().join("game-wave.wav")).unwrap();
+        assert_eq!(&wav[..4], b"RIFF");
+        assert!(wav.len() > 44);
+        assert_eq!(stem.len(), wav.len());
+    }
+
+    #[test]
+    fn test_load_palette() {
+        use rustygameboy::palette::PalettePreset;
+
+        let directory = tempdir().unwrap();
+        let path = directory.path().join("game.pal");
+        fs::write(&path, "JASC-PAL\n0100\n4\n1 2 3\n4 5 6\n7 8 9\n0 0 0\n").unwrap();
+
+        let file = load_palette(path.to_str().unwrap());
+
+        assert_eq!(file.unwrap().bg[1], [4, 5, 6, 0xFF]);
+        assert_eq!(
+            load_palette("pocket").unwrap(),
+            PalettePreset::Pocket.palette()
+        );
+        assert!(load_palette("missing.pal").is_err());
+    }
+
+    #[test]
+    fn test_apply_settings() {
+        // Arrange
+        let config =
+            Config::parse("[video]\nscale = 5\nfilter = \"lcd\"\n[paths]\nsave_dir = \"saves\"\n")
+                .unwrap();
+        let matches = Cli::command()
+            .try_get_matches_from(["rusty_gameboy", "game.gb", "--scale", "2"])
+            .unwrap();
+        let mut args = Cli::from_arg_matches(&matches).unwrap().run;
+
+        // Act
+        let rom = rom::Rom::from_bytes_with_policy(vec![0; 0x8000], rom::ValidationPolicy::Lenient)
+            .unwrap();
+        apply_settings(&mut args, &config.settings_for(&rom), |id| {
+            matches.value_source(id) == Some(ValueSource::CommandLine)
+        });
+
+        // Assert
+        // The command line wins.
+        assert_eq!(args.scale, 2);
+        assert_eq!(args.filter, Filter::Lcd);
+        assert_eq!(args.save_dir.as_deref(), Some("saves"));
+        assert_eq!(args.sample_rate, rustygameboy::apu::DEFAULT_SAMPLE_RATE);
+    }
+
+    #[test]
+    fn test_display_options() {
+        let cli = Cli::try_parse_from(["rusty_gameboy", "game.gb", "--scaling", "fit"]).unwrap();
+
+        assert_eq!((cli.run.scale, cli.run.scaling), (3, Scaling::Fit));
+        assert_eq!(cli.run.filter, Filter::None);
+        assert_eq!(cli.run.frame_blend, 1);
+        assert!(Cli::try_parse_from(["rusty_gameboy", "game.gb", "--frame-blend", "5"]).is_err());
+        assert!(Cli::try_parse_from(["rusty_gameboy", "game.gb", "--scale", "7"]).is_err());
+    }
+
+    #[test]
+    fn test_cgb_palette() {
+        let cli =
+            Cli::try_parse_from(["rusty_gameboy", "game.gb", "--cgb-palette", "left+a"]).unwrap();
+
+        assert_eq!(cli.run.cgb_palette, Some(PaletteCombo::LeftA));
+        assert!(Cli::try_parse_from(["rusty_gameboy", "game.gb", "--cgb-palette", "a"]).is_err());
+    }
+
+    #[test]
+    fn test_mute_channels() {
+        let cli = Cli::try_parse_from(["rusty_gameboy", "game.gb", "--mute-channels", "3,noise"])
+            .unwrap();
+
+        assert_eq!(cli.run.mute_channels, [Channel::Wave, Channel::Noise]);
+        assert!(Cli::try_parse_from(["rusty_gameboy", "game.gb", "--mute-channels", "5"]).is_err());
+    }
+
+    #[test]
+    fn test_hide_layers() {
+        let cli =
+            Cli::try_parse_from(["rusty_gameboy", "game.gb", "--hide-layers", "bg,OBJ"]).unwrap();
+
+        assert_eq!(cli.run.hide_layers, [Layer::Background, Layer::Sprites]);
+        assert!(Cli::try_parse_from(["rusty_gameboy", "game.gb", "--hide-layers", "hud"]).is_err());
+    }
+
+    #[rstest]
+    #[case("wram.bin", Region::Wram, "wram.bin")]
+    #[case("out/VRAM1.txt", Region::Vram(1), "out/VRAM1.txt")]
+    #[case("C000..C0FF=table.bin", Region::Addresses(0xC000, 0xC0FF), "table.bin")]
+    fn test_dump_on_exit(#[case] value: &str, #[case] region: Region, #[case] path: &str) {
+        let cli =
+            Cli::try_parse_from(["rusty_gameboy", "game.gb", "--dump-on-exit", value]).unwrap();
+
+        assert_eq!(cli.run.dump_on_exit, [(region, PathBuf::from(path))]);
+    }
+
+    #[test]
+    fn test_dump_on_exit_without_region() {
+        assert!(
+            Cli::try_parse_from(["rusty_gameboy", "game.gb", "--dump-on-exit", "memory.bin"])
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn test_missing_rom_path() {
+        let cli = Cli::try_parse_from(["rusty_gameboy", "--headless"]).unwrap();
+
+        assert_eq!(cli.run.rom, None);
+        assert!(run_rom(cli.run, &Config::default(), |_| true).is_err());
+    }
+}
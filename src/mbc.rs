@@ -0,0 +1,261 @@
+mod huc1;
+mod mbc1;
+mod mbc2;
+mod mbc3;
+mod mbc5;
+mod mbc7;
+mod mmm01;
+mod pocket_camera;
+mod rtc;
+
+use std::io::{Error, Result};
+
+use crate::camera::CameraSource;
+use crate::rom::{MemoryBankType, Rom};
+use crate::savestate::{Snapshot, StateReader, StateWriter};
+
+pub use huc1::Huc1;
+pub use mbc1::Mbc1;
+pub use mbc2::Mbc2;
+pub use mbc3::Mbc3;
+pub use mbc5::Mbc5;
+pub use mbc7::{Accelerometer, Mbc7, Tilt};
+pub use mmm01::Mmm01;
+pub use pocket_camera::PocketCamera;
+pub use rtc::{Rtc, RtcClock};
+
+// Its `Snapshot` is the banking registers and RAM. The ROM itself is never part of a save state.
+pub trait Mbc: Snapshot {
+    // Reads from 0x0000-0x7FFF.
+    fn read_rom(&self, address: u16) -> u8;
+
+    // Where a read from 0x0000-0x7FFF lands in the ROM with the banks mapped now, for tools that
+    // track ROM bytes. It can be past the end of a ROM that's too short.
+    fn rom_offset(&self, address: u16) -> usize;
+
+    // Writes to 0x0000-0x7FFF, which go to the controller's registers.
+    fn write_rom(&mut self, address: u16, value: u8);
+
+    // Reads from 0xA000-0xBFFF.
+    fn read_ram(&self, address: u16) -> u8;
+
+    // Writes to 0xA000-0xBFFF.
+    fn write_ram(&mut self, address: u16, value: u8);
+
+    // Whether anything on the cartridge answers a read from 0xA000-0xBFFF. When nothing does, the
+    // data bus keeps the last value that was on it.
+    fn ram_readable(&self, _address: u16) -> bool {
+        true
+    }
+
+    // The whole external RAM, across all banks.
+    fn ram(&self) -> &[u8];
+
+    fn ram_mut(&mut self) -> &mut [u8];
+
+    // Battery-backed state as stored in a .sav file: the external RAM followed by any extra state.
+    fn save_data(&self) -> Vec<u8> {
+        self.ram().to_vec()
+    }
+
+    // Accepts data shorter or longer than the RAM so saves from other emulators still load.
+    fn load_save_data(&mut self, data: &[u8]) {
+        let ram = self.ram_mut();
+        let length = ram.len().min(data.len());
+        ram[..length].copy_from_slice(&data[..length]);
+    }
+
+    // Only cartridges with a rumble motor ever call this.
+    fn set_rumble_callback(&mut self, _callback: RumbleCallback) {}
+
+    // Only MBC7 cartridges have an accelerometer. Without one they lie flat.
+    fn set_accelerometer(&mut self, _accelerometer: Option<Box<dyn Accelerometer>>) {}
+
+    // Only the Pocket Camera has a sensor. Without a source it sees a test pattern.
+    fn set_camera_source(&mut self, _source: Option<Box<dyn CameraSource>>) {}
+
+    // Only cartridges with a real-time clock have anything to do for these, see `Rtc`.
+    fn set_rtc_clock(&mut self, _clock: RtcClock) {}
+
+    // Cycles at normal speed. The Pocket Camera's sensor times its captures with these too.
+    fn tick_rtc(&mut self, _cycles: u64) {}
+}
+
+pub type RumbleCallback = Box<dyn FnMut(bool)>;
+
+pub fn new(rom: Rom) -> Result<Box<dyn Mbc>> {
+    let features = rom.cartridge_features()?;
+    let multicart = rom.is_mbc1_multicart();
+    let ram = vec![0; rom.get_ram_size()? as usize];
+    let rom = rom.into_content();
+
+    let mbc: Box<dyn Mbc> = match features.memory_bank_type {
+        MemoryBankType::ROM => Box::new(RomOnly { rom, ram }),
+        MemoryBankType::MBC1 => Box::new(Mbc1::new(rom, ram, multicart)),
+        MemoryBankType::MBC2 => Box::new(Mbc2::new(rom)),
+        MemoryBankType::MMM01 => Box::new(Mmm01::new(rom, ram)),
+        MemoryBankType::MBC3 => Box::new(Mbc3::new(rom, ram, features.rtc)),
+        MemoryBankType::MBC5 => Box::new(Mbc5::new(rom, ram, features.rumble)),
+        MemoryBankType::MBC7 => Box::new(Mbc7::new(rom)),
+        MemoryBankType::HuC1 => Box::new(Huc1::new(rom, ram)),
+        MemoryBankType::PocketCamera => Box::new(PocketCamera::new(rom)),
+        _ => {
+            return Err(Error::other(format!(
+                "The memory bank type {:?} is not supported yet.",
+                features.memory_bank_type
+            )))
+        }
+    };
+
+    Ok(mbc)
+}
+
+// Cartridges without a controller map 32 KB of ROM directly and optionally up to 8 KB of RAM.
+pub struct RomOnly {
+    rom: Vec<u8>,
+    ram: Vec<u8>,
+}
+
+impl Mbc for RomOnly {
+    fn read_rom(&self, address: u16) -> u8 {
+        self.rom.get(address as usize).copied().unwrap_or(0xFF)
+    }
+
+    fn rom_offset(&self, address: u16) -> usize {
+        address as usize
+    }
+
+    fn write_rom(&mut self, _address: u16, _value: u8) {}
+
+    fn read_ram(&self, address: u16) -> u8 {
+        self.ram
+            .get((address - 0xA000) as usize)
+            .copied()
+            .unwrap_or(0xFF)
+    }
+
+    fn write_ram(&mut self, address: u16, value: u8) {
+        if let Some(byte) = self.ram.get_mut((address - 0xA000) as usize) {
+            *byte = value;
+        }
+    }
+
+    fn ram_readable(&self, address: u16) -> bool {
+        ((address - 0xA000) as usize) < self.ram.len()
+    }
+
+    fn ram(&self) -> &[u8] {
+        &self.ram
+    }
+
+    fn ram_mut(&mut self) -> &mut [u8] {
+        &mut self.ram
+    }
+}
+
+impl Snapshot for RomOnly {
+    fn component(&self) -> &'static str {
+        "ROM-only cartridge"
+    }
+
+    fn version(&self) -> u8 {
+        1
+    }
+
+    fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_bytes(&self.ram);
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader) -> Result<()> {
+        reader.read_bytes_into(&mut self.ram)
+    }
+}
+
+const ROM_BANK_SIZE: usize = 0x4000;
+
+const RAM_BANK_SIZE: usize = 0x2000;
+
+#[cfg(test)]
+mod tests {
+    use rstest::rstest;
+
+    use super::*;
+
+    fn rom_with_cartridge_type(cartridge_type: u8, ram_size: u8) -> Rom {
+        let mut content = vec![0; 0x8000];
+        content[0x147] = cartridge_type;
+        content[0x149] = ram_size;
+        content[0x4000] = 0x42;
+        Rom::from_content(content)
+    }
+
+    #[rstest]
+    #[case(0x00)]
+    #[case(0x01)]
+    #[case(0x03)]
+    #[case(0x05)]
+    #[case(0x06)]
+    #[case(0x08)]
+    #[case(0x0B)]
+    #[case(0x0F)]
+    #[case(0x13)]
+    #[case(0x19)]
+    #[case(0x1E)]
+    #[case(0x22)]
+    #[case(0xFC)]
+    #[case(0xFF)]
+    fn test_new(#[case] cartridge_type: u8) {
+        let mbc = new(rom_with_cartridge_type(cartridge_type, 0x00)).unwrap();
+        assert_eq!(mbc.read_rom(0x4000), 0x42);
+    }
+
+    #[test]
+    fn test_new_unsupported() {
+        assert!(new(rom_with_cartridge_type(0x20, 0x00)).is_err());
+    }
+
+    #[test]
+    fn test_rom_only_ram() {
+        let mut mbc = new(rom_with_cartridge_type(0x08, 0x02)).unwrap();
+
+        mbc.write_ram(0xA123, 0x55);
+        mbc.write_rom(0x0000, 0x0A);
+
+        assert_eq!(mbc.read_ram(0xA123), 0x55);
+        assert_eq!(mbc.read_rom(0x0000), 0x00);
+    }
+
+    #[test]
+    fn test_rom_only_without_ram() {
+        let mut mbc = new(rom_with_cartridge_type(0x00, 0x00)).unwrap();
+
+        mbc.write_ram(0xA000, 0x55);
+
+        assert_eq!(mbc.read_ram(0xA000), 0xFF);
+    }
+
+    #[test]
+    fn test_save_data_round_trip() {
+        let mut mbc = new(rom_with_cartridge_type(0x03, 0x02)).unwrap();
+        mbc.write_rom(0x0000, 0x0A);
+        mbc.write_ram(0xA010, 0x42);
+
+        let data = mbc.save_data();
+        let mut loaded = new(rom_with_cartridge_type(0x03, 0x02)).unwrap();
+        loaded.load_save_data(&data);
+        loaded.write_rom(0x0000, 0x0A);
+
+        assert_eq!(data.len(), 0x2000);
+        assert_eq!(loaded.read_ram(0xA010), 0x42);
+    }
+
+    #[test]
+    fn test_load_short_save_data() {
+        let mut mbc = new(rom_with_cartridge_type(0x09, 0x02)).unwrap();
+
+        mbc.load_save_data(&[0x12, 0x34]);
+
+        assert_eq!(mbc.read_ram(0xA001), 0x34);
+        assert_eq!(mbc.read_ram(0xA002), 0x00);
+    }
+}
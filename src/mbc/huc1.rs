@@ -0,0 +1,188 @@
+use std::io::Result;
+
+use super::{Mbc, RAM_BANK_SIZE, ROM_BANK_SIZE};
+use crate::savestate::{Snapshot, StateReader, StateWriter};
+
+// Hudson's controller: MBC1-like banking, RAM that needs no enabling, and an infrared port that
+// replaces the RAM at 0xA000-0xBFFF while selected.
+pub struct Huc1 {
+    rom: Vec<u8>,
+    ram: Vec<u8>,
+    ir_selected: bool,
+    // The 6-bit register at 0x2000-0x3FFF. Unlike MBC1, bank 0 can be mapped here.
+    rom_bank: u8,
+    ram_bank: u8,
+    ir_led: bool,
+}
+
+impl Huc1 {
+    pub fn new(rom: Vec<u8>, ram: Vec<u8>) -> Huc1 {
+        Huc1 {
+            rom,
+            ram,
+            ir_selected: false,
+            rom_bank: 1,
+            ram_bank: 0,
+            ir_led: false,
+        }
+    }
+
+    pub fn ir_led(&self) -> bool {
+        self.ir_led
+    }
+
+    fn rom_bank_count(&self) -> usize {
+        (self.rom.len() / ROM_BANK_SIZE).max(1)
+    }
+
+    fn ram_offset(&self, address: u16) -> Option<usize> {
+        if self.ram.is_empty() {
+            return None;
+        }
+
+        let offset = self.ram_bank as usize * RAM_BANK_SIZE + (address - 0xA000) as usize;
+        Some(offset % self.ram.len())
+    }
+}
+
+impl Mbc for Huc1 {
+    fn read_rom(&self, address: u16) -> u8 {
+        self.rom
+            .get(self.rom_offset(address))
+            .copied()
+            .unwrap_or(0xFF)
+    }
+
+    fn rom_offset(&self, address: u16) -> usize {
+        let bank = match address {
+            0x0000..=0x3FFF => 0,
+            _ => self.rom_bank as usize % self.rom_bank_count(),
+        };
+        bank * ROM_BANK_SIZE + (address as usize & (ROM_BANK_SIZE - 1))
+    }
+
+    fn write_rom(&mut self, address: u16, value: u8) {
+        match address {
+            0x0000..=0x1FFF => self.ir_selected = value & 0x0F == 0x0E,
+            0x2000..=0x3FFF => self.rom_bank = value & 0x3F,
+            0x4000..=0x5FFF => self.ram_bank = value & 0x03,
+            _ => {}
+        }
+    }
+
+    fn read_ram(&self, address: u16) -> u8 {
+        // Without another device there is never any light to receive.
+        if self.ir_selected {
+            return 0xC0;
+        }
+
+        match self.ram_offset(address) {
+            Some(offset) => self.ram[offset],
+            None => 0xFF,
+        }
+    }
+
+    fn write_ram(&mut self, address: u16, value: u8) {
+        if self.ir_selected {
+            self.ir_led = value & 0x01 != 0;
+        } else if let Some(offset) = self.ram_offset(address) {
+            self.ram[offset] = value;
+        }
+    }
+
+    fn ram_readable(&self, address: u16) -> bool {
+        self.ir_selected || self.ram_offset(address).is_some()
+    }
+
+    fn ram(&self) -> &[u8] {
+        &self.ram
+    }
+
+    fn ram_mut(&mut self) -> &mut [u8] {
+        &mut self.ram
+    }
+}
+
+impl Snapshot for Huc1 {
+    fn component(&self) -> &'static str {
+        "HuC1"
+    }
+
+    fn version(&self) -> u8 {
+        1
+    }
+
+    fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_bytes(&self.ram);
+        writer.write_bool(self.ir_selected);
+        writer.write_u8(self.rom_bank);
+        writer.write_u8(self.ram_bank);
+        writer.write_bool(self.ir_led);
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader) -> Result<()> {
+        reader.read_bytes_into(&mut self.ram)?;
+        self.ir_selected = reader.read_bool()?;
+        self.rom_bank = reader.read_u8()?;
+        self.ram_bank = reader.read_u8()?;
+        self.ir_led = reader.read_bool()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rstest::rstest;
+
+    use super::*;
+
+    fn huc1(rom_banks: usize, ram_size: usize) -> Huc1 {
+        let mut rom = vec![0; rom_banks * ROM_BANK_SIZE];
+        for bank in 0..rom_banks {
+            rom[bank * ROM_BANK_SIZE] = bank as u8;
+        }
+        Huc1::new(rom, vec![0; ram_size])
+    }
+
+    #[rstest]
+    #[case(0x00, 0x00)]
+    #[case(0x01, 0x01)]
+    #[case(0x3F, 0x3F)]
+    #[case(0x7F, 0x3F)]
+    fn test_switch_rom_bank(#[case] value: u8, #[case] expected_bank: u8) {
+        let mut mbc = huc1(64, 0);
+
+        mbc.write_rom(0x2000, value);
+
+        assert_eq!(mbc.read_rom(0x4000), expected_bank);
+    }
+
+    #[test]
+    fn test_ram_needs_no_enable() {
+        let mut mbc = huc1(4, 0x8000);
+
+        mbc.write_rom(0x4000, 0x02);
+        mbc.write_ram(0xA000, 0x12);
+
+        assert_eq!(mbc.read_ram(0xA000), 0x12);
+        mbc.write_rom(0x4000, 0x00);
+        assert_eq!(mbc.read_ram(0xA000), 0x00);
+    }
+
+    #[test]
+    fn test_infrared_replaces_ram() {
+        // Arrange
+        let mut mbc = huc1(4, 0x2000);
+        mbc.write_ram(0xA000, 0x12);
+
+        // Act
+        mbc.write_rom(0x0000, 0x0E);
+        mbc.write_ram(0xA000, 0x01);
+
+        // Assert
+        assert_eq!(mbc.read_ram(0xA000), 0xC0);
+        assert!(mbc.ir_led());
+        mbc.write_rom(0x0000, 0x0A);
+        assert_eq!(mbc.read_ram(0xA000), 0x12);
+    }
+}
@@ -0,0 +1,320 @@
+use std::io::Result;
+
+use super::{Mbc, RAM_BANK_SIZE, ROM_BANK_SIZE};
+use crate::savestate::{Snapshot, StateReader, StateWriter};
+
+pub struct Mbc1 {
+    rom: Vec<u8>,
+    ram: Vec<u8>,
+    ram_enabled: bool,
+    // The 5-bit register at 0x2000-0x3FFF.
+    bank1: u8,
+    // The 2-bit register at 0x4000-0x5FFF, used for the upper ROM bank bits or the RAM bank.
+    bank2: u8,
+    advanced_banking: bool,
+    // MBC1M multicarts don't connect bit 4 of the bank1 register, so bank2 starts at bit 4 and
+    // selects one of four 256 KB games.
+    multicart: bool,
+}
+
+impl Mbc1 {
+    pub fn new(rom: Vec<u8>, ram: Vec<u8>, multicart: bool) -> Mbc1 {
+        Mbc1 {
+            rom,
+            ram,
+            ram_enabled: false,
+            bank1: 1,
+            bank2: 0,
+            advanced_banking: false,
+            multicart,
+        }
+    }
+
+    fn bank2_shift(&self) -> u32 {
+        if self.multicart {
+            4
+        } else {
+            5
+        }
+    }
+
+    fn bank1_mask(&self) -> u8 {
+        if self.multicart {
+            0x0F
+        } else {
+            0x1F
+        }
+    }
+
+    fn rom_bank_count(&self) -> usize {
+        (self.rom.len() / ROM_BANK_SIZE).max(1)
+    }
+
+    fn rom_bank_offset(&self, bank: usize, address: u16) -> usize {
+        let bank = bank % self.rom_bank_count();
+        bank * ROM_BANK_SIZE + (address as usize & (ROM_BANK_SIZE - 1))
+    }
+
+    fn ram_offset(&self, address: u16) -> Option<usize> {
+        if !self.ram_enabled || self.ram.is_empty() {
+            return None;
+        }
+
+        let bank = if self.advanced_banking {
+            self.bank2 as usize
+        } else {
+            0
+        };
+        let offset = bank * RAM_BANK_SIZE + (address - 0xA000) as usize;
+        Some(offset % self.ram.len())
+    }
+}
+
+impl Mbc for Mbc1 {
+    fn read_rom(&self, address: u16) -> u8 {
+        self.rom
+            .get(self.rom_offset(address))
+            .copied()
+            .unwrap_or(0xFF)
+    }
+
+    fn rom_offset(&self, address: u16) -> usize {
+        match address {
+            0x0000..=0x3FFF => {
+                let bank = if self.advanced_banking {
+                    (self.bank2 as usize) << self.bank2_shift()
+                } else {
+                    0
+                };
+                self.rom_bank_offset(bank, address)
+            }
+            _ => {
+                let bank = ((self.bank2 as usize) << self.bank2_shift())
+                    | (self.bank1 & self.bank1_mask()) as usize;
+                self.rom_bank_offset(bank, address)
+            }
+        }
+    }
+
+    fn write_rom(&mut self, address: u16, value: u8) {
+        match address {
+            0x0000..=0x1FFF => self.ram_enabled = value & 0x0F == 0x0A,
+            0x2000..=0x3FFF => {
+                // Bank 0 can't be selected here; the register treats it as 1. This checks all five
+                // bits even on multicarts, so 0x10 there maps bank 0 of the selected game.
+                self.bank1 = (value & 0x1F).max(1);
+            }
+            0x4000..=0x5FFF => self.bank2 = value & 0x03,
+            _ => self.advanced_banking = value & 0x01 != 0,
+        }
+    }
+
+    fn read_ram(&self, address: u16) -> u8 {
+        match self.ram_offset(address) {
+            Some(offset) => self.ram[offset],
+            None => 0xFF,
+        }
+    }
+
+    fn write_ram(&mut self, address: u16, value: u8) {
+        if let Some(offset) = self.ram_offset(address) {
+            self.ram[offset] = value;
+        }
+    }
+
+    fn ram_readable(&self, address: u16) -> bool {
+        self.ram_offset(address).is_some()
+    }
+
+    fn ram(&self) -> &[u8] {
+        &self.ram
+    }
+
+    fn ram_mut(&mut self) -> &mut [u8] {
+        &mut self.ram
+    }
+}
+
+impl Snapshot for Mbc1 {
+    fn component(&self) -> &'static str {
+        "MBC1"
+    }
+
+    fn version(&self) -> u8 {
+        1
+    }
+
+    fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_bytes(&self.ram);
+        writer.write_bool(self.ram_enabled);
+        writer.write_u8(self.bank1);
+        writer.write_u8(self.bank2);
+        writer.write_bool(self.advanced_banking);
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader) -> Result<()> {
+        reader.read_bytes_into(&mut self.ram)?;
+        self.ram_enabled = reader.read_bool()?;
+        self.bank1 = reader.read_u8()?;
+        self.bank2 = reader.read_u8()?;
+        self.advanced_banking = reader.read_bool()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rstest::rstest;
+
+    use super::*;
+
+    // Every bank starts with its own bank number so reads reveal which bank is mapped.
+    fn mbc1(rom_banks: usize, ram_size: usize) -> Mbc1 {
+        let mut rom = vec![0; rom_banks * ROM_BANK_SIZE];
+        for bank in 0..rom_banks {
+            rom[bank * ROM_BANK_SIZE] = bank as u8;
+        }
+        Mbc1::new(rom, vec![0; ram_size], false)
+    }
+
+    fn mbc1m() -> Mbc1 {
+        let mut mbc = mbc1(64, 0);
+        mbc.multicart = true;
+        mbc
+    }
+
+    #[test]
+    fn test_default_banks() {
+        let mbc = mbc1(4, 0);
+
+        assert_eq!(mbc.read_rom(0x0000), 0);
+        assert_eq!(mbc.read_rom(0x4000), 1);
+    }
+
+    #[rstest]
+    #[case(0x00, 1)]
+    #[case(0x01, 1)]
+    #[case(0x02, 2)]
+    #[case(0x1F, 31)]
+    #[case(0x20, 1)]
+    #[case(0x25, 5)]
+    fn test_switch_rom_bank(#[case] value: u8, #[case] expected_bank: u8) {
+        // Arrange
+        let mut mbc = mbc1(32, 0);
+
+        // Act
+        mbc.write_rom(0x2000, value);
+
+        // Assert
+        assert_eq!(mbc.read_rom(0x4000), expected_bank);
+    }
+
+    #[test]
+    fn test_rom_bank_is_masked_to_rom_size() {
+        let mut mbc = mbc1(4, 0);
+
+        mbc.write_rom(0x2000, 0x05);
+
+        assert_eq!(mbc.read_rom(0x4000), 1);
+    }
+
+    #[test]
+    fn test_upper_rom_bank_bits() {
+        let mut mbc = mbc1(128, 0);
+
+        mbc.write_rom(0x4000, 0x02);
+        mbc.write_rom(0x2000, 0x00);
+
+        assert_eq!(mbc.read_rom(0x4000), 0x41);
+        assert_eq!(mbc.read_rom(0x0000), 0x00);
+    }
+
+    #[test]
+    fn test_advanced_banking_maps_bank2_into_low_area() {
+        let mut mbc = mbc1(128, 0);
+
+        mbc.write_rom(0x4000, 0x03);
+        mbc.write_rom(0x6000, 0x01);
+
+        assert_eq!(mbc.read_rom(0x0000), 0x60);
+        assert_eq!(mbc.read_rom(0x4000), 0x61);
+    }
+
+    #[test]
+    fn test_ram_disabled_by_default() {
+        let mut mbc = mbc1(4, 0x8000);
+
+        mbc.write_ram(0xA000, 0x12);
+
+        assert_eq!(mbc.read_ram(0xA000), 0xFF);
+    }
+
+    #[rstest]
+    #[case(0x0A, true)]
+    #[case(0x1A, true)]
+    #[case(0x0B, false)]
+    #[case(0x00, false)]
+    fn test_ram_enable(#[case] value: u8, #[case] enabled: bool) {
+        let mut mbc = mbc1(4, 0x2000);
+        mbc.write_rom(0x0000, 0x0A);
+        mbc.write_ram(0xA000, 0x12);
+
+        mbc.write_rom(0x1FFF, value);
+
+        assert_eq!(mbc.read_ram(0xA000) == 0x12, enabled);
+    }
+
+    #[test]
+    fn test_ram_banking() {
+        let mut mbc = mbc1(4, 0x8000);
+        mbc.write_rom(0x0000, 0x0A);
+        mbc.write_rom(0x6000, 0x01);
+
+        mbc.write_rom(0x4000, 0x00);
+        mbc.write_ram(0xA000, 0x11);
+        mbc.write_rom(0x4000, 0x02);
+        mbc.write_ram(0xA000, 0x22);
+
+        assert_eq!(mbc.read_ram(0xA000), 0x22);
+        mbc.write_rom(0x4000, 0x00);
+        assert_eq!(mbc.read_ram(0xA000), 0x11);
+    }
+
+    #[test]
+    fn test_ram_bank_ignored_in_simple_banking() {
+        let mut mbc = mbc1(4, 0x8000);
+        mbc.write_rom(0x0000, 0x0A);
+        mbc.write_ram(0xA000, 0x11);
+
+        mbc.write_rom(0x4000, 0x02);
+
+        assert_eq!(mbc.read_ram(0xA000), 0x11);
+    }
+
+    #[rstest]
+    #[case(0x00, 0x01, 0x01)]
+    #[case(0x01, 0x0F, 0x1F)]
+    #[case(0x03, 0x02, 0x32)]
+    #[case(0x01, 0x10, 0x10)]
+    #[case(0x02, 0x1F, 0x2F)]
+    #[case(0x02, 0x00, 0x21)]
+    fn test_multicart_banking(#[case] bank2: u8, #[case] bank1: u8, #[case] expected_bank: u8) {
+        let mut mbc = mbc1m();
+
+        mbc.write_rom(0x4000, bank2);
+        mbc.write_rom(0x2000, bank1);
+
+        assert_eq!(mbc.read_rom(0x4000), expected_bank);
+    }
+
+    #[test]
+    fn test_multicart_advanced_banking_maps_game_into_low_area() {
+        let mut mbc = mbc1m();
+
+        mbc.write_rom(0x4000, 0x02);
+        mbc.write_rom(0x6000, 0x01);
+
+        assert_eq!(mbc.read_rom(0x0000), 0x20);
+        assert_eq!(mbc.read_rom(0x4000), 0x21);
+    }
+}
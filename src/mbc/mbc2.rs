@@ -0,0 +1,192 @@
+use std::io::Result;
+
+use super::{Mbc, ROM_BANK_SIZE};
+use crate::savestate::{Snapshot, StateReader, StateWriter};
+
+// Up to 256 KB of ROM and 512 half-bytes of RAM built into the controller itself, which is why the
+// header declares no RAM.
+pub struct Mbc2 {
+    rom: Vec<u8>,
+    // One nibble per byte, kept in the low half.
+    ram: Vec<u8>,
+    ram_enabled: bool,
+    // 4 bits, bank 0 is treated as 1 like on MBC1.
+    rom_bank: u8,
+}
+
+impl Mbc2 {
+    pub fn new(rom: Vec<u8>) -> Mbc2 {
+        Mbc2 {
+            rom,
+            ram: vec![0; RAM_SIZE],
+            ram_enabled: false,
+            rom_bank: 1,
+        }
+    }
+
+    fn rom_bank_count(&self) -> usize {
+        (self.rom.len() / ROM_BANK_SIZE).max(1)
+    }
+
+    // Only the bottom 9 address bits are wired, so the RAM repeats across 0xA000-0xBFFF.
+    fn ram_offset(&self, address: u16) -> Option<usize> {
+        self.ram_enabled
+            .then_some((address as usize) & (RAM_SIZE - 1))
+    }
+}
+
+impl Mbc for Mbc2 {
+    fn read_rom(&self, address: u16) -> u8 {
+        self.rom
+            .get(self.rom_offset(address))
+            .copied()
+            .unwrap_or(0xFF)
+    }
+
+    fn rom_offset(&self, address: u16) -> usize {
+        let bank = match address {
+            0x0000..=0x3FFF => 0,
+            _ => self.rom_bank as usize % self.rom_bank_count(),
+        };
+        bank * ROM_BANK_SIZE + (address as usize & (ROM_BANK_SIZE - 1))
+    }
+
+    fn write_rom(&mut self, address: u16, value: u8) {
+        // Both registers live at 0x0000-0x3FFF; bit 8 of the address picks which one is written.
+        match address {
+            0x0000..=0x3FFF if address & 0x0100 == 0 => self.ram_enabled = value & 0x0F == 0x0A,
+            0x0000..=0x3FFF => self.rom_bank = (value & 0x0F).max(1),
+            _ => {}
+        }
+    }
+
+    fn read_ram(&self, address: u16) -> u8 {
+        // The upper nibble isn't connected and reads as ones.
+        match self.ram_offset(address) {
+            Some(offset) => self.ram[offset] | 0xF0,
+            None => 0xFF,
+        }
+    }
+
+    fn write_ram(&mut self, address: u16, value: u8) {
+        if let Some(offset) = self.ram_offset(address) {
+            self.ram[offset] = value & 0x0F;
+        }
+    }
+
+    fn ram_readable(&self, address: u16) -> bool {
+        self.ram_offset(address).is_some()
+    }
+
+    fn ram(&self) -> &[u8] {
+        &self.ram
+    }
+
+    fn ram_mut(&mut self) -> &mut [u8] {
+        &mut self.ram
+    }
+
+    // Saves from other emulators may have set the upper nibbles.
+    fn load_save_data(&mut self, data: &[u8]) {
+        for (byte, value) in self.ram.iter_mut().zip(data) {
+            *byte = value & 0x0F;
+        }
+    }
+}
+
+impl Snapshot for Mbc2 {
+    fn component(&self) -> &'static str {
+        "MBC2"
+    }
+
+    fn version(&self) -> u8 {
+        1
+    }
+
+    fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_bytes(&self.ram);
+        writer.write_bool(self.ram_enabled);
+        writer.write_u8(self.rom_bank);
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader) -> Result<()> {
+        reader.read_bytes_into(&mut self.ram)?;
+        self.ram_enabled = reader.read_bool()?;
+        self.rom_bank = reader.read_u8()?;
+        Ok(())
+    }
+}
+
+const RAM_SIZE: usize = 512;
+
+#[cfg(test)]
+mod tests {
+    use rstest::rstest;
+
+    use super::*;
+
+    fn mbc2(rom_banks: usize) -> Mbc2 {
+        let mut rom = vec![0; rom_banks * ROM_BANK_SIZE];
+        for bank in 0..rom_banks {
+            rom[bank * ROM_BANK_SIZE] = bank as u8;
+        }
+        Mbc2::new(rom)
+    }
+
+    #[rstest]
+    #[case(0x2100, 0x05, 0x05)]
+    #[case(0x0100, 0x0F, 0x0F)]
+    #[case(0x2100, 0x00, 0x01)]
+    #[case(0x2100, 0x13, 0x03)]
+    fn test_switch_rom_bank(#[case] address: u16, #[case] value: u8, #[case] expected_bank: u8) {
+        let mut mbc = mbc2(16);
+
+        mbc.write_rom(address, value);
+
+        assert_eq!(mbc.read_rom(0x4000), expected_bank);
+    }
+
+    #[test]
+    fn test_address_bit_8_selects_register() {
+        let mut mbc = mbc2(16);
+
+        mbc.write_rom(0x2000, 0x05);
+        mbc.write_ram(0xA000, 0x03);
+
+        // The write enabled RAM instead of switching banks.
+        assert_eq!(mbc.read_rom(0x4000), 0x01);
+        assert_eq!(mbc.read_ram(0xA000), 0xFF);
+        mbc.write_rom(0x0000, 0x0A);
+        mbc.write_ram(0xA000, 0x03);
+        assert_eq!(mbc.read_ram(0xA000), 0xF3);
+    }
+
+    #[test]
+    fn test_ram_holds_nibbles_and_repeats() {
+        // Arrange
+        let mut mbc = mbc2(2);
+        mbc.write_rom(0x0000, 0x0A);
+
+        // Act
+        mbc.write_ram(0xA1FF, 0xAB);
+
+        // Assert
+        assert_eq!(mbc.read_ram(0xA1FF), 0xFB);
+        assert_eq!(mbc.read_ram(0xA3FF), 0xFB);
+        assert_eq!(mbc.read_ram(0xBFFF), 0xFB);
+        assert_eq!(mbc.ram()[0x1FF], 0x0B);
+    }
+
+    #[test]
+    fn test_ram_disabled() {
+        let mut mbc = mbc2(2);
+        mbc.write_rom(0x0000, 0x0A);
+        mbc.write_ram(0xA000, 0x01);
+
+        mbc.write_rom(0x0000, 0x00);
+        mbc.write_ram(0xA000, 0x02);
+
+        assert_eq!(mbc.read_ram(0xA000), 0xFF);
+        assert_eq!(mbc.ram()[0], 0x01);
+    }
+}
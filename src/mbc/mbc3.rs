@@ -0,0 +1,311 @@
+use std::io::Result;
+
+use super::rtc::{Rtc, RtcClock, RTC_STATE_SIZE};
+use super::{Mbc, RAM_BANK_SIZE, ROM_BANK_SIZE};
+use crate::savestate::{Snapshot, StateReader, StateWriter};
+
+pub struct Mbc3 {
+    rom: Vec<u8>,
+    ram: Vec<u8>,
+    rtc: Option<Rtc>,
+    ram_and_rtc_enabled: bool,
+    rom_bank: u8,
+    // 0x00-0x03 select a RAM bank, 0x08-0x0C select an RTC register.
+    ram_bank_or_rtc_register: u8,
+    latch_armed: bool,
+}
+
+impl Mbc3 {
+    pub fn new(rom: Vec<u8>, ram: Vec<u8>, has_rtc: bool) -> Mbc3 {
+        Mbc3 {
+            rom,
+            ram,
+            rtc: has_rtc.then(Rtc::new),
+            ram_and_rtc_enabled: false,
+            rom_bank: 1,
+            ram_bank_or_rtc_register: 0,
+            latch_armed: false,
+        }
+    }
+
+    pub fn rtc(&self) -> Option<&Rtc> {
+        self.rtc.as_ref()
+    }
+
+    pub fn set_rtc(&mut self, rtc: Rtc) {
+        if self.rtc.is_some() {
+            self.rtc = Some(rtc);
+        }
+    }
+
+    fn rom_bank_count(&self) -> usize {
+        (self.rom.len() / ROM_BANK_SIZE).max(1)
+    }
+
+    fn ram_offset(&self, address: u16) -> Option<usize> {
+        if self.ram.is_empty() || self.ram_bank_or_rtc_register > 0x07 {
+            return None;
+        }
+
+        let offset =
+            self.ram_bank_or_rtc_register as usize * RAM_BANK_SIZE + (address - 0xA000) as usize;
+        Some(offset % self.ram.len())
+    }
+}
+
+impl Mbc for Mbc3 {
+    fn read_rom(&self, address: u16) -> u8 {
+        self.rom
+            .get(self.rom_offset(address))
+            .copied()
+            .unwrap_or(0xFF)
+    }
+
+    fn rom_offset(&self, address: u16) -> usize {
+        let bank = match address {
+            0x0000..=0x3FFF => 0,
+            _ => self.rom_bank as usize % self.rom_bank_count(),
+        };
+        bank * ROM_BANK_SIZE + (address as usize & (ROM_BANK_SIZE - 1))
+    }
+
+    fn write_rom(&mut self, address: u16, value: u8) {
+        match address {
+            0x0000..=0x1FFF => self.ram_and_rtc_enabled = value & 0x0F == 0x0A,
+            0x2000..=0x3FFF => self.rom_bank = (value & 0x7F).max(1),
+            0x4000..=0x5FFF => self.ram_bank_or_rtc_register = value & 0x0F,
+            _ => {
+                // Writing 0x00 followed by 0x01 latches the clock.
+                if self.latch_armed && value == 0x01 {
+                    if let Some(rtc) = self.rtc.as_mut() {
+                        rtc.latch();
+                    }
+                }
+                self.latch_armed = value == 0x00;
+            }
+        }
+    }
+
+    fn read_ram(&self, address: u16) -> u8 {
+        if !self.ram_and_rtc_enabled {
+            return 0xFF;
+        }
+
+        match (self.ram_bank_or_rtc_register, self.rtc.as_ref()) {
+            (0x08..=0x0C, Some(rtc)) => rtc.read(self.ram_bank_or_rtc_register),
+            _ => match self.ram_offset(address) {
+                Some(offset) => self.ram[offset],
+                None => 0xFF,
+            },
+        }
+    }
+
+    fn write_ram(&mut self, address: u16, value: u8) {
+        if !self.ram_and_rtc_enabled {
+            return;
+        }
+
+        let register = self.ram_bank_or_rtc_register;
+        match (register, self.rtc.as_mut()) {
+            (0x08..=0x0C, Some(rtc)) => rtc.write(register, value),
+            _ => {
+                if let Some(offset) = self.ram_offset(address) {
+                    self.ram[offset] = value;
+                }
+            }
+        }
+    }
+
+    fn ram_readable(&self, address: u16) -> bool {
+        if !self.ram_and_rtc_enabled {
+            return false;
+        }
+
+        match (self.ram_bank_or_rtc_register, self.rtc.as_ref()) {
+            (0x08..=0x0C, Some(_)) => true,
+            _ => self.ram_offset(address).is_some(),
+        }
+    }
+
+    fn ram(&self) -> &[u8] {
+        &self.ram
+    }
+
+    fn ram_mut(&mut self) -> &mut [u8] {
+        &mut self.ram
+    }
+
+    fn save_data(&self) -> Vec<u8> {
+        let mut data = self.ram.clone();
+        if let Some(rtc) = &self.rtc {
+            data.extend_from_slice(&rtc.to_bytes());
+        }
+        data
+    }
+
+    // The clock state, when present, follows the RAM.
+    fn load_save_data(&mut self, data: &[u8]) {
+        let length = self.ram.len().min(data.len());
+        self.ram[..length].copy_from_slice(&data[..length]);
+
+        if let (Some(rtc), Some(bytes)) = (self.rtc.as_mut(), data.get(self.ram.len()..)) {
+            if let Ok(bytes) = <&[u8; RTC_STATE_SIZE]>::try_from(bytes) {
+                rtc.load_bytes(bytes);
+            }
+        }
+    }
+
+    fn set_rtc_clock(&mut self, clock: RtcClock) {
+        if let Some(rtc) = self.rtc.as_mut() {
+            rtc.set_clock(clock);
+        }
+    }
+
+    fn tick_rtc(&mut self, cycles: u64) {
+        if let Some(rtc) = self.rtc.as_mut() {
+            rtc.tick(cycles);
+        }
+    }
+}
+
+impl Snapshot for Mbc3 {
+    fn component(&self) -> &'static str {
+        "MBC3"
+    }
+
+    fn version(&self) -> u8 {
+        1
+    }
+
+    fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_bytes(&self.ram);
+        if let Some(rtc) = &self.rtc {
+            rtc.save_state(writer);
+        }
+        writer.write_bool(self.ram_and_rtc_enabled);
+        writer.write_u8(self.rom_bank);
+        writer.write_u8(self.ram_bank_or_rtc_register);
+        writer.write_bool(self.latch_armed);
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader) -> Result<()> {
+        reader.read_bytes_into(&mut self.ram)?;
+        if let Some(rtc) = self.rtc.as_mut() {
+            rtc.load_state(reader)?;
+        }
+        self.ram_and_rtc_enabled = reader.read_bool()?;
+        self.rom_bank = reader.read_u8()?;
+        self.ram_bank_or_rtc_register = reader.read_u8()?;
+        self.latch_armed = reader.read_bool()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rstest::rstest;
+
+    use super::*;
+
+    fn mbc3(rom_banks: usize, ram_size: usize, has_rtc: bool) -> Mbc3 {
+        let mut rom = vec![0; rom_banks * ROM_BANK_SIZE];
+        for bank in 0..rom_banks {
+            rom[bank * ROM_BANK_SIZE] = bank as u8;
+        }
+        Mbc3::new(rom, vec![0; ram_size], has_rtc)
+    }
+
+    #[rstest]
+    #[case(0x00, 1)]
+    #[case(0x01, 1)]
+    #[case(0x20, 0x20)]
+    #[case(0x7F, 0x7F)]
+    #[case(0xFF, 0x7F)]
+    fn test_switch_rom_bank(#[case] value: u8, #[case] expected_bank: u8) {
+        // Arrange
+        let mut mbc = mbc3(128, 0, false);
+
+        // Act
+        mbc.write_rom(0x2000, value);
+
+        // Assert
+        assert_eq!(mbc.read_rom(0x4000), expected_bank);
+        assert_eq!(mbc.read_rom(0x0000), 0);
+    }
+
+    #[test]
+    fn test_ram_banking() {
+        let mut mbc = mbc3(4, 0x8000, false);
+        mbc.write_rom(0x0000, 0x0A);
+
+        for bank in 0..4 {
+            mbc.write_rom(0x4000, bank);
+            mbc.write_ram(0xA000, bank + 0x10);
+        }
+
+        for bank in 0..4 {
+            mbc.write_rom(0x4000, bank);
+            assert_eq!(mbc.read_ram(0xA000), bank + 0x10);
+        }
+    }
+
+    #[test]
+    fn test_ram_disabled() {
+        let mut mbc = mbc3(4, 0x2000, true);
+
+        mbc.write_ram(0xA000, 0x12);
+
+        assert_eq!(mbc.read_ram(0xA000), 0xFF);
+    }
+
+    #[test]
+    fn test_rtc_registers_need_latch() {
+        // Arrange
+        let mut mbc = mbc3(4, 0x2000, true);
+        mbc.write_rom(0x0000, 0x0A);
+        mbc.write_rom(0x4000, 0x09);
+
+        // Act
+        mbc.write_ram(0xA000, 42);
+        mbc.write_rom(0x6000, 0x00);
+        mbc.write_rom(0x6000, 0x01);
+
+        // Assert
+        assert_eq!(mbc.read_ram(0xA000), 42);
+    }
+
+    #[test]
+    fn test_rtc_select_without_rtc_reads_open_bus() {
+        let mut mbc = mbc3(4, 0x2000, false);
+        mbc.write_rom(0x0000, 0x0A);
+        mbc.write_rom(0x4000, 0x08);
+
+        mbc.write_ram(0xA000, 0x12);
+
+        assert_eq!(mbc.read_ram(0xA000), 0xFF);
+    }
+
+    #[test]
+    fn test_save_data_includes_rtc() {
+        // Arrange
+        let mut mbc = mbc3(2, 0x2000, true);
+        mbc.write_rom(0x0000, 0x0A);
+        mbc.write_rom(0x4000, 0x08);
+        mbc.write_ram(0xA000, 42);
+        mbc.write_rom(0x4000, 0x00);
+        mbc.write_ram(0xA000, 0x12);
+
+        // Act
+        let data = mbc.save_data();
+        let mut loaded = mbc3(2, 0x2000, true);
+        loaded.load_save_data(&data);
+
+        // Assert
+        assert_eq!(data.len(), 0x2000 + RTC_STATE_SIZE);
+        assert_eq!(loaded.ram()[0], 0x12);
+        assert_eq!(
+            loaded.rtc().unwrap().to_bytes(),
+            mbc.rtc().unwrap().to_bytes()
+        );
+    }
+}
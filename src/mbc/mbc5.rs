@@ -0,0 +1,248 @@
+use std::io::Result;
+
+use super::{Mbc, RumbleCallback, RAM_BANK_SIZE, ROM_BANK_SIZE};
+use crate::savestate::{Snapshot, StateReader, StateWriter};
+
+pub struct Mbc5 {
+    rom: Vec<u8>,
+    ram: Vec<u8>,
+    ram_enabled: bool,
+    // 9 bits; unlike MBC1 and MBC3, bank 0 can be mapped into 0x4000-0x7FFF.
+    rom_bank: u16,
+    ram_bank: u8,
+    has_rumble: bool,
+    rumbling: bool,
+    rumble_callback: Option<RumbleCallback>,
+}
+
+impl Mbc5 {
+    pub fn new(rom: Vec<u8>, ram: Vec<u8>, has_rumble: bool) -> Mbc5 {
+        Mbc5 {
+            rom,
+            ram,
+            ram_enabled: false,
+            rom_bank: 1,
+            ram_bank: 0,
+            has_rumble,
+            rumbling: false,
+            rumble_callback: None,
+        }
+    }
+
+    pub fn rumbling(&self) -> bool {
+        self.rumbling
+    }
+
+    fn rom_bank_count(&self) -> usize {
+        (self.rom.len() / ROM_BANK_SIZE).max(1)
+    }
+
+    fn ram_offset(&self, address: u16) -> Option<usize> {
+        if !self.ram_enabled || self.ram.is_empty() {
+            return None;
+        }
+
+        let offset = self.ram_bank as usize * RAM_BANK_SIZE + (address - 0xA000) as usize;
+        Some(offset % self.ram.len())
+    }
+
+    fn set_rumbling(&mut self, rumbling: bool) {
+        if self.rumbling == rumbling {
+            return;
+        }
+
+        self.rumbling = rumbling;
+        if let Some(callback) = self.rumble_callback.as_mut() {
+            callback(rumbling);
+        }
+    }
+}
+
+impl Mbc for Mbc5 {
+    fn read_rom(&self, address: u16) -> u8 {
+        self.rom
+            .get(self.rom_offset(address))
+            .copied()
+            .unwrap_or(0xFF)
+    }
+
+    fn rom_offset(&self, address: u16) -> usize {
+        let bank = match address {
+            0x0000..=0x3FFF => 0,
+            _ => self.rom_bank as usize % self.rom_bank_count(),
+        };
+        bank * ROM_BANK_SIZE + (address as usize & (ROM_BANK_SIZE - 1))
+    }
+
+    fn write_rom(&mut self, address: u16, value: u8) {
+        match address {
+            0x0000..=0x1FFF => self.ram_enabled = value == 0x0A,
+            0x2000..=0x2FFF => self.rom_bank = (self.rom_bank & 0x100) | value as u16,
+            0x3000..=0x3FFF => {
+                self.rom_bank = (self.rom_bank & 0x0FF) | ((value as u16 & 0x01) << 8)
+            }
+            0x4000..=0x5FFF => {
+                // On rumble cartridges bit 3 drives the motor instead of selecting a RAM bank.
+                if self.has_rumble {
+                    self.ram_bank = value & 0x07;
+                    self.set_rumbling(value & 0x08 != 0);
+                } else {
+                    self.ram_bank = value & 0x0F;
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn read_ram(&self, address: u16) -> u8 {
+        match self.ram_offset(address) {
+            Some(offset) => self.ram[offset],
+            None => 0xFF,
+        }
+    }
+
+    fn write_ram(&mut self, address: u16, value: u8) {
+        if let Some(offset) = self.ram_offset(address) {
+            self.ram[offset] = value;
+        }
+    }
+
+    fn ram_readable(&self, address: u16) -> bool {
+        self.ram_offset(address).is_some()
+    }
+
+    fn ram(&self) -> &[u8] {
+        &self.ram
+    }
+
+    fn ram_mut(&mut self) -> &mut [u8] {
+        &mut self.ram
+    }
+
+    fn set_rumble_callback(&mut self, callback: RumbleCallback) {
+        self.rumble_callback = Some(callback);
+    }
+}
+
+impl Snapshot for Mbc5 {
+    fn component(&self) -> &'static str {
+        "MBC5"
+    }
+
+    fn version(&self) -> u8 {
+        1
+    }
+
+    fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_bytes(&self.ram);
+        writer.write_bool(self.ram_enabled);
+        writer.write_u16(self.rom_bank);
+        writer.write_u8(self.ram_bank);
+        writer.write_bool(self.rumbling);
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader) -> Result<()> {
+        reader.read_bytes_into(&mut self.ram)?;
+        self.ram_enabled = reader.read_bool()?;
+        self.rom_bank = reader.read_u16()?;
+        self.ram_bank = reader.read_u8()?;
+        let rumbling = reader.read_bool()?;
+        self.set_rumbling(rumbling);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    use rstest::rstest;
+
+    use super::*;
+
+    fn mbc5(rom_banks: usize, ram_size: usize, has_rumble: bool) -> Mbc5 {
+        let mut rom = vec![0; rom_banks * ROM_BANK_SIZE];
+        for bank in 0..rom_banks {
+            rom[bank * ROM_BANK_SIZE] = bank as u8;
+            rom[bank * ROM_BANK_SIZE + 1] = (bank >> 8) as u8;
+        }
+        Mbc5::new(rom, vec![0; ram_size], has_rumble)
+    }
+
+    #[rstest]
+    #[case(0x00, 0x00, 0x000)]
+    #[case(0x01, 0x00, 0x001)]
+    #[case(0xFF, 0x00, 0x0FF)]
+    #[case(0x00, 0x01, 0x100)]
+    #[case(0xFF, 0x01, 0x1FF)]
+    fn test_switch_rom_bank(#[case] low: u8, #[case] high: u8, #[case] expected_bank: u16) {
+        // Arrange
+        let mut mbc = mbc5(512, 0, false);
+
+        // Act
+        mbc.write_rom(0x2000, low);
+        mbc.write_rom(0x3000, high);
+
+        // Assert
+        let bank = u16::from_le_bytes([mbc.read_rom(0x4000), mbc.read_rom(0x4001)]);
+        assert_eq!(bank, expected_bank);
+    }
+
+    #[test]
+    fn test_ram_banking() {
+        let mut mbc = mbc5(4, 0x20000, false);
+        mbc.write_rom(0x0000, 0x0A);
+
+        mbc.write_rom(0x4000, 0x0F);
+        mbc.write_ram(0xA000, 0x0F);
+        mbc.write_rom(0x4000, 0x00);
+        mbc.write_ram(0xA000, 0x01);
+
+        mbc.write_rom(0x4000, 0x0F);
+        assert_eq!(mbc.read_ram(0xA000), 0x0F);
+    }
+
+    #[rstest]
+    #[case(0x0A, true)]
+    #[case(0x1A, false)]
+    #[case(0x00, false)]
+    fn test_ram_enable(#[case] value: u8, #[case] enabled: bool) {
+        let mut mbc = mbc5(4, 0x2000, false);
+        mbc.write_rom(0x0000, 0x0A);
+        mbc.write_ram(0xA000, 0x12);
+
+        mbc.write_rom(0x0000, value);
+
+        assert_eq!(mbc.read_ram(0xA000) == 0x12, enabled);
+    }
+
+    #[test]
+    fn test_rumble_callback() {
+        // Arrange
+        let mut mbc = mbc5(4, 0x8000, true);
+        let events = Rc::new(RefCell::new(Vec::new()));
+        let recorded = events.clone();
+        mbc.set_rumble_callback(Box::new(move |on| recorded.borrow_mut().push(on)));
+
+        // Act
+        mbc.write_rom(0x4000, 0x08);
+        mbc.write_rom(0x4000, 0x09);
+        mbc.write_rom(0x4000, 0x01);
+
+        // Assert
+        assert_eq!(*events.borrow(), vec![true, false]);
+        assert!(!mbc.rumbling());
+    }
+
+    #[test]
+    fn test_rumble_bit_does_not_select_ram_bank() {
+        let mut mbc = mbc5(4, 0x8000, true);
+        mbc.write_rom(0x0000, 0x0A);
+        mbc.write_ram(0xA000, 0x33);
+
+        mbc.write_rom(0x4000, 0x08);
+
+        assert_eq!(mbc.read_ram(0xA000), 0x33);
+    }
+}
@@ -0,0 +1,641 @@
+use std::cell::Cell;
+use std::io::{Error, Result};
+use std::rc::Rc;
+
+use super::{Mbc, ROM_BANK_SIZE};
+use crate::savestate::{Snapshot, StateReader, StateWriter};
+
+// Whatever tells the MBC7 which way the Game Boy is held, in g. X grows as the right side goes
+// down and Y as the top comes toward the player.
+pub trait Accelerometer {
+    fn tilt(&mut self) -> (f32, f32);
+}
+
+// An accelerometer a frontend tilts from a stick or keys. Clones share the same tilt, so one can
+// go in the cartridge and another stay with the input handling.
+#[derive(Clone, Default)]
+pub struct Tilt(Rc<Cell<(f32, f32)>>);
+
+impl Tilt {
+    pub fn get(&self) -> (f32, f32) {
+        self.0.get()
+    }
+
+    // Clamped to the ±1 g of a cartridge held straight up or on its side.
+    pub fn set(&self, x: f32, y: f32) {
+        self.0.set((x.clamp(-1.0, 1.0), y.clamp(-1.0, 1.0)));
+    }
+}
+
+impl Accelerometer for Tilt {
+    fn tilt(&mut self) -> (f32, f32) {
+        self.get()
+    }
+}
+
+// What the 93LC56 is in the middle of.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum EepromState {
+    // Waiting for a 1 to start a command.
+    Idle,
+    // The opcode and address so far.
+    Command {
+        bits: u8,
+        value: u16,
+    },
+    // Shifting out words from `address` on.
+    Read {
+        address: u8,
+        bit: u8,
+    },
+    // The data for one word, or all of them without an address.
+    Write {
+        address: Option<u8>,
+        bits: u8,
+        value: u16,
+    },
+}
+
+// The 93LC56 serial EEPROM the MBC7 saves to: 128 16-bit words the game talks to a bit at a time
+// through chip select, clock, data in and data out. Commands start with a 1 followed by 2 bits of
+// opcode and 8 of address, all sampled when the clock rises.
+struct Eeprom {
+    // The words, low byte first.
+    data: Vec<u8>,
+    select: bool,
+    clock: bool,
+    data_in: bool,
+    data_out: bool,
+    write_enabled: bool,
+    state: EepromState,
+}
+
+impl Eeprom {
+    fn new() -> Eeprom {
+        Eeprom {
+            data: vec![0xFF; EEPROM_SIZE],
+            select: false,
+            clock: false,
+            data_in: false,
+            data_out: true,
+            write_enabled: false,
+            state: EepromState::Idle,
+        }
+    }
+
+    fn read(&self) -> u8 {
+        let mut value = 0;
+        for (bit, set) in [
+            (EEPROM_SELECT, self.select),
+            (EEPROM_CLOCK, self.clock),
+            (EEPROM_DATA_IN, self.data_in),
+            (EEPROM_DATA_OUT, self.data_out),
+        ] {
+            if set {
+                value |= bit;
+            }
+        }
+        value
+    }
+
+    fn write(&mut self, value: u8) {
+        let (select, clock) = (value & EEPROM_SELECT != 0, value & EEPROM_CLOCK != 0);
+        self.data_in = value & EEPROM_DATA_IN != 0;
+        // Dropping chip select abandons whatever command was going on.
+        if !select {
+            self.state = EepromState::Idle;
+        } else if clock && !self.clock {
+            self.clock_in(self.data_in);
+        }
+        self.select = select;
+        self.clock = clock;
+    }
+
+    fn clock_in(&mut self, input: bool) {
+        self.state = match self.state {
+            EepromState::Idle if input => EepromState::Command { bits: 0, value: 0 },
+            EepromState::Idle => EepromState::Idle,
+            EepromState::Command { bits, value } => {
+                let value = (value << 1) | input as u16;
+                if bits + 1 == COMMAND_BITS {
+                    self.run(value)
+                } else {
+                    EepromState::Command {
+                        bits: bits + 1,
+                        value,
+                    }
+                }
+            }
+            EepromState::Read { address, bit } => {
+                self.data_out = self.word(address) & (0x8000 >> bit) != 0;
+                // Reading on past a word carries on with the next one.
+                if bit == 15 {
+                    EepromState::Read {
+                        address: (address + 1) % EEPROM_WORDS,
+                        bit: 0,
+                    }
+                } else {
+                    EepromState::Read {
+                        address,
+                        bit: bit + 1,
+                    }
+                }
+            }
+            EepromState::Write {
+                address,
+                bits,
+                value,
+            } => {
+                let value = (value << 1) | input as u16;
+                if bits + 1 < 16 {
+                    EepromState::Write {
+                        address,
+                        bits: bits + 1,
+                        value,
+                    }
+                } else {
+                    match address {
+                        Some(address) => self.set_word(address, value),
+                        None => (0..EEPROM_WORDS).for_each(|address| self.set_word(address, value)),
+                    }
+                    self.data_out = true;
+                    EepromState::Idle
+                }
+            }
+        };
+    }
+
+    fn run(&mut self, command: u16) -> EepromState {
+        let address = (command & 0x7F) as u8;
+        match (command >> 8) & 0x03 {
+            0b10 => {
+                // A dummy 0 comes before the data.
+                self.data_out = false;
+                return EepromState::Read { address, bit: 0 };
+            }
+            0b01 => {
+                return EepromState::Write {
+                    address: Some(address),
+                    bits: 0,
+                    value: 0,
+                }
+            }
+            0b11 => self.set_word(address, 0xFFFF),
+            _ => match (command >> 6) & 0x03 {
+                0b11 => self.write_enabled = true,
+                0b00 => self.write_enabled = false,
+                0b10 => (0..EEPROM_WORDS).for_each(|address| self.set_word(address, 0xFFFF)),
+                _ => {
+                    return EepromState::Write {
+                        address: None,
+                        bits: 0,
+                        value: 0,
+                    }
+                }
+            },
+        }
+        self.data_out = true;
+        EepromState::Idle
+    }
+
+    fn word(&self, address: u8) -> u16 {
+        let offset = address as usize * 2;
+        u16::from_le_bytes([self.data[offset], self.data[offset + 1]])
+    }
+
+    // Writes and erases only go through after EWEN.
+    fn set_word(&mut self, address: u8, value: u16) {
+        if self.write_enabled {
+            let offset = address as usize * 2;
+            self.data[offset..offset + 2].copy_from_slice(&value.to_le_bytes());
+        }
+    }
+
+    fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_bytes(&self.data);
+        for flag in [
+            self.select,
+            self.clock,
+            self.data_in,
+            self.data_out,
+            self.write_enabled,
+        ] {
+            writer.write_bool(flag);
+        }
+        let (kind, address, bits, value) = match self.state {
+            EepromState::Idle => (0, 0, 0, 0),
+            EepromState::Command { bits, value } => (1, 0, bits, value),
+            EepromState::Read { address, bit } => (2, address, bit, 0),
+            EepromState::Write {
+                address: Some(address),
+                bits,
+                value,
+            } => (3, address, bits, value),
+            EepromState::Write {
+                address: None,
+                bits,
+                value,
+            } => (4, 0, bits, value),
+        };
+        writer.write_u8(kind);
+        writer.write_u8(address);
+        writer.write_u8(bits);
+        writer.write_u16(value);
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader) -> Result<()> {
+        reader.read_bytes_into(&mut self.data)?;
+        for flag in [
+            &mut self.select,
+            &mut self.clock,
+            &mut self.data_in,
+            &mut self.data_out,
+            &mut self.write_enabled,
+        ] {
+            *flag = reader.read_bool()?;
+        }
+        let kind = reader.read_u8()?;
+        let address = reader.read_u8()? % EEPROM_WORDS;
+        let bits = reader.read_u8()?;
+        let value = reader.read_u16()?;
+        self.state = match kind {
+            0 => EepromState::Idle,
+            1 => EepromState::Command {
+                bits: bits.min(COMMAND_BITS - 1),
+                value,
+            },
+            2 => EepromState::Read {
+                address,
+                bit: bits % 16,
+            },
+            3 | 4 => EepromState::Write {
+                address: (kind == 3).then_some(address),
+                bits: bits % 16,
+                value,
+            },
+            _ => {
+                return Err(Error::other(format!(
+                    "{} is an invalid EEPROM state in the save state.",
+                    kind
+                )))
+            }
+        };
+        Ok(())
+    }
+}
+
+// Kirby Tilt 'n' Tumble's controller: ROM banking, an accelerometer that's latched and read
+// through 0xA000-0xAFFF and the EEPROM in place of RAM. RAM access needs both enable registers.
+pub struct Mbc7 {
+    rom: Vec<u8>,
+    ram_enabled: bool,
+    ram_enabled_2: bool,
+    rom_bank: u8,
+    // Writing 0x55 resets the latch and 0xAA then latches the accelerometer once.
+    erased: bool,
+    x: u16,
+    y: u16,
+    accelerometer: Option<Box<dyn Accelerometer>>,
+    eeprom: Eeprom,
+}
+
+impl Mbc7 {
+    pub fn new(rom: Vec<u8>) -> Mbc7 {
+        Mbc7 {
+            rom,
+            ram_enabled: false,
+            ram_enabled_2: false,
+            rom_bank: 1,
+            erased: false,
+            x: LATCH_ERASED,
+            y: LATCH_ERASED,
+            accelerometer: None,
+            eeprom: Eeprom::new(),
+        }
+    }
+
+    fn rom_bank_count(&self) -> usize {
+        (self.rom.len() / ROM_BANK_SIZE).max(1)
+    }
+
+    fn registers_enabled(&self, address: u16) -> bool {
+        self.ram_enabled && self.ram_enabled_2 && address < 0xB000
+    }
+
+    fn latch(&mut self) {
+        let (x, y) = self
+            .accelerometer
+            .as_mut()
+            .map_or((0.0, 0.0), |accelerometer| accelerometer.tilt());
+        let axis = |g: f32| (LATCH_CENTER as f32 + g.clamp(-1.0, 1.0) * LATCH_PER_G) as u16;
+        self.x = axis(x);
+        self.y = axis(y);
+    }
+}
+
+impl Mbc for Mbc7 {
+    fn read_rom(&self, address: u16) -> u8 {
+        self.rom
+            .get(self.rom_offset(address))
+            .copied()
+            .unwrap_or(0xFF)
+    }
+
+    fn rom_offset(&self, address: u16) -> usize {
+        let bank = match address {
+            0x0000..=0x3FFF => 0,
+            _ => self.rom_bank as usize % self.rom_bank_count(),
+        };
+        bank * ROM_BANK_SIZE + (address as usize & (ROM_BANK_SIZE - 1))
+    }
+
+    fn write_rom(&mut self, address: u16, value: u8) {
+        match address {
+            0x0000..=0x1FFF => self.ram_enabled = value == 0x0A,
+            0x2000..=0x3FFF => self.rom_bank = value & 0x7F,
+            0x4000..=0x5FFF => self.ram_enabled_2 = value == 0x40,
+            _ => {}
+        }
+    }
+
+    fn read_ram(&self, address: u16) -> u8 {
+        if !self.registers_enabled(address) {
+            return 0xFF;
+        }
+        match (address >> 4) & 0x0F {
+            0x2 => self.x as u8,
+            0x3 => (self.x >> 8) as u8,
+            0x4 => self.y as u8,
+            0x5 => (self.y >> 8) as u8,
+            0x6 => 0x00,
+            0x8 => self.eeprom.read(),
+            _ => 0xFF,
+        }
+    }
+
+    fn write_ram(&mut self, address: u16, value: u8) {
+        if !self.registers_enabled(address) {
+            return;
+        }
+        match (address >> 4) & 0x0F {
+            0x0 if value == 0x55 => {
+                self.erased = true;
+                self.x = LATCH_ERASED;
+                self.y = LATCH_ERASED;
+            }
+            0x1 if value == 0xAA && self.erased => {
+                self.erased = false;
+                self.latch();
+            }
+            0x8 => self.eeprom.write(value),
+            _ => {}
+        }
+    }
+
+    fn ram_readable(&self, address: u16) -> bool {
+        self.registers_enabled(address)
+    }
+
+    fn ram(&self) -> &[u8] {
+        &self.eeprom.data
+    }
+
+    fn ram_mut(&mut self) -> &mut [u8] {
+        &mut self.eeprom.data
+    }
+
+    fn set_accelerometer(&mut self, accelerometer: Option<Box<dyn Accelerometer>>) {
+        self.accelerometer = accelerometer;
+    }
+}
+
+impl Snapshot for Mbc7 {
+    fn component(&self) -> &'static str {
+        "MBC7"
+    }
+
+    fn version(&self) -> u8 {
+        1
+    }
+
+    fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_bool(self.ram_enabled);
+        writer.write_bool(self.ram_enabled_2);
+        writer.write_u8(self.rom_bank);
+        writer.write_bool(self.erased);
+        writer.write_u16(self.x);
+        writer.write_u16(self.y);
+        self.eeprom.save_state(writer);
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader) -> Result<()> {
+        self.ram_enabled = reader.read_bool()?;
+        self.ram_enabled_2 = reader.read_bool()?;
+        self.rom_bank = reader.read_u8()? & 0x7F;
+        self.erased = reader.read_bool()?;
+        self.x = reader.read_u16()?;
+        self.y = reader.read_u16()?;
+        self.eeprom.load_state(reader)
+    }
+}
+
+const EEPROM_SIZE: usize = 0x100;
+
+const EEPROM_WORDS: u8 = (EEPROM_SIZE / 2) as u8;
+
+// 2 bits of opcode and 8 of address after the start bit.
+const COMMAND_BITS: u8 = 10;
+
+const EEPROM_SELECT: u8 = 0x80;
+
+const EEPROM_CLOCK: u8 = 0x40;
+
+const EEPROM_DATA_IN: u8 = 0x02;
+
+const EEPROM_DATA_OUT: u8 = 0x01;
+
+// What the accelerometer reads lying flat, and how far 1 g moves it.
+const LATCH_CENTER: u16 = 0x81D0;
+
+const LATCH_PER_G: f32 = 0x70 as f32;
+
+const LATCH_ERASED: u16 = 0x8000;
+
+#[cfg(test)]
+mod tests {
+    use rstest::rstest;
+
+    use super::*;
+
+    fn mbc7() -> Mbc7 {
+        let mut rom = vec![0; 8 * ROM_BANK_SIZE];
+        for bank in 0..8 {
+            rom[bank * ROM_BANK_SIZE] = bank as u8;
+        }
+        let mut mbc = Mbc7::new(rom);
+        mbc.write_rom(0x0000, 0x0A);
+        mbc.write_rom(0x4000, 0x40);
+        mbc
+    }
+
+    // Clocks bits into the EEPROM with chip select held high, returning data out after each one.
+    fn send(mbc: &mut Mbc7, bits: &[bool]) -> Vec<bool> {
+        bits.iter()
+            .map(|&bit| {
+                let data = if bit { EEPROM_DATA_IN } else { 0 };
+                mbc.write_ram(0xA080, EEPROM_SELECT | data);
+                mbc.write_ram(0xA080, EEPROM_SELECT | EEPROM_CLOCK | data);
+                mbc.read_ram(0xA080) & EEPROM_DATA_OUT != 0
+            })
+            .collect()
+    }
+
+    fn bits(value: u32, count: usize) -> Vec<bool> {
+        (0..count)
+            .rev()
+            .map(|bit| value & (1 << bit) != 0)
+            .collect()
+    }
+
+    // A start bit, the opcode, the address and any data, then chip select dropped.
+    fn command(mbc: &mut Mbc7, opcode: u32, address: u32, data: Option<u16>) -> Vec<bool> {
+        let mut sent = vec![true];
+        sent.extend(bits((opcode << 8) | address, 10));
+        if let Some(data) = data {
+            sent.extend(bits(data as u32, 16));
+        }
+        let received = send(mbc, &sent);
+        mbc.write_ram(0xA080, 0x00);
+        received
+    }
+
+    fn read_word(mbc: &mut Mbc7, address: u32) -> u16 {
+        let mut sent = vec![true];
+        sent.extend(bits((0b10 << 8) | address, 10));
+        send(mbc, &sent);
+        let received = send(mbc, &[false; 16]);
+        mbc.write_ram(0xA080, 0x00);
+        received
+            .iter()
+            .fold(0, |word, &bit| (word << 1) | bit as u16)
+    }
+
+    #[test]
+    fn test_switch_rom_bank() {
+        let mut mbc = mbc7();
+
+        mbc.write_rom(0x2000, 0x05);
+
+        assert_eq!(mbc.read_rom(0x4000), 0x05);
+    }
+
+    #[rstest]
+    #[case(0x0A, 0x40, true)]
+    #[case(0x0A, 0x00, false)]
+    #[case(0x00, 0x40, false)]
+    fn test_ram_enable(#[case] first: u8, #[case] second: u8, #[case] enabled: bool) {
+        let mut mbc = mbc7();
+
+        mbc.write_rom(0x0000, first);
+        mbc.write_rom(0x4000, second);
+
+        assert_eq!(mbc.read_ram(0xA060) == 0x00, enabled);
+    }
+
+    #[rstest]
+    #[case((0.0, 0.0), 0x81D0, 0x81D0)]
+    #[case((1.0, -0.5), 0x8240, 0x8198)]
+    #[case((3.0, 0.0), 0x8240, 0x81D0)]
+    fn test_latch_accelerometer(
+        #[case] tilt: (f32, f32),
+        #[case] expected_x: u16,
+        #[case] expected_y: u16,
+    ) {
+        // Arrange
+        let mut mbc = mbc7();
+        let accelerometer = Tilt::default();
+        accelerometer.set(tilt.0, tilt.1);
+        mbc.set_accelerometer(Some(Box::new(accelerometer)));
+
+        // Act
+        mbc.write_ram(0xA000, 0x55);
+        mbc.write_ram(0xA010, 0xAA);
+
+        // Assert
+        let x = u16::from_le_bytes([mbc.read_ram(0xA020), mbc.read_ram(0xA030)]);
+        let y = u16::from_le_bytes([mbc.read_ram(0xA040), mbc.read_ram(0xA050)]);
+        assert_eq!((x, y), (expected_x, expected_y));
+    }
+
+    #[test]
+    fn test_latch_needs_erase() {
+        let mut mbc = mbc7();
+        mbc.write_ram(0xA000, 0x55);
+        mbc.write_ram(0xA010, 0xAA);
+        let accelerometer = Tilt::default();
+        accelerometer.set(1.0, 1.0);
+        mbc.set_accelerometer(Some(Box::new(accelerometer)));
+
+        mbc.write_ram(0xA010, 0xAA);
+
+        assert_eq!(mbc.read_ram(0xA020), 0xD0);
+    }
+
+    #[test]
+    fn test_eeprom_write_and_read() {
+        // Arrange
+        let mut mbc = mbc7();
+        command(&mut mbc, 0b00, 0xC0, None);
+
+        // Act
+        let written = command(&mut mbc, 0b01, 0x12, Some(0xBEEF));
+
+        // Assert
+        assert!(written.last().unwrap());
+        assert_eq!(read_word(&mut mbc, 0x12), 0xBEEF);
+        assert_eq!(mbc.ram()[0x24..0x26], [0xEF, 0xBE]);
+    }
+
+    #[test]
+    fn test_eeprom_write_protected() {
+        let mut mbc = mbc7();
+
+        command(&mut mbc, 0b01, 0x12, Some(0xBEEF));
+
+        assert_eq!(read_word(&mut mbc, 0x12), 0xFFFF);
+    }
+
+    #[test]
+    fn test_eeprom_erase_all() {
+        // Arrange
+        let mut mbc = mbc7();
+        mbc.ram_mut().fill(0x00);
+        command(&mut mbc, 0b00, 0xC0, None);
+
+        // Act
+        command(&mut mbc, 0b00, 0x80, None);
+
+        // Assert
+        assert!(mbc.ram().iter().all(|&byte| byte == 0xFF));
+    }
+
+    #[test]
+    fn test_save_state() {
+        // Arrange
+        let mut mbc = mbc7();
+        mbc.write_rom(0x2000, 0x03);
+        command(&mut mbc, 0b00, 0xC0, None);
+        command(&mut mbc, 0b01, 0x01, Some(0x1234));
+        let mut writer = StateWriter::new();
+        mbc.save_state(&mut writer);
+        let data = writer.into_bytes();
+
+        // Act
+        let mut loaded = mbc7();
+        loaded.load_state(&mut StateReader::new(&data)).unwrap();
+
+        // Assert
+        assert_eq!(loaded.read_rom(0x4000), 0x03);
+        assert_eq!(read_word(&mut loaded, 0x01), 0x1234);
+    }
+}
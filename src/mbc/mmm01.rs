@@ -0,0 +1,307 @@
+use std::io::Result;
+
+use super::{Mbc, RAM_BANK_SIZE, ROM_BANK_SIZE};
+use crate::savestate::{Snapshot, StateReader, StateWriter};
+
+// The multicart controller. It powers on unmapped, showing the menu in the last 32 KB of ROM; the
+// menu then writes the selected game's outer bank bits and masks and maps it, after which the
+// registers behave like a restricted MBC1 until the next power cycle.
+pub struct Mmm01 {
+    rom: Vec<u8>,
+    ram: Vec<u8>,
+    mapped: bool,
+    ram_enabled: bool,
+    // ROM bank bits 0-4, 5-6 and 7-8.
+    rom_bank_low: u8,
+    rom_bank_mid: u8,
+    rom_bank_high: u8,
+    // Bits 1-4 of the low ROM bank that the game can't change once mapped.
+    rom_bank_mask: u8,
+    ram_bank_low: u8,
+    ram_bank_high: u8,
+    // Bits of the low RAM bank that the game can't change once mapped.
+    ram_bank_mask: u8,
+    advanced_banking: bool,
+    advanced_banking_locked: bool,
+}
+
+impl Mmm01 {
+    pub fn new(rom: Vec<u8>, ram: Vec<u8>) -> Mmm01 {
+        Mmm01 {
+            rom,
+            ram,
+            mapped: false,
+            ram_enabled: false,
+            rom_bank_low: 0,
+            rom_bank_mid: 0,
+            rom_bank_high: 0,
+            rom_bank_mask: 0,
+            ram_bank_low: 0,
+            ram_bank_high: 0,
+            ram_bank_mask: 0,
+            advanced_banking: false,
+            advanced_banking_locked: false,
+        }
+    }
+
+    fn rom_bank_count(&self) -> usize {
+        (self.rom.len() / ROM_BANK_SIZE).max(1)
+    }
+
+    fn rom_bank_offset(&self, bank: usize, address: u16) -> usize {
+        let bank = bank % self.rom_bank_count();
+        bank * ROM_BANK_SIZE + (address as usize & (ROM_BANK_SIZE - 1))
+    }
+
+    fn outer_rom_bank(&self) -> usize {
+        ((self.rom_bank_high as usize) << 7) | ((self.rom_bank_mid as usize) << 5)
+    }
+
+    // The low ROM bank bits the mask fixes in place.
+    fn fixed_rom_bits(&self) -> u8 {
+        self.rom_bank_mask << 1
+    }
+
+    fn ram_offset(&self, address: u16) -> Option<usize> {
+        if !self.ram_enabled || self.ram.is_empty() {
+            return None;
+        }
+
+        let low = if self.advanced_banking {
+            self.ram_bank_low
+        } else {
+            0
+        };
+        let bank = ((self.ram_bank_high as usize) << 2) | low as usize;
+        let offset = bank * RAM_BANK_SIZE + (address - 0xA000) as usize;
+        Some(offset % self.ram.len())
+    }
+}
+
+impl Mbc for Mmm01 {
+    fn read_rom(&self, address: u16) -> u8 {
+        self.rom
+            .get(self.rom_offset(address))
+            .copied()
+            .unwrap_or(0xFF)
+    }
+
+    fn rom_offset(&self, address: u16) -> usize {
+        // Unmapped, every bank bit except the lowest reads as 1, which maps the last 32 KB.
+        if !self.mapped {
+            let bank = match address {
+                0x0000..=0x3FFF => UNMAPPED_BANK,
+                _ => UNMAPPED_BANK | 1,
+            };
+            return self.rom_bank_offset(bank, address);
+        }
+
+        let fixed = self.rom_bank_low & self.fixed_rom_bits();
+        match address {
+            0x0000..=0x3FFF => {
+                self.rom_bank_offset(self.outer_rom_bank() | fixed as usize, address)
+            }
+            _ => {
+                // Like MBC1, bank 0 becomes 1, but only the bits the game controls count.
+                let mut low = self.rom_bank_low;
+                if low & !self.fixed_rom_bits() == 0 {
+                    low |= 1;
+                }
+                self.rom_bank_offset(self.outer_rom_bank() | low as usize, address)
+            }
+        }
+    }
+
+    fn write_rom(&mut self, address: u16, value: u8) {
+        match address {
+            0x0000..=0x1FFF => {
+                self.ram_enabled = value & 0x0F == 0x0A;
+                if !self.mapped {
+                    self.ram_bank_mask = (value >> 4) & 0x03;
+                    self.mapped = value & 0x40 != 0;
+                }
+            }
+            0x2000..=0x3FFF if self.mapped => {
+                let fixed = self.fixed_rom_bits();
+                self.rom_bank_low = (self.rom_bank_low & fixed) | (value & 0x1F & !fixed);
+            }
+            0x2000..=0x3FFF => {
+                self.rom_bank_low = value & 0x1F;
+                self.rom_bank_mid = (value >> 5) & 0x03;
+            }
+            0x4000..=0x5FFF if self.mapped => {
+                let fixed = self.ram_bank_mask;
+                self.ram_bank_low = (self.ram_bank_low & fixed) | (value & 0x03 & !fixed);
+            }
+            0x4000..=0x5FFF => {
+                self.ram_bank_low = value & 0x03;
+                self.ram_bank_high = (value >> 2) & 0x03;
+                self.rom_bank_high = (value >> 4) & 0x03;
+                self.advanced_banking_locked = value & 0x40 != 0;
+            }
+            _ => {
+                if !self.advanced_banking_locked {
+                    self.advanced_banking = value & 0x01 != 0;
+                }
+                if !self.mapped {
+                    self.rom_bank_mask = (value >> 2) & 0x0F;
+                }
+            }
+        }
+    }
+
+    fn read_ram(&self, address: u16) -> u8 {
+        match self.ram_offset(address) {
+            Some(offset) => self.ram[offset],
+            None => 0xFF,
+        }
+    }
+
+    fn write_ram(&mut self, address: u16, value: u8) {
+        if let Some(offset) = self.ram_offset(address) {
+            self.ram[offset] = value;
+        }
+    }
+
+    fn ram_readable(&self, address: u16) -> bool {
+        self.ram_offset(address).is_some()
+    }
+
+    fn ram(&self) -> &[u8] {
+        &self.ram
+    }
+
+    fn ram_mut(&mut self) -> &mut [u8] {
+        &mut self.ram
+    }
+}
+
+impl Snapshot for Mmm01 {
+    fn component(&self) -> &'static str {
+        "MMM01"
+    }
+
+    fn version(&self) -> u8 {
+        1
+    }
+
+    fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_bytes(&self.ram);
+        writer.write_bool(self.mapped);
+        writer.write_bool(self.ram_enabled);
+        writer.write_u8(self.rom_bank_low);
+        writer.write_u8(self.rom_bank_mid);
+        writer.write_u8(self.rom_bank_high);
+        writer.write_u8(self.rom_bank_mask);
+        writer.write_u8(self.ram_bank_low);
+        writer.write_u8(self.ram_bank_high);
+        writer.write_u8(self.ram_bank_mask);
+        writer.write_bool(self.advanced_banking);
+        writer.write_bool(self.advanced_banking_locked);
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader) -> Result<()> {
+        reader.read_bytes_into(&mut self.ram)?;
+        self.mapped = reader.read_bool()?;
+        self.ram_enabled = reader.read_bool()?;
+        self.rom_bank_low = reader.read_u8()?;
+        self.rom_bank_mid = reader.read_u8()?;
+        self.rom_bank_high = reader.read_u8()?;
+        self.rom_bank_mask = reader.read_u8()?;
+        self.ram_bank_low = reader.read_u8()?;
+        self.ram_bank_high = reader.read_u8()?;
+        self.ram_bank_mask = reader.read_u8()?;
+        self.advanced_banking = reader.read_bool()?;
+        self.advanced_banking_locked = reader.read_bool()?;
+        Ok(())
+    }
+}
+
+// ROM bank bits 1-8 all set.
+const UNMAPPED_BANK: usize = 0x1FE;
+
+#[cfg(test)]
+mod tests {
+    use rstest::rstest;
+
+    use super::*;
+
+    fn mmm01(rom_banks: usize, ram_size: usize) -> Mmm01 {
+        let mut rom = vec![0; rom_banks * ROM_BANK_SIZE];
+        for bank in 0..rom_banks {
+            rom[bank * ROM_BANK_SIZE] = bank as u8;
+        }
+        Mmm01::new(rom, vec![0; ram_size])
+    }
+
+    #[test]
+    fn test_unmapped_shows_last_32_kb() {
+        let mut mmm01 = mmm01(64, 0);
+
+        mmm01.write_rom(0x2000, 0x05);
+
+        assert_eq!(mmm01.read_rom(0x0000), 62);
+        assert_eq!(mmm01.read_rom(0x4000), 63);
+    }
+
+    #[test]
+    fn test_mapping_selects_game() {
+        // Arrange
+        let mut mmm01 = mmm01(64, 0);
+        // Game at bank 0x20, 8 banks long: bits 3-4 fixed, bits 0-2 left to the game.
+        mmm01.write_rom(0x2000, 0x20);
+        mmm01.write_rom(0x6000, 0x30);
+
+        // Act
+        mmm01.write_rom(0x0000, 0x40);
+
+        // Assert
+        assert_eq!(mmm01.read_rom(0x0000), 0x20);
+        assert_eq!(mmm01.read_rom(0x4000), 0x21);
+    }
+
+    #[rstest]
+    #[case(0x00, 0x09)]
+    #[case(0x03, 0x0B)]
+    #[case(0x07, 0x0F)]
+    #[case(0x1F, 0x0F)]
+    fn test_mask_fixes_bank_bits(#[case] value: u8, #[case] expected_bank: u8) {
+        let mut mmm01 = mmm01(64, 0);
+        mmm01.write_rom(0x2000, 0x08);
+        mmm01.write_rom(0x6000, 0x30);
+        mmm01.write_rom(0x0000, 0x40);
+
+        mmm01.write_rom(0x2000, value);
+
+        assert_eq!(mmm01.read_rom(0x4000), expected_bank);
+        assert_eq!(mmm01.read_rom(0x0000), 0x08);
+    }
+
+    #[test]
+    fn test_configuration_locked_once_mapped() {
+        let mut mmm01 = mmm01(64, 0);
+        mmm01.write_rom(0x2000, 0x20);
+        mmm01.write_rom(0x0000, 0x40);
+
+        mmm01.write_rom(0x0000, 0x00);
+        mmm01.write_rom(0x2000, 0x60);
+        mmm01.write_rom(0x4000, 0x30);
+
+        assert_eq!(mmm01.read_rom(0x0000), 0x20);
+    }
+
+    #[test]
+    fn test_ram_banking() {
+        let mut mmm01 = mmm01(4, 0x8000);
+        mmm01.write_rom(0x0000, 0x4A);
+        mmm01.write_rom(0x6000, 0x01);
+
+        mmm01.write_rom(0x4000, 0x02);
+        mmm01.write_ram(0xA000, 0x22);
+        mmm01.write_rom(0x4000, 0x00);
+
+        assert_eq!(mmm01.read_ram(0xA000), 0x00);
+        mmm01.write_rom(0x4000, 0x02);
+        assert_eq!(mmm01.read_ram(0xA000), 0x22);
+    }
+}
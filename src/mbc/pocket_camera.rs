@@ -0,0 +1,431 @@
+use std::io::Result;
+
+use super::{Mbc, RAM_BANK_SIZE, ROM_BANK_SIZE};
+use crate::camera::{CameraSource, TestPattern, CAMERA_HEIGHT, CAMERA_WIDTH};
+use crate::savestate::{Snapshot, StateReader, StateWriter};
+
+// The Game Boy Camera's controller: MBC3-like banking of 1 MB of ROM and 128 KB of RAM, plus the
+// M64282FP sensor's registers mapped in place of RAM when bit 4 of the RAM bank is set. A
+// capture takes a picture from the `CameraSource`, exposes, sharpens and dithers it to 2 bits per
+// pixel and leaves it as tiles in RAM bank 0 for the game to copy.
+pub struct PocketCamera {
+    rom: Vec<u8>,
+    ram: Vec<u8>,
+    ram_enabled: bool,
+    rom_bank: u8,
+    ram_bank: u8,
+    registers: [u8; REGISTER_COUNT],
+    // Left of the capture in progress, 0 when the sensor is idle.
+    capture_cycles: u64,
+    source: Option<Box<dyn CameraSource>>,
+}
+
+impl PocketCamera {
+    pub fn new(rom: Vec<u8>) -> PocketCamera {
+        PocketCamera {
+            rom,
+            ram: vec![0; RAM_SIZE],
+            ram_enabled: false,
+            rom_bank: 1,
+            ram_bank: 0,
+            registers: [0; REGISTER_COUNT],
+            capture_cycles: 0,
+            source: None,
+        }
+    }
+
+    pub fn capturing(&self) -> bool {
+        self.capture_cycles > 0
+    }
+
+    fn rom_bank_count(&self) -> usize {
+        (self.rom.len() / ROM_BANK_SIZE).max(1)
+    }
+
+    fn registers_mapped(&self) -> bool {
+        self.ram_bank & 0x10 != 0
+    }
+
+    fn ram_offset(&self, address: u16) -> usize {
+        (self.ram_bank as usize & 0x0F) * RAM_BANK_SIZE + (address - 0xA000) as usize
+    }
+
+    fn exposure(&self) -> u16 {
+        u16::from_be_bytes([self.registers[2], self.registers[3]])
+    }
+
+    fn start_capture(&mut self) {
+        // The sensor is read out at a fixed rate and then held for the exposure time. The N bit
+        // saves a little of the readout.
+        let readout = if self.registers[1] & 0x80 != 0 {
+            CAPTURE_CYCLES
+        } else {
+            CAPTURE_CYCLES + NO_N_CYCLES
+        };
+        self.capture_cycles = readout + self.exposure() as u64 * EXPOSURE_CYCLES;
+    }
+
+    fn finish_capture(&mut self) {
+        self.registers[0] &= !CAPTURE_BUSY;
+        let image = match self.source.as_mut() {
+            Some(source) => source.capture(),
+            None => TestPattern.capture(),
+        };
+
+        let exposure = self.exposure() as f32 / EXPOSURE_NEUTRAL;
+        let exposed: Vec<f32> = (0..CAMERA_WIDTH * CAMERA_HEIGHT)
+            .map(|i| image.get(i).copied().unwrap_or(0) as f32 * exposure)
+            .collect();
+        let pixel = |x: usize, y: usize| exposed[y * CAMERA_WIDTH + x];
+
+        let edge_enhanced = self.registers[1] & 0xE0 == 0xE0;
+        let ratio = EDGE_RATIOS[(self.registers[4] >> 4) as usize & 0x07];
+        let inverted = self.registers[4] & 0x08 != 0;
+
+        for y in 0..CAMERA_HEIGHT {
+            for x in 0..CAMERA_WIDTH {
+                let mut brightness = pixel(x, y);
+                if edge_enhanced {
+                    let neighbours = pixel(x.saturating_sub(1), y)
+                        + pixel((x + 1).min(CAMERA_WIDTH - 1), y)
+                        + pixel(x, y.saturating_sub(1))
+                        + pixel(x, (y + 1).min(CAMERA_HEIGHT - 1));
+                    brightness += (brightness * 4.0 - neighbours) * ratio;
+                }
+                let mut brightness = brightness.clamp(0.0, 255.0) as u8;
+                if inverted {
+                    brightness = 255 - brightness;
+                }
+
+                // Each of the 4x4 cells of the dither matrix has 3 thresholds, from black to
+                // light gray.
+                let matrix = 6 + ((y & 3) * 4 + (x & 3)) * 3;
+                let thresholds = &self.registers[matrix..matrix + 3];
+                let color = match thresholds.iter().position(|&t| brightness < t) {
+                    Some(level) => 3 - level as u8,
+                    None => 0,
+                };
+
+                let tile = (y / 8) * (CAMERA_WIDTH / 8) + x / 8;
+                let offset = IMAGE_OFFSET + tile * 16 + (y & 7) * 2;
+                let bit = 0x80 >> (x & 7);
+                for (plane, mask) in [(0, 0x01), (1, 0x02)] {
+                    if color & mask != 0 {
+                        self.ram[offset + plane] |= bit;
+                    } else {
+                        self.ram[offset + plane] &= !bit;
+                    }
+                }
+            }
+        }
+    }
+}
+
+impl Mbc for PocketCamera {
+    fn read_rom(&self, address: u16) -> u8 {
+        self.rom
+            .get(self.rom_offset(address))
+            .copied()
+            .unwrap_or(0xFF)
+    }
+
+    fn rom_offset(&self, address: u16) -> usize {
+        let bank = match address {
+            0x0000..=0x3FFF => 0,
+            _ => self.rom_bank as usize % self.rom_bank_count(),
+        };
+        bank * ROM_BANK_SIZE + (address as usize & (ROM_BANK_SIZE - 1))
+    }
+
+    fn write_rom(&mut self, address: u16, value: u8) {
+        match address {
+            0x0000..=0x1FFF => self.ram_enabled = value & 0x0F == 0x0A,
+            0x2000..=0x3FFF => self.rom_bank = value & 0x3F,
+            0x4000..=0x5FFF => self.ram_bank = value & 0x1F,
+            _ => {}
+        }
+    }
+
+    fn read_ram(&self, address: u16) -> u8 {
+        if self.registers_mapped() {
+            // Only the busy flag can be read back.
+            return match address & 0x7F {
+                0x00 => self.registers[0] & 0x07,
+                _ => 0x00,
+            };
+        }
+        // The sensor owns the RAM while it's writing the picture.
+        if self.capturing() {
+            return 0x00;
+        }
+        self.ram[self.ram_offset(address)]
+    }
+
+    fn write_ram(&mut self, address: u16, value: u8) {
+        if self.registers_mapped() {
+            let register = (address & 0x7F) as usize;
+            if register == 0 {
+                self.registers[0] = value & 0x07;
+                if value & CAPTURE_BUSY == 0 {
+                    self.capture_cycles = 0;
+                } else if !self.capturing() {
+                    self.start_capture();
+                }
+            } else if register < REGISTER_COUNT {
+                self.registers[register] = value;
+            }
+            return;
+        }
+        if self.ram_enabled && !self.capturing() {
+            let offset = self.ram_offset(address);
+            self.ram[offset] = value;
+        }
+    }
+
+    fn ram(&self) -> &[u8] {
+        &self.ram
+    }
+
+    fn ram_mut(&mut self) -> &mut [u8] {
+        &mut self.ram
+    }
+
+    fn set_camera_source(&mut self, source: Option<Box<dyn CameraSource>>) {
+        self.source = source;
+    }
+
+    fn tick_rtc(&mut self, cycles: u64) {
+        if !self.capturing() {
+            return;
+        }
+        self.capture_cycles = self.capture_cycles.saturating_sub(cycles);
+        if self.capture_cycles == 0 {
+            self.finish_capture();
+        }
+    }
+}
+
+impl Snapshot for PocketCamera {
+    fn component(&self) -> &'static str {
+        "Pocket Camera"
+    }
+
+    fn version(&self) -> u8 {
+        1
+    }
+
+    fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_bytes(&self.ram);
+        writer.write_bool(self.ram_enabled);
+        writer.write_u8(self.rom_bank);
+        writer.write_u8(self.ram_bank);
+        writer.write_bytes(&self.registers);
+        writer.write_u64(self.capture_cycles);
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader) -> Result<()> {
+        reader.read_bytes_into(&mut self.ram)?;
+        self.ram_enabled = reader.read_bool()?;
+        self.rom_bank = reader.read_u8()? & 0x3F;
+        self.ram_bank = reader.read_u8()? & 0x1F;
+        reader.read_bytes_into(&mut self.registers)?;
+        self.capture_cycles = reader.read_u64()?;
+        Ok(())
+    }
+}
+
+const RAM_SIZE: usize = 0x20000;
+
+// 0xA000-0xA035, mirrored through the rest of 0xA000-0xBFFF every 0x80 bytes.
+const REGISTER_COUNT: usize = 0x36;
+
+const CAPTURE_BUSY: u8 = 0x01;
+
+const CAPTURE_CYCLES: u64 = 32446 * 4;
+
+const NO_N_CYCLES: u64 = 512 * 4;
+
+const EXPOSURE_CYCLES: u64 = 16 * 4;
+
+// The exposure that leaves the source's brightness as it is.
+const EXPOSURE_NEUTRAL: f32 = 0x1000 as f32;
+
+const EDGE_RATIOS: [f32; 8] = [0.5, 0.75, 1.0, 1.25, 2.0, 3.0, 4.0, 5.0];
+
+// Where in RAM bank 0 the 16x14 tiles of the picture go.
+const IMAGE_OFFSET: usize = 0x100;
+
+#[cfg(test)]
+mod tests {
+    use rstest::rstest;
+
+    use super::*;
+
+    struct Flat(u8);
+
+    impl CameraSource for Flat {
+        fn capture(&mut self) -> Vec<u8> {
+            vec![self.0; CAMERA_WIDTH * CAMERA_HEIGHT]
+        }
+    }
+
+    fn pocket_camera() -> PocketCamera {
+        let mut rom = vec![0; 64 * ROM_BANK_SIZE];
+        for bank in 0..64 {
+            rom[bank * ROM_BANK_SIZE] = bank as u8;
+        }
+        PocketCamera::new(rom)
+    }
+
+    // Sets the neutral exposure and a dither matrix that's the same in every cell.
+    fn set_up_sensor(camera: &mut PocketCamera, thresholds: [u8; 3]) {
+        camera.write_rom(0x4000, 0x10);
+        camera.write_ram(0xA001, 0x80);
+        camera.write_ram(0xA002, 0x10);
+        camera.write_ram(0xA003, 0x00);
+        for cell in 0..16 {
+            for (i, threshold) in thresholds.iter().enumerate() {
+                camera.write_ram(0xA006 + cell * 3 + i as u16, *threshold);
+            }
+        }
+    }
+
+    fn capture(camera: &mut PocketCamera) {
+        camera.write_rom(0x4000, 0x10);
+        camera.write_ram(0xA000, 0x01);
+        camera.tick_rtc(u64::MAX);
+        camera.write_rom(0x4000, 0x00);
+    }
+
+    #[rstest]
+    #[case(0x00, 0x00)]
+    #[case(0x01, 0x01)]
+    #[case(0x3F, 0x3F)]
+    #[case(0x41, 0x01)]
+    fn test_switch_rom_bank(#[case] value: u8, #[case] expected_bank: u8) {
+        let mut camera = pocket_camera();
+
+        camera.write_rom(0x2000, value);
+
+        assert_eq!(camera.read_rom(0x4000), expected_bank);
+    }
+
+    #[test]
+    fn test_ram_banking() {
+        let mut camera = pocket_camera();
+        camera.write_rom(0x0000, 0x0A);
+
+        camera.write_rom(0x4000, 0x0F);
+        camera.write_ram(0xA000, 0x0F);
+        camera.write_rom(0x4000, 0x00);
+        camera.write_ram(0xA000, 0x01);
+
+        camera.write_rom(0x4000, 0x0F);
+        assert_eq!(camera.read_ram(0xA000), 0x0F);
+    }
+
+    #[test]
+    fn test_capture_takes_time() {
+        // Arrange
+        let mut camera = pocket_camera();
+        set_up_sensor(&mut camera, [0x40, 0x80, 0xC0]);
+
+        // Act
+        camera.write_ram(0xA000, 0x03);
+        camera.tick_rtc(CAPTURE_CYCLES);
+        let busy = camera.read_ram(0xA000);
+        camera.tick_rtc(0x1000 * EXPOSURE_CYCLES);
+
+        // Assert
+        assert_eq!(busy, 0x03);
+        assert_eq!(camera.read_ram(0xA000), 0x02);
+        assert_eq!(camera.read_ram(0xA080), 0x02);
+    }
+
+    #[rstest]
+    #[case(0x00, 0x00, 3)]
+    #[case(0x50, 0x00, 2)]
+    #[case(0x90, 0x00, 1)]
+    #[case(0xF0, 0x00, 0)]
+    #[case(0xF0, 0x08, 3)]
+    fn test_capture_dithers(
+        #[case] brightness: u8,
+        #[case] invert: u8,
+        #[case] expected_color: u8,
+    ) {
+        // Arrange
+        let mut camera = pocket_camera();
+        camera.set_camera_source(Some(Box::new(Flat(brightness))));
+        set_up_sensor(&mut camera, [0x40, 0x80, 0xC0]);
+        camera.write_ram(0xA004, invert);
+
+        // Act
+        capture(&mut camera);
+
+        // Assert
+        let planes = [camera.read_ram(0xA100), camera.read_ram(0xA101)];
+        let expected = |mask: u8| {
+            if expected_color & mask != 0 {
+                0xFF
+            } else {
+                0x00
+            }
+        };
+        assert_eq!(planes, [expected(0x01), expected(0x02)]);
+        assert_eq!(camera.read_ram(0xAEFF), expected(0x02));
+    }
+
+    #[test]
+    fn test_capture_exposure() {
+        // Arrange
+        let mut camera = pocket_camera();
+        camera.set_camera_source(Some(Box::new(Flat(0x50))));
+        set_up_sensor(&mut camera, [0x40, 0x80, 0xC0]);
+        // Twice the neutral exposure.
+        camera.write_ram(0xA002, 0x20);
+
+        // Act
+        capture(&mut camera);
+
+        // Assert
+        assert_eq!(camera.read_ram(0xA100), 0xFF);
+        assert_eq!(camera.read_ram(0xA101), 0x00);
+    }
+
+    #[test]
+    fn test_ram_locked_while_capturing() {
+        let mut camera = pocket_camera();
+        camera.write_rom(0x0000, 0x0A);
+        camera.write_ram(0xA000, 0x42);
+        camera.write_rom(0x4000, 0x10);
+        camera.write_ram(0xA000, 0x01);
+        camera.write_rom(0x4000, 0x00);
+
+        assert_eq!(camera.read_ram(0xA000), 0x00);
+    }
+
+    #[test]
+    fn test_save_state() {
+        // Arrange
+        let mut camera = pocket_camera();
+        camera.write_rom(0x0000, 0x0A);
+        camera.write_rom(0x2000, 0x05);
+        camera.write_ram(0xA010, 0x42);
+        set_up_sensor(&mut camera, [0x40, 0x80, 0xC0]);
+        camera.write_ram(0xA000, 0x01);
+        let mut writer = StateWriter::new();
+        camera.save_state(&mut writer);
+        let data = writer.into_bytes();
+
+        // Act
+        let mut loaded = pocket_camera();
+        loaded.load_state(&mut StateReader::new(&data)).unwrap();
+
+        // Assert
+        assert_eq!(loaded.read_rom(0x4000), 0x05);
+        assert_eq!(loaded.read_ram(0xA000), 0x01);
+        loaded.tick_rtc(u64::MAX);
+        loaded.write_rom(0x4000, 0x00);
+        assert_eq!(loaded.read_ram(0xA010), 0x42);
+    }
+}
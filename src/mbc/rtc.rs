@@ -0,0 +1,411 @@
+use std::io::Result;
+#[cfg(not(all(feature = "wasm", target_arch = "wasm32")))]
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::apu::CPU_CLOCK;
+use crate::savestate::{StateReader, StateWriter};
+
+// What the RTC counts seconds with.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RtcClock {
+    // The host's wall clock, so the clock keeps running while the emulator is closed.
+    Host,
+    // The cycles the emulator ran, so the same run always sees the same times.
+    Emulated,
+}
+
+// The MBC3 real-time clock. Instead of ticking, the clock is stored as the time at which its
+// counter was zero, in seconds on the clock it follows.
+pub struct Rtc {
+    base: i64,
+    halted_counter: Option<u64>,
+    day_carry: bool,
+    latched: [u8; 5],
+    clock: RtcClock,
+    // Cycles at the normal speed's 4 MiHz since power-on, which the emulated clock counts.
+    cycles: u64,
+}
+
+impl Default for Rtc {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Rtc {
+    pub fn new() -> Rtc {
+        Rtc {
+            base: host_now(),
+            halted_counter: None,
+            day_carry: false,
+            latched: [0; 5],
+            clock: RtcClock::Host,
+            cycles: 0,
+        }
+    }
+
+    pub fn clock(&self) -> RtcClock {
+        self.clock
+    }
+
+    // Moves to another clock, keeping the current count.
+    pub fn set_clock(&mut self, clock: RtcClock) {
+        let counter = self.counter();
+        self.clock = clock;
+        self.set_counter(counter);
+    }
+
+    // Advances the emulated clock. The host clock doesn't need it.
+    pub fn tick(&mut self, cycles: u64) {
+        self.cycles += cycles;
+    }
+
+    // Copies the live counter into the registers the game reads.
+    pub fn latch(&mut self) {
+        let counter = self.counter();
+        let days = counter / SECONDS_PER_DAY;
+        self.latched = [
+            (counter % 60) as u8,
+            (counter / 60 % 60) as u8,
+            (counter / 3600 % 24) as u8,
+            days as u8,
+            self.day_high_register(days),
+        ];
+    }
+
+    // `register` is the value written to 0x4000-0x5FFF, 0x08-0x0C.
+    pub fn read(&self, register: u8) -> u8 {
+        match register {
+            0x08..=0x0C => self.latched[(register - 0x08) as usize],
+            _ => 0xFF,
+        }
+    }
+
+    pub fn write(&mut self, register: u8, value: u8) {
+        let counter = self.counter();
+        let seconds = counter % 60;
+        let minutes = counter / 60 % 60;
+        let hours = counter / 3600 % 24;
+        let days = counter / SECONDS_PER_DAY;
+
+        let counter = match register {
+            0x08 => counter - seconds + (value & 0x3F) as u64,
+            0x09 => counter - minutes * 60 + (value & 0x3F) as u64 * 60,
+            0x0A => counter - hours * 3600 + (value & 0x1F) as u64 * 3600,
+            0x0B => counter - (days & 0xFF) * SECONDS_PER_DAY + value as u64 * SECONDS_PER_DAY,
+            0x0C => {
+                self.day_carry = value & 0x80 != 0;
+                let days = (days & 0xFF) | ((value as u64 & 0x01) << 8);
+                let counter = counter % SECONDS_PER_DAY + days * SECONDS_PER_DAY;
+                let halt = value & 0x40 != 0;
+                self.halted_counter = if halt { Some(counter) } else { None };
+                counter
+            }
+            _ => return,
+        };
+
+        self.set_counter(counter);
+        if let Some(register) = self.latched.get_mut((register - 0x08) as usize) {
+            *register = value;
+        }
+    }
+
+    // The battery file format, which always counts on the host clock so the clock carries on after
+    // a run on the emulated one.
+    pub fn to_bytes(&self) -> [u8; RTC_STATE_SIZE] {
+        let base = match self.clock {
+            RtcClock::Host => self.base,
+            RtcClock::Emulated => host_now() - (self.now() - self.base),
+        };
+        let mut bytes = [0; RTC_STATE_SIZE];
+        bytes[0..8].copy_from_slice(&base.to_le_bytes());
+        bytes[8..16].copy_from_slice(&self.halted_counter.unwrap_or(u64::MAX).to_le_bytes());
+        bytes[16] = self.day_carry as u8;
+        bytes[17..22].copy_from_slice(&self.latched);
+        bytes
+    }
+
+    pub fn from_bytes(bytes: &[u8; RTC_STATE_SIZE]) -> Rtc {
+        let halted_counter = u64::from_le_bytes(bytes[8..16].try_into().unwrap());
+        Rtc {
+            base: i64::from_le_bytes(bytes[0..8].try_into().unwrap()),
+            halted_counter: (halted_counter != u64::MAX).then_some(halted_counter),
+            day_carry: bytes[16] != 0,
+            latched: bytes[17..22].try_into().unwrap(),
+            clock: RtcClock::Host,
+            cycles: 0,
+        }
+    }
+
+    // Takes the registers from a battery file. The emulated clock can't tell how long the host was
+    // off for, so it carries on from the time the game last latched.
+    pub fn load_bytes(&mut self, bytes: &[u8; RTC_STATE_SIZE]) {
+        let (clock, cycles) = (self.clock, self.cycles);
+        *self = Rtc::from_bytes(bytes);
+        if clock == RtcClock::Emulated {
+            let [seconds, minutes, hours, days, day_high] = self.latched;
+            let days = days as u64 | (day_high as u64 & 0x01) << 8;
+            let counter = days * SECONDS_PER_DAY
+                + hours as u64 % 24 * 3600
+                + minutes as u64 % 60 * 60
+                + seconds as u64 % 60;
+            self.clock = clock;
+            self.cycles = cycles;
+            self.set_counter(counter);
+        }
+    }
+
+    // Unlike `to_bytes`, keeps the clock as it is so loading the state resumes the same run.
+    pub fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_u64(self.base as u64);
+        writer.write_u64(self.halted_counter.unwrap_or(u64::MAX));
+        writer.write_bool(self.day_carry);
+        writer.write_bytes(&self.latched);
+        writer.write_bool(self.clock == RtcClock::Emulated);
+        writer.write_u64(self.cycles);
+    }
+
+    pub fn load_state(&mut self, reader: &mut StateReader) -> Result<()> {
+        self.base = reader.read_u64()? as i64;
+        let halted_counter = reader.read_u64()?;
+        self.halted_counter = (halted_counter != u64::MAX).then_some(halted_counter);
+        self.day_carry = reader.read_bool()?;
+        reader.read_bytes_into(&mut self.latched)?;
+        self.clock = if reader.read_bool()? {
+            RtcClock::Emulated
+        } else {
+            RtcClock::Host
+        };
+        self.cycles = reader.read_u64()?;
+        Ok(())
+    }
+
+    fn now(&self) -> i64 {
+        match self.clock {
+            RtcClock::Host => host_now(),
+            RtcClock::Emulated => (self.cycles / CPU_CLOCK) as i64,
+        }
+    }
+
+    fn counter(&mut self) -> u64 {
+        let mut counter = match self.halted_counter {
+            Some(counter) => counter,
+            None => (self.now() - self.base).max(0) as u64,
+        };
+
+        // The day counter is 9 bits wide; overflowing it sets the sticky carry flag.
+        if counter >= DAY_COUNTER_LIMIT * SECONDS_PER_DAY {
+            counter %= DAY_COUNTER_LIMIT * SECONDS_PER_DAY;
+            self.day_carry = true;
+            self.set_counter(counter);
+        }
+
+        counter
+    }
+
+    fn set_counter(&mut self, counter: u64) {
+        if self.halted_counter.is_some() {
+            self.halted_counter = Some(counter);
+        }
+        self.base = self.now() - counter as i64;
+    }
+
+    fn day_high_register(&self, days: u64) -> u8 {
+        ((days >> 8) as u8 & 0x01)
+            | if self.halted_counter.is_some() {
+                0x40
+            } else {
+                0
+            }
+            | if self.day_carry { 0x80 } else { 0 }
+    }
+}
+
+#[cfg(not(all(feature = "wasm", target_arch = "wasm32")))]
+fn host_now() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_secs() as i64)
+        .unwrap_or(0)
+}
+
+// Browsers have no SystemTime, asking for it panics.
+#[cfg(all(feature = "wasm", target_arch = "wasm32"))]
+fn host_now() -> i64 {
+    #[wasm_bindgen::prelude::wasm_bindgen]
+    extern "C" {
+        #[wasm_bindgen(js_namespace = Date, js_name = now)]
+        fn date_now() -> f64;
+    }
+
+    (date_now() / 1000.0) as i64
+}
+
+pub const RTC_STATE_SIZE: usize = 22;
+
+const SECONDS_PER_DAY: u64 = 86400;
+
+const DAY_COUNTER_LIMIT: u64 = 512;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A clock at zero that only moves when the test says, unlike the host's.
+    fn stopped_rtc() -> Rtc {
+        Rtc {
+            base: 0,
+            clock: RtcClock::Emulated,
+            ..Rtc::new()
+        }
+    }
+
+    #[test]
+    fn test_latch_reports_elapsed_time() {
+        // Arrange
+        let mut rtc = stopped_rtc();
+        rtc.base -= (2 * SECONDS_PER_DAY + 3 * 3600 + 4 * 60 + 5) as i64;
+
+        // Act
+        rtc.latch();
+
+        // Assert
+        assert_eq!(rtc.read(0x08), 5);
+        assert_eq!(rtc.read(0x09), 4);
+        assert_eq!(rtc.read(0x0A), 3);
+        assert_eq!(rtc.read(0x0B), 2);
+        assert_eq!(rtc.read(0x0C), 0);
+    }
+
+    #[test]
+    fn test_latched_values_do_not_change_until_next_latch() {
+        let mut rtc = stopped_rtc();
+        rtc.latch();
+
+        rtc.base -= 30;
+
+        assert_eq!(rtc.read(0x08), 0);
+        rtc.latch();
+        assert_eq!(rtc.read(0x08), 30);
+    }
+
+    #[test]
+    fn test_day_counter_high_bit_and_carry() {
+        let mut rtc = stopped_rtc();
+        rtc.base -= (300 * SECONDS_PER_DAY) as i64;
+        rtc.latch();
+        assert_eq!(rtc.read(0x0B), (300 & 0xFF) as u8);
+        assert_eq!(rtc.read(0x0C), 0x01);
+
+        rtc.base -= (212 * SECONDS_PER_DAY) as i64;
+        rtc.latch();
+        assert_eq!(rtc.read(0x0B), 0);
+        assert_eq!(rtc.read(0x0C), 0x80);
+    }
+
+    #[test]
+    fn test_write_registers() {
+        let mut rtc = stopped_rtc();
+
+        rtc.write(0x08, 10);
+        rtc.write(0x09, 20);
+        rtc.write(0x0A, 5);
+        rtc.write(0x0B, 7);
+        rtc.latch();
+
+        assert_eq!(rtc.read(0x08), 10);
+        assert_eq!(rtc.read(0x09), 20);
+        assert_eq!(rtc.read(0x0A), 5);
+        assert_eq!(rtc.read(0x0B), 7);
+    }
+
+    #[test]
+    fn test_halt_stops_the_clock() {
+        let mut rtc = stopped_rtc();
+        rtc.write(0x08, 10);
+
+        rtc.write(0x0C, 0x40);
+        rtc.base -= 100;
+        rtc.latch();
+
+        assert_eq!(rtc.read(0x08), 10);
+        assert_eq!(rtc.read(0x0C), 0x40);
+    }
+
+    #[test]
+    fn test_clearing_day_carry() {
+        let mut rtc = stopped_rtc();
+        rtc.base -= (DAY_COUNTER_LIMIT * SECONDS_PER_DAY) as i64;
+        rtc.latch();
+        assert_eq!(rtc.read(0x0C) & 0x80, 0x80);
+
+        rtc.write(0x0C, 0x00);
+        rtc.latch();
+
+        assert_eq!(rtc.read(0x0C) & 0x80, 0);
+    }
+
+    #[test]
+    fn test_bytes_roundtrip() {
+        let mut rtc = Rtc::new();
+        rtc.write(0x0A, 12);
+        rtc.write(0x0C, 0x41);
+        rtc.latch();
+
+        let restored = Rtc::from_bytes(&rtc.to_bytes());
+
+        assert_eq!(restored.base, rtc.base);
+        assert_eq!(restored.halted_counter, rtc.halted_counter);
+        assert_eq!(restored.latched, rtc.latched);
+    }
+
+    #[test]
+    fn test_emulated_clock_counts_cycles() {
+        let mut rtc = stopped_rtc();
+        rtc.write(0x09, 2);
+
+        rtc.tick(CPU_CLOCK * 5 + 1);
+        rtc.latch();
+
+        assert_eq!(rtc.read(0x08), 5);
+        assert_eq!(rtc.read(0x09), 2);
+    }
+
+    #[test]
+    fn test_emulated_clock_resumes_from_latched_time() {
+        // Arrange
+        let mut saved = Rtc::new();
+        saved.write(0x0A, 3);
+        saved.latch();
+        // Hours of host time pass before the save is loaded.
+        let mut bytes = saved.to_bytes();
+        bytes[0..8].copy_from_slice(&(saved.base - 7200).to_le_bytes());
+        let mut rtc = Rtc::new();
+        rtc.set_clock(RtcClock::Emulated);
+
+        // Act
+        rtc.load_bytes(&bytes);
+        rtc.latch();
+
+        // Assert
+        assert_eq!(rtc.clock(), RtcClock::Emulated);
+        assert_eq!(rtc.read(0x0A), 3);
+    }
+
+    #[test]
+    fn test_state_round_trip() {
+        let mut rtc = stopped_rtc();
+        rtc.tick(CPU_CLOCK * 90);
+        let mut writer = StateWriter::new();
+        rtc.save_state(&mut writer);
+
+        let bytes = writer.into_bytes();
+        let mut restored = Rtc::new();
+        restored.load_state(&mut StateReader::new(&bytes)).unwrap();
+        restored.latch();
+
+        assert_eq!(restored.clock(), RtcClock::Emulated);
+        assert_eq!(restored.read(0x09), 1);
+        assert_eq!(restored.read(0x08), 30);
+    }
+}
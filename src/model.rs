@@ -0,0 +1,125 @@
+use std::fmt;
+use std::io::{Error, Result};
+use std::str::FromStr;
+
+use crate::rom::{CgbSupport, Rom};
+
+// The console being emulated. Games and test ROMs tell them apart by the registers the boot ROM
+// leaves behind, and a few behave differently on each.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum EmulatorModel {
+    Dmg,
+    // The Game Boy Pocket and Light.
+    Mgb,
+    Sgb,
+    Cgb,
+}
+
+impl EmulatorModel {
+    // CGB for cartridges that support it, SGB for the ones that only support that and DMG for
+    // everything else.
+    pub fn for_rom(rom: &Rom) -> Result<EmulatorModel> {
+        Ok(match rom.get_cgb_support()? {
+            CgbSupport::None if rom.sgb_support()? => EmulatorModel::Sgb,
+            CgbSupport::None => EmulatorModel::Dmg,
+            CgbSupport::Compatible | CgbSupport::Only => EmulatorModel::Cgb,
+        })
+    }
+
+    pub fn is_cgb(self) -> bool {
+        self == EmulatorModel::Cgb
+    }
+
+    // Whether a cartridge runs with the CGB features enabled. A CGB runs the rest in DMG
+    // compatibility mode, and the other models never have them.
+    pub fn cgb_mode(self, rom: &Rom) -> Result<bool> {
+        Ok(self.is_cgb() && rom.get_cgb_support()? != CgbSupport::None)
+    }
+
+    pub fn to_u8(self) -> u8 {
+        match self {
+            EmulatorModel::Dmg => 0,
+            EmulatorModel::Mgb => 1,
+            EmulatorModel::Sgb => 2,
+            EmulatorModel::Cgb => 3,
+        }
+    }
+
+    pub fn from_u8(value: u8) -> Result<EmulatorModel> {
+        match value {
+            0 => Ok(EmulatorModel::Dmg),
+            1 => Ok(EmulatorModel::Mgb),
+            2 => Ok(EmulatorModel::Sgb),
+            3 => Ok(EmulatorModel::Cgb),
+            _ => Err(Error::other(format!("{} is not a valid model.", value))),
+        }
+    }
+}
+
+impl fmt::Display for EmulatorModel {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            EmulatorModel::Dmg => "dmg",
+            EmulatorModel::Mgb => "mgb",
+            EmulatorModel::Sgb => "sgb",
+            EmulatorModel::Cgb => "cgb",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+impl FromStr for EmulatorModel {
+    type Err = Error;
+
+    fn from_str(name: &str) -> Result<EmulatorModel> {
+        match name.to_ascii_lowercase().as_str() {
+            "dmg" => Ok(EmulatorModel::Dmg),
+            "mgb" => Ok(EmulatorModel::Mgb),
+            "sgb" => Ok(EmulatorModel::Sgb),
+            "cgb" => Ok(EmulatorModel::Cgb),
+            _ => Err(Error::other(format!(
+                "{} is not a model, expected dmg, mgb, sgb or cgb.",
+                name
+            ))),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rstest::rstest;
+
+    use super::*;
+
+    #[rstest]
+    #[case(0x00, 0x00, EmulatorModel::Dmg)]
+    #[case(0x00, 0x03, EmulatorModel::Sgb)]
+    #[case(0x80, 0x00, EmulatorModel::Cgb)]
+    #[case(0x80, 0x03, EmulatorModel::Cgb)]
+    #[case(0xC0, 0x00, EmulatorModel::Cgb)]
+    fn test_for_rom(#[case] cgb_flag: u8, #[case] sgb_flag: u8, #[case] expected: EmulatorModel) {
+        let mut content = vec![0; 0x8000];
+        content[0x143] = cgb_flag;
+        content[0x146] = sgb_flag;
+
+        assert_eq!(
+            EmulatorModel::for_rom(&Rom::from_content(content)).unwrap(),
+            expected
+        );
+    }
+
+    #[rstest]
+    #[case(EmulatorModel::Dmg)]
+    #[case(EmulatorModel::Mgb)]
+    #[case(EmulatorModel::Sgb)]
+    #[case(EmulatorModel::Cgb)]
+    fn test_names_and_numbers_round_trip(#[case] model: EmulatorModel) {
+        assert_eq!(model.to_string().parse::<EmulatorModel>().unwrap(), model);
+        assert_eq!(EmulatorModel::from_u8(model.to_u8()).unwrap(), model);
+    }
+
+    #[test]
+    fn test_unknown_name() {
+        assert!("gba".parse::<EmulatorModel>().is_err());
+    }
+}
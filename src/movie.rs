@@ -0,0 +1,398 @@
+use std::fs;
+use std::io::{Error, Result};
+use std::path::Path;
+
+use crate::emulator::Emulator;
+use crate::savestate::{StateReader, StateWriter};
+
+// The buttons held during every frame of a run, and the state it started from. Replaying the
+// inputs from the same state gives the same run, so a movie is a few bytes per frame instead of a
+// video. The file is a magic number and version, the rerecord count, the start state, the
+// cartridge RAM and then one byte per frame, with bit `Button as u8` set for each button held.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Movie {
+    start_state: Vec<u8>,
+    save_data: Vec<u8>,
+    inputs: Vec<u8>,
+    // How many times part of the movie was thrown away and recorded again.
+    rerecords: u32,
+}
+
+impl Movie {
+    // An empty movie starting from where `emulator` is now.
+    pub fn new(emulator: &Emulator) -> Movie {
+        Movie {
+            start_state: emulator.save_state(),
+            save_data: emulator.save_data(),
+            inputs: Vec::new(),
+            rerecords: 0,
+        }
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Movie> {
+        let mut reader = StateReader::new(bytes);
+        if &reader.read_array::<4>()? != MAGIC {
+            return Err(Error::other("The file is not a movie."));
+        }
+        let version = reader.read_u32()?;
+        if version != VERSION {
+            return Err(Error::other(format!(
+                "The movie version {} is not supported, expected {}.",
+                version, VERSION
+            )));
+        }
+
+        let movie = Movie {
+            rerecords: reader.read_u32()?,
+            start_state: reader.read_bytes()?.to_vec(),
+            save_data: reader.read_bytes()?.to_vec(),
+            inputs: reader.read_bytes()?.to_vec(),
+        };
+        if !reader.is_empty() {
+            return Err(Error::other("The movie has trailing data."));
+        }
+        Ok(movie)
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut writer = StateWriter::new();
+        for &byte in MAGIC {
+            writer.write_u8(byte);
+        }
+        writer.write_u32(VERSION);
+        writer.write_u32(self.rerecords);
+        writer.write_bytes(&self.start_state);
+        writer.write_bytes(&self.save_data);
+        writer.write_bytes(&self.inputs);
+        writer.into_bytes()
+    }
+
+    pub fn load(path: &Path) -> Result<Movie> {
+        let bytes = fs::read(path).map_err(|error| {
+            Error::new(
+                error.kind(),
+                format!("Could not read {}: {}", path.display(), error),
+            )
+        })?;
+        Movie::from_bytes(&bytes)
+            .map_err(|error| Error::other(format!("{}: {}", path.display(), error)))
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        fs::write(path, self.to_bytes())
+    }
+
+    // Puts `emulator` back where the movie starts. Fails without touching it if the movie was
+    // recorded with another game. The state goes last since its cartridge RAM and clock are the
+    // exact ones the movie started with.
+    pub fn rewind(&self, emulator: &mut Emulator) -> Result<()> {
+        let backup = emulator.save_data();
+        emulator.load_save_data(&self.save_data);
+        emulator.load_state(&self.start_state).inspect_err(|_| {
+            emulator.load_save_data(&backup);
+        })
+    }
+
+    pub fn inputs(&self) -> &[u8] {
+        &self.inputs
+    }
+
+    pub fn len(&self) -> usize {
+        self.inputs.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.inputs.is_empty()
+    }
+
+    pub fn rerecords(&self) -> u32 {
+        self.rerecords
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MovieMode {
+    // Adding the buttons held to the end of the movie every frame.
+    Recording,
+    // Replacing the buttons held with the movie's every frame.
+    Playing,
+    // Past the end of a read-only movie, where the buttons are the player's again.
+    Finished,
+}
+
+// A movie being recorded or played, which `Emulator::run_frame` advances a frame at a time.
+//
+// A read-only movie never changes. A read-write one plays until its end and then carries on
+// recording, and `seek` to an earlier frame throws away what came after it and records over it,
+// which is how tool-assisted runs get made one attempt at a time.
+pub struct MovieSession {
+    movie: Movie,
+    mode: MovieMode,
+    frame: usize,
+    read_only: bool,
+    modified: bool,
+}
+
+impl MovieSession {
+    // Starts an empty read-write movie from where `emulator` is now, in deterministic mode so the
+    // movie replays the same anywhere. See `Emulator::set_deterministic`.
+    pub fn record(emulator: &mut Emulator) -> MovieSession {
+        emulator.set_deterministic(true);
+        MovieSession {
+            movie: Movie::new(emulator),
+            mode: MovieMode::Recording,
+            frame: 0,
+            read_only: false,
+            modified: true,
+        }
+    }
+
+    // Puts `emulator` where `movie` starts and plays it from the first frame.
+    pub fn play(movie: Movie, emulator: &mut Emulator, read_only: bool) -> Result<MovieSession> {
+        movie.rewind(emulator)?;
+        let mut session = MovieSession {
+            movie,
+            mode: MovieMode::Playing,
+            frame: 0,
+            read_only,
+            modified: false,
+        };
+        session.update_mode();
+        Ok(session)
+    }
+
+    pub fn movie(&self) -> &Movie {
+        &self.movie
+    }
+
+    pub fn into_movie(self) -> Movie {
+        self.movie
+    }
+
+    pub fn mode(&self) -> MovieMode {
+        self.mode
+    }
+
+    // The frame about to run, counting from 0 at the start of the movie.
+    pub fn frame(&self) -> usize {
+        self.frame
+    }
+
+    pub fn read_only(&self) -> bool {
+        self.read_only
+    }
+
+    // Making a movie read-only stops a recording. A finished movie only carries on recording when
+    // made read-write right at its end, since the frames after it weren't kept.
+    pub fn set_read_only(&mut self, read_only: bool) {
+        self.read_only = read_only;
+        self.update_mode();
+    }
+
+    // Whether the movie changed since it was recorded or loaded, so it needs saving.
+    pub fn modified(&self) -> bool {
+        self.modified
+    }
+
+    // Moves to `frame` after the emulator was put back to the state it had there, like when a save
+    // state is loaded. A read-write movie is cut off at `frame` and recorded again from there.
+    pub fn seek(&mut self, frame: usize) {
+        self.frame = frame.min(self.movie.len());
+        if !self.read_only {
+            if self.frame < self.movie.len() {
+                self.movie.inputs.truncate(self.frame);
+                self.movie.rerecords += 1;
+            }
+            self.modified = true;
+        }
+        self.update_mode();
+    }
+
+    // Plays or records the buttons for the frame about to run.
+    pub fn advance(&mut self, emulator: &mut Emulator) {
+        match self.mode {
+            MovieMode::Recording => {
+                self.movie.inputs.push(emulator.buttons());
+                self.modified = true;
+            }
+            MovieMode::Playing => emulator.set_buttons(self.movie.inputs[self.frame]),
+            MovieMode::Finished => {}
+        }
+        self.frame += 1;
+        self.update_mode();
+    }
+
+    fn update_mode(&mut self) {
+        self.mode = if self.frame < self.movie.len() {
+            MovieMode::Playing
+        } else if self.read_only || self.frame > self.movie.len() {
+            MovieMode::Finished
+        } else {
+            MovieMode::Recording
+        };
+    }
+}
+
+const MAGIC: &[u8; 4] = b"RGBM";
+
+const VERSION: u32 = 1;
+
+#[cfg(test)]
+mod tests {
+    use rstest::rstest;
+
+    use super::*;
+    use crate::joypad::Button;
+    use crate::rom::Rom;
+
+    fn emulator() -> Emulator {
+        // Copies the action buttons to 0xC000 forever: LD A, 0x10; LDH (0x00), A;
+        // LDH A, (0x00); LD (0xC000), A; JR -7
+        let mut content = vec![0; 0x8000];
+        content[0x100..0x10C].copy_from_slice(&[
+            0x3E, 0x10, 0xE0, 0x00, 0xF0, 0x00, 0xEA, 0x00, 0xC0, 0x18, 0xF9, 0x00,
+        ]);
+        Emulator::new(Rom::from_content(content)).unwrap()
+    }
+
+    fn record(emulator: &mut Emulator) -> MovieSession {
+        let mut session = MovieSession::record(emulator);
+        for frame in 0..10 {
+            emulator.set_button(Button::A, frame % 3 == 0);
+            emulator.set_button(Button::Start, frame >= 5);
+            session.advance(emulator);
+            emulator.run_frame();
+        }
+        session
+    }
+
+    #[test]
+    fn test_replay() {
+        // Arrange
+        let mut emulator = emulator();
+        let recorded = record(&mut emulator);
+        let end = emulator.save_state();
+
+        // Act
+        let session = MovieSession::play(recorded.into_movie(), &mut emulator, true).unwrap();
+        emulator.set_movie(Some(session));
+        for _ in 0..10 {
+            // Whatever the player holds is ignored.
+            emulator.set_button(Button::B, true);
+            emulator.run_frame();
+        }
+        let session = emulator.take_movie().unwrap();
+
+        // Assert
+        assert_eq!(session.mode(), MovieMode::Finished);
+        assert_eq!(emulator.save_state(), end);
+        assert!(!session.modified());
+    }
+
+    // An MBC3 with a clock that latches it every frame and copies the seconds to 0xC001.
+    fn emulator_with_clock() -> Emulator {
+        let mut content = vec![0; 0x8000];
+        content[0x147] = 0x10;
+        content[0x149] = 0x02;
+        let program = [
+            0x3E, 0x0A, 0xEA, 0x00, 0x00, // LD A, 0x0A; LD (0x0000), A
+            0x3E, 0x08, 0xEA, 0x00, 0x40, // LD A, 0x08; LD (0x4000), A
+            0xAF, 0xEA, 0x00, 0x60, // XOR A; LD (0x6000), A
+            0x3C, 0xEA, 0x00, 0x60, // INC A; LD (0x6000), A
+            0xFA, 0x00, 0xA0, 0xEA, 0x01, 0xC0, // LD A, (0xA000); LD (0xC001), A
+            0x18, 0xF0, // JR -16
+        ];
+        content[0x100..0x100 + program.len()].copy_from_slice(&program);
+        Emulator::new(Rom::from_content(content)).unwrap()
+    }
+
+    #[test]
+    fn test_replays_hash_the_same() {
+        // Arrange
+        let mut emulator = emulator_with_clock();
+        let mut session = MovieSession::record(&mut emulator);
+        for frame in 0..70 {
+            emulator.set_button(Button::A, frame % 7 < 3);
+            session.advance(&mut emulator);
+            emulator.run_frame();
+        }
+        let movie = session.into_movie();
+        let replay = |emulator: &mut Emulator| {
+            let mut session = MovieSession::play(movie.clone(), emulator, true).unwrap();
+            for _ in 0..movie.len() {
+                session.advance(emulator);
+                emulator.run_frame();
+            }
+            emulator.state_hash()
+        };
+
+        // Act
+        let first = replay(&mut emulator);
+        let second = replay(&mut emulator_with_clock());
+
+        // Assert
+        assert_eq!(first, second);
+        // A second of emulated time, whatever the host clock did.
+        assert_eq!(emulator.peek(0xC001), 1);
+    }
+
+    #[test]
+    fn test_file_round_trip() {
+        let mut emulator = emulator();
+        let movie = record(&mut emulator).into_movie();
+
+        let loaded = Movie::from_bytes(&movie.to_bytes()).unwrap();
+
+        assert_eq!(loaded, movie);
+        assert_eq!(loaded.len(), 10);
+        assert_eq!(loaded.inputs()[0], 0x10);
+        assert_eq!(loaded.inputs()[9], 0x90);
+    }
+
+    #[rstest]
+    #[case(b"RGBS\x01\x00\x00\x00", "The file is not a movie.")]
+    #[case(
+        b"RGBM\x02\x00\x00\x00",
+        "The movie version 2 is not supported, expected 1."
+    )]
+    fn test_invalid_file(#[case] bytes: &[u8], #[case] expected: &str) {
+        let error = Movie::from_bytes(bytes).unwrap_err();
+
+        assert_eq!(error.to_string(), expected);
+    }
+
+    #[test]
+    fn test_read_write_rerecords() {
+        // Arrange
+        let mut emulator = emulator();
+        let movie = record(&mut emulator).into_movie();
+        let mut session = MovieSession::play(movie, &mut emulator, false).unwrap();
+        for _ in 0..4 {
+            session.advance(&mut emulator);
+        }
+
+        // Act
+        session.seek(2);
+        emulator.set_buttons(0x20);
+        session.advance(&mut emulator);
+
+        // Assert
+        assert_eq!(session.mode(), MovieMode::Recording);
+        assert_eq!(session.movie().inputs(), &[0x10, 0x00, 0x20]);
+        assert_eq!(session.movie().rerecords(), 1);
+        assert!(session.modified());
+    }
+
+    #[test]
+    fn test_read_only_stops_recording() {
+        let mut emulator = emulator();
+        let mut session = MovieSession::record(&mut emulator);
+        session.advance(&mut emulator);
+
+        session.set_read_only(true);
+        session.advance(&mut emulator);
+
+        assert_eq!(session.mode(), MovieMode::Finished);
+        assert_eq!(session.movie().len(), 1);
+    }
+}
@@ -0,0 +1,698 @@
+use std::cell::RefCell;
+use std::collections::VecDeque;
+use std::io::{Error, ErrorKind, Result};
+use std::net::{ToSocketAddrs, UdpSocket};
+use std::rc::Rc;
+use std::time::{Duration, Instant};
+
+use crate::emulator::{Emulator, RunLimit};
+use crate::rom::Rom;
+use crate::serial::SerialDevice;
+
+// Link cable play over UDP with rollback. Both players run both Game Boys, linked in-process, so
+// only the buttons have to cross the network. Each side starts from the other's save state and
+// then sends its buttons every frame, a few frames ahead of using them. Until the other side's
+// buttons for a frame arrive they're guessed to be the last ones that did, and a wrong guess
+// loads the state from before that frame and runs it again with the right ones.
+pub struct Netplay {
+    socket: UdpSocket,
+    // HOST or GUEST, which is also this side's place in the pair.
+    side: usize,
+    // This emulator's copy of the other player's Game Boy.
+    remote: Emulator,
+    link: Rc<RefCell<LinkState>>,
+    // The next frame to run.
+    frame: u32,
+    // This side's buttons for every frame so far, INPUT_DELAY frames ahead.
+    local_inputs: Vec<u8>,
+    // The other side's buttons, as far as they've arrived.
+    remote_inputs: Vec<u8>,
+    // The other side's buttons each frame was run with, guessed or not.
+    used_remote: Vec<u8>,
+    // How many of `local_inputs` the other side has.
+    acked: usize,
+    // The earliest frame run with a wrong guess.
+    rollback: Option<u32>,
+    // The states before every frame that can still be rolled back to, the last one being now.
+    history: VecDeque<Checkpoint>,
+    last_heard: Instant,
+}
+
+impl Netplay {
+    // Waits on `port` for the other player to join.
+    pub fn host(port: u16, local: &mut Emulator, rom: Rom) -> Result<Netplay> {
+        let socket = UdpSocket::bind(("0.0.0.0", port))?;
+        Netplay::start(socket, HOST, local, rom)
+    }
+
+    // Joins a player hosting at `address`.
+    pub fn join(address: impl ToSocketAddrs, local: &mut Emulator, rom: Rom) -> Result<Netplay> {
+        let socket = UdpSocket::bind(("0.0.0.0", 0))?;
+        socket.connect(address)?;
+        Netplay::start(socket, GUEST, local, rom)
+    }
+
+    // `rom` is the one `local` runs, for the other player's Game Boy.
+    fn start(socket: UdpSocket, side: usize, local: &mut Emulator, rom: Rom) -> Result<Netplay> {
+        if side == HOST {
+            // The guest speaks first.
+            socket.set_read_timeout(Some(HANDSHAKE_TIMEOUT))?;
+            let (_, peer) = socket.peek_from(&mut [0; PACKET_SIZE]).map_err(|error| {
+                if matches!(error.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) {
+                    Error::other("Nobody joined.")
+                } else {
+                    error
+                }
+            })?;
+            socket.connect(peer)?;
+        }
+
+        local.set_deterministic(true);
+        let peer_state = handshake(&socket, &local.save_state())?;
+        let mut remote = Emulator::with_model(rom, local.model())?;
+        remote.load_state(&peer_state).map_err(|error| {
+            Error::other(format!(
+                "Could not start from the other player's game: {}",
+                error
+            ))
+        })?;
+        socket.set_nonblocking(true)?;
+
+        let link = Rc::new(RefCell::new(LinkState::default()));
+        local.set_serial_device(Some(Box::new(LinkEnd {
+            state: link.clone(),
+            side,
+        })));
+        remote.set_serial_device(Some(Box::new(LinkEnd {
+            state: link.clone(),
+            side: 1 - side,
+        })));
+        let mut netplay = Netplay {
+            socket,
+            side,
+            remote,
+            link,
+            frame: 0,
+            local_inputs: vec![0; INPUT_DELAY],
+            remote_inputs: Vec::new(),
+            used_remote: Vec::new(),
+            acked: 0,
+            rollback: None,
+            history: VecDeque::new(),
+            last_heard: Instant::now(),
+        };
+        let checkpoint = netplay.checkpoint(0, local);
+        netplay.history.push_back(checkpoint);
+        Ok(netplay)
+    }
+
+    pub fn frame(&self) -> u32 {
+        self.frame
+    }
+
+    // Runs a frame with the buttons held on `local`, instead of `Emulator::run_frame`. Returns
+    // false without running one when the other side is too far behind to guess for. Between
+    // frames `local` holds the live buttons rather than the ones the game sees, and anything
+    // else done to it is undone.
+    pub fn advance(&mut self, local: &mut Emulator) -> Result<bool> {
+        self.receive()?;
+        let live = local.buttons();
+        if self.frame as usize >= self.remote_inputs.len() + MAX_ROLLBACK {
+            self.send_inputs();
+            return Ok(false);
+        }
+        self.local_inputs.push(live);
+        self.send_inputs();
+
+        let from = self.rollback.take().unwrap_or(self.frame);
+        self.run(local, from, self.frame + 1);
+        self.frame += 1;
+        while self.history.len() > 1 && (self.history[0].frame as usize) < self.remote_inputs.len()
+        {
+            self.history.pop_front();
+        }
+        local.notify_observers();
+        local.set_buttons(live);
+        Ok(true)
+    }
+
+    // Goes back to the state before frame `from` and runs up to `to`. Only the last frame's
+    // sound is kept and recorded, the rest was already heard the first time round.
+    fn run(&mut self, local: &mut Emulator, from: u32, to: u32) {
+        while self.history.back().is_some_and(|last| last.frame > from) {
+            self.history.pop_back();
+        }
+        let checkpoint = self
+            .history
+            .back()
+            .expect("the state to roll back to is kept");
+        local
+            .load_state(&checkpoint.local)
+            .expect("a state saved this session loads");
+        self.remote
+            .load_state(&checkpoint.remote)
+            .expect("a state saved this session loads");
+        *self.link.borrow_mut() = checkpoint.link.clone();
+
+        for frame in from..to {
+            let remote_input = self.remote_input(frame);
+            local.set_buttons(self.local_inputs[frame as usize]);
+            self.remote.set_buttons(remote_input);
+            if frame as usize == self.used_remote.len() {
+                self.used_remote.push(remote_input);
+            } else {
+                self.used_remote[frame as usize] = remote_input;
+            }
+
+            local.suspend_recordings(frame + 1 < to);
+            let mut pair = if self.side == HOST {
+                [&mut *local, &mut self.remote]
+            } else {
+                [&mut self.remote, &mut *local]
+            };
+            // Each runs a line's worth at a time so link transfers see the other side in time.
+            for _ in 0..SLICES {
+                for emulator in pair.iter_mut() {
+                    let slice = if emulator.bus().double_speed() {
+                        SLICE_CYCLES * 2
+                    } else {
+                        SLICE_CYCLES
+                    };
+                    emulator.run_for(RunLimit::Cycles(slice));
+                }
+            }
+
+            self.remote.audio_samples();
+            if frame + 1 < to {
+                local.audio_samples();
+            }
+            let checkpoint = self.checkpoint(frame + 1, local);
+            self.history.push_back(checkpoint);
+        }
+        local.suspend_recordings(false);
+    }
+
+    // The other side's buttons for `frame`, or a guess.
+    fn remote_input(&self, frame: u32) -> u8 {
+        self.remote_inputs
+            .get(frame as usize)
+            .or(self.remote_inputs.last())
+            .copied()
+            .unwrap_or(0)
+    }
+
+    fn checkpoint(&self, frame: u32, local: &Emulator) -> Checkpoint {
+        Checkpoint {
+            frame,
+            local: local.save_state(),
+            remote: self.remote.save_state(),
+            link: self.link.borrow().clone(),
+        }
+    }
+
+    // Everything the other side sent since the last frame.
+    fn receive(&mut self) -> Result<()> {
+        let mut packet = [0; PACKET_SIZE];
+        loop {
+            let length = match self.socket.recv(&mut packet) {
+                Ok(length) => length,
+                Err(error) if error.kind() == ErrorKind::WouldBlock => break,
+                // The other side's port isn't open yet or anymore. Waiting tells which.
+                Err(error) if error.kind() == ErrorKind::ConnectionRefused => break,
+                Err(error) => return Err(error),
+            };
+            self.last_heard = Instant::now();
+            match Message::parse(&packet[..length]) {
+                Some(Message::Input { ack, first, inputs }) => {
+                    self.acked = self.acked.max(ack as usize);
+                    self.add_remote_inputs(first as usize, &inputs);
+                }
+                // The other side missed our READY.
+                Some(Message::State { .. }) => {
+                    let _ = self.socket.send(&[KIND_READY]);
+                }
+                Some(Message::Ready) | None => {}
+            }
+        }
+        if self.last_heard.elapsed() > DISCONNECT_TIMEOUT {
+            return Err(Error::other("The other player stopped answering."));
+        }
+        Ok(())
+    }
+
+    fn add_remote_inputs(&mut self, first: usize, inputs: &[u8]) {
+        for (frame, &input) in (first..).zip(inputs) {
+            if frame < self.remote_inputs.len() {
+                continue;
+            }
+            // A gap, the rest arrives with the next message.
+            if frame > self.remote_inputs.len() {
+                break;
+            }
+            if self
+                .used_remote
+                .get(frame)
+                .is_some_and(|&used| used != input)
+            {
+                let frame = frame as u32;
+                self.rollback = Some(self.rollback.map_or(frame, |earliest| earliest.min(frame)));
+            }
+            self.remote_inputs.push(input);
+        }
+    }
+
+    // Everything the other side hasn't confirmed yet. Lost messages don't matter since the next
+    // one repeats them.
+    fn send_inputs(&mut self) {
+        let first = self.acked.min(self.local_inputs.len());
+        let end = self.local_inputs.len().min(first + MAX_INPUTS);
+        let message = Message::Input {
+            ack: self.remote_inputs.len() as u32,
+            first: first as u32,
+            inputs: self.local_inputs[first..end].to_vec(),
+        };
+        // A lost message is like a dropped packet.
+        let _ = self.socket.send(&message.encode());
+    }
+}
+
+// Both sides send their save state and wait for the other's. Chunks go out a few at a time so
+// they don't overflow the other side's buffer, round and round until it says it has them all.
+fn handshake(socket: &UdpSocket, state: &[u8]) -> Result<Vec<u8>> {
+    socket.set_read_timeout(Some(RESEND_INTERVAL))?;
+    let start = Instant::now();
+    let mut last_sent: Option<Instant> = None;
+    let mut next_chunk = 0;
+    let mut received = Vec::new();
+    let mut chunks: Vec<bool> = Vec::new();
+    let mut peer_state: Option<Vec<u8>> = None;
+    let mut peer_ready = false;
+    let mut packet = [0; PACKET_SIZE];
+    loop {
+        if let (true, Some(peer_state)) = (peer_ready, &peer_state) {
+            return Ok(peer_state.clone());
+        }
+        if start.elapsed() > HANDSHAKE_TIMEOUT {
+            return Err(Error::other("The other player didn't answer."));
+        }
+        if !peer_ready && last_sent.is_none_or(|sent| sent.elapsed() >= RESEND_INTERVAL) {
+            let count = state.len().div_ceil(CHUNK_SIZE);
+            for _ in 0..CHUNKS_PER_SEND.min(count) {
+                let offset = next_chunk * CHUNK_SIZE;
+                let message = Message::State {
+                    total: state.len() as u32,
+                    offset: offset as u32,
+                    data: state[offset..state.len().min(offset + CHUNK_SIZE)].to_vec(),
+                };
+                let _ = socket.send(&message.encode());
+                next_chunk = (next_chunk + 1) % count;
+            }
+            last_sent = Some(Instant::now());
+        }
+
+        let length = match socket.recv(&mut packet) {
+            Ok(length) => length,
+            Err(error)
+                if matches!(
+                    error.kind(),
+                    ErrorKind::WouldBlock | ErrorKind::TimedOut | ErrorKind::ConnectionRefused
+                ) =>
+            {
+                continue
+            }
+            Err(error) => return Err(error),
+        };
+        match Message::parse(&packet[..length]) {
+            Some(Message::State {
+                total,
+                offset,
+                data,
+            }) => {
+                let (total, offset) = (total as usize, offset as usize);
+                // The first chunk sets the size, and chunks of any other size are ignored.
+                if chunks.is_empty() && (1..=MAX_STATE_SIZE).contains(&total) {
+                    received = vec![0; total];
+                    chunks = vec![false; total.div_ceil(CHUNK_SIZE)];
+                }
+                if received.len() != total
+                    || offset % CHUNK_SIZE != 0
+                    || offset + data.len() > total
+                {
+                    continue;
+                }
+                received[offset..offset + data.len()].copy_from_slice(&data);
+                chunks[offset / CHUNK_SIZE] = true;
+                if chunks.iter().all(|&chunk| chunk) {
+                    peer_state = Some(received.clone());
+                    let _ = socket.send(&[KIND_READY]);
+                }
+            }
+            // Buttons mean the other side is already playing.
+            Some(Message::Ready | Message::Input { .. }) => peer_ready = true,
+            None => {}
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum Message {
+    // A piece of a save state `total` bytes long.
+    State {
+        total: u32,
+        offset: u32,
+        data: Vec<u8>,
+    },
+    // The whole save state arrived.
+    Ready,
+    // The sender's buttons for the frames from `first` on, and how many of the receiver's it has.
+    Input {
+        ack: u32,
+        first: u32,
+        inputs: Vec<u8>,
+    },
+}
+
+impl Message {
+    fn encode(&self) -> Vec<u8> {
+        match self {
+            Message::State {
+                total,
+                offset,
+                data,
+            } => {
+                let mut packet = vec![KIND_STATE];
+                packet.extend_from_slice(&total.to_le_bytes());
+                packet.extend_from_slice(&offset.to_le_bytes());
+                packet.extend_from_slice(data);
+                packet
+            }
+            Message::Ready => vec![KIND_READY],
+            Message::Input { ack, first, inputs } => {
+                let mut packet = vec![KIND_INPUT];
+                packet.extend_from_slice(&ack.to_le_bytes());
+                packet.extend_from_slice(&first.to_le_bytes());
+                packet.push(inputs.len() as u8);
+                packet.extend_from_slice(inputs);
+                packet
+            }
+        }
+    }
+
+    // None for anything malformed, which is dropped like a lost packet.
+    fn parse(packet: &[u8]) -> Option<Message> {
+        let u32_at = |offset: usize| {
+            packet
+                .get(offset..offset + 4)
+                .map(|bytes| u32::from_le_bytes(bytes.try_into().unwrap()))
+        };
+        match *packet.first()? {
+            KIND_STATE => Some(Message::State {
+                total: u32_at(1)?,
+                offset: u32_at(5)?,
+                data: packet[9..].to_vec(),
+            }),
+            KIND_READY => Some(Message::Ready),
+            KIND_INPUT => {
+                let count = *packet.get(9)? as usize;
+                Some(Message::Input {
+                    ack: u32_at(1)?,
+                    first: u32_at(5)?,
+                    inputs: packet.get(10..10 + count)?.to_vec(),
+                })
+            }
+            _ => None,
+        }
+    }
+}
+
+struct Checkpoint {
+    // The frame this is the state before.
+    frame: u32,
+    local: Vec<u8>,
+    remote: Vec<u8>,
+    link: LinkState,
+}
+
+// The cable between the two Game Boys, which rolls back with them.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+struct LinkState {
+    // The byte each side shifts out while waiting for the other to clock a transfer.
+    waiting: [Option<u8>; 2],
+    // The byte each waiting side gets once the other clocked its transfer.
+    delivered: [Option<u8>; 2],
+}
+
+struct LinkEnd {
+    state: Rc<RefCell<LinkState>>,
+    side: usize,
+}
+
+impl SerialDevice for LinkEnd {
+    fn transfer(&mut self, byte: u8) -> u8 {
+        let mut state = self.state.borrow_mut();
+        let other = 1 - self.side;
+        match state.waiting[other].take() {
+            Some(received) => {
+                state.delivered[other] = Some(byte);
+                received
+            }
+            None => 0xFF,
+        }
+    }
+
+    fn clock(&mut self, byte: Option<u8>) -> Option<u8> {
+        let mut state = self.state.borrow_mut();
+        let side = self.side;
+        if let Some(received) = state.delivered[side].take() {
+            state.waiting[side] = None;
+            return byte.map(|_| received);
+        }
+        state.waiting[side] = byte;
+        None
+    }
+}
+
+const HOST: usize = 0;
+
+const GUEST: usize = 1;
+
+// How many frames a button press waits before the game sees it, to give it time to arrive.
+const INPUT_DELAY: usize = 2;
+
+// How many frames the other side's buttons can be guessed before waiting for them.
+const MAX_ROLLBACK: usize = 8;
+
+// The most buttons one message carries.
+const MAX_INPUTS: usize = 128;
+
+// A frame is run in 154 slices of a line's cycles, see `Netplay::run`.
+const SLICES: u32 = 154;
+
+const SLICE_CYCLES: u64 = 456;
+
+const CHUNK_SIZE: usize = 1024;
+
+const CHUNKS_PER_SEND: usize = 32;
+
+const PACKET_SIZE: usize = 2048;
+
+// Well over the largest save state, a CGB with 128 KB of cartridge RAM.
+const MAX_STATE_SIZE: usize = 1024 * 1024;
+
+const KIND_STATE: u8 = 0;
+
+const KIND_READY: u8 = 1;
+
+const KIND_INPUT: u8 = 2;
+
+const RESEND_INTERVAL: Duration = Duration::from_millis(20);
+
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(30);
+
+const DISCONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+
+#[cfg(test)]
+mod tests {
+    use std::thread;
+
+    use rstest::rstest;
+
+    use super::*;
+
+    fn emulator() -> (Emulator, Rom) {
+        let mut content = vec![0; 0x8000];
+        // LD A, 0x10; LDH (0x00), A; then over and over LDH A, (0x00); LD B, A; LDH A, (0x80);
+        // ADD B; LDH (0x80), A, adding up the buttons as the game sees them.
+        let program = [
+            0x3E, 0x10, 0xE0, 0x00, 0xF0, 0x00, 0x47, 0xF0, 0x80, 0x80, 0xE0, 0x80, 0x18, 0xF6,
+        ];
+        content[0x100..0x100 + program.len()].copy_from_slice(&program);
+        let emulator = Emulator::new(Rom::from_content(content.clone())).unwrap();
+        (emulator, Rom::from_content(content))
+    }
+
+    // Plays until `frames`, then waits for the other side's buttons for all of them and runs
+    // again what was guessed. Returns this side's states of both Game Boys.
+    fn play(
+        mut netplay: Netplay,
+        mut local: Emulator,
+        frames: u32,
+        buttons: fn(u32) -> u8,
+    ) -> (Vec<u8>, Vec<u8>) {
+        while netplay.frame() < frames {
+            local.set_buttons(buttons(netplay.frame()));
+            if !netplay.advance(&mut local).unwrap() {
+                thread::sleep(Duration::from_millis(1));
+            }
+        }
+        while netplay.remote_inputs.len() < frames as usize {
+            netplay.receive().unwrap();
+            netplay.send_inputs();
+            thread::sleep(Duration::from_millis(1));
+        }
+        // Long enough for the other side to get the last buttons too.
+        for _ in 0..50 {
+            netplay.send_inputs();
+            thread::sleep(Duration::from_millis(1));
+        }
+        if let Some(from) = netplay.rollback.take() {
+            netplay.run(&mut local, from, frames);
+        }
+        let now = netplay.history.back().unwrap();
+        (now.local.clone(), now.remote.clone())
+    }
+
+    #[test]
+    fn test_both_sides_agree() {
+        // Arrange
+        let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let port = socket.local_addr().unwrap().port();
+        let host = thread::spawn(move || {
+            let (mut local, rom) = emulator();
+            let netplay = Netplay::start(socket, HOST, &mut local, rom).unwrap();
+            play(netplay, local, 60, |frame| (frame / 7 % 4) as u8 * 0x10)
+        });
+
+        // Act
+        let (mut local, rom) = emulator();
+        let netplay = Netplay::join(("127.0.0.1", port), &mut local, rom).unwrap();
+        let guest = play(netplay, local, 60, |frame| (frame / 3 % 2) as u8 * 0x20);
+        let host = host.join().unwrap();
+
+        // Assert
+        assert_eq!(host.0, guest.1);
+        assert_eq!(host.1, guest.0);
+    }
+
+    #[test]
+    fn test_link_transfer() {
+        // Arrange
+        let state = Rc::new(RefCell::new(LinkState::default()));
+        let mut master = LinkEnd {
+            state: state.clone(),
+            side: HOST,
+        };
+        let mut slave = LinkEnd { state, side: GUEST };
+
+        // Act
+        assert_eq!(slave.clock(Some(0x42)), None);
+        let received = master.transfer(0x29);
+
+        // Assert
+        assert_eq!(received, 0x42);
+        assert_eq!(slave.clock(Some(0x42)), Some(0x29));
+        assert_eq!(master.transfer(0x29), 0xFF);
+    }
+
+    #[test]
+    fn test_link_transfer_when_not_waiting() {
+        let state = Rc::new(RefCell::new(LinkState::default()));
+        let mut master = LinkEnd {
+            state: state.clone(),
+            side: HOST,
+        };
+        let mut slave = LinkEnd { state, side: GUEST };
+
+        slave.clock(Some(0x42));
+        slave.clock(None);
+
+        assert_eq!(master.transfer(0x29), 0xFF);
+    }
+
+    #[rstest]
+    #[case(Message::Ready)]
+    #[case(Message::State { total: 3000, offset: 2048, data: vec![1, 2, 3] })]
+    #[case(Message::Input { ack: 5, first: 7, inputs: vec![0x10, 0x20] })]
+    fn test_message_round_trip(#[case] message: Message) {
+        assert_eq!(Message::parse(&message.encode()), Some(message));
+    }
+
+    #[rstest]
+    #[case(&[])]
+    #[case(&[0xFF])]
+    #[case(&[KIND_STATE, 0, 0])]
+    #[case(&[KIND_INPUT, 0, 0, 0, 0, 0, 0, 0, 0, 3, 0x10])]
+    fn test_malformed_message(#[case] packet: &[u8]) {
+        assert_eq!(Message::parse(packet), None);
+    }
+
+    #[test]
+    fn test_handshake_ignores_other_sizes() {
+        // Arrange
+        let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let peer = UdpSocket::bind("127.0.0.1:0").unwrap();
+        socket.connect(peer.local_addr().unwrap()).unwrap();
+        peer.connect(socket.local_addr().unwrap()).unwrap();
+        let state = vec![7; CHUNK_SIZE + 10];
+        let chunk = |total: usize, offset: usize| Message::State {
+            total: total as u32,
+            offset: offset as u32,
+            data: state[offset..state.len().min(offset + CHUNK_SIZE)].to_vec(),
+        };
+
+        // Act
+        // Too large to allocate, then the real state with a chunk of another size in between.
+        for message in [
+            chunk(u32::MAX as usize, 0),
+            chunk(state.len(), 0),
+            chunk(5 * CHUNK_SIZE, CHUNK_SIZE),
+            chunk(state.len(), CHUNK_SIZE),
+        ] {
+            peer.send(&message.encode()).unwrap();
+        }
+        peer.send(&[KIND_READY]).unwrap();
+        let received = handshake(&socket, &[1, 2, 3]).unwrap();
+
+        // Assert
+        assert_eq!(received, state);
+    }
+
+    #[test]
+    fn test_misprediction_rolls_back() {
+        // Arrange
+        let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let (_, rom) = emulator();
+        let mut netplay = Netplay {
+            socket,
+            side: HOST,
+            remote: Emulator::new(rom).unwrap(),
+            link: Rc::default(),
+            frame: 3,
+            local_inputs: vec![0; 5],
+            remote_inputs: vec![0],
+            used_remote: vec![0, 0, 0],
+            acked: 0,
+            rollback: None,
+            history: VecDeque::new(),
+            last_heard: Instant::now(),
+        };
+
+        // Act
+        netplay.add_remote_inputs(0, &[0, 0, 0x10, 0x10]);
+
+        // Assert
+        assert_eq!(netplay.rollback, Some(2));
+        assert_eq!(netplay.remote_inputs, [0, 0, 0x10, 0x10]);
+        assert_eq!(netplay.remote_input(5), 0x10);
+    }
+}
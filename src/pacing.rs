@@ -0,0 +1,317 @@
+use std::fmt;
+use std::io::{Error, Result};
+use std::str::FromStr;
+use std::time::{Duration, Instant};
+
+use crate::apu::CPU_CLOCK;
+use crate::emulator::CYCLES_PER_FRAME;
+
+// What the window waits on between frames. Sleeping by the clock is simplest, but the sound card's
+// and the display's clocks drift from it, which shows up as crackling when the audio queue runs
+// dry or as tearing. Syncing to one of them instead keeps that one clean.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum SyncMode {
+    // Sleeps until each frame is due, see `FramePacer`.
+    #[default]
+    Timer,
+    // Waits for the sound card to play the queued audio down far enough to fit the next frame's.
+    Audio,
+    // Waits for the display to refresh, running the frames due by then, see `RefreshPacer`. The
+    // sample rate follows the audio queue's fill, see `rate_control`.
+    Vsync,
+    // Runs as fast as the host can and drops the audio that doesn't fit.
+    None,
+}
+
+impl fmt::Display for SyncMode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            SyncMode::Timer => "timer",
+            SyncMode::Audio => "audio",
+            SyncMode::Vsync => "vsync",
+            SyncMode::None => "none",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+impl FromStr for SyncMode {
+    type Err = Error;
+
+    fn from_str(name: &str) -> Result<SyncMode> {
+        match name.to_ascii_lowercase().as_str() {
+            "timer" => Ok(SyncMode::Timer),
+            "audio" => Ok(SyncMode::Audio),
+            "vsync" => Ok(SyncMode::Vsync),
+            "none" => Ok(SyncMode::None),
+            _ => Err(Error::other(format!(
+                "{} is not a sync mode, expected timer, audio, vsync or none.",
+                name
+            ))),
+        }
+    }
+}
+
+// Keeps frames in step with real time at a speed multiplier: 2.0 runs twice as fast as the
+// hardware and 0.5 is slow motion. Turbo runs as fast as the host can without losing the speed
+// to go back to.
+pub struct FramePacer {
+    speed: f64,
+    turbo: bool,
+    // When the last frame was due.
+    deadline: Option<Instant>,
+}
+
+impl FramePacer {
+    pub fn new(speed: f64) -> Result<FramePacer> {
+        let mut pacer = FramePacer {
+            speed: 1.0,
+            turbo: false,
+            deadline: None,
+        };
+        pacer.set_speed(speed)?;
+        Ok(pacer)
+    }
+
+    pub fn speed(&self) -> f64 {
+        self.speed
+    }
+
+    pub fn set_speed(&mut self, speed: f64) -> Result<()> {
+        self.speed = check_speed(speed)?;
+        self.deadline = None;
+        Ok(())
+    }
+
+    pub fn turbo(&self) -> bool {
+        self.turbo
+    }
+
+    pub fn set_turbo(&mut self, turbo: bool) {
+        self.turbo = turbo;
+        self.deadline = None;
+    }
+
+    // How long to wait after a frame finished at `now` before starting the next. A pacer that
+    // fell behind starts over from `now` instead of rushing to catch up.
+    pub fn frame_done(&mut self, now: Instant) -> Duration {
+        if self.turbo {
+            return Duration::ZERO;
+        }
+        let deadline = self.deadline.unwrap_or(now) + FRAME_DURATION.div_f64(self.speed);
+        if deadline > now {
+            self.deadline = Some(deadline);
+            deadline - now
+        } else {
+            self.deadline = Some(now);
+            Duration::ZERO
+        }
+    }
+}
+
+// How many frames to run for each refresh of a display, so the game keeps its speed on any refresh
+// rate. When that's within MAX_SKEW of a whole number, or of one frame every whole number of
+// refreshes, it runs exactly that and the game runs that much faster or slower, since a frame
+// skipped or doubled now and then stutters. That's 0.5% fast on a 60 Hz display.
+pub struct RefreshPacer {
+    refresh_rate: f64,
+    frames_per_refresh: f64,
+    // Frames due but not run yet, less than one.
+    due: f64,
+}
+
+impl RefreshPacer {
+    pub fn new(refresh_rate: f64, speed: f64) -> Result<RefreshPacer> {
+        if !(refresh_rate.is_finite() && refresh_rate > 0.0) {
+            return Err(Error::other(format!(
+                "{} Hz is not a refresh rate.",
+                refresh_rate
+            )));
+        }
+        let mut pacer = RefreshPacer {
+            refresh_rate,
+            frames_per_refresh: 1.0,
+            due: 0.0,
+        };
+        pacer.set_speed(speed)?;
+        Ok(pacer)
+    }
+
+    pub fn set_speed(&mut self, speed: f64) -> Result<()> {
+        let exact = check_speed(speed)? / FRAME_DURATION.as_secs_f64() / self.refresh_rate;
+        let whole = if exact >= 1.0 {
+            exact.round()
+        } else {
+            1.0 / (1.0 / exact).round()
+        };
+        self.frames_per_refresh = if (whole / exact - 1.0).abs() <= MAX_SKEW {
+            whole
+        } else {
+            exact
+        };
+        self.due = 0.0;
+        Ok(())
+    }
+
+    pub fn refresh_rate(&self) -> f64 {
+        self.refresh_rate
+    }
+
+    // How much faster than asked the game runs, which the emulator's speed has to include for the
+    // sound to keep up.
+    pub fn skew(&self) -> f64 {
+        self.frames_per_refresh * self.refresh_rate * FRAME_DURATION.as_secs_f64()
+    }
+
+    // The frames to run before the next refresh.
+    pub fn refresh(&mut self) -> u32 {
+        self.due += self.frames_per_refresh;
+        let frames = self.due.floor();
+        self.due -= frames;
+        frames as u32
+    }
+}
+
+// Dynamic rate control: what to scale the sample rate by with the audio queue `fill` full, from 0
+// to 1. Below half full the emulator makes a little more sound per frame and above half a little
+// less, which keeps the queue from running dry or over without a pitch change anyone can hear.
+pub fn rate_control(fill: f64) -> f64 {
+    1.0 + (1.0 - 2.0 * fill.clamp(0.0, 1.0)) * MAX_RATE_ADJUSTMENT
+}
+
+// Speeds have to be positive, and anything past MAX_SPEED is what turbo is for.
+pub fn check_speed(speed: f64) -> Result<f64> {
+    if speed.is_finite() && (MIN_SPEED..=MAX_SPEED).contains(&speed) {
+        Ok(speed)
+    } else {
+        Err(Error::other(format!(
+            "{} is not a speed between {} and {}.",
+            speed, MIN_SPEED, MAX_SPEED
+        )))
+    }
+}
+
+// The Game Boy runs at ~59.7 frames per second.
+pub const FRAME_DURATION: Duration =
+    Duration::from_nanos(CYCLES_PER_FRAME as u64 * 1_000_000_000 / CPU_CLOCK);
+
+pub const MIN_SPEED: f64 = 0.1;
+
+pub const MAX_SPEED: f64 = 16.0;
+
+const MAX_SKEW: f64 = 0.01;
+
+const MAX_RATE_ADJUSTMENT: f64 = 0.005;
+
+#[cfg(test)]
+mod tests {
+    use rstest::rstest;
+
+    use super::*;
+
+    #[rstest]
+    #[case(1.0, FRAME_DURATION)]
+    #[case(2.0, FRAME_DURATION / 2)]
+    #[case(0.5, FRAME_DURATION * 2)]
+    fn test_frame_done(#[case] speed: f64, #[case] expected: Duration) {
+        let mut pacer = FramePacer::new(speed).unwrap();
+        let start = Instant::now();
+
+        let delay = pacer.frame_done(start);
+
+        assert!(delay.abs_diff(expected) < Duration::from_micros(1));
+    }
+
+    #[test]
+    fn test_frame_done_keeps_schedule() {
+        // Arrange
+        let mut pacer = FramePacer::new(1.0).unwrap();
+        let start = Instant::now();
+        pacer.frame_done(start);
+
+        // Act
+        // The next frame took a millisecond longer to run than the wait.
+        let delay = pacer.frame_done(start + FRAME_DURATION + Duration::from_millis(1));
+
+        // Assert
+        assert!(
+            delay.abs_diff(FRAME_DURATION - Duration::from_millis(1)) < Duration::from_micros(1)
+        );
+    }
+
+    #[test]
+    fn test_frame_done_when_behind() {
+        let mut pacer = FramePacer::new(1.0).unwrap();
+        let start = Instant::now();
+        pacer.frame_done(start);
+
+        let delay = pacer.frame_done(start + FRAME_DURATION * 5);
+
+        assert_eq!(delay, Duration::ZERO);
+        assert!(pacer.frame_done(start + FRAME_DURATION * 5) > Duration::ZERO);
+    }
+
+    #[test]
+    fn test_turbo() {
+        let mut pacer = FramePacer::new(0.5).unwrap();
+
+        pacer.set_turbo(true);
+
+        assert_eq!(pacer.frame_done(Instant::now()), Duration::ZERO);
+        assert_eq!(pacer.speed(), 0.5);
+    }
+
+    #[rstest]
+    #[case(0.0)]
+    #[case(-1.0)]
+    #[case(100.0)]
+    #[case(f64::NAN)]
+    fn test_invalid_speed(#[case] speed: f64) {
+        assert!(FramePacer::new(speed).is_err());
+    }
+
+    #[rstest]
+    #[case("timer", Some(SyncMode::Timer))]
+    #[case("Audio", Some(SyncMode::Audio))]
+    #[case("vsync", Some(SyncMode::Vsync))]
+    #[case("none", Some(SyncMode::None))]
+    #[case("gsync", None)]
+    fn test_sync_mode_from_str(#[case] name: &str, #[case] expected: Option<SyncMode>) {
+        assert_eq!(name.parse::<SyncMode>().ok(), expected);
+    }
+
+    // Frames run over 10 refreshes.
+    #[rstest]
+    #[case(60.0, 1.0, 10)]
+    #[case(120.0, 1.0, 5)]
+    #[case(144.0, 1.0, 4)]
+    #[case(60.0, 2.0, 20)]
+    #[case(75.0, 1.0, 7)]
+    fn test_refresh_pacer(#[case] refresh_rate: f64, #[case] speed: f64, #[case] expected: u32) {
+        let mut pacer = RefreshPacer::new(refresh_rate, speed).unwrap();
+
+        let frames: u32 = (0..10).map(|_| pacer.refresh()).sum();
+
+        assert_eq!(frames, expected);
+    }
+
+    #[test]
+    fn test_refresh_pacer_skew() {
+        let on_60_hz = RefreshPacer::new(60.0, 1.0).unwrap();
+        let on_144_hz = RefreshPacer::new(144.0, 1.0).unwrap();
+
+        // Slightly faster to run a frame on every refresh, the game's own speed otherwise.
+        assert!((on_60_hz.skew() - 1.0046).abs() < 0.0001);
+        assert!((on_144_hz.skew() - 1.0).abs() < 1e-9);
+        assert!(RefreshPacer::new(0.0, 1.0).is_err());
+    }
+
+    #[rstest]
+    #[case(0.0, 1.005)]
+    #[case(0.5, 1.0)]
+    #[case(1.0, 0.995)]
+    #[case(2.0, 0.995)]
+    fn test_rate_control(#[case] fill: f64, #[case] expected: f64) {
+        assert!((rate_control(fill) - expected).abs() < 1e-9);
+    }
+}
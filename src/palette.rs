@@ -0,0 +1,219 @@
+use std::fs;
+use std::io::{Error, Result};
+use std::path::Path;
+use std::str::FromStr;
+
+use crate::ppu::DMG_COLORS;
+
+// The RGBA colors a DMG game's shades 0-3 are shown in, for the background and window and for
+// each of the two object palettes.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Palette {
+    pub bg: [[u8; 4]; 4],
+    pub obj0: [[u8; 4]; 4],
+    pub obj1: [[u8; 4]; 4],
+}
+
+impl Default for Palette {
+    fn default() -> Self {
+        PalettePreset::Gray.palette()
+    }
+}
+
+impl Palette {
+    // The same colors for the background and objects.
+    pub const fn uniform(colors: [[u8; 4]; 4]) -> Palette {
+        Palette {
+            bg: colors,
+            obj0: colors,
+            obj1: colors,
+        }
+    }
+
+    pub fn load(path: &Path) -> Result<Palette> {
+        let text = fs::read_to_string(path).map_err(|error| {
+            Error::new(
+                error.kind(),
+                format!("Could not read {}: {}", path.display(), error),
+            )
+        })?;
+        Palette::parse(&text)
+            .map_err(|error| Error::other(format!("{}: {}", path.display(), error)))
+    }
+
+    // Reads a JASC .pal file, the kind most palette sites offer, with the shades from lightest to
+    // darkest. 4 colors are used for everything and 12 are the background's followed by OBP0's
+    // and OBP1's.
+    pub fn parse(text: &str) -> Result<Palette> {
+        let mut lines = text.lines().map(str::trim).filter(|line| !line.is_empty());
+        if lines.next() != Some("JASC-PAL") || lines.next() != Some("0100") {
+            return Err(Error::other("Not a JASC palette, expected JASC-PAL 0100."));
+        }
+        let count = lines.next().and_then(|count| count.parse::<usize>().ok());
+        if !matches!(count, Some(4 | 12)) {
+            return Err(Error::other("A palette needs 4 or 12 colors."));
+        }
+
+        let colors = lines.map(parse_color).collect::<Result<Vec<_>>>()?;
+        if Some(colors.len()) != count {
+            return Err(Error::other(format!(
+                "The palette says it has {} colors but has {}.",
+                count.unwrap_or_default(),
+                colors.len()
+            )));
+        }
+        let shades = |start: usize| {
+            let mut shades = [[0; 4]; 4];
+            shades.copy_from_slice(&colors[start..start + 4]);
+            shades
+        };
+        Ok(if colors.len() == 4 {
+            Palette::uniform(shades(0))
+        } else {
+            Palette {
+                bg: shades(0),
+                obj0: shades(4),
+                obj1: shades(8),
+            }
+        })
+    }
+}
+
+fn parse_color(line: &str) -> Result<[u8; 4]> {
+    let channels = line
+        .split_whitespace()
+        .map(str::parse::<u8>)
+        .collect::<std::result::Result<Vec<_>, _>>();
+    match channels.as_deref() {
+        Ok(&[red, green, blue]) => Ok([red, green, blue, 0xFF]),
+        _ => Err(Error::other(format!(
+            "{} is not a color, expected red, green and blue from 0 to 255.",
+            line
+        ))),
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PalettePreset {
+    // Even steps of gray, the colors before palettes could be picked.
+    Gray,
+    // The original DMG's green screen.
+    Green,
+    // The Game Boy Pocket's grayish screen.
+    Pocket,
+    // Black and white with two strong colors between them, for telling shades apart easily.
+    HighContrast,
+}
+
+impl PalettePreset {
+    pub const ALL: [PalettePreset; 4] = [
+        PalettePreset::Gray,
+        PalettePreset::Green,
+        PalettePreset::Pocket,
+        PalettePreset::HighContrast,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            PalettePreset::Gray => "gray",
+            PalettePreset::Green => "green",
+            PalettePreset::Pocket => "pocket",
+            PalettePreset::HighContrast => "high-contrast",
+        }
+    }
+
+    pub fn palette(self) -> Palette {
+        Palette::uniform(match self {
+            PalettePreset::Gray => DMG_COLORS,
+            PalettePreset::Green => GREEN,
+            PalettePreset::Pocket => POCKET,
+            PalettePreset::HighContrast => HIGH_CONTRAST,
+        })
+    }
+}
+
+impl FromStr for PalettePreset {
+    type Err = Error;
+
+    fn from_str(name: &str) -> Result<PalettePreset> {
+        PalettePreset::ALL
+            .into_iter()
+            .find(|preset| preset.name().eq_ignore_ascii_case(name))
+            .ok_or_else(|| {
+                Error::other(format!(
+                    "{} is not a palette, expected gray, green, pocket or high-contrast.",
+                    name
+                ))
+            })
+    }
+}
+
+const GREEN: [[u8; 4]; 4] = [
+    [0x9B, 0xBC, 0x0F, 0xFF],
+    [0x8B, 0xAC, 0x0F, 0xFF],
+    [0x30, 0x62, 0x30, 0xFF],
+    [0x0F, 0x38, 0x0F, 0xFF],
+];
+
+const POCKET: [[u8; 4]; 4] = [
+    [0xC4, 0xCF, 0xA1, 0xFF],
+    [0x8B, 0x95, 0x6D, 0xFF],
+    [0x4D, 0x53, 0x3C, 0xFF],
+    [0x1F, 0x1F, 0x1F, 0xFF],
+];
+
+const HIGH_CONTRAST: [[u8; 4]; 4] = [
+    [0xFF, 0xFF, 0xFF, 0xFF],
+    [0xFF, 0xD0, 0x00, 0xFF],
+    [0xD0, 0x00, 0x50, 0xFF],
+    [0x00, 0x00, 0x00, 0xFF],
+];
+
+#[cfg(test)]
+mod tests {
+    use rstest::rstest;
+
+    use super::*;
+
+    #[test]
+    fn test_parse_uniform() {
+        let text = "JASC-PAL\r\n0100\r\n4\r\n255 255 255\r\n170 170 170\r\n85 85 85\r\n0 0 0\r\n";
+
+        let palette = Palette::parse(text).unwrap();
+
+        assert_eq!(palette, PalettePreset::Gray.palette());
+    }
+
+    #[test]
+    fn test_parse_separate_objects() {
+        let mut text = String::from("JASC-PAL\n0100\n12\n");
+        for shade in 0..12 {
+            text += &format!("{} 0 0\n", shade);
+        }
+
+        let palette = Palette::parse(&text).unwrap();
+
+        assert_eq!(palette.bg[3], [3, 0, 0, 0xFF]);
+        assert_eq!(palette.obj0[0], [4, 0, 0, 0xFF]);
+        assert_eq!(palette.obj1[3], [11, 0, 0, 0xFF]);
+    }
+
+    #[rstest]
+    #[case("GIMP Palette\n", "Not a JASC palette")]
+    #[case("JASC-PAL\n0100\n16\n", "4 or 12 colors")]
+    #[case("JASC-PAL\n0100\n4\n0 0 0\n", "has 4 colors but has 1")]
+    #[case("JASC-PAL\n0100\n4\n0 0 256\n", "0 0 256 is not a color")]
+    fn test_parse_errors(#[case] text: &str, #[case] message: &str) {
+        let error = Palette::parse(text).unwrap_err();
+
+        assert!(error.to_string().contains(message), "{}", error);
+    }
+
+    #[rstest]
+    #[case("green", Some(PalettePreset::Green))]
+    #[case("High-Contrast", Some(PalettePreset::HighContrast))]
+    #[case("sepia", None)]
+    fn test_parse_preset(#[case] name: &str, #[case] expected: Option<PalettePreset>) {
+        assert_eq!(name.parse::<PalettePreset>().ok(), expected);
+    }
+}
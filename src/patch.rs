@@ -0,0 +1,374 @@
+use std::io::{Error, Result};
+
+use crate::hash::crc32;
+use crate::rom::MAX_ROM_SIZE;
+
+// Applies an IPS or BPS patch to a ROM image, telling them apart by their magic number. BPS
+// patches carry checksums of the ROM they were made for and of the result, and both are checked.
+pub fn apply(rom: &[u8], patch: &[u8]) -> Result<Vec<u8>> {
+    if patch.starts_with(IPS_MAGIC) {
+        apply_ips(rom, patch)
+    } else if patch.starts_with(BPS_MAGIC) {
+        apply_bps(rom, patch)
+    } else {
+        Err(Error::other("The patch is neither an IPS nor a BPS patch."))
+    }
+}
+
+// Records of a 3 byte offset and a 2 byte length followed by that many bytes, or by a 2 byte count
+// and a byte to repeat when the length is 0. Records past the end grow the ROM, and a 3 byte
+// length after the EOF marker truncates it.
+fn apply_ips(rom: &[u8], patch: &[u8]) -> Result<Vec<u8>> {
+    let mut reader = PatchReader::new(&patch[IPS_MAGIC.len()..]);
+    let mut output = rom.to_vec();
+    loop {
+        let record = reader.bytes(3)?;
+        if record == IPS_EOF {
+            break;
+        }
+        let offset = be(record);
+        let length = be(reader.bytes(2)?);
+        let (data, length) = if length == 0 {
+            let count = be(reader.bytes(2)?);
+            (None, count)
+        } else {
+            (Some(reader.bytes(length)?), length)
+        };
+        if output.len() < offset + length {
+            output.resize(offset + length, 0);
+        }
+        match data {
+            Some(data) => output[offset..offset + length].copy_from_slice(data),
+            None => output[offset..offset + length].fill(reader.byte()?),
+        }
+    }
+    if let Ok(length) = reader.bytes(3) {
+        output.truncate(be(length));
+    }
+    Ok(output)
+}
+
+// See https://www.romhacking.net/documents/746/ for the format.
+fn apply_bps(rom: &[u8], patch: &[u8]) -> Result<Vec<u8>> {
+    if patch.len() < BPS_MAGIC.len() + BPS_FOOTER {
+        return Err(Error::other("The BPS patch is truncated."));
+    }
+    let (body, footer) = patch.split_at(patch.len() - BPS_FOOTER);
+    let crc = |index: usize| u32::from_le_bytes(footer[index..index + 4].try_into().unwrap());
+    let (source_crc, target_crc, patch_crc) = (crc(0), crc(4), crc(8));
+    if crc32(&patch[..patch.len() - 4]) != patch_crc {
+        return Err(Error::other(
+            "The BPS patch is corrupt, its checksum doesn't match.",
+        ));
+    }
+    if crc32(rom) != source_crc {
+        return Err(Error::other(format!(
+            "The BPS patch is for a ROM with CRC32 {:08X}, not {:08X}.",
+            source_crc,
+            crc32(rom)
+        )));
+    }
+
+    let mut reader = PatchReader::new(&body[BPS_MAGIC.len()..]);
+    let source_size = reader.number()?;
+    let target_size = reader.number()?;
+    let metadata_size = reader.number()?;
+    reader.bytes(metadata_size)?;
+    if source_size != rom.len() {
+        return Err(Error::other(format!(
+            "The BPS patch is for a {} byte ROM, not {} bytes.",
+            source_size,
+            rom.len()
+        )));
+    }
+    if target_size > MAX_ROM_SIZE {
+        return Err(Error::other(format!(
+            "The BPS patch makes a {} byte ROM, more than a cartridge can hold.",
+            target_size
+        )));
+    }
+
+    let out_of_range = || Error::other("The BPS patch copies from outside the ROM.");
+    let mut output = Vec::with_capacity(target_size);
+    let (mut source_offset, mut target_offset) = (0, 0);
+    while !reader.is_empty() {
+        let action = reader.number()?;
+        let length = (action >> 2) + 1;
+        if length > target_size - output.len() {
+            return Err(Error::other(
+                "The BPS patch writes past the end of the ROM.",
+            ));
+        }
+        match action & 0x03 {
+            BPS_SOURCE_READ => {
+                let start = output.len();
+                let bytes = rom.get(start..start + length).ok_or_else(out_of_range)?;
+                output.extend_from_slice(bytes);
+            }
+            BPS_TARGET_READ => output.extend_from_slice(reader.bytes(length)?),
+            BPS_SOURCE_COPY => {
+                source_offset = reader.offset(source_offset)?;
+                let end = source_offset.checked_add(length).ok_or_else(out_of_range)?;
+                let bytes = rom.get(source_offset..end).ok_or_else(out_of_range)?;
+                output.extend_from_slice(bytes);
+                source_offset = end;
+            }
+            _ => {
+                target_offset = reader.offset(target_offset)?;
+                // The copy can overlap what it writes, which repeats a pattern.
+                for _ in 0..length {
+                    let byte = *output.get(target_offset).ok_or_else(out_of_range)?;
+                    output.push(byte);
+                    target_offset += 1;
+                }
+            }
+        }
+    }
+
+    if output.len() != target_size || crc32(&output) != target_crc {
+        return Err(Error::other(
+            "Patching didn't produce the ROM the BPS patch expects.",
+        ));
+    }
+    Ok(output)
+}
+
+struct PatchReader<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> PatchReader<'a> {
+    fn new(data: &'a [u8]) -> PatchReader<'a> {
+        PatchReader { data }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.data.is_empty()
+    }
+
+    fn bytes(&mut self, count: usize) -> Result<&'a [u8]> {
+        if count > self.data.len() {
+            return Err(Error::other("The patch is truncated."));
+        }
+        let (bytes, rest) = self.data.split_at(count);
+        self.data = rest;
+        Ok(bytes)
+    }
+
+    fn byte(&mut self) -> Result<u8> {
+        Ok(self.bytes(1)?[0])
+    }
+
+    // BPS numbers are 7 bits per byte, least significant first, ending with the byte that has
+    // bit 7 set. Each continuation also adds one so every number has a single encoding.
+    fn number(&mut self) -> Result<usize> {
+        let too_large = || Error::other("The patch has a number that's too large.");
+        let mut number: usize = 0;
+        let mut shift: usize = 1;
+        loop {
+            let byte = self.byte()?;
+            number = (byte as usize & 0x7F)
+                .checked_mul(shift)
+                .and_then(|value| number.checked_add(value))
+                .ok_or_else(too_large)?;
+            if byte & 0x80 != 0 {
+                return Ok(number);
+            }
+            shift = shift
+                .checked_shl(7)
+                .filter(|&shift| shift != 0)
+                .ok_or_else(too_large)?;
+            number = number.checked_add(shift).ok_or_else(too_large)?;
+        }
+    }
+
+    // Copies move their offset by a signed amount, with the sign in the lowest bit.
+    fn offset(&mut self, offset: usize) -> Result<usize> {
+        let number = self.number()?;
+        let distance = number >> 1;
+        let moved = if number & 1 != 0 {
+            offset.checked_sub(distance)
+        } else {
+            offset.checked_add(distance)
+        };
+        moved.ok_or_else(|| Error::other("The BPS patch copies from outside the ROM."))
+    }
+}
+
+fn be(bytes: &[u8]) -> usize {
+    bytes
+        .iter()
+        .fold(0, |value, &byte| value << 8 | byte as usize)
+}
+
+const IPS_MAGIC: &[u8] = b"PATCH";
+
+const IPS_EOF: &[u8] = b"EOF";
+
+const BPS_MAGIC: &[u8] = b"BPS1";
+
+// The source, target and patch CRC32s.
+const BPS_FOOTER: usize = 12;
+
+const BPS_SOURCE_READ: usize = 0;
+
+const BPS_TARGET_READ: usize = 1;
+
+const BPS_SOURCE_COPY: usize = 2;
+
+#[cfg(test)]
+mod tests {
+    use rstest::rstest;
+
+    use super::*;
+
+    fn ips(records: &[u8]) -> Vec<u8> {
+        [IPS_MAGIC, records, IPS_EOF].concat()
+    }
+
+    #[rstest]
+    // Replaces bytes 1 and 2.
+    #[case(&[0, 0, 1, 0, 2, 0xAA, 0xBB], &[1, 0xAA, 0xBB, 4])]
+    // Fills bytes 0-2 with 0xCC.
+    #[case(&[0, 0, 0, 0, 0, 0, 3, 0xCC], &[0xCC, 0xCC, 0xCC, 4])]
+    // Writes past the end.
+    #[case(&[0, 0, 5, 0, 1, 0xDD], &[1, 2, 3, 4, 0, 0xDD])]
+    fn test_ips(#[case] records: &[u8], #[case] expected: &[u8]) {
+        let patched = apply(&[1, 2, 3, 4], &ips(records)).unwrap();
+
+        assert_eq!(patched, expected);
+    }
+
+    #[test]
+    fn test_ips_truncates() {
+        let mut patch = ips(&[]);
+        patch.extend_from_slice(&[0, 0, 2]);
+
+        assert_eq!(apply(&[1, 2, 3, 4], &patch).unwrap(), [1, 2]);
+    }
+
+    #[rstest]
+    #[case(b"PATCH\x00\x00\x01\x00\x05\xAA")]
+    #[case(b"PATCH")]
+    #[case(b"NOT A PATCH")]
+    fn test_invalid_patch(#[case] patch: &[u8]) {
+        assert!(apply(&[1, 2, 3, 4], patch).is_err());
+    }
+
+    fn number(mut value: usize) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        loop {
+            let low = (value & 0x7F) as u8;
+            value >>= 7;
+            if value == 0 {
+                bytes.push(low | 0x80);
+                return bytes;
+            }
+            bytes.push(low);
+            value -= 1;
+        }
+    }
+
+    fn bps(source: &[u8], target: &[u8], actions: &[u8]) -> Vec<u8> {
+        bps_sized(source, target.len(), crc32(target), actions)
+    }
+
+    fn bps_sized(source: &[u8], target_size: usize, target_crc: u32, actions: &[u8]) -> Vec<u8> {
+        let mut patch = BPS_MAGIC.to_vec();
+        patch.extend(number(source.len()));
+        patch.extend(number(target_size));
+        patch.extend(number(0));
+        patch.extend_from_slice(actions);
+        patch.extend_from_slice(&crc32(source).to_le_bytes());
+        patch.extend_from_slice(&target_crc.to_le_bytes());
+        patch.extend_from_slice(&crc32(&patch).to_le_bytes());
+        patch
+    }
+
+    fn bps_actions() -> Vec<u8> {
+        [
+            // Keep the first 2 bytes.
+            number((1 << 2) | BPS_SOURCE_READ),
+            // Write 0xEE.
+            number(BPS_TARGET_READ),
+            vec![0xEE],
+            // Copy source bytes 0-1.
+            number((1 << 2) | BPS_SOURCE_COPY),
+            number(0),
+            // Copy 6 bytes from byte 2 of the output, overlapping what it writes.
+            number((5 << 2) | 3),
+            number(2 << 1),
+        ]
+        .concat()
+    }
+
+    #[test]
+    fn test_bps() {
+        // Arrange
+        let source = [1, 2, 3, 4];
+        let target = [1, 2, 0xEE, 1, 2, 0xEE, 1, 2, 0xEE, 1, 2];
+        let patch = bps(&source, &target, &bps_actions());
+
+        // Act
+        let patched = apply(&source, &patch).unwrap();
+
+        // Assert
+        assert_eq!(patched, target);
+    }
+
+    #[test]
+    fn test_bps_checks_crcs() {
+        let source = [1, 2, 3, 4];
+        let target = [1, 2, 0xEE, 1, 2, 0xEE, 1, 2, 0xEE, 1, 2];
+        let mut patch = bps(&source, &target, &bps_actions());
+
+        // Another ROM.
+        assert!(apply(&[1, 2, 3, 5], &patch).is_err());
+        // A damaged patch.
+        let last = patch.len() - BPS_FOOTER - 1;
+        patch[last] ^= 0xFF;
+        assert!(apply(&source, &patch).is_err());
+        // A patch that doesn't produce what it says.
+        let patch = bps(&source, &[1, 2, 3], &bps_actions());
+        assert!(apply(&source, &patch).is_err());
+    }
+
+    #[rstest]
+    // A target far larger than any cartridge.
+    #[case(1 << 62, number(BPS_TARGET_READ))]
+    // Writes a byte, then repeats it a million times into a 4 byte target.
+    #[case(4, [number(BPS_TARGET_READ), vec![0xEE], number((999_999 << 2) | 3), number(0)].concat())]
+    // Copies from far past the end of the ROM.
+    #[case(4, [number((3 << 2) | BPS_SOURCE_COPY), number((usize::MAX >> 2) << 1)].concat())]
+    fn test_bps_rejects_oversized(#[case] target_size: usize, #[case] actions: Vec<u8>) {
+        let source = [1, 2, 3, 4];
+        let patch = bps_sized(&source, target_size, 0, &actions);
+
+        assert!(apply(&source, &patch).is_err());
+    }
+
+    #[rstest]
+    #[case(0)]
+    #[case(0x7F)]
+    #[case(0x80)]
+    #[case(0x4080)]
+    #[case(123_456_789)]
+    fn test_number_round_trip(#[case] value: usize) {
+        let bytes = number(value);
+
+        assert_eq!(PatchReader::new(&bytes).number().unwrap(), value);
+    }
+
+    #[test]
+    fn test_number_too_large() {
+        // Each continuation adds its shift, which runs past usize before the shift itself does.
+        let bytes = [[0x7F; 9].as_slice(), &[0x80]].concat();
+
+        let error = PatchReader::new(&bytes).number().unwrap_err();
+
+        assert_eq!(
+            error.to_string(),
+            "The patch has a number that's too large."
+        );
+    }
+}
@@ -0,0 +1,36 @@
+use std::path::{Path, PathBuf};
+
+// The first of game-001.png, game-002.png and so on next to `path` that doesn't exist yet, for
+// game.gb and an `extension` of png. Only the file stem of `path` is used, it doesn't have to exist.
+pub fn next_free_path(path: &Path, extension: &str) -> PathBuf {
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    (1..)
+        .map(|number| path.with_file_name(format!("{}-{:03}.{}", stem, number, extension)))
+        .find(|path| !path.exists())
+        .expect("there's always a free number")
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use tempfile::tempdir;
+
+    use super::*;
+
+    #[test]
+    fn test_next_free_path() {
+        // Arrange
+        let directory = tempdir().unwrap();
+        let rom_path = directory.path().join("game.gb");
+        fs::write(directory.path().join("game-001.wav"), []).unwrap();
+
+        // Act
+        let wav = next_free_path(&rom_path, "wav");
+        let png = next_free_path(&rom_path, "png");
+
+        // Assert
+        assert_eq!(wav, directory.path().join("game-002.wav"));
+        assert_eq!(png, directory.path().join("game-001.png"));
+    }
+}
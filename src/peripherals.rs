@@ -0,0 +1,4 @@
+// Devices that plug into the link port, see `serial::SerialDevice`.
+pub mod link;
+#[cfg(feature = "png")]
+pub mod printer;
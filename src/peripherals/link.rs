@@ -0,0 +1,196 @@
+use std::io::{ErrorKind, Read, Result, Write};
+use std::net::{TcpListener, TcpStream, ToSocketAddrs};
+use std::time::Duration;
+
+use crate::serial::SerialDevice;
+
+// A link cable to another emulator over TCP. Every message is two bytes: a kind and a data byte.
+// The side whose game clocks a transfer sends TRANSFER with its byte and waits for the REPLY with
+// the other side's, so both games see the bytes swap at once. When both clock a transfer at the
+// same time, each answers the other with 0xFF like an empty port would.
+pub struct TcpLink {
+    stream: TcpStream,
+    // A message only partly read while polling.
+    buffer: [u8; 2],
+    buffered: usize,
+    connected: bool,
+}
+
+impl TcpLink {
+    pub fn connect(address: impl ToSocketAddrs) -> Result<TcpLink> {
+        TcpLink::new(TcpStream::connect(address)?)
+    }
+
+    // Waits for the other emulator to connect.
+    pub fn listen(address: impl ToSocketAddrs) -> Result<TcpLink> {
+        let (stream, _) = TcpListener::bind(address)?.accept()?;
+        TcpLink::new(stream)
+    }
+
+    pub fn new(stream: TcpStream) -> Result<TcpLink> {
+        stream.set_nodelay(true)?;
+        stream.set_read_timeout(Some(REPLY_TIMEOUT))?;
+        Ok(TcpLink {
+            stream,
+            buffer: [0; 2],
+            buffered: 0,
+            connected: true,
+        })
+    }
+
+    // False once the other side hung up or the connection broke. The port then acts empty.
+    pub fn is_connected(&self) -> bool {
+        self.connected
+    }
+
+    fn send(&mut self, kind: u8, byte: u8) {
+        if self.stream.write_all(&[kind, byte]).is_err() {
+            self.connected = false;
+        }
+    }
+
+    // Returns the next message, or None if there isn't one yet. Without `wait` this never blocks,
+    // with it this gives up after REPLY_TIMEOUT.
+    fn receive(&mut self, wait: bool) -> Option<[u8; 2]> {
+        if self.stream.set_nonblocking(!wait).is_err() {
+            self.connected = false;
+            return None;
+        }
+        while self.buffered < self.buffer.len() {
+            match self.stream.read(&mut self.buffer[self.buffered..]) {
+                Ok(0) => {
+                    self.connected = false;
+                    return None;
+                }
+                Ok(read) => self.buffered += read,
+                Err(error) if error.kind() == ErrorKind::Interrupted => {}
+                Err(error)
+                    if matches!(error.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) =>
+                {
+                    return None
+                }
+                Err(_) => {
+                    self.connected = false;
+                    return None;
+                }
+            }
+        }
+        self.buffered = 0;
+        Some(self.buffer)
+    }
+}
+
+impl SerialDevice for TcpLink {
+    fn transfer(&mut self, byte: u8) -> u8 {
+        if !self.connected {
+            return 0xFF;
+        }
+        self.send(TRANSFER, byte);
+        while self.connected {
+            match self.receive(true) {
+                Some([REPLY, received]) => return received,
+                Some([TRANSFER, _]) => self.send(REPLY, 0xFF),
+                Some(_) => self.connected = false,
+                // The other side is paused or gone. A late reply gets dropped by `clock`.
+                None => break,
+            }
+        }
+        0xFF
+    }
+
+    fn clock(&mut self, byte: Option<u8>) -> Option<u8> {
+        while self.connected {
+            match self.receive(false)? {
+                [TRANSFER, received] => {
+                    self.send(REPLY, byte.unwrap_or(0xFF));
+                    if byte.is_some() {
+                        return Some(received);
+                    }
+                }
+                [REPLY, _] => {}
+                _ => self.connected = false,
+            }
+        }
+        None
+    }
+}
+
+const TRANSFER: u8 = 0;
+
+const REPLY: u8 = 1;
+
+const REPLY_TIMEOUT: Duration = Duration::from_secs(1);
+
+#[cfg(test)]
+mod tests {
+    use std::thread;
+
+    use super::*;
+
+    fn pair() -> (TcpLink, TcpLink) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let client = TcpLink::connect(listener.local_addr().unwrap()).unwrap();
+        let (stream, _) = listener.accept().unwrap();
+        (client, TcpLink::new(stream).unwrap())
+    }
+
+    // Polls like the bus does until a transfer happens.
+    fn wait_for_transfer(link: &mut TcpLink, byte: u8) -> u8 {
+        loop {
+            if let Some(received) = link.clock(Some(byte)) {
+                return received;
+            }
+            thread::sleep(Duration::from_millis(1));
+        }
+    }
+
+    #[test]
+    fn test_transfer() {
+        // Arrange
+        let (mut master, mut slave) = pair();
+        let slave = thread::spawn(move || wait_for_transfer(&mut slave, 0x42));
+
+        // Act
+        let received = master.transfer(0x29);
+
+        // Assert
+        assert_eq!(received, 0x42);
+        assert_eq!(slave.join().unwrap(), 0x29);
+    }
+
+    #[test]
+    fn test_transfer_when_both_clock() {
+        let (mut first, mut second) = pair();
+
+        let second = thread::spawn(move || second.transfer(0x02));
+        let received = first.transfer(0x01);
+
+        assert_eq!(received, 0xFF);
+        assert_eq!(second.join().unwrap(), 0xFF);
+    }
+
+    #[test]
+    fn test_transfer_when_not_waiting() {
+        // Arrange
+        let (mut master, mut slave) = pair();
+        let master = thread::spawn(move || master.transfer(0x29));
+
+        // Act
+        while !master.is_finished() {
+            assert_eq!(slave.clock(None), None);
+            thread::sleep(Duration::from_millis(1));
+        }
+
+        // Assert
+        assert_eq!(master.join().unwrap(), 0xFF);
+    }
+
+    #[test]
+    fn test_disconnect() {
+        let (mut link, other) = pair();
+        drop(other);
+
+        assert_eq!(link.transfer(0x29), 0xFF);
+        assert!(!link.is_connected());
+    }
+}
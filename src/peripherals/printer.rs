@@ -0,0 +1,419 @@
+use std::fs::{self, File};
+use std::io::{BufWriter, Error, Result};
+use std::path::{Path, PathBuf};
+
+use crate::paths;
+use crate::serial::SerialDevice;
+
+// The Game Boy Printer. Games send it packets of 0x88 0x33, a command, a compression flag, a
+// little-endian length, the data and a checksum of everything after the magic bytes. The printer
+// answers the two bytes after each packet with 0x81 and its status.
+//
+// Printed images are saved as PNGs in the output directory. Prints without a margin between them
+// are joined into one image, since games like Pokémon print long pictures a strip at a time.
+pub struct Printer {
+    directory: PathBuf,
+    stage: Stage,
+    command: u8,
+    compressed: bool,
+    length: u16,
+    data: Vec<u8>,
+    sum: u16,
+    checksum: u16,
+    status: u8,
+    // Tile data received since the last print.
+    tiles: Vec<u8>,
+    // Status queries left until the current print finishes.
+    busy: u8,
+    // Printed lines not saved yet, 160 grayscale pixels each.
+    page: Vec<u8>,
+    pages: Vec<PathBuf>,
+    // The first page that failed to save, returned by `finish`.
+    error: Option<Error>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Stage {
+    Magic,
+    Magic2,
+    Command,
+    Compression,
+    LengthLow,
+    LengthHigh,
+    Data,
+    ChecksumLow,
+    ChecksumHigh,
+    Alive,
+    Status,
+}
+
+impl Printer {
+    pub fn new(directory: impl Into<PathBuf>) -> Printer {
+        Printer {
+            directory: directory.into(),
+            stage: Stage::Magic,
+            command: 0,
+            compressed: false,
+            length: 0,
+            data: Vec::new(),
+            sum: 0,
+            checksum: 0,
+            status: 0,
+            tiles: Vec::new(),
+            busy: 0,
+            page: Vec::new(),
+            pages: Vec::new(),
+            error: None,
+        }
+    }
+
+    // The images saved so far.
+    pub fn pages(&self) -> &[PathBuf] {
+        &self.pages
+    }
+
+    fn add_to_sum(&mut self, byte: u8) {
+        self.sum = self.sum.wrapping_add(byte as u16);
+    }
+
+    fn process_packet(&mut self) {
+        if self.checksum != self.sum {
+            self.status |= CHECKSUM_ERROR;
+            return;
+        }
+        self.status &= !CHECKSUM_ERROR;
+
+        match self.command {
+            INITIALIZE => {
+                self.tiles.clear();
+                self.status = 0;
+                self.busy = 0;
+            }
+            DATA => {
+                let data = std::mem::take(&mut self.data);
+                let data = if self.compressed {
+                    decompress(&data)
+                } else {
+                    data
+                };
+                let room = MAX_TILE_DATA - self.tiles.len();
+                self.tiles.extend(data.into_iter().take(room));
+                self.status |= UNPROCESSED_DATA;
+            }
+            PRINT if self.data.len() == 4 => {
+                // Also holds the number of sheets and the exposure, which don't matter here.
+                self.print(self.data[1], self.data[2]);
+                self.status = (self.status & !UNPROCESSED_DATA) | PRINTING | IMAGE_FULL;
+                self.busy = PRINT_STATUS_QUERIES;
+            }
+            STATUS if self.busy > 0 => {
+                self.busy -= 1;
+                if self.busy == 0 {
+                    self.status &= !(PRINTING | IMAGE_FULL);
+                }
+            }
+            STATUS => {}
+            _ => self.status |= PACKET_ERROR,
+        }
+    }
+
+    // The top margin's in the high nibble and the bottom one's in the low nibble.
+    fn print(&mut self, margins: u8, palette: u8) {
+        if margins >> 4 != 0 {
+            self.save_page();
+        }
+
+        // A palette of 0 means the usual one.
+        let palette = if palette == 0 { 0xE4 } else { palette };
+        let tiles = std::mem::take(&mut self.tiles);
+        for tile_row in tiles.chunks(TILES_PER_ROW * TILE_SIZE) {
+            for y in 0..8 {
+                for x in 0..PAGE_WIDTH {
+                    let index = (x / 8) * TILE_SIZE + y * 2;
+                    let (Some(&low), Some(&high)) = (tile_row.get(index), tile_row.get(index + 1))
+                    else {
+                        self.page.push(SHADES[0]);
+                        continue;
+                    };
+                    let bit = 7 - (x % 8);
+                    let color = ((high >> bit) & 1) << 1 | ((low >> bit) & 1);
+                    let shade = (palette >> (color * 2)) & 0x03;
+                    self.page.push(SHADES[shade as usize]);
+                }
+            }
+        }
+
+        if margins & 0x0F != 0 {
+            self.save_page();
+        }
+    }
+
+    fn save_page(&mut self) {
+        if self.page.is_empty() {
+            return;
+        }
+        let page = std::mem::take(&mut self.page);
+        match self.write_page(&page) {
+            Ok(path) => self.pages.push(path),
+            Err(error) => {
+                self.error.get_or_insert(error);
+            }
+        }
+    }
+
+    fn write_page(&self, page: &[u8]) -> Result<PathBuf> {
+        fs::create_dir_all(&self.directory)?;
+        let path = paths::next_free_path(&self.directory.join("print"), "png");
+        write_png(&path, page)?;
+        Ok(path)
+    }
+}
+
+impl SerialDevice for Printer {
+    fn transfer(&mut self, byte: u8) -> u8 {
+        let mut response = 0x00;
+        self.stage = match self.stage {
+            Stage::Magic if byte == MAGIC[0] => Stage::Magic2,
+            Stage::Magic => Stage::Magic,
+            Stage::Magic2 if byte == MAGIC[1] => Stage::Command,
+            Stage::Magic2 => Stage::Magic,
+            Stage::Command => {
+                self.command = byte;
+                self.sum = 0;
+                self.data.clear();
+                self.add_to_sum(byte);
+                Stage::Compression
+            }
+            Stage::Compression => {
+                self.compressed = byte & 0x01 != 0;
+                self.add_to_sum(byte);
+                Stage::LengthLow
+            }
+            Stage::LengthLow => {
+                self.length = byte as u16;
+                self.add_to_sum(byte);
+                Stage::LengthHigh
+            }
+            Stage::LengthHigh => {
+                self.length |= (byte as u16) << 8;
+                self.add_to_sum(byte);
+                if self.length == 0 {
+                    Stage::ChecksumLow
+                } else {
+                    Stage::Data
+                }
+            }
+            Stage::Data => {
+                self.data.push(byte);
+                self.add_to_sum(byte);
+                if self.data.len() == self.length as usize {
+                    Stage::ChecksumLow
+                } else {
+                    Stage::Data
+                }
+            }
+            Stage::ChecksumLow => {
+                self.checksum = byte as u16;
+                Stage::ChecksumHigh
+            }
+            Stage::ChecksumHigh => {
+                self.checksum |= (byte as u16) << 8;
+                Stage::Alive
+            }
+            Stage::Alive => {
+                response = ALIVE;
+                Stage::Status
+            }
+            Stage::Status => {
+                self.process_packet();
+                response = self.status;
+                Stage::Magic
+            }
+        };
+        response
+    }
+
+    fn finish(mut self: Box<Self>) -> Result<()> {
+        self.save_page();
+        match self.error {
+            Some(error) => Err(error),
+            None => Ok(()),
+        }
+    }
+}
+
+// Runs of 2-129 copies of a byte have the high bit set in their count, runs of 1-128 literal bytes
+// don't.
+fn decompress(data: &[u8]) -> Vec<u8> {
+    let mut output = Vec::new();
+    let mut bytes = data.iter().copied();
+    while let Some(count) = bytes.next() {
+        if count & 0x80 != 0 {
+            let Some(byte) = bytes.next() else {
+                break;
+            };
+            output.extend(std::iter::repeat_n(byte, (count & 0x7F) as usize + 2));
+        } else {
+            output.extend(bytes.by_ref().take(count as usize + 1));
+        }
+    }
+    output
+}
+
+fn write_png(path: &Path, page: &[u8]) -> Result<()> {
+    let file = BufWriter::new(File::create(path)?);
+    let mut encoder = png::Encoder::new(file, PAGE_WIDTH as u32, (page.len() / PAGE_WIDTH) as u32);
+    encoder.set_color(png::ColorType::Grayscale);
+    encoder.set_depth(png::BitDepth::Eight);
+    let mut writer = encoder.write_header().map_err(Error::other)?;
+    writer.write_image_data(page).map_err(Error::other)?;
+    writer.finish().map_err(Error::other)
+}
+
+const MAGIC: [u8; 2] = [0x88, 0x33];
+
+const ALIVE: u8 = 0x81;
+
+const INITIALIZE: u8 = 0x01;
+const PRINT: u8 = 0x02;
+const DATA: u8 = 0x04;
+const STATUS: u8 = 0x0F;
+
+const CHECKSUM_ERROR: u8 = 0x01;
+const PRINTING: u8 = 0x02;
+const IMAGE_FULL: u8 = 0x04;
+const UNPROCESSED_DATA: u8 = 0x08;
+const PACKET_ERROR: u8 = 0x10;
+
+// Long enough that games notice the printer was busy.
+const PRINT_STATUS_QUERIES: u8 = 4;
+
+const TILE_SIZE: usize = 16;
+
+const TILES_PER_ROW: usize = 20;
+
+const PAGE_WIDTH: usize = TILES_PER_ROW * 8;
+
+// The printer's RAM holds 9 packets of 2 tile rows.
+const MAX_TILE_DATA: usize = 0x2000;
+
+// White to black.
+const SHADES: [u8; 4] = [0xFF, 0xAA, 0x55, 0x00];
+
+#[cfg(test)]
+mod tests {
+    use rstest::rstest;
+    use tempfile::tempdir;
+
+    use super::*;
+
+    // Sends a packet and returns the printer's answers to the two bytes after it.
+    fn send(printer: &mut Printer, command: u8, data: &[u8]) -> (u8, u8) {
+        let mut packet = vec![command, 0x00, data.len() as u8, (data.len() >> 8) as u8];
+        packet.extend_from_slice(data);
+        let checksum = packet
+            .iter()
+            .fold(0u16, |sum, &byte| sum.wrapping_add(byte as u16));
+
+        for byte in MAGIC
+            .into_iter()
+            .chain(packet)
+            .chain(checksum.to_le_bytes())
+        {
+            assert_eq!(printer.transfer(byte), 0x00);
+        }
+        (printer.transfer(0x00), printer.transfer(0x00))
+    }
+
+    #[rstest]
+    #[case(&[0x03, 1, 2, 3, 4], &[1, 2, 3, 4])]
+    #[case(&[0x81, 0xAA], &[0xAA, 0xAA, 0xAA])]
+    #[case(&[0x80, 0x00, 0x00, 0x07], &[0x00, 0x00, 0x07])]
+    fn test_decompress(#[case] data: &[u8], #[case] expected: &[u8]) {
+        assert_eq!(decompress(data), expected);
+    }
+
+    #[test]
+    fn test_status() {
+        let directory = tempdir().unwrap();
+        let mut printer = Printer::new(directory.path());
+
+        assert_eq!(send(&mut printer, INITIALIZE, &[]), (ALIVE, 0x00));
+        assert_eq!(
+            send(&mut printer, DATA, &[0; 0x280]),
+            (ALIVE, UNPROCESSED_DATA)
+        );
+        assert_eq!(send(&mut printer, STATUS, &[]), (ALIVE, UNPROCESSED_DATA));
+    }
+
+    #[test]
+    fn test_checksum_error() {
+        // Arrange
+        let directory = tempdir().unwrap();
+        let mut printer = Printer::new(directory.path());
+        for byte in [0x88, 0x33, STATUS, 0x00, 0x00, 0x00, 0x00, 0x00] {
+            printer.transfer(byte);
+        }
+
+        // Act
+        let answer = (printer.transfer(0x00), printer.transfer(0x00));
+
+        // Assert
+        assert_eq!(answer, (ALIVE, CHECKSUM_ERROR));
+    }
+
+    #[test]
+    fn test_print() {
+        // Arrange
+        let directory = tempdir().unwrap();
+        let mut printer = Box::new(Printer::new(directory.path()));
+        // Two rows of tiles, the first tile all color 3.
+        let mut tiles = vec![0; 0x280];
+        tiles[..16].fill(0xFF);
+        send(&mut printer, INITIALIZE, &[]);
+        send(&mut printer, DATA, &tiles);
+        send(&mut printer, DATA, &[]);
+
+        // Act
+        let (_, status) = send(&mut printer, PRINT, &[1, 0x13, 0xE4, 0x40]);
+        for _ in 0..PRINT_STATUS_QUERIES {
+            send(&mut printer, STATUS, &[]);
+        }
+        let (_, done) = send(&mut printer, STATUS, &[]);
+
+        // Assert
+        assert_eq!(status, PRINTING | IMAGE_FULL);
+        assert_eq!(done, 0x00);
+        let path = directory.path().join("print-001.png");
+        assert_eq!(printer.pages(), std::slice::from_ref(&path));
+        let decoder = png::Decoder::new(File::open(&path).unwrap());
+        let mut reader = decoder.read_info().unwrap();
+        let mut pixels = vec![0; reader.output_buffer_size()];
+        reader.next_frame(&mut pixels).unwrap();
+        assert_eq!((reader.info().width, reader.info().height), (160, 16));
+        assert_eq!(pixels[0], 0x00);
+        assert_eq!(pixels[8], 0xFF);
+        assert!(printer.finish().is_ok());
+    }
+
+    #[test]
+    fn test_prints_without_margins_join() {
+        // Arrange
+        let directory = tempdir().unwrap();
+        let mut printer = Box::new(Printer::new(directory.path()));
+
+        // Act
+        for _ in 0..2 {
+            send(&mut printer, DATA, &[0; 0x280]);
+            send(&mut printer, PRINT, &[1, 0x00, 0xE4, 0x40]);
+        }
+        let pages = printer.pages().len();
+        printer.finish().unwrap();
+
+        // Assert
+        assert_eq!(pages, 0);
+        let decoder =
+            png::Decoder::new(File::open(directory.path().join("print-001.png")).unwrap());
+        assert_eq!(decoder.read_info().unwrap().info().height, 32);
+    }
+}
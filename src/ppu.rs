@@ -0,0 +1,1762 @@
+mod fifo;
+mod oam_bug;
+pub mod viewer;
+
+use std::io::{Error, Result};
+use std::ops::Range;
+use std::str::FromStr;
+
+use fifo::Fifo;
+pub use oam_bug::OamCorruption;
+
+use crate::accuracy::Accuracy;
+use crate::blend::FrameBlend;
+use crate::colorization::CompatibilityPalettes;
+use crate::interrupts::{STAT_INTERRUPT, VBLANK_INTERRUPT};
+use crate::model::EmulatorModel;
+use crate::palette::Palette;
+use crate::savestate::{Snapshot, StateReader, StateWriter};
+
+pub const SCREEN_WIDTH: usize = 160;
+
+pub const SCREEN_HEIGHT: usize = 144;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Mode {
+    HBlank = 0,
+    VBlank = 1,
+    OamScan = 2,
+    Drawing = 3,
+}
+
+// What the PPU draws a line from, which can be hidden one at a time for debugging.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Layer {
+    Background,
+    Window,
+    Sprites,
+}
+
+impl Layer {
+    pub const ALL: [Layer; 3] = [Layer::Background, Layer::Window, Layer::Sprites];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Layer::Background => "background",
+            Layer::Window => "window",
+            Layer::Sprites => "sprites",
+        }
+    }
+}
+
+impl FromStr for Layer {
+    type Err = Error;
+
+    fn from_str(name: &str) -> Result<Layer> {
+        match name.to_ascii_lowercase().as_str() {
+            "bg" | "background" => Ok(Layer::Background),
+            "window" => Ok(Layer::Window),
+            "obj" | "sprites" => Ok(Layer::Sprites),
+            _ => Err(Error::other(format!(
+                "{} is not a layer, expected background, window or sprites.",
+                name
+            ))),
+        }
+    }
+}
+
+pub struct Ppu {
+    // Color Game Boy mode: two VRAM banks, tile attributes and color palettes.
+    cgb: bool,
+    vram: Vec<u8>,
+    vram_bank: u8,
+    oam: Vec<u8>,
+    lcdc: u8,
+    stat: u8,
+    scy: u8,
+    scx: u8,
+    ly: u8,
+    lyc: u8,
+    bgp: u8,
+    obp0: u8,
+    obp1: u8,
+    wy: u8,
+    wx: u8,
+    mode: Mode,
+    line_dot: u32,
+    // The window keeps its own line counter which only advances on lines where it was drawn.
+    window_line: u8,
+    // How far the scanline renderer has drawn the current line. A register write in mode 3 first
+    // draws the pixels up to where the PPU has got to with the old value.
+    line_x: u8,
+    line_window: bool,
+    // SCX's low 3 bits, which only count when a line starts drawing.
+    fine_scroll: u8,
+    // The STAT interrupt line, high while any enabled source's condition holds. Only a rising edge
+    // requests an interrupt, so a source is blocked while another one keeps the line high.
+    stat_line: bool,
+    interrupts: u8,
+    frames: u64,
+    framebuffer: Vec<u8>,
+    bg_palettes: [u8; PALETTE_RAM_SIZE],
+    obj_palettes: [u8; PALETTE_RAM_SIZE],
+    // BCPS and OCPS: the palette RAM index in the low 6 bits and auto-increment in bit 7.
+    bg_palette_index: u8,
+    obj_palette_index: u8,
+    // OPRI: bit 0 gives CGB mode the DMG's object priority by X coordinate instead of OAM order.
+    opri: u8,
+    // Set when a visible line enters HBlank, which is when HDMA copies a block.
+    hblank_started: bool,
+    // Writing STAT on the monochrome models briefly enables every STAT source.
+    stat_write_bug: bool,
+    // The monochrome models also corrupt OAM when the CPU accesses it in mode 2.
+    oam_bug: bool,
+    // A CGB running a DMG cartridge: BGP, OBP0 and OBP1 pick colors from the first palettes.
+    dmg_compatibility: bool,
+    // What BGP, OBP0 and OBP1's shades look like in DMG mode.
+    dmg_palette: Palette,
+    // Averages finished frames for games that count on the LCD's ghosting.
+    frame_blend: Option<FrameBlend>,
+    // Indexed by Layer. Hidden layers aren't drawn whatever LCDC says.
+    layers_enabled: [bool; 3],
+    accuracy: Accuracy,
+    fifo: Fifo,
+}
+
+impl Default for Ppu {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Ppu {
+    pub fn new() -> Ppu {
+        Ppu {
+            cgb: false,
+            vram: vec![0; VRAM_BANK_SIZE],
+            vram_bank: 0,
+            oam: vec![0; OAM_SIZE],
+            lcdc: 0x91,
+            stat: 0,
+            scy: 0,
+            scx: 0,
+            ly: 0,
+            lyc: 0,
+            bgp: 0xFC,
+            obp0: 0xFF,
+            obp1: 0xFF,
+            wy: 0,
+            wx: 0,
+            mode: Mode::OamScan,
+            line_dot: 0,
+            window_line: 0,
+            line_x: 0,
+            line_window: false,
+            fine_scroll: 0,
+            stat_line: false,
+            interrupts: 0,
+            frames: 0,
+            framebuffer: vec![0xFF; SCREEN_WIDTH * SCREEN_HEIGHT * 4],
+            bg_palettes: [0xFF; PALETTE_RAM_SIZE],
+            obj_palettes: [0xFF; PALETTE_RAM_SIZE],
+            bg_palette_index: 0,
+            obj_palette_index: 0,
+            opri: 0,
+            hblank_started: false,
+            stat_write_bug: true,
+            oam_bug: true,
+            dmg_compatibility: false,
+            dmg_palette: Palette::default(),
+            frame_blend: None,
+            layers_enabled: [true; 3],
+            accuracy: Accuracy::Normal,
+            fifo: Fifo::default(),
+        }
+    }
+
+    pub fn new_cgb() -> Ppu {
+        Ppu {
+            cgb: true,
+            vram: vec![0; VRAM_BANK_SIZE * 2],
+            stat_write_bug: false,
+            oam_bug: false,
+            ..Ppu::new()
+        }
+    }
+
+    pub fn for_model(model: EmulatorModel, cgb_mode: bool) -> Ppu {
+        if cgb_mode {
+            return Ppu::new_cgb();
+        }
+
+        let mut ppu = Ppu::new();
+        if model.is_cgb() {
+            ppu.stat_write_bug = false;
+            ppu.oam_bug = false;
+            ppu.dmg_compatibility = true;
+            ppu.set_compatibility_palettes(CompatibilityPalettes::default());
+        }
+        ppu
+    }
+
+    // Loads the colors BGP, OBP0 and OBP1 map to in DMG compatibility mode.
+    pub fn set_compatibility_palettes(&mut self, palettes: CompatibilityPalettes) {
+        let bg = palettes.bg.iter();
+        let obj = palettes.obj0.iter().chain(palettes.obj1.iter());
+        let ram = self.bg_palettes[..8]
+            .chunks_exact_mut(2)
+            .zip(bg)
+            .chain(self.obj_palettes[..16].chunks_exact_mut(2).zip(obj));
+        for (bytes, color) in ram {
+            bytes.copy_from_slice(&color.to_le_bytes());
+        }
+    }
+
+    pub fn cgb(&self) -> bool {
+        self.cgb
+    }
+
+    pub fn dmg_palette(&self) -> &Palette {
+        &self.dmg_palette
+    }
+
+    // Takes effect from the next line drawn. CGB and compatibility mode colors come from palette
+    // RAM instead.
+    pub fn set_dmg_palette(&mut self, palette: Palette) {
+        self.dmg_palette = palette;
+    }
+
+    // How many frames are averaged, 1 when blending is off.
+    pub fn frame_blend(&self) -> usize {
+        self.frame_blend.as_ref().map_or(1, FrameBlend::frames)
+    }
+
+    // Averages the last `frames` frames from the next one on, or turns blending off with 0 or 1.
+    pub fn set_frame_blend(&mut self, frames: usize) {
+        self.frame_blend = (frames > 1).then(|| FrameBlend::new(frames));
+    }
+
+    pub fn layer_enabled(&self, layer: Layer) -> bool {
+        self.layers_enabled[layer as usize]
+    }
+
+    // Hides a layer from the next line drawn on, for debugging rendering and ripping graphics.
+    // Hidden background and window pixels are the lightest DMG color and never cover sprites. The
+    // game can't tell, timing and the window's line counter carry on as usual.
+    pub fn set_layer_enabled(&mut self, layer: Layer, enabled: bool) {
+        self.layers_enabled[layer as usize] = enabled;
+    }
+
+    pub fn accuracy(&self) -> Accuracy {
+        self.accuracy
+    }
+
+    // Takes effect from the next line drawn.
+    pub fn set_accuracy(&mut self, accuracy: Accuracy) {
+        self.accuracy = accuracy;
+    }
+
+    pub fn mode(&self) -> Mode {
+        self.mode
+    }
+
+    // A VRAM bank as it is, whatever mode the PPU is in. Only the CGB has bank 1.
+    pub fn vram_bank(&self, bank: usize) -> Option<&[u8]> {
+        self.vram.chunks_exact(VRAM_BANK_SIZE).nth(bank)
+    }
+
+    pub fn oam(&self) -> &[u8] {
+        &self.oam
+    }
+
+    pub fn ly(&self) -> u8 {
+        self.ly
+    }
+
+    // The number of frames that finished so far, which lets callers wait for VBlank.
+    pub fn frames(&self) -> u64 {
+        self.frames
+    }
+
+    // 160x144 pixels, 4 bytes (RGBA) per pixel. With frame blending on, the average of the last
+    // finished frames.
+    pub fn framebuffer(&self) -> &[u8] {
+        self.frame_blend
+            .as_ref()
+            .and_then(FrameBlend::output)
+            .unwrap_or(&self.framebuffer)
+    }
+
+    // The shade (0-3) BGP, OBP0 or OBP1 gave each pixel, or None in CGB and compatibility mode
+    // where colors come from palette RAM instead.
+    pub fn shades(&self) -> Option<Vec<u8>> {
+        if self.cgb || self.dmg_compatibility {
+            return None;
+        }
+        let palette = &self.dmg_palette;
+        let shade = |pixel: &[u8]| {
+            [palette.bg, palette.obj0, palette.obj1]
+                .iter()
+                .find_map(|colors| colors.iter().position(|color| color == pixel))
+        };
+        Some(
+            self.framebuffer
+                .chunks_exact(4)
+                .map(|pixel| shade(pixel).unwrap_or(0) as u8)
+                .collect(),
+        )
+    }
+
+    // Returns and clears the interrupts (VBlank and STAT) requested since the last call.
+    pub fn take_interrupts(&mut self) -> u8 {
+        std::mem::take(&mut self.interrupts)
+    }
+
+    // Returns and clears whether a visible line entered HBlank since the last call.
+    pub fn take_hblank_started(&mut self) -> bool {
+        std::mem::take(&mut self.hblank_started)
+    }
+
+    pub fn read(&self, address: u16) -> u8 {
+        match address {
+            0x8000..=0x9FFF => {
+                if self.mode == Mode::Drawing {
+                    0xFF
+                } else {
+                    self.vram[self.vram_offset(address)]
+                }
+            }
+            0xFE00..=0xFE9F => {
+                if matches!(self.mode, Mode::OamScan | Mode::Drawing) {
+                    0xFF
+                } else {
+                    self.oam[(address - 0xFE00) as usize]
+                }
+            }
+            0xFF40 => self.lcdc,
+            0xFF41 => {
+                let coincidence = if self.ly == self.lyc { 0x04 } else { 0 };
+                0x80 | (self.stat & 0x78) | coincidence | self.mode as u8
+            }
+            0xFF42 => self.scy,
+            0xFF43 => self.scx,
+            0xFF44 => self.ly,
+            0xFF45 => self.lyc,
+            0xFF47 => self.bgp,
+            0xFF48 => self.obp0,
+            0xFF49 => self.obp1,
+            0xFF4A => self.wy,
+            0xFF4B => self.wx,
+            0xFF4F if self.cgb => 0xFE | self.vram_bank,
+            0xFF68 if self.cgb => 0x40 | self.bg_palette_index,
+            0xFF69 if self.cgb && self.mode != Mode::Drawing => {
+                self.bg_palettes[(self.bg_palette_index & 0x3F) as usize]
+            }
+            0xFF6A if self.cgb => 0x40 | self.obj_palette_index,
+            0xFF6C if self.cgb => 0xFE | self.opri,
+            0xFF6B if self.cgb && self.mode != Mode::Drawing => {
+                self.obj_palettes[(self.obj_palette_index & 0x3F) as usize]
+            }
+            _ => 0xFF,
+        }
+    }
+
+    pub fn write(&mut self, address: u16, value: u8) {
+        let raster_register = matches!(address, 0xFF40 | 0xFF42 | 0xFF43 | 0xFF47..=0xFF4B);
+        if raster_register && self.mode == Mode::Drawing && !self.fifo.active {
+            self.render_pixels(self.drawing_x());
+        }
+
+        match address {
+            // VRAM and OAM ignore writes while the PPU is using them.
+            0x8000..=0x9FFF if self.mode != Mode::Drawing => {
+                let offset = self.vram_offset(address);
+                self.vram[offset] = value;
+            }
+            0xFE00..=0xFE9F if !matches!(self.mode, Mode::OamScan | Mode::Drawing) => {
+                self.oam[(address - 0xFE00) as usize] = value;
+            }
+            0xFF40 => self.write_lcdc(value),
+            0xFF41 => {
+                // For a cycle the HBlank, VBlank and LYC sources are all on, which raises an
+                // interrupt in modes 0 and 1 or when LY matches LYC. Games like Road Rash and Zerd
+                // no Densetsu depend on it.
+                if self.stat_write_bug {
+                    self.update_stat_line(STAT_HBLANK | STAT_VBLANK | STAT_LYC);
+                }
+                self.stat = value & 0x78;
+                self.update_stat_line(self.stat);
+            }
+            0xFF42 => self.scy = value,
+            0xFF43 => self.scx = value,
+            0xFF44 => {}
+            0xFF45 => {
+                self.lyc = value;
+                self.update_stat_line(self.stat);
+            }
+            0xFF47 => self.bgp = value,
+            0xFF48 => self.obp0 = value,
+            0xFF49 => self.obp1 = value,
+            0xFF4A => self.wy = value,
+            0xFF4B => self.wx = value,
+            0xFF4F if self.cgb => self.vram_bank = value & 0x01,
+            0xFF68 if self.cgb => self.bg_palette_index = value & 0xBF,
+            0xFF69 if self.cgb => {
+                let drawing = self.mode == Mode::Drawing;
+                Self::write_palette(
+                    &mut self.bg_palettes,
+                    &mut self.bg_palette_index,
+                    value,
+                    drawing,
+                );
+            }
+            0xFF6A if self.cgb => self.obj_palette_index = value & 0xBF,
+            0xFF6C if self.cgb => self.opri = value & 0x01,
+            0xFF6B if self.cgb => {
+                let drawing = self.mode == Mode::Drawing;
+                Self::write_palette(
+                    &mut self.obj_palettes,
+                    &mut self.obj_palette_index,
+                    value,
+                    drawing,
+                );
+            }
+            _ => {}
+        }
+    }
+
+    // The index still auto-increments when the write itself is blocked during mode 3.
+    fn write_palette(palettes: &mut [u8], index: &mut u8, value: u8, drawing: bool) {
+        if !drawing {
+            palettes[(*index & 0x3F) as usize] = value;
+        }
+        if *index & PALETTE_AUTO_INCREMENT != 0 {
+            *index = PALETTE_AUTO_INCREMENT | (index.wrapping_add(1) & 0x3F);
+        }
+    }
+
+    fn vram_offset(&self, address: u16) -> usize {
+        self.vram_bank as usize * VRAM_BANK_SIZE + (address - 0x8000) as usize
+    }
+
+    // OAM DMA writes regardless of the current mode.
+    pub fn write_oam_dma(&mut self, index: usize, value: u8) {
+        self.oam[index] = value;
+    }
+
+    // HDMA writes to the selected VRAM bank regardless of the current mode.
+    pub fn write_vram_dma(&mut self, offset: u16, value: u8) {
+        let offset = self.vram_offset(0x8000 + offset);
+        self.vram[offset] = value;
+    }
+
+    pub fn tick(&mut self, cycles: u32) {
+        if !self.lcd_enabled() {
+            return;
+        }
+
+        // Nothing observable happens between mode changes, so skip straight to the next one. The
+        // FIFO draws a dot at a time instead.
+        let mut remaining = cycles;
+        while remaining > 0 {
+            if self.mode == Mode::Drawing && self.fifo.active {
+                self.line_dot += 1;
+                remaining -= 1;
+                if self.step_fifo() {
+                    self.change_mode();
+                }
+                continue;
+            }
+            let dots = remaining.min(self.dots_until_mode_change());
+            self.line_dot += dots;
+            remaining -= dots;
+            if self.dots_until_mode_change() == 0 {
+                self.change_mode();
+            }
+        }
+    }
+
+    // The dots until the PPU next changes mode (or line during VBlank) and may request an
+    // interrupt, or None with the LCD off. While the FIFO draws it's the soonest mode 3 could end.
+    pub fn cycles_until_mode_change(&self) -> Option<u32> {
+        self.lcd_enabled().then(|| self.dots_until_mode_change())
+    }
+
+    fn dots_until_mode_change(&self) -> u32 {
+        let end = match self.mode {
+            Mode::OamScan => OAM_SCAN_DOTS,
+            Mode::Drawing if self.fifo.active => {
+                return (SCREEN_WIDTH as u32 - self.fifo.x as u32).max(1)
+            }
+            Mode::Drawing => OAM_SCAN_DOTS + DRAWING_DOTS,
+            Mode::VBlank if self.ly == LINES_PER_FRAME - 1 => LAST_LINE_LY_DOTS,
+            Mode::HBlank | Mode::VBlank => LINE_DOTS,
+        };
+        end.saturating_sub(self.line_dot)
+    }
+
+    pub fn lcd_enabled(&self) -> bool {
+        self.lcdc & LCDC_ENABLE != 0
+    }
+
+    fn write_lcdc(&mut self, value: u8) {
+        let was_enabled = self.lcd_enabled();
+        self.lcdc = value;
+
+        if was_enabled && !self.lcd_enabled() {
+            self.ly = 0;
+            self.line_dot = 0;
+            self.window_line = 0;
+            self.mode = Mode::HBlank;
+            self.stat_line = false;
+        } else if !was_enabled && self.lcd_enabled() {
+            self.line_dot = 0;
+            self.mode = Mode::OamScan;
+            self.update_stat_line(self.stat);
+        }
+    }
+
+    // VBlank counts as one mode spanning the last 10 lines, with LY advancing every 456 dots. LY
+    // goes back to 0 a few dots into line 153, so LYC=0 matches there before the next frame.
+    fn change_mode(&mut self) {
+        match self.mode {
+            Mode::OamScan => {
+                self.mode = Mode::Drawing;
+                self.line_x = 0;
+                self.line_window = false;
+                self.fine_scroll = self.scx % 8;
+                if self.accuracy == Accuracy::High {
+                    self.start_fifo_line();
+                } else {
+                    self.fifo.active = false;
+                }
+            }
+            Mode::Drawing => {
+                let window = if self.fifo.active {
+                    self.fifo.window
+                } else {
+                    self.render_pixels(SCREEN_WIDTH);
+                    self.line_window
+                };
+                if window {
+                    self.window_line += 1;
+                }
+                self.mode = Mode::HBlank;
+                self.hblank_started = true;
+            }
+            Mode::HBlank => {
+                self.line_dot = 0;
+                self.ly += 1;
+                if self.ly as usize == SCREEN_HEIGHT {
+                    self.mode = Mode::VBlank;
+                    self.interrupts |= VBLANK_INTERRUPT;
+                    self.frames += 1;
+                    if let Some(blend) = &mut self.frame_blend {
+                        blend.push(&self.framebuffer);
+                    }
+                } else {
+                    self.mode = Mode::OamScan;
+                }
+            }
+            Mode::VBlank if self.ly == LINES_PER_FRAME - 1 => self.ly = 0,
+            // LY is already 0 at the end of line 153.
+            Mode::VBlank if self.ly == 0 => {
+                self.line_dot = 0;
+                self.window_line = 0;
+                self.mode = Mode::OamScan;
+            }
+            Mode::VBlank => {
+                self.line_dot = 0;
+                self.ly += 1;
+            }
+        }
+        self.update_stat_line(self.stat);
+    }
+
+    // Whether the sources enabled in `stat` hold the STAT interrupt line high right now.
+    fn stat_sources_active(&self, stat: u8) -> bool {
+        if !self.lcd_enabled() {
+            return false;
+        }
+        let mode = match self.mode {
+            Mode::HBlank => stat & STAT_HBLANK != 0,
+            // Line 144 starts like any other line as far as the OAM source is concerned.
+            Mode::VBlank => {
+                stat & STAT_VBLANK != 0
+                    || (self.ly as usize == SCREEN_HEIGHT
+                        && self.line_dot == 0
+                        && stat & STAT_OAM != 0)
+            }
+            Mode::OamScan => stat & STAT_OAM != 0,
+            Mode::Drawing => false,
+        };
+        mode || (self.ly == self.lyc && stat & STAT_LYC != 0)
+    }
+
+    fn update_stat_line(&mut self, stat: u8) {
+        let line = self.stat_sources_active(stat);
+        if line && !self.stat_line {
+            self.interrupts |= STAT_INTERRUPT;
+        }
+        self.stat_line = line;
+    }
+
+    // The pixel mode 3 has got to, going by the time the FIFO takes to push its first pixel.
+    fn drawing_x(&self) -> usize {
+        let dots = self
+            .line_dot
+            .saturating_sub(OAM_SCAN_DOTS + FIRST_PIXEL_DOTS);
+        (dots as usize).min(SCREEN_WIDTH)
+    }
+
+    // Draws the current line from where it was left up to `end` with the registers as they are
+    // now.
+    fn render_pixels(&mut self, end: usize) {
+        let pixels = self.line_x as usize..end;
+        if pixels.is_empty() {
+            return;
+        }
+        let mut bg_color_ids = [0u8; SCREEN_WIDTH];
+        let mut bg_attributes = [0u8; SCREEN_WIDTH];
+
+        // On CGB, LCDC bit 0 doesn't hide the background, it only takes away its priority.
+        if self.cgb || self.lcdc & LCDC_BG_ENABLE != 0 {
+            self.render_background(pixels.clone(), &mut bg_color_ids, &mut bg_attributes);
+            self.render_window(pixels.clone(), &mut bg_color_ids, &mut bg_attributes);
+        } else {
+            for x in pixels.clone() {
+                self.set_pixel(x, self.dmg_palette.bg[0]);
+            }
+        }
+
+        if self.lcdc & LCDC_OBJ_ENABLE != 0 && self.layer_enabled(Layer::Sprites) {
+            self.render_sprites(pixels, &bg_color_ids, &bg_attributes);
+        }
+        self.line_x = end as u8;
+    }
+
+    fn render_background(
+        &mut self,
+        pixels: Range<usize>,
+        bg_color_ids: &mut [u8; SCREEN_WIDTH],
+        bg_attributes: &mut [u8; SCREEN_WIDTH],
+    ) {
+        let map = if self.lcdc & LCDC_BG_MAP != 0 {
+            0x1C00
+        } else {
+            0x1800
+        };
+        let y = self.scy.wrapping_add(self.ly);
+        if !self.layer_enabled(Layer::Background) {
+            for x in pixels {
+                self.set_pixel(x, self.dmg_palette.bg[0]);
+            }
+            return;
+        }
+
+        // The fetcher reads SCX's tile column for each tile, but the fine scroll stays put.
+        for x in pixels {
+            let map_x = (self.scx & 0xF8).wrapping_add(x as u8 + self.fine_scroll);
+            let (color_id, attributes) = self.tile_map_pixel(map, map_x, y);
+            bg_color_ids[x] = color_id;
+            bg_attributes[x] = attributes;
+            self.set_pixel(x, self.bg_color(color_id, attributes));
+        }
+    }
+
+    fn render_window(
+        &mut self,
+        pixels: Range<usize>,
+        bg_color_ids: &mut [u8; SCREEN_WIDTH],
+        bg_attributes: &mut [u8; SCREEN_WIDTH],
+    ) {
+        if self.lcdc & LCDC_WINDOW_ENABLE == 0 || self.wy > self.ly || self.wx > 166 {
+            return;
+        }
+
+        let map = if self.lcdc & LCDC_WINDOW_MAP != 0 {
+            0x1C00
+        } else {
+            0x1800
+        };
+        let start = (self.wx as usize).saturating_sub(7).max(pixels.start);
+        if start >= pixels.end {
+            return;
+        }
+        self.line_window = true;
+        if !self.layer_enabled(Layer::Window) {
+            return;
+        }
+
+        for x in start..pixels.end {
+            let window_x = (x + 7 - self.wx as usize) as u8;
+            let (color_id, attributes) = self.tile_map_pixel(map, window_x, self.window_line);
+            bg_color_ids[x] = color_id;
+            bg_attributes[x] = attributes;
+            self.set_pixel(x, self.bg_color(color_id, attributes));
+        }
+    }
+
+    fn render_sprites(
+        &mut self,
+        pixels: Range<usize>,
+        bg_color_ids: &[u8; SCREEN_WIDTH],
+        bg_attributes: &[u8; SCREEN_WIDTH],
+    ) {
+        let height = if self.lcdc & LCDC_OBJ_SIZE != 0 {
+            16
+        } else {
+            8
+        };
+        let ly = self.ly as i16;
+        let mut sprites = self.line_objects();
+
+        // On DMG the object with the lower X coordinate wins, then the one earlier in OAM. CGB
+        // only looks at the OAM order unless OPRI says otherwise.
+        if self.x_priority() {
+            sprites.sort_by_key(|&index| self.oam[index * 4 + 1]);
+        }
+
+        let mut claimed = [false; SCREEN_WIDTH];
+        for index in sprites {
+            let y = self.oam[index * 4] as i16 - 16;
+            let x = self.oam[index * 4 + 1] as i16 - 8;
+            let mut tile = self.oam[index * 4 + 2];
+            let flags = self.oam[index * 4 + 3];
+
+            let mut row = ly - y;
+            if flags & OBJ_Y_FLIP != 0 {
+                row = height - 1 - row;
+            }
+            if height == 16 {
+                tile &= 0xFE;
+            }
+            let bank_offset = if self.cgb && flags & OBJ_CGB_BANK != 0 {
+                VRAM_BANK_SIZE
+            } else {
+                0
+            };
+            let tile_address = bank_offset + tile as usize * 16 + row as usize * 2;
+
+            for column in 0..8 {
+                let screen_x = x + column;
+                if !(pixels.start as i16..pixels.end as i16).contains(&screen_x)
+                    || claimed[screen_x as usize]
+                {
+                    continue;
+                }
+
+                let bit = if flags & OBJ_X_FLIP != 0 {
+                    column
+                } else {
+                    7 - column
+                };
+                let color_id = self.tile_data_pixel(tile_address, bit as u8);
+                if color_id == 0 {
+                    continue;
+                }
+
+                let screen_x = screen_x as usize;
+                claimed[screen_x] = true;
+                if self.background_has_priority(
+                    flags,
+                    bg_color_ids[screen_x],
+                    bg_attributes[screen_x],
+                ) {
+                    continue;
+                }
+                self.set_pixel(screen_x, self.obj_color(color_id, flags));
+            }
+        }
+    }
+
+    // Only the first 10 objects in OAM that overlap the line are drawn.
+    fn line_objects(&self) -> Vec<usize> {
+        let height = if self.lcdc & LCDC_OBJ_SIZE != 0 {
+            16
+        } else {
+            8
+        };
+        let ly = self.ly as i16;
+        (0..OAM_SIZE / 4)
+            .filter(|&index| {
+                let y = self.oam[index * 4] as i16 - 16;
+                ly >= y && ly < y + height
+            })
+            .take(MAX_SPRITES_PER_LINE)
+            .collect()
+    }
+
+    fn x_priority(&self) -> bool {
+        !self.cgb || self.opri & 0x01 != 0
+    }
+
+    fn background_has_priority(&self, flags: u8, bg_color_id: u8, bg_attributes: u8) -> bool {
+        if bg_color_id == 0 {
+            return false;
+        }
+        if self.cgb && self.lcdc & LCDC_BG_ENABLE == 0 {
+            return false;
+        }
+
+        flags & OBJ_BEHIND_BG != 0 || bg_attributes & BG_PRIORITY != 0
+    }
+
+    // Returns the color ID and the tile's attributes, which only exist in CGB mode.
+    fn tile_map_pixel(&self, map: usize, x: u8, y: u8) -> (u8, u8) {
+        let map_address = map + (y as usize / 8) * 32 + x as usize / 8;
+        let tile_index = self.vram[map_address];
+        let attributes = if self.cgb {
+            self.vram[VRAM_BANK_SIZE + map_address]
+        } else {
+            0
+        };
+
+        let bit = if attributes & BG_X_FLIP != 0 {
+            x % 8
+        } else {
+            7 - x % 8
+        };
+        let color_id = self.tile_data_pixel(self.tile_row_address(tile_index, attributes, y), bit);
+        (color_id, attributes)
+    }
+
+    // Where row `y % 8` of a background or window tile is, with LCDC's tile data area and the
+    // tile's bank and flip attributes.
+    fn tile_row_address(&self, tile_index: u8, attributes: u8, y: u8) -> usize {
+        let mut tile_address = if self.lcdc & LCDC_TILE_DATA != 0 {
+            tile_index as usize * 16
+        } else {
+            (0x1000 + tile_index as i8 as i32 * 16) as usize
+        };
+        if attributes & BG_BANK != 0 {
+            tile_address += VRAM_BANK_SIZE;
+        }
+        let row = if attributes & BG_Y_FLIP != 0 {
+            7 - y % 8
+        } else {
+            y % 8
+        };
+        tile_address + row as usize * 2
+    }
+
+    fn tile_data_pixel(&self, row_address: usize, bit: u8) -> u8 {
+        let low = (self.vram[row_address] >> bit) & 0x01;
+        let high = (self.vram[row_address + 1] >> bit) & 0x01;
+        (high << 1) | low
+    }
+
+    fn bg_color(&self, color_id: u8, attributes: u8) -> [u8; 4] {
+        if self.cgb {
+            Self::cgb_color(&self.bg_palettes, attributes & 0x07, color_id)
+        } else if self.dmg_compatibility {
+            Self::cgb_color(&self.bg_palettes, 0, Self::shade(self.bgp, color_id))
+        } else {
+            self.dmg_palette.bg[Self::shade(self.bgp, color_id) as usize]
+        }
+    }
+
+    fn obj_color(&self, color_id: u8, flags: u8) -> [u8; 4] {
+        if self.cgb {
+            Self::cgb_color(&self.obj_palettes, flags & 0x07, color_id)
+        } else {
+            let (palette, index, colors) = if flags & OBJ_PALETTE != 0 {
+                (self.obp1, 1, &self.dmg_palette.obj1)
+            } else {
+                (self.obp0, 0, &self.dmg_palette.obj0)
+            };
+            let shade = Self::shade(palette, color_id);
+            if self.dmg_compatibility {
+                Self::cgb_color(&self.obj_palettes, index, shade)
+            } else {
+                colors[shade as usize]
+            }
+        }
+    }
+
+    fn shade(palette: u8, color_id: u8) -> u8 {
+        (palette >> (color_id * 2)) & 0x03
+    }
+
+    // Palette RAM holds 8 palettes of 4 little-endian RGB555 colors.
+    fn cgb_color(palettes: &[u8], palette: u8, color_id: u8) -> [u8; 4] {
+        let index = palette as usize * 8 + color_id as usize * 2;
+        rgba(u16::from_le_bytes([palettes[index], palettes[index + 1]]))
+    }
+
+    fn set_pixel(&mut self, x: usize, color: [u8; 4]) {
+        let offset = (self.ly as usize * SCREEN_WIDTH + x) * 4;
+        self.framebuffer[offset..offset + 4].copy_from_slice(&color);
+    }
+}
+
+impl Snapshot for Ppu {
+    fn component(&self) -> &'static str {
+        "PPU"
+    }
+
+    fn version(&self) -> u8 {
+        1
+    }
+
+    fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_bytes(&self.vram);
+        writer.write_bytes(&self.oam);
+        for register in [
+            self.lcdc, self.stat, self.scy, self.scx, self.ly, self.lyc, self.bgp, self.obp0,
+            self.obp1, self.wy, self.wx,
+        ] {
+            writer.write_u8(register);
+        }
+        writer.write_u8(self.mode as u8);
+        writer.write_u32(self.line_dot);
+        writer.write_u8(self.window_line);
+        writer.write_u8(self.line_x);
+        writer.write_bool(self.line_window);
+        writer.write_u8(self.fine_scroll);
+        writer.write_u8(self.interrupts);
+        writer.write_u64(self.frames);
+        writer.write_bytes(&self.framebuffer);
+        writer.write_u8(self.vram_bank);
+        writer.write_bytes(&self.bg_palettes);
+        writer.write_bytes(&self.obj_palettes);
+        writer.write_u8(self.bg_palette_index);
+        writer.write_u8(self.obj_palette_index);
+        writer.write_u8(self.opri);
+        writer.write_bool(self.hblank_started);
+        self.fifo.save_state(writer);
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader) -> Result<()> {
+        reader.read_bytes_into(&mut self.vram)?;
+        reader.read_bytes_into(&mut self.oam)?;
+        for register in [
+            &mut self.lcdc,
+            &mut self.stat,
+            &mut self.scy,
+            &mut self.scx,
+            &mut self.ly,
+            &mut self.lyc,
+            &mut self.bgp,
+            &mut self.obp0,
+            &mut self.obp1,
+            &mut self.wy,
+            &mut self.wx,
+        ] {
+            *register = reader.read_u8()?;
+        }
+        self.mode = match reader.read_u8()? {
+            0 => Mode::HBlank,
+            1 => Mode::VBlank,
+            2 => Mode::OamScan,
+            3 => Mode::Drawing,
+            mode => {
+                return Err(Error::other(format!(
+                    "{} is an invalid PPU mode in the save state.",
+                    mode
+                )))
+            }
+        };
+        self.line_dot = reader.read_u32()?;
+        self.window_line = reader.read_u8()?;
+        self.line_x = reader.read_u8()?.min(SCREEN_WIDTH as u8);
+        self.line_window = reader.read_bool()?;
+        self.fine_scroll = reader.read_u8()? % 8;
+        self.interrupts = reader.read_u8()?;
+        self.frames = reader.read_u64()?;
+        reader.read_bytes_into(&mut self.framebuffer)?;
+        self.vram_bank = reader.read_u8()? & 0x01;
+        reader.read_bytes_into(&mut self.bg_palettes)?;
+        reader.read_bytes_into(&mut self.obj_palettes)?;
+        self.bg_palette_index = reader.read_u8()?;
+        self.obj_palette_index = reader.read_u8()?;
+        self.opri = reader.read_u8()? & 0x01;
+        self.hblank_started = reader.read_bool()?;
+        self.fifo.load_state(reader)?;
+        self.stat_line = self.stat_sources_active(self.stat);
+        if let Some(blend) = &mut self.frame_blend {
+            blend.clear();
+        }
+        Ok(())
+    }
+}
+
+// Converts an RGB555 color to RGBA.
+pub fn rgba(color: u16) -> [u8; 4] {
+    let expand = |channel: u16| {
+        let channel = (channel & 0x1F) as u8;
+        (channel << 3) | (channel >> 2)
+    };
+    [expand(color), expand(color >> 5), expand(color >> 10), 0xFF]
+}
+
+const VRAM_BANK_SIZE: usize = 0x2000;
+
+const PALETTE_RAM_SIZE: usize = 0x40;
+
+const PALETTE_AUTO_INCREMENT: u8 = 0x80;
+
+const OAM_SIZE: usize = 0xA0;
+
+const LINE_DOTS: u32 = 456;
+
+const OAM_SCAN_DOTS: u32 = 80;
+
+const DRAWING_DOTS: u32 = 172;
+
+// Mode 3 dots before the first pixel reaches the screen.
+const FIRST_PIXEL_DOTS: u32 = 12;
+
+const LINES_PER_FRAME: u8 = 154;
+
+// How long LY reads 153 on the last line before it reads 0.
+const LAST_LINE_LY_DOTS: u32 = 4;
+
+const MAX_SPRITES_PER_LINE: usize = 10;
+
+const LCDC_ENABLE: u8 = 0x80;
+
+const LCDC_WINDOW_MAP: u8 = 0x40;
+
+const LCDC_WINDOW_ENABLE: u8 = 0x20;
+
+const LCDC_TILE_DATA: u8 = 0x10;
+
+const LCDC_BG_MAP: u8 = 0x08;
+
+const LCDC_OBJ_SIZE: u8 = 0x04;
+
+const LCDC_OBJ_ENABLE: u8 = 0x02;
+
+const LCDC_BG_ENABLE: u8 = 0x01;
+
+const STAT_LYC: u8 = 0x40;
+
+const STAT_OAM: u8 = 0x20;
+
+const STAT_VBLANK: u8 = 0x10;
+
+const STAT_HBLANK: u8 = 0x08;
+
+const OBJ_BEHIND_BG: u8 = 0x80;
+
+const OBJ_Y_FLIP: u8 = 0x40;
+
+const OBJ_X_FLIP: u8 = 0x20;
+
+const OBJ_PALETTE: u8 = 0x10;
+
+const OBJ_CGB_BANK: u8 = 0x08;
+
+const BG_PRIORITY: u8 = 0x80;
+
+const BG_Y_FLIP: u8 = 0x40;
+
+const BG_X_FLIP: u8 = 0x20;
+
+const BG_BANK: u8 = 0x08;
+
+// The RGBA colors of shades 0-3 in DMG mode unless another palette is picked, see `Palette`.
+pub const DMG_COLORS: [[u8; 4]; 4] = [
+    [0xFF, 0xFF, 0xFF, 0xFF],
+    [0xAA, 0xAA, 0xAA, 0xFF],
+    [0x55, 0x55, 0x55, 0xFF],
+    [0x00, 0x00, 0x00, 0xFF],
+];
+
+#[cfg(test)]
+mod tests {
+    use rstest::rstest;
+
+    use super::*;
+    use crate::palette::PalettePreset;
+
+    fn pixel(ppu: &Ppu, x: usize, y: usize) -> [u8; 4] {
+        let offset = (y * SCREEN_WIDTH + x) * 4;
+        ppu.framebuffer()[offset..offset + 4].try_into().unwrap()
+    }
+
+    // Tile 1 is solid color 3, tile 2 is solid color 1 and tile 3 has color 2 in its left column.
+    fn ppu_with_tiles() -> Ppu {
+        let mut ppu = Ppu::new();
+        ppu.lcdc = 0;
+        for row in 0..8 {
+            ppu.vram[16 + row * 2] = 0xFF;
+            ppu.vram[16 + row * 2 + 1] = 0xFF;
+            ppu.vram[32 + row * 2] = 0xFF;
+            ppu.vram[48 + row * 2 + 1] = 0x80;
+        }
+        ppu.bgp = 0xE4;
+        ppu.obp0 = 0xE4;
+        ppu
+    }
+
+    fn run_lines(ppu: &mut Ppu, lines: u32) {
+        ppu.tick(LINE_DOTS * lines);
+    }
+
+    #[test]
+    fn test_mode_timing() {
+        let mut ppu = Ppu::new();
+
+        assert_eq!(ppu.mode(), Mode::OamScan);
+        ppu.tick(80);
+        assert_eq!(ppu.mode(), Mode::Drawing);
+        ppu.tick(172);
+        assert_eq!(ppu.mode(), Mode::HBlank);
+        ppu.tick(204);
+        assert_eq!(ppu.mode(), Mode::OamScan);
+        assert_eq!(ppu.ly(), 1);
+    }
+
+    #[test]
+    fn test_vblank() {
+        // Arrange
+        let mut ppu = Ppu::new();
+
+        // Act
+        run_lines(&mut ppu, 144);
+
+        // Assert
+        assert_eq!(ppu.mode(), Mode::VBlank);
+        assert_eq!(ppu.frames(), 1);
+        assert_eq!(ppu.take_interrupts(), VBLANK_INTERRUPT);
+        assert_eq!(ppu.take_interrupts(), 0);
+    }
+
+    #[test]
+    fn test_frame_wraps_after_154_lines() {
+        let mut ppu = Ppu::new();
+
+        run_lines(&mut ppu, 153);
+        assert_eq!(ppu.ly(), 153);
+        run_lines(&mut ppu, 1);
+
+        assert_eq!(ppu.ly(), 0);
+        assert_eq!(ppu.mode(), Mode::OamScan);
+    }
+
+    #[rstest]
+    #[case(STAT_HBLANK, 252)]
+    #[case(STAT_VBLANK, LINE_DOTS * 144)]
+    #[case(STAT_OAM, LINE_DOTS)]
+    fn test_stat_mode_interrupts(#[case] source: u8, #[case] dots: u32) {
+        let mut ppu = Ppu::new();
+        ppu.write(0xFF41, source);
+        // Clear the interrupt the DMG STAT write bug raises because LY matches LYC.
+        ppu.take_interrupts();
+
+        ppu.tick(dots - 1);
+        assert_eq!(ppu.take_interrupts() & STAT_INTERRUPT, 0);
+        ppu.tick(1);
+
+        assert_eq!(ppu.take_interrupts() & STAT_INTERRUPT, STAT_INTERRUPT);
+    }
+
+    #[test]
+    fn test_lyc_coincidence() {
+        let mut ppu = Ppu::new();
+        ppu.write(0xFF45, 2);
+        ppu.write(0xFF41, STAT_LYC);
+
+        run_lines(&mut ppu, 1);
+        assert_eq!(ppu.read(0xFF41) & 0x04, 0);
+        assert_eq!(ppu.take_interrupts(), 0);
+        run_lines(&mut ppu, 1);
+
+        assert_eq!(ppu.read(0xFF41) & 0x04, 0x04);
+        assert_eq!(ppu.take_interrupts(), STAT_INTERRUPT);
+    }
+
+    #[test]
+    fn test_stat_interrupt_blocking() {
+        // Arrange
+        let mut ppu = Ppu::new();
+        ppu.write(0xFF45, 1);
+        ppu.write(0xFF41, STAT_HBLANK | STAT_LYC);
+        ppu.tick(252);
+        assert_eq!(ppu.take_interrupts(), STAT_INTERRUPT);
+
+        // Act
+        // HBlank holds the line high until LY matches LYC.
+        ppu.tick(204);
+
+        // Assert
+        assert_eq!(ppu.ly(), 1);
+        assert_eq!(ppu.take_interrupts(), 0);
+    }
+
+    #[test]
+    fn test_ly_is_zero_early_on_last_line() {
+        // Arrange
+        let mut ppu = Ppu::new();
+        ppu.write(0xFF41, STAT_LYC);
+        run_lines(&mut ppu, 153);
+        ppu.take_interrupts();
+
+        // Act
+        ppu.tick(LAST_LINE_LY_DOTS - 1);
+        let last_line = ppu.ly();
+        ppu.tick(1);
+        let early = (ppu.ly(), ppu.mode(), ppu.take_interrupts());
+        ppu.tick(LINE_DOTS - LAST_LINE_LY_DOTS);
+
+        // Assert
+        assert_eq!(last_line, 153);
+        assert_eq!(early, (0, Mode::VBlank, STAT_INTERRUPT));
+        assert_eq!((ppu.ly(), ppu.mode()), (0, Mode::OamScan));
+        // LY was already 0, so the line stays high.
+        assert_eq!(ppu.take_interrupts(), 0);
+    }
+
+    #[test]
+    fn test_vblank_raises_oam_stat_interrupt() {
+        let mut ppu = Ppu::new();
+        run_lines(&mut ppu, 143);
+        ppu.write(0xFF41, STAT_OAM);
+        ppu.tick(LINE_DOTS - 1);
+        ppu.take_interrupts();
+
+        ppu.tick(1);
+
+        assert_eq!(ppu.take_interrupts(), VBLANK_INTERRUPT | STAT_INTERRUPT);
+    }
+
+    #[test]
+    fn test_lcd_off_resets_ly() {
+        let mut ppu = Ppu::new();
+        run_lines(&mut ppu, 10);
+
+        ppu.write(0xFF40, 0x00);
+        ppu.tick(1000);
+
+        assert_eq!(ppu.ly(), 0);
+        assert_eq!(ppu.read(0xFF41) & 0x03, 0);
+    }
+
+    #[test]
+    fn test_vram_blocked_while_drawing() {
+        let mut ppu = Ppu::new();
+        ppu.write(0x8000, 0x12);
+
+        ppu.tick(80);
+        ppu.write(0x8000, 0x34);
+
+        assert_eq!(ppu.read(0x8000), 0xFF);
+        ppu.tick(172);
+        assert_eq!(ppu.read(0x8000), 0x12);
+    }
+
+    #[test]
+    fn test_oam_blocked_during_scan() {
+        let mut ppu = Ppu::new();
+
+        ppu.write(0xFE00, 0x12);
+        assert_eq!(ppu.read(0xFE00), 0xFF);
+
+        ppu.tick(252);
+        ppu.write(0xFE00, 0x34);
+        assert_eq!(ppu.read(0xFE00), 0x34);
+    }
+
+    #[test]
+    fn test_render_background() {
+        // Arrange
+        let mut ppu = ppu_with_tiles();
+        ppu.vram[0x1800] = 1;
+        ppu.vram[0x1801] = 2;
+        ppu.write(0xFF40, LCDC_ENABLE | LCDC_BG_ENABLE | LCDC_TILE_DATA);
+
+        // Act
+        run_lines(&mut ppu, 1);
+
+        // Assert
+        assert_eq!(pixel(&ppu, 0, 0), DMG_COLORS[3]);
+        assert_eq!(pixel(&ppu, 8, 0), DMG_COLORS[1]);
+        assert_eq!(pixel(&ppu, 16, 0), DMG_COLORS[0]);
+    }
+
+    #[test]
+    fn test_dmg_palette() {
+        // Arrange
+        let mut ppu = ppu_with_tiles();
+        ppu.vram[0x1800] = 1;
+        ppu.vram[0x1801] = 2;
+        ppu.write(0xFF40, LCDC_ENABLE | LCDC_BG_ENABLE | LCDC_TILE_DATA);
+        let palette = PalettePreset::Green.palette();
+
+        // Act
+        ppu.set_dmg_palette(palette);
+        run_lines(&mut ppu, 1);
+
+        // Assert
+        assert_eq!(pixel(&ppu, 0, 0), palette.bg[3]);
+        assert_eq!(pixel(&ppu, 8, 0), palette.bg[1]);
+        assert_eq!(ppu.shades().unwrap()[..9], [3, 3, 3, 3, 3, 3, 3, 3, 1]);
+    }
+
+    #[test]
+    fn test_frame_blend() {
+        // Arrange
+        let mut ppu = ppu_with_tiles();
+        ppu.vram[0x1800] = 1;
+        ppu.write(0xFF40, LCDC_ENABLE | LCDC_BG_ENABLE | LCDC_TILE_DATA);
+        ppu.set_frame_blend(2);
+        run_lines(&mut ppu, LINES_PER_FRAME as u32);
+
+        // Act
+        ppu.bgp = 0x00;
+        run_lines(&mut ppu, LINES_PER_FRAME as u32);
+
+        // Assert
+        // Half black from the first frame and half white from the second.
+        assert_eq!(pixel(&ppu, 0, 0), [0x80, 0x80, 0x80, 0xFF]);
+        assert_eq!(ppu.frame_blend(), 2);
+        assert_eq!(ppu.shades().unwrap()[0], 0);
+    }
+
+    #[test]
+    fn test_shades() {
+        let mut ppu = ppu_with_tiles();
+        ppu.vram[0x1800] = 1;
+        ppu.vram[0x1801] = 2;
+        ppu.write(0xFF40, LCDC_ENABLE | LCDC_BG_ENABLE | LCDC_TILE_DATA);
+
+        run_lines(&mut ppu, 1);
+
+        let shades = ppu.shades().unwrap();
+        assert_eq!(
+            shades[..17],
+            [3, 3, 3, 3, 3, 3, 3, 3, 1, 1, 1, 1, 1, 1, 1, 1, 0]
+        );
+        assert_eq!(Ppu::new_cgb().shades(), None);
+    }
+
+    #[test]
+    fn test_dmg_compatibility_palettes() {
+        // Arrange
+        let mut ppu = Ppu::for_model(EmulatorModel::Cgb, false);
+        ppu.lcdc = 0;
+        for row in 0..8 {
+            ppu.vram[16 + row * 2] = 0xFF;
+        }
+        ppu.vram[0x1800] = 1;
+        ppu.bgp = 0xE4;
+        ppu.write(0xFF40, LCDC_ENABLE | LCDC_BG_ENABLE | LCDC_TILE_DATA);
+
+        // Act
+        run_lines(&mut ppu, 1);
+
+        // Assert
+        // Color 1 is 0x1BEF in the default BG palette.
+        assert_eq!(pixel(&ppu, 0, 0), [0x7B, 0xFF, 0x31, 0xFF]);
+        assert_eq!(pixel(&ppu, 8, 0), [0xFF, 0xFF, 0xFF, 0xFF]);
+        assert_eq!(ppu.read(0xFF69), 0xFF);
+    }
+
+    #[rstest]
+    #[case(EmulatorModel::Dmg, STAT_INTERRUPT)]
+    #[case(EmulatorModel::Cgb, 0)]
+    fn test_stat_write_bug(#[case] model: EmulatorModel, #[case] expected: u8) {
+        let mut ppu = Ppu::for_model(model, false);
+        ppu.write(0xFF45, 5);
+        ppu.tick(252);
+        ppu.take_interrupts();
+
+        ppu.write(0xFF41, 0x00);
+
+        assert_eq!(ppu.take_interrupts() & STAT_INTERRUPT, expected);
+    }
+
+    #[test]
+    fn test_render_background_scrolled_with_signed_tile_data() {
+        let mut ppu = ppu_with_tiles();
+        // With signed addressing, tile 0 lives at 0x9000.
+        ppu.vram.copy_within(16..32, 0x1000);
+        ppu.vram[0x1800 + 32] = 0x00;
+        ppu.vram[0x1800 + 33] = 0x01;
+        ppu.write(0xFF42, 8);
+        ppu.write(0xFF43, 4);
+        ppu.write(0xFF40, LCDC_ENABLE | LCDC_BG_ENABLE);
+
+        run_lines(&mut ppu, 1);
+
+        assert_eq!(pixel(&ppu, 0, 0), DMG_COLORS[3]);
+        assert_eq!(pixel(&ppu, 3, 0), DMG_COLORS[3]);
+        assert_eq!(pixel(&ppu, 4, 0), DMG_COLORS[0]);
+    }
+
+    #[test]
+    fn test_mid_line_palette_change() {
+        // Arrange
+        let mut ppu = ppu_with_tiles();
+        ppu.vram[0x1800..0x1C00].fill(1);
+        ppu.write(0xFF40, LCDC_ENABLE | LCDC_BG_ENABLE | LCDC_TILE_DATA);
+        ppu.tick(OAM_SCAN_DOTS + FIRST_PIXEL_DOTS + 40);
+
+        // Act
+        ppu.write(0xFF47, 0x00);
+        run_lines(&mut ppu, 1);
+
+        // Assert
+        assert_eq!(pixel(&ppu, 39, 0), DMG_COLORS[3]);
+        assert_eq!(pixel(&ppu, 40, 0), DMG_COLORS[0]);
+        assert_eq!(pixel(&ppu, 40, 1), DMG_COLORS[0]);
+    }
+
+    #[test]
+    fn test_mid_line_scroll_change() {
+        // Arrange
+        let mut ppu = ppu_with_tiles();
+        ppu.vram[0x1800 + 10] = 1;
+        ppu.write(0xFF40, LCDC_ENABLE | LCDC_BG_ENABLE | LCDC_TILE_DATA);
+        ppu.tick(OAM_SCAN_DOTS + FIRST_PIXEL_DOTS + 40);
+
+        // Act
+        // The fine scroll only counts from the next line.
+        ppu.write(0xFF43, 16 + 3);
+        ppu.tick(LINE_DOTS - OAM_SCAN_DOTS - FIRST_PIXEL_DOTS - 40);
+
+        // Assert
+        assert_eq!(pixel(&ppu, 63, 0), DMG_COLORS[0]);
+        assert_eq!(pixel(&ppu, 64, 0), DMG_COLORS[3]);
+        assert_eq!(pixel(&ppu, 71, 0), DMG_COLORS[3]);
+        assert_eq!(pixel(&ppu, 80, 0), DMG_COLORS[0]);
+    }
+
+    #[test]
+    fn test_render_window() {
+        let mut ppu = ppu_with_tiles();
+        ppu.vram[0x1C00] = 1;
+        ppu.write(0xFF4A, 1);
+        ppu.write(0xFF4B, 7 + 80);
+        ppu.write(
+            0xFF40,
+            LCDC_ENABLE | LCDC_BG_ENABLE | LCDC_TILE_DATA | LCDC_WINDOW_ENABLE | LCDC_WINDOW_MAP,
+        );
+
+        run_lines(&mut ppu, 2);
+
+        assert_eq!(pixel(&ppu, 80, 0), DMG_COLORS[0]);
+        assert_eq!(pixel(&ppu, 79, 1), DMG_COLORS[0]);
+        assert_eq!(pixel(&ppu, 80, 1), DMG_COLORS[3]);
+        assert_eq!(pixel(&ppu, 88, 1), DMG_COLORS[0]);
+    }
+
+    #[test]
+    fn test_hidden_window() {
+        let mut ppu = ppu_with_tiles();
+        ppu.vram[0x1800] = 2;
+        ppu.vram[0x1C00] = 1;
+        ppu.vram[0x1C20] = 3;
+        ppu.write(0xFF4B, 7);
+        ppu.write(
+            0xFF40,
+            LCDC_ENABLE | LCDC_BG_ENABLE | LCDC_TILE_DATA | LCDC_WINDOW_ENABLE | LCDC_WINDOW_MAP,
+        );
+
+        ppu.set_layer_enabled(Layer::Window, false);
+        run_lines(&mut ppu, 8);
+        ppu.set_layer_enabled(Layer::Window, true);
+        run_lines(&mut ppu, 1);
+
+        // The background shows through, and the window still moved on to its second row of tiles.
+        assert_eq!(pixel(&ppu, 0, 0), DMG_COLORS[1]);
+        assert_eq!(pixel(&ppu, 0, 8), DMG_COLORS[2]);
+    }
+
+    #[test]
+    fn test_hidden_background_and_sprites() {
+        // Arrange
+        let mut ppu = ppu_with_tiles();
+        ppu.vram[0x1800] = 2;
+        ppu.oam[0..4].copy_from_slice(&[16, 8, 1, OBJ_BEHIND_BG]);
+        ppu.oam[4..8].copy_from_slice(&[16, 16, 1, 0]);
+        ppu.write(
+            0xFF40,
+            LCDC_ENABLE | LCDC_BG_ENABLE | LCDC_OBJ_ENABLE | LCDC_TILE_DATA,
+        );
+
+        // Act
+        ppu.set_layer_enabled(Layer::Background, false);
+        run_lines(&mut ppu, 1);
+        ppu.set_layer_enabled(Layer::Sprites, false);
+        run_lines(&mut ppu, 1);
+
+        // Assert
+        // The sprite behind the background isn't hidden by it anymore.
+        assert_eq!(pixel(&ppu, 0, 0), DMG_COLORS[3]);
+        assert_eq!(pixel(&ppu, 8, 0), DMG_COLORS[3]);
+        assert_eq!(pixel(&ppu, 16, 0), DMG_COLORS[0]);
+        assert_eq!(pixel(&ppu, 0, 1), DMG_COLORS[0]);
+    }
+
+    #[test]
+    fn test_render_sprite_with_flip_and_palette() {
+        // Arrange
+        let mut ppu = ppu_with_tiles();
+        ppu.oam[0..4].copy_from_slice(&[16, 8, 3, OBJ_X_FLIP | OBJ_PALETTE]);
+        ppu.obp1 = 0x30;
+        ppu.write(0xFF40, LCDC_ENABLE | LCDC_OBJ_ENABLE);
+
+        // Act
+        run_lines(&mut ppu, 1);
+
+        // Assert
+        assert_eq!(pixel(&ppu, 0, 0), DMG_COLORS[0]);
+        assert_eq!(pixel(&ppu, 7, 0), DMG_COLORS[3]);
+    }
+
+    #[test]
+    fn test_sprite_behind_background() {
+        let mut ppu = ppu_with_tiles();
+        ppu.vram[0x1800] = 2;
+        ppu.oam[0..4].copy_from_slice(&[16, 8, 1, OBJ_BEHIND_BG]);
+        ppu.oam[4..8].copy_from_slice(&[16, 16, 1, OBJ_BEHIND_BG]);
+        ppu.write(
+            0xFF40,
+            LCDC_ENABLE | LCDC_BG_ENABLE | LCDC_OBJ_ENABLE | LCDC_TILE_DATA,
+        );
+
+        run_lines(&mut ppu, 1);
+
+        assert_eq!(pixel(&ppu, 0, 0), DMG_COLORS[1]);
+        assert_eq!(pixel(&ppu, 8, 0), DMG_COLORS[3]);
+    }
+
+    #[test]
+    fn test_sprite_x_priority() {
+        let mut ppu = ppu_with_tiles();
+        ppu.oam[0..4].copy_from_slice(&[16, 12, 2, 0]);
+        ppu.oam[4..8].copy_from_slice(&[16, 8, 1, 0]);
+        ppu.write(0xFF40, LCDC_ENABLE | LCDC_OBJ_ENABLE);
+
+        run_lines(&mut ppu, 1);
+
+        assert_eq!(pixel(&ppu, 6, 0), DMG_COLORS[3]);
+        assert_eq!(pixel(&ppu, 8, 0), DMG_COLORS[1]);
+    }
+
+    #[test]
+    fn test_ten_sprites_per_line() {
+        let mut ppu = ppu_with_tiles();
+        for index in 0..11 {
+            let offset = index * 4;
+            ppu.oam[offset..offset + 4].copy_from_slice(&[16, 8 + index as u8 * 8, 1, 0]);
+        }
+        ppu.write(0xFF40, LCDC_ENABLE | LCDC_OBJ_ENABLE);
+
+        run_lines(&mut ppu, 1);
+
+        assert_eq!(pixel(&ppu, 72, 0), DMG_COLORS[3]);
+        assert_eq!(pixel(&ppu, 80, 0), DMG_COLORS[0]);
+    }
+
+    #[test]
+    fn test_offscreen_sprites_count_towards_limit() {
+        let mut ppu = ppu_with_tiles();
+        for index in 0..10 {
+            let offset = index * 4;
+            ppu.oam[offset..offset + 4].copy_from_slice(&[16, 0, 1, 0]);
+        }
+        ppu.oam[40..44].copy_from_slice(&[16, 8, 1, 0]);
+        ppu.write(0xFF40, LCDC_ENABLE | LCDC_OBJ_ENABLE);
+
+        run_lines(&mut ppu, 1);
+
+        assert_eq!(pixel(&ppu, 0, 0), DMG_COLORS[0]);
+    }
+
+    #[test]
+    fn test_tall_sprites() {
+        let mut ppu = ppu_with_tiles();
+        ppu.oam[0..4].copy_from_slice(&[16, 8, 3, 0]);
+        ppu.write(0xFF40, LCDC_ENABLE | LCDC_OBJ_ENABLE | LCDC_OBJ_SIZE);
+
+        run_lines(&mut ppu, 9);
+
+        // Tile 3 is forced to tile 2 for the top half and tile 3 is the bottom half.
+        assert_eq!(pixel(&ppu, 1, 0), DMG_COLORS[1]);
+        assert_eq!(pixel(&ppu, 0, 8), DMG_COLORS[2]);
+        assert_eq!(pixel(&ppu, 1, 8), DMG_COLORS[0]);
+    }
+
+    fn cgb_ppu_with_tiles() -> Ppu {
+        let mut ppu = Ppu::new_cgb();
+        ppu.lcdc = 0;
+        for row in 0..8 {
+            // Bank 0 tile 1 is solid color 3, bank 1 tile 1 has color 1 in its left column.
+            ppu.vram[16 + row * 2] = 0xFF;
+            ppu.vram[16 + row * 2 + 1] = 0xFF;
+            ppu.vram[VRAM_BANK_SIZE + 16 + row * 2] = 0x80;
+        }
+        ppu
+    }
+
+    fn write_palette_colors(ppu: &mut Ppu, index_register: u16, palette: u8, colors: [u16; 4]) {
+        ppu.write(index_register, PALETTE_AUTO_INCREMENT | (palette * 8));
+        for color in colors {
+            ppu.write(index_register + 1, color as u8);
+            ppu.write(index_register + 1, (color >> 8) as u8);
+        }
+    }
+
+    #[test]
+    fn test_vram_banking() {
+        let mut ppu = Ppu::new_cgb();
+        ppu.write(0x8000, 0x12);
+
+        ppu.write(0xFF4F, 0x01);
+        ppu.write(0x8000, 0x34);
+
+        assert_eq!(ppu.read(0xFF4F), 0xFF);
+        assert_eq!(ppu.read(0x8000), 0x34);
+        ppu.write(0xFF4F, 0x00);
+        assert_eq!(ppu.read(0xFF4F), 0xFE);
+        assert_eq!(ppu.read(0x8000), 0x12);
+    }
+
+    #[test]
+    fn test_cgb_registers_unmapped_on_dmg() {
+        let mut ppu = Ppu::new();
+
+        ppu.write(0xFF4F, 0x01);
+        ppu.write(0xFF68, 0x80);
+
+        assert_eq!(ppu.read(0xFF4F), 0xFF);
+        assert_eq!(ppu.read(0xFF68), 0xFF);
+    }
+
+    #[test]
+    fn test_palette_auto_increment() {
+        // Arrange
+        let mut ppu = Ppu::new_cgb();
+        ppu.write(0xFF6A, 0xBF);
+
+        // Act
+        ppu.write(0xFF6B, 0x12);
+        ppu.write(0xFF6B, 0x34);
+
+        // Assert
+        assert_eq!(ppu.read(0xFF6A), 0xC1);
+        assert_eq!(ppu.obj_palettes[0x3F], 0x12);
+        assert_eq!(ppu.obj_palettes[0x00], 0x34);
+    }
+
+    #[test]
+    fn test_palette_blocked_while_drawing() {
+        let mut ppu = Ppu::new_cgb();
+        ppu.write(0xFF68, 0x80);
+        ppu.tick(80);
+
+        ppu.write(0xFF69, 0x12);
+
+        assert_eq!(ppu.read(0xFF69), 0xFF);
+        assert_eq!(ppu.read(0xFF68), 0xC1);
+        assert_eq!(ppu.bg_palettes[0], 0xFF);
+    }
+
+    #[test]
+    fn test_render_cgb_background_attributes() {
+        // Arrange
+        let mut ppu = cgb_ppu_with_tiles();
+        ppu.vram[0x1800] = 1;
+        ppu.vram[0x1801] = 1;
+        ppu.vram[VRAM_BANK_SIZE + 0x1800] = 0x02;
+        ppu.vram[VRAM_BANK_SIZE + 0x1801] = BG_BANK | BG_X_FLIP | 0x03;
+        write_palette_colors(&mut ppu, 0xFF68, 2, [0x001F; 4]);
+        write_palette_colors(&mut ppu, 0xFF68, 3, [0x0000, 0x7C00, 0x0000, 0x0000]);
+        ppu.write(0xFF40, LCDC_ENABLE | LCDC_BG_ENABLE | LCDC_TILE_DATA);
+
+        // Act
+        run_lines(&mut ppu, 1);
+
+        // Assert
+        assert_eq!(pixel(&ppu, 0, 0), [0xFF, 0x00, 0x00, 0xFF]);
+        assert_eq!(pixel(&ppu, 14, 0), [0x00, 0x00, 0x00, 0xFF]);
+        assert_eq!(pixel(&ppu, 15, 0), [0x00, 0x00, 0xFF, 0xFF]);
+    }
+
+    #[rstest]
+    #[case(LCDC_BG_ENABLE, BG_PRIORITY, false)]
+    #[case(LCDC_BG_ENABLE, 0, true)]
+    #[case(0, BG_PRIORITY, true)]
+    fn test_cgb_background_priority(
+        #[case] master_priority: u8,
+        #[case] attributes: u8,
+        #[case] sprite_visible: bool,
+    ) {
+        // Arrange
+        let mut ppu = cgb_ppu_with_tiles();
+        ppu.vram[0x1800] = 1;
+        ppu.vram[VRAM_BANK_SIZE + 0x1800] = attributes;
+        ppu.oam[0..4].copy_from_slice(&[16, 8, 1, 0x01]);
+        write_palette_colors(&mut ppu, 0xFF68, 0, [0x001F; 4]);
+        write_palette_colors(&mut ppu, 0xFF6A, 1, [0x03E0; 4]);
+        ppu.write(
+            0xFF40,
+            LCDC_ENABLE | LCDC_OBJ_ENABLE | LCDC_TILE_DATA | master_priority,
+        );
+
+        // Act
+        run_lines(&mut ppu, 1);
+
+        // Assert
+        let expected = if sprite_visible {
+            [0x00, 0xFF, 0x00, 0xFF]
+        } else {
+            [0xFF, 0x00, 0x00, 0xFF]
+        };
+        assert_eq!(pixel(&ppu, 0, 0), expected);
+    }
+
+    #[test]
+    fn test_cgb_sprites_use_oam_order() {
+        let mut ppu = cgb_ppu_with_tiles();
+        ppu.oam[0..4].copy_from_slice(&[16, 12, 1, 0x01]);
+        ppu.oam[4..8].copy_from_slice(&[16, 8, 1, 0x02]);
+        write_palette_colors(&mut ppu, 0xFF6A, 1, [0x03E0; 4]);
+        write_palette_colors(&mut ppu, 0xFF6A, 2, [0x7C00; 4]);
+        ppu.write(0xFF40, LCDC_ENABLE | LCDC_OBJ_ENABLE);
+
+        run_lines(&mut ppu, 1);
+
+        assert_eq!(pixel(&ppu, 4, 0), [0x00, 0xFF, 0x00, 0xFF]);
+        assert_eq!(pixel(&ppu, 3, 0), [0x00, 0x00, 0xFF, 0xFF]);
+    }
+
+    #[rstest]
+    #[case(Accuracy::Normal)]
+    #[case(Accuracy::High)]
+    fn test_opri_x_priority(#[case] accuracy: Accuracy) {
+        // Arrange
+        let mut ppu = cgb_ppu_with_tiles();
+        ppu.set_accuracy(accuracy);
+        ppu.oam[0..4].copy_from_slice(&[16, 12, 1, 0x01]);
+        ppu.oam[4..8].copy_from_slice(&[16, 8, 1, 0x02]);
+        write_palette_colors(&mut ppu, 0xFF6A, 1, [0x03E0; 4]);
+        write_palette_colors(&mut ppu, 0xFF6A, 2, [0x7C00; 4]);
+
+        // Act
+        ppu.write(0xFF6C, 0x01);
+        ppu.write(0xFF40, LCDC_ENABLE | LCDC_OBJ_ENABLE);
+        run_lines(&mut ppu, 1);
+
+        // Assert
+        assert_eq!(ppu.read(0xFF6C), 0xFF);
+        assert_eq!(pixel(&ppu, 4, 0), [0x00, 0x00, 0xFF, 0xFF]);
+        assert_eq!(pixel(&ppu, 8, 0), [0x00, 0xFF, 0x00, 0xFF]);
+    }
+
+    #[test]
+    fn test_hblank_started() {
+        let mut ppu = Ppu::new();
+
+        ppu.tick(OAM_SCAN_DOTS + DRAWING_DOTS - 1);
+        assert!(!ppu.take_hblank_started());
+
+        ppu.tick(1);
+        assert!(ppu.take_hblank_started());
+        assert!(!ppu.take_hblank_started());
+    }
+}
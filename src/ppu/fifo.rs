@@ -0,0 +1,570 @@
+use std::collections::VecDeque;
+use std::io::{Error, Result};
+
+use super::{
+    Layer, Ppu, BG_X_FLIP, LCDC_BG_ENABLE, LCDC_BG_MAP, LCDC_OBJ_ENABLE, LCDC_OBJ_SIZE,
+    LCDC_WINDOW_ENABLE, LCDC_WINDOW_MAP, OAM_SIZE, OBJ_CGB_BANK, OBJ_X_FLIP, OBJ_Y_FLIP,
+    SCREEN_WIDTH, VRAM_BANK_SIZE,
+};
+use crate::savestate::{StateReader, StateWriter};
+
+// What the PPU keeps while it draws a line a dot at a time in high accuracy. A fetcher reads the
+// tile map and tile data and pushes 8 pixels at a time into the background FIFO, which shifts a
+// pixel out to the screen each dot. Objects stop the shifting while they're fetched and mixed into
+// a FIFO of their own. Registers are read when each step needs them, so a write in the middle of a
+// line takes effect at the pixel it would on hardware, and mode 3 takes as long as it does there.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub(super) struct Fifo {
+    // Whether the line being drawn goes through the FIFO, decided as it starts drawing so that
+    // changing the accuracy never leaves a line half done.
+    pub(super) active: bool,
+    // Pixels shifted out to the screen so far.
+    pub(super) x: u8,
+    // Dots before the fetcher starts, while the hardware fetches the first tile and throws it away.
+    delay: u8,
+    // Pixels still to drop from the FIFO before the screen starts, for SCX's fine scroll.
+    discard: u8,
+    background: VecDeque<Pixel>,
+    objects: VecDeque<ObjectPixel>,
+    fetcher: Fetcher,
+    // The objects on the line that haven't been fetched, by OAM index, leftmost first.
+    pending: VecDeque<u8>,
+    // Dots spent fetching the first pending object.
+    object_dots: u8,
+    // Whether the fetcher switched to the window on this line.
+    pub(super) window: bool,
+    // Whether LY has matched WY this frame, which the window has to wait for.
+    window_y: bool,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+struct Pixel {
+    color: u8,
+    // The tile's attributes, which only exist in CGB mode.
+    attributes: u8,
+    window: bool,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+struct ObjectPixel {
+    color: u8,
+    flags: u8,
+    index: u8,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+struct Fetcher {
+    step: Step,
+    // Dots into the step.
+    dots: u8,
+    // Tiles pushed since the line or the window started.
+    tile_x: u8,
+    tile: u8,
+    attributes: u8,
+    low: u8,
+    high: u8,
+}
+
+// Each step but the push takes 2 dots. The push waits for the background FIFO to empty.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+enum Step {
+    #[default]
+    Tile = 0,
+    Low = 1,
+    High = 2,
+    Push = 3,
+}
+
+impl Step {
+    fn next(self) -> Step {
+        match self {
+            Step::Tile => Step::Low,
+            Step::Low => Step::High,
+            Step::High => Step::Push,
+            Step::Push => Step::Tile,
+        }
+    }
+}
+
+impl Fifo {
+    pub(super) fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_bool(self.active);
+        writer.write_u8(self.x);
+        writer.write_u8(self.delay);
+        writer.write_u8(self.discard);
+        let background: Vec<u8> = self
+            .background
+            .iter()
+            .flat_map(|pixel| [pixel.color, pixel.attributes, pixel.window as u8])
+            .collect();
+        writer.write_bytes(&background);
+        let objects: Vec<u8> = self
+            .objects
+            .iter()
+            .flat_map(|pixel| [pixel.color, pixel.flags, pixel.index])
+            .collect();
+        writer.write_bytes(&objects);
+        let fetcher = &self.fetcher;
+        for value in [
+            fetcher.step as u8,
+            fetcher.dots,
+            fetcher.tile_x,
+            fetcher.tile,
+            fetcher.attributes,
+            fetcher.low,
+            fetcher.high,
+        ] {
+            writer.write_u8(value);
+        }
+        let pending: Vec<u8> = self.pending.iter().copied().collect();
+        writer.write_bytes(&pending);
+        writer.write_u8(self.object_dots);
+        writer.write_bool(self.window);
+        writer.write_bool(self.window_y);
+    }
+
+    pub(super) fn load_state(&mut self, reader: &mut StateReader) -> Result<()> {
+        let invalid = || Error::other("The pixel FIFO in the save state is invalid.");
+        self.active = reader.read_bool()?;
+        self.x = reader.read_u8()?;
+        self.delay = reader.read_u8()?;
+        self.discard = reader.read_u8()?;
+        let background = reader.read_bytes()?;
+        if background.len() % 3 != 0 || self.x as usize > SCREEN_WIDTH {
+            return Err(invalid());
+        }
+        self.background = background
+            .chunks_exact(3)
+            .map(|pixel| Pixel {
+                color: pixel[0] & 0x03,
+                attributes: pixel[1],
+                window: pixel[2] != 0,
+            })
+            .collect();
+        let objects = reader.read_bytes()?;
+        if objects.len() % 3 != 0 {
+            return Err(invalid());
+        }
+        self.objects = objects
+            .chunks_exact(3)
+            .map(|pixel| ObjectPixel {
+                color: pixel[0] & 0x03,
+                flags: pixel[1],
+                index: pixel[2],
+            })
+            .collect();
+        let step = match reader.read_u8()? {
+            0 => Step::Tile,
+            1 => Step::Low,
+            2 => Step::High,
+            3 => Step::Push,
+            _ => return Err(invalid()),
+        };
+        let [dots, tile_x, tile, attributes, low, high] = reader.read_array()?;
+        self.fetcher = Fetcher {
+            step,
+            dots,
+            tile_x,
+            tile,
+            attributes,
+            low,
+            high,
+        };
+        let pending = reader.read_bytes()?;
+        if pending.iter().any(|&index| index as usize >= OAM_SIZE / 4) {
+            return Err(invalid());
+        }
+        self.pending = pending.iter().copied().collect();
+        self.object_dots = reader.read_u8()?;
+        self.window = reader.read_bool()?;
+        self.window_y = reader.read_bool()?;
+        Ok(())
+    }
+}
+
+impl Ppu {
+    // Gets the FIFO ready as the line starts drawing, after the OAM scan.
+    pub(super) fn start_fifo_line(&mut self) {
+        let mut pending = self.line_objects();
+        // A stable sort, so objects at the same X come up in OAM order.
+        pending.sort_by_key(|&index| object_start(self.oam[index * 4 + 1]));
+
+        let fifo = &mut self.fifo;
+        fifo.active = true;
+        fifo.x = 0;
+        fifo.delay = FIRST_FETCH_DOTS;
+        fifo.discard = self.scx % 8;
+        fifo.background.clear();
+        fifo.objects.clear();
+        fifo.fetcher = Fetcher::default();
+        fifo.pending = pending.into_iter().map(|index| index as u8).collect();
+        fifo.object_dots = 0;
+        fifo.window = false;
+        fifo.window_y = (fifo.window_y && self.ly != 0) || self.ly == self.wy;
+    }
+
+    // Runs a dot of drawing. Returns true once the line's last pixel is out.
+    pub(super) fn step_fifo(&mut self) -> bool {
+        if self.fifo.delay > 0 {
+            self.fifo.delay -= 1;
+            return false;
+        }
+        self.start_window();
+
+        // The background fetcher finishes the tile it's on before the object's fetch can start.
+        if self.object_due() {
+            self.step_fetcher();
+            if self.fifo.fetcher.step == Step::Push && !self.fifo.background.is_empty() {
+                self.fifo.object_dots += 1;
+                if self.fifo.object_dots == OBJECT_FETCH_DOTS {
+                    self.fifo.object_dots = 0;
+                    if let Some(index) = self.fifo.pending.pop_front() {
+                        self.fetch_object(index);
+                    }
+                }
+            }
+            return false;
+        }
+
+        self.step_fetcher();
+        self.shift_pixel()
+    }
+
+    fn object_due(&self) -> bool {
+        self.fifo.discard == 0
+            && self.lcdc & LCDC_OBJ_ENABLE != 0
+            && self
+                .fifo
+                .pending
+                .front()
+                .is_some_and(|&index| object_start(self.oam[index as usize * 4 + 1]) == self.fifo.x)
+    }
+
+    // Throws away the background pixels and fetches the window instead once it's reached.
+    fn start_window(&mut self) {
+        let x = self.fifo.x as u16;
+        let reached = x + 7 == self.wx as u16 || (x == 0 && self.wx < 7);
+        if self.fifo.window
+            || !self.fifo.window_y
+            || self.lcdc & LCDC_WINDOW_ENABLE == 0
+            || !reached
+        {
+            return;
+        }
+
+        self.fifo.window = true;
+        self.fifo.background.clear();
+        self.fifo.fetcher = Fetcher::default();
+        // The window ignores SCX, but starts partway into its first tile when WX is below 7.
+        self.fifo.discard = 7u8.saturating_sub(self.wx);
+    }
+
+    fn step_fetcher(&mut self) {
+        let step = self.fifo.fetcher.step;
+        if step == Step::Push {
+            if self.fifo.background.is_empty() {
+                self.push_tile();
+            }
+            return;
+        }
+
+        self.fifo.fetcher.dots += 1;
+        if self.fifo.fetcher.dots < FETCH_STEP_DOTS {
+            return;
+        }
+        self.fifo.fetcher.dots = 0;
+        match step {
+            Step::Tile => self.fetch_tile(),
+            Step::Low => self.fifo.fetcher.low = self.vram[self.fetcher_row_address()],
+            Step::High | Step::Push => {
+                self.fifo.fetcher.high = self.vram[self.fetcher_row_address() + 1]
+            }
+        }
+        self.fifo.fetcher.step = step.next();
+    }
+
+    fn fetch_tile(&mut self) {
+        let tile_x = self.fifo.fetcher.tile_x;
+        let (map_bit, column, y) = if self.fifo.window {
+            (LCDC_WINDOW_MAP, tile_x, self.window_line)
+        } else {
+            (
+                LCDC_BG_MAP,
+                (self.scx / 8).wrapping_add(tile_x),
+                self.scy.wrapping_add(self.ly),
+            )
+        };
+        let map = if self.lcdc & map_bit != 0 {
+            0x1C00
+        } else {
+            0x1800
+        };
+        let address = map + (y as usize / 8) * 32 + column as usize % 32;
+        self.fifo.fetcher.tile = self.vram[address];
+        self.fifo.fetcher.attributes = if self.cgb {
+            self.vram[VRAM_BANK_SIZE + address]
+        } else {
+            0
+        };
+    }
+
+    // SCY is read again for the data, as on hardware, so changing it mid-fetch mixes two rows.
+    fn fetcher_row_address(&self) -> usize {
+        let y = if self.fifo.window {
+            self.window_line
+        } else {
+            self.scy.wrapping_add(self.ly)
+        };
+        let fetcher = &self.fifo.fetcher;
+        self.tile_row_address(fetcher.tile, fetcher.attributes, y)
+    }
+
+    fn push_tile(&mut self) {
+        let fetcher = self.fifo.fetcher;
+        for column in 0..8 {
+            let bit = if fetcher.attributes & BG_X_FLIP != 0 {
+                column
+            } else {
+                7 - column
+            };
+            self.fifo.background.push_back(Pixel {
+                color: color_id(fetcher.low, fetcher.high, bit),
+                attributes: fetcher.attributes,
+                window: self.fifo.window,
+            });
+        }
+        self.fifo.fetcher.tile_x = fetcher.tile_x.wrapping_add(1);
+        self.fifo.fetcher.step = Step::Tile;
+    }
+
+    // Mixes the object's row into the object FIFO. Pixels already there from an earlier object
+    // stay, except that on the CGB the object earlier in OAM wins.
+    fn fetch_object(&mut self, index: u8) {
+        let base = index as usize * 4;
+        let [y, x, mut tile, flags] = [0, 1, 2, 3].map(|offset| self.oam[base + offset]);
+        let height: u8 = if self.lcdc & LCDC_OBJ_SIZE != 0 {
+            16
+        } else {
+            8
+        };
+        let mut row = self.ly.wrapping_add(16).wrapping_sub(y) & (height - 1);
+        if flags & OBJ_Y_FLIP != 0 {
+            row = height - 1 - row;
+        }
+        if height == 16 {
+            tile &= 0xFE;
+        }
+        let bank_offset = if self.cgb && flags & OBJ_CGB_BANK != 0 {
+            VRAM_BANK_SIZE
+        } else {
+            0
+        };
+        let address = bank_offset + tile as usize * 16 + row as usize * 2;
+        let (low, high) = (self.vram[address], self.vram[address + 1]);
+
+        // Columns left of the screen are never shifted out.
+        let clipped = 8u8.saturating_sub(x);
+        let x_priority = self.x_priority();
+        for column in clipped..8 {
+            let bit = if flags & OBJ_X_FLIP != 0 {
+                column
+            } else {
+                7 - column
+            };
+            let pixel = ObjectPixel {
+                color: color_id(low, high, bit),
+                flags,
+                index,
+            };
+            match self.fifo.objects.get_mut((column - clipped) as usize) {
+                Some(existing) => {
+                    let wins = existing.color == 0 || (!x_priority && index < existing.index);
+                    if pixel.color != 0 && wins {
+                        *existing = pixel;
+                    }
+                }
+                None => self.fifo.objects.push_back(pixel),
+            }
+        }
+    }
+
+    // Shifts a pixel out of the FIFOs onto the screen, with the palettes and LCDC as they are now.
+    // Returns true once the line is done.
+    fn shift_pixel(&mut self) -> bool {
+        let Some(pixel) = self.fifo.background.pop_front() else {
+            return false;
+        };
+        if self.fifo.discard > 0 {
+            self.fifo.discard -= 1;
+            return false;
+        }
+
+        let layer = if pixel.window {
+            Layer::Window
+        } else {
+            Layer::Background
+        };
+        // On CGB, LCDC bit 0 doesn't hide the background, it only takes away its priority.
+        let (color_id, attributes, mut color) =
+            if self.layer_enabled(layer) && (self.cgb || self.lcdc & LCDC_BG_ENABLE != 0) {
+                let color = self.bg_color(pixel.color, pixel.attributes);
+                (pixel.color, pixel.attributes, color)
+            } else {
+                (0, 0, self.dmg_palette.bg[0])
+            };
+        if let Some(object) = self.fifo.objects.pop_front() {
+            let visible = object.color != 0
+                && self.lcdc & LCDC_OBJ_ENABLE != 0
+                && self.layer_enabled(Layer::Sprites);
+            if visible && !self.background_has_priority(object.flags, color_id, attributes) {
+                color = self.obj_color(object.color, object.flags);
+            }
+        }
+        self.set_pixel(self.fifo.x as usize, color);
+        self.fifo.x += 1;
+
+        // Objects that came up while objects were off don't get fetched later.
+        while let Some(&index) = self.fifo.pending.front() {
+            if object_start(self.oam[index as usize * 4 + 1]) >= self.fifo.x {
+                break;
+            }
+            self.fifo.pending.pop_front();
+        }
+        self.fifo.x as usize == SCREEN_WIDTH
+    }
+}
+
+// The first pixel an object covers. Objects further left than the screen come up at 0.
+fn object_start(x: u8) -> u8 {
+    x.saturating_sub(8)
+}
+
+fn color_id(low: u8, high: u8, bit: u8) -> u8 {
+    (((high >> bit) & 0x01) << 1) | ((low >> bit) & 0x01)
+}
+
+const FIRST_FETCH_DOTS: u8 = 6;
+
+const FETCH_STEP_DOTS: u8 = 2;
+
+const OBJECT_FETCH_DOTS: u8 = 5;
+
+#[cfg(test)]
+mod tests {
+    use rstest::rstest;
+
+    use super::*;
+    use crate::accuracy::Accuracy;
+    use crate::ppu::{Mode, DMG_COLORS, LCDC_ENABLE, LCDC_TILE_DATA, LINE_DOTS, OAM_SCAN_DOTS};
+    use crate::savestate::Snapshot;
+
+    // Tile 1 is solid color 3 and fills the background.
+    fn ppu(accuracy: Accuracy) -> Ppu {
+        let mut ppu = Ppu::new();
+        ppu.set_accuracy(accuracy);
+        ppu.vram[16..32].fill(0xFF);
+        ppu.vram[0x1800..0x1C00].fill(1);
+        ppu.bgp = 0xE4;
+        ppu.obp0 = 0xE4;
+        ppu.lcdc = LCDC_ENABLE | LCDC_BG_ENABLE | LCDC_OBJ_ENABLE | LCDC_TILE_DATA;
+        ppu
+    }
+
+    // A frame's worth of busy tiles, scrolling, window and overlapping objects.
+    fn scene(accuracy: Accuracy) -> Ppu {
+        let mut ppu = ppu(accuracy);
+        let mut seed: u32 = 1;
+        for byte in ppu.vram[..0x2000].iter_mut() {
+            seed = seed.wrapping_mul(1_103_515_245).wrapping_add(12_345);
+            *byte = (seed >> 16) as u8;
+        }
+        for (index, object) in ppu.oam.chunks_exact_mut(4).enumerate() {
+            let (y, x) = (16 + index * 7 % 150, 8 + index * 13 % 150);
+            let index = index as u8;
+            object.copy_from_slice(&[y as u8, x as u8, index, index << 4]);
+        }
+        ppu.scx = 13;
+        ppu.scy = 5;
+        ppu.wx = 50;
+        ppu.wy = 20;
+        ppu.lcdc = 0xFF;
+        ppu
+    }
+
+    fn drawing_dots(ppu: &mut Ppu) -> u32 {
+        ppu.tick(OAM_SCAN_DOTS);
+        let mut dots = 0;
+        while ppu.mode() == Mode::Drawing {
+            ppu.tick(1);
+            dots += 1;
+        }
+        dots
+    }
+
+    fn pixel(ppu: &Ppu, x: usize) -> [u8; 4] {
+        ppu.framebuffer()[x * 4..x * 4 + 4].try_into().unwrap()
+    }
+
+    #[test]
+    fn test_draws_like_scanline_renderer() {
+        let mut scanline = scene(Accuracy::Normal);
+        let mut fifo = scene(Accuracy::High);
+
+        scanline.tick(LINE_DOTS * 154);
+        fifo.tick(LINE_DOTS * 154);
+
+        assert!(scanline.framebuffer() == fifo.framebuffer());
+    }
+
+    #[rstest]
+    #[case(0, None, 172)]
+    #[case(3, None, 175)]
+    #[case(0, Some(8), 172 + 11)]
+    #[case(0, Some(8 + 16 + 5), 172 + 6)]
+    #[case(0, Some(0), 172 + 11)]
+    fn test_drawing_length(#[case] scx: u8, #[case] object_x: Option<u8>, #[case] expected: u32) {
+        let mut ppu = ppu(Accuracy::High);
+        ppu.scx = scx;
+        if let Some(x) = object_x {
+            ppu.oam[0..4].copy_from_slice(&[16, x, 0, 0]);
+        }
+
+        assert_eq!(drawing_dots(&mut ppu), expected);
+    }
+
+    #[test]
+    fn test_palette_change_mid_line() {
+        // Arrange
+        let mut ppu = ppu(Accuracy::High);
+        ppu.tick(OAM_SCAN_DOTS + FIRST_FETCH_DOTS as u32 + 6 + 80);
+
+        // Act
+        ppu.write(0xFF47, 0x00);
+        ppu.tick(LINE_DOTS);
+
+        // Assert
+        assert_eq!(pixel(&ppu, 0), DMG_COLORS[3]);
+        assert_eq!(pixel(&ppu, 78), DMG_COLORS[3]);
+        assert_eq!(pixel(&ppu, 82), DMG_COLORS[0]);
+    }
+
+    #[test]
+    fn test_save_state_mid_line() {
+        // Arrange
+        let mut ppu = scene(Accuracy::High);
+        ppu.tick(LINE_DOTS * 30 + OAM_SCAN_DOTS + 100);
+        let mut writer = StateWriter::new();
+        ppu.save_state(&mut writer);
+        let bytes = writer.into_bytes();
+        let mut loaded = scene(Accuracy::High);
+
+        // Act
+        loaded.load_state(&mut StateReader::new(&bytes)).unwrap();
+        ppu.tick(LINE_DOTS);
+        loaded.tick(LINE_DOTS);
+
+        // Assert
+        assert_eq!(loaded.fifo, ppu.fifo);
+        assert!(loaded.framebuffer() == ppu.framebuffer());
+    }
+}
@@ -0,0 +1,168 @@
+use super::{Mode, Ppu, OAM_SIZE};
+use crate::accuracy::Accuracy;
+
+// How the CPU touched 0xFE00-0xFEFF. Any access counts, even the increment/decrement unit just
+// holding an address there while it works on a register pair.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum OamCorruption {
+    // A write, or an increment or decrement on its own like INC rr.
+    Write,
+    Read,
+    // A read while the register pair is incremented or decremented, like LD A,(HL+) and POP.
+    ReadIncrementing,
+}
+
+// The monochrome models scan OAM a row of 8 bytes every machine cycle in mode 2, and an access at
+// the same time garbles the row being scanned with the one before it. Games never do it on
+// purpose, but a few trip over it and test ROMs check for it, so it's only emulated in high
+// accuracy.
+impl Ppu {
+    pub fn corrupt_oam(&mut self, corruption: OamCorruption) {
+        if self.accuracy != Accuracy::High
+            || !self.oam_bug
+            || !self.lcd_enabled()
+            || self.mode != Mode::OamScan
+        {
+            return;
+        }
+        // The first row has nothing before it and is left alone.
+        let row = (self.line_dot / 4) as usize;
+        if row == 0 || row >= OAM_ROWS {
+            return;
+        }
+
+        match corruption {
+            OamCorruption::Write => {
+                let (a, b, c) = (
+                    self.oam_word(row, 0),
+                    self.oam_word(row - 1, 0),
+                    self.oam_word(row - 1, 2),
+                );
+                self.set_oam_word(row, 0, ((a ^ c) & (b ^ c)) ^ c);
+                self.copy_oam_row(row - 1, row, 2);
+            }
+            OamCorruption::Read => self.corrupt_oam_read(row),
+            OamCorruption::ReadIncrementing => {
+                // Rows near either end only get the read's corruption.
+                if (4..OAM_ROWS - 1).contains(&row) {
+                    let a = self.oam_word(row - 2, 0);
+                    let b = self.oam_word(row - 1, 0);
+                    let c = self.oam_word(row, 0);
+                    let d = self.oam_word(row - 1, 2);
+                    self.set_oam_word(row - 1, 0, (b & (a | c | d)) | (a & c & d));
+                    self.copy_oam_row(row - 1, row, 0);
+                    self.copy_oam_row(row - 1, row - 2, 0);
+                }
+                self.corrupt_oam_read(row);
+            }
+        }
+    }
+
+    fn corrupt_oam_read(&mut self, row: usize) {
+        let (a, b, c) = (
+            self.oam_word(row, 0),
+            self.oam_word(row - 1, 0),
+            self.oam_word(row - 1, 2),
+        );
+        self.set_oam_word(row, 0, b | (a & c));
+        self.copy_oam_row(row - 1, row, 2);
+    }
+
+    fn oam_word(&self, row: usize, word: usize) -> u16 {
+        let offset = row * OAM_ROW_SIZE + word * 2;
+        u16::from_le_bytes([self.oam[offset], self.oam[offset + 1]])
+    }
+
+    fn set_oam_word(&mut self, row: usize, word: usize, value: u16) {
+        let offset = row * OAM_ROW_SIZE + word * 2;
+        self.oam[offset..offset + 2].copy_from_slice(&value.to_le_bytes());
+    }
+
+    // Copies a row from byte `start` on.
+    fn copy_oam_row(&mut self, from: usize, to: usize, start: usize) {
+        let from = from * OAM_ROW_SIZE;
+        self.oam
+            .copy_within(from + start..from + OAM_ROW_SIZE, to * OAM_ROW_SIZE + start);
+    }
+}
+
+const OAM_ROW_SIZE: usize = 8;
+
+const OAM_ROWS: usize = OAM_SIZE / OAM_ROW_SIZE;
+
+#[cfg(test)]
+mod tests {
+    use rstest::rstest;
+
+    use super::*;
+    use crate::model::EmulatorModel;
+    use crate::ppu::{LINE_DOTS, OAM_SCAN_DOTS};
+
+    // Every byte of OAM is its own index, so moved bytes are easy to spot.
+    fn ppu(model: EmulatorModel, accuracy: Accuracy, dots: u32) -> Ppu {
+        let mut ppu = Ppu::for_model(model, model.is_cgb());
+        ppu.set_accuracy(accuracy);
+        for (index, byte) in ppu.oam.iter_mut().enumerate() {
+            *byte = index as u8;
+        }
+        ppu.tick(dots);
+        ppu
+    }
+
+    fn row(ppu: &Ppu, row: usize) -> &[u8] {
+        &ppu.oam[row * OAM_ROW_SIZE..(row + 1) * OAM_ROW_SIZE]
+    }
+
+    #[test]
+    fn test_write_corruption() {
+        // Arrange
+        let mut ppu = ppu(EmulatorModel::Dmg, Accuracy::High, 4 * 5);
+
+        // Act
+        ppu.corrupt_oam(OamCorruption::Write);
+
+        // Assert
+        let (a, b, c) = (0x2928u16, 0x2120u16, 0x2524u16);
+        let first = (((a ^ c) & (b ^ c)) ^ c).to_le_bytes();
+        assert_eq!(row(&ppu, 5), [first[0], first[1], 34, 35, 36, 37, 38, 39]);
+        assert_eq!(row(&ppu, 4), [32, 33, 34, 35, 36, 37, 38, 39]);
+    }
+
+    #[test]
+    fn test_read_incrementing_corruption() {
+        // Arrange
+        let mut ppu = ppu(EmulatorModel::Dmg, Accuracy::High, 4 * 5);
+
+        // Act
+        ppu.corrupt_oam(OamCorruption::ReadIncrementing);
+
+        // Assert
+        let (a, b, c, d) = (0x1918u16, 0x2120u16, 0x2928u16, 0x2524u16);
+        let previous = ((b & (a | c | d)) | (a & c & d)).to_le_bytes();
+        let expected = [previous[0], previous[1], 34, 35, 36, 37, 38, 39];
+        // The read corruption that follows mixes the row with an identical one.
+        for index in 3..=5 {
+            assert_eq!(row(&ppu, index), expected);
+        }
+    }
+
+    #[rstest]
+    #[case(EmulatorModel::Dmg, Accuracy::Normal, 4 * 5)]
+    #[case(EmulatorModel::Cgb, Accuracy::High, 4 * 5)]
+    #[case(EmulatorModel::Dmg, Accuracy::High, 0)]
+    #[case(EmulatorModel::Dmg, Accuracy::High, OAM_SCAN_DOTS + 4)]
+    #[case(EmulatorModel::Dmg, Accuracy::High, LINE_DOTS * 144 + 20)]
+    fn test_no_corruption(
+        #[case] model: EmulatorModel,
+        #[case] accuracy: Accuracy,
+        #[case] dots: u32,
+    ) {
+        let mut ppu = ppu(model, accuracy, dots);
+        let before = ppu.oam.clone();
+
+        ppu.corrupt_oam(OamCorruption::Write);
+        ppu.corrupt_oam(OamCorruption::Read);
+
+        assert_eq!(ppu.oam, before);
+    }
+}
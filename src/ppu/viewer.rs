@@ -0,0 +1,358 @@
+use std::fmt;
+#[cfg(feature = "png")]
+use std::io::{Error, Result};
+
+use super::{
+    Ppu, LCDC_BG_MAP, LCDC_WINDOW_ENABLE, LCDC_WINDOW_MAP, OBJ_BEHIND_BG, OBJ_CGB_BANK,
+    OBJ_PALETTE, OBJ_X_FLIP, OBJ_Y_FLIP, SCREEN_HEIGHT, SCREEN_WIDTH, VRAM_BANK_SIZE,
+};
+
+// A picture of VRAM, OAM or palette RAM for debugging and ripping graphics. Decoding one doesn't
+// change the PPU, so it can happen between any two frames.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Image {
+    pub width: usize,
+    pub height: usize,
+    // 4 bytes (RGBA) a pixel.
+    pub pixels: Vec<u8>,
+}
+
+impl Image {
+    pub fn new(width: usize, height: usize) -> Image {
+        Image {
+            width,
+            height,
+            pixels: BACKGROUND.repeat(width * height),
+        }
+    }
+
+    pub fn pixel(&self, x: usize, y: usize) -> [u8; 4] {
+        let offset = (y * self.width + x) * 4;
+        self.pixels[offset..offset + 4].try_into().unwrap()
+    }
+
+    fn set_pixel(&mut self, x: usize, y: usize, color: [u8; 4]) {
+        let offset = (y * self.width + x) * 4;
+        self.pixels[offset..offset + 4].copy_from_slice(&color);
+    }
+
+    // Copies `image` in with its top left corner at `x` and `y`.
+    fn draw(&mut self, image: &Image, x: usize, y: usize) {
+        for row in 0..image.height {
+            let source = row * image.width * 4;
+            let target = ((y + row) * self.width + x) * 4;
+            self.pixels[target..target + image.width * 4]
+                .copy_from_slice(&image.pixels[source..source + image.width * 4]);
+        }
+    }
+
+    // The outline of a `width` by `height` rectangle, wrapping around the edges like the tile maps
+    // do.
+    fn draw_outline(&mut self, x: usize, y: usize, width: usize, height: usize, color: [u8; 4]) {
+        for offset in 0..width {
+            let column = (x + offset) % self.width;
+            self.set_pixel(column, y % self.height, color);
+            self.set_pixel(column, (y + height - 1) % self.height, color);
+        }
+        for offset in 0..height {
+            let row = (y + offset) % self.height;
+            self.set_pixel(x % self.width, row, color);
+            self.set_pixel((x + width - 1) % self.width, row, color);
+        }
+    }
+
+    #[cfg(feature = "png")]
+    pub fn to_png(&self) -> Result<Vec<u8>> {
+        let mut png = Vec::new();
+        let mut encoder = png::Encoder::new(&mut png, self.width as u32, self.height as u32);
+        encoder.set_color(png::ColorType::Rgba);
+        encoder.set_depth(png::BitDepth::Eight);
+        let mut writer = encoder.write_header().map_err(Error::other)?;
+        writer
+            .write_image_data(&self.pixels)
+            .map_err(Error::other)?;
+        writer.finish().map_err(Error::other)?;
+        Ok(png)
+    }
+}
+
+// An entry in OAM.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Object {
+    pub index: usize,
+    // Where the sprite's top left corner is on screen, off screen when negative or past the edge.
+    pub x: i16,
+    pub y: i16,
+    pub tile: u8,
+    pub attributes: u8,
+}
+
+impl fmt::Display for Object {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{:2}: x {:4} y {:4} tile {:02X} attributes {:02X}",
+            self.index, self.x, self.y, self.tile, self.attributes
+        )?;
+        // The low 3 bits are the CGB palette, which the hex already shows.
+        for (flag, name) in [
+            (OBJ_BEHIND_BG, "behind-bg"),
+            (OBJ_Y_FLIP, "y-flip"),
+            (OBJ_X_FLIP, "x-flip"),
+            (OBJ_PALETTE, "obp1"),
+            (OBJ_CGB_BANK, "bank-1"),
+        ] {
+            if self.attributes & flag != 0 {
+                write!(f, " {}", name)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Ppu {
+    // Every tile in VRAM, 16 to a row, with the background palette 0 colors. CGB's second bank sits
+    // to the right of the first.
+    pub fn tile_sheet(&self) -> Image {
+        let banks = self.vram.len() / VRAM_BANK_SIZE;
+        let mut sheet = Image::new(TILE_SHEET_COLUMNS * 8 * banks, TILE_SHEET_ROWS * 8);
+        for bank in 0..banks {
+            for tile in 0..TILE_SHEET_COLUMNS * TILE_SHEET_ROWS {
+                let address = bank * VRAM_BANK_SIZE + tile * 16;
+                let (left, top) = (
+                    (bank * TILE_SHEET_COLUMNS + tile % TILE_SHEET_COLUMNS) * 8,
+                    tile / TILE_SHEET_COLUMNS * 8,
+                );
+                for row in 0..8 {
+                    for column in 0..8 {
+                        let color_id = self.tile_data_pixel(address + row * 2, 7 - column as u8);
+                        sheet.set_pixel(left + column, top + row, self.bg_color(color_id, 0));
+                    }
+                }
+            }
+        }
+        sheet
+    }
+
+    // The 256x256 tile map at 0x9800 (`map` 0) or 0x9C00 (1), using the tile data LCDC picks. The
+    // part the screen shows is outlined when the background uses the map, and so is the window's
+    // when it's on and uses it.
+    pub fn tile_map(&self, map: usize) -> Image {
+        let base = if map == 0 { 0x1800 } else { 0x1C00 };
+        let mut image = Image::new(TILE_MAP_SIZE, TILE_MAP_SIZE);
+        for y in 0..TILE_MAP_SIZE {
+            for x in 0..TILE_MAP_SIZE {
+                let (color_id, attributes) = self.tile_map_pixel(base, x as u8, y as u8);
+                image.set_pixel(x, y, self.bg_color(color_id, attributes));
+            }
+        }
+
+        let uses = |flag| (self.lcdc & flag != 0) == (map == 1);
+        if uses(LCDC_BG_MAP) {
+            image.draw_outline(
+                self.scx as usize,
+                self.scy as usize,
+                SCREEN_WIDTH,
+                SCREEN_HEIGHT,
+                VIEWPORT_COLOR,
+            );
+        }
+        let window_x = (self.wx as usize).saturating_sub(7);
+        if self.lcdc & LCDC_WINDOW_ENABLE != 0
+            && uses(LCDC_WINDOW_MAP)
+            && window_x < SCREEN_WIDTH
+            && (self.wy as usize) < SCREEN_HEIGHT
+        {
+            image.draw_outline(
+                0,
+                0,
+                SCREEN_WIDTH - window_x,
+                SCREEN_HEIGHT - self.wy as usize,
+                WINDOW_COLOR,
+            );
+        }
+        image
+    }
+
+    pub fn objects(&self) -> Vec<Object> {
+        self.oam
+            .chunks_exact(4)
+            .enumerate()
+            .map(|(index, entry)| Object {
+                index,
+                x: entry[1] as i16 - 8,
+                y: entry[0] as i16 - 16,
+                tile: entry[2],
+                attributes: entry[3],
+            })
+            .collect()
+    }
+
+    // A row of 4 swatches for each palette: the 8 background then 8 object palettes in CGB and
+    // compatibility mode, BGP, OBP0 and OBP1 in DMG mode.
+    pub fn palette_swatches(&self) -> Image {
+        let rows: Vec<[[u8; 4]; 4]> = if self.cgb || self.dmg_compatibility {
+            [&self.bg_palettes, &self.obj_palettes]
+                .into_iter()
+                .flat_map(|ram| {
+                    (0..8u8).map(move |palette| {
+                        [0, 1, 2, 3].map(|color_id| Ppu::cgb_color(ram, palette, color_id))
+                    })
+                })
+                .collect()
+        } else {
+            let shades = |register: u8, colors: &[[u8; 4]; 4]| {
+                [0, 1, 2, 3].map(|color_id| colors[Ppu::shade(register, color_id) as usize])
+            };
+            vec![
+                shades(self.bgp, &self.dmg_palette.bg),
+                shades(self.obp0, &self.dmg_palette.obj0),
+                shades(self.obp1, &self.dmg_palette.obj1),
+            ]
+        };
+
+        let mut image = Image::new(4 * SWATCH_SIZE, rows.len() * SWATCH_SIZE);
+        for (row, colors) in rows.iter().enumerate() {
+            for (column, &color) in colors.iter().enumerate() {
+                for y in 0..SWATCH_SIZE {
+                    for x in 0..SWATCH_SIZE {
+                        image.set_pixel(column * SWATCH_SIZE + x, row * SWATCH_SIZE + y, color);
+                    }
+                }
+            }
+        }
+        image
+    }
+
+    // The tile sheet, both tile maps and the palettes side by side, for a debug window.
+    pub fn vram_overview(&self) -> Image {
+        let parts = [
+            self.tile_sheet(),
+            self.tile_map(0),
+            self.tile_map(1),
+            self.palette_swatches(),
+        ];
+        let width = parts.iter().map(|part| part.width + GAP).sum::<usize>() - GAP;
+        let height = parts.iter().map(|part| part.height).max().unwrap_or(0);
+        let mut overview = Image::new(width, height);
+        let mut x = 0;
+        for part in &parts {
+            overview.draw(part, x, 0);
+            x += part.width + GAP;
+        }
+        overview
+    }
+}
+
+const TILE_SHEET_COLUMNS: usize = 16;
+
+// 384 tiles in each bank.
+const TILE_SHEET_ROWS: usize = 24;
+
+const TILE_MAP_SIZE: usize = 256;
+
+const SWATCH_SIZE: usize = 8;
+
+// Between the parts of the overview.
+const GAP: usize = 8;
+
+const BACKGROUND: [u8; 4] = [0x40, 0x40, 0x40, 0xFF];
+
+const VIEWPORT_COLOR: [u8; 4] = [0xFF, 0x00, 0x00, 0xFF];
+
+const WINDOW_COLOR: [u8; 4] = [0x00, 0x80, 0xFF, 0xFF];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ppu::{DMG_COLORS, LCDC_ENABLE, LCDC_TILE_DATA, OAM_SIZE};
+
+    // Tile 1 is solid color 3.
+    fn ppu() -> Ppu {
+        let mut ppu = Ppu::new();
+        ppu.vram[16..32].fill(0xFF);
+        ppu.bgp = 0xE4;
+        ppu.lcdc = LCDC_ENABLE | LCDC_TILE_DATA;
+        ppu
+    }
+
+    #[test]
+    fn test_tile_sheet() {
+        let sheet = ppu().tile_sheet();
+
+        assert_eq!((sheet.width, sheet.height), (128, 192));
+        assert_eq!(sheet.pixel(7, 0), DMG_COLORS[0]);
+        assert_eq!(sheet.pixel(8, 7), DMG_COLORS[3]);
+        assert_eq!(Ppu::new_cgb().tile_sheet().width, 256);
+    }
+
+    #[test]
+    fn test_tile_map_outlines() {
+        // Arrange
+        let mut ppu = ppu();
+        ppu.vram[0x1800 + 32 + 1] = 1;
+        ppu.scx = 200;
+        ppu.scy = 8;
+
+        // Act
+        let map = ppu.tile_map(0);
+
+        // Assert
+        assert_eq!(map.pixel(9, 9), DMG_COLORS[3]);
+        // The screen wraps around the right edge.
+        assert_eq!(map.pixel(200, 8), VIEWPORT_COLOR);
+        assert_eq!(map.pixel((200 + 159) % 256, 151), VIEWPORT_COLOR);
+        assert_eq!(map.pixel(201, 9), DMG_COLORS[0]);
+        assert_eq!(ppu.tile_map(1).pixel(200, 8), DMG_COLORS[0]);
+    }
+
+    #[test]
+    fn test_window_outline() {
+        let mut ppu = ppu();
+        ppu.lcdc |= LCDC_WINDOW_ENABLE | LCDC_WINDOW_MAP;
+        ppu.wx = 7 + 100;
+        ppu.wy = 100;
+
+        let map = ppu.tile_map(1);
+
+        assert_eq!(map.pixel(59, 43), WINDOW_COLOR);
+        assert_eq!(map.pixel(60, 44), DMG_COLORS[0]);
+    }
+
+    #[test]
+    fn test_objects() {
+        let mut ppu = ppu();
+        ppu.oam[4..8].copy_from_slice(&[16, 10, 0x42, OBJ_X_FLIP | OBJ_PALETTE]);
+
+        let objects = ppu.objects();
+
+        assert_eq!(objects.len(), OAM_SIZE / 4);
+        assert_eq!(
+            objects[1].to_string(),
+            " 1: x    2 y    0 tile 42 attributes 30 x-flip obp1"
+        );
+    }
+
+    #[test]
+    fn test_palette_swatches() {
+        let mut ppu = ppu();
+        ppu.obp1 = 0x1B;
+
+        let swatches = ppu.palette_swatches();
+
+        assert_eq!((swatches.width, swatches.height), (32, 24));
+        assert_eq!(swatches.pixel(0, 0), DMG_COLORS[0]);
+        assert_eq!(swatches.pixel(0, 16), DMG_COLORS[3]);
+        assert_eq!(Ppu::new_cgb().palette_swatches().height, 128);
+    }
+
+    #[test]
+    fn test_vram_overview() {
+        let overview = ppu().vram_overview();
+
+        assert_eq!(
+            (overview.width, overview.height),
+            (128 + 256 + 256 + 32 + 3 * GAP, 256)
+        );
+    }
+}
@@ -0,0 +1,173 @@
+use std::collections::HashMap;
+use std::fmt::Write;
+
+use crate::call_stack::CallStack;
+use crate::cpu::Registers;
+use crate::emulator::Emulator;
+
+// Where cycles go, by routine. A routine is whatever a CALL, RST or interrupt jumped to, so the
+// cycles of code that jumps between routines without calling count towards the caller. Code
+// outside any call seen since profiling started, like the main loop, is the top level.
+#[derive(Default)]
+pub struct Profiler {
+    calls: CallStack,
+    routines: HashMap<Routine, Stats>,
+    cycles: u64,
+}
+
+// A routine's entry point. Banked code at the same address in different banks is different code.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum Routine {
+    TopLevel,
+    At { bank: usize, address: u16 },
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Stats {
+    pub calls: u64,
+    // In the routine itself.
+    pub own_cycles: u64,
+    // Including the routines it called.
+    pub total_cycles: u64,
+}
+
+impl Profiler {
+    pub fn new() -> Profiler {
+        Profiler::default()
+    }
+
+    // All the T-cycles profiled.
+    pub fn cycles(&self) -> u64 {
+        self.cycles
+    }
+
+    pub fn stats(&self, routine: Routine) -> Stats {
+        self.routines.get(&routine).copied().unwrap_or_default()
+    }
+
+    // Counts the step that just ran, which started at `before` with `opcode` at PC. The cycles
+    // go to the routine the step started in, so callers pay for their CALLs and routines for their
+    // RETs.
+    pub fn record(&mut self, emulator: &mut Emulator, before: &Registers, opcode: u8, cycles: u32) {
+        let cycles = cycles as u64;
+        self.cycles += cycles;
+        let innermost = match self.calls.frames().last() {
+            Some(frame) => routine(frame.bank, frame.target),
+            None => Routine::TopLevel,
+        };
+        self.routines.entry(innermost).or_default().own_cycles += cycles;
+        // A recursive routine is on the stack more than once but only spends the cycles once.
+        let mut seen = Vec::with_capacity(self.calls.depth() + 1);
+        let outer = self
+            .calls
+            .frames()
+            .iter()
+            .map(|frame| routine(frame.bank, frame.target));
+        for routine in std::iter::once(Routine::TopLevel).chain(outer) {
+            if !seen.contains(&routine) {
+                seen.push(routine);
+                self.routines.entry(routine).or_default().total_cycles += cycles;
+            }
+        }
+
+        if let Some(frame) = self.calls.update(emulator, before, opcode) {
+            self.routines
+                .entry(routine(frame.bank, frame.target))
+                .or_default()
+                .calls += 1;
+        }
+    }
+
+    // The routines that took the most cycles of their own first, with `name` giving them labels
+    // where it has one.
+    pub fn report(&self, name: impl Fn(Routine) -> Option<String>) -> String {
+        let mut routines: Vec<(Routine, Stats)> = self
+            .routines
+            .iter()
+            .map(|(&r, &stats)| (r, stats))
+            .collect();
+        routines.sort_by(|(a, a_stats), (b, b_stats)| {
+            b_stats.own_cycles.cmp(&a_stats.own_cycles).then(a.cmp(b))
+        });
+        let percent = |cycles: u64| cycles as f64 * 100.0 / self.cycles.max(1) as f64;
+
+        let mut report = format!(
+            "{:<32} {:>8} {:>14} {:>7} {:>14} {:>7}\n",
+            "Routine", "Calls", "Own cycles", "%", "Total cycles", "%"
+        );
+        for (routine, stats) in routines {
+            let label = match (routine, name(routine)) {
+                (Routine::TopLevel, _) => "(top level)".to_string(),
+                (Routine::At { bank, address }, Some(name)) => {
+                    format!("{:02X}:{:04X} {}", bank, address, name)
+                }
+                (Routine::At { bank, address }, None) => format!("{:02X}:{:04X}", bank, address),
+            };
+            let _ = writeln!(
+                report,
+                "{:<32} {:>8} {:>14} {:>6.2}% {:>14} {:>6.2}%",
+                label,
+                stats.calls,
+                stats.own_cycles,
+                percent(stats.own_cycles),
+                stats.total_cycles,
+                percent(stats.total_cycles)
+            );
+        }
+        report
+    }
+}
+
+fn routine(bank: usize, address: u16) -> Routine {
+    Routine::At { bank, address }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rom::Rom;
+
+    #[test]
+    fn test_record() {
+        // Arrange
+        let mut content = vec![0; 0x8000];
+        // CALL 0200; JR -5
+        content[0x0100..0x0105].copy_from_slice(&[0xCD, 0x00, 0x02, 0x18, 0xFB]);
+        // NOP; CALL 0300; RET
+        content[0x0200..0x0205].copy_from_slice(&[0x00, 0xCD, 0x00, 0x03, 0xC9]);
+        // RET
+        content[0x0300] = 0xC9;
+        let mut emulator = Emulator::new(Rom::from_content(content)).unwrap();
+        let mut profiler = Profiler::new();
+
+        // Act
+        // Twice around the loop.
+        for _ in 0..12 {
+            let before = emulator.cpu().registers;
+            let opcode = emulator.peek(before.pc);
+            let cycles = emulator.step();
+            profiler.record(&mut emulator, &before, opcode, cycles);
+        }
+
+        // Assert
+        let outer = profiler.stats(routine(0, 0x0200));
+        let inner = profiler.stats(routine(0, 0x0300));
+        let top = profiler.stats(Routine::TopLevel);
+        // CALL takes 24 cycles, NOP 4, RET 16 and JR 12.
+        assert_eq!((outer.calls, outer.own_cycles), (2, 2 * (4 + 24 + 16)));
+        assert_eq!((inner.calls, inner.own_cycles), (2, 2 * 16));
+        assert_eq!(outer.total_cycles, outer.own_cycles + inner.own_cycles);
+        assert_eq!(top.own_cycles, 2 * (24 + 12));
+        assert_eq!(top.total_cycles, profiler.cycles());
+        let report = profiler.report(|routine| match routine {
+            Routine::At {
+                address: 0x0300, ..
+            } => Some("Inner".to_string()),
+            _ => None,
+        });
+        let lines: Vec<&str> = report.lines().collect();
+        assert!(lines[1].starts_with("00:0200 "));
+        assert!(lines[2].starts_with("(top level)"));
+        assert!(lines[3].starts_with("00:0300 Inner"));
+    }
+}
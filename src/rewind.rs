@@ -0,0 +1,273 @@
+use std::collections::VecDeque;
+
+use crate::emulator::Emulator;
+
+// Save states taken every few frames so play can run backwards. Only the newest state is kept
+// whole. Each older one is stored as the difference from the state after it, which is mostly
+// zeros between nearby frames and compresses to a few hundred bytes.
+pub struct Rewind {
+    interval: u32,
+    capacity: usize,
+    newest: Option<Vec<u8>>,
+    // Oldest first. Applying the last one to `newest` gives the state before it.
+    deltas: VecDeque<Vec<u8>>,
+    frames: u32,
+}
+
+impl Rewind {
+    // Keeps about `seconds` of history with a state every `interval` frames.
+    pub fn new(seconds: u32, interval: u32) -> Rewind {
+        let interval = interval.max(1);
+        Rewind {
+            interval,
+            capacity: (seconds * FRAMES_PER_SECOND / interval) as usize,
+            newest: None,
+            deltas: VecDeque::new(),
+            frames: 0,
+        }
+    }
+
+    // The number of states that can be rewound to.
+    pub fn len(&self) -> usize {
+        self.deltas.len() + self.newest.is_some() as usize
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.newest.is_none()
+    }
+
+    // The memory the history takes up.
+    pub fn size(&self) -> usize {
+        self.newest.as_ref().map_or(0, Vec::len) + self.deltas.iter().map(Vec::len).sum::<usize>()
+    }
+
+    // Call once per frame. Takes a state every `interval` frames.
+    pub fn capture(&mut self, emulator: &Emulator) {
+        if self.capacity == 0 {
+            return;
+        }
+        self.frames += 1;
+        if self.frames < self.interval {
+            return;
+        }
+        self.frames = 0;
+        self.push(emulator.save_state());
+    }
+
+    // Loads the newest state and forgets it, so holding rewind keeps going further back. Returns
+    // false once there's no history left.
+    pub fn rewind(&mut self, emulator: &mut Emulator) -> bool {
+        let Some(state) = self.pop() else {
+            return false;
+        };
+        self.frames = 0;
+        emulator
+            .load_state(&state)
+            .expect("rewind states come from the same emulator");
+        true
+    }
+
+    pub fn clear(&mut self) {
+        self.newest = None;
+        self.deltas.clear();
+        self.frames = 0;
+    }
+
+    fn push(&mut self, state: Vec<u8>) {
+        if let Some(previous) = self.newest.replace(state) {
+            let delta = encode_delta(self.newest.as_ref().unwrap(), &previous);
+            self.deltas.push_back(delta);
+        }
+        while self.len() > self.capacity {
+            self.deltas.pop_front();
+        }
+    }
+
+    fn pop(&mut self) -> Option<Vec<u8>> {
+        let newest = self.newest.take()?;
+        self.newest = self
+            .deltas
+            .pop_back()
+            .map(|delta| apply_delta(&newest, &delta));
+        Some(newest)
+    }
+}
+
+// Describes `old` as `new` XORed with a run-length encoded mask. The mask is a little-endian u32
+// length followed by pairs of a zero run and a run of literal bytes, both lengths as LEB128.
+fn encode_delta(new: &[u8], old: &[u8]) -> Vec<u8> {
+    let mut delta = Vec::new();
+    delta.extend_from_slice(&(old.len() as u32).to_le_bytes());
+    let mask: Vec<u8> = (0..old.len())
+        .map(|index| old[index] ^ new.get(index).copied().unwrap_or(0))
+        .collect();
+
+    let mut index = 0;
+    while index < mask.len() {
+        let zeros = mask[index..].iter().take_while(|&&byte| byte == 0).count();
+        index += zeros;
+        // Short zero runs cost more to end than to keep as literals.
+        let literals = mask[index..]
+            .windows(MIN_ZERO_RUN)
+            .position(|window| window.iter().all(|&byte| byte == 0))
+            .unwrap_or(mask.len() - index);
+        write_length(&mut delta, zeros);
+        write_length(&mut delta, literals);
+        delta.extend_from_slice(&mask[index..index + literals]);
+        index += literals;
+    }
+    delta
+}
+
+fn apply_delta(new: &[u8], delta: &[u8]) -> Vec<u8> {
+    let length = u32::from_le_bytes(delta[..4].try_into().unwrap()) as usize;
+    let mut old: Vec<u8> = (0..length)
+        .map(|index| new.get(index).copied().unwrap_or(0))
+        .collect();
+
+    let mut bytes = delta[4..].iter().copied();
+    let mut index = 0;
+    while let Some(zeros) = read_length(&mut bytes) {
+        index += zeros;
+        let literals = read_length(&mut bytes).unwrap_or(0);
+        for byte in bytes.by_ref().take(literals) {
+            old[index] ^= byte;
+            index += 1;
+        }
+    }
+    old
+}
+
+fn write_length(out: &mut Vec<u8>, mut length: usize) {
+    while length >= 0x80 {
+        out.push(length as u8 | 0x80);
+        length >>= 7;
+    }
+    out.push(length as u8);
+}
+
+fn read_length(bytes: &mut impl Iterator<Item = u8>) -> Option<usize> {
+    let mut length = 0;
+    let mut shift = 0;
+    loop {
+        let byte = bytes.next()?;
+        length |= ((byte & 0x7F) as usize) << shift;
+        if byte & 0x80 == 0 {
+            return Some(length);
+        }
+        shift += 7;
+    }
+}
+
+const FRAMES_PER_SECOND: u32 = 60;
+
+const MIN_ZERO_RUN: usize = 4;
+
+#[cfg(test)]
+mod tests {
+    use rstest::rstest;
+
+    use super::*;
+    use crate::rom::Rom;
+
+    #[rstest]
+    #[case(&[1, 2, 3, 4, 5, 6, 7, 8, 9, 10], &[1, 2, 3, 4, 5, 6, 7, 8, 9, 10])]
+    #[case(&[1, 2, 3, 4, 5, 6, 7, 8, 9, 10], &[1, 9, 3, 4, 5, 6, 7, 8, 9, 0])]
+    #[case(&[1, 2, 3], &[1, 2, 3, 4, 5])]
+    #[case(&[1, 2, 3, 4, 5], &[7, 2])]
+    #[case(&[], &[])]
+    fn test_delta_round_trip(#[case] new: &[u8], #[case] old: &[u8]) {
+        let delta = encode_delta(new, old);
+
+        assert_eq!(apply_delta(new, &delta), old);
+    }
+
+    #[test]
+    fn test_delta_of_similar_states_is_small() {
+        let old = vec![0x55; 0x10000];
+        let mut new = old.clone();
+        new[0x1234] = 0;
+        new[0x8000..0x8010].fill(0);
+
+        let delta = encode_delta(&new, &old);
+
+        assert!(delta.len() < 40);
+        assert_eq!(apply_delta(&new, &delta), old);
+    }
+
+    #[test]
+    fn test_length_round_trip() {
+        let mut out = Vec::new();
+        for length in [0, 0x7F, 0x80, 0x3FFF, 0x12345] {
+            write_length(&mut out, length);
+        }
+
+        let mut bytes = out.into_iter();
+
+        for length in [0, 0x7F, 0x80, 0x3FFF, 0x12345] {
+            assert_eq!(read_length(&mut bytes), Some(length));
+        }
+        assert_eq!(read_length(&mut bytes), None);
+    }
+
+    fn emulator() -> Emulator {
+        // INC A; LD (0xC000), A; JR -6
+        let mut content = vec![0; 0x8000];
+        content[0x100..0x106].copy_from_slice(&[0x3C, 0xEA, 0x00, 0xC0, 0x18, 0xFA]);
+        Emulator::new(Rom::from_content(content)).unwrap()
+    }
+
+    #[test]
+    fn test_rewind() {
+        // Arrange
+        let mut emulator = emulator();
+        let mut rewind = Rewind::new(10, 2);
+        let mut states = Vec::new();
+        for frame in 1..=6 {
+            emulator.run_frame();
+            rewind.capture(&emulator);
+            if frame % 2 == 0 {
+                states.push(emulator.save_state());
+            }
+        }
+        emulator.run_frame();
+
+        // Act
+        let mut rewound = Vec::new();
+        while rewind.rewind(&mut emulator) {
+            rewound.push(emulator.save_state());
+        }
+
+        // Assert
+        states.reverse();
+        assert_eq!(rewound, states);
+    }
+
+    #[test]
+    fn test_history_is_bounded() {
+        // A second of history with a state every 10 frames.
+        let mut rewind = Rewind::new(1, 10);
+
+        for state in 0..20 {
+            rewind.push(vec![state; 64]);
+        }
+
+        assert_eq!(rewind.len(), 6);
+        for state in (14..20).rev() {
+            assert_eq!(rewind.pop(), Some(vec![state; 64]));
+        }
+        assert_eq!(rewind.pop(), None);
+    }
+
+    #[test]
+    fn test_disabled() {
+        let mut emulator = emulator();
+        let mut rewind = Rewind::new(0, 1);
+
+        emulator.run_frame();
+        rewind.capture(&emulator);
+
+        assert!(rewind.is_empty());
+        assert!(!rewind.rewind(&mut emulator));
+    }
+}
@@ -1,13 +1,21 @@
 use std::cmp::Ordering;
-use std::io::{Error, ErrorKind, Result};
+use std::fmt;
+use std::io::{self, Read};
 use std::num::Wrapping;
+use std::slice::SliceIndex;
+
+use serde::Serialize;
+
+use crate::{archive, hash};
 
 pub struct Rom {
     content: Vec<u8>,
+    warnings: Vec<RomWarning>,
 }
 
-#[derive(Debug, PartialEq)]
-enum MemoryBankType {
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum MemoryBankType {
     ROM,
     MBC1,
     MBC2,
@@ -16,30 +24,362 @@ enum MemoryBankType {
     MBC5,
     MBC6,
     MBC7,
+    #[serde(rename = "pocket_camera")]
+    PocketCamera,
+    TAMA5,
+    HuC3,
+    HuC1,
+}
+
+// Everything the cartridge type at 0x147 says about the hardware on the cartridge.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+pub struct CartridgeFeatures {
+    pub memory_bank_type: MemoryBankType,
+    // External RAM, which MBC2 doesn't count as its RAM is part of the controller.
+    pub ram: bool,
+    pub battery: bool,
+    pub rtc: bool,
+    pub rumble: bool,
+    pub accelerometer: bool,
+}
+
+impl CartridgeFeatures {
+    fn new(memory_bank_type: MemoryBankType) -> CartridgeFeatures {
+        CartridgeFeatures {
+            memory_bank_type,
+            ram: false,
+            battery: false,
+            rtc: false,
+            rumble: false,
+            accelerometer: false,
+        }
+    }
+
+    fn ram(self) -> CartridgeFeatures {
+        CartridgeFeatures { ram: true, ..self }
+    }
+
+    fn battery(self) -> CartridgeFeatures {
+        CartridgeFeatures {
+            battery: true,
+            ..self
+        }
+    }
+
+    fn rtc(self) -> CartridgeFeatures {
+        CartridgeFeatures { rtc: true, ..self }
+    }
+
+    fn rumble(self) -> CartridgeFeatures {
+        CartridgeFeatures {
+            rumble: true,
+            ..self
+        }
+    }
+
+    fn accelerometer(self) -> CartridgeFeatures {
+        CartridgeFeatures {
+            accelerometer: true,
+            ..self
+        }
+    }
+}
+
+// The CGB flag at 0x143.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CgbSupport {
+    None,
+    // Works on DMG too but uses CGB features when available.
+    Compatible,
+    Only,
+}
+
+#[derive(Debug)]
+pub enum RomError {
+    Io(io::Error),
+    InvalidLogo,
+    InvalidCartridgeType(u8),
+    InvalidRomSize(u8),
+    InvalidRamSize(u8),
+    // MBC2 has its own RAM, so the header must not declare any.
+    Mbc2WithRam(u32),
+    BadHeaderChecksum { expected: u8, actual: u8 },
+    BadGlobalChecksum { expected: u16, actual: u16 },
+    TruncatedHeader,
+    SizeMismatch { expected: u32, actual: usize },
+}
+
+impl fmt::Display for RomError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            RomError::Io(error) => write!(f, "{}", error),
+            RomError::InvalidLogo => write!(f, "Nintendo logo not found in header."),
+            RomError::InvalidCartridgeType(byte) => {
+                write!(f, "{} is an invalid value for the cartridge type.", byte)
+            }
+            RomError::InvalidRomSize(byte) => {
+                write!(f, "{} is an invalid value for the ROM size.", byte)
+            }
+            RomError::InvalidRamSize(byte) => {
+                write!(f, "{} is an invalid value for the RAM size.", byte)
+            }
+            RomError::Mbc2WithRam(ram_size) => write!(
+                f,
+                "When the memory bank type is MBC2, the ram must be 0 but was {}",
+                ram_size
+            ),
+            RomError::BadHeaderChecksum { expected, actual } => write!(
+                f,
+                "The expected checksum, {}, did not match the actual checksum, {}.",
+                expected, actual
+            ),
+            RomError::BadGlobalChecksum { expected, actual } => write!(
+                f,
+                "The expected global checksum, {:#06X}, did not match the actual checksum, {:#06X}.",
+                expected, actual
+            ),
+            RomError::TruncatedHeader => write!(f, "The ROM is too short to contain a header."),
+            RomError::SizeMismatch { expected, actual } => {
+                write_size_mismatch(f, *expected, *actual)
+            }
+        }
+    }
+}
+
+impl std::error::Error for RomError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            RomError::Io(error) => Some(error),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for RomError {
+    fn from(error: io::Error) -> Self {
+        RomError::Io(error)
+    }
+}
+
+// Lets callers working with io::Result use `?` on ROM errors.
+impl From<RomError> for io::Error {
+    fn from(error: RomError) -> Self {
+        match error {
+            RomError::Io(error) => error,
+            error => io::Error::other(error),
+        }
+    }
+}
+
+// Too many bytes usually means a padded or overdumped file, too few a bad dump.
+fn write_size_mismatch(f: &mut fmt::Formatter, expected: u32, actual: usize) -> fmt::Result {
+    let expected = expected as usize;
+    if actual > expected {
+        write!(
+            f,
+            "The ROM is overdumped by {} bytes, the header declares {} but the file has {}.",
+            actual - expected,
+            expected,
+            actual
+        )
+    } else {
+        write!(
+            f,
+            "The ROM is underdumped by {} bytes, the header declares {} but the file has {}.",
+            expected - actual,
+            expected,
+            actual
+        )
+    }
+}
+
+pub type Result<T> = std::result::Result<T, RomError>;
+
+// How strictly `Rom::with_policy` treats a header that real hardware would still boot.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ValidationPolicy {
+    // Reject ROMs without the Nintendo logo, like the DMG boot ROM does.
+    Strict,
+    // Only reject ROMs the emulator can't run and report everything else as warnings.
+    Lenient,
+}
+
+// Header problems that don't stop the emulator from running the ROM.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum RomWarning {
+    LogoMismatch,
+    HeaderChecksumMismatch { expected: u8, actual: u8 },
+    GlobalChecksumMismatch { expected: u16, actual: u16 },
+    SizeMismatch { expected: u32, actual: usize },
+}
+
+impl fmt::Display for RomWarning {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            RomWarning::LogoMismatch => write!(f, "Nintendo logo not found in header."),
+            RomWarning::HeaderChecksumMismatch { expected, actual } => write!(
+                f,
+                "The header checksum is {:#04X} but the header sums to {:#04X}.",
+                expected, actual
+            ),
+            RomWarning::GlobalChecksumMismatch { expected, actual } => write!(
+                f,
+                "The global checksum is {:#06X} but the ROM sums to {:#06X}.",
+                expected, actual
+            ),
+            RomWarning::SizeMismatch { expected, actual } => {
+                write_size_mismatch(f, *expected, *actual)
+            }
+        }
+    }
+}
+
+// The old licensee code at 0x14B, or the two-character new one at 0x144-0x145 when the old code is
+// 0x33.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Licensee {
+    Old(u8),
+    New(String),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Destination {
+    Japan,
+    Overseas,
+    Unknown(u8),
+}
+
+// Serializes with the field names below, which scripts rely on, so renaming one is a breaking
+// change.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct HeaderInfo {
+    pub title: String,
+    pub manufacturer_code: Option<String>,
+    pub licensee: Licensee,
+    pub destination: Destination,
+    pub version: u8,
+    pub cgb_support: CgbSupport,
+    pub sgb_support: bool,
 }
 
 impl Rom {
     pub fn new(path: &str) -> Result<Rom> {
-        let rom = Rom {
-            content: std::fs::read(path)?,
-        };
+        Rom::with_policy(path, ValidationPolicy::Strict)
+    }
 
-        rom.verify_nintendo_logo()?;
-        rom.verify_memory_bank_matches_ram()?;
+    // Accepts homebrew with a missing logo or wrong checksums, see `warnings`.
+    pub fn new_lenient(path: &str) -> Result<Rom> {
+        Rom::with_policy(path, ValidationPolicy::Lenient)
+    }
+
+    // `path` may be a .zip or .gz archive, see `archive::read`.
+    pub fn with_policy(path: &str, policy: ValidationPolicy) -> Result<Rom> {
+        Rom::from_bytes_with_policy(archive::read(path)?, policy)
+    }
 
+    // For hosts without a file system, like the browser or a fuzzer.
+    pub fn from_bytes(content: Vec<u8>) -> Result<Rom> {
+        Rom::from_bytes_with_policy(content, ValidationPolicy::Strict)
+    }
+
+    pub fn from_bytes_with_policy(content: Vec<u8>, policy: ValidationPolicy) -> Result<Rom> {
+        let mut rom = Rom {
+            content,
+            warnings: Vec::new(),
+        };
+        rom.validate(policy)?;
         Ok(rom)
     }
 
+    pub fn from_reader(mut reader: impl Read) -> Result<Rom> {
+        let mut content = Vec::new();
+        reader.read_to_end(&mut content)?;
+        Rom::from_bytes(content)
+    }
+
+    #[cfg(test)]
+    pub(crate) fn from_content(content: Vec<u8>) -> Rom {
+        Rom {
+            content,
+            warnings: Vec::new(),
+        }
+    }
+
+    fn validate(&mut self, policy: ValidationPolicy) -> Result<()> {
+        if self.content.len() < HEADER_END {
+            return Err(RomError::TruncatedHeader);
+        }
+
+        match self.verify_nintendo_logo() {
+            Err(error) if policy == ValidationPolicy::Strict => return Err(error),
+            Err(_) => self.warnings.push(RomWarning::LogoMismatch),
+            Ok(()) => {}
+        }
+        self.verify_memory_bank_matches_ram()?;
+
+        if let Err(RomError::BadHeaderChecksum { expected, actual }) = self.verify_header_checksum()
+        {
+            self.warnings
+                .push(RomWarning::HeaderChecksumMismatch { expected, actual });
+        }
+        if let Err(RomError::BadGlobalChecksum { expected, actual }) = self.verify_global_checksum()
+        {
+            self.warnings
+                .push(RomWarning::GlobalChecksumMismatch { expected, actual });
+        }
+        if let Err(RomError::SizeMismatch { expected, actual }) = self.verify_rom_size() {
+            self.warnings
+                .push(RomWarning::SizeMismatch { expected, actual });
+        }
+
+        Ok(())
+    }
+
+    // Problems found while loading that the emulator can live with.
+    pub fn warnings(&self) -> &[RomWarning] {
+        &self.warnings
+    }
+
+    pub fn content(&self) -> &[u8] {
+        &self.content
+    }
+
+    // The whole ROM's CRC-32 and SHA-1, which is how ROM databases recognize dumps.
+    pub fn crc32(&self) -> u32 {
+        hash::crc32(&self.content)
+    }
+
+    pub fn sha1(&self) -> [u8; 20] {
+        hash::sha1(&self.content)
+    }
+
+    pub fn into_content(self) -> Vec<u8> {
+        self.content
+    }
+
+    // Construction rejects ROMs without a full header, but `from_content` in tests doesn't.
+    fn header_byte(&self, index: usize) -> Result<u8> {
+        self.content
+            .get(index)
+            .copied()
+            .ok_or(RomError::TruncatedHeader)
+    }
+
+    fn header_bytes<R: SliceIndex<[u8], Output = [u8]>>(&self, range: R) -> Result<&[u8]> {
+        self.content.get(range).ok_or(RomError::TruncatedHeader)
+    }
+
     fn verify_nintendo_logo(&self) -> Result<()> {
-        if self.content[NINTENDO_LOGO_RANGE]
+        if self
+            .header_bytes(NINTENDO_LOGO_RANGE)?
             .iter()
             .cmp(NINTENDO_LOGO.iter())
             != Ordering::Equal
         {
-            return Err(Error::new(
-                ErrorKind::Other,
-                "Nintendo logo not found in header.",
-            ));
+            return Err(RomError::InvalidLogo);
         }
 
         Ok(())
@@ -47,95 +387,262 @@ impl Rom {
 
     fn verify_memory_bank_matches_ram(&self) -> Result<()> {
         let ram_size = self.get_ram_size()?;
-        if self.get_memory_bank_type()? == MemoryBankType::MBC2 && ram_size != 0 {
-            Err(Error::new(
-                ErrorKind::Other,
-                format!(
-                    "When the memory bank type is MBC2, the ram must be 0 but was {}",
-                    ram_size
-                ),
-            ))
+        let features = self.cartridge_features()?;
+        if features.memory_bank_type == MemoryBankType::MBC2 && ram_size != 0 {
+            Err(RomError::Mbc2WithRam(ram_size))
         } else {
             Ok(())
         }
     }
 
-    fn get_memory_bank_type(&self) -> Result<MemoryBankType> {
-        let memory_bank_type = match self.content[CARTRIDGE_TYPE_INDEX] {
-            0x00 | 0x08 | 0x09 => MemoryBankType::ROM,
-            0x01..=0x03 => MemoryBankType::MBC1,
-            0x05 | 0x06 => MemoryBankType::MBC2,
-            0x0B..=0x0D => MemoryBankType::MMM01,
-            0x0F..=0x13 => MemoryBankType::MBC3,
-            0x19..=0x1E => MemoryBankType::MBC5,
-            0x20 => MemoryBankType::MBC6,
-            0x22 => MemoryBankType::MBC7,
-            _ => {
-                return Err(Error::new(
-                    ErrorKind::Other,
-                    format!(
-                        "{} is an invalid value for the cartridge type.",
-                        self.content[CARTRIDGE_TYPE_INDEX]
-                    ),
-                ))
-            }
+    // MBC1M multicarts are 1 MB collections of 256 KB games, each with its own header. The cartridge
+    // header doesn't say so, but the extra logos at the start of every game give it away.
+    pub fn is_mbc1_multicart(&self) -> bool {
+        if self.content.len() != MBC1M_SIZE
+            || !matches!(self.get_memory_bank_type(), Ok(MemoryBankType::MBC1))
+        {
+            return false;
+        }
+
+        let games_with_logo = (MBC1M_GAME_SIZE..MBC1M_SIZE)
+            .step_by(MBC1M_GAME_SIZE)
+            .filter(|game| {
+                let start = game + NINTENDO_LOGO_RANGE.start;
+                self.content[start..start + NINTENDO_LOGO.len()] == NINTENDO_LOGO
+            })
+            .count();
+        games_with_logo > 0
+    }
+
+    pub fn header_info(&self) -> Result<HeaderInfo> {
+        Ok(HeaderInfo {
+            title: self.title()?,
+            manufacturer_code: self.manufacturer_code()?,
+            licensee: self.licensee()?,
+            destination: self.destination()?,
+            version: self.version()?,
+            cgb_support: self.get_cgb_support()?,
+            sgb_support: self.sgb_support()?,
+        })
+    }
+
+    // Up to 16 characters padded with zeros. CGB cartridges use the last byte for the CGB flag.
+    pub fn title(&self) -> Result<String> {
+        let end = if self.get_cgb_support()? == CgbSupport::None {
+            TITLE_RANGE.end
+        } else {
+            CGB_FLAG_INDEX
         };
+        let title = self.header_bytes(TITLE_RANGE.start..end)?;
+        let length = title
+            .iter()
+            .position(|&byte| byte == 0)
+            .unwrap_or(title.len());
+        Ok(String::from_utf8_lossy(&title[..length])
+            .trim_end()
+            .to_string())
+    }
+
+    // Newer cartridges shorten the title to 11 characters and put a 4 character code after it.
+    pub fn manufacturer_code(&self) -> Result<Option<String>> {
+        if self.get_cgb_support()? == CgbSupport::None {
+            return Ok(None);
+        }
 
-        Ok(memory_bank_type)
+        let code = self.header_bytes(MANUFACTURER_CODE_RANGE)?;
+        if !code
+            .iter()
+            .all(|byte| byte.is_ascii_uppercase() || byte.is_ascii_digit())
+        {
+            return Ok(None);
+        }
+
+        Ok(Some(String::from_utf8_lossy(code).to_string()))
+    }
+
+    pub fn licensee(&self) -> Result<Licensee> {
+        Ok(match self.header_byte(OLD_LICENSEE_INDEX)? {
+            USE_NEW_LICENSEE => Licensee::New(
+                String::from_utf8_lossy(self.header_bytes(NEW_LICENSEE_RANGE)?).to_string(),
+            ),
+            code => Licensee::Old(code),
+        })
+    }
+
+    pub fn destination(&self) -> Result<Destination> {
+        Ok(match self.header_byte(DESTINATION_INDEX)? {
+            0x00 => Destination::Japan,
+            0x01 => Destination::Overseas,
+            code => Destination::Unknown(code),
+        })
+    }
+
+    pub fn version(&self) -> Result<u8> {
+        self.header_byte(VERSION_INDEX)
+    }
+
+    pub fn sgb_support(&self) -> Result<bool> {
+        Ok(self.header_byte(SGB_FLAG_INDEX)? == 0x03)
+    }
+
+    pub fn get_cartridge_type(&self) -> Result<u8> {
+        self.header_byte(CARTRIDGE_TYPE_INDEX)
+    }
+
+    pub fn get_cgb_support(&self) -> Result<CgbSupport> {
+        Ok(match self.header_byte(CGB_FLAG_INDEX)? {
+            0xC0 => CgbSupport::Only,
+            flag if flag & 0x80 != 0 => CgbSupport::Compatible,
+            _ => CgbSupport::None,
+        })
+    }
+
+    // Whether external RAM (and the clock, if any) is kept alive by a battery.
+    pub fn has_battery(&self) -> Result<bool> {
+        Ok(self.cartridge_features()?.battery)
+    }
+
+    pub fn get_memory_bank_type(&self) -> Result<MemoryBankType> {
+        Ok(self.cartridge_features()?.memory_bank_type)
+    }
+
+    pub fn cartridge_features(&self) -> Result<CartridgeFeatures> {
+        use MemoryBankType::*;
+
+        let features = match self.get_cartridge_type()? {
+            0x00 => CartridgeFeatures::new(ROM),
+            0x01 => CartridgeFeatures::new(MBC1),
+            0x02 => CartridgeFeatures::new(MBC1).ram(),
+            0x03 => CartridgeFeatures::new(MBC1).ram().battery(),
+            0x05 => CartridgeFeatures::new(MBC2),
+            0x06 => CartridgeFeatures::new(MBC2).battery(),
+            0x08 => CartridgeFeatures::new(ROM).ram(),
+            0x09 => CartridgeFeatures::new(ROM).ram().battery(),
+            0x0B => CartridgeFeatures::new(MMM01),
+            0x0C => CartridgeFeatures::new(MMM01).ram(),
+            0x0D => CartridgeFeatures::new(MMM01).ram().battery(),
+            0x0F => CartridgeFeatures::new(MBC3).rtc().battery(),
+            0x10 => CartridgeFeatures::new(MBC3).rtc().ram().battery(),
+            0x11 => CartridgeFeatures::new(MBC3),
+            0x12 => CartridgeFeatures::new(MBC3).ram(),
+            0x13 => CartridgeFeatures::new(MBC3).ram().battery(),
+            0x19 => CartridgeFeatures::new(MBC5),
+            0x1A => CartridgeFeatures::new(MBC5).ram(),
+            0x1B => CartridgeFeatures::new(MBC5).ram().battery(),
+            0x1C => CartridgeFeatures::new(MBC5).rumble(),
+            0x1D => CartridgeFeatures::new(MBC5).rumble().ram(),
+            0x1E => CartridgeFeatures::new(MBC5).rumble().ram().battery(),
+            0x20 => CartridgeFeatures::new(MBC6),
+            0x22 => CartridgeFeatures::new(MBC7)
+                .accelerometer()
+                .rumble()
+                .ram()
+                .battery(),
+            0xFC => CartridgeFeatures::new(PocketCamera).ram().battery(),
+            0xFD => CartridgeFeatures::new(TAMA5).rtc().battery(),
+            0xFE => CartridgeFeatures::new(HuC3).rtc().ram().battery(),
+            0xFF => CartridgeFeatures::new(HuC1).ram().battery(),
+            byte => return Err(RomError::InvalidCartridgeType(byte)),
+        };
+
+        Ok(features)
     }
 
-    fn get_rom_size(&self) -> Result<u32> {
-        let byte = self.content[ROM_SIZE_INDEX];
-        if byte > 0x08 {
-            return Err(Error::new(
-                ErrorKind::Other,
-                format!("{} is an invalid value for the ROM size.", byte),
-            ));
+    pub fn get_rom_size(&self) -> Result<u32> {
+        match self.header_byte(ROM_SIZE_INDEX)? {
+            // This ranges from 32 KB to 8 MB.
+            byte @ 0x00..=0x08 => Ok(32768 << byte),
+            // Undocumented codes only found in a few unlicensed cartridges.
+            0x52 => Ok(72 * ROM_BANK_SIZE),
+            0x53 => Ok(80 * ROM_BANK_SIZE),
+            0x54 => Ok(96 * ROM_BANK_SIZE),
+            byte => Err(RomError::InvalidRomSize(byte)),
         }
+    }
 
-        // This ranges from 32 KB to 8 MB.
-        return Ok(32768 << byte);
+    // Whether the file is exactly as long as the header says.
+    pub fn verify_rom_size(&self) -> Result<()> {
+        let expected = self.get_rom_size()?;
+        if expected as usize != self.content.len() {
+            return Err(RomError::SizeMismatch {
+                expected,
+                actual: self.content.len(),
+            });
+        }
+
+        Ok(())
     }
 
-    fn get_ram_size(&self) -> Result<u32> {
-        let ram_size = match self.content[RAM_SIZE_INDEX] {
+    pub fn get_ram_size(&self) -> Result<u32> {
+        let ram_size = match self.header_byte(RAM_SIZE_INDEX)? {
             0x00 | 0x01 => 0, // 0x01 is not officially documented. Only used in homebrew ROMs and the expect no RAM so having it set to 0.
             0x02 => 8 * KB,
             0x03 => 32 * KB,
             0x04 => 128 * KB,
             0x05 => 64 * KB,
-            _ => {
-                return Err(Error::new(
-                    ErrorKind::Other,
-                    format!(
-                        "{} is an invalid value for the RAM size.",
-                        self.content[RAM_SIZE_INDEX]
-                    ),
-                ));
-            }
+            byte => return Err(RomError::InvalidRamSize(byte)),
         };
 
         Ok(ram_size)
     }
 
-    fn verify_header_checksum(&self) -> Result<()> {
-        let checksum = self.content[HEADER_CHECKSUM_RANGE]
+    pub fn verify_header_checksum(&self) -> Result<()> {
+        let expected = self.stored_header_checksum()?;
+        let actual = self.header_checksum()?;
+        if actual != expected {
+            return Err(RomError::BadHeaderChecksum { expected, actual });
+        }
+
+        Ok(())
+    }
+
+    // The header checksum computed over 0x134-0x14C, which the boot ROM compares against 0x14D.
+    pub fn header_checksum(&self) -> Result<u8> {
+        let checksum = self
+            .header_bytes(HEADER_CHECKSUM_RANGE)?
             .iter()
             .cloned()
             .fold(Wrapping(0), |acc, v| acc - Wrapping(v) - Wrapping(1));
+        Ok(checksum.0)
+    }
+
+    pub fn stored_header_checksum(&self) -> Result<u8> {
+        self.header_byte(HEADER_CHECKSUM_INDEX)
+    }
 
-        if checksum.0 != self.content[HEADER_CHECKSUM_INDEX] {
-            return Err(Error::new(
-                ErrorKind::Other,
-                format!(
-                    "The expected checksum, {}, did not match the actual checksum, {}.",
-                    self.content[HEADER_CHECKSUM_INDEX], checksum
-                ),
-            ));
+    // Hardware never checks the global checksum, so loading doesn't either, but it is useful to
+    // tell whether a dump is good.
+    pub fn verify_global_checksum(&self) -> Result<()> {
+        let expected = self.stored_global_checksum()?;
+        let actual = self.global_checksum()?;
+        if actual != expected {
+            return Err(RomError::BadGlobalChecksum { expected, actual });
         }
 
         Ok(())
     }
+
+    // The sum of every byte except the stored global checksum itself.
+    pub fn global_checksum(&self) -> Result<u16> {
+        if self.content.len() <= *GLOBAL_CHECKSUM_RANGE.end() {
+            return Err(RomError::TruncatedHeader);
+        }
+
+        Ok(self
+            .content
+            .iter()
+            .enumerate()
+            .filter(|(index, _)| !GLOBAL_CHECKSUM_RANGE.contains(index))
+            .fold(0u16, |acc, (_, &byte)| acc.wrapping_add(byte as u16)))
+    }
+
+    // Stored big-endian at 0x14E-0x14F.
+    pub fn stored_global_checksum(&self) -> Result<u16> {
+        Ok(u16::from_be_bytes([
+            self.header_byte(*GLOBAL_CHECKSUM_RANGE.start())?,
+            self.header_byte(*GLOBAL_CHECKSUM_RANGE.end())?,
+        ]))
+    }
 }
 
 const NINTENDO_LOGO: [u8; 48] = [
@@ -146,6 +653,20 @@ const NINTENDO_LOGO: [u8; 48] = [
 
 const NINTENDO_LOGO_RANGE: std::ops::Range<usize> = 0x104..0x134;
 
+const MBC1M_SIZE: usize = 64 * ROM_BANK_SIZE as usize;
+
+const MBC1M_GAME_SIZE: usize = 16 * ROM_BANK_SIZE as usize;
+
+const TITLE_RANGE: std::ops::Range<usize> = 0x134..0x144;
+
+const MANUFACTURER_CODE_RANGE: std::ops::Range<usize> = 0x13F..0x143;
+
+const CGB_FLAG_INDEX: usize = 0x143;
+
+const NEW_LICENSEE_RANGE: std::ops::Range<usize> = 0x144..0x146;
+
+const SGB_FLAG_INDEX: usize = 0x146;
+
 const CARTRIDGE_TYPE_INDEX: usize = 0x147;
 
 const ROM_SIZE_INDEX: usize = 0x148;
@@ -154,10 +675,27 @@ const RAM_SIZE_INDEX: usize = 0x149;
 
 const KB: u32 = 1024;
 
+const ROM_BANK_SIZE: u32 = 16 * KB;
+
+// The largest ROM the header can describe, 8 MB.
+pub const MAX_ROM_SIZE: usize = 512 * ROM_BANK_SIZE as usize;
+
+const DESTINATION_INDEX: usize = 0x14A;
+
+const OLD_LICENSEE_INDEX: usize = 0x14B;
+
+const USE_NEW_LICENSEE: u8 = 0x33;
+
+const VERSION_INDEX: usize = 0x14C;
+
 const HEADER_CHECKSUM_INDEX: usize = 0x14D;
 
 const HEADER_CHECKSUM_RANGE: std::ops::RangeInclusive<usize> = 0x134..=0x14C;
 
+const GLOBAL_CHECKSUM_RANGE: std::ops::RangeInclusive<usize> = 0x14E..=0x14F;
+
+const HEADER_END: usize = 0x150;
+
 #[cfg(test)]
 mod tests {
     use rstest::rstest;
@@ -172,7 +710,7 @@ mod tests {
             content[byte] = NINTENDO_LOGO[byte - NINTENDO_LOGO_RANGE.start];
         }
 
-        let rom = Rom { content };
+        let rom = Rom::from_content(content);
 
         // Act + Assert
         assert!(rom.verify_nintendo_logo().is_ok());
@@ -183,10 +721,13 @@ mod tests {
         // Arrange
         let content: Vec<u8> = vec![0; NINTENDO_LOGO_RANGE.end];
 
-        let rom = Rom { content };
+        let rom = Rom::from_content(content);
 
         // Act + Assert
-        assert!(rom.verify_nintendo_logo().is_err());
+        assert!(matches!(
+            rom.verify_nintendo_logo(),
+            Err(RomError::InvalidLogo)
+        ));
     }
 
     #[rstest]
@@ -214,12 +755,16 @@ mod tests {
     #[case(0x1E, MemoryBankType::MBC5)]
     #[case(0x20, MemoryBankType::MBC6)]
     #[case(0x22, MemoryBankType::MBC7)]
+    #[case(0xFC, MemoryBankType::PocketCamera)]
+    #[case(0xFD, MemoryBankType::TAMA5)]
+    #[case(0xFE, MemoryBankType::HuC3)]
+    #[case(0xFF, MemoryBankType::HuC1)]
     fn test_get_memory_bank_type(#[case] byte: u8, #[case] memory_bank_type: MemoryBankType) {
         // Arrange
         let mut content: Vec<u8> = vec![0; CARTRIDGE_TYPE_INDEX + 1];
         content[CARTRIDGE_TYPE_INDEX] = byte;
 
-        let rom = Rom { content };
+        let rom = Rom::from_content(content);
 
         // Act
         let actual_memory_bank_type = rom.get_memory_bank_type().unwrap();
@@ -228,16 +773,51 @@ mod tests {
         assert_eq!(actual_memory_bank_type, memory_bank_type);
     }
 
+    #[rstest]
+    #[case(0x00, false)]
+    #[case(0x02, false)]
+    #[case(0x03, true)]
+    #[case(0x06, true)]
+    #[case(0x0F, true)]
+    #[case(0x11, false)]
+    #[case(0x13, true)]
+    #[case(0x1B, true)]
+    #[case(0x1C, false)]
+    #[case(0x1E, true)]
+    fn test_has_battery(#[case] byte: u8, #[case] expected: bool) {
+        let mut content: Vec<u8> = vec![0; CARTRIDGE_TYPE_INDEX + 1];
+        content[CARTRIDGE_TYPE_INDEX] = byte;
+        let rom = Rom::from_content(content);
+
+        assert_eq!(rom.has_battery().unwrap(), expected);
+    }
+
+    #[rstest]
+    #[case(0x00, CgbSupport::None)]
+    #[case(0x42, CgbSupport::None)]
+    #[case(0x80, CgbSupport::Compatible)]
+    #[case(0xC0, CgbSupport::Only)]
+    fn test_get_cgb_support(#[case] byte: u8, #[case] expected: CgbSupport) {
+        let mut content: Vec<u8> = vec![0; CGB_FLAG_INDEX + 1];
+        content[CGB_FLAG_INDEX] = byte;
+        let rom = Rom::from_content(content);
+
+        assert_eq!(rom.get_cgb_support().unwrap(), expected);
+    }
+
     #[test]
     fn test_get_memory_bank_type_negative() {
         // Arrange
         let mut content: Vec<u8> = vec![0; CARTRIDGE_TYPE_INDEX + 1];
         content[CARTRIDGE_TYPE_INDEX] = 0x23;
 
-        let rom = Rom { content };
+        let rom = Rom::from_content(content);
 
         // Act + Assert
-        assert!(rom.get_memory_bank_type().is_err());
+        assert!(matches!(
+            rom.get_memory_bank_type(),
+            Err(RomError::InvalidCartridgeType(0x23))
+        ));
     }
 
     #[rstest]
@@ -250,11 +830,14 @@ mod tests {
     #[case(0x06, 2097152)]
     #[case(0x07, 4194304)]
     #[case(0x08, 8388608)]
+    #[case(0x52, 1179648)]
+    #[case(0x53, 1310720)]
+    #[case(0x54, 1572864)]
     fn test_get_rom_size(#[case] byte: u8, #[case] expected_size: u32) {
         // Arrange
         let mut content: Vec<u8> = vec![0; ROM_SIZE_INDEX + 1];
         content[ROM_SIZE_INDEX] = byte;
-        let rom = Rom { content };
+        let rom = Rom::from_content(content);
 
         // Act + Assert
         assert_eq!(expected_size, rom.get_rom_size().unwrap());
@@ -264,8 +847,11 @@ mod tests {
     fn test_get_rom_size_negative() {
         let mut content: Vec<u8> = vec![0; ROM_SIZE_INDEX + 1];
         content[ROM_SIZE_INDEX] = 0x09;
-        let rom = Rom { content };
-        assert!(rom.get_rom_size().is_err());
+        let rom = Rom::from_content(content);
+        assert!(matches!(
+            rom.get_rom_size(),
+            Err(RomError::InvalidRomSize(0x09))
+        ));
     }
 
     #[rstest]
@@ -279,7 +865,7 @@ mod tests {
         // Arrange
         let mut content: Vec<u8> = vec![0; RAM_SIZE_INDEX + 1];
         content[RAM_SIZE_INDEX] = byte;
-        let rom = Rom { content };
+        let rom = Rom::from_content(content);
 
         // Act + Assert
         assert_eq!(expected_size, rom.get_ram_size().unwrap());
@@ -289,8 +875,11 @@ mod tests {
     fn test_get_ram_size_negative() {
         let mut content: Vec<u8> = vec![0; RAM_SIZE_INDEX + 1];
         content[RAM_SIZE_INDEX] = 0x06;
-        let rom = Rom { content };
-        assert!(rom.get_ram_size().is_err());
+        let rom = Rom::from_content(content);
+        assert!(matches!(
+            rom.get_ram_size(),
+            Err(RomError::InvalidRamSize(0x06))
+        ));
     }
 
     #[rstest]
@@ -299,7 +888,7 @@ mod tests {
     fn test_verify_memory_bank_matches_ram(#[case] byte: u8) {
         let mut content: Vec<u8> = vec![0; RAM_SIZE_INDEX + 1];
         content[CARTRIDGE_TYPE_INDEX] = byte;
-        let rom = Rom { content };
+        let rom = Rom::from_content(content);
         assert!(rom.verify_memory_bank_matches_ram().is_ok());
     }
 
@@ -310,8 +899,11 @@ mod tests {
         let mut content: Vec<u8> = vec![0; RAM_SIZE_INDEX + 1];
         content[CARTRIDGE_TYPE_INDEX] = byte;
         content[RAM_SIZE_INDEX] = 0x02;
-        let rom = Rom { content };
-        assert!(rom.verify_memory_bank_matches_ram().is_err());
+        let rom = Rom::from_content(content);
+        assert!(matches!(
+            rom.verify_memory_bank_matches_ram(),
+            Err(RomError::Mbc2WithRam(8192))
+        ));
     }
 
     #[test]
@@ -328,14 +920,380 @@ mod tests {
 
         content[0x14D] = 0xD3;
 
-        let rom = Rom { content };
+        let rom = Rom::from_content(content);
         assert!(rom.verify_header_checksum().is_ok());
     }
 
     #[test]
     fn test_verify_header_checksum_negative() {
+        let content: Vec<u8> = vec![0; HEADER_CHECKSUM_INDEX + 1];
+        let rom = Rom::from_content(content);
+        assert!(matches!(
+            rom.verify_header_checksum(),
+            Err(RomError::BadHeaderChecksum {
+                expected: 0x00,
+                actual: 0xE7
+            })
+        ));
+    }
+
+    #[test]
+    fn test_computed_and_stored_checksums() {
+        let mut content: Vec<u8> = vec![0x01; 0x8000];
+        content[0x14D] = 0x12;
+        content[0x14E] = 0x34;
+        content[0x14F] = 0x56;
+        let rom = Rom::from_content(content);
+
+        assert_eq!(rom.stored_header_checksum().unwrap(), 0x12);
+        // 25 bytes of 0x01, each subtracting 2.
+        assert_eq!(rom.header_checksum().unwrap(), 0xCE);
+        assert_eq!(rom.stored_global_checksum().unwrap(), 0x3456);
+        assert_eq!(rom.global_checksum().unwrap(), 0x7FFD + 0x12);
+    }
+
+    fn rom_with_title(title: &[u8], cgb_flag: u8) -> Rom {
         let mut content: Vec<u8> = vec![0; HEADER_CHECKSUM_INDEX + 1];
-        let rom = Rom { content };
-        assert!(rom.verify_header_checksum().is_err());
+        content[CGB_FLAG_INDEX] = cgb_flag;
+        content[TITLE_RANGE.start..TITLE_RANGE.start + title.len()].copy_from_slice(title);
+        Rom::from_content(content)
+    }
+
+    #[rstest]
+    #[case(b"POKEMON RED", 0x00, "POKEMON RED")]
+    #[case(b"SIXTEEN_CHARS_AB", 0x00, "SIXTEEN_CHARS_AB")]
+    #[case(b"ZELDA          ", 0x80, "ZELDA")]
+    #[case(b"FIFTEEN_CHARS_A", 0x80, "FIFTEEN_CHARS_A")]
+    fn test_title(#[case] title: &[u8], #[case] cgb_flag: u8, #[case] expected: &str) {
+        let rom = rom_with_title(title, cgb_flag);
+
+        assert_eq!(rom.title().unwrap(), expected);
+    }
+
+    #[rstest]
+    #[case(b"POKEMON YELAAUE", 0x80, Some("AAUE"))]
+    #[case(b"POKEMON YELLOW", 0x80, None)]
+    #[case(b"POKEMON YELAAUE", 0x00, None)]
+    fn test_manufacturer_code(
+        #[case] title: &[u8],
+        #[case] cgb_flag: u8,
+        #[case] expected: Option<&str>,
+    ) {
+        let rom = rom_with_title(title, cgb_flag);
+
+        assert_eq!(rom.manufacturer_code().unwrap().as_deref(), expected);
+    }
+
+    #[test]
+    fn test_header_info() {
+        // Arrange
+        let mut rom = rom_with_title(b"TETRIS", 0x00);
+        rom.content[NEW_LICENSEE_RANGE].copy_from_slice(b"01");
+        rom.content[SGB_FLAG_INDEX] = 0x03;
+        rom.content[DESTINATION_INDEX] = 0x01;
+        rom.content[OLD_LICENSEE_INDEX] = USE_NEW_LICENSEE;
+        rom.content[VERSION_INDEX] = 0x02;
+
+        // Act
+        let info = rom.header_info().unwrap();
+
+        // Assert
+        assert_eq!(
+            info,
+            HeaderInfo {
+                title: "TETRIS".to_string(),
+                manufacturer_code: None,
+                licensee: Licensee::New("01".to_string()),
+                destination: Destination::Overseas,
+                version: 0x02,
+                cgb_support: CgbSupport::None,
+                sgb_support: true,
+            }
+        );
+    }
+
+    #[rstest]
+    #[case(0x00, Destination::Japan)]
+    #[case(0x01, Destination::Overseas)]
+    #[case(0x02, Destination::Unknown(0x02))]
+    fn test_destination(#[case] byte: u8, #[case] expected: Destination) {
+        let mut rom = rom_with_title(b"", 0x00);
+        rom.content[DESTINATION_INDEX] = byte;
+
+        assert_eq!(rom.destination().unwrap(), expected);
+    }
+
+    #[test]
+    fn test_old_licensee() {
+        let mut rom = rom_with_title(b"", 0x00);
+        rom.content[OLD_LICENSEE_INDEX] = 0x01;
+
+        assert_eq!(rom.licensee().unwrap(), Licensee::Old(0x01));
+    }
+
+    #[test]
+    fn test_verify_global_checksum() {
+        let mut content: Vec<u8> = vec![0x01; 0x8000];
+        // 0x7FFE bytes of 0x01 sum to 0x7FFE.
+        content[0x14E] = 0x7F;
+        content[0x14F] = 0xFE;
+        let rom = Rom::from_content(content);
+
+        assert!(rom.verify_global_checksum().is_ok());
+    }
+
+    #[test]
+    fn test_verify_global_checksum_negative() {
+        let mut content: Vec<u8> = vec![0x01; 0x8000];
+        content[0x14E] = 0x7F;
+        content[0x14F] = 0xFF;
+        let rom = Rom::from_content(content);
+
+        assert!(matches!(
+            rom.verify_global_checksum(),
+            Err(RomError::BadGlobalChecksum {
+                expected: 0x7FFF,
+                actual: 0x7FFE
+            })
+        ));
+    }
+
+    #[test]
+    fn test_rom_error_into_io_error() {
+        let not_found = io::Error::from(io::ErrorKind::NotFound);
+
+        let from_io = io::Error::from(RomError::from(not_found));
+        let from_rom = io::Error::from(RomError::InvalidRomSize(0x09));
+
+        assert_eq!(from_io.kind(), io::ErrorKind::NotFound);
+        assert_eq!(
+            from_rom.to_string(),
+            "9 is an invalid value for the ROM size."
+        );
+    }
+
+    fn multicart_content(logo_at: usize, cartridge_type: u8) -> Vec<u8> {
+        let mut content = vec![0; MBC1M_SIZE];
+        content[CARTRIDGE_TYPE_INDEX] = cartridge_type;
+        let start = logo_at + NINTENDO_LOGO_RANGE.start;
+        content[start..start + NINTENDO_LOGO.len()].copy_from_slice(&NINTENDO_LOGO);
+        content
+    }
+
+    #[rstest]
+    #[case(multicart_content(0x40000, 0x01), true)]
+    #[case(multicart_content(0xC0000, 0x03), true)]
+    #[case(multicart_content(0x00000, 0x01), false)]
+    #[case(multicart_content(0x20000, 0x01), false)]
+    #[case(multicart_content(0x40000, 0x19), false)]
+    #[case(multicart_content(0x40000, 0x01)[..0x80000].to_vec(), false)]
+    fn test_is_mbc1_multicart(#[case] content: Vec<u8>, #[case] expected: bool) {
+        let rom = Rom::from_content(content);
+
+        assert_eq!(rom.is_mbc1_multicart(), expected);
+    }
+
+    fn valid_content() -> Vec<u8> {
+        let mut content = vec![0; 0x8000];
+        content[NINTENDO_LOGO_RANGE].copy_from_slice(&NINTENDO_LOGO);
+        content[HEADER_CHECKSUM_INDEX] = 0xE7;
+        content
+    }
+
+    #[test]
+    fn test_validate_without_warnings() {
+        let mut rom = Rom::from_content(valid_content());
+        // Only the logo and header checksum contribute to the global checksum.
+        let sum = NINTENDO_LOGO.iter().map(|&b| b as u16).sum::<u16>() + 0xE7;
+        rom.content[0x14E..0x150].copy_from_slice(&sum.to_be_bytes());
+
+        rom.validate(ValidationPolicy::Strict).unwrap();
+
+        assert!(rom.warnings().is_empty());
+    }
+
+    #[test]
+    fn test_strict_rejects_missing_logo() {
+        let mut content = valid_content();
+        content[NINTENDO_LOGO_RANGE.start] = 0;
+        let mut rom = Rom::from_content(content);
+
+        assert!(matches!(
+            rom.validate(ValidationPolicy::Strict),
+            Err(RomError::InvalidLogo)
+        ));
+    }
+
+    #[test]
+    fn test_lenient_collects_warnings() {
+        // Arrange
+        let mut content = valid_content();
+        content[NINTENDO_LOGO_RANGE.start] = 0;
+        content[HEADER_CHECKSUM_INDEX] = 0x00;
+        content.truncate(0x4000);
+        let mut rom = Rom::from_content(content);
+
+        // Act
+        rom.validate(ValidationPolicy::Lenient).unwrap();
+
+        // Assert
+        assert_eq!(rom.warnings().len(), 4);
+        assert_eq!(rom.warnings()[0], RomWarning::LogoMismatch);
+        assert_eq!(
+            rom.warnings()[1],
+            RomWarning::HeaderChecksumMismatch {
+                expected: 0x00,
+                actual: 0xE7
+            }
+        );
+        assert!(matches!(
+            rom.warnings()[2],
+            RomWarning::GlobalChecksumMismatch { .. }
+        ));
+        assert_eq!(
+            rom.warnings()[3],
+            RomWarning::SizeMismatch {
+                expected: 0x8000,
+                actual: 0x4000
+            }
+        );
+    }
+
+    #[test]
+    fn test_lenient_still_rejects_unsupported_header() {
+        let mut content = valid_content();
+        content[CARTRIDGE_TYPE_INDEX] = 0x05;
+        content[RAM_SIZE_INDEX] = 0x02;
+        let mut rom = Rom::from_content(content);
+
+        assert!(rom.validate(ValidationPolicy::Lenient).is_err());
+    }
+
+    #[test]
+    fn test_from_bytes() {
+        let rom = Rom::from_bytes(valid_content()).unwrap();
+
+        assert_eq!(rom.content().len(), 0x8000);
+    }
+
+    #[test]
+    fn test_from_bytes_validates() {
+        assert!(matches!(
+            Rom::from_bytes(vec![0; 0x8000]),
+            Err(RomError::InvalidLogo)
+        ));
+        assert!(Rom::from_bytes_with_policy(vec![0; 0x8000], ValidationPolicy::Lenient).is_ok());
+    }
+
+    #[test]
+    fn test_from_reader() {
+        let content = valid_content();
+
+        let rom = Rom::from_reader(content.as_slice()).unwrap();
+
+        assert_eq!(rom.content(), content.as_slice());
+    }
+
+    #[test]
+    fn test_empty_file() {
+        assert!(matches!(
+            Rom::from_bytes(Vec::new()),
+            Err(RomError::TruncatedHeader)
+        ));
+    }
+
+    #[rstest]
+    #[case(0x100)]
+    #[case(0x14F)]
+    fn test_partial_header(#[case] length: usize) {
+        let mut content = valid_content();
+        content.truncate(length);
+
+        assert!(matches!(
+            Rom::from_bytes_with_policy(content, ValidationPolicy::Lenient),
+            Err(RomError::TruncatedHeader)
+        ));
+    }
+
+    #[test]
+    fn test_accessors_past_end_of_content() {
+        let rom = Rom::from_content(vec![0; CARTRIDGE_TYPE_INDEX + 1]);
+
+        assert_eq!(rom.get_cartridge_type().unwrap(), 0x00);
+        assert!(matches!(rom.get_rom_size(), Err(RomError::TruncatedHeader)));
+        assert!(matches!(rom.header_info(), Err(RomError::TruncatedHeader)));
+        assert!(matches!(
+            rom.verify_global_checksum(),
+            Err(RomError::TruncatedHeader)
+        ));
+    }
+
+    #[rstest]
+    #[case(0x8000, true)]
+    #[case(0x4000, false)]
+    #[case(0x10000, false)]
+    fn test_verify_rom_size(#[case] length: usize, #[case] expected: bool) {
+        let mut content = valid_content();
+        content.resize(length, 0);
+        let rom = Rom::from_content(content);
+
+        assert_eq!(rom.verify_rom_size().is_ok(), expected);
+    }
+
+    #[rstest]
+    #[case(0x8100, "overdumped by 256 bytes")]
+    #[case(0x7F00, "underdumped by 256 bytes")]
+    fn test_size_mismatch_reports_discrepancy(#[case] length: usize, #[case] expected: &str) {
+        let mut content = valid_content();
+        content.resize(length, 0);
+        let rom = Rom::from_content(content);
+
+        let message = rom.verify_rom_size().unwrap_err().to_string();
+
+        assert!(message.contains(expected), "{}", message);
+    }
+
+    #[rstest]
+    #[case(0x00, false, false, false, false)]
+    #[case(0x03, true, true, false, false)]
+    #[case(0x06, false, true, false, false)]
+    #[case(0x0F, false, true, true, false)]
+    #[case(0x10, true, true, true, false)]
+    #[case(0x1C, false, false, false, true)]
+    #[case(0x1E, true, true, false, true)]
+    fn test_cartridge_features(
+        #[case] byte: u8,
+        #[case] ram: bool,
+        #[case] battery: bool,
+        #[case] rtc: bool,
+        #[case] rumble: bool,
+    ) {
+        let mut content: Vec<u8> = vec![0; CARTRIDGE_TYPE_INDEX + 1];
+        content[CARTRIDGE_TYPE_INDEX] = byte;
+        let rom = Rom::from_content(content);
+
+        let features = rom.cartridge_features().unwrap();
+
+        assert_eq!(
+            (
+                features.ram,
+                features.battery,
+                features.rtc,
+                features.rumble
+            ),
+            (ram, battery, rtc, rumble)
+        );
+        assert!(!features.accelerometer);
+    }
+
+    #[test]
+    fn test_mbc7_features() {
+        let mut content: Vec<u8> = vec![0; CARTRIDGE_TYPE_INDEX + 1];
+        content[CARTRIDGE_TYPE_INDEX] = 0x22;
+        let rom = Rom::from_content(content);
+
+        let features = rom.cartridge_features().unwrap();
+
+        assert!(features.accelerometer && features.rumble && features.ram && features.battery);
     }
 }
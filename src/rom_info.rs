@@ -0,0 +1,322 @@
+use std::fmt::Write;
+use std::io;
+use std::path::Path;
+
+use clap::ValueEnum;
+use rustygameboy::dat::Database;
+use rustygameboy::hash::hex;
+use rustygameboy::rom::{
+    CartridgeFeatures, CgbSupport, Destination, HeaderInfo, Licensee, MemoryBankType, Rom,
+};
+use serde::Serialize;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum Format {
+    Text,
+    Json,
+}
+
+// Everything `rom-info` prints. The JSON output uses these field names, so keep them stable.
+#[derive(Serialize)]
+pub struct Report {
+    header: HeaderInfo,
+    cartridge_type: u8,
+    // None when the header holds a code this emulator doesn't know.
+    features: Option<CartridgeFeatures>,
+    rom_size: Option<u32>,
+    ram_size: Option<u32>,
+    header_checksum: Checksum<u8>,
+    global_checksum: Checksum<u16>,
+    crc32: String,
+    sha1: String,
+    // None without a database to look the ROM up in.
+    database: Option<Lookup>,
+    warnings: Vec<String>,
+}
+
+#[derive(Serialize)]
+struct Lookup {
+    // The canonical name, None if the database doesn't know the dump.
+    name: Option<String>,
+    verified: bool,
+}
+
+#[derive(Serialize)]
+struct Checksum<T> {
+    stored: T,
+    computed: T,
+}
+
+impl<T: PartialEq> Checksum<T> {
+    fn matches(&self) -> bool {
+        self.stored == self.computed
+    }
+}
+
+impl Report {
+    pub fn new(rom: &Rom, database: Option<&Database>) -> io::Result<Report> {
+        let lookup = |database: &Database| {
+            let entry = database.lookup(rom);
+            Lookup {
+                name: entry.map(|entry| entry.name.clone()),
+                verified: entry.is_some_and(|entry| entry.verified),
+            }
+        };
+        Ok(Report {
+            header: rom.header_info()?,
+            cartridge_type: rom.get_cartridge_type()?,
+            features: rom.cartridge_features().ok(),
+            rom_size: rom.get_rom_size().ok(),
+            ram_size: rom.get_ram_size().ok(),
+            header_checksum: Checksum {
+                stored: rom.stored_header_checksum()?,
+                computed: rom.header_checksum()?,
+            },
+            global_checksum: Checksum {
+                stored: rom.stored_global_checksum()?,
+                computed: rom.global_checksum()?,
+            },
+            crc32: format!("{:08x}", rom.crc32()),
+            sha1: hex(&rom.sha1()),
+            database: database.map(lookup),
+            warnings: rom.warnings().iter().map(|w| w.to_string()).collect(),
+        })
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).expect("the report always serializes")
+    }
+
+    pub fn to_text(&self) -> String {
+        let mut report = String::new();
+        let mut line = |label: &str, value: String| {
+            writeln!(report, "{:<17}{}", format!("{}:", label), value).unwrap();
+        };
+
+        line("Title", self.header.title.clone());
+        line(
+            "Manufacturer",
+            self.header
+                .manufacturer_code
+                .clone()
+                .unwrap_or_else(|| "-".to_string()),
+        );
+        line("Licensee", licensee(&self.header.licensee));
+        line("Destination", destination(self.header.destination));
+        line("Version", self.header.version.to_string());
+        line(
+            "Cartridge type",
+            cartridge_type(self.cartridge_type, self.features),
+        );
+        line("ROM size", size(self.rom_size));
+        line("RAM size", size(self.ram_size));
+        line(
+            "CGB support",
+            cgb_support(self.header.cgb_support).to_string(),
+        );
+        line("SGB support", yes_no(self.header.sgb_support).to_string());
+        line(
+            "Header checksum",
+            format!(
+                "0x{:02X} (computed 0x{:02X}, {})",
+                self.header_checksum.stored,
+                self.header_checksum.computed,
+                status(self.header_checksum.matches())
+            ),
+        );
+        line(
+            "Global checksum",
+            format!(
+                "0x{:04X} (computed 0x{:04X}, {})",
+                self.global_checksum.stored,
+                self.global_checksum.computed,
+                status(self.global_checksum.matches())
+            ),
+        );
+        line("CRC32", self.crc32.clone());
+        line("SHA-1", self.sha1.clone());
+        if let Some(lookup) = &self.database {
+            line(
+                "Database",
+                match &lookup.name {
+                    Some(name) if lookup.verified => format!("{} (verified)", name),
+                    Some(name) => name.clone(),
+                    None => "not found".to_string(),
+                },
+            );
+        }
+
+        if self.warnings.is_empty() {
+            writeln!(report, "No warnings.").unwrap();
+        } else {
+            writeln!(report, "Warnings:").unwrap();
+            for warning in &self.warnings {
+                writeln!(report, "  {}", warning).unwrap();
+            }
+        }
+
+        report
+    }
+}
+
+// Loads leniently so broken headers can still be inspected; problems show up as warnings. The ROM
+// is looked up in the DAT file at `dat` if there is one.
+pub fn print(path: &str, format: Format, dat: Option<&Path>) -> io::Result<()> {
+    let database = dat.map(Database::load).transpose()?;
+    let report = Report::new(&Rom::new_lenient(path)?, database.as_ref())?;
+    match format {
+        Format::Text => print!("{}", report.to_text()),
+        Format::Json => println!("{}", report.to_json()),
+    }
+    Ok(())
+}
+
+fn licensee(licensee: &Licensee) -> String {
+    match licensee {
+        Licensee::Old(code) => format!("0x{:02X}", code),
+        Licensee::New(code) => format!("\"{}\" (new code)", code),
+    }
+}
+
+fn destination(destination: Destination) -> String {
+    match destination {
+        Destination::Japan => "Japan".to_string(),
+        Destination::Overseas => "Overseas".to_string(),
+        Destination::Unknown(code) => format!("Unknown (0x{:02X})", code),
+    }
+}
+
+fn cartridge_type(code: u8, features: Option<CartridgeFeatures>) -> String {
+    let features = match features {
+        Some(features) => features,
+        None => return format!("0x{:02X} (unknown)", code),
+    };
+
+    let mut parts = vec![memory_bank_type(features.memory_bank_type)];
+    for (present, name) in [
+        (features.ram, "RAM"),
+        (features.battery, "battery"),
+        (features.rtc, "RTC"),
+        (features.rumble, "rumble"),
+        (features.accelerometer, "accelerometer"),
+    ] {
+        if present {
+            parts.push(name);
+        }
+    }
+    format!("0x{:02X} ({})", code, parts.join(", "))
+}
+
+pub fn memory_bank_type(memory_bank_type: MemoryBankType) -> &'static str {
+    match memory_bank_type {
+        MemoryBankType::ROM => "ROM only",
+        MemoryBankType::MBC1 => "MBC1",
+        MemoryBankType::MBC2 => "MBC2",
+        MemoryBankType::MMM01 => "MMM01",
+        MemoryBankType::MBC3 => "MBC3",
+        MemoryBankType::MBC5 => "MBC5",
+        MemoryBankType::MBC6 => "MBC6",
+        MemoryBankType::MBC7 => "MBC7",
+        MemoryBankType::PocketCamera => "Pocket Camera",
+        MemoryBankType::TAMA5 => "TAMA5",
+        MemoryBankType::HuC3 => "HuC3",
+        MemoryBankType::HuC1 => "HuC1",
+    }
+}
+
+fn cgb_support(cgb_support: CgbSupport) -> &'static str {
+    match cgb_support {
+        CgbSupport::None => "no",
+        CgbSupport::Compatible => "compatible",
+        CgbSupport::Only => "CGB only",
+    }
+}
+
+fn yes_no(value: bool) -> &'static str {
+    if value {
+        "yes"
+    } else {
+        "no"
+    }
+}
+
+fn status(matches: bool) -> &'static str {
+    if matches {
+        "ok"
+    } else {
+        "mismatch"
+    }
+}
+
+fn size(bytes: Option<u32>) -> String {
+    match bytes {
+        None => "unknown".to_string(),
+        Some(0) => "none".to_string(),
+        Some(bytes) => format!("{} KiB", bytes / 1024),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rustygameboy::rom::ValidationPolicy;
+
+    use super::*;
+
+    fn rom() -> Rom {
+        let mut content = vec![0; 0x8000];
+        content[0x134..0x13B].copy_from_slice(b"TETRIS\0");
+        content[0x147] = 0x13;
+        content[0x149] = 0x03;
+        Rom::from_bytes_with_policy(content, ValidationPolicy::Lenient).unwrap()
+    }
+
+    #[test]
+    fn test_text_report() {
+        let report = Report::new(&rom(), None).unwrap().to_text();
+
+        assert!(report.contains("Title:           TETRIS\n"));
+        assert!(report.contains("Cartridge type:  0x13 (MBC3, RAM, battery)\n"));
+        assert!(report.contains("ROM size:        32 KiB\n"));
+        assert!(report.contains("RAM size:        32 KiB\n"));
+        assert!(report.contains("Header checksum: 0x00 (computed 0x"));
+        assert!(report.contains("Warnings:\n  Nintendo logo not found"));
+    }
+
+    #[test]
+    fn test_json_report() {
+        let json: serde_json::Value =
+            serde_json::from_str(&Report::new(&rom(), None).unwrap().to_json()).unwrap();
+
+        assert_eq!(json["header"]["title"], "TETRIS");
+        assert_eq!(json["header"]["licensee"]["old"], 0);
+        assert_eq!(json["header"]["destination"], "japan");
+        assert_eq!(json["header"]["cgb_support"], "none");
+        assert_eq!(json["cartridge_type"], 0x13);
+        assert_eq!(json["features"]["memory_bank_type"], "mbc3");
+        assert_eq!(json["features"]["battery"], true);
+        assert_eq!(json["ram_size"], 0x8000);
+        assert_eq!(json["header_checksum"]["stored"], 0);
+        assert_eq!(json["warnings"].as_array().unwrap().len(), 3);
+        assert_eq!(json["sha1"].as_str().unwrap().len(), 40);
+        assert_eq!(json["database"], serde_json::Value::Null);
+    }
+
+    #[test]
+    fn test_database_lookup() {
+        // Arrange
+        let rom = rom();
+        let dat = format!(
+            r#"<datafile><game name="Tetris (World)"><rom name="Tetris (World).gb" crc="{:08x}" sha1="{}" status="verified"/></game></datafile>"#,
+            rom.crc32(),
+            hex(&rom.sha1())
+        );
+        let database = Database::parse(&dat).unwrap();
+
+        // Act
+        let report = Report::new(&rom, Some(&database)).unwrap().to_text();
+
+        // Assert
+        assert!(report.contains(&format!("CRC32:           {:08x}\n", rom.crc32())));
+        assert!(report.contains("Database:        Tetris (World) (verified)\n"));
+    }
+}
@@ -0,0 +1,319 @@
+use std::io::{Error, Result};
+use std::path::{Path, PathBuf};
+
+// Save states are a magic number and format version followed by each component's fields in a
+// fixed order. Multi-byte values are little-endian and byte arrays are length-prefixed.
+pub struct StateWriter {
+    data: Vec<u8>,
+}
+
+impl Default for StateWriter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl StateWriter {
+    pub fn new() -> StateWriter {
+        StateWriter { data: Vec::new() }
+    }
+
+    pub fn into_bytes(self) -> Vec<u8> {
+        self.data
+    }
+
+    pub fn write_u8(&mut self, value: u8) {
+        self.data.push(value);
+    }
+
+    pub fn write_bool(&mut self, value: bool) {
+        self.write_u8(value as u8);
+    }
+
+    pub fn write_u16(&mut self, value: u16) {
+        self.data.extend_from_slice(&value.to_le_bytes());
+    }
+
+    pub fn write_u32(&mut self, value: u32) {
+        self.data.extend_from_slice(&value.to_le_bytes());
+    }
+
+    pub fn write_u64(&mut self, value: u64) {
+        self.data.extend_from_slice(&value.to_le_bytes());
+    }
+
+    pub fn write_f32(&mut self, value: f32) {
+        self.data.extend_from_slice(&value.to_le_bytes());
+    }
+
+    pub fn write_bytes(&mut self, bytes: &[u8]) {
+        self.write_u32(bytes.len() as u32);
+        self.data.extend_from_slice(bytes);
+    }
+
+    // The component's version followed by its state.
+    pub fn write_snapshot<T: Snapshot + ?Sized>(&mut self, component: &T) {
+        self.write_u8(component.version());
+        component.save_state(self);
+    }
+}
+
+// A part of the machine that saves and restores itself. Save states, rewind, movies and netplay
+// all go through this, with each component's state behind its own version so a change to one
+// is caught where it happened instead of misreading everything after it.
+pub trait Snapshot {
+    // Names the component in errors.
+    fn component(&self) -> &'static str;
+
+    // Bump it whenever the fields in `save_state` change.
+    fn version(&self) -> u8;
+
+    fn save_state(&self, writer: &mut StateWriter);
+
+    fn load_state(&mut self, reader: &mut StateReader) -> Result<()>;
+}
+
+pub struct StateReader<'a> {
+    data: &'a [u8],
+    position: usize,
+}
+
+impl<'a> StateReader<'a> {
+    pub fn new(data: &'a [u8]) -> StateReader<'a> {
+        StateReader { data, position: 0 }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.position == self.data.len()
+    }
+
+    pub fn read_u8(&mut self) -> Result<u8> {
+        Ok(self.take(1)?[0])
+    }
+
+    pub fn read_bool(&mut self) -> Result<bool> {
+        Ok(self.read_u8()? != 0)
+    }
+
+    pub fn read_u16(&mut self) -> Result<u16> {
+        Ok(u16::from_le_bytes(self.read_array()?))
+    }
+
+    pub fn read_u32(&mut self) -> Result<u32> {
+        Ok(u32::from_le_bytes(self.read_array()?))
+    }
+
+    pub fn read_u64(&mut self) -> Result<u64> {
+        Ok(u64::from_le_bytes(self.read_array()?))
+    }
+
+    pub fn read_f32(&mut self) -> Result<f32> {
+        Ok(f32::from_le_bytes(self.read_array()?))
+    }
+
+    pub fn read_bytes(&mut self) -> Result<&'a [u8]> {
+        let length = self.read_u32()? as usize;
+        self.take(length)
+    }
+
+    // Reads a byte array that has to exactly fill `buffer`, like a RAM of known size.
+    pub fn read_bytes_into(&mut self, buffer: &mut [u8]) -> Result<()> {
+        let bytes = self.read_bytes()?;
+        if bytes.len() != buffer.len() {
+            return Err(Error::other(format!(
+                "Expected {} bytes in the save state but found {}.",
+                buffer.len(),
+                bytes.len()
+            )));
+        }
+
+        buffer.copy_from_slice(bytes);
+        Ok(())
+    }
+
+    pub fn read_snapshot<T: Snapshot + ?Sized>(&mut self, component: &mut T) -> Result<()> {
+        let version = self.read_u8()?;
+        if version != component.version() {
+            return Err(Error::other(format!(
+                "The {} state version {} is not supported, expected {}.",
+                component.component(),
+                version,
+                component.version()
+            )));
+        }
+
+        component.load_state(self)
+    }
+
+    pub fn read_array<const N: usize>(&mut self) -> Result<[u8; N]> {
+        let mut array = [0; N];
+        array.copy_from_slice(self.take(N)?);
+        Ok(array)
+    }
+
+    fn take(&mut self, length: usize) -> Result<&'a [u8]> {
+        let end = self.position + length;
+        if end > self.data.len() {
+            return Err(Error::other("The save state ended unexpectedly."));
+        }
+
+        let bytes = &self.data[self.position..end];
+        self.position = end;
+        Ok(bytes)
+    }
+}
+
+pub fn write_header(writer: &mut StateWriter) {
+    writer.data.extend_from_slice(MAGIC);
+    writer.write_u32(VERSION);
+}
+
+pub fn read_header(reader: &mut StateReader) -> Result<()> {
+    if &reader.read_array::<4>()? != MAGIC {
+        return Err(Error::other("The file is not a save state."));
+    }
+
+    let version = reader.read_u32()?;
+    if version != VERSION {
+        return Err(Error::other(format!(
+            "The save state version {} is not supported, expected {}.",
+            version, VERSION
+        )));
+    }
+
+    Ok(())
+}
+
+// States live next to the ROM, e.g. game.gb and game.ss1 for slot 1.
+pub fn state_path(rom_path: &Path, slot: u8) -> PathBuf {
+    rom_path.with_extension(format!("ss{}", slot))
+}
+
+const MAGIC: &[u8; 4] = b"RGBS";
+
+const VERSION: u32 = 14;
+
+#[cfg(test)]
+mod tests {
+    use rstest::rstest;
+
+    use super::*;
+
+    #[test]
+    fn test_round_trip() {
+        // Arrange
+        let mut writer = StateWriter::new();
+        write_header(&mut writer);
+        writer.write_u8(0x12);
+        writer.write_bool(true);
+        writer.write_u16(0x3456);
+        writer.write_u32(0x789A_BCDE);
+        writer.write_u64(u64::MAX - 1);
+        writer.write_f32(-0.5);
+        writer.write_bytes(&[1, 2, 3]);
+
+        // Act
+        let bytes = writer.into_bytes();
+        let mut reader = StateReader::new(&bytes);
+
+        // Assert
+        read_header(&mut reader).unwrap();
+        assert_eq!(reader.read_u8().unwrap(), 0x12);
+        assert!(reader.read_bool().unwrap());
+        assert_eq!(reader.read_u16().unwrap(), 0x3456);
+        assert_eq!(reader.read_u32().unwrap(), 0x789A_BCDE);
+        assert_eq!(reader.read_u64().unwrap(), u64::MAX - 1);
+        assert_eq!(reader.read_f32().unwrap(), -0.5);
+        assert_eq!(reader.read_bytes().unwrap(), &[1, 2, 3]);
+        assert!(reader.is_empty());
+    }
+
+    struct Counter {
+        version: u8,
+        count: u16,
+    }
+
+    impl Snapshot for Counter {
+        fn component(&self) -> &'static str {
+            "counter"
+        }
+
+        fn version(&self) -> u8 {
+            self.version
+        }
+
+        fn save_state(&self, writer: &mut StateWriter) {
+            writer.write_u16(self.count);
+        }
+
+        fn load_state(&mut self, reader: &mut StateReader) -> Result<()> {
+            self.count = reader.read_u16()?;
+            Ok(())
+        }
+    }
+
+    #[rstest]
+    #[case(2, Some(0x1234))]
+    #[case(3, None)]
+    fn test_snapshot_version(#[case] version: u8, #[case] expected: Option<u16>) {
+        // Arrange
+        let mut writer = StateWriter::new();
+        writer.write_snapshot(&Counter {
+            version: 2,
+            count: 0x1234,
+        });
+        let bytes = writer.into_bytes();
+        let mut counter = Counter { version, count: 0 };
+
+        // Act
+        let result = StateReader::new(&bytes).read_snapshot(&mut counter);
+
+        // Assert
+        assert_eq!(result.ok().map(|_| counter.count), expected);
+    }
+
+    #[test]
+    fn test_truncated_state() {
+        let mut reader = StateReader::new(&[0x01]);
+
+        assert!(reader.read_u16().is_err());
+    }
+
+    #[test]
+    fn test_read_bytes_into_wrong_size() {
+        let mut writer = StateWriter::new();
+        writer.write_bytes(&[1, 2, 3]);
+        let bytes = writer.into_bytes();
+        let mut buffer = [0; 4];
+
+        assert!(StateReader::new(&bytes)
+            .read_bytes_into(&mut buffer)
+            .is_err());
+    }
+
+    #[test]
+    fn test_bad_header() {
+        let mut writer = StateWriter::new();
+        writer.write_u32(0);
+        writer.write_u32(VERSION);
+        let bytes = writer.into_bytes();
+
+        assert!(read_header(&mut StateReader::new(&bytes)).is_err());
+    }
+
+    #[test]
+    fn test_unsupported_version() {
+        let mut bytes = b"RGBS".to_vec();
+        bytes.extend_from_slice(&(VERSION + 1).to_le_bytes());
+
+        assert!(read_header(&mut StateReader::new(&bytes)).is_err());
+    }
+
+    #[test]
+    fn test_state_path() {
+        assert_eq!(
+            state_path(Path::new("roms/game.gb"), 3),
+            PathBuf::from("roms/game.ss3")
+        );
+    }
+}
@@ -0,0 +1,370 @@
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use clap::ValueEnum;
+use rayon::prelude::*;
+use rustygameboy::hash::hex;
+use rustygameboy::rom::{Rom, ValidationPolicy};
+
+use crate::rom_info::memory_bank_type;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum Format {
+    Table,
+    Csv,
+}
+
+// One ROM found by `scan`. The header fields are None when the ROM couldn't be loaded at all.
+pub struct Entry {
+    // Relative to the scanned directory.
+    path: PathBuf,
+    title: Option<String>,
+    memory_bank_type: Option<&'static str>,
+    crc32: Option<String>,
+    sha1: Option<String>,
+    status: Status,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum Status {
+    Ok,
+    // Runs, but with header problems like a bad checksum or a missing logo.
+    Warnings(Vec<String>),
+    Invalid(String),
+}
+
+// A path `find_roms` came across.
+#[derive(Debug, PartialEq, Eq)]
+pub enum Found {
+    Rom(PathBuf),
+    // A directory or file that couldn't be looked at, with why.
+    Unreadable(PathBuf, String),
+}
+
+impl Entry {
+    // Loads leniently like `rom-info`, so problems the emulator can live with end up as warnings.
+    fn new(root: &Path, path: &Path) -> Entry {
+        let relative = path.strip_prefix(root).unwrap_or(path).to_path_buf();
+        let rom = fs::read(path)
+            .map_err(|error| error.to_string())
+            .and_then(|content| {
+                Rom::from_bytes_with_policy(content, ValidationPolicy::Lenient)
+                    .map_err(|error| error.to_string())
+            });
+        let rom = match rom {
+            Ok(rom) => rom,
+            Err(error) => return Entry::invalid(root, path, error),
+        };
+        let warnings: Vec<String> = rom.warnings().iter().map(|w| w.to_string()).collect();
+        Entry {
+            path: relative,
+            title: rom.title().ok(),
+            memory_bank_type: rom.get_memory_bank_type().ok().map(memory_bank_type),
+            crc32: Some(format!("{:08x}", rom.crc32())),
+            sha1: Some(hex(&rom.sha1())),
+            status: if warnings.is_empty() {
+                Status::Ok
+            } else {
+                Status::Warnings(warnings)
+            },
+        }
+    }
+
+    fn invalid(root: &Path, path: &Path, error: String) -> Entry {
+        Entry {
+            path: path.strip_prefix(root).unwrap_or(path).to_path_buf(),
+            title: None,
+            memory_bank_type: None,
+            crc32: None,
+            sha1: None,
+            status: Status::Invalid(error),
+        }
+    }
+
+    pub fn valid(&self) -> bool {
+        !matches!(self.status, Status::Invalid(_))
+    }
+}
+
+impl Status {
+    fn name(&self) -> &'static str {
+        match self {
+            Status::Ok => "ok",
+            Status::Warnings(_) => "warnings",
+            Status::Invalid(_) => "invalid",
+        }
+    }
+
+    fn problems(&self) -> String {
+        match self {
+            Status::Ok => String::new(),
+            Status::Warnings(warnings) => warnings.join(" "),
+            Status::Invalid(error) => error.clone(),
+        }
+    }
+}
+
+// Every .gb and .gbc file under `root`, sorted so the output doesn't depend on the file system.
+// Symbolic links to directories aren't followed, as they could loop. Anything under `root` that
+// can't be read is passed over and reported, only `root` itself has to be readable.
+pub fn find_roms(root: &Path) -> io::Result<Vec<Found>> {
+    let mut found = Vec::new();
+    let mut directories = vec![root.to_path_buf()];
+    while let Some(directory) = directories.pop() {
+        let entries = match fs::read_dir(&directory) {
+            Ok(entries) => entries,
+            Err(error) if directory == root => return Err(error),
+            Err(error) => {
+                found.push(Found::Unreadable(directory, error.to_string()));
+                continue;
+            }
+        };
+        for entry in entries {
+            let entry = match entry {
+                Ok(entry) => entry,
+                // Which entry is unknown, so the directory is what's reported.
+                Err(error) => {
+                    found.push(Found::Unreadable(directory.clone(), error.to_string()));
+                    continue;
+                }
+            };
+            let path = entry.path();
+            match entry.file_type() {
+                Ok(file_type) if file_type.is_dir() => directories.push(path),
+                Ok(_) if is_rom(&path) => found.push(Found::Rom(path)),
+                Ok(_) => {}
+                Err(error) => found.push(Found::Unreadable(path, error.to_string())),
+            }
+        }
+    }
+    found.sort_by(|a, b| a.path().cmp(b.path()));
+    Ok(found)
+}
+
+impl Found {
+    fn path(&self) -> &Path {
+        match self {
+            Found::Rom(path) | Found::Unreadable(path, _) => path,
+        }
+    }
+}
+
+fn is_rom(path: &Path) -> bool {
+    path.extension()
+        .and_then(|extension| extension.to_str())
+        .is_some_and(|extension| {
+            extension.eq_ignore_ascii_case("gb") || extension.eq_ignore_ascii_case("gbc")
+        })
+}
+
+// Loads the ROMs on all cores, keeping them in the order given. What couldn't be read is invalid.
+pub fn scan(root: &Path, found: &[Found]) -> Vec<Entry> {
+    found
+        .par_iter()
+        .map(|found| match found {
+            Found::Rom(path) => Entry::new(root, path),
+            Found::Unreadable(path, error) => Entry::invalid(root, path, error.clone()),
+        })
+        .collect()
+}
+
+pub fn to_table(entries: &[Entry]) -> String {
+    let rows: Vec<[String; 5]> = entries
+        .iter()
+        .map(|entry| {
+            [
+                entry.path.display().to_string(),
+                entry.title.clone().unwrap_or_else(|| "-".to_string()),
+                entry.memory_bank_type.unwrap_or("-").to_string(),
+                entry.crc32.clone().unwrap_or_else(|| "-".to_string()),
+                match &entry.status {
+                    Status::Ok => "ok".to_string(),
+                    status => format!("{}: {}", status.name(), status.problems()),
+                },
+            ]
+        })
+        .collect();
+    let header = ["Path", "Title", "MBC", "CRC32", "Status"].map(String::from);
+    let mut widths = header.clone().map(|cell| cell.chars().count());
+    for row in &rows {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(cell.chars().count());
+        }
+    }
+
+    let mut table = String::new();
+    for row in std::iter::once(&header).chain(&rows) {
+        // The status is last and can be long, so it isn't padded.
+        for (index, cell) in row.iter().enumerate() {
+            if index + 1 < row.len() {
+                table.push_str(&format!("{:<width$}  ", cell, width = widths[index]));
+            } else {
+                table.push_str(cell);
+            }
+        }
+        table.push('\n');
+    }
+    let invalid = entries.iter().filter(|entry| !entry.valid()).count();
+    table.push_str(&format!("{} ROMs, {} invalid.\n", entries.len(), invalid));
+    table
+}
+
+pub fn to_csv(entries: &[Entry]) -> String {
+    let mut csv = String::from("path,title,mbc,crc32,sha1,status,problems\n");
+    for entry in entries {
+        let fields = [
+            entry.path.display().to_string(),
+            entry.title.clone().unwrap_or_default(),
+            entry.memory_bank_type.unwrap_or_default().to_string(),
+            entry.crc32.clone().unwrap_or_default(),
+            entry.sha1.clone().unwrap_or_default(),
+            entry.status.name().to_string(),
+            entry.status.problems(),
+        ];
+        let fields: Vec<String> = fields.iter().map(|field| csv_field(field)).collect();
+        csv.push_str(&fields.join(","));
+        csv.push('\n');
+    }
+    csv
+}
+
+// Quotes a field with a comma, quote or line break in it, doubling its quotes, as RFC 4180 does.
+fn csv_field(field: &str) -> String {
+    if field.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+// Returns whether every ROM could be loaded.
+pub fn print(root: &Path, format: Format) -> io::Result<bool> {
+    let entries = scan(root, &find_roms(root)?);
+    match format {
+        Format::Table => print!("{}", to_table(&entries)),
+        Format::Csv => print!("{}", to_csv(&entries)),
+    }
+    Ok(entries.iter().all(Entry::valid))
+}
+
+#[cfg(test)]
+mod tests {
+    use rstest::rstest;
+    use tempfile::{tempdir, TempDir};
+
+    use super::*;
+
+    // A directory with a 32 KB MBC1 ROM without the logo in a subdirectory, a file too short to
+    // have a header and a text file.
+    fn directory() -> TempDir {
+        let directory = tempdir().unwrap();
+        let root = directory.path();
+        fs::create_dir(root.join("sub")).unwrap();
+        let mut content = vec![0; 0x8000];
+        content[0x134..0x13B].copy_from_slice(b"TETRIS\0");
+        content[0x147] = 0x01;
+        fs::write(root.join("sub").join("tetris.GB"), content).unwrap();
+        fs::write(root.join("broken.gbc"), [0; 16]).unwrap();
+        fs::write(root.join("notes.txt"), "not a ROM").unwrap();
+        directory
+    }
+
+    #[test]
+    fn test_find_roms() {
+        let directory = directory();
+        let root = directory.path();
+
+        let roms = find_roms(root).unwrap();
+
+        assert_eq!(
+            roms,
+            vec![
+                Found::Rom(root.join("broken.gbc")),
+                Found::Rom(root.join("sub").join("tetris.GB"))
+            ]
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_unreadable_directory_is_invalid() {
+        use std::os::unix::fs::PermissionsExt;
+
+        // Arrange
+        let directory = directory();
+        let root = directory.path();
+        let locked = root.join("locked");
+        fs::create_dir(&locked).unwrap();
+        fs::set_permissions(&locked, fs::Permissions::from_mode(0o000)).unwrap();
+        // Permissions don't stop root.
+        let readable = fs::read_dir(&locked).is_ok();
+
+        // Act
+        let entries = scan(root, &find_roms(root).unwrap());
+
+        // Assert
+        // Otherwise it can't be cleaned up.
+        fs::set_permissions(&locked, fs::Permissions::from_mode(0o755)).unwrap();
+        if readable {
+            return;
+        }
+        assert_eq!(entries.len(), 3);
+        assert_eq!(entries[1].path, Path::new("locked"));
+        assert_eq!(entries[1].status.name(), "invalid");
+        assert_eq!(entries[2].title.as_deref(), Some("TETRIS"));
+    }
+
+    #[test]
+    fn test_scan() {
+        // Arrange
+        let directory = directory();
+        let root = directory.path();
+
+        // Act
+        let entries = scan(root, &find_roms(root).unwrap());
+
+        // Assert
+        assert_eq!(entries[0].path, Path::new("broken.gbc"));
+        assert!(!entries[0].valid());
+        assert_eq!(entries[1].title.as_deref(), Some("TETRIS"));
+        assert_eq!(entries[1].memory_bank_type, Some("MBC1"));
+        assert_eq!(entries[1].status.name(), "warnings");
+    }
+
+    #[test]
+    fn test_csv() {
+        let directory = directory();
+        let root = directory.path();
+
+        let csv = to_csv(&scan(root, &find_roms(root).unwrap()));
+
+        let lines: Vec<&str> = csv.lines().collect();
+        assert_eq!(lines[0], "path,title,mbc,crc32,sha1,status,problems");
+        assert_eq!(
+            lines[1],
+            "broken.gbc,,,,,invalid,The ROM is too short to contain a header."
+        );
+        assert!(lines[2].contains(",TETRIS,MBC1,"));
+    }
+
+    #[test]
+    fn test_table() {
+        let directory = directory();
+        let root = directory.path();
+
+        let table = to_table(&scan(root, &find_roms(root).unwrap()));
+
+        assert!(table.starts_with("Path"));
+        assert!(table.contains("TETRIS"));
+        assert!(table.ends_with("2 ROMs, 1 invalid.\n"));
+    }
+
+    #[rstest]
+    #[case("TETRIS", "TETRIS")]
+    #[case("A, B", "\"A, B\"")]
+    #[case("say \"hi\"", "\"say \"\"hi\"\"\"")]
+    fn test_csv_field(#[case] field: &str, #[case] expected: &str) {
+        assert_eq!(csv_field(field), expected);
+    }
+}
@@ -0,0 +1,188 @@
+use std::io::Result;
+
+use crate::savestate::{Snapshot, StateReader, StateWriter};
+
+// Things that happen at a known point in the future. Components only need to be brought up to
+// date when one of their events is due or when the CPU touches their registers.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Event {
+    PpuModeChange,
+    TimerInterrupt,
+    SerialTransfer,
+    ApuFrameSequencer,
+}
+
+impl Event {
+    const ALL: [Event; EVENT_COUNT] = [
+        Event::PpuModeChange,
+        Event::TimerInterrupt,
+        Event::SerialTransfer,
+        Event::ApuFrameSequencer,
+    ];
+}
+
+// Keeps the time in T-cycles since power-on and when each event is due. Each event is scheduled at
+// most once, so scheduling it again moves it.
+pub struct Scheduler {
+    now: u64,
+    due: [Option<u64>; EVENT_COUNT],
+}
+
+impl Default for Scheduler {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Scheduler {
+    pub fn new() -> Scheduler {
+        Scheduler {
+            now: 0,
+            due: [None; EVENT_COUNT],
+        }
+    }
+
+    pub fn now(&self) -> u64 {
+        self.now
+    }
+
+    pub fn advance(&mut self, cycles: u32) {
+        self.now += cycles as u64;
+    }
+
+    pub fn schedule(&mut self, event: Event, cycles: u64) {
+        self.due[event as usize] = Some(self.now + cycles);
+    }
+
+    pub fn schedule_at(&mut self, event: Event, time: u64) {
+        self.due[event as usize] = Some(time);
+    }
+
+    pub fn cancel(&mut self, event: Event) {
+        self.due[event as usize] = None;
+    }
+
+    pub fn is_scheduled(&self, event: Event) -> bool {
+        self.due[event as usize].is_some()
+    }
+
+    // Removes and returns the event due the soonest if it is due by now. Events due at the same
+    // time come out in declaration order.
+    pub fn pop_due(&mut self) -> Option<Event> {
+        let (index, time) = self
+            .due
+            .iter()
+            .enumerate()
+            .filter_map(|(index, due)| due.map(|time| (index, time)))
+            .min_by_key(|&(_, time)| time)?;
+        if time > self.now {
+            return None;
+        }
+
+        self.due[index] = None;
+        Some(Event::ALL[index])
+    }
+}
+
+impl Snapshot for Scheduler {
+    fn component(&self) -> &'static str {
+        "scheduler"
+    }
+
+    fn version(&self) -> u8 {
+        1
+    }
+
+    fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_u64(self.now);
+        for due in self.due {
+            writer.write_bool(due.is_some());
+            writer.write_u64(due.unwrap_or_default());
+        }
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader) -> Result<()> {
+        self.now = reader.read_u64()?;
+        for due in self.due.iter_mut() {
+            let scheduled = reader.read_bool()?;
+            let time = reader.read_u64()?;
+            *due = scheduled.then_some(time);
+        }
+        Ok(())
+    }
+}
+
+const EVENT_COUNT: usize = 4;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_event_fires_when_due() {
+        let mut scheduler = Scheduler::new();
+        scheduler.schedule(Event::TimerInterrupt, 8);
+
+        scheduler.advance(4);
+        let early = scheduler.pop_due();
+        scheduler.advance(4);
+
+        assert_eq!(early, None);
+        assert_eq!(scheduler.pop_due(), Some(Event::TimerInterrupt));
+        assert_eq!(scheduler.pop_due(), None);
+    }
+
+    #[test]
+    fn test_events_come_out_in_time_order() {
+        let mut scheduler = Scheduler::new();
+        scheduler.schedule(Event::SerialTransfer, 12);
+        scheduler.schedule(Event::PpuModeChange, 4);
+        scheduler.schedule(Event::ApuFrameSequencer, 8);
+
+        scheduler.advance(16);
+
+        assert_eq!(scheduler.pop_due(), Some(Event::PpuModeChange));
+        assert_eq!(scheduler.pop_due(), Some(Event::ApuFrameSequencer));
+        assert_eq!(scheduler.pop_due(), Some(Event::SerialTransfer));
+    }
+
+    #[test]
+    fn test_rescheduling_moves_event() {
+        let mut scheduler = Scheduler::new();
+        scheduler.schedule(Event::PpuModeChange, 4);
+
+        scheduler.schedule(Event::PpuModeChange, 100);
+        scheduler.advance(4);
+
+        assert_eq!(scheduler.pop_due(), None);
+        assert!(scheduler.is_scheduled(Event::PpuModeChange));
+    }
+
+    #[test]
+    fn test_cancel() {
+        let mut scheduler = Scheduler::new();
+        scheduler.schedule(Event::SerialTransfer, 4);
+
+        scheduler.cancel(Event::SerialTransfer);
+        scheduler.advance(4);
+
+        assert_eq!(scheduler.pop_due(), None);
+    }
+
+    #[test]
+    fn test_save_and_load_state() {
+        let mut scheduler = Scheduler::new();
+        scheduler.advance(20);
+        scheduler.schedule(Event::ApuFrameSequencer, 8);
+        let mut writer = StateWriter::new();
+        scheduler.save_state(&mut writer);
+        let bytes = writer.into_bytes();
+
+        let mut loaded = Scheduler::new();
+        loaded.load_state(&mut StateReader::new(&bytes)).unwrap();
+        loaded.advance(8);
+
+        assert_eq!(loaded.now(), 28);
+        assert_eq!(loaded.pop_due(), Some(Event::ApuFrameSequencer));
+    }
+}
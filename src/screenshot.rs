@@ -0,0 +1,188 @@
+use std::io::{Error, Result};
+use std::str::FromStr;
+
+use crate::ppu::SCREEN_WIDTH;
+#[cfg(feature = "png")]
+use crate::ppu::{Ppu, DMG_COLORS, SCREEN_HEIGHT};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ScreenshotColors {
+    // The colors on screen.
+    Screen,
+    // An indexed PNG holding each pixel's DMG shade (0-3) with the DMG colors as its palette, for
+    // tools that work with the game's own shades. Not available in CGB or compatibility mode.
+    Shades,
+}
+
+impl FromStr for ScreenshotColors {
+    type Err = Error;
+
+    fn from_str(name: &str) -> Result<ScreenshotColors> {
+        match name.to_ascii_lowercase().as_str() {
+            "screen" => Ok(ScreenshotColors::Screen),
+            "shades" => Ok(ScreenshotColors::Shades),
+            _ => Err(Error::other(format!(
+                "{} is not a screenshot color mode, expected screen or shades.",
+                name
+            ))),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ScreenshotOptions {
+    // Each Game Boy pixel becomes a square this many pixels wide.
+    pub scale: u32,
+    pub colors: ScreenshotColors,
+}
+
+impl Default for ScreenshotOptions {
+    fn default() -> Self {
+        ScreenshotOptions {
+            scale: 1,
+            colors: ScreenshotColors::Screen,
+        }
+    }
+}
+
+// Encodes the last frame as a PNG, `screen` being what the screen showed.
+#[cfg(feature = "png")]
+pub fn encode(ppu: &Ppu, screen: &[u8], options: ScreenshotOptions) -> Result<Vec<u8>> {
+    if options.scale == 0 {
+        return Err(Error::other("A screenshot can't be scaled by 0."));
+    }
+
+    let (pixels, bytes_per_pixel) = match options.colors {
+        ScreenshotColors::Screen => (screen.to_vec(), 4),
+        ScreenshotColors::Shades => {
+            let shades = ppu.shades().ok_or_else(|| {
+                Error::other("Only DMG mode has shades, CGB colors come from palette RAM.")
+            })?;
+            (shades, 1)
+        }
+    };
+    let pixels = scale(&pixels, bytes_per_pixel, options.scale as usize);
+
+    let mut png = Vec::new();
+    let mut encoder = png::Encoder::new(
+        &mut png,
+        SCREEN_WIDTH as u32 * options.scale,
+        SCREEN_HEIGHT as u32 * options.scale,
+    );
+    encoder.set_depth(png::BitDepth::Eight);
+    match options.colors {
+        ScreenshotColors::Screen => encoder.set_color(png::ColorType::Rgba),
+        ScreenshotColors::Shades => {
+            encoder.set_color(png::ColorType::Indexed);
+            encoder.set_palette(
+                DMG_COLORS
+                    .iter()
+                    .flat_map(|color| &color[..3])
+                    .copied()
+                    .collect::<Vec<_>>(),
+            );
+        }
+    }
+    let mut writer = encoder.write_header().map_err(Error::other)?;
+    writer.write_image_data(&pixels).map_err(Error::other)?;
+    writer.finish().map_err(Error::other)?;
+    Ok(png)
+}
+
+// Repeats every pixel of a frame `scale` times in both directions.
+pub fn scale(pixels: &[u8], bytes_per_pixel: usize, scale: usize) -> Vec<u8> {
+    if scale == 1 {
+        return pixels.to_vec();
+    }
+    let row_size = SCREEN_WIDTH * bytes_per_pixel;
+    let mut scaled = Vec::with_capacity(pixels.len() * scale * scale);
+    for row in pixels.chunks_exact(row_size) {
+        let start = scaled.len();
+        for pixel in row.chunks_exact(bytes_per_pixel) {
+            for _ in 0..scale {
+                scaled.extend_from_slice(pixel);
+            }
+        }
+        for _ in 1..scale {
+            scaled.extend_from_within(start..start + row_size * scale);
+        }
+    }
+    scaled
+}
+
+#[cfg(test)]
+mod tests {
+    use rstest::rstest;
+
+    use super::*;
+
+    #[cfg(feature = "png")]
+    fn decode(png: &[u8]) -> (png::OutputInfo, Vec<u8>) {
+        let mut reader = png::Decoder::new(png).read_info().unwrap();
+        let mut pixels = vec![0; reader.output_buffer_size()];
+        let info = reader.next_frame(&mut pixels).unwrap();
+        (info, pixels)
+    }
+
+    #[cfg(feature = "png")]
+    #[rstest]
+    #[case(1, 160, 144)]
+    #[case(3, 480, 432)]
+    fn test_encode_scaled(#[case] scale: u32, #[case] width: u32, #[case] height: u32) {
+        let ppu = Ppu::new();
+
+        let png = encode(
+            &ppu,
+            ppu.framebuffer(),
+            ScreenshotOptions {
+                scale,
+                ..Default::default()
+            },
+        )
+        .unwrap();
+
+        let (info, pixels) = decode(&png);
+        assert_eq!((info.width, info.height), (width, height));
+        assert_eq!(info.color_type, png::ColorType::Rgba);
+        assert!(pixels.iter().all(|&byte| byte == 0xFF));
+    }
+
+    #[cfg(feature = "png")]
+    #[test]
+    fn test_encode_shades() {
+        let ppu = Ppu::new();
+        let options = ScreenshotOptions {
+            scale: 1,
+            colors: ScreenshotColors::Shades,
+        };
+
+        let png = encode(&ppu, ppu.framebuffer(), options).unwrap();
+
+        let (info, pixels) = decode(&png);
+        assert_eq!(info.color_type, png::ColorType::Indexed);
+        assert!(pixels.iter().all(|&shade| shade == 0));
+        let cgb = Ppu::new_cgb();
+        assert!(encode(&cgb, cgb.framebuffer(), options).is_err());
+    }
+
+    #[test]
+    fn test_scale() {
+        // A 2 pixel wide row would do, but rows are always the screen's width.
+        let mut pixels = vec![0; SCREEN_WIDTH];
+        pixels[0] = 1;
+
+        let scaled = scale(&pixels, 1, 2);
+
+        assert_eq!(scaled.len(), SCREEN_WIDTH * 4);
+        assert_eq!(scaled[..3], [1, 1, 0]);
+        assert_eq!(scaled[SCREEN_WIDTH * 2..SCREEN_WIDTH * 2 + 3], [1, 1, 0]);
+    }
+
+    #[rstest]
+    #[case("screen", Some(ScreenshotColors::Screen))]
+    #[case("Shades", Some(ScreenshotColors::Shades))]
+    #[case("indices", None)]
+    fn test_parse_colors(#[case] name: &str, #[case] expected: Option<ScreenshotColors>) {
+        assert_eq!(name.parse::<ScreenshotColors>().ok(), expected);
+    }
+}
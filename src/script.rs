@@ -0,0 +1,263 @@
+use std::cell::RefCell;
+use std::fs;
+use std::io::{Error, Result};
+use std::path::Path;
+use std::rc::Rc;
+
+use mlua::{Function, Lua, Scope};
+
+use crate::bus::{AccessKind, Watchpoint};
+use crate::emulator::Emulator;
+use crate::joypad::Button;
+
+// A Lua script that runs alongside the game. Scripts get an `emu` table:
+//
+//   emu.read(address)               a byte, without side effects
+//   emu.write(address, value)       writes like the CPU would
+//   emu.press(button)               holds "a", "b", "select", "start", "up", "down", "left" or
+//   emu.release(button)             "right" until it's released
+//   emu.frame()                     the number of frames so far
+//   emu.on_frame(function)          calls it after every frame
+//   emu.on_memory_write(address, function)
+//                                   calls it with the address and value for every write to the
+//                                   address, after the frame the writes happened in
+//
+// The write hooks use watchpoints, so they don't mix with a debugger.
+pub struct Script {
+    lua: Lua,
+    hooks: Rc<RefCell<Hooks>>,
+}
+
+#[derive(Default)]
+struct Hooks {
+    frame: Vec<Function>,
+    writes: Vec<(u16, Function)>,
+    // Watchpoints the emulator doesn't have yet.
+    new_watchpoints: Vec<Watchpoint>,
+}
+
+impl Script {
+    pub fn load(emulator: &mut Emulator, path: &Path) -> Result<Script> {
+        let source = fs::read_to_string(path).map_err(|error| {
+            Error::new(
+                error.kind(),
+                format!("Could not read {}: {}", path.display(), error),
+            )
+        })?;
+        Script::new(emulator, &source, &path.display().to_string())
+    }
+
+    // Runs the top level of the script, which is where it registers its hooks.
+    pub fn new(emulator: &mut Emulator, source: &str, name: &str) -> Result<Script> {
+        let script = Script {
+            lua: Lua::new(),
+            hooks: Rc::default(),
+        };
+        script.with_api(emulator, |lua| {
+            lua.load(source).set_name(format!("@{}", name)).exec()
+        })?;
+        Ok(script)
+    }
+
+    // Calls the hooks for the frame that just finished.
+    pub fn frame(&mut self, emulator: &mut Emulator) -> Result<()> {
+        let hits = emulator.take_watch_hits();
+        let hooks = self.hooks.clone();
+        self.with_api(emulator, |_| {
+            let (writes, frame) = {
+                let hooks = hooks.borrow();
+                (hooks.writes.clone(), hooks.frame.clone())
+            };
+            for hit in hits.iter().filter(|hit| hit.kind == AccessKind::Write) {
+                for (_, hook) in writes.iter().filter(|(address, _)| *address == hit.address) {
+                    hook.call::<()>((hit.address, hit.value))?;
+                }
+            }
+            for hook in &frame {
+                hook.call::<()>(())?;
+            }
+            Ok(())
+        })
+    }
+
+    // Makes `emu` point at `emulator` while `f` runs.
+    fn with_api(
+        &self,
+        emulator: &mut Emulator,
+        f: impl FnOnce(&Lua) -> mlua::Result<()>,
+    ) -> Result<()> {
+        let emulator = RefCell::new(emulator);
+        let result = self.lua.scope(|scope| {
+            let api = self.lua.create_table()?;
+            self.add_functions(scope, &api, &emulator)?;
+            self.lua.globals().set("emu", api)?;
+            f(&self.lua)
+        });
+
+        let emulator = emulator.into_inner();
+        for watchpoint in self.hooks.borrow_mut().new_watchpoints.drain(..) {
+            emulator.add_watchpoint(watchpoint);
+        }
+        result.map_err(|error| Error::other(error.to_string()))
+    }
+
+    fn add_functions<'scope, 'env: 'scope>(
+        &self,
+        scope: &'scope Scope<'scope, 'env>,
+        api: &mlua::Table,
+        emulator: &'env RefCell<&mut Emulator>,
+    ) -> mlua::Result<()> {
+        api.set(
+            "read",
+            scope.create_function(|_, address: u16| Ok(emulator.borrow_mut().peek(address)))?,
+        )?;
+        api.set(
+            "write",
+            scope.create_function(|_, (address, value): (u16, u8)| {
+                emulator.borrow_mut().poke(address, value);
+                Ok(())
+            })?,
+        )?;
+        api.set(
+            "press",
+            scope.create_function(|_, name: String| {
+                emulator.borrow_mut().set_button(button(&name)?, true);
+                Ok(())
+            })?,
+        )?;
+        api.set(
+            "release",
+            scope.create_function(|_, name: String| {
+                emulator.borrow_mut().set_button(button(&name)?, false);
+                Ok(())
+            })?,
+        )?;
+        api.set(
+            "frame",
+            scope.create_function(|_, ()| Ok(emulator.borrow().bus().ppu().frames()))?,
+        )?;
+
+        let hooks = self.hooks.clone();
+        api.set(
+            "on_frame",
+            self.lua.create_function(move |_, hook: Function| {
+                hooks.borrow_mut().frame.push(hook);
+                Ok(())
+            })?,
+        )?;
+        let hooks = self.hooks.clone();
+        api.set(
+            "on_memory_write",
+            self.lua
+                .create_function(move |_, (address, hook): (u16, Function)| {
+                    let mut hooks = hooks.borrow_mut();
+                    if hooks.writes.iter().all(|(watched, _)| *watched != address) {
+                        hooks.new_watchpoints.push(Watchpoint {
+                            start: address,
+                            end: address,
+                            read: false,
+                            write: true,
+                            execute: false,
+                        });
+                    }
+                    hooks.writes.push((address, hook));
+                    Ok(())
+                })?,
+        )?;
+        Ok(())
+    }
+}
+
+fn button(name: &str) -> mlua::Result<Button> {
+    name.parse()
+        .map_err(|error: Error| mlua::Error::runtime(error.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rom::Rom;
+
+    fn emulator() -> Emulator {
+        // LD A, 0x2A; LD (0xC000), A; INC A; JR -6
+        let mut content = vec![0; 0x8000];
+        content[0x100..0x108].copy_from_slice(&[0x3E, 0x2A, 0xEA, 0x00, 0xC0, 0x3C, 0x18, 0xFA]);
+        Emulator::new(Rom::from_content(content)).unwrap()
+    }
+
+    #[test]
+    fn test_read_and_write() {
+        let mut emulator = emulator();
+
+        Script::new(
+            &mut emulator,
+            "emu.write(0xC100, emu.read(0x0101) + 1)",
+            "test",
+        )
+        .unwrap();
+
+        assert_eq!(emulator.peek(0xC100), 0x2B);
+    }
+
+    #[test]
+    fn test_hooks() {
+        // Arrange
+        let mut emulator = emulator();
+        let source = "
+            writes = 0
+            emu.on_memory_write(0xC000, function(address, value)
+                writes = writes + 1
+                last = value
+            end)
+            emu.on_frame(function()
+                emu.write(0xC200, writes)
+                emu.write(0xC201, last)
+                emu.press('start')
+            end)
+        ";
+        let mut script = Script::new(&mut emulator, source, "test").unwrap();
+
+        // Act
+        emulator.run_for(crate::emulator::RunLimit::Cycles(1000));
+        script.frame(&mut emulator).unwrap();
+
+        // Assert
+        let writes = emulator.peek(0xC200);
+        assert!(writes > 0);
+        assert_eq!(emulator.peek(0xC201), 0x2A + writes - 1);
+        assert!(emulator.bus_mut().joypad_mut().pressed(Button::Start));
+    }
+
+    #[test]
+    fn test_frame_error_stops_the_script() {
+        // Arrange
+        let mut emulator = emulator();
+        let source = "emu.on_frame(function() emu.press('turbo') end)";
+        let script = Script::new(&mut emulator, source, "test.lua").unwrap();
+        emulator.set_script(Some(script));
+
+        // Act
+        emulator.run_frame();
+        emulator.run_frame();
+
+        // Assert
+        let error = emulator.take_script_error().unwrap();
+        assert!(error.to_string().starts_with("Stopped the script"));
+        assert!(emulator.take_script().is_none());
+        assert!(emulator.take_script_error().is_none());
+    }
+
+    #[test]
+    fn test_errors() {
+        let mut emulator = emulator();
+
+        let error = Script::new(&mut emulator, "emu.press('turbo')", "test.lua")
+            .err()
+            .unwrap();
+
+        assert!(error.to_string().contains("test.lua:1"));
+        assert!(error
+            .to_string()
+            .contains("turbo is not a Game Boy button."));
+    }
+}
@@ -0,0 +1,312 @@
+use std::io::{Result, Write};
+
+use crate::interrupts::SERIAL_INTERRUPT;
+use crate::savestate::{Snapshot, StateReader, StateWriter};
+
+// Whatever is plugged into the link port: a sink for test output, another Game Boy or a printer.
+pub trait SerialDevice {
+    // Called when a transfer clocked by this side finishes. The device gets the byte shifted out
+    // and returns the one shifted in at the same time.
+    fn transfer(&mut self, byte: u8) -> u8;
+
+    // Polled regularly so a device can clock a transfer itself, like the other Game Boy on a link
+    // cable. `byte` is what this side shifts out if it's waiting for the external clock. Returns
+    // the byte shifted in when a transfer happened.
+    fn clock(&mut self, _byte: Option<u8>) -> Option<u8> {
+        None
+    }
+
+    // Called when the device is unplugged, to write out anything it still holds.
+    fn finish(self: Box<Self>) -> Result<()> {
+        Ok(())
+    }
+}
+
+// Writes every byte sent to `out` and answers like an empty port. Blargg's test ROMs print their
+// results this way.
+pub struct SerialOutput<W: Write> {
+    out: W,
+}
+
+impl<W: Write> SerialOutput<W> {
+    pub fn new(out: W) -> SerialOutput<W> {
+        SerialOutput { out }
+    }
+}
+
+impl<W: Write> SerialDevice for SerialOutput<W> {
+    fn transfer(&mut self, byte: u8) -> u8 {
+        // Flushed as it goes so the output shows up while the game runs. A closed pipe isn't a
+        // reason to stop the game.
+        let _ = self.out.write_all(&[byte]).and_then(|_| self.out.flush());
+        0xFF
+    }
+}
+
+pub type SerialCallback = Box<dyn FnMut(u8)>;
+
+// SB (0xFF01) and SC (0xFF02). Without a link partner every bit shifted in is a 1.
+pub struct Serial {
+    data: u8,
+    control: u8,
+    cgb: bool,
+    interrupts: u8,
+    device: Option<Box<dyn SerialDevice>>,
+    callback: Option<SerialCallback>,
+}
+
+impl Serial {
+    pub fn new(cgb: bool) -> Serial {
+        Serial {
+            data: 0,
+            control: 0,
+            cgb,
+            interrupts: 0,
+            device: None,
+            callback: None,
+        }
+    }
+
+    pub fn set_device(&mut self, device: Option<Box<dyn SerialDevice>>) {
+        self.device = device;
+    }
+
+    pub fn take_device(&mut self) -> Option<Box<dyn SerialDevice>> {
+        self.device.take()
+    }
+
+    pub fn has_device(&self) -> bool {
+        self.device.is_some()
+    }
+
+    // Called with every byte this side sends, whoever clocks the transfer and whether or not
+    // anything is plugged in. It isn't part of save states.
+    pub fn set_callback(&mut self, callback: Option<SerialCallback>) {
+        self.callback = callback;
+    }
+
+    pub fn take_callback(&mut self) -> Option<SerialCallback> {
+        self.callback.take()
+    }
+
+    fn sent(&mut self, byte: u8) {
+        if let Some(callback) = self.callback.as_mut() {
+            callback(byte);
+        }
+    }
+
+    // Gives the device a chance to run a transfer on the external clock.
+    pub fn poll_device(&mut self) {
+        let Some(device) = &mut self.device else {
+            return;
+        };
+        let waiting = self.control & (TRANSFER_START | INTERNAL_CLOCK) == TRANSFER_START;
+        let received = device.clock(waiting.then_some(self.data));
+        if let (true, Some(received)) = (waiting, received) {
+            self.sent(self.data);
+            self.data = received;
+            self.control &= !TRANSFER_START;
+            self.interrupts |= SERIAL_INTERRUPT;
+        }
+    }
+
+    // Returns and clears the serial interrupt requested since the last call.
+    pub fn take_interrupts(&mut self) -> u8 {
+        std::mem::take(&mut self.interrupts)
+    }
+
+    pub fn read(&self, address: u16) -> u8 {
+        match address {
+            0xFF01 => self.data,
+            0xFF02 if self.cgb => self.control | 0x7C,
+            0xFF02 => self.control | 0x7E,
+            _ => 0xFF,
+        }
+    }
+
+    pub fn write(&mut self, address: u16, value: u8) {
+        match address {
+            0xFF01 => self.data = value,
+            0xFF02 if self.cgb => self.control = value & 0x83,
+            0xFF02 => self.control = value & 0x81,
+            _ => {}
+        }
+    }
+
+    // The T-cycles a transfer started with the internal clock takes, or None if no transfer is
+    // running on this side of the cable.
+    pub fn transfer_cycles(&self) -> Option<u64> {
+        if self.control & (TRANSFER_START | INTERNAL_CLOCK) != TRANSFER_START | INTERNAL_CLOCK {
+            return None;
+        }
+
+        // 8192 Hz, or 262144 Hz with the CGB fast clock, for 8 bits.
+        let cycles_per_bit = if self.control & FAST_CLOCK != 0 {
+            16
+        } else {
+            512
+        };
+        Some(8 * cycles_per_bit)
+    }
+
+    pub fn complete_transfer(&mut self) {
+        self.sent(self.data);
+        self.data = match &mut self.device {
+            Some(device) => device.transfer(self.data),
+            None => 0xFF,
+        };
+        self.control &= !TRANSFER_START;
+        self.interrupts |= SERIAL_INTERRUPT;
+    }
+}
+
+impl Snapshot for Serial {
+    fn component(&self) -> &'static str {
+        "link port"
+    }
+
+    fn version(&self) -> u8 {
+        1
+    }
+
+    fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_u8(self.data);
+        writer.write_u8(self.control);
+        writer.write_u8(self.interrupts);
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader) -> Result<()> {
+        self.data = reader.read_u8()?;
+        self.control = reader.read_u8()?;
+        self.interrupts = reader.read_u8()?;
+        Ok(())
+    }
+}
+
+const TRANSFER_START: u8 = 0x80;
+
+const FAST_CLOCK: u8 = 0x02;
+
+const INTERNAL_CLOCK: u8 = 0x01;
+
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    use rstest::rstest;
+
+    use super::*;
+
+    #[rstest]
+    #[case(false, 0x81, Some(4096))]
+    #[case(false, 0x83, Some(4096))]
+    #[case(true, 0x83, Some(128))]
+    #[case(false, 0x80, None)]
+    #[case(false, 0x01, None)]
+    fn test_transfer_cycles(#[case] cgb: bool, #[case] control: u8, #[case] expected: Option<u64>) {
+        let mut serial = Serial::new(cgb);
+
+        serial.write(0xFF02, control);
+
+        assert_eq!(serial.transfer_cycles(), expected);
+    }
+
+    #[test]
+    fn test_complete_transfer() {
+        // Arrange
+        let mut serial = Serial::new(false);
+        serial.write(0xFF01, 0x42);
+        serial.write(0xFF02, 0x81);
+
+        // Act
+        serial.complete_transfer();
+
+        // Assert
+        assert_eq!(serial.read(0xFF01), 0xFF);
+        assert_eq!(serial.read(0xFF02), 0x7F);
+        assert_eq!(serial.take_interrupts(), SERIAL_INTERRUPT);
+    }
+
+    struct Echo;
+
+    impl SerialDevice for Echo {
+        fn transfer(&mut self, byte: u8) -> u8 {
+            byte.wrapping_add(1)
+        }
+
+        fn clock(&mut self, byte: Option<u8>) -> Option<u8> {
+            Some(byte.unwrap_or(0).wrapping_add(1))
+        }
+    }
+
+    #[test]
+    fn test_complete_transfer_with_device() {
+        // Arrange
+        let mut serial = Serial::new(false);
+        serial.set_device(Some(Box::new(Echo)));
+        serial.write(0xFF01, 0x42);
+        serial.write(0xFF02, 0x81);
+
+        // Act
+        serial.complete_transfer();
+
+        // Assert
+        assert_eq!(serial.read(0xFF01), 0x43);
+    }
+
+    #[rstest]
+    #[case(0x80, 0x43, 0x7E, SERIAL_INTERRUPT)]
+    #[case(0x81, 0x42, 0xFF, 0)]
+    #[case(0x00, 0x42, 0x7E, 0)]
+    fn test_poll_device(
+        #[case] control: u8,
+        #[case] expected_data: u8,
+        #[case] expected_control: u8,
+        #[case] expected_interrupts: u8,
+    ) {
+        // Arrange
+        let mut serial = Serial::new(false);
+        serial.set_device(Some(Box::new(Echo)));
+        serial.write(0xFF01, 0x42);
+        serial.write(0xFF02, control);
+
+        // Act
+        serial.poll_device();
+
+        // Assert
+        assert_eq!(serial.read(0xFF01), expected_data);
+        assert_eq!(serial.read(0xFF02), expected_control);
+        assert_eq!(serial.take_interrupts(), expected_interrupts);
+    }
+
+    #[test]
+    fn test_callback_sees_sent_bytes() {
+        // Arrange
+        let sent = Rc::new(RefCell::new(Vec::new()));
+        let log = sent.clone();
+        let mut serial = Serial::new(false);
+        serial.set_device(Some(Box::new(Echo)));
+        serial.set_callback(Some(Box::new(move |byte| log.borrow_mut().push(byte))));
+
+        // Act
+        serial.write(0xFF01, 0x42);
+        serial.write(0xFF02, 0x81);
+        serial.complete_transfer();
+        serial.write(0xFF02, 0x80);
+        serial.poll_device();
+
+        // Assert
+        assert_eq!(*sent.borrow(), [0x42, 0x43]);
+    }
+
+    #[test]
+    fn test_serial_output() {
+        let mut out = Vec::new();
+
+        let received = SerialOutput::new(&mut out).transfer(b'P');
+
+        assert_eq!(received, 0xFF);
+        assert_eq!(out, b"P");
+    }
+}
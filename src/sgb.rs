@@ -0,0 +1,838 @@
+use std::io::{Error, Result};
+
+use crate::ppu::{rgba, SCREEN_HEIGHT, SCREEN_WIDTH};
+use crate::savestate::{Snapshot, StateReader, StateWriter};
+
+// What the screen shows instead of the game while a game sets up its colors, from MASK_EN.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Mask {
+    Off,
+    // Keeps showing the last frame.
+    Freeze,
+    Black,
+    // Fills the screen with color 0.
+    Color0,
+}
+
+impl Mask {
+    fn from_u8(value: u8) -> Mask {
+        match value & 0x03 {
+            0 => Mask::Off,
+            1 => Mask::Freeze,
+            2 => Mask::Black,
+            _ => Mask::Color0,
+        }
+    }
+}
+
+// Data a game sends by putting it on screen as tiles, which the SGB reads from the next frame.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Transfer {
+    Palettes,
+    // The lower or upper 128 border tiles.
+    Tiles(bool),
+    Border,
+    Attributes,
+}
+
+impl Transfer {
+    fn to_u8(self) -> u8 {
+        match self {
+            Transfer::Palettes => 1,
+            Transfer::Tiles(false) => 2,
+            Transfer::Tiles(true) => 3,
+            Transfer::Border => 4,
+            Transfer::Attributes => 5,
+        }
+    }
+
+    fn from_u8(value: u8) -> Result<Option<Transfer>> {
+        Ok(Some(match value {
+            0 => return Ok(None),
+            1 => Transfer::Palettes,
+            2 => Transfer::Tiles(false),
+            3 => Transfer::Tiles(true),
+            4 => Transfer::Border,
+            5 => Transfer::Attributes,
+            _ => {
+                return Err(Error::other(format!(
+                    "{} is an invalid SGB transfer in the save state.",
+                    value
+                )))
+            }
+        }))
+    }
+}
+
+// The Super Game Boy's side of a game that supports it. Games send it 16-byte command packets a
+// bit at a time by pulsing P14 and P15, and it colors the screen with four palettes picked per
+// 8x8 cell and draws a border around it. Sound and SNES code commands are ignored.
+pub struct Sgb {
+    // P1's select bits as last written.
+    select: u8,
+    // The next bit of the packet being received, or None between packets.
+    bit: Option<usize>,
+    packet: [u8; PACKET_SIZE],
+    // The packets of the command so far. The first byte says how many there are.
+    command: Vec<u8>,
+    // MLT_REQ: how many controllers the game reads, and whose turn it is.
+    players: u8,
+    player: u8,
+    // Color 0 is shared by all four.
+    palettes: [[u16; 4]; 4],
+    // 512 palettes of 4 colors from PAL_TRN, which PAL_SET picks from.
+    system_palettes: Vec<u8>,
+    // The palette of each 8x8 cell of the screen.
+    attributes: Vec<u8>,
+    // 45 files from ATTR_TRN of 90 bytes each, 4 cells to a byte.
+    attribute_files: Vec<u8>,
+    mask: Mask,
+    transfer: Option<Transfer>,
+    // Set while the frame that was being drawn when a transfer was asked for finishes.
+    transfer_waiting: bool,
+    // 256 tiles of 4 bits a pixel in the SNES layout.
+    border_tiles: Vec<u8>,
+    // From PCT_TRN: a 32x32 map of tiles and at BORDER_PALETTES, palettes 4-7 of 16 colors.
+    border: Vec<u8>,
+    screen: Vec<u8>,
+}
+
+impl Default for Sgb {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Sgb {
+    pub fn new() -> Sgb {
+        let mut sgb = Sgb {
+            select: 0x30,
+            bit: None,
+            packet: [0; PACKET_SIZE],
+            command: Vec::new(),
+            players: 1,
+            player: 0,
+            palettes: [DEFAULT_PALETTE; 4],
+            system_palettes: vec![0; SYSTEM_PALETTES_SIZE],
+            attributes: vec![0; CELLS_WIDE * CELLS_HIGH],
+            attribute_files: vec![0; ATTRIBUTE_FILES_SIZE],
+            mask: Mask::Off,
+            transfer: None,
+            transfer_waiting: false,
+            border_tiles: vec![0; BORDER_TILES_SIZE],
+            border: vec![0; TRANSFER_SIZE],
+            screen: Vec::new(),
+        };
+        sgb.fill_screen(rgba(DEFAULT_PALETTE[0]));
+        sgb
+    }
+
+    pub fn mask(&self) -> Mask {
+        self.mask
+    }
+
+    pub fn players(&self) -> u8 {
+        self.players
+    }
+
+    // 160x144 pixels, 4 bytes (RGBA) per pixel, colored as of the last finished frame.
+    pub fn screen(&self) -> &[u8] {
+        &self.screen
+    }
+
+    // The screen in the middle of the border, 256x224 pixels of RGBA. Transparent border pixels
+    // show color 0.
+    pub fn bordered_screen(&self) -> Vec<u8> {
+        let mut frame = rgba(self.palettes[0][0]).repeat(BORDER_WIDTH * BORDER_HEIGHT);
+        for (y, row) in self.screen.chunks_exact(SCREEN_WIDTH * 4).enumerate() {
+            let offset = ((SCREEN_Y + y) * BORDER_WIDTH + SCREEN_X) * 4;
+            frame[offset..offset + row.len()].copy_from_slice(row);
+        }
+
+        for (index, entry) in self.border[..BORDER_MAP_SIZE].chunks_exact(2).enumerate() {
+            let entry = u16::from_le_bytes([entry[0], entry[1]]);
+            let tile = &self.border_tiles[(entry & 0xFF) as usize * BORDER_TILE_SIZE..];
+            let palette = BORDER_PALETTES + ((entry >> 10) & 0x03) as usize * 32;
+            let (tile_x, tile_y) = (index % 32, index / 32);
+            for row in 0..8 {
+                let line = if entry & 0x8000 != 0 { 7 - row } else { row };
+                let planes = [
+                    tile[line * 2],
+                    tile[line * 2 + 1],
+                    tile[16 + line * 2],
+                    tile[17 + line * 2],
+                ];
+                for column in 0..8 {
+                    let bit = if entry & 0x4000 != 0 {
+                        column
+                    } else {
+                        7 - column
+                    };
+                    let color = planes.iter().enumerate().fold(0, |color, (plane, byte)| {
+                        color | (((byte >> bit) & 1) as usize) << plane
+                    });
+                    if color == 0 {
+                        continue;
+                    }
+                    let color = &self.border[palette + color * 2..];
+                    let color = u16::from_le_bytes([color[0], color[1]]);
+                    let offset = ((tile_y * 8 + row) * BORDER_WIDTH + tile_x * 8 + column) * 4;
+                    frame[offset..offset + 4].copy_from_slice(&rgba(color));
+                }
+            }
+        }
+        frame
+    }
+
+    // Watches writes to P1 for packets and MLT_REQ's player changes.
+    pub fn write_joypad(&mut self, value: u8) {
+        let select = value & 0x30;
+        let previous = std::mem::replace(&mut self.select, select);
+        if select == 0 {
+            // Both lines low starts a packet.
+            self.bit = Some(0);
+            self.packet = [0; PACKET_SIZE];
+            return;
+        }
+        if select == 0x30 {
+            if previous & 0x20 == 0 {
+                self.player = (self.player + 1) % self.players;
+            }
+            return;
+        }
+        // A pulse only counts after both lines went back high.
+        let Some(bit) = self.bit.filter(|_| previous == 0x30) else {
+            return;
+        };
+        // P15 low sends a 1 and P14 low a 0, lowest bit first.
+        if select == 0x10 {
+            self.packet[bit / 8] |= 1 << (bit % 8);
+        }
+        if bit + 1 < PACKET_SIZE * 8 {
+            self.bit = Some(bit + 1);
+        } else {
+            self.bit = None;
+            self.receive_packet();
+        }
+    }
+
+    // P1 as the game sees it: the player's ID when nothing is selected with more than one player,
+    // and no buttons held on the controllers besides the first.
+    pub fn read_joypad(&self, value: u8) -> u8 {
+        if self.players > 1 && self.select == 0x30 {
+            (value & 0xF0) | (0x0F - self.player)
+        } else if self.player != 0 {
+            value | 0x0F
+        } else {
+            value
+        }
+    }
+
+    // Takes the data of a transfer from the finished frame's shades (0-3 a pixel) and colors it.
+    pub fn end_frame(&mut self, shades: &[u8]) {
+        if let Some(transfer) = self.transfer {
+            if self.transfer_waiting {
+                self.transfer_waiting = false;
+            } else {
+                self.transfer = None;
+                self.receive_transfer(transfer, &transfer_data(shades));
+            }
+        }
+
+        match self.mask {
+            Mask::Off => {
+                for (pixel, (index, &shade)) in self
+                    .screen
+                    .chunks_exact_mut(4)
+                    .zip(shades.iter().enumerate())
+                {
+                    let cell = index / SCREEN_WIDTH / 8 * CELLS_WIDE + index % SCREEN_WIDTH / 8;
+                    let palette = self.palettes[self.attributes[cell] as usize];
+                    pixel.copy_from_slice(&rgba(palette[shade as usize & 0x03]));
+                }
+            }
+            Mask::Freeze => {}
+            Mask::Black => self.fill_screen([0x00, 0x00, 0x00, 0xFF]),
+            Mask::Color0 => self.fill_screen(rgba(self.palettes[0][0])),
+        }
+    }
+
+    fn fill_screen(&mut self, color: [u8; 4]) {
+        self.screen = color.repeat(SCREEN_WIDTH * SCREEN_HEIGHT);
+    }
+
+    fn receive_packet(&mut self) {
+        self.command.extend_from_slice(&self.packet);
+        let packets = (self.command[0] & 0x07).max(1) as usize;
+        if self.command.len() >= packets * PACKET_SIZE {
+            let command = std::mem::take(&mut self.command);
+            self.run(&command);
+        }
+    }
+
+    fn run(&mut self, data: &[u8]) {
+        match data[0] >> 3 {
+            PAL01 => self.set_palettes(0, 1, data),
+            PAL23 => self.set_palettes(2, 3, data),
+            PAL03 => self.set_palettes(0, 3, data),
+            PAL12 => self.set_palettes(1, 2, data),
+            ATTR_BLK => self.attribute_blocks(data),
+            ATTR_LIN => self.attribute_lines(data),
+            ATTR_DIV => self.attribute_division(data),
+            ATTR_CHR => self.attribute_cells(data),
+            PAL_SET => {
+                for (index, number) in data[1..9].chunks_exact(2).enumerate() {
+                    let number = u16::from_le_bytes([number[0], number[1]]) as usize & 0x1FF;
+                    self.palettes[index] = system_palette(&self.system_palettes, number);
+                }
+                self.share_color0(self.palettes[0][0]);
+                if data[9] & 0x80 != 0 {
+                    self.set_attributes(data[9]);
+                } else if data[9] & 0x40 != 0 {
+                    self.mask = Mask::Off;
+                }
+            }
+            PAL_TRN => self.start_transfer(Transfer::Palettes),
+            MLT_REQ => {
+                self.players = match data[1] & 0x03 {
+                    1 => 2,
+                    3 => 4,
+                    _ => 1,
+                };
+                self.player = 0;
+            }
+            CHR_TRN => self.start_transfer(Transfer::Tiles(data[1] & 0x01 != 0)),
+            PCT_TRN => self.start_transfer(Transfer::Border),
+            ATTR_TRN => self.start_transfer(Transfer::Attributes),
+            ATTR_SET => self.set_attributes(data[1]),
+            MASK_EN => self.mask = Mask::from_u8(data[1]),
+            _ => {}
+        }
+    }
+
+    // Colors 1-3 of palettes `first` and `second`, and color 0 for all of them.
+    fn set_palettes(&mut self, first: usize, second: usize, data: &[u8]) {
+        let colors: Vec<u16> = data[1..15]
+            .chunks_exact(2)
+            .map(|color| u16::from_le_bytes([color[0], color[1]]))
+            .collect();
+        self.palettes[first][1..].copy_from_slice(&colors[1..4]);
+        self.palettes[second][1..].copy_from_slice(&colors[4..7]);
+        self.share_color0(colors[0]);
+    }
+
+    fn share_color0(&mut self, color: u16) {
+        for palette in &mut self.palettes {
+            palette[0] = color;
+        }
+    }
+
+    // Data sets of 6 bytes: which areas to color, their palettes and the block's corners in cells.
+    fn attribute_blocks(&mut self, data: &[u8]) {
+        let count = data[1] as usize;
+        for set in data[2..].chunks_exact(6).take(count) {
+            let (control, palettes) = (set[0], set[1]);
+            let (inside, border, outside) = (
+                palettes & 0x03,
+                (palettes >> 2) & 0x03,
+                (palettes >> 4) & 0x03,
+            );
+            // With only the inside or the outside colored, the border goes with it.
+            let border = match control & 0x07 {
+                0x01 => Some(inside),
+                0x04 => Some(outside),
+                control if control & 0x02 != 0 => Some(border),
+                _ => None,
+            };
+            let (left, top, right, bottom) = (set[2] as usize, set[3] as usize, set[4], set[5]);
+            let (right, bottom) = (right as usize, bottom as usize);
+            for y in 0..CELLS_HIGH {
+                for x in 0..CELLS_WIDE {
+                    let palette = if x > left && x < right && y > top && y < bottom {
+                        (control & 0x01 != 0).then_some(inside)
+                    } else if (left..=right).contains(&x) && (top..=bottom).contains(&y) {
+                        border
+                    } else {
+                        (control & 0x04 != 0).then_some(outside)
+                    };
+                    if let Some(palette) = palette {
+                        self.set_attribute(x, y, palette);
+                    }
+                }
+            }
+        }
+    }
+
+    // A byte per line: its number, palette, and whether it's a row rather than a column.
+    fn attribute_lines(&mut self, data: &[u8]) {
+        let count = data[1] as usize;
+        for &line in data[2..].iter().take(count) {
+            let (number, palette) = ((line & 0x1F) as usize, (line >> 5) & 0x03);
+            if line & 0x80 != 0 {
+                (0..CELLS_WIDE).for_each(|x| self.set_attribute(x, number, palette));
+            } else {
+                (0..CELLS_HIGH).for_each(|y| self.set_attribute(number, y, palette));
+            }
+        }
+    }
+
+    // Splits the screen in two at a row or column, which gets a palette of its own.
+    fn attribute_division(&mut self, data: &[u8]) {
+        let (control, line) = (data[1], data[2] as usize);
+        let (after, before, on) = (control & 0x03, (control >> 2) & 0x03, (control >> 4) & 0x03);
+        for y in 0..CELLS_HIGH {
+            for x in 0..CELLS_WIDE {
+                let position = if control & 0x40 != 0 { y } else { x };
+                let palette = match position.cmp(&line) {
+                    std::cmp::Ordering::Less => before,
+                    std::cmp::Ordering::Equal => on,
+                    std::cmp::Ordering::Greater => after,
+                };
+                self.set_attribute(x, y, palette);
+            }
+        }
+    }
+
+    // A palette for each cell from a starting one on, 4 to a byte, across rows or down columns.
+    fn attribute_cells(&mut self, data: &[u8]) {
+        let (mut x, mut y) = (data[1] as usize, data[2] as usize);
+        let count = (u16::from_le_bytes([data[3], data[4]]) as usize).min(CELLS_WIDE * CELLS_HIGH);
+        let vertical = data[5] & 0x01 != 0;
+        for (index, byte) in (0..count).zip(data[6..].iter().flat_map(|&byte| [byte; 4])) {
+            if x >= CELLS_WIDE || y >= CELLS_HIGH {
+                break;
+            }
+            self.set_attribute(x, y, (byte >> (6 - index % 4 * 2)) & 0x03);
+            if vertical {
+                y += 1;
+                if y == CELLS_HIGH {
+                    (x, y) = (x + 1, 0);
+                }
+            } else {
+                x += 1;
+                if x == CELLS_WIDE {
+                    (x, y) = (0, y + 1);
+                }
+            }
+        }
+    }
+
+    // ATTR_SET and PAL_SET: an attribute file in bits 0-5, and bit 6 to turn the mask off.
+    fn set_attributes(&mut self, value: u8) {
+        let file = (value & 0x3F) as usize;
+        if file < ATTRIBUTE_FILES {
+            let file = &self.attribute_files[file * ATTRIBUTE_FILE_SIZE..][..ATTRIBUTE_FILE_SIZE];
+            for (index, attribute) in self.attributes.iter_mut().enumerate() {
+                *attribute = (file[index / 4] >> (6 - index % 4 * 2)) & 0x03;
+            }
+        }
+        if value & 0x40 != 0 {
+            self.mask = Mask::Off;
+        }
+    }
+
+    fn set_attribute(&mut self, x: usize, y: usize, palette: u8) {
+        if x < CELLS_WIDE && y < CELLS_HIGH {
+            self.attributes[y * CELLS_WIDE + x] = palette;
+        }
+    }
+
+    fn start_transfer(&mut self, transfer: Transfer) {
+        self.transfer = Some(transfer);
+        self.transfer_waiting = true;
+    }
+
+    fn receive_transfer(&mut self, transfer: Transfer, data: &[u8]) {
+        match transfer {
+            Transfer::Palettes => self.system_palettes.copy_from_slice(data),
+            Transfer::Tiles(upper) => {
+                let offset = if upper { TRANSFER_SIZE } else { 0 };
+                self.border_tiles[offset..offset + TRANSFER_SIZE].copy_from_slice(data);
+            }
+            Transfer::Border => self.border.copy_from_slice(data),
+            Transfer::Attributes => self
+                .attribute_files
+                .copy_from_slice(&data[..ATTRIBUTE_FILES_SIZE]),
+        }
+    }
+}
+
+impl Snapshot for Sgb {
+    fn component(&self) -> &'static str {
+        "SGB"
+    }
+
+    fn version(&self) -> u8 {
+        1
+    }
+
+    fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_u8(self.select);
+        writer.write_u8(self.bit.map_or(0xFF, |bit| bit as u8));
+        writer.write_bytes(&self.packet);
+        writer.write_bytes(&self.command);
+        writer.write_u8(self.players);
+        writer.write_u8(self.player);
+        for color in self.palettes.iter().flatten() {
+            writer.write_u16(*color);
+        }
+        writer.write_bytes(&self.system_palettes);
+        writer.write_bytes(&self.attributes);
+        writer.write_bytes(&self.attribute_files);
+        writer.write_u8(self.mask as u8);
+        writer.write_u8(self.transfer.map_or(0, Transfer::to_u8));
+        writer.write_bool(self.transfer_waiting);
+        writer.write_bytes(&self.border_tiles);
+        writer.write_bytes(&self.border);
+        writer.write_bytes(&self.screen);
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader) -> Result<()> {
+        self.select = reader.read_u8()? & 0x30;
+        let bit = reader.read_u8()? as usize;
+        self.bit = (bit < PACKET_SIZE * 8).then_some(bit);
+        reader.read_bytes_into(&mut self.packet)?;
+        self.command = reader.read_bytes()?.to_vec();
+        self.command.truncate(MAX_COMMAND_SIZE);
+        self.players = match reader.read_u8()? {
+            players @ (2 | 4) => players,
+            _ => 1,
+        };
+        self.player = reader.read_u8()? % self.players;
+        for color in self.palettes.iter_mut().flatten() {
+            *color = reader.read_u16()?;
+        }
+        reader.read_bytes_into(&mut self.system_palettes)?;
+        reader.read_bytes_into(&mut self.attributes)?;
+        for attribute in &mut self.attributes {
+            *attribute &= 0x03;
+        }
+        reader.read_bytes_into(&mut self.attribute_files)?;
+        self.mask = Mask::from_u8(reader.read_u8()?);
+        self.transfer = Transfer::from_u8(reader.read_u8()?)?;
+        self.transfer_waiting = reader.read_bool()?;
+        reader.read_bytes_into(&mut self.border_tiles)?;
+        reader.read_bytes_into(&mut self.border)?;
+        reader.read_bytes_into(&mut self.screen)?;
+        Ok(())
+    }
+}
+
+fn system_palette(system_palettes: &[u8], number: usize) -> [u16; 4] {
+    let bytes = &system_palettes[number * 8..number * 8 + 8];
+    let mut palette = [0; 4];
+    for (color, bytes) in palette.iter_mut().zip(bytes.chunks_exact(2)) {
+        *color = u16::from_le_bytes([bytes[0], bytes[1]]);
+    }
+    palette
+}
+
+// The first 256 tiles on screen, 20 to a row, back in the Game Boy's 2 bits a pixel.
+fn transfer_data(shades: &[u8]) -> Vec<u8> {
+    let mut data = vec![0; TRANSFER_SIZE];
+    for (tile, bytes) in data.chunks_exact_mut(16).enumerate() {
+        let (tile_x, tile_y) = (tile % CELLS_WIDE, tile / CELLS_WIDE);
+        for row in 0..8 {
+            for column in 0..8 {
+                let shade = shades[(tile_y * 8 + row) * SCREEN_WIDTH + tile_x * 8 + column];
+                let bit = 0x80 >> column;
+                if shade & 0x01 != 0 {
+                    bytes[row * 2] |= bit;
+                }
+                if shade & 0x02 != 0 {
+                    bytes[row * 2 + 1] |= bit;
+                }
+            }
+        }
+    }
+    data
+}
+
+pub const BORDER_WIDTH: usize = 256;
+
+pub const BORDER_HEIGHT: usize = 224;
+
+// Where the Game Boy's screen sits in the border.
+const SCREEN_X: usize = 48;
+
+const SCREEN_Y: usize = 40;
+
+const PACKET_SIZE: usize = 16;
+
+// A command is at most 7 packets.
+const MAX_COMMAND_SIZE: usize = PACKET_SIZE * 7;
+
+const CELLS_WIDE: usize = SCREEN_WIDTH / 8;
+
+const CELLS_HIGH: usize = SCREEN_HEIGHT / 8;
+
+const TRANSFER_SIZE: usize = 0x1000;
+
+const SYSTEM_PALETTES_SIZE: usize = 512 * 8;
+
+const ATTRIBUTE_FILES: usize = 45;
+
+const ATTRIBUTE_FILE_SIZE: usize = 90;
+
+const ATTRIBUTE_FILES_SIZE: usize = ATTRIBUTE_FILES * ATTRIBUTE_FILE_SIZE;
+
+const BORDER_TILE_SIZE: usize = 32;
+
+const BORDER_TILES_SIZE: usize = 256 * BORDER_TILE_SIZE;
+
+// 32x28 tiles of the 32x32 map are on screen.
+const BORDER_MAP_SIZE: usize = 32 * 28 * 2;
+
+const BORDER_PALETTES: usize = 0x800;
+
+// The colors the SGB starts with before a game picks its own.
+const DEFAULT_PALETTE: [u16; 4] = [0x67BF, 0x265B, 0x10B5, 0x2866];
+
+const PAL01: u8 = 0x00;
+
+const PAL23: u8 = 0x01;
+
+const PAL03: u8 = 0x02;
+
+const PAL12: u8 = 0x03;
+
+const ATTR_BLK: u8 = 0x04;
+
+const ATTR_LIN: u8 = 0x05;
+
+const ATTR_DIV: u8 = 0x06;
+
+const ATTR_CHR: u8 = 0x07;
+
+const PAL_SET: u8 = 0x0A;
+
+const PAL_TRN: u8 = 0x0B;
+
+const MLT_REQ: u8 = 0x11;
+
+const CHR_TRN: u8 = 0x13;
+
+const PCT_TRN: u8 = 0x14;
+
+const ATTR_TRN: u8 = 0x15;
+
+const ATTR_SET: u8 = 0x16;
+
+const MASK_EN: u8 = 0x17;
+
+#[cfg(test)]
+mod tests {
+    use rstest::rstest;
+
+    use super::*;
+
+    // Sends a command the way games do: a reset pulse, a pulse per bit and a 0 to end each packet.
+    fn send(sgb: &mut Sgb, command: &[u8]) {
+        for packet in command.chunks(PACKET_SIZE) {
+            sgb.write_joypad(0x00);
+            sgb.write_joypad(0x30);
+            for index in 0..PACKET_SIZE * 8 {
+                let byte = packet.get(index / 8).copied().unwrap_or(0);
+                let bit = (byte >> (index % 8)) & 1;
+                sgb.write_joypad(if bit != 0 { 0x10 } else { 0x20 });
+                sgb.write_joypad(0x30);
+            }
+            sgb.write_joypad(0x20);
+            sgb.write_joypad(0x30);
+        }
+    }
+
+    fn attribute(sgb: &Sgb, x: usize, y: usize) -> u8 {
+        sgb.attributes[y * CELLS_WIDE + x]
+    }
+
+    #[test]
+    fn test_pal01() {
+        // Arrange
+        let mut sgb = Sgb::new();
+        let mut command = vec![(PAL01 << 3) | 1];
+        for color in [0x0001u16, 0x0002, 0x0003, 0x0004, 0x0005, 0x0006, 0x0007] {
+            command.extend_from_slice(&color.to_le_bytes());
+        }
+
+        // Act
+        send(&mut sgb, &command);
+
+        // Assert
+        assert_eq!(sgb.palettes[0], [0x0001, 0x0002, 0x0003, 0x0004]);
+        assert_eq!(sgb.palettes[1], [0x0001, 0x0005, 0x0006, 0x0007]);
+        assert_eq!(sgb.palettes[2], [0x0001, 0x265B, 0x10B5, 0x2866]);
+    }
+
+    #[test]
+    fn test_pulses_need_both_lines_high_in_between() {
+        let mut sgb = Sgb::new();
+        sgb.write_joypad(0x00);
+        sgb.write_joypad(0x30);
+
+        sgb.write_joypad(0x10);
+        sgb.write_joypad(0x10);
+
+        assert_eq!(sgb.bit, Some(1));
+    }
+
+    #[test]
+    fn test_attr_blk() {
+        // Arrange
+        let mut sgb = Sgb::new();
+        // Palette 1 inside and on the border of cells 2-5 across and 3-6 down, 2 outside.
+        let command = [(ATTR_BLK << 3) | 1, 1, 0x07, 0x25, 2, 3, 5, 6];
+
+        // Act
+        send(&mut sgb, &command);
+
+        // Assert
+        assert_eq!(attribute(&sgb, 3, 4), 1);
+        assert_eq!(attribute(&sgb, 2, 3), 1);
+        assert_eq!(attribute(&sgb, 5, 6), 1);
+        assert_eq!(attribute(&sgb, 6, 6), 2);
+        assert_eq!(attribute(&sgb, 0, 0), 2);
+    }
+
+    #[rstest]
+    #[case(0x01, 3)]
+    #[case(0x04, 0)]
+    fn test_attr_blk_border_follows_only_area(#[case] control: u8, #[case] expected: u8) {
+        let mut sgb = Sgb::new();
+
+        send(
+            &mut sgb,
+            &[(ATTR_BLK << 3) | 1, 1, control, 0x03, 2, 3, 5, 6],
+        );
+
+        assert_eq!(attribute(&sgb, 2, 3), expected);
+    }
+
+    #[test]
+    fn test_attr_lin_and_div() {
+        let mut sgb = Sgb::new();
+
+        send(
+            &mut sgb,
+            &[(ATTR_DIV << 3) | 1, 0x40 | 0x20 | 0x04 | 0x03, 9],
+        );
+        send(&mut sgb, &[(ATTR_LIN << 3) | 1, 1, 0x80 | 2]);
+
+        assert_eq!(attribute(&sgb, 0, 0), 1);
+        assert_eq!(attribute(&sgb, 0, 2), 0);
+        assert_eq!(attribute(&sgb, 0, 9), 2);
+        assert_eq!(attribute(&sgb, 0, 17), 3);
+    }
+
+    #[test]
+    fn test_mlt_req() {
+        // Arrange
+        let mut sgb = Sgb::new();
+        send(&mut sgb, &[(MLT_REQ << 3) | 1, 0x01]);
+
+        // Act
+        let first = sgb.read_joypad(0xCF);
+        sgb.write_joypad(0x10);
+        sgb.write_joypad(0x30);
+        let second = sgb.read_joypad(0xCF);
+        sgb.write_joypad(0x20);
+        let buttons = sgb.read_joypad(0xEE);
+
+        // Assert
+        assert_eq!(sgb.players(), 2);
+        assert_eq!(first, 0xCF);
+        assert_eq!(second, 0xCE);
+        assert_eq!(buttons, 0xEF);
+    }
+
+    #[test]
+    fn test_pal_set_from_transferred_palettes() {
+        // Arrange
+        let mut sgb = Sgb::new();
+        send(&mut sgb, &[(PAL_TRN << 3) | 1]);
+        // Palette 2 is all of shade 1's tile bytes, 0xFF00.
+        let mut shades = vec![0; SCREEN_WIDTH * SCREEN_HEIGHT];
+        for y in 0..8 {
+            shades[y * SCREEN_WIDTH + 8..][..8].fill(1);
+        }
+
+        // Act
+        sgb.end_frame(&shades);
+        sgb.end_frame(&shades);
+        send(&mut sgb, &[(PAL_SET << 3) | 1, 2, 0, 0, 0, 0, 0, 0, 0]);
+
+        // Assert
+        assert_eq!(sgb.palettes[0], [0x00FF; 4]);
+        assert_eq!(sgb.palettes[1][1..], [0; 3]);
+    }
+
+    #[test]
+    fn test_colorizes_by_cell() {
+        // Arrange
+        let mut sgb = Sgb::new();
+        send(&mut sgb, &[(ATTR_LIN << 3) | 1, 1, 0x20 | 1]);
+        let mut command = vec![(PAL01 << 3) | 1, 0x00, 0x00];
+        for color in [0x7FFFu16, 0x0000, 0x0000, 0x001F, 0x0000, 0x0000] {
+            command.extend_from_slice(&color.to_le_bytes());
+        }
+        send(&mut sgb, &command);
+
+        // Act
+        sgb.end_frame(&vec![1; SCREEN_WIDTH * SCREEN_HEIGHT]);
+
+        // Assert
+        assert_eq!(sgb.screen()[..4], [0xFF, 0xFF, 0xFF, 0xFF]);
+        assert_eq!(sgb.screen()[8 * 4..8 * 4 + 4], [0xFF, 0x00, 0x00, 0xFF]);
+    }
+
+    #[test]
+    fn test_border() {
+        // Arrange
+        let mut sgb = Sgb::new();
+        // Tile 1's top left pixel is color 1 of palette 4.
+        sgb.border_tiles[BORDER_TILE_SIZE] = 0x80;
+        // The top left map entry is tile 1, flipped both ways.
+        sgb.border[..2].copy_from_slice(&0xC001u16.to_le_bytes());
+        sgb.border[BORDER_PALETTES + 2..][..2].copy_from_slice(&0x001Fu16.to_le_bytes());
+
+        // Act
+        let frame = sgb.bordered_screen();
+
+        // Assert
+        let pixel = |x: usize, y: usize| &frame[(y * BORDER_WIDTH + x) * 4..][..4];
+        assert_eq!(frame.len(), BORDER_WIDTH * BORDER_HEIGHT * 4);
+        assert_eq!(pixel(7, 7), [0xFF, 0x00, 0x00, 0xFF]);
+        assert_eq!(pixel(0, 0), rgba(DEFAULT_PALETTE[0]));
+        assert_eq!(pixel(SCREEN_X, SCREEN_Y), rgba(DEFAULT_PALETTE[0]));
+    }
+
+    #[test]
+    fn test_mask() {
+        let mut sgb = Sgb::new();
+
+        send(&mut sgb, &[(MASK_EN << 3) | 1, 2]);
+        sgb.end_frame(&vec![3; SCREEN_WIDTH * SCREEN_HEIGHT]);
+
+        assert_eq!(sgb.mask(), Mask::Black);
+        assert_eq!(sgb.screen()[..4], [0x00, 0x00, 0x00, 0xFF]);
+    }
+
+    #[test]
+    fn test_save_state() {
+        // Arrange
+        let mut sgb = Sgb::new();
+        send(&mut sgb, &[(MLT_REQ << 3) | 1, 0x03]);
+        send(&mut sgb, &[(ATTR_LIN << 3) | 1, 1, 0x80 | 0x60 | 4]);
+        let mut writer = StateWriter::new();
+        sgb.save_state(&mut writer);
+        let data = writer.into_bytes();
+
+        // Act
+        let mut loaded = Sgb::new();
+        loaded.load_state(&mut StateReader::new(&data)).unwrap();
+
+        // Assert
+        assert_eq!(loaded.players(), 4);
+        assert_eq!(loaded.attributes, sgb.attributes);
+    }
+}
@@ -0,0 +1,169 @@
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::io::{Error, ErrorKind, Result};
+use std::path::Path;
+
+use crate::emulator::Emulator;
+
+// Labels from a .sym file, the `BB:AAAA Name` lines RGBDS writes and wla-dx writes under
+// [labels]. Anything after a ; is a comment.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Symbols {
+    // By address, then bank.
+    labels: BTreeMap<(u16, usize), String>,
+    names: HashMap<String, (usize, u16)>,
+}
+
+impl Symbols {
+    pub fn load(path: &Path) -> Result<Symbols> {
+        Symbols::parse(&fs::read_to_string(path)?)
+            .map_err(|error| Error::other(format!("{}: {}", path.display(), error)))
+    }
+
+    // The .sym file next to the ROM with the same name, if there is one.
+    pub fn for_rom(rom_path: &Path) -> Result<Option<Symbols>> {
+        match Symbols::load(&rom_path.with_extension("sym")) {
+            Ok(symbols) => Ok(Some(symbols)),
+            Err(error) if error.kind() == ErrorKind::NotFound => Ok(None),
+            Err(error) => Err(error),
+        }
+    }
+
+    pub fn parse(text: &str) -> Result<Symbols> {
+        let mut symbols = Symbols::default();
+        let mut section: Option<&str> = None;
+        for (number, line) in text.lines().enumerate() {
+            let line = line.split(';').next().unwrap_or_default().trim();
+            if line.is_empty() {
+                continue;
+            }
+            if let Some(name) = line.strip_prefix('[') {
+                section = Some(name.trim_end_matches(']'));
+                continue;
+            }
+            // wla-dx lists definitions and other things in sections of their own.
+            if section.is_some_and(|section| section != "labels") {
+                continue;
+            }
+
+            let parsed = line
+                .split_once(char::is_whitespace)
+                .and_then(|(location, name)| {
+                    let (bank, address) = location.split_once(':')?;
+                    let bank = usize::from_str_radix(bank, 16).ok()?;
+                    let address = u16::from_str_radix(address, 16).ok()?;
+                    Some((bank, address, name.trim()))
+                });
+            let Some((bank, address, name)) = parsed else {
+                return Err(Error::other(format!(
+                    "Line {} is \"{}\", expected a bank, an address and a name like 01:4000 Main.",
+                    number + 1,
+                    line
+                )));
+            };
+            symbols.insert(bank, address, name);
+        }
+        Ok(symbols)
+    }
+
+    pub fn insert(&mut self, bank: usize, address: u16, name: &str) {
+        self.labels
+            .entry((address, bank))
+            .or_insert_with(|| name.to_string());
+        self.names.insert(name.to_string(), (bank, address));
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.labels.is_empty()
+    }
+
+    // The label at `address` in `bank`. Without a bank, switchable ROM addresses only get a label
+    // when a single bank has one there. Other banked memory, like work RAM on the CGB, goes by the
+    // address alone.
+    pub fn label(&self, bank: Option<usize>, address: u16) -> Option<&str> {
+        let switchable = (0x4000..=0x7FFF).contains(&address);
+        if let (Some(bank), true) = (bank, switchable) {
+            return self.labels.get(&(address, bank)).map(String::as_str);
+        }
+        let mut labels = self.labels.range((address, 0)..=(address, usize::MAX));
+        let (_, first) = labels.next()?;
+        if switchable && labels.next().is_some() {
+            return None;
+        }
+        Some(first)
+    }
+
+    // The label at `address` with the ROM bank the emulator has mapped there now.
+    pub fn label_in(&self, emulator: &Emulator, address: u16) -> Option<&str> {
+        let bank = emulator.bus().mbc().rom_offset(address) / ROM_BANK_SIZE;
+        self.label(Some(bank), address)
+    }
+
+    // The bank and address of a label. Names are case sensitive, like in the assemblers.
+    pub fn get(&self, name: &str) -> Option<(usize, u16)> {
+        self.names.get(name).copied()
+    }
+}
+
+const ROM_BANK_SIZE: usize = 0x4000;
+
+#[cfg(test)]
+mod tests {
+    use rstest::rstest;
+
+    use super::*;
+
+    const RGBDS: &str = "; File generated by rgblink
+00:0150 Start
+00:0200 UpdateSprites
+01:4000 BankedA
+02:4000 BankedB
+02:4100 OnlyInTwo
+00:C000 wPlayerX ; comment
+";
+
+    #[rstest]
+    #[case(None, 0x0150, Some("Start"))]
+    #[case(Some(1), 0x4000, Some("BankedA"))]
+    #[case(Some(2), 0x4000, Some("BankedB"))]
+    #[case(None, 0x4000, None)]
+    #[case(None, 0x4100, Some("OnlyInTwo"))]
+    #[case(Some(1), 0x4100, None)]
+    #[case(Some(3), 0xC000, Some("wPlayerX"))]
+    #[case(None, 0x0151, None)]
+    fn test_label(
+        #[case] bank: Option<usize>,
+        #[case] address: u16,
+        #[case] expected: Option<&str>,
+    ) {
+        let symbols = Symbols::parse(RGBDS).unwrap();
+
+        assert_eq!(symbols.label(bank, address), expected);
+    }
+
+    #[test]
+    fn test_get() {
+        let symbols = Symbols::parse(RGBDS).unwrap();
+
+        assert_eq!(symbols.get("BankedB"), Some((2, 0x4000)));
+        assert_eq!(symbols.get("updatesprites"), None);
+    }
+
+    #[test]
+    fn test_parse_wla_dx() {
+        let text = "[information]\nversion 2\n\n[labels]\n0000:0150 main\n0001:4000 banked\n[definitions]\n00000010 _sizeof_thing\n";
+
+        let symbols = Symbols::parse(text).unwrap();
+
+        assert_eq!(symbols.get("main"), Some((0, 0x0150)));
+        assert_eq!(symbols.label(Some(1), 0x4000), Some("banked"));
+        assert_eq!(symbols.get("_sizeof_thing"), None);
+    }
+
+    #[test]
+    fn test_parse_invalid() {
+        assert!(Symbols::parse("00:0150").is_err());
+        assert!(Symbols::parse("0150 Start").is_err());
+        assert!(Symbols::parse("zz:0150 Start").is_err());
+    }
+}
@@ -0,0 +1,380 @@
+use std::io::Result;
+
+use crate::interrupts::TIMER_INTERRUPT;
+use crate::savestate::{Snapshot, StateReader, StateWriter};
+
+pub struct Timer {
+    // DIV is the upper byte of this counter, which advances every T-cycle.
+    counter: u16,
+    tima: u8,
+    tma: u8,
+    tac: u8,
+    // TIMA overflowed on the last M-cycle and reads as 0 until it is reloaded on the next one.
+    overflow: bool,
+    // TIMA was reloaded from TMA during the current M-cycle, so writes to TIMA are ignored.
+    reloading: bool,
+    interrupts: u8,
+}
+
+impl Default for Timer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Timer {
+    pub fn new() -> Timer {
+        Timer {
+            counter: POST_BOOT_COUNTER,
+            tima: 0,
+            tma: 0,
+            tac: 0,
+            overflow: false,
+            reloading: false,
+            interrupts: 0,
+        }
+    }
+
+    // Returns and clears the timer interrupt requested since the last call.
+    pub fn take_interrupts(&mut self) -> u8 {
+        std::mem::take(&mut self.interrupts)
+    }
+
+    pub fn read(&self, address: u16) -> u8 {
+        match address {
+            0xFF04 => (self.counter >> 8) as u8,
+            0xFF05 => self.tima,
+            0xFF06 => self.tma,
+            0xFF07 => self.tac | 0xF8,
+            _ => 0xFF,
+        }
+    }
+
+    pub fn write(&mut self, address: u16, value: u8) {
+        match address {
+            0xFF04 => {
+                let before = self.signal();
+                self.counter = 0;
+                self.detect_falling_edge(before);
+            }
+            // A write on the cycle TIMA is reloaded loses against TMA.
+            0xFF05 if self.reloading => {}
+            0xFF05 => {
+                // A write while the reload is pending cancels it.
+                self.tima = value;
+                self.overflow = false;
+            }
+            0xFF06 => {
+                self.tma = value;
+                if self.reloading {
+                    self.tima = value;
+                }
+            }
+            0xFF07 => {
+                // Disabling the timer or switching frequencies can drop the signal and tick TIMA.
+                let before = self.signal();
+                self.tac = value & 0x07;
+                self.detect_falling_edge(before);
+            }
+            _ => {}
+        }
+    }
+
+    pub fn tick(&mut self, cycles: u32) {
+        for _ in 0..cycles / 4 {
+            self.step();
+        }
+    }
+
+    fn step(&mut self) {
+        self.reloading = false;
+        if self.overflow {
+            self.overflow = false;
+            self.tima = self.tma;
+            self.interrupts |= TIMER_INTERRUPT;
+            self.reloading = true;
+        }
+
+        let before = self.signal();
+        self.counter = self.counter.wrapping_add(4);
+        self.detect_falling_edge(before);
+    }
+
+    // T-cycles until the timer requests its interrupt, or None while it is stopped.
+    pub fn cycles_until_interrupt(&self) -> Option<u64> {
+        if self.overflow {
+            return Some(4);
+        }
+        if self.tac & TAC_ENABLE == 0 {
+            return None;
+        }
+
+        // TIMA ticks each time the counter passes a multiple of twice the selected bit.
+        let period = 2u64 << self.selected_bit();
+        let first_tick = period - self.counter as u64 % period;
+        Some(first_tick + (0xFF - self.tima) as u64 * period + 4)
+    }
+
+    // The APU frame sequencer is clocked by the falling edge of DIV bit 4, or bit 5 in double
+    // speed mode.
+    pub fn div_apu_signal(&self, double_speed: bool) -> bool {
+        self.counter & (1 << div_apu_bit(double_speed)) != 0
+    }
+
+    pub fn cycles_until_div_apu_clock(&self, double_speed: bool) -> u64 {
+        let period = 2u64 << div_apu_bit(double_speed);
+        period - self.counter as u64 % period
+    }
+
+    fn selected_bit(&self) -> u32 {
+        match self.tac & 0x03 {
+            0 => 9,
+            1 => 3,
+            2 => 5,
+            _ => 7,
+        }
+    }
+
+    // TIMA is clocked by the falling edge of the selected counter bit ANDed with the enable bit.
+    fn signal(&self) -> bool {
+        self.tac & TAC_ENABLE != 0 && self.counter & (1 << self.selected_bit()) != 0
+    }
+
+    fn detect_falling_edge(&mut self, before: bool) {
+        if !before || self.signal() {
+            return;
+        }
+
+        let (tima, overflowed) = self.tima.overflowing_add(1);
+        self.tima = tima;
+        if overflowed {
+            self.overflow = true;
+        }
+    }
+}
+
+impl Snapshot for Timer {
+    fn component(&self) -> &'static str {
+        "timer"
+    }
+
+    fn version(&self) -> u8 {
+        1
+    }
+
+    fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_u16(self.counter);
+        writer.write_u8(self.tima);
+        writer.write_u8(self.tma);
+        writer.write_u8(self.tac);
+        writer.write_bool(self.overflow);
+        writer.write_bool(self.reloading);
+        writer.write_u8(self.interrupts);
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader) -> Result<()> {
+        self.counter = reader.read_u16()?;
+        self.tima = reader.read_u8()?;
+        self.tma = reader.read_u8()?;
+        self.tac = reader.read_u8()?;
+        self.overflow = reader.read_bool()?;
+        self.reloading = reader.read_bool()?;
+        self.interrupts = reader.read_u8()?;
+        Ok(())
+    }
+}
+
+fn div_apu_bit(double_speed: bool) -> u32 {
+    if double_speed {
+        13
+    } else {
+        12
+    }
+}
+
+const TAC_ENABLE: u8 = 0x04;
+
+// The DMG boot ROM leaves DIV at 0xAB.
+const POST_BOOT_COUNTER: u16 = 0xABCC;
+
+#[cfg(test)]
+mod tests {
+    use rstest::rstest;
+
+    use super::*;
+
+    fn timer() -> Timer {
+        let mut timer = Timer::new();
+        timer.write(0xFF04, 0);
+        timer
+    }
+
+    #[test]
+    fn test_div_increments_every_256_cycles() {
+        let mut timer = timer();
+
+        timer.tick(252);
+        assert_eq!(timer.read(0xFF04), 0x00);
+
+        timer.tick(4);
+        assert_eq!(timer.read(0xFF04), 0x01);
+    }
+
+    #[test]
+    fn test_div_write_resets_counter() {
+        let mut timer = timer();
+        timer.tick(1024);
+
+        timer.write(0xFF04, 0x12);
+
+        assert_eq!(timer.read(0xFF04), 0x00);
+    }
+
+    #[rstest]
+    #[case(0x04, 1024)]
+    #[case(0x05, 16)]
+    #[case(0x06, 64)]
+    #[case(0x07, 256)]
+    fn test_tima_frequency(#[case] tac: u8, #[case] period: u32) {
+        // Arrange
+        let mut timer = timer();
+        timer.write(0xFF07, tac);
+
+        // Act
+        timer.tick(period * 3);
+
+        // Assert
+        assert_eq!(timer.read(0xFF05), 3);
+    }
+
+    #[test]
+    fn test_tima_stopped_when_disabled() {
+        let mut timer = timer();
+        timer.write(0xFF07, 0x01);
+
+        timer.tick(1024);
+
+        assert_eq!(timer.read(0xFF05), 0);
+    }
+
+    #[test]
+    fn test_overflow_reloads_after_delay() {
+        // Arrange
+        let mut timer = timer();
+        timer.write(0xFF06, 0xAB);
+        timer.write(0xFF05, 0xFF);
+        timer.write(0xFF07, 0x05);
+
+        // Act + Assert
+        timer.tick(16);
+        assert_eq!(timer.read(0xFF05), 0x00);
+        assert_eq!(timer.take_interrupts(), 0);
+
+        timer.tick(4);
+        assert_eq!(timer.read(0xFF05), 0xAB);
+        assert_eq!(timer.take_interrupts(), TIMER_INTERRUPT);
+    }
+
+    #[test]
+    fn test_tima_write_cancels_pending_reload() {
+        let mut timer = timer();
+        timer.write(0xFF06, 0xAB);
+        timer.write(0xFF05, 0xFF);
+        timer.write(0xFF07, 0x05);
+        timer.tick(16);
+
+        timer.write(0xFF05, 0x42);
+        timer.tick(4);
+
+        assert_eq!(timer.read(0xFF05), 0x42);
+        assert_eq!(timer.take_interrupts(), 0);
+    }
+
+    #[test]
+    fn test_tima_write_ignored_on_reload_cycle() {
+        let mut timer = timer();
+        timer.write(0xFF06, 0xAB);
+        timer.write(0xFF05, 0xFF);
+        timer.write(0xFF07, 0x05);
+        timer.tick(20);
+
+        timer.write(0xFF05, 0x42);
+
+        assert_eq!(timer.read(0xFF05), 0xAB);
+    }
+
+    #[test]
+    fn test_tma_write_on_reload_cycle_reaches_tima() {
+        let mut timer = timer();
+        timer.write(0xFF05, 0xFF);
+        timer.write(0xFF07, 0x05);
+        timer.tick(20);
+
+        timer.write(0xFF06, 0x42);
+
+        assert_eq!(timer.read(0xFF05), 0x42);
+    }
+
+    #[test]
+    fn test_div_reset_falling_edge_increments_tima() {
+        let mut timer = timer();
+        timer.write(0xFF07, 0x05);
+        timer.tick(8);
+
+        timer.write(0xFF04, 0);
+
+        assert_eq!(timer.read(0xFF05), 1);
+    }
+
+    #[test]
+    fn test_tac_unused_bits_read_high() {
+        let mut timer = timer();
+
+        timer.write(0xFF07, 0xFF);
+
+        assert_eq!(timer.read(0xFF07), 0xFF);
+        assert_eq!(timer.read(0xFF04), 0x00);
+    }
+
+    #[rstest]
+    #[case(0x04, 0x00)]
+    #[case(0x05, 0xF0)]
+    #[case(0x06, 0xFF)]
+    #[case(0x07, 0x80)]
+    fn test_cycles_until_interrupt(#[case] tac: u8, #[case] tima: u8) {
+        // Arrange
+        let mut timer = timer();
+        timer.tick(40);
+        timer.write(0xFF07, tac);
+        timer.write(0xFF05, tima);
+        let expected = timer.cycles_until_interrupt().unwrap();
+
+        // Act
+        timer.tick(expected as u32 - 4);
+        let early = timer.take_interrupts();
+        timer.tick(4);
+
+        // Assert
+        assert_eq!(early, 0);
+        assert_eq!(timer.take_interrupts(), TIMER_INTERRUPT);
+    }
+
+    #[test]
+    fn test_no_interrupt_while_stopped() {
+        let timer = timer();
+
+        assert_eq!(timer.cycles_until_interrupt(), None);
+    }
+
+    #[rstest]
+    #[case(false, 8192)]
+    #[case(true, 16384)]
+    fn test_div_apu_clock(#[case] double_speed: bool, #[case] period: u64) {
+        let mut timer = timer();
+        timer.tick(100);
+
+        assert_eq!(timer.cycles_until_div_apu_clock(double_speed), period - 100);
+        timer.tick(period as u32 / 2);
+        assert!(timer.div_apu_signal(double_speed));
+    }
+}
@@ -0,0 +1,265 @@
+use std::fmt;
+use std::io::{Error, Result, Write};
+use std::rc::Rc;
+use std::str::FromStr;
+
+use crate::cpu::Registers;
+use crate::disasm;
+use crate::symbols::Symbols;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TraceFormat {
+    // Exactly the lines Gameboy Doctor and most emulators' trace loggers produce, for diffing.
+    Doctor,
+    // The same line followed by the T-cycles run so far and the disassembled instruction, with
+    // labels when there are symbols.
+    Full,
+}
+
+impl FromStr for TraceFormat {
+    type Err = Error;
+
+    fn from_str(name: &str) -> Result<TraceFormat> {
+        match name.to_ascii_lowercase().as_str() {
+            "doctor" => Ok(TraceFormat::Doctor),
+            "full" => Ok(TraceFormat::Full),
+            _ => Err(Error::other(format!(
+                "{} is not a trace format, expected doctor or full.",
+                name
+            ))),
+        }
+    }
+}
+
+// The state right before an instruction runs.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct TraceEntry {
+    pub registers: Registers,
+    // The 4 bytes starting at PC.
+    pub memory: [u8; 4],
+    pub cycles: u64,
+}
+
+impl TraceEntry {
+    pub fn format(&self, format: TraceFormat) -> String {
+        self.format_with(format, None)
+    }
+
+    // The tracer doesn't know which ROM bank is mapped, so banked addresses only get a label when
+    // a single bank has one there.
+    pub fn format_with(&self, format: TraceFormat, symbols: Option<&Symbols>) -> String {
+        let r = &self.registers;
+        let [m0, m1, m2, m3] = self.memory;
+        let line = format!(
+            "A:{:02X} F:{:02X} B:{:02X} C:{:02X} D:{:02X} E:{:02X} H:{:02X} L:{:02X} SP:{:04X} PC:{:04X} PCMEM:{:02X},{:02X},{:02X},{:02X}",
+            r.a, r.f, r.b, r.c, r.d, r.e, r.h, r.l, r.sp, r.pc, m0, m1, m2, m3
+        );
+        match format {
+            TraceFormat::Doctor => line,
+            TraceFormat::Full => {
+                let label = |address| symbols.and_then(|symbols| symbols.label(None, address));
+                let instruction = disasm::decode(&self.memory, r.pc).format_with(label);
+                match label(r.pc) {
+                    Some(name) => {
+                        format!("{} CY:{} | {}: {}", line, self.cycles, name, instruction)
+                    }
+                    None => format!("{} CY:{} | {}", line, self.cycles, instruction),
+                }
+            }
+        }
+    }
+}
+
+impl fmt::Display for TraceEntry {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.format(TraceFormat::Full))
+    }
+}
+
+// Writes a line per executed instruction. Interrupt dispatches and time spent halted only show up
+// in the cycle count. The CPU can't fail a step, so the first write error stops the trace and is
+// returned by `finish`.
+pub struct Tracer {
+    out: Box<dyn Write>,
+    format: TraceFormat,
+    symbols: Option<Rc<Symbols>>,
+    cycles: u64,
+    error: Option<Error>,
+}
+
+impl Tracer {
+    pub fn new(out: Box<dyn Write>, format: TraceFormat) -> Tracer {
+        Tracer {
+            out,
+            format,
+            symbols: None,
+            cycles: 0,
+            error: None,
+        }
+    }
+
+    pub fn set_symbols(&mut self, symbols: Option<Rc<Symbols>>) {
+        self.symbols = symbols;
+    }
+
+    pub fn trace(&mut self, registers: Registers, memory: [u8; 4]) {
+        if self.error.is_some() {
+            return;
+        }
+
+        let entry = TraceEntry {
+            registers,
+            memory,
+            cycles: self.cycles,
+        };
+        if let Err(error) = writeln!(
+            self.out,
+            "{}",
+            entry.format_with(self.format, self.symbols.as_deref())
+        ) {
+            self.error = Some(error);
+        }
+    }
+
+    pub fn add_cycles(&mut self, cycles: u32) {
+        self.cycles += cycles as u64;
+    }
+
+    pub fn finish(mut self) -> Result<()> {
+        match self.error.take() {
+            Some(error) => Err(error),
+            None => self.out.flush(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+
+    use super::*;
+    use crate::cpu::{Cpu, Memory};
+
+    #[derive(Clone, Default)]
+    struct SharedBuffer(Rc<RefCell<Vec<u8>>>);
+
+    impl Write for SharedBuffer {
+        fn write(&mut self, buf: &[u8]) -> Result<usize> {
+            self.0.borrow_mut().write(buf)
+        }
+
+        fn flush(&mut self) -> Result<()> {
+            Ok(())
+        }
+    }
+
+    struct Program(Vec<u8>);
+
+    impl Memory for Program {
+        fn read(&mut self, address: u16) -> u8 {
+            self.0.get(address as usize).copied().unwrap_or(0)
+        }
+
+        fn write(&mut self, _address: u16, _value: u8) {}
+    }
+
+    fn registers() -> Registers {
+        Registers {
+            a: 0x01,
+            f: 0xB0,
+            b: 0x00,
+            c: 0x13,
+            d: 0x00,
+            e: 0xD8,
+            h: 0x01,
+            l: 0x4D,
+            sp: 0xFFFE,
+            pc: 0x0100,
+        }
+    }
+
+    #[test]
+    fn test_formats() {
+        let entry = TraceEntry {
+            registers: registers(),
+            memory: [0xC3, 0x50, 0x01, 0xCE],
+            cycles: 1234,
+        };
+
+        assert_eq!(
+            entry.format(TraceFormat::Doctor),
+            "A:01 F:B0 B:00 C:13 D:00 E:D8 H:01 L:4D SP:FFFE PC:0100 PCMEM:C3,50,01,CE"
+        );
+        assert_eq!(
+            entry.format(TraceFormat::Full),
+            "A:01 F:B0 B:00 C:13 D:00 E:D8 H:01 L:4D SP:FFFE PC:0100 PCMEM:C3,50,01,CE CY:1234 | jp $0150"
+        );
+    }
+
+    #[test]
+    fn test_format_with_symbols() {
+        let entry = TraceEntry {
+            registers: registers(),
+            memory: [0xC3, 0x50, 0x01, 0xCE],
+            cycles: 0,
+        };
+        let symbols = Symbols::parse("00:0100 Entry\n00:0150 Start\n").unwrap();
+
+        assert!(entry
+            .format_with(TraceFormat::Full, Some(&symbols))
+            .ends_with("CY:0 | Entry: jp Start"));
+        assert!(!entry
+            .format_with(TraceFormat::Doctor, Some(&symbols))
+            .contains("Start"));
+    }
+
+    #[test]
+    fn test_tracer_counts_cycles() {
+        // Arrange
+        let buffer = SharedBuffer::default();
+        let mut tracer = Tracer::new(Box::new(buffer.clone()), TraceFormat::Full);
+
+        // Act
+        tracer.trace(registers(), [0x00; 4]);
+        tracer.add_cycles(4);
+        tracer.trace(registers(), [0x00; 4]);
+        tracer.finish().unwrap();
+
+        // Assert
+        let output = String::from_utf8(buffer.0.take()).unwrap();
+        let lines: Vec<&str> = output.lines().collect();
+        assert_eq!(lines.len(), 2);
+        assert!(lines[0].ends_with("CY:0 | nop"));
+        assert!(lines[1].ends_with("CY:4 | nop"));
+    }
+
+    #[test]
+    fn test_cpu_traces_each_instruction() {
+        // Arrange
+        let buffer = SharedBuffer::default();
+        let mut cpu = Cpu::new();
+        let mut program = vec![0; 0x0100];
+        program.extend([0x3E, 0x42, 0xC3, 0x00, 0x01]);
+        let mut mem = Program(program);
+        cpu.set_tracer(Some(Tracer::new(
+            Box::new(buffer.clone()),
+            TraceFormat::Full,
+        )));
+
+        // Act
+        cpu.step(&mut mem);
+        cpu.step(&mut mem);
+        cpu.take_tracer().unwrap().finish().unwrap();
+
+        // Assert
+        let output = String::from_utf8(buffer.0.take()).unwrap();
+        let lines: Vec<&str> = output.lines().collect();
+        assert_eq!(
+            lines,
+            [
+                "A:01 F:B0 B:00 C:13 D:00 E:D8 H:01 L:4D SP:FFFE PC:0100 PCMEM:3E,42,C3,00 CY:0 | ld a, $42",
+                "A:42 F:B0 B:00 C:13 D:00 E:D8 H:01 L:4D SP:FFFE PC:0102 PCMEM:C3,00,01,00 CY:8 | jp $0100",
+            ]
+        );
+    }
+}
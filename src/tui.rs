@@ -0,0 +1,354 @@
+use std::io::Result;
+use std::rc::Rc;
+use std::time::Duration;
+
+use ratatui::crossterm::event::{self, Event, KeyCode, KeyEventKind};
+use ratatui::layout::{Constraint, Layout, Rect};
+use ratatui::style::{Color, Style};
+use ratatui::text::Line;
+use ratatui::widgets::{Block, Paragraph};
+use ratatui::{DefaultTerminal, Frame};
+use rustygameboy::cpu::Flag;
+use rustygameboy::debugger::{self, Command, Debugger};
+use rustygameboy::disasm;
+use rustygameboy::emulator::{Emulator, CYCLES_PER_FRAME};
+use rustygameboy::symbols::Symbols;
+
+// The terminal debugger: disassembly, registers, stack and a memory view, driven by commands typed
+// at the bottom. Enter on an empty line repeats the last command and Escape pauses a running game.
+// With symbols, the code and calls show labels and commands take them for addresses.
+pub fn run(emulator: &mut Emulator, symbols: Option<Rc<Symbols>>) -> Result<()> {
+    let mut terminal = ratatui::init();
+    let result = App::new(symbols).run(&mut terminal, emulator);
+    ratatui::restore();
+    result
+}
+
+struct App {
+    debugger: Debugger,
+    symbols: Option<Rc<Symbols>>,
+    input: String,
+    last_command: Option<Command>,
+    status: String,
+    running: bool,
+    memory_address: u16,
+    // Nothing plays the audio, so it's drained here to keep it from piling up.
+    samples: Vec<f32>,
+}
+
+impl App {
+    fn new(symbols: Option<Rc<Symbols>>) -> App {
+        App {
+            debugger: Debugger::new(),
+            symbols,
+            input: String::new(),
+            last_command: None,
+            status: "Paused. Type s, n, o, c, b <addr>, d <addr>, m <addr>, w <addr> <value> or q."
+                .to_string(),
+            running: false,
+            memory_address: 0xC000,
+            samples: vec![0.0; AUDIO_CHUNK],
+        }
+    }
+
+    fn run(&mut self, terminal: &mut DefaultTerminal, emulator: &mut Emulator) -> Result<()> {
+        loop {
+            terminal.draw(|frame| self.draw(frame, emulator))?;
+
+            if self.running {
+                if let Some(stop) = self.debugger.run(emulator, CYCLES_PER_FRAME) {
+                    self.running = false;
+                    self.status = stop.to_string();
+                }
+                while emulator.fill_audio_buffer(&mut self.samples) > 0 {}
+                if let Some(violation) = emulator.take_memory_violations().last() {
+                    self.status = violation.to_string();
+                }
+            }
+
+            let timeout = if self.running {
+                Duration::ZERO
+            } else {
+                INPUT_POLL
+            };
+            if !event::poll(timeout)? {
+                continue;
+            }
+            let Event::Key(key) = event::read()? else {
+                continue;
+            };
+            if key.kind != KeyEventKind::Press {
+                continue;
+            }
+
+            match key.code {
+                KeyCode::Esc if self.running => {
+                    self.running = false;
+                    self.status = "Paused".to_string();
+                }
+                KeyCode::Esc => self.input.clear(),
+                KeyCode::Enter if self.submit(emulator) => return Ok(()),
+                KeyCode::Backspace => {
+                    self.input.pop();
+                }
+                KeyCode::Char(c) => self.input.push(c),
+                _ => {}
+            }
+        }
+    }
+
+    // Runs the typed command. Returns true to quit.
+    fn submit(&mut self, emulator: &mut Emulator) -> bool {
+        let input = std::mem::take(&mut self.input);
+        let command = if input.trim().is_empty() {
+            match &self.last_command {
+                Some(command) => command.clone(),
+                None => return false,
+            }
+        } else {
+            match Command::parse_with(&input, self.symbols.as_deref()) {
+                Ok(command) => command,
+                Err(error) => {
+                    self.status = error.to_string();
+                    return false;
+                }
+            }
+        };
+        self.last_command = Some(command.clone());
+
+        self.status.clear();
+        match command {
+            Command::Step => self.debugger.step(emulator),
+            Command::StepOver => self.running = self.debugger.step_over(emulator),
+            Command::StepOut => {
+                self.running = self.debugger.step_out(emulator);
+                if !self.running {
+                    self.status = "Not in a call".to_string();
+                }
+            }
+            Command::Continue => self.running = true,
+            Command::Break(address, breakpoint) => {
+                self.status = format!("Breakpoint set at {:04X} {}", address, breakpoint)
+                    .trim_end()
+                    .to_string();
+                self.debugger.set_breakpoint(address, breakpoint);
+            }
+            Command::Delete(address) => {
+                self.status = if self.debugger.remove_breakpoint(address) {
+                    format!("Breakpoint at {:04X} deleted", address)
+                } else {
+                    format!("No breakpoint at {:04X}", address)
+                };
+            }
+            Command::Watch(watchpoint) => {
+                emulator.add_watchpoint(watchpoint);
+                self.status = format!("Watching {:04X}..{:04X}", watchpoint.start, watchpoint.end);
+            }
+            Command::Unwatch(watchpoint) => {
+                self.status = if emulator.remove_watchpoint(&watchpoint) {
+                    "Watchpoint deleted".to_string()
+                } else {
+                    "No such watchpoint".to_string()
+                };
+            }
+            Command::Memory(address) => self.memory_address = address,
+            Command::Write(address, value) => {
+                emulator.poke(address, value);
+                self.memory_address = address & 0xFFF0;
+            }
+            Command::Dump(region, path) => {
+                self.status = match debugger::dump_to_file(emulator, region, &path) {
+                    Ok(()) => format!("Dumped {} to {}", region, path.display()),
+                    Err(error) => error.to_string(),
+                };
+            }
+            Command::Quit => return true,
+        }
+        if self.running {
+            self.status = "Running, Escape to pause".to_string();
+        }
+        false
+    }
+
+    fn draw(&self, frame: &mut Frame, emulator: &mut Emulator) {
+        let [main, memory, status, input] = Layout::vertical([
+            Constraint::Min(10),
+            Constraint::Length(MEMORY_ROWS as u16 + 2),
+            Constraint::Length(1),
+            Constraint::Length(1),
+        ])
+        .areas(frame.area());
+        let [code, side] =
+            Layout::horizontal([Constraint::Min(40), Constraint::Length(28)]).areas(main);
+        let [registers, calls, stack] = Layout::vertical([
+            Constraint::Length(9),
+            Constraint::Length(CALL_ROWS as u16 + 2),
+            Constraint::Min(3),
+        ])
+        .areas(side);
+
+        self.draw_code(frame, code, emulator);
+        draw_registers(frame, registers, emulator);
+        self.draw_calls(frame, calls);
+        draw_stack(frame, stack, emulator);
+        self.draw_memory(frame, memory, emulator);
+        frame.render_widget(Paragraph::new(self.status.as_str()), status);
+        frame.render_widget(Paragraph::new(format!("> {}", self.input)), input);
+    }
+
+    // Disassembles forward from PC, since instructions can't be reliably decoded backwards.
+    fn draw_code(&self, frame: &mut Frame, area: Rect, emulator: &mut Emulator) {
+        let pc = emulator.cpu().registers.pc;
+        let rows = area.height.saturating_sub(2) as usize;
+        let mut lines = Vec::with_capacity(rows);
+        let mut address = pc;
+        while lines.len() < rows {
+            if let Some(name) = self.label(emulator, address) {
+                lines.push(Line::raw(format!(" {}:", name)));
+            }
+            let bytes = [0, 1, 2].map(|offset| emulator.peek(address.wrapping_add(offset)));
+            let instruction = disasm::decode(&bytes, address);
+            let length = instruction.length as usize;
+            let line = disasm::Line {
+                address,
+                bytes: bytes[..length].to_vec(),
+                instruction,
+            };
+            let marker = if self.debugger.has_breakpoint(address) {
+                '*'
+            } else {
+                ' '
+            };
+            let style = if address == pc {
+                Style::new().fg(Color::Black).bg(Color::Yellow)
+            } else {
+                Style::new()
+            };
+            let text = line.format_with(|target| self.label(emulator, target));
+            lines.push(Line::styled(format!("{}{}", marker, text), style));
+            address = address.wrapping_add(length as u16);
+        }
+        frame.render_widget(
+            Paragraph::new(lines).block(Block::bordered().title("Code")),
+            area,
+        );
+    }
+
+    // With the ROM bank mapped now.
+    fn label(&self, emulator: &Emulator, address: u16) -> Option<&str> {
+        self.symbols
+            .as_deref()
+            .and_then(|symbols| symbols.label_in(emulator, address))
+    }
+
+    // Innermost first.
+    fn draw_calls(&self, frame: &mut Frame, area: Rect) {
+        let lines: Vec<Line> = self
+            .debugger
+            .call_stack()
+            .iter()
+            .rev()
+            .take(CALL_ROWS)
+            .map(|call| {
+                let name = self
+                    .symbols
+                    .as_deref()
+                    .and_then(|symbols| symbols.label(Some(call.bank), call.target));
+                match name {
+                    Some(name) if call.interrupt => {
+                        Line::raw(format!("{} interrupt at {:04X}", name, call.caller))
+                    }
+                    Some(name) => Line::raw(format!("{} from {:04X}", name, call.caller)),
+                    None => Line::raw(call.to_string()),
+                }
+            })
+            .collect();
+        frame.render_widget(
+            Paragraph::new(lines).block(Block::bordered().title("Calls")),
+            area,
+        );
+    }
+
+    fn draw_memory(&self, frame: &mut Frame, area: Rect, emulator: &mut Emulator) {
+        let mut lines = Vec::with_capacity(MEMORY_ROWS);
+        for row in 0..MEMORY_ROWS as u16 {
+            let address = self.memory_address.wrapping_add(row * 16);
+            let bytes: Vec<u8> = (0..16)
+                .map(|offset| emulator.peek(address.wrapping_add(offset)))
+                .collect();
+            let hex: Vec<String> = bytes.iter().map(|b| format!("{:02X}", b)).collect();
+            let text: String = bytes
+                .iter()
+                .map(|&b| if b.is_ascii_graphic() { b as char } else { '.' })
+                .collect();
+            lines.push(Line::raw(format!(
+                "{:04X}  {}  {}",
+                address,
+                hex.join(" "),
+                text
+            )));
+        }
+        frame.render_widget(
+            Paragraph::new(lines).block(Block::bordered().title("Memory")),
+            area,
+        );
+    }
+}
+
+fn draw_registers(frame: &mut Frame, area: Rect, emulator: &Emulator) {
+    let cpu = emulator.cpu();
+    let r = &cpu.registers;
+    let flags: String = [
+        (Flag::Zero, 'Z'),
+        (Flag::Subtract, 'N'),
+        (Flag::HalfCarry, 'H'),
+        (Flag::Carry, 'C'),
+    ]
+    .iter()
+    .map(|&(flag, name)| if r.flag(flag) { name } else { '-' })
+    .collect();
+    let lines = vec![
+        Line::raw(format!("AF {:04X}   BC {:04X}", r.af(), r.bc())),
+        Line::raw(format!("DE {:04X}   HL {:04X}", r.de(), r.hl())),
+        Line::raw(format!("SP {:04X}   PC {:04X}", r.sp, r.pc)),
+        Line::raw(format!("Flags {}", flags)),
+        Line::raw(format!(
+            "IME {}  {}",
+            if cpu.ime() { "on " } else { "off" },
+            if cpu.halted() { "halted" } else { "" }
+        )),
+        Line::raw(format!("LY {:3}", emulator.bus().ppu().ly())),
+        Line::raw(format!("Frame {}", emulator.bus().ppu().frames())),
+    ];
+    frame.render_widget(
+        Paragraph::new(lines).block(Block::bordered().title("Registers")),
+        area,
+    );
+}
+
+fn draw_stack(frame: &mut Frame, area: Rect, emulator: &mut Emulator) {
+    let sp = emulator.cpu().registers.sp;
+    let rows = area.height.saturating_sub(2);
+    let lines: Vec<Line> = (0..rows)
+        .map(|row| {
+            let address = sp.wrapping_add(row * 2);
+            let value = u16::from_le_bytes([
+                emulator.peek(address),
+                emulator.peek(address.wrapping_add(1)),
+            ]);
+            Line::raw(format!("{:04X}  {:04X}", address, value))
+        })
+        .collect();
+    frame.render_widget(
+        Paragraph::new(lines).block(Block::bordered().title("Stack")),
+        area,
+    );
+}
+
+const MEMORY_ROWS: usize = 8;
+
+const CALL_ROWS: usize = 4;
+
+const INPUT_POLL: Duration = Duration::from_millis(50);
+
+const AUDIO_CHUNK: usize = 2048;
@@ -0,0 +1,254 @@
+use std::fs::{self, File};
+use std::io::{BufWriter, Error, ErrorKind, Result, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Child, ChildStdin, Command, Stdio};
+
+use crate::apu::CPU_CLOCK;
+use crate::emulator::CYCLES_PER_FRAME;
+use crate::ppu::{SCREEN_HEIGHT, SCREEN_WIDTH};
+use crate::wav::WavWriter;
+
+// Where recordings can go, picked from the file's extension.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Container {
+    // .mkv, FFV1 video and FLAC audio.
+    Matroska,
+    // .mp4, lossless H.264 in RGB and ALAC audio.
+    Mp4,
+}
+
+impl Container {
+    pub fn for_path(path: &Path) -> Result<Container> {
+        let extension = path.extension().unwrap_or_default().to_string_lossy();
+        match extension.to_ascii_lowercase().as_str() {
+            "mkv" => Ok(Container::Matroska),
+            "mp4" => Ok(Container::Mp4),
+            _ => Err(Error::other(format!(
+                "{} is not a video file, expected .mkv or .mp4.",
+                path.display()
+            ))),
+        }
+    }
+
+    fn codecs(self) -> &'static [&'static str] {
+        match self {
+            Container::Matroska => &["-c:v", "copy", "-c:a", "flac"],
+            Container::Mp4 => &["-c:v", "libx264rgb", "-qp", "0", "-c:a", "alac"],
+        }
+    }
+}
+
+// A lossless recording of the screen and sound made with ffmpeg, which has to be on the PATH.
+// Frames are piped to ffmpeg as they come and the sound goes to a WAV file next to the recording,
+// and `finish` muxes the two together.
+//
+// The sound decides the time: the samples are made from the APU's cycles, so every sample is
+// exactly as long as it was on the hardware, and frames are repeated or skipped to keep the video
+// at 59.73 frames per second of those cycles. Frames with the LCD off and speed changes stay in
+// sync that way.
+pub struct VideoRecording {
+    path: PathBuf,
+    container: Container,
+    ffmpeg: Child,
+    frames: BufWriter<ChildStdin>,
+    video_path: PathBuf,
+    audio: WavWriter<BufWriter<File>>,
+    audio_path: PathBuf,
+    sample_rate: u32,
+    // Stereo samples written so far.
+    samples: u64,
+    video_frames: u64,
+}
+
+impl VideoRecording {
+    pub fn start(path: &Path, sample_rate: u32) -> Result<VideoRecording> {
+        VideoRecording::start_with(FFMPEG, path, sample_rate)
+    }
+
+    fn start_with(program: &str, path: &Path, sample_rate: u32) -> Result<VideoRecording> {
+        let container = Container::for_path(path)?;
+        let video_path = part_path(path, "video.mkv");
+        let audio_path = part_path(path, "audio.wav");
+        let mut ffmpeg = Command::new(program)
+            .args(["-y", "-loglevel", "error", "-f", "rawvideo"])
+            .args(["-pixel_format", "rgba"])
+            .args([
+                "-video_size",
+                &format!("{}x{}", SCREEN_WIDTH, SCREEN_HEIGHT),
+            ])
+            .args(["-framerate", &format!("{}/{}", CPU_CLOCK, CYCLES_PER_FRAME)])
+            .args(["-i", "-", "-c:v", "ffv1", "-pix_fmt", "bgr0"])
+            .arg(&video_path)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::null())
+            .spawn()
+            .map_err(|error| ffmpeg_error(program, error))?;
+        let frames = BufWriter::new(ffmpeg.stdin.take().expect("stdin is piped"));
+        let audio = File::create(&audio_path)
+            .and_then(|file| WavWriter::new(BufWriter::new(file), sample_rate));
+        let audio = match audio {
+            Ok(audio) => audio,
+            Err(error) => {
+                let _ = ffmpeg.kill();
+                return Err(error);
+            }
+        };
+        Ok(VideoRecording {
+            path: path.to_path_buf(),
+            container,
+            ffmpeg,
+            frames,
+            video_path,
+            audio,
+            audio_path,
+            sample_rate,
+            samples: 0,
+            video_frames: 0,
+        })
+    }
+
+    pub fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    // Adds the interleaved stereo `samples` made since the last call, and `frame` as many times
+    // as the video needs to catch up with them.
+    pub fn write(&mut self, frame: &[u8], samples: &[f32]) -> Result<()> {
+        self.audio.write(samples)?;
+        self.samples += samples.len() as u64 / 2;
+        let due = frames_due(self.samples, self.sample_rate);
+        while self.video_frames < due {
+            self.frames.write_all(frame)?;
+            self.video_frames += 1;
+        }
+        Ok(())
+    }
+
+    // Waits for ffmpeg to encode the frames and muxes them with the sound into the recording. The
+    // parts are left next to it if that fails.
+    pub fn finish(self) -> Result<()> {
+        let VideoRecording {
+            path,
+            container,
+            mut ffmpeg,
+            frames,
+            video_path,
+            audio,
+            audio_path,
+            ..
+        } = self;
+        audio.finish()?;
+        // Closing stdin tells ffmpeg there are no more frames.
+        frames.into_inner().map_err(|error| error.into_error())?;
+        check(ffmpeg.wait()?, "encode the video")?;
+
+        let status = Command::new(FFMPEG)
+            .args(["-y", "-loglevel", "error", "-i"])
+            .arg(&video_path)
+            .arg("-i")
+            .arg(&audio_path)
+            .args(["-map", "0:v", "-map", "1:a"])
+            .args(container.codecs())
+            .arg(&path)
+            .stdin(Stdio::null())
+            .stdout(Stdio::null())
+            .status()
+            .map_err(|error| ffmpeg_error(FFMPEG, error))?;
+        check(status, "mux the video and sound")?;
+        fs::remove_file(&video_path)?;
+        fs::remove_file(&audio_path)
+    }
+}
+
+// How many frames the hardware showed in the time it took to make `samples`, rounded to the
+// nearest so the video neither leads nor lags by more than half a frame.
+fn frames_due(samples: u64, sample_rate: u32) -> u64 {
+    let cycles = samples as u128 * CPU_CLOCK as u128;
+    let per_frame = sample_rate as u128 * CYCLES_PER_FRAME as u128;
+    ((cycles + per_frame / 2) / per_frame) as u64
+}
+
+// out.mkv's parts are out.video.mkv and out.audio.wav.
+fn part_path(path: &Path, part: &str) -> PathBuf {
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    path.with_file_name(format!("{}.{}", stem, part))
+}
+
+fn ffmpeg_error(program: &str, error: Error) -> Error {
+    if error.kind() == ErrorKind::NotFound {
+        Error::new(
+            error.kind(),
+            format!(
+                "Recording video needs {}, which isn't on the PATH.",
+                program
+            ),
+        )
+    } else {
+        Error::new(
+            error.kind(),
+            format!("Could not run {}: {}", program, error),
+        )
+    }
+}
+
+fn check(status: std::process::ExitStatus, action: &str) -> Result<()> {
+    if status.success() {
+        Ok(())
+    } else {
+        Err(Error::other(format!(
+            "ffmpeg could not {} ({}).",
+            action, status
+        )))
+    }
+}
+
+// Looked up on the PATH, by the webcam too.
+pub const FFMPEG: &str = "ffmpeg";
+
+#[cfg(test)]
+mod tests {
+    use rstest::rstest;
+    use tempfile::tempdir;
+
+    use super::*;
+
+    #[rstest]
+    #[case("run.mkv", Some(Container::Matroska))]
+    #[case("RUN.MP4", Some(Container::Mp4))]
+    #[case("run.avi", None)]
+    #[case("run", None)]
+    fn test_container(#[case] path: &str, #[case] expected: Option<Container>) {
+        assert_eq!(Container::for_path(Path::new(path)).ok(), expected);
+    }
+
+    #[rstest]
+    #[case(0, 0)]
+    // Just under half a frame.
+    #[case(401, 0)]
+    #[case(48_000, 60)]
+    #[case(48_000 * 60, 3584)]
+    fn test_frames_due(#[case] samples: u64, #[case] expected: u64) {
+        assert_eq!(frames_due(samples, 48_000), expected);
+    }
+
+    #[test]
+    fn test_part_path() {
+        assert_eq!(
+            part_path(Path::new("dir/out.mp4"), "audio.wav"),
+            Path::new("dir/out.audio.wav")
+        );
+    }
+
+    #[test]
+    fn test_missing_ffmpeg() {
+        let directory = tempdir().unwrap();
+        let path = directory.path().join("run.mkv");
+
+        let error = VideoRecording::start_with("rusty_gameboy_no_such_program", &path, 48_000)
+            .err()
+            .unwrap();
+
+        assert_eq!(error.kind(), ErrorKind::NotFound);
+        assert!(error.to_string().contains("isn't on the PATH"));
+    }
+}
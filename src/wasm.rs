@@ -0,0 +1,137 @@
+// Bindings for running in a browser. Build with
+// `cargo build --release --lib --target wasm32-unknown-unknown --features wasm --no-default-features`
+// and run wasm-bindgen on the output, see the README.
+use wasm_bindgen::prelude::*;
+
+use crate::emulator::Emulator;
+use crate::joypad::Button;
+use crate::ppu::{SCREEN_HEIGHT, SCREEN_WIDTH};
+use crate::rom::{Rom, ValidationPolicy};
+
+#[wasm_bindgen]
+pub struct WasmEmulator {
+    emulator: Emulator,
+}
+
+#[wasm_bindgen]
+impl WasmEmulator {
+    // Starts the ROM with the model it was made for.
+    pub fn load_rom(rom: &[u8]) -> Result<WasmEmulator, JsError> {
+        let rom = Rom::from_bytes_with_policy(rom.to_vec(), ValidationPolicy::Lenient)?;
+        Ok(WasmEmulator {
+            emulator: Emulator::new(rom)?,
+        })
+    }
+
+    pub fn run_frame(&mut self) {
+        self.emulator.run_frame();
+    }
+
+    // Where the frame is in the module's memory, for an ImageData over
+    // `new Uint8ClampedArray(memory.buffer, pointer, width * height * 4)` without copying. Growing
+    // the memory detaches the array, so make a new one every frame.
+    pub fn framebuffer_pointer(&self) -> *const u8 {
+        self.emulator.framebuffer().as_ptr()
+    }
+
+    pub fn width() -> usize {
+        SCREEN_WIDTH
+    }
+
+    pub fn height() -> usize {
+        SCREEN_HEIGHT
+    }
+
+    // Match the AudioContext's rate.
+    pub fn set_sample_rate(&mut self, sample_rate: u32) {
+        self.emulator.set_sample_rate(sample_rate);
+    }
+
+    // The interleaved stereo samples made since the last call, as a Float32Array.
+    pub fn audio_buffer(&mut self) -> Vec<f32> {
+        self.emulator.audio_samples()
+    }
+
+    // Takes a KeyboardEvent's code and returns whether it's one of the Game Boy's buttons, so the
+    // page knows to call preventDefault.
+    pub fn key_down(&mut self, code: &str) -> bool {
+        self.set_key(code, true)
+    }
+
+    pub fn key_up(&mut self, code: &str) -> bool {
+        self.set_key(code, false)
+    }
+
+    pub fn save_state(&self) -> Vec<u8> {
+        self.emulator.save_state()
+    }
+
+    pub fn load_state(&mut self, state: &[u8]) -> Result<(), JsError> {
+        Ok(self.emulator.load_state(state)?)
+    }
+
+    // Cartridge RAM for keeping in localStorage or IndexedDB, empty without a battery.
+    pub fn save_data(&self) -> Vec<u8> {
+        if self.emulator.has_battery() {
+            self.emulator.save_data()
+        } else {
+            Vec::new()
+        }
+    }
+
+    pub fn load_save_data(&mut self, data: &[u8]) {
+        self.emulator.load_save_data(data);
+    }
+
+    fn set_key(&mut self, code: &str, pressed: bool) -> bool {
+        match button(code) {
+            Some(button) => {
+                self.emulator.set_button(button, pressed);
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+// The same keys as the desktop window.
+fn button(code: &str) -> Option<Button> {
+    match code {
+        "ArrowRight" => Some(Button::Right),
+        "ArrowLeft" => Some(Button::Left),
+        "ArrowUp" => Some(Button::Up),
+        "ArrowDown" => Some(Button::Down),
+        "KeyZ" => Some(Button::A),
+        "KeyX" => Some(Button::B),
+        "Backspace" | "ShiftRight" => Some(Button::Select),
+        "Enter" => Some(Button::Start),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rstest::rstest;
+
+    use super::*;
+
+    #[rstest]
+    #[case("KeyZ", Some(Button::A))]
+    #[case("ShiftRight", Some(Button::Select))]
+    #[case("KeyA", None)]
+    fn test_button(#[case] code: &str, #[case] expected: Option<Button>) {
+        assert_eq!(button(code), expected);
+    }
+
+    #[test]
+    fn test_run_frame() {
+        let mut emulator = WasmEmulator::load_rom(&[0; 0x8000]).unwrap_or_else(|_| panic!());
+
+        emulator.run_frame();
+
+        assert!(!emulator.framebuffer_pointer().is_null());
+        assert!(!emulator.audio_buffer().is_empty());
+        assert!(emulator.key_down("Enter"));
+        assert!(!emulator.key_up("Space"));
+    }
+}
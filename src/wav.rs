@@ -0,0 +1,191 @@
+use std::fs::File;
+use std::io::{BufWriter, Error, Result, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+
+// Writes interleaved stereo samples as a 16-bit PCM WAV. The sizes in the header are only right
+// after `finish`.
+pub struct WavWriter<W: Write + Seek> {
+    writer: W,
+    data_size: u32,
+}
+
+impl<W: Write + Seek> WavWriter<W> {
+    pub fn new(mut writer: W, sample_rate: u32) -> Result<WavWriter<W>> {
+        let block_align = CHANNELS * BITS_PER_SAMPLE / 8;
+        writer.write_all(b"RIFF")?;
+        writer.write_all(&0u32.to_le_bytes())?;
+        writer.write_all(b"WAVEfmt ")?;
+        writer.write_all(&16u32.to_le_bytes())?;
+        writer.write_all(&PCM.to_le_bytes())?;
+        writer.write_all(&CHANNELS.to_le_bytes())?;
+        writer.write_all(&sample_rate.to_le_bytes())?;
+        writer.write_all(&(sample_rate * block_align as u32).to_le_bytes())?;
+        writer.write_all(&block_align.to_le_bytes())?;
+        writer.write_all(&BITS_PER_SAMPLE.to_le_bytes())?;
+        writer.write_all(b"data")?;
+        writer.write_all(&0u32.to_le_bytes())?;
+        Ok(WavWriter {
+            writer,
+            data_size: 0,
+        })
+    }
+
+    pub fn write(&mut self, samples: &[f32]) -> Result<()> {
+        let bytes = samples
+            .iter()
+            .flat_map(|sample| ((sample.clamp(-1.0, 1.0) * i16::MAX as f32) as i16).to_le_bytes())
+            .collect::<Vec<_>>();
+        let data_size = u32::try_from(bytes.len())
+            .ok()
+            .and_then(|size| self.data_size.checked_add(size))
+            .filter(|&size| size <= MAX_DATA_SIZE)
+            .ok_or_else(|| Error::other("The recording is too long for a WAV file."))?;
+        self.writer.write_all(&bytes)?;
+        self.data_size = data_size;
+        Ok(())
+    }
+
+    // Fills in the sizes and returns the writer.
+    pub fn finish(mut self) -> Result<W> {
+        self.writer.seek(SeekFrom::Start(4))?;
+        self.writer
+            .write_all(&(HEADER_SIZE - 8 + self.data_size).to_le_bytes())?;
+        self.writer.seek(SeekFrom::Start(HEADER_SIZE as u64 - 4))?;
+        self.writer.write_all(&self.data_size.to_le_bytes())?;
+        self.writer.seek(SeekFrom::End(0))?;
+        self.writer.flush()?;
+        Ok(self.writer)
+    }
+}
+
+// The APU's output going to a WAV file, and with stems each channel going to its own file next to
+// it: out.wav, out-square1.wav, out-square2.wav, out-wave.wav and out-noise.wav. Takes the tracks
+// in the order `Apu::take_captured` returns them.
+pub struct AudioRecording {
+    writers: Vec<WavWriter<BufWriter<File>>>,
+    sample_rate: u32,
+}
+
+impl AudioRecording {
+    pub fn create(path: &Path, sample_rate: u32, stems: bool) -> Result<AudioRecording> {
+        let mut paths = vec![path.to_path_buf()];
+        if stems {
+            paths.extend(STEMS.iter().map(|stem| stem_path(path, stem)));
+        }
+        let writers = paths
+            .iter()
+            .map(|path| {
+                let file = File::create(path).map_err(|error| {
+                    Error::new(
+                        error.kind(),
+                        format!("Could not create {}: {}", path.display(), error),
+                    )
+                })?;
+                WavWriter::new(BufWriter::new(file), sample_rate)
+            })
+            .collect::<Result<_>>()?;
+        Ok(AudioRecording {
+            writers,
+            sample_rate,
+        })
+    }
+
+    pub fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    pub fn stems(&self) -> bool {
+        self.writers.len() > 1
+    }
+
+    pub fn write(&mut self, tracks: &[Vec<f32>]) -> Result<()> {
+        for (writer, samples) in self.writers.iter_mut().zip(tracks) {
+            writer.write(samples)?;
+        }
+        Ok(())
+    }
+
+    pub fn finish(self) -> Result<()> {
+        for writer in self.writers {
+            writer.finish()?;
+        }
+        Ok(())
+    }
+}
+
+fn stem_path(path: &Path, stem: &str) -> PathBuf {
+    let name = path.file_stem().unwrap_or_default().to_string_lossy();
+    path.with_file_name(format!("{}-{}.wav", name, stem))
+}
+
+const PCM: u16 = 1;
+
+const CHANNELS: u16 = 2;
+
+const BITS_PER_SAMPLE: u16 = 16;
+
+const HEADER_SIZE: u32 = 44;
+
+// The RIFF size has to fit in a u32 too.
+const MAX_DATA_SIZE: u32 = u32::MAX - HEADER_SIZE;
+
+const STEMS: [&str; 4] = ["square1", "square2", "wave", "noise"];
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::io::Cursor;
+
+    use tempfile::tempdir;
+
+    use super::*;
+
+    #[test]
+    fn test_write() {
+        // Arrange
+        let mut writer = WavWriter::new(Cursor::new(Vec::new()), 48_000).unwrap();
+
+        // Act
+        writer.write(&[0.0, 1.0, -1.0, 2.0]).unwrap();
+        let wav = writer.finish().unwrap().into_inner();
+
+        // Assert
+        assert_eq!(wav.len(), 52);
+        assert_eq!(&wav[..4], b"RIFF");
+        assert_eq!(wav[4..8], 44u32.to_le_bytes());
+        assert_eq!(wav[24..28], 48_000u32.to_le_bytes());
+        assert_eq!(wav[28..32], 192_000u32.to_le_bytes());
+        assert_eq!(&wav[36..40], b"data");
+        assert_eq!(wav[40..44], 8u32.to_le_bytes());
+        let samples = wav[44..]
+            .chunks_exact(2)
+            .map(|bytes| i16::from_le_bytes([bytes[0], bytes[1]]))
+            .collect::<Vec<_>>();
+        assert_eq!(samples, [0, i16::MAX, -i16::MAX, i16::MAX]);
+    }
+
+    #[test]
+    fn test_recording_with_stems() {
+        // Arrange
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("out.wav");
+
+        // Act
+        let mut recording = AudioRecording::create(&path, 44_100, true).unwrap();
+        recording
+            .write(&[vec![0.5; 4], vec![0.0; 2], vec![], vec![], vec![]])
+            .unwrap();
+        recording.finish().unwrap();
+
+        // Assert
+        assert_eq!(fs::read(&path).unwrap().len(), 52);
+        assert_eq!(
+            fs::read(dir.path().join("out-square1.wav")).unwrap().len(),
+            48
+        );
+        assert_eq!(
+            fs::read(dir.path().join("out-noise.wav")).unwrap().len(),
+            44
+        );
+    }
+}
@@ -0,0 +1,190 @@
+// Runs the SingleStepTests SM83 vectors: thousands of cases per opcode, each with the registers
+// and memory before and after a single instruction and the machine cycles it takes. They're too
+// big to keep in the repository, so this only runs when SM83_TESTS points at the directory with
+// the JSON files, like v1 in the SingleStepTests/sm83 repository:
+//
+//     SM83_TESTS=path/to/sm83/v1 cargo test --release --test sm83
+//
+// Without it the test passes without running anything. Each instruction runs against flat memory
+// with no interrupts, which is what the vectors assume.
+
+use std::collections::HashMap;
+use std::env;
+use std::fmt::Write;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use rustygameboy::cpu::{Cpu, Memory};
+use serde::Deserialize;
+
+#[derive(Deserialize)]
+struct Case {
+    name: String,
+    initial: State,
+    #[serde(rename = "final")]
+    expected: State,
+    // One entry per machine cycle, the bus activity isn't compared.
+    cycles: Vec<serde_json::Value>,
+}
+
+#[derive(Deserialize, PartialEq, Eq)]
+struct State {
+    a: u8,
+    f: u8,
+    b: u8,
+    c: u8,
+    d: u8,
+    e: u8,
+    h: u8,
+    l: u8,
+    pc: u16,
+    sp: u16,
+    #[serde(default)]
+    ime: Option<u8>,
+    ram: Vec<(u16, u8)>,
+}
+
+struct TestMemory(HashMap<u16, u8>);
+
+impl Memory for TestMemory {
+    fn read(&mut self, address: u16) -> u8 {
+        self.0.get(&address).copied().unwrap_or(0)
+    }
+
+    fn write(&mut self, address: u16, value: u8) {
+        self.0.insert(address, value);
+    }
+
+    fn pending_interrupts(&mut self) -> u8 {
+        0
+    }
+}
+
+// Runs the case and describes how the result differs from the expected state, if it does.
+fn run(case: &Case) -> Option<String> {
+    let initial = &case.initial;
+    let mut cpu = Cpu::new();
+    let registers = &mut cpu.registers;
+    (registers.a, registers.f, registers.b, registers.c) =
+        (initial.a, initial.f, initial.b, initial.c);
+    (registers.d, registers.e, registers.h, registers.l) =
+        (initial.d, initial.e, initial.h, initial.l);
+    (registers.pc, registers.sp) = (initial.pc, initial.sp);
+    cpu.set_ime(initial.ime == Some(1));
+    let mut mem = TestMemory(initial.ram.iter().copied().collect());
+
+    let cycles = cpu.step(&mut mem);
+
+    let expected = &case.expected;
+    let registers = cpu.registers;
+    let actual = State {
+        a: registers.a,
+        f: registers.f,
+        b: registers.b,
+        c: registers.c,
+        d: registers.d,
+        e: registers.e,
+        h: registers.h,
+        l: registers.l,
+        pc: registers.pc,
+        sp: registers.sp,
+        ime: expected.ime.map(|_| cpu.ime() as u8),
+        ram: expected
+            .ram
+            .iter()
+            .map(|&(address, _)| (address, mem.read(address)))
+            .collect(),
+    };
+    let expected_cycles = case.cycles.len() as u32 * 4;
+    if actual == *expected && cycles == expected_cycles {
+        return None;
+    }
+
+    let mut difference = String::new();
+    for (name, actual, expected) in [
+        ("A", actual.a, expected.a),
+        ("F", actual.f, expected.f),
+        ("B", actual.b, expected.b),
+        ("C", actual.c, expected.c),
+        ("D", actual.d, expected.d),
+        ("E", actual.e, expected.e),
+        ("H", actual.h, expected.h),
+        ("L", actual.l, expected.l),
+    ] {
+        if actual != expected {
+            write!(difference, " {} {:02X} not {:02X},", name, actual, expected).unwrap();
+        }
+    }
+    for (name, actual, expected) in [
+        ("PC", actual.pc, expected.pc),
+        ("SP", actual.sp, expected.sp),
+    ] {
+        if actual != expected {
+            write!(difference, " {} {:04X} not {:04X},", name, actual, expected).unwrap();
+        }
+    }
+    if actual.ime != expected.ime {
+        write!(difference, " IME {:?} not {:?},", actual.ime, expected.ime).unwrap();
+    }
+    for (&(address, actual), &(_, expected)) in actual.ram.iter().zip(&expected.ram) {
+        if actual != expected {
+            write!(
+                difference,
+                " {:04X} {:02X} not {:02X},",
+                address, actual, expected
+            )
+            .unwrap();
+        }
+    }
+    if cycles != expected_cycles {
+        write!(difference, " {} cycles not {},", cycles, expected_cycles).unwrap();
+    }
+    Some(format!(
+        "{}:{}",
+        case.name,
+        difference.trim_end_matches(',')
+    ))
+}
+
+fn test_files(directory: &Path) -> Vec<PathBuf> {
+    let mut files: Vec<PathBuf> = fs::read_dir(directory)
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| {
+            path.extension()
+                .is_some_and(|extension| extension == "json")
+        })
+        .collect();
+    files.sort();
+    files
+}
+
+#[test]
+fn test_sm83() {
+    let Some(directory) = env::var_os("SM83_TESTS") else {
+        eprintln!("SM83_TESTS isn't set, skipping the SM83 tests");
+        return;
+    };
+    let files = test_files(Path::new(&directory));
+    assert!(!files.is_empty(), "no JSON files in {:?}", directory);
+
+    let mut cases = 0;
+    let mut failures = Vec::new();
+    for file in files {
+        let json = fs::read_to_string(&file).unwrap();
+        let file_cases: Vec<Case> = serde_json::from_str(&json)
+            .unwrap_or_else(|error| panic!("{}: {}", file.display(), error));
+        cases += file_cases.len();
+        failures.extend(file_cases.iter().filter_map(run));
+    }
+
+    assert!(
+        failures.is_empty(),
+        "{} of {} cases failed, the first ones:\n{}",
+        failures.len(),
+        cases,
+        failures[..failures.len().min(MAX_REPORTED)].join("\n")
+    );
+}
+
+const MAX_REPORTED: usize = 50;
@@ -0,0 +1,157 @@
+// Runs Blargg's and Mooneye's test ROMs headlessly. They can't be redistributed, so these only run
+// when GB_TEST_ROMS points at a directory laid out like the c-sp/gameboy-test-roms release:
+//
+//     GB_TEST_ROMS=path/to/game-boy-test-roms cargo test --release --test test_roms
+//
+// Without it every test passes without running anything.
+
+use std::cell::RefCell;
+use std::env;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+
+use rstest::rstest;
+use rustygameboy::apu::CPU_CLOCK;
+use rustygameboy::emulator::Emulator;
+use rustygameboy::rom::Rom;
+use rustygameboy::serial::SerialDevice;
+
+fn rom_path(relative: &str) -> Option<PathBuf> {
+    let Some(directory) = env::var_os("GB_TEST_ROMS") else {
+        eprintln!("GB_TEST_ROMS isn't set, skipping {}", relative);
+        return None;
+    };
+    let path = PathBuf::from(directory).join(relative);
+    assert!(path.exists(), "{} doesn't exist", path.display());
+    Some(path)
+}
+
+fn emulator(path: &Path) -> Emulator {
+    let rom = Rom::new_lenient(path.to_str().unwrap()).unwrap();
+    Emulator::new(rom).unwrap()
+}
+
+// Collects what a game sends where the test can still read it.
+struct SerialCapture(Rc<RefCell<String>>);
+
+impl SerialDevice for SerialCapture {
+    fn transfer(&mut self, byte: u8) -> u8 {
+        self.0.borrow_mut().push(byte as char);
+        0xFF
+    }
+}
+
+// Blargg's ROMs print their results over the serial port and end with "Passed" or "Failed".
+fn run_blargg(relative: &str, seconds: u64) {
+    let Some(path) = rom_path(relative) else {
+        return;
+    };
+    let mut emulator = emulator(&path);
+    let output = Rc::new(RefCell::new(String::new()));
+    emulator.set_serial_device(Some(Box::new(SerialCapture(output.clone()))));
+
+    let mut cycles = 0;
+    let mut checked = 0;
+    while cycles < seconds * CPU_CLOCK {
+        cycles += emulator.step() as u64;
+
+        let output = output.borrow();
+        if output.len() == checked {
+            continue;
+        }
+        checked = output.len();
+        if output.contains("Passed") {
+            return;
+        }
+        assert!(!output.contains("Failed"), "{}:\n{}", relative, output);
+    }
+    panic!("{} timed out:\n{}", relative, output.borrow());
+}
+
+// Mooneye's ROMs execute LD B,B when done, with the Fibonacci numbers in B, C, D, E, H and L if
+// they passed and 0x42 in all of them if they failed.
+fn run_mooneye(relative: &str) {
+    let Some(path) = rom_path(relative) else {
+        return;
+    };
+    let mut emulator = emulator(&path);
+
+    let mut cycles = 0;
+    while cycles < MOONEYE_SECONDS * CPU_CLOCK {
+        let r = emulator.cpu().registers;
+        if emulator.peek(r.pc) == LD_B_B {
+            let registers = [r.b, r.c, r.d, r.e, r.h, r.l];
+            assert_eq!(registers, MOONEYE_PASSED, "{} failed", relative);
+            return;
+        }
+        cycles += emulator.step() as u64;
+    }
+    panic!("{} timed out", relative);
+}
+
+#[test]
+fn test_blargg_cpu_instrs() {
+    // Takes close to a minute on hardware.
+    run_blargg("blargg/cpu_instrs/cpu_instrs.gb", 120);
+}
+
+#[test]
+fn test_blargg_instr_timing() {
+    run_blargg("blargg/instr_timing/instr_timing.gb", 10);
+}
+
+#[rstest]
+#[case("bits/mem_oam.gb")]
+#[case("bits/reg_f.gb")]
+#[case("bits/unused_hwio-GS.gb")]
+#[case("instr/daa.gb")]
+#[case("interrupts/ie_push.gb")]
+#[case("oam_dma/basic.gb")]
+#[case("oam_dma/reg_read.gb")]
+#[case("ppu/stat_irq_blocking.gb")]
+#[case("ppu/vblank_stat_intr-GS.gb")]
+#[case("timer/div_write.gb")]
+#[case("timer/rapid_toggle.gb")]
+#[case("timer/tim00.gb")]
+#[case("timer/tim00_div_trigger.gb")]
+#[case("timer/tim01.gb")]
+#[case("timer/tim01_div_trigger.gb")]
+#[case("timer/tim10.gb")]
+#[case("timer/tim10_div_trigger.gb")]
+#[case("timer/tim11.gb")]
+#[case("timer/tim11_div_trigger.gb")]
+#[case("timer/tima_reload.gb")]
+#[case("timer/tima_write_reloading.gb")]
+#[case("timer/tma_write_reloading.gb")]
+#[case("add_sp_e_timing.gb")]
+#[case("call_cc_timing.gb")]
+#[case("call_timing.gb")]
+#[case("di_timing-GS.gb")]
+#[case("div_timing.gb")]
+#[case("ei_sequence.gb")]
+#[case("ei_timing.gb")]
+#[case("halt_ime0_ei.gb")]
+#[case("halt_ime0_nointr_timing.gb")]
+#[case("halt_ime1_timing.gb")]
+#[case("if_ie_registers.gb")]
+#[case("intr_timing.gb")]
+#[case("jp_cc_timing.gb")]
+#[case("jp_timing.gb")]
+#[case("ld_hl_sp_e_timing.gb")]
+#[case("pop_timing.gb")]
+#[case("push_timing.gb")]
+#[case("rapid_di_ei.gb")]
+#[case("ret_cc_timing.gb")]
+#[case("ret_timing.gb")]
+#[case("reti_intr_timing.gb")]
+#[case("reti_timing.gb")]
+#[case("rst_timing.gb")]
+fn test_mooneye_acceptance(#[case] rom: &str) {
+    run_mooneye(&format!("mooneye-test-suite/acceptance/{}", rom));
+}
+
+const LD_B_B: u8 = 0x40;
+
+const MOONEYE_PASSED: [u8; 6] = [3, 5, 8, 13, 21, 34];
+
+const MOONEYE_SECONDS: u64 = 10;